@@ -0,0 +1,662 @@
+//! `renamer-cli`: the Sortify rename engine without the desktop app, for
+//! scripts and scheduled jobs.
+//!
+//! Presets and the rename history are read from the same place the app keeps
+//! them, so a preset saved in the app can be used here and a batch renamed
+//! here can be undone in the app (after restarting it) or with `undo`. The
+//! options for applying a batch set in the app's settings apply here too;
+//! flags can only add to them, and with the audit log on in the settings,
+//! batches renamed and undone here are written to it as well. In safe mode
+//! nothing is renamed, undone or recovered.
+//!
+//! Exits with 1 when some files could not be renamed or moved back. When
+//! nothing could be done at all it exits with 3 for rules that are not
+//! valid, 4 for a plan that needs a flag to go ahead or is refused in safe
+//! mode, 5 for files that could not be read or written, and 2 for anything
+//! else. With `--json`, such an
+//! error is also printed to stderr as `{"error": {"code", "message",
+//! "params"}}`, with the code and parameters `renamer_core::Error` gives.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use renamer_core::{
+    ApplyReport, AuditLog, CancelToken, ErrorCode, HistoryDb, HistoryStore, JobSummary, Journal,
+    ManifestFormat, OutcomeStatus, PlanOptions, PostAction, Preset, PresetStore, PreviewRow,
+    Recovery, RenamePlan, ReplayReport, Rule, Settings, SettingsStore, Severity, WebhookEvent,
+};
+
+/// The app's Tauri identifier, which names its data folder.
+const APP_IDENTIFIER: &str = "com.renamer.app";
+
+/// Next to a portable app's executable, and the folder its data is in.
+const PORTABLE_FLAG: &str = "portable.flag";
+const PORTABLE_DIR: &str = "data";
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(
+    name = "renamer-cli",
+    version,
+    about = "Rename files with Sortify rules"
+)]
+struct Cli {
+    /// Print results as JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Where presets, history and the journal live; defaults to the app's
+    /// data folder.
+    #[arg(long, global = true, env = "RENAMER_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// Use the `data` folder next to this program, as the app does in
+    /// portable mode; implied by a `portable.flag` file there.
+    #[arg(long, global = true)]
+    portable: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the new names without renaming anything.
+    Preview(RuleArgs),
+    /// Rename the files, then run the preset's post action if it has one.
+    Apply {
+        #[command(flatten)]
+        rules: RuleArgs,
+        /// Replace files that already exist at a target.
+        #[arg(long)]
+        overwrite: bool,
+        /// Let rules change or remove extensions.
+        #[arg(long)]
+        allow_extension_changes: bool,
+        /// Let files be moved to another drive by copying them.
+        #[arg(long)]
+        allow_cross_volume: bool,
+        /// Try a file another program has open this many more times; as
+        /// often as the settings say if not given.
+        #[arg(long)]
+        retry_locked: Option<u32>,
+        /// Leave files another program has open alone instead of undoing
+        /// the whole batch.
+        #[arg(long)]
+        skip_locked: bool,
+        /// Check each file again right before renaming it, and leave alone
+        /// those that changed or whose new name was taken meanwhile.
+        #[arg(long)]
+        recheck: bool,
+        /// Leave files alone whose plan finds more than this: `info` renames
+        /// only files without warnings.
+        #[arg(long, value_enum)]
+        skip_above: Option<SkipAbove>,
+        /// Write a checksum manifest of the renamed files into each folder
+        /// they end up in.
+        #[arg(long, value_enum)]
+        manifest: Option<Manifest>,
+        /// Move the git index entries of tracked files along with them, as
+        /// `git mv` does.
+        #[arg(long)]
+        git: bool,
+    },
+    /// Move the files of the last renamed batch back.
+    Undo,
+    /// Finish batches a crash or power loss cut off.
+    Resume(RecoverArgs),
+    /// Move back what batches cut off by a crash or power loss had moved.
+    Rollback(RecoverArgs),
+    /// List the saved presets.
+    Presets,
+    /// Check that the audit log has not been edited since it was written.
+    VerifyAudit,
+}
+
+/// The severities `--skip-above` accepts; errors are never applied anyway.
+#[derive(Clone, Copy, ValueEnum)]
+enum SkipAbove {
+    Info,
+    Warning,
+}
+
+/// The manifests `--manifest` writes.
+#[derive(Clone, Copy, ValueEnum)]
+enum Manifest {
+    /// `SHA256SUMS`, as `sha256sum` writes.
+    Sha256sums,
+    /// `checksums.json`.
+    Json,
+}
+
+impl From<Manifest> for ManifestFormat {
+    fn from(format: Manifest) -> Self {
+        match format {
+            Manifest::Sha256sums => ManifestFormat::Sha256Sums,
+            Manifest::Json => ManifestFormat::Json,
+        }
+    }
+}
+
+impl From<SkipAbove> for Severity {
+    fn from(level: SkipAbove) -> Self {
+        match level {
+            SkipAbove::Info => Severity::Info,
+            SkipAbove::Warning => Severity::Warning,
+        }
+    }
+}
+
+#[derive(Args)]
+struct RecoverArgs {
+    /// The batch to recover; all interrupted batches if omitted.
+    batch: Option<String>,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false, id = "chain")]
+struct RuleChoice {
+    /// Use the rules of a preset saved in the app.
+    #[arg(long)]
+    preset: Option<String>,
+    /// Read the rules from a JSON file holding a list of rules.
+    #[arg(long)]
+    rules: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct RuleArgs {
+    #[command(flatten)]
+    choice: RuleChoice,
+    /// Files and folders to rename.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// Number the files in the order given instead of in natural order.
+    #[arg(long)]
+    keep_order: bool,
+}
+
+impl RuleArgs {
+    fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.paths.clone();
+        if !self.keep_order {
+            renamer_core::sort_natural(&mut paths);
+        }
+        paths
+    }
+}
+
+fn data_dir(cli: &Cli) -> Result<PathBuf> {
+    if let Some(dir) = &cli.data_dir {
+        return Ok(dir.clone());
+    }
+    let exe = std::env::current_exe()?;
+    if let Some(dir) = exe.parent() {
+        if cli.portable || dir.join(PORTABLE_FLAG).is_file() {
+            return Ok(dir.join(PORTABLE_DIR));
+        }
+    }
+    Ok(dirs::data_dir()
+        .ok_or("cannot find the data folder; pass --data-dir")?
+        .join(APP_IDENTIFIER))
+}
+
+fn settings(data_dir: &Path) -> Result<Settings> {
+    Ok(SettingsStore::new(data_dir.join("settings.json")).load()?)
+}
+
+/// The settings, or an error if they are in safe mode; for commands that
+/// move files without going through `renamer_core::apply`, which checks
+/// that itself.
+fn unless_safe_mode(data_dir: &Path) -> Result<Settings> {
+    let settings = settings(data_dir)?;
+    if settings.apply.safe_mode {
+        return Err(renamer_core::Error::SafeMode.into());
+    }
+    Ok(settings)
+}
+
+/// The rules `args` ask for, with their planning options and the preset they
+/// came from.
+struct Chain {
+    rules: Vec<Rule>,
+    plan_options: PlanOptions,
+    preset: Option<Preset>,
+    post_action: Option<PostAction>,
+}
+
+impl Chain {
+    fn preset_name(&self) -> Option<&str> {
+        self.preset.as_ref().map(|p| p.name.as_str())
+    }
+
+    /// The new names for `paths`; a preset picks which of them it renames,
+    /// sends them to its target folder and refuses those out of its scope.
+    fn preview(&self, paths: &[PathBuf]) -> Result<Vec<PreviewRow>> {
+        let Some(preset) = &self.preset else {
+            return Ok(renamer_core::preview(paths, &self.rules)?);
+        };
+        let paths = preset.select(paths)?;
+        let mut rows = preset.localized(|| renamer_core::preview(&paths, &self.rules))??;
+        rows.iter_mut().for_each(|row| preset.place(row));
+        Ok(rows)
+    }
+
+    /// The plan for the changed rows, checked against the preset's scope.
+    fn plan(&self, rows: &[PreviewRow]) -> Result<RenamePlan> {
+        let ops = rows
+            .iter()
+            .filter(|r| r.changed)
+            .map(PreviewRow::to_op)
+            .collect();
+        let plan = renamer_core::plan(ops, &self.plan_options);
+        if let Some(preset) = &self.preset {
+            preset.check_scope(
+                plan.entries
+                    .iter()
+                    .flat_map(|e| [e.source.as_path(), e.target.as_path()]),
+            )?;
+        }
+        Ok(plan)
+    }
+}
+
+/// The rules to run, with `{ocr.*}` tokens reading files with the program the
+/// settings name and writing names in the locale they name, and new names
+/// checked against the naming policy they name.
+fn chain(data_dir: &Path, args: &RuleChoice) -> Result<Chain> {
+    if let Ok(settings) = settings(data_dir) {
+        renamer_core::ocr::set_engine(Some(Arc::new(settings.ocr)));
+        if let Some(tag) = &settings.locale {
+            renamer_core::locale::set_default(Some(renamer_core::locale::parse(tag)?));
+        }
+        if let Some(path) = &settings.naming_policy {
+            renamer_core::policy::set_enforced(Some(renamer_core::policy::load(path)?));
+        }
+    }
+    if let Some(path) = &args.rules {
+        let json = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        return Ok(Chain {
+            rules: serde_json::from_slice(&json)?,
+            plan_options: PlanOptions::default(),
+            preset: None,
+            post_action: None,
+        });
+    }
+    let name = args.preset.as_deref().unwrap_or_default();
+    let store = PresetStore::open(data_dir.join("presets.json"))?;
+    let preset = store
+        .get(name)
+        .ok_or_else(|| format!("no preset named `{}`", name))?;
+    Ok(Chain {
+        rules: preset.rules.clone(),
+        plan_options: preset.plan_options.clone(),
+        post_action: preset.post_action.clone(),
+        preset: Some(preset.clone()),
+    })
+}
+
+fn print_json(value: &impl serde::Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_preview(rows: &[PreviewRow]) {
+    let changed = rows.iter().filter(|r| r.changed).count();
+    for row in rows.iter().filter(|r| r.changed) {
+        let mut notes = Vec::new();
+        if row.conflict {
+            notes.push("conflict".to_string());
+        }
+        notes.extend(row.warnings.iter().map(|w| format!("{:?}", w)));
+        notes.extend(row.step_errors.iter().cloned());
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!("  [{}]", notes.join(", "))
+        };
+        // Files an organize rule moves show where they go.
+        let to = if row.target.parent() == row.source.parent() {
+            row.new_name.clone()
+        } else {
+            row.target.display().to_string()
+        };
+        println!("{} -> {}{}", row.source.display(), to, notes);
+    }
+    println!("{} of {} files would change", changed, rows.len());
+}
+
+fn print_report(report: &ApplyReport) {
+    for outcome in &report.outcomes {
+        match outcome.status {
+            OutcomeStatus::Renamed => println!(
+                "{} -> {}",
+                outcome.source.display(),
+                outcome.target.display()
+            ),
+            OutcomeStatus::Failed => eprintln!(
+                "failed: {}: {}",
+                outcome.source.display(),
+                outcome.error.as_deref().unwrap_or("unknown error")
+            ),
+            OutcomeStatus::Locked => eprintln!("in use, skipped: {}", outcome.source.display()),
+            OutcomeStatus::Paused => eprintln!("paused: {}", outcome.source.display()),
+            OutcomeStatus::Stale => eprintln!(
+                "changed, skipped: {}: {}",
+                outcome.source.display(),
+                outcome.error.as_deref().unwrap_or("unknown change")
+            ),
+            _ => {}
+        }
+    }
+    if report.committed {
+        println!(
+            "renamed {}, skipped {}, failed {}",
+            report.renamed, report.skipped, report.failed
+        );
+        if let Some(path) = &report.unreachable {
+            eprintln!(
+                "paused: {} stopped answering; reconnect it and run `resume` to finish the batch",
+                path.display()
+            );
+        }
+        if report.unverified > 0 {
+            eprintln!(
+                "{} renamed files could not be verified and cannot be undone",
+                report.unverified
+            );
+        }
+        for manifest in &report.manifests {
+            match &manifest.error {
+                Some(error) => eprintln!(
+                    "manifest not written: {}: {}",
+                    manifest.path.display(),
+                    error
+                ),
+                None => println!(
+                    "manifest: {} ({} files)",
+                    manifest.path.display(),
+                    manifest.files
+                ),
+            }
+            for path in &manifest.unreadable {
+                eprintln!("not in the manifest, unreadable: {}", path.display());
+            }
+        }
+        for repo in &report.git {
+            if let Some(error) = &repo.error {
+                eprintln!(
+                    "git index not updated in {}: {}",
+                    repo.root.display(),
+                    error
+                );
+            }
+        }
+    } else {
+        println!(
+            "batch rolled back: {} restored, {} could not be restored",
+            report.rolled_back, report.rollback_failed
+        );
+    }
+}
+
+fn print_replay(report: Option<&ReplayReport>) {
+    match report {
+        Some(report) => println!(
+            "moved {} files back, {} problems",
+            report.done, report.problems
+        ),
+        None => println!("nothing to undo"),
+    }
+}
+
+/// Finishes or undoes the interrupted batches `args` pick.
+fn recover(cli: &Cli, data_dir: &Path, args: &RecoverArgs, how: Recovery) -> Result<bool> {
+    unless_safe_mode(data_dir)?;
+    let dir = data_dir.join("journal");
+    let journals: Vec<Journal> = Journal::incomplete(&dir)?
+        .into_iter()
+        .filter(|j| {
+            args.batch
+                .as_ref()
+                .map_or(true, |b| j.batch_id.to_string() == *b)
+        })
+        .collect();
+    if let (Some(batch), true) = (&args.batch, journals.is_empty()) {
+        return Err(format!("no interrupted batch {}", batch).into());
+    }
+    let mut reports = Vec::new();
+    for journal in &journals {
+        reports.push(journal.recover(&dir, how)?);
+    }
+    if cli.json {
+        print_json(&reports)?;
+    } else if reports.is_empty() {
+        println!("no interrupted batches");
+    }
+    for report in reports.iter().filter(|_| !cli.json) {
+        println!(
+            "batch {}: moved {} files, {} problems",
+            report.batch_id, report.done, report.problems
+        );
+    }
+    Ok(reports.iter().all(|r| r.problems == 0))
+}
+
+fn run(cli: &Cli) -> Result<bool> {
+    let data_dir = data_dir(cli)?;
+    if !matches!(cli.command, Command::Resume(_) | Command::Rollback(_)) {
+        let interrupted = Journal::incomplete(&data_dir.join("journal"))?;
+        if !interrupted.is_empty() {
+            eprintln!(
+                "warning: {} batches were cut off before they finished; \
+                 run `resume` or `rollback` to recover them",
+                interrupted.len()
+            );
+        }
+    }
+    match &cli.command {
+        Command::Preview(args) => {
+            let chain = chain(&data_dir, &args.choice)?;
+            let rows = chain.preview(&args.paths())?;
+            if cli.json {
+                print_json(&rows)?;
+            } else {
+                print_preview(&rows);
+            }
+            Ok(true)
+        }
+        Command::Apply {
+            rules: args,
+            overwrite,
+            allow_extension_changes,
+            allow_cross_volume,
+            retry_locked,
+            skip_locked,
+            recheck,
+            skip_above,
+            manifest,
+            git,
+        } => {
+            let chain = chain(&data_dir, &args.choice)?;
+            let plan = chain.plan(&chain.preview(&args.paths())?)?;
+            let settings = settings(&data_dir)?;
+            let mut options = settings.apply;
+            options.confirm_overwrite |= *overwrite;
+            options.allow_extension_changes |= *allow_extension_changes;
+            options.allow_cross_volume |= *allow_cross_volume;
+            options.lock_retries = retry_locked.unwrap_or(options.lock_retries);
+            options.skip_locked |= *skip_locked;
+            options.recheck |= *recheck;
+            options.skip_above = skip_above.map(Severity::from).or(options.skip_above);
+            options.manifest = manifest.map(ManifestFormat::from).or(options.manifest);
+            options.git |= *git;
+            let checked = renamer_core::preflight(&plan);
+            if checked.blocked {
+                if cli.json {
+                    print_json(&checked)?;
+                } else {
+                    for problem in &checked.problems {
+                        eprintln!(
+                            "cannot rename: {}: {}",
+                            problem.path.display(),
+                            problem.message
+                        );
+                    }
+                }
+                return Ok(false);
+            }
+            let report = renamer_core::apply(&plan, &options, &data_dir.join("journal"))?;
+            HistoryStore::open(data_dir.join("history.json"))?.record(&report)?;
+            HistoryDb::open(data_dir.join("history.db"))?.record(&report, chain.preset_name())?;
+            if settings.audit_log {
+                AuditLog::new(data_dir.join("audit.jsonl")).record(&report)?;
+            }
+            let summary = JobSummary::new(WebhookEvent::Batch, &report, chain.preset_name());
+            for failure in renamer_core::webhook::send(&settings.webhooks, &summary) {
+                eprintln!("webhook {} not told: {}", failure.url, failure.message);
+            }
+            if cli.json {
+                print_json(&report)?;
+            } else {
+                print_report(&report);
+            }
+            let mut post_ok = true;
+            if let Some(action) = &chain.post_action {
+                for run in renamer_core::post_action::run(action, &report, &CancelToken::new()) {
+                    if !run.succeeded() {
+                        post_ok = false;
+                        let what = run.path.as_deref().unwrap_or(Path::new("the batch"));
+                        let why = run.error.as_deref().unwrap_or(run.stderr.trim());
+                        eprintln!("post action failed for {}: {}", what.display(), why);
+                    }
+                }
+                HistoryStore::open(data_dir.join("history.json"))?
+                    .fingerprint_again(report.batch_id)?;
+            }
+            Ok(report.committed && report.failed == 0 && report.unreachable.is_none() && post_ok)
+        }
+        Command::Undo => {
+            let settings = unless_safe_mode(&data_dir)?;
+            let mut history = HistoryStore::open(data_dir.join("history.json"))?;
+            let report = history.undo_last()?;
+            if let (Some(report), true) = (&report, settings.audit_log) {
+                AuditLog::new(data_dir.join("audit.jsonl")).record_replay(report, true)?;
+            }
+            if let (Some(report), true) = (&report, settings.apply.git) {
+                for repo in renamer_core::git::stage_replay(report, true) {
+                    if let Some(e) = repo.error {
+                        eprintln!("git index not updated in {}: {}", repo.root.display(), e);
+                    }
+                }
+            }
+            if cli.json {
+                print_json(&report)?;
+            } else {
+                print_replay(report.as_ref());
+            }
+            Ok(report.map_or(true, |r| r.problems == 0))
+        }
+        Command::Resume(args) => recover(cli, &data_dir, args, Recovery::Resume),
+        Command::Rollback(args) => recover(cli, &data_dir, args, Recovery::Rollback),
+        Command::Presets => {
+            let store = PresetStore::open(data_dir.join("presets.json"))?;
+            if cli.json {
+                print_json(&store.presets())?;
+            } else {
+                for preset in store.presets() {
+                    println!("{} ({} rules)", preset.name, preset.rules.len());
+                }
+            }
+            Ok(true)
+        }
+        Command::VerifyAudit => {
+            let verified = AuditLog::new(data_dir.join("audit.jsonl")).verify()?;
+            if cli.json {
+                print_json(&verified)?;
+            } else if verified.intact {
+                println!("{} entries, intact", verified.entries);
+                if let Some(hash) = &verified.last_hash {
+                    println!("last hash {}", hash);
+                }
+            } else {
+                println!(
+                    "broken at line {}: {}",
+                    verified.broken_at.unwrap_or_default(),
+                    verified.problem.as_deref().unwrap_or_default()
+                );
+            }
+            Ok(verified.intact)
+        }
+    }
+}
+
+/// The exit code for a run that failed with an error of `code`.
+fn exit_code(code: Option<ErrorCode>) -> u8 {
+    match code {
+        Some(
+            ErrorCode::InvalidPattern
+            | ErrorCode::InvalidGlob
+            | ErrorCode::InvalidIgnorePattern
+            | ErrorCode::InvalidCondition
+            | ErrorCode::InvalidScript
+            | ErrorCode::InvalidPlugin
+            | ErrorCode::UnknownPluginRule
+            | ErrorCode::InvalidTemplate
+            | ErrorCode::UnknownLocale
+            | ErrorCode::InvalidReplacement
+            | ErrorCode::InvalidPolicy
+            | ErrorCode::InvalidPresetName,
+        ) => 3,
+        Some(
+            ErrorCode::PlanBlocked
+            | ErrorCode::OverwriteNotConfirmed
+            | ErrorCode::ExtensionChangeNotConfirmed
+            | ErrorCode::SafeMode
+            | ErrorCode::CrossVolume
+            | ErrorCode::TargetInFlight,
+        ) => 4,
+        Some(
+            ErrorCode::NotFound
+            | ErrorCode::PermissionDenied
+            | ErrorCode::AlreadyExists
+            | ErrorCode::FileLocked
+            | ErrorCode::Io
+            | ErrorCode::OutOfScope
+            | ErrorCode::PresetScope
+            | ErrorCode::Watch
+            | ErrorCode::Remote
+            | ErrorCode::ShareUnreachable
+            | ErrorCode::Stale
+            | ErrorCode::Archive,
+        ) => 5,
+        _ => 2,
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            let error = e.downcast_ref::<renamer_core::Error>();
+            let code = error.map(renamer_core::Error::code);
+            if cli.json {
+                let error = serde_json::json!({
+                    "error": {
+                        "code": code,
+                        "message": e.to_string(),
+                        "params": error.map(renamer_core::Error::params).unwrap_or_default(),
+                    }
+                });
+                eprintln!("{}", error);
+            } else {
+                eprintln!("error: {}", e);
+            }
+            ExitCode::from(exit_code(code))
+        }
+    }
+}
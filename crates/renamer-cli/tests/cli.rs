@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use renamer_core::{Preset, PresetStore, Rule};
+
+fn cli(data_dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_renamer-cli"))
+        .arg("--data-dir")
+        .arg(data_dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn presets_saved_by_the_app_preview_apply_and_undo() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("data");
+    PresetStore::open(data.join("presets.json"))
+        .unwrap()
+        .insert(Preset::new(
+            "Podcast episodes",
+            vec![Rule::Prefix { text: "ep-".into() }],
+        ))
+        .unwrap();
+    let file = dir.path().join("intro.mp3");
+    fs::write(&file, "mp3").unwrap();
+    let file = file.to_str().unwrap();
+
+    let output = cli(&data, &["preview", "--preset", "podcast episodes", file]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("-> ep-intro.mp3"), "{}", stdout);
+    assert!(Path::new(file).exists());
+
+    let output = cli(
+        &data,
+        &["apply", "--json", "--preset", "Podcast episodes", file],
+    );
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["renamed"], 1);
+    assert!(dir.path().join("ep-intro.mp3").exists());
+
+    let output = cli(&data, &["undo"]);
+    assert!(output.status.success());
+    assert!(Path::new(file).exists());
+    assert!(!dir.path().join("ep-intro.mp3").exists());
+}
+
+#[test]
+fn rules_can_come_from_a_file_and_unknown_presets_fail() {
+    let dir = tempfile::tempdir().unwrap();
+    let rules = dir.path().join("rules.json");
+    fs::write(&rules, r#"[{"type": "suffix", "text": "-final"}]"#).unwrap();
+    let file = dir.path().join("report.pdf");
+    fs::write(&file, "pdf").unwrap();
+
+    let output = cli(
+        dir.path(),
+        &[
+            "preview",
+            "--json",
+            "--rules",
+            rules.to_str().unwrap(),
+            file.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    let rows: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(rows[0]["new_name"], "report-final.pdf");
+
+    let output = cli(
+        dir.path(),
+        &["apply", "--preset", "Photo import", file.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no preset named"));
+    assert!(file.exists());
+}
+
+#[test]
+fn errors_pick_the_exit_code_and_come_as_json_with_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let rules = dir.path().join("rules.json");
+    fs::write(
+        &rules,
+        r#"[{"type": "regex", "pattern": "(", "replacement": ""}]"#,
+    )
+    .unwrap();
+    let file = dir.path().join("report.pdf");
+    fs::write(&file, "pdf").unwrap();
+
+    let output = cli(
+        dir.path(),
+        &[
+            "preview",
+            "--json",
+            "--rules",
+            rules.to_str().unwrap(),
+            file.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(output.status.code(), Some(3));
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["error"]["code"], "invalid_pattern");
+    assert_eq!(error["error"]["params"]["pattern"], "(");
+}
+
+#[test]
+fn the_audit_log_records_batches_when_on_and_verifies() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("data");
+    fs::create_dir_all(&data).unwrap();
+    fs::write(
+        data.join("settings.json"),
+        r#"{"version": 1, "audit_log": true}"#,
+    )
+    .unwrap();
+    let rules = dir.path().join("rules.json");
+    fs::write(&rules, r#"[{"type": "prefix", "text": "2024-"}]"#).unwrap();
+    let file = dir.path().join("taxes.pdf");
+    fs::write(&file, "pdf").unwrap();
+
+    let rules = rules.to_str().unwrap();
+    let output = cli(&data, &["apply", "--rules", rules, file.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(cli(&data, &["undo"]).status.success());
+
+    let output = cli(&data, &["verify-audit", "--json"]);
+    assert!(output.status.success());
+    let verified: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(verified["entries"], 2);
+
+    let log = data.join("audit.jsonl");
+    let edited = fs::read_to_string(&log)
+        .unwrap()
+        .replacen("2024-taxes", "2023-taxes", 1);
+    fs::write(&log, edited).unwrap();
+    let output = cli(&data, &["verify-audit"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("broken at line 1"), "{}", stdout);
+}
+
+#[test]
+fn safe_mode_in_the_settings_refuses_apply_and_undo() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("data");
+    fs::create_dir_all(&data).unwrap();
+    fs::write(
+        data.join("settings.json"),
+        r#"{"version": 1, "apply": {"safe_mode": true}}"#,
+    )
+    .unwrap();
+    let rules = dir.path().join("rules.json");
+    fs::write(&rules, r#"[{"type": "prefix", "text": "old-"}]"#).unwrap();
+    let file = dir.path().join("notes.txt");
+    fs::write(&file, "txt").unwrap();
+
+    let output = cli(
+        &data,
+        &[
+            "apply",
+            "--json",
+            "--rules",
+            rules.to_str().unwrap(),
+            file.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(output.status.code(), Some(4));
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["error"]["code"], "safe_mode");
+    assert!(file.exists());
+
+    assert_eq!(cli(&data, &["undo"]).status.code(), Some(4));
+}
+
+#[test]
+fn apply_writes_a_manifest_when_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("data");
+    let rules = dir.path().join("rules.json");
+    fs::write(&rules, r#"[{"type": "prefix", "text": "2024-"}]"#).unwrap();
+    let file = dir.path().join("taxes.pdf");
+    fs::write(&file, "pdf").unwrap();
+
+    let output = cli(
+        &data,
+        &[
+            "apply",
+            "--manifest",
+            "sha256sums",
+            "--rules",
+            rules.to_str().unwrap(),
+            file.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    let sums = fs::read_to_string(dir.path().join("SHA256SUMS")).unwrap();
+    assert!(sums.ends_with("  2024-taxes.pdf\n"), "{}", sums);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(1 files)"), "{}", stdout);
+}
@@ -0,0 +1,421 @@
+//! Archives opened as folders, so the names inside a `.zip`, `.tar` or
+//! `.tar.gz` can be fixed without extracting it.
+//!
+//! An [`Archive`] is a [`RemoteFs`] over the archive's index: it is listed
+//! with [`remote::scan`], named with [`remote::preview`] and renamed with
+//! [`remote::apply`] like a remote folder, paths inside it being absolute
+//! and `/`-separated. Renames only change the index; [`Archive::save`]
+//! then writes the archive anew under the new names and puts it in place
+//! of the old one, so a failed batch leaves the file as it was. Zip members
+//! are copied as they are stored, compressed and encrypted alike; tar
+//! members are copied with their headers, and a `.tar.gz` is compressed
+//! again. 7z archives are recognized but not supported.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use uuid::Uuid;
+
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::executor::ApplyReport;
+use crate::fsutil;
+use crate::plan::RenameOp;
+use crate::remote::{self, RemoteEntry, RemoteFs};
+use crate::scan::{ScanOptions, ScanPage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+/// A file or folder stored in an archive.
+#[derive(Debug, Clone)]
+struct Member {
+    /// The name as stored, e.g. `./docs/`.
+    stored: String,
+    /// The name now, without a leading `./` or trailing `/`; empty for an
+    /// entry of the archive's top folder.
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+    /// What a tar hard link is a link to, as stored.
+    hard_link: Option<String>,
+}
+
+impl Member {
+    fn new(stored: String, is_dir: bool, size: u64, modified: Option<DateTime<Utc>>) -> Self {
+        let name = plain(&stored).to_string();
+        Member {
+            stored,
+            name,
+            is_dir,
+            size,
+            modified,
+            hard_link: None,
+        }
+    }
+
+    fn renamed(&self) -> bool {
+        self.name != plain(&self.stored)
+    }
+
+    /// The name to store it under.
+    fn written(&self) -> String {
+        respell(&self.stored, &self.name)
+    }
+}
+
+/// A stored name without a leading `./` or trailing `/`.
+fn plain(stored: &str) -> &str {
+    stored.trim_start_matches("./").trim_matches('/')
+}
+
+/// `name` spelled as `stored` is, with its `./` and trailing `/`.
+fn respell(stored: &str, name: &str) -> String {
+    if plain(stored) == name {
+        return stored.to_string();
+    }
+    let prefix = if stored.starts_with("./") { "./" } else { "" };
+    let suffix = if stored.ends_with('/') { "/" } else { "" };
+    format!("{}{}{}", prefix, name, suffix)
+}
+
+/// An archive opened as a folder.
+pub struct Archive {
+    path: PathBuf,
+    format: Format,
+    members: Vec<Member>,
+}
+
+impl Archive {
+    /// Reads the index of the archive at `path`, telling its format by its
+    /// contents rather than its extension.
+    pub fn open(path: &Path) -> Result<Self> {
+        let invalid = |message: String| Error::Archive {
+            path: path.to_path_buf(),
+            message,
+        };
+        let mut file = File::open(fsutil::long(path)).map_err(|e| Error::io(path, e))?;
+        let mut head = Vec::with_capacity(512);
+        (&mut file)
+            .take(512)
+            .read_to_end(&mut head)
+            .map_err(|e| Error::io(path, e))?;
+        file.rewind().map_err(|e| Error::io(path, e))?;
+        let format = if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            Format::Zip
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Format::TarGz
+        } else if head.get(257..262) == Some(b"ustar") {
+            Format::Tar
+        } else if head.starts_with(b"7z\xbc\xaf\x27\x1c") {
+            return Err(invalid("7z archives are not supported".into()));
+        } else {
+            return Err(invalid("not a zip or tar archive".into()));
+        };
+        let members = match format {
+            Format::Zip => zip_members(file),
+            Format::Tar => tar_members(BufReader::new(file)),
+            Format::TarGz => tar_members(GzDecoder::new(BufReader::new(file))),
+        }
+        .map_err(|e| invalid(e.to_string()))?;
+        Ok(Archive {
+            path: path.to_path_buf(),
+            format,
+            members,
+        })
+    }
+
+    /// Whether any member has been renamed since the archive was opened.
+    pub fn changed(&self) -> bool {
+        self.members.iter().any(Member::renamed)
+    }
+
+    /// Writes the archive with its members' new names, through a hidden
+    /// copy next to it that then takes its place. Nothing is written if no
+    /// member was renamed.
+    pub fn save(&self) -> Result<()> {
+        if !self.changed() {
+            return Ok(());
+        }
+        let temp = fsutil::hidden_sibling(&self.path, &format!("sortify-{}.tmp", Uuid::new_v4()));
+        let written = self.write(&temp).and_then(|()| {
+            let permissions = fs::metadata(fsutil::long(&self.path))?.permissions();
+            fs::set_permissions(fsutil::long(&temp), permissions)?;
+            fs::rename(fsutil::long(&temp), fsutil::long(&self.path))
+        });
+        written.map_err(|e| {
+            let _ = fs::remove_file(fsutil::long(&temp));
+            Error::io(&self.path, e)
+        })
+    }
+
+    fn write(&self, temp: &Path) -> io::Result<()> {
+        let source = File::open(fsutil::long(&self.path))?;
+        let target = BufWriter::new(File::create(fsutil::long(temp))?);
+        match self.format {
+            Format::Zip => self.write_zip(source, target),
+            Format::Tar => self.write_tar(BufReader::new(source), target)?.flush(),
+            Format::TarGz => self
+                .write_tar(
+                    GzDecoder::new(BufReader::new(source)),
+                    GzEncoder::new(target, flate2::Compression::default()),
+                )?
+                .finish()?
+                .flush(),
+        }
+    }
+
+    fn write_zip(&self, source: File, target: BufWriter<File>) -> io::Result<()> {
+        let mut source = zip::ZipArchive::new(BufReader::new(source)).map_err(io::Error::other)?;
+        let mut writer = zip::ZipWriter::new(target);
+        writer.set_raw_comment(source.comment().into());
+        for (i, member) in self.members.iter().enumerate() {
+            let file = source.by_index_raw(i).map_err(io::Error::other)?;
+            writer
+                .raw_copy_file_rename(file, member.written())
+                .map_err(io::Error::other)?;
+        }
+        writer.finish().map_err(io::Error::other)?.flush()
+    }
+
+    fn write_tar<R: Read, W: Write>(&self, source: R, target: W) -> io::Result<W> {
+        let mut source = tar::Archive::new(source);
+        let mut builder = tar::Builder::new(target);
+        // A hard link's target may be spelled unlike the member's own name.
+        let linked = |to: &str| match self.members.iter().find(|m| plain(&m.stored) == plain(to)) {
+            Some(target) => respell(to, &target.name),
+            None => to.to_string(),
+        };
+        for (entry, member) in source.entries()?.zip(&self.members) {
+            let mut entry = entry?;
+            // Extended headers other than the names, which are written
+            // anew, go along with the entry.
+            let extensions: Vec<(String, Vec<u8>)> = match entry.pax_extensions()? {
+                Some(extensions) => extensions
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| Some((e.key().ok()?.to_string(), e.value_bytes().to_vec())))
+                    .filter(|(key, _)| key != "path" && key != "linkpath")
+                    .collect(),
+                None => Vec::new(),
+            };
+            builder.append_pax_extensions(extensions.iter().map(|(k, v)| (k.as_str(), &v[..])))?;
+            let mut header = entry.header().clone();
+            match (&member.hard_link, entry.link_name()?) {
+                (Some(to), _) => builder.append_link(&mut header, member.written(), linked(to))?,
+                (None, Some(to)) => {
+                    let to = to.into_owned();
+                    builder.append_link(&mut header, member.written(), to)?
+                }
+                (None, None) => builder.append_data(&mut header, member.written(), &mut entry)?,
+            }
+        }
+        builder.into_inner()
+    }
+
+    /// The members at or under the folder `rel`.
+    fn under<'a>(&'a self, rel: &'a str) -> impl Iterator<Item = &'a Member> + 'a {
+        self.members.iter().filter(move |m| {
+            !m.name.is_empty()
+                && (rel.is_empty()
+                    || m.name == rel
+                    || m.name.strip_prefix(rel).is_some_and(|r| r.starts_with('/')))
+        })
+    }
+}
+
+fn zip_members(file: File) -> io::Result<Vec<Member>> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(io::Error::other)?;
+    let mut members = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i).map_err(io::Error::other)?;
+        let modified = file
+            .last_modified()
+            .and_then(|t| NaiveDateTime::try_from(t).ok())
+            .map(|t| t.and_utc());
+        members.push(Member::new(
+            file.name().to_string(),
+            file.is_dir(),
+            file.size(),
+            modified,
+        ));
+    }
+    Ok(members)
+}
+
+fn tar_members<R: Read>(source: R) -> io::Result<Vec<Member>> {
+    let mut archive = tar::Archive::new(source);
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let kind = header.entry_type();
+        let modified = header
+            .mtime()
+            .ok()
+            .and_then(|t| DateTime::from_timestamp(t as i64, 0));
+        let mut member = Member::new(
+            entry.path()?.to_string_lossy().into_owned(),
+            kind.is_dir(),
+            header.size()?,
+            modified,
+        );
+        if kind.is_hard_link() {
+            member.hard_link = entry
+                .link_name()?
+                .map(|to| to.to_string_lossy().into_owned());
+        }
+        members.push(member);
+    }
+    Ok(members)
+}
+
+/// The name inside the archive of the path `path`.
+fn relative(path: &Path) -> String {
+    let parts: Vec<String> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+impl RemoteFs for Archive {
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        let rel = relative(dir);
+        let mut entries: Vec<RemoteEntry> = Vec::new();
+        let mut found = rel.is_empty();
+        for member in self.under(&rel) {
+            found = true;
+            let rest = match rel.is_empty() {
+                true => &member.name[..],
+                false => member.name[rel.len()..].trim_start_matches('/'),
+            };
+            if rest.is_empty() {
+                continue;
+            }
+            // Zip archives need not store their folders, only the files in
+            // them.
+            let (name, nested) = match rest.split_once('/') {
+                Some((name, _)) => (name, true),
+                None => (rest, false),
+            };
+            match entries.iter_mut().find(|e| e.name == name) {
+                Some(entry) if !nested => {
+                    entry.is_dir = member.is_dir;
+                    entry.modified = member.modified;
+                }
+                Some(_) => {}
+                None => entries.push(RemoteEntry {
+                    name: name.to_string(),
+                    is_dir: nested || member.is_dir,
+                    is_symlink: false,
+                    size: if nested || member.is_dir {
+                        0
+                    } else {
+                        member.size
+                    },
+                    modified: (!nested).then_some(member.modified).flatten(),
+                    link_target: None,
+                }),
+            }
+        }
+        if !found {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such folder"));
+        }
+        Ok(entries)
+    }
+
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        let rel = relative(path);
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if rel.is_empty() {
+            return Ok(Some(RemoteEntry {
+                name,
+                is_dir: true,
+                is_symlink: false,
+                size: 0,
+                modified: None,
+                link_target: None,
+            }));
+        }
+        let own = self.members.iter().find(|m| m.name == rel);
+        let nested = self.under(&rel).any(|m| m.name != rel);
+        Ok((own.is_some() || nested).then(|| RemoteEntry {
+            name,
+            is_dir: nested || own.is_some_and(|m| m.is_dir),
+            is_symlink: false,
+            size: own.filter(|m| !m.is_dir).map_or(0, |m| m.size),
+            modified: own.and_then(|m| m.modified),
+            link_target: None,
+        }))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (relative(from), relative(to));
+        if from.is_empty() || to.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the archive's top folder cannot be renamed",
+            ));
+        }
+        if to.starts_with(&format!("{}/", from)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a folder cannot be moved into itself",
+            ));
+        }
+        if self.under(&to).next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a file already has the new name",
+            ));
+        }
+        let mut moved = false;
+        for member in &mut self.members {
+            if member.name == from {
+                member.name = to.clone();
+            } else if let Some(rest) = member.name.strip_prefix(&format!("{}/", from)) {
+                member.name = format!("{}/{}", to, rest);
+            } else {
+                continue;
+            }
+            moved = true;
+        }
+        if !moved {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        }
+        Ok(())
+    }
+}
+
+/// Lists the archive at `path` as [`remote::scan`] lists a folder.
+pub fn scan(path: &Path, options: &ScanOptions, cancel: &CancelToken) -> Result<ScanPage> {
+    let mut archive = Archive::open(path)?;
+    remote::scan(&mut archive, Path::new("/"), options, cancel)
+}
+
+/// Renames members of the archive at `path` as [`remote::apply`] renames
+/// files on a host, and saves the archive if the batch commits. A batch
+/// that fails, or is cancelled, leaves the archive as it was.
+pub fn apply(path: &Path, ops: &[RenameOp], cancel: &CancelToken) -> Result<ApplyReport> {
+    let mut archive = Archive::open(path)?;
+    let report = remote::apply(&mut archive, ops, cancel);
+    if report.committed && !report.cancelled {
+        archive.save()?;
+    }
+    Ok(report)
+}
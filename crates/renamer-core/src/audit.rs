@@ -0,0 +1,295 @@
+//! An append-only audit log of every change made to a file, for places that
+//! have to be able to show what was renamed, and when, later on.
+//!
+//! The log is JSON lines, one change per line. Each line carries the SHA-256
+//! of the line before it and a SHA-256 of its own over that and its fields,
+//! so editing, removing or reordering lines breaks the chain from there on,
+//! which [`AuditLog::verify`] finds. The chain shows the log was not changed
+//! line by line; a log replaced as a whole is only caught by comparing the
+//! hash of its last line with one kept somewhere else.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::executor::{ApplyReport, OutcomeStatus};
+use crate::history::{ReplayReport, ReplayStatus};
+use crate::history_db::ChangeKind;
+
+/// What the first entry gives as the hash before it.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Most bytes read from the end of the log to find its last line.
+const TAIL_SIZE: u64 = 64 * 1024;
+
+/// One change in the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, from 1.
+    pub seq: u64,
+    pub batch_id: Uuid,
+    /// The entry's id in its batch.
+    pub entry_id: usize,
+    pub kind: ChangeKind,
+    pub at: DateTime<Utc>,
+    pub source: String,
+    pub target: String,
+    /// `hash` of the entry before this one.
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// The fields a hash is taken over, in this order.
+#[derive(Serialize)]
+struct Hashed<'a> {
+    seq: u64,
+    batch_id: Uuid,
+    entry_id: usize,
+    kind: ChangeKind,
+    at: &'a DateTime<Utc>,
+    source: &'a str,
+    target: &'a str,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let fields = serde_json::to_string(&Hashed {
+            seq: self.seq,
+            batch_id: self.batch_id,
+            entry_id: self.entry_id,
+            kind: self.kind,
+            at: &self.at,
+            source: &self.source,
+            target: &self.target,
+        })
+        .expect("audit fields serialize");
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(fields.as_bytes());
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// What [`AuditLog::verify`] found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditVerification {
+    /// Entries checked, up to and including a broken one.
+    pub entries: u64,
+    pub intact: bool,
+    /// The line, from 1, where the chain breaks.
+    pub broken_at: Option<u64>,
+    /// Why it breaks there.
+    pub problem: Option<String>,
+    /// The hash of the last entry, to keep somewhere else and compare with
+    /// later.
+    pub last_hash: Option<String>,
+}
+
+/// The audit log at a path; the file is made by the first change.
+///
+/// Appending is not locked against other processes, so the app and the CLI
+/// should not apply batches at the same moment with the log on.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        AuditLog { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the entries of an applied batch that were renamed. Returns how
+    /// many it added.
+    pub fn record(&self, report: &ApplyReport) -> Result<usize> {
+        let changes = report
+            .outcomes
+            .iter()
+            .filter(|o| o.status == OutcomeStatus::Renamed)
+            .map(|o| (o.id, o.source.as_path(), o.target.as_path()));
+        self.append(report.batch_id, ChangeKind::Renamed, changes)
+    }
+
+    /// Adds the entries an undo, or a redo, moved.
+    pub fn record_replay(&self, report: &ReplayReport, undo: bool) -> Result<usize> {
+        let kind = if undo {
+            ChangeKind::Undone
+        } else {
+            ChangeKind::Redone
+        };
+        let changes = report
+            .outcomes
+            .iter()
+            .filter(|o| o.status == ReplayStatus::Done)
+            .map(|o| (o.id, o.from.as_path(), o.to.as_path()));
+        self.append(report.batch_id, kind, changes)
+    }
+
+    fn append<'a>(
+        &self,
+        batch_id: Uuid,
+        kind: ChangeKind,
+        changes: impl Iterator<Item = (usize, &'a Path, &'a Path)>,
+    ) -> Result<usize> {
+        let io_error = |e| Error::io(&self.path, e);
+        let (mut seq, mut prev_hash) = match self.last_entry()? {
+            Some(last) => (last.seq, last.hash),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        let at = Utc::now();
+        let mut lines = String::new();
+        let mut added = 0;
+        for (entry_id, source, target) in changes {
+            seq += 1;
+            let mut entry = AuditEntry {
+                seq,
+                batch_id,
+                entry_id,
+                kind,
+                at,
+                source: source.to_string_lossy().into_owned(),
+                target: target.to_string_lossy().into_owned(),
+                prev_hash,
+                hash: String::new(),
+            };
+            entry.hash = entry.compute_hash();
+            lines.push_str(&serde_json::to_string(&entry)?);
+            lines.push('\n');
+            prev_hash = entry.hash;
+            added += 1;
+        }
+        if added == 0 {
+            return Ok(0);
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        // One write for the batch, flushed to disk before the call returns.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(io_error)?;
+        file.write_all(lines.as_bytes()).map_err(io_error)?;
+        file.sync_data().map_err(io_error)?;
+        Ok(added)
+    }
+
+    /// The last entry, read from the end of the file; `None` for a log with
+    /// none yet.
+    fn last_entry(&self) -> Result<Option<AuditEntry>> {
+        let io_error = |e| Error::io(&self.path, e);
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(e)),
+        };
+        let len = file.metadata().map_err(io_error)?.len();
+        let start = len.saturating_sub(TAIL_SIZE);
+        file.seek(SeekFrom::Start(start)).map_err(io_error)?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).map_err(io_error)?;
+        let text = String::from_utf8_lossy(&tail);
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        match lines.next_back() {
+            // A line cut at the start of the tail would not parse; one this
+            // long does not happen, but read it all rather than guess.
+            Some(line) if start == 0 || text.trim_end().contains('\n') => {
+                Ok(Some(serde_json::from_str(line)?))
+            }
+            Some(_) => Ok(self.entries()?.pop()),
+            None => Ok(None),
+        }
+    }
+
+    /// Every entry in the log, oldest first.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Error::io(&self.path, e)),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| Error::io(&self.path, e))?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Checks the chain from the first line to the last: that every line
+    /// reads, is numbered next, names the hash of the line before and has
+    /// the hash of its own fields. A missing log is intact and empty.
+    pub fn verify(&self) -> Result<AuditVerification> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(AuditVerification {
+                    entries: 0,
+                    intact: true,
+                    broken_at: None,
+                    problem: None,
+                    last_hash: None,
+                })
+            }
+            Err(e) => return Err(Error::io(&self.path, e)),
+        };
+        let mut checked = 0;
+        let mut prev_hash = GENESIS_HASH.to_string();
+        let broken = |line: u64, problem: String| AuditVerification {
+            entries: line,
+            intact: false,
+            broken_at: Some(line),
+            problem: Some(problem),
+            last_hash: None,
+        };
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| Error::io(&self.path, e))?;
+            let number = index as u64 + 1;
+            if line.trim().is_empty() {
+                return Ok(broken(number, "empty line".into()));
+            }
+            let entry: AuditEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(e) => return Ok(broken(number, format!("not an audit entry: {}", e))),
+            };
+            if entry.seq != number {
+                return Ok(broken(
+                    number,
+                    format!("numbered {} instead of {}", entry.seq, number),
+                ));
+            }
+            if entry.prev_hash != prev_hash {
+                return Ok(broken(number, "does not follow the entry before it".into()));
+            }
+            if entry.compute_hash() != entry.hash {
+                return Ok(broken(number, "changed after it was written".into()));
+            }
+            prev_hash = entry.hash;
+            checked = number;
+        }
+        Ok(AuditVerification {
+            entries: checked,
+            intact: true,
+            broken_at: None,
+            problem: None,
+            last_hash: (checked > 0).then_some(prev_hash),
+        })
+    }
+}
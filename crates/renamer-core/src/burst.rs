@@ -0,0 +1,160 @@
+//! Shots of one moment: bursts, photos taken within a few seconds of each
+//! other, and copies with the same contents. They are found before the rules
+//! run, so a batch numbers each moment once and tells its shots apart by a
+//! letter after the counter (`IMG_0042a`, `IMG_0042b`), or leaves all but
+//! the first shot as they are; see [`crate::preview_bursts`].
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::CancelToken;
+use crate::dedupe::find_duplicates;
+use crate::metadata::{self, DateSource, Metadata};
+
+/// What makes shots one burst, and what to do with them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BurstOptions {
+    /// A photo taken at most this many seconds after another in the same
+    /// folder, by their EXIF or XMP dates, is in its burst; `None` leaves
+    /// times out. Files without such a date are never in one by time.
+    pub within_secs: Option<u64>,
+    /// Files with the same contents are in one burst too.
+    pub same_content: bool,
+    pub action: BurstAction,
+}
+
+impl BurstOptions {
+    /// True if nothing would be grouped.
+    pub fn is_off(&self) -> bool {
+        self.within_secs.is_none() && !self.same_content
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BurstAction {
+    /// Number the burst once, with a letter for each shot after the number.
+    #[default]
+    Letter,
+    /// Rename the first shot and leave the others alone.
+    Skip,
+}
+
+/// Shots of one moment, at least two, in batch order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Burst {
+    pub paths: Vec<PathBuf>,
+}
+
+/// Where a file of a preview is in its burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BurstShot {
+    /// Which burst of the batch, counting from 0 in batch order.
+    pub group: usize,
+    /// Which shot of it, counting from 0.
+    pub shot: usize,
+    /// Left as it is by [`BurstAction::Skip`].
+    pub skipped: bool,
+}
+
+/// The bursts among `paths`, reading the photos' dates and, for
+/// [`BurstOptions::same_content`], their contents.
+pub fn find_bursts(paths: &[PathBuf], options: &BurstOptions) -> Vec<Burst> {
+    let embedded = match options.within_secs {
+        Some(_) => metadata::extract_all(paths).into_iter().map(Some).collect(),
+        None => vec![None; paths.len()],
+    };
+    group(paths, &embedded, options)
+}
+
+/// [`find_bursts`] with what was read from inside the files already, in
+/// the order of `paths`.
+pub(crate) fn group(
+    paths: &[PathBuf],
+    embedded: &[Option<Metadata>],
+    options: &BurstOptions,
+) -> Vec<Burst> {
+    let mut sets = Sets::new(paths.len());
+    if let Some(within) = options.within_secs {
+        let taken = |i: usize| -> Option<NaiveDateTime> {
+            let chain = [DateSource::Exif, DateSource::Xmp];
+            metadata::best_date(&chain, embedded[i].as_ref(), None, "").map(|(date, _)| date)
+        };
+        let mut dated: Vec<(&Path, NaiveDateTime, usize)> = (0..paths.len())
+            .filter_map(|i| Some((paths[i].parent()?, taken(i)?, i)))
+            .collect();
+        dated.sort();
+        for pair in dated.windows(2) {
+            let ((folder, before, a), (next_folder, after, b)) = (pair[0], pair[1]);
+            let apart = (after - before).num_seconds();
+            if folder == next_folder && apart <= within as i64 {
+                sets.join(a, b);
+            }
+        }
+    }
+    if options.same_content {
+        let position: HashMap<&Path, usize> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.as_path(), i))
+            .collect();
+        for copies in find_duplicates(paths, &CancelToken::new()) {
+            let mut members = copies
+                .paths
+                .iter()
+                .filter_map(|p| position.get(p.as_path()));
+            if let Some(&first) = members.next() {
+                members.for_each(|&other| sets.join(first, other));
+            }
+        }
+    }
+
+    let mut bursts: Vec<Burst> = Vec::new();
+    let mut by_root: HashMap<usize, usize> = HashMap::new();
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for i in 0..paths.len() {
+        *sizes.entry(sets.root(i)).or_default() += 1;
+    }
+    for (i, path) in paths.iter().enumerate() {
+        let root = sets.root(i);
+        if sizes[&root] < 2 {
+            continue;
+        }
+        let at = *by_root.entry(root).or_insert_with(|| {
+            bursts.push(Burst { paths: Vec::new() });
+            bursts.len() - 1
+        });
+        bursts[at].paths.push(path.clone());
+    }
+    bursts
+}
+
+/// Disjoint sets of positions in the batch.
+struct Sets {
+    parent: Vec<usize>,
+}
+
+impl Sets {
+    fn new(len: usize) -> Self {
+        Sets {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.root(a), self.root(b));
+        self.parent[a.max(b)] = a.min(b);
+    }
+}
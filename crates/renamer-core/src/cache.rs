@@ -0,0 +1,344 @@
+//! A cache of the slow things read from files, hashes and metadata, kept in
+//! SQLite so reopening a folder of large photos does not read them all again.
+//!
+//! Entries are keyed by path and kept with the size and modification time
+//! the file had when it was read; a file that changed since is read again.
+//! Renaming a file keeps both, so [`FileCache::moved`] carries entries over
+//! to the new path. Thumbnails are cached on disk by
+//! [`crate::thumbnail`] the same way.
+//!
+//! Answers worked out elsewhere from what a file is, such as the app's name
+//! suggestions, are kept too, by a key of the caller's that would usually
+//! hold a content hash; see [`FileCache::lookup`].
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::hash::{hash_file, HashAlgorithm};
+use crate::metadata::{self, MetaValue, Metadata};
+
+/// Bumped when the tables change, or what is read from files does; stored
+/// as SQLite's `user_version`. Version 2 reads XMP dates, 3 email headers,
+/// 4 keeps lookups.
+const SCHEMA_VERSION: u32 = 4;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS hashes (
+    path TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    used_at INTEGER NOT NULL,
+    PRIMARY KEY (path, algorithm)
+);
+CREATE TABLE IF NOT EXISTS metadata (
+    path TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    json TEXT NOT NULL,
+    used_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS lookups (
+    kind TEXT NOT NULL,
+    key TEXT NOT NULL,
+    json TEXT NOT NULL,
+    used_at INTEGER NOT NULL,
+    PRIMARY KEY (kind, key)
+);
+";
+
+/// A metadata value as stored. [`MetaValue`] is untagged, which would read
+/// text that looks like a date back as a date.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Stored {
+    Integer(i64),
+    Date(NaiveDateTime),
+    Text(String),
+}
+
+impl From<&MetaValue> for Stored {
+    fn from(value: &MetaValue) -> Self {
+        match value {
+            MetaValue::Integer(n) => Stored::Integer(*n),
+            MetaValue::Date(d) => Stored::Date(*d),
+            MetaValue::Text(t) => Stored::Text(t.clone()),
+        }
+    }
+}
+
+impl From<Stored> for MetaValue {
+    fn from(value: Stored) -> Self {
+        match value {
+            Stored::Integer(n) => MetaValue::Integer(n),
+            Stored::Date(d) => MetaValue::Date(d),
+            Stored::Text(t) => MetaValue::Text(t),
+        }
+    }
+}
+
+fn algorithm_name(algorithm: HashAlgorithm) -> &'static str {
+    match algorithm {
+        HashAlgorithm::Xxh3 => "xxh3",
+        HashAlgorithm::Sha256 => "sha256",
+        HashAlgorithm::Md5 => "md5",
+    }
+}
+
+fn text(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// The size and modification time (in nanoseconds) of `path`, which an
+/// entry must match to be used.
+fn stamp(path: &Path) -> Option<(i64, i64)> {
+    let stat = fs::metadata(fsutil::long(path)).ok()?;
+    let modified = stat
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some((stat.len() as i64, i64::try_from(modified).ok()?))
+}
+
+fn now() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+/// The cache database. It can be shared between threads; reading a file on a
+/// miss happens outside the lock.
+pub struct FileCache {
+    conn: Mutex<Connection>,
+}
+
+impl FileCache {
+    /// Opens the cache at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let conn = Connection::open(path)?;
+        let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(Error::UnsupportedVersion {
+                path: path.to_path_buf(),
+                version,
+            });
+        }
+        // Losing the last few entries to a power cut only means reading those
+        // files again.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        if version < SCHEMA_VERSION {
+            // Read before there was more to read.
+            conn.execute("DELETE FROM metadata", [])?;
+        }
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(FileCache {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// [`hash_file`], answered from the cache while the file is unchanged.
+    /// Files that cannot be read are hashed uncached, so the error is the
+    /// one reading it gives.
+    pub fn hash(&self, path: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+        let Some((size, modified)) = stamp(path) else {
+            return hash_file(path, algorithm);
+        };
+        let key = text(path);
+        let name = algorithm_name(algorithm);
+        if let Ok(Some(hash)) = self.cached_hash(&key, name, size, modified) {
+            return Ok(hash);
+        }
+        let hash = hash_file(path, algorithm)?;
+        // Failing to store it only means hashing the file again next time.
+        let _ = self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO hashes (path, algorithm, size, modified, hash, used_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![key, name, size, modified, hash, now()],
+        );
+        Ok(hash)
+    }
+
+    fn cached_hash(
+        &self,
+        key: &str,
+        algorithm: &str,
+        size: i64,
+        modified: i64,
+    ) -> rusqlite::Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let hash = conn
+            .query_row(
+                "SELECT hash FROM hashes
+                 WHERE path = ?1 AND algorithm = ?2 AND size = ?3 AND modified = ?4",
+                params![key, algorithm, size, modified],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        if hash.is_some() {
+            conn.execute(
+                "UPDATE hashes SET used_at = ?3 WHERE path = ?1 AND algorithm = ?2",
+                params![key, algorithm, now()],
+            )?;
+        }
+        Ok(hash)
+    }
+
+    /// [`metadata::extract`], answered from the cache while the file is
+    /// unchanged.
+    pub fn metadata(&self, path: &Path) -> Metadata {
+        let Some((size, modified)) = stamp(path) else {
+            return metadata::extract(path);
+        };
+        let key = text(path);
+        if let Ok(Some(values)) = self.cached_metadata(&key, size, modified) {
+            return Metadata {
+                path: path.to_path_buf(),
+                values,
+            };
+        }
+        let found = metadata::extract(path);
+        let values: BTreeMap<&str, Stored> = found
+            .values
+            .iter()
+            .map(|(k, v)| (k.as_str(), Stored::from(v)))
+            .collect();
+        if let Ok(json) = serde_json::to_string(&values) {
+            let _ = self.conn.lock().unwrap().execute(
+                "INSERT OR REPLACE INTO metadata (path, size, modified, json, used_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![key, size, modified, json, now()],
+            );
+        }
+        found
+    }
+
+    fn cached_metadata(
+        &self,
+        key: &str,
+        size: i64,
+        modified: i64,
+    ) -> Result<Option<BTreeMap<String, MetaValue>>> {
+        let conn = self.conn.lock().unwrap();
+        let json = conn
+            .query_row(
+                "SELECT json FROM metadata WHERE path = ?1 AND size = ?2 AND modified = ?3",
+                params![key, size, modified],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        let Some(json) = json else {
+            return Ok(None);
+        };
+        conn.execute(
+            "UPDATE metadata SET used_at = ?2 WHERE path = ?1",
+            params![key, now()],
+        )?;
+        let values: BTreeMap<String, Stored> = serde_json::from_str(&json)?;
+        Ok(Some(
+            values.into_iter().map(|(k, v)| (k, v.into())).collect(),
+        ))
+    }
+
+    /// Carries the entries of files that were renamed from the first path of
+    /// each pair to the second over to their new path.
+    pub fn moved<'a>(&self, moves: impl IntoIterator<Item = (&'a Path, &'a Path)>) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (from, to) in moves {
+            let (from, to) = (text(from), text(to));
+            for table in ["hashes", "metadata"] {
+                tx.execute(&format!("DELETE FROM {} WHERE path = ?1", table), [&to])?;
+                tx.execute(
+                    &format!("UPDATE {} SET path = ?2 WHERE path = ?1", table),
+                    [&from, &to],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The JSON kept for `key` among the lookups of `kind`, e.g.
+    /// `suggestion`, if any.
+    pub fn lookup(&self, kind: &str, key: &str) -> Option<String> {
+        let conn = self.conn.lock().unwrap();
+        let json = conn
+            .query_row(
+                "SELECT json FROM lookups WHERE kind = ?1 AND key = ?2",
+                params![kind, key],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .ok()??;
+        let _ = conn.execute(
+            "UPDATE lookups SET used_at = ?3 WHERE kind = ?1 AND key = ?2",
+            params![kind, key, now()],
+        );
+        Some(json)
+    }
+
+    /// Keeps `json` for `key` among the lookups of `kind`, in place of what
+    /// was kept for it before.
+    pub fn store_lookup(&self, kind: &str, key: &str, json: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO lookups (kind, key, json, used_at) VALUES (?1, ?2, ?3, ?4)",
+            params![kind, key, json, now()],
+        )?;
+        Ok(())
+    }
+
+    /// Drops the lookups of `kind`, once what they were worked out from
+    /// changed.
+    pub fn clear_lookups(&self, kind: &str) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM lookups WHERE kind = ?1", [kind])?;
+        Ok(())
+    }
+
+    /// Drops the entries not used for `days` days and gives the space they
+    /// took back to the disk. Returns how many it dropped.
+    pub fn prune(&self, days: u32) -> Result<usize> {
+        let cutoff = (Utc::now() - Duration::days(days.into())).timestamp_millis();
+        let conn = self.conn.lock().unwrap();
+        let mut dropped = 0;
+        for table in ["hashes", "metadata", "lookups"] {
+            dropped += conn.execute(
+                &format!("DELETE FROM {} WHERE used_at < ?1", table),
+                [cutoff],
+            )?;
+        }
+        if dropped > 0 {
+            // The copy VACUUM makes goes to the write-ahead log first.
+            conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+        }
+        Ok(dropped)
+    }
+
+    /// Drops every entry.
+    pub fn clear(&self) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute_batch("DELETE FROM hashes; DELETE FROM metadata; DELETE FROM lookups;")?;
+        Ok(())
+    }
+}
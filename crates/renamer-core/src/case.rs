@@ -0,0 +1,171 @@
+//! Unicode-aware case conversion for rename rules.
+//!
+//! Conversions go through the full Unicode case mappings from `std` (so `ß`
+//! upper-cases to `SS`), with an optional Turkish/Azeri locale that maps the
+//! dotted and dotless `i` correctly.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseMode {
+    Upper,
+    Lower,
+    Title,
+    Sentence,
+    Camel,
+    Snake,
+    Kebab,
+}
+
+/// Locale rules that change how individual letters map between cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CaseLocale {
+    turkic: bool,
+}
+
+impl CaseLocale {
+    /// Parses a BCP 47 tag such as `tr` or `az-Latn`; unknown tags fall back
+    /// to the default Unicode mappings.
+    pub fn from_tag(tag: Option<&str>) -> Self {
+        let lang = tag
+            .and_then(|t| t.split(['-', '_']).next())
+            .map(str::to_ascii_lowercase);
+        CaseLocale {
+            turkic: matches!(lang.as_deref(), Some("tr" | "az")),
+        }
+    }
+
+    fn push_upper(&self, c: char, out: &mut String) {
+        match c {
+            'i' if self.turkic => out.push('İ'),
+            _ => out.extend(c.to_uppercase()),
+        }
+    }
+
+    fn push_lower(&self, c: char, out: &mut String) {
+        match c {
+            'I' if self.turkic => out.push('ı'),
+            'İ' if self.turkic => out.push('i'),
+            _ => out.extend(c.to_lowercase()),
+        }
+    }
+
+    fn upper(&self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        s.chars().for_each(|c| self.push_upper(c, &mut out));
+        out
+    }
+
+    fn lower(&self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        s.chars().for_each(|c| self.push_lower(c, &mut out));
+        out
+    }
+
+    /// Upper-cases the first character and lower-cases the rest.
+    fn capitalize(&self, word: &str) -> String {
+        let mut out = String::with_capacity(word.len());
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            self.push_upper(first, &mut out);
+        }
+        chars.for_each(|c| self.push_lower(c, &mut out));
+        out
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\''
+}
+
+/// Splits on anything that is not a letter or digit and on lower→upper
+/// transitions, so `myHolidayPhoto`, `my_holiday photo` and `MY-HOLIDAY-PHOTO`
+/// all yield the same words.
+fn words(s: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut prev: Option<char> = None;
+    for (i, c) in s.char_indices() {
+        if !c.is_alphanumeric() {
+            if let Some(st) = start.take() {
+                words.push(&s[st..i]);
+            }
+        } else {
+            let boundary = prev.is_some_and(|p| p.is_lowercase() && c.is_uppercase());
+            match start {
+                Some(st) if boundary => {
+                    words.push(&s[st..i]);
+                    start = Some(i);
+                }
+                None => start = Some(i),
+                _ => {}
+            }
+        }
+        prev = Some(c);
+    }
+    if let Some(st) = start {
+        words.push(&s[st..]);
+    }
+    words
+}
+
+pub fn convert(s: &str, mode: CaseMode, locale: CaseLocale) -> String {
+    match mode {
+        CaseMode::Upper => locale.upper(s),
+        CaseMode::Lower => locale.lower(s),
+        CaseMode::Title => {
+            // Keep separators as they are, only recase each word.
+            let mut out = String::with_capacity(s.len());
+            let mut at_word_start = true;
+            for c in s.chars() {
+                if is_word_char(c) {
+                    if at_word_start {
+                        locale.push_upper(c, &mut out);
+                    } else {
+                        locale.push_lower(c, &mut out);
+                    }
+                    at_word_start = false;
+                } else {
+                    out.push(c);
+                    at_word_start = true;
+                }
+            }
+            out
+        }
+        CaseMode::Sentence => {
+            let mut out = String::with_capacity(s.len());
+            let mut seen_letter = false;
+            for c in s.chars() {
+                if !seen_letter && c.is_alphabetic() {
+                    locale.push_upper(c, &mut out);
+                    seen_letter = true;
+                } else {
+                    locale.push_lower(c, &mut out);
+                }
+            }
+            out
+        }
+        CaseMode::Camel => words(s)
+            .iter()
+            .enumerate()
+            .map(|(i, w)| {
+                if i == 0 {
+                    locale.lower(w)
+                } else {
+                    locale.capitalize(w)
+                }
+            })
+            .collect(),
+        CaseMode::Snake => words(s)
+            .iter()
+            .map(|w| locale.lower(w))
+            .collect::<Vec<_>>()
+            .join("_"),
+        CaseMode::Kebab => words(s)
+            .iter()
+            .map(|w| locale.lower(w))
+            .collect::<Vec<_>>()
+            .join("-"),
+    }
+}
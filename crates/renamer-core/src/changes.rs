@@ -0,0 +1,144 @@
+//! Noticing when the files of a loaded list are added, removed or renamed
+//! by another program, so a preview is not applied to paths that are gone.
+//!
+//! The folders holding the files are watched, not the files, so a file
+//! moved back in or created under a previewed target name is seen too.
+//! Changes are collected until the folders have been quiet for a moment,
+//! or for at most a second, and then reported together; whether a path
+//! counts as added or removed is decided by whether it exists at that
+//! point. Content changes are not reported, and neither are the hidden
+//! files the executor uses while it renames.
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::preview::PreviewRow;
+
+/// How long the folders must be quiet before changes are reported.
+const QUIET: Duration = Duration::from_millis(200);
+
+/// Longest changes wait in a folder that never goes quiet.
+const MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// Paths that appeared or disappeared in the watched folders.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceChanges {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl SourceChanges {
+    /// Ids of the rows these changes make stale: the file to rename is
+    /// gone, or another file now has the name it would get.
+    pub fn stale_rows(&self, rows: &[PreviewRow]) -> Vec<usize> {
+        let removed: HashSet<&Path> = self.removed.iter().map(PathBuf::as_path).collect();
+        let added: HashSet<&Path> = self.added.iter().map(PathBuf::as_path).collect();
+        rows.iter()
+            .filter(|row| {
+                removed.contains(row.source.as_path())
+                    || (row.target != row.source && added.contains(row.target.as_path()))
+            })
+            .map(|row| row.id)
+            .collect()
+    }
+}
+
+/// Watches the folders of a file list until dropped.
+pub struct SourceWatcher {
+    watcher: Option<RecommendedWatcher>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SourceWatcher {
+    /// Starts watching the folders `paths` are in, calling `on_change` from a
+    /// background thread.
+    pub fn start(
+        paths: &[PathBuf],
+        on_change: impl FnMut(SourceChanges) + Send + 'static,
+    ) -> Result<Self> {
+        let folders: BTreeSet<&Path> = paths.iter().filter_map(|p| p.parent()).collect();
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|source| Error::Watch {
+            path: folders.first().map(|p| p.to_path_buf()).unwrap_or_default(),
+            source,
+        })?;
+        for folder in folders {
+            watcher
+                .watch(folder, RecursiveMode::NonRecursive)
+                .map_err(|source| Error::Watch {
+                    path: folder.to_path_buf(),
+                    source,
+                })?;
+        }
+        let worker = thread::spawn(move || run(rx, on_change));
+        Ok(SourceWatcher {
+            watcher: Some(watcher),
+            worker: Some(worker),
+        })
+    }
+}
+
+impl Drop for SourceWatcher {
+    fn drop(&mut self) {
+        // Dropping the watcher closes the channel, which ends the worker.
+        self.watcher.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The executor's temporary and backup names, `.name.sortify-…`.
+fn is_ours(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|n| n.to_string_lossy().contains(".sortify-"))
+}
+
+fn run(
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    mut on_change: impl FnMut(SourceChanges),
+) {
+    let mut pending: BTreeSet<PathBuf> = BTreeSet::new();
+    // When the oldest pending change came in.
+    let mut since: Option<Instant> = None;
+    loop {
+        let quiet = match events.recv_timeout(QUIET) {
+            Ok(Ok(event)) => {
+                let moved = matches!(
+                    event.kind,
+                    EventKind::Create(_)
+                        | EventKind::Remove(_)
+                        | EventKind::Modify(ModifyKind::Name(_))
+                );
+                if moved {
+                    pending.extend(event.paths.into_iter().filter(|p| !is_ours(p)));
+                }
+                false
+            }
+            // A lost event at worst leaves a row to fail at planning.
+            Ok(Err(_)) => false,
+            Err(RecvTimeoutError::Timeout) => true,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if pending.is_empty() {
+            continue;
+        }
+        let first = *since.get_or_insert_with(Instant::now);
+        if quiet || first.elapsed() >= MAX_WAIT {
+            since = None;
+            let (added, removed) = std::mem::take(&mut pending)
+                .into_iter()
+                .partition(|path| fsutil::exists(path));
+            on_change(SourceChanges { added, removed });
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Tests on a file that decide whether a [`Rule::When`] runs its rules.
+//!
+//! Conditions look at the file as it is at that point of the chain: the
+//! extension and pattern tests read the name earlier rules produced, size and
+//! metadata come from the file on disk.
+//!
+//! [`Rule::When`]: crate::rules::Rule::When
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::metadata;
+use crate::rules::{split_name, RegexRule};
+use crate::template::FileContext;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// The name has one of `extensions`, given without the dot and compared
+    /// ignoring case.
+    Extension { extensions: Vec<String> },
+    /// The file is larger than `bytes`.
+    LargerThan { bytes: u64 },
+    /// The file is smaller than `bytes`.
+    SmallerThan { bytes: u64 },
+    /// `pattern` matches somewhere in the name.
+    Matches {
+        pattern: String,
+        #[serde(default)]
+        case_insensitive: bool,
+    },
+    /// The file has a value for metadata `key`, e.g. `exif.date_taken`.
+    HasMetadata { key: String },
+}
+
+/// Whether the conditions of one [`Rule::When`] held for a file.
+///
+/// [`Rule::When`]: crate::rules::Rule::When
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConditionCheck {
+    /// Where the rule is: its index in the chain, followed by its index in
+    /// each enclosing rule's `rules` for nested conditions.
+    pub rule: Vec<usize>,
+    /// Whether each condition held, in the order given.
+    pub matched: Vec<bool>,
+    /// The rule's own rules ran.
+    pub applied: bool,
+}
+
+/// A condition with its pattern compiled.
+#[derive(Debug, Clone)]
+pub(crate) enum Check {
+    Extension(Vec<String>),
+    LargerThan(u64),
+    SmallerThan(u64),
+    Matches(Regex),
+    HasMetadata(String),
+}
+
+impl Check {
+    pub(crate) fn compile(condition: &Condition) -> Result<Self> {
+        Ok(match condition {
+            Condition::Extension { extensions } => Check::Extension(
+                extensions
+                    .iter()
+                    .map(|e| e.trim_start_matches('.').to_lowercase())
+                    .collect(),
+            ),
+            Condition::LargerThan { bytes } => Check::LargerThan(*bytes),
+            Condition::SmallerThan { bytes } => Check::SmallerThan(*bytes),
+            Condition::Matches {
+                pattern,
+                case_insensitive,
+            } => Check::Matches(
+                RegexRule {
+                    pattern: pattern.clone(),
+                    replacement: String::new(),
+                    case_insensitive: *case_insensitive,
+                    replace_all: false,
+                }
+                .compile()?,
+            ),
+            Condition::HasMetadata { key } => {
+                let (key, _) = metadata::kind(key).ok_or_else(|| Error::InvalidCondition {
+                    message: format!("unknown metadata key `{}`", key),
+                })?;
+                Check::HasMetadata(key.to_string())
+            }
+        })
+    }
+
+    pub(crate) fn needs_metadata(&self) -> bool {
+        matches!(self, Check::LargerThan(_) | Check::SmallerThan(_))
+    }
+
+    pub(crate) fn needs_embedded(&self) -> bool {
+        matches!(self, Check::HasMetadata(_))
+    }
+
+    /// Whether the condition holds for the file `ctx` describes, now called
+    /// `name`. Sizes that could not be read never match.
+    pub(crate) fn holds(&self, name: &str, ctx: &FileContext) -> bool {
+        match self {
+            Check::Extension(extensions) => {
+                let ext = split_name(name).1.trim_start_matches('.').to_lowercase();
+                extensions.contains(&ext)
+            }
+            Check::LargerThan(bytes) => ctx.size.is_some_and(|size| size > *bytes),
+            Check::SmallerThan(bytes) => ctx.size.is_some_and(|size| size < *bytes),
+            Check::Matches(re) => re.is_match(name),
+            Check::HasMetadata(key) => ctx.embedded.as_ref().is_some_and(|m| m.get(key).is_some()),
+        }
+    }
+}
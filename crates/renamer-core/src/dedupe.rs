@@ -0,0 +1,248 @@
+//! Finding files with identical contents.
+//!
+//! Files are compared in rounds that each rule out most candidates cheaply:
+//! first by size, then by a quick XXH3 hash of their first and last 64 KiB,
+//! and only files that still match are read in full. On an archive of
+//! photos and videos the first two rounds settle nearly every file, so the
+//! full hash is only taken of the actual copies. [`DedupeOptions`] sets how
+//! much the quick round reads and whether the full round runs at all.
+
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::CancelToken;
+use crate::executor::PROGRESS_INTERVAL;
+use crate::fsutil;
+use crate::hash::{self, HashAlgorithm};
+use crate::power::SleepGuard;
+
+/// How much of each end of a file the quick round hashes by default.
+const SAMPLE_SIZE: u64 = 64 << 10;
+
+/// How [`find_duplicates_with_progress`] compares files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DedupeOptions {
+    /// Bytes the quick round hashes from the start and from the end of
+    /// each file; 0 leaves the round out, and the full round then always
+    /// runs.
+    pub sample_size: u64,
+    /// Confirms files alike after the quick round by hashing them in full.
+    /// Without it, files of the same size whose samples match are taken as
+    /// copies: much faster on large files, and rarely wrong for photos and
+    /// videos, but files that differ only in the middle, such as disk
+    /// images, are then grouped too.
+    pub full_hash: bool,
+    /// The hash of the full round.
+    pub algorithm: HashAlgorithm,
+}
+
+impl Default for DedupeOptions {
+    fn default() -> Self {
+        DedupeOptions {
+            sample_size: SAMPLE_SIZE,
+            full_hash: true,
+            algorithm: HashAlgorithm::Sha256,
+        }
+    }
+}
+
+/// The rounds of a search, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupeStage {
+    Size,
+    Sample,
+    Full,
+}
+
+/// Progress of [`find_duplicates_with_progress`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DedupeProgress {
+    pub stage: DedupeStage,
+    /// Files compared in this round so far.
+    pub completed: usize,
+    /// Files this round compares: those the rounds before left alike.
+    pub total: usize,
+    /// The file just compared.
+    pub current: PathBuf,
+    /// File data this round read so far.
+    pub bytes: u64,
+}
+
+/// Files with the same contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    /// The hash of the contents, SHA-256 unless [`DedupeOptions`] said
+    /// otherwise; without the full round, the XXH3 hash of the samples.
+    pub hash: String,
+    /// In the order given, so the first is the natural one to keep.
+    pub paths: Vec<PathBuf>,
+}
+
+/// Counts the files of one round and reports them at most every 100 ms,
+/// and always for the last one.
+struct Round<'a> {
+    stage: DedupeStage,
+    total: usize,
+    completed: AtomicUsize,
+    bytes: AtomicU64,
+    last: Mutex<(Option<Instant>, usize)>,
+    progress: &'a (dyn Fn(&DedupeProgress) + Sync),
+}
+
+impl<'a> Round<'a> {
+    fn new(
+        stage: DedupeStage,
+        total: usize,
+        progress: &'a (dyn Fn(&DedupeProgress) + Sync),
+    ) -> Self {
+        Round {
+            stage,
+            total,
+            completed: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            last: Mutex::new((None, 0)),
+            progress,
+        }
+    }
+
+    fn done(&self, path: &Path, read: u64) {
+        let bytes = self.bytes.fetch_add(read, Ordering::Relaxed) + read;
+        let done = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap();
+        let due = last
+            .0
+            .map_or(true, |t| now.duration_since(t) >= PROGRESS_INTERVAL);
+        if done > last.1 && (due || done == self.total) {
+            *last = (Some(now), done);
+            (self.progress)(&DedupeProgress {
+                stage: self.stage,
+                completed: done,
+                total: self.total,
+                current: path.to_path_buf(),
+                bytes,
+            });
+        }
+    }
+}
+
+/// Splits each group by `key`, keeping order and dropping files without a
+/// key and groups left with a single file.
+fn split<K>(
+    groups: Vec<Vec<usize>>,
+    key: impl Fn(usize) -> Option<K> + Sync,
+    cancel: &CancelToken,
+) -> Vec<(K, Vec<usize>)>
+where
+    K: Clone + Eq + Hash + Send,
+{
+    groups
+        .into_par_iter()
+        .flat_map_iter(|group| {
+            let keys: Vec<Option<K>> = group
+                .par_iter()
+                .map(|&i| if cancel.is_cancelled() { None } else { key(i) })
+                .collect();
+            let mut slots: HashMap<K, usize> = HashMap::new();
+            let mut split: Vec<(K, Vec<usize>)> = Vec::new();
+            for (i, key) in group.into_iter().zip(keys) {
+                let Some(key) = key else {
+                    continue;
+                };
+                match slots.get(&key) {
+                    Some(&slot) => split[slot].1.push(i),
+                    None => {
+                        slots.insert(key.clone(), split.len());
+                        split.push((key, vec![i]));
+                    }
+                }
+            }
+            split.into_iter().filter(|(_, files)| files.len() > 1)
+        })
+        .collect()
+}
+
+/// Groups the regular files among `paths` that have identical, non-empty
+/// contents, with the default [`DedupeOptions`]. Directories, symlinks and
+/// files that cannot be read are left out. Once `cancel` fires the remaining
+/// files are skipped, so only groups confirmed up to then are returned.
+pub fn find_duplicates(paths: &[PathBuf], cancel: &CancelToken) -> Vec<DuplicateGroup> {
+    find_duplicates_with_progress(paths, &DedupeOptions::default(), &|_| {}, cancel)
+}
+
+/// [`find_duplicates`] with the rounds set by `options`, calling `progress`
+/// as files are compared.
+pub fn find_duplicates_with_progress(
+    paths: &[PathBuf],
+    options: &DedupeOptions,
+    progress: &(dyn Fn(&DedupeProgress) + Sync),
+    cancel: &CancelToken,
+) -> Vec<DuplicateGroup> {
+    let _awake = SleepGuard::acquire("Finding duplicates");
+    let round = Round::new(DedupeStage::Size, paths.len(), progress);
+    let sizes: Vec<Option<u64>> = paths
+        .par_iter()
+        .map(|path| {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            let meta = fs::symlink_metadata(fsutil::long(path)).ok();
+            round.done(path, 0);
+            let meta = meta?;
+            (meta.is_file() && meta.len() > 0).then_some(meta.len())
+        })
+        .collect();
+    let size = |i: usize| sizes[i].unwrap_or(0);
+    let all = vec![(0..paths.len()).collect()];
+    let mut groups: Vec<(String, Vec<usize>)> = split(all, |i| sizes[i], cancel)
+        .into_iter()
+        .map(|(_, files)| (String::new(), files))
+        .collect();
+
+    let files_in = |groups: &[(String, Vec<usize>)]| groups.iter().map(|(_, f)| f.len()).sum();
+    if options.sample_size > 0 {
+        let sample = options.sample_size;
+        let round = Round::new(DedupeStage::Sample, files_in(&groups), progress);
+        groups = split(
+            groups.into_iter().map(|(_, files)| files).collect(),
+            |i| {
+                let hash = hash::hash_ends(&paths[i], size(i), HashAlgorithm::Xxh3, sample).ok();
+                round.done(&paths[i], size(i).min(sample.saturating_mul(2)));
+                hash
+            },
+            cancel,
+        );
+    }
+    if options.full_hash || options.sample_size == 0 {
+        let round = Round::new(DedupeStage::Full, files_in(&groups), progress);
+        groups = split(
+            groups.into_iter().map(|(_, files)| files).collect(),
+            |i| {
+                let hash = hash::hash_file(&paths[i], options.algorithm).ok();
+                round.done(&paths[i], size(i));
+                hash
+            },
+            cancel,
+        );
+    }
+
+    groups
+        .into_iter()
+        .map(|(hash, files)| DuplicateGroup {
+            size: size(files[0]),
+            hash,
+            paths: files.into_iter().map(|i| paths[i].clone()).collect(),
+        })
+        .collect()
+}
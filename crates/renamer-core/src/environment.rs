@@ -0,0 +1,104 @@
+//! What the optional parts of the engine can do on this machine, for a
+//! self-test to show: whether HEIC photos get previews, whether `{ocr.*}`
+//! tokens have a program to read with and whether paths past the old
+//! Windows limit of 260 characters can be written.
+//!
+//! Nothing here fails: a part that is missing is reported as unavailable,
+//! with what it takes to get it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::fsutil;
+use crate::heif;
+use crate::ocr::OcrCommand;
+
+/// Whether an optional part works, and what was found or is missing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Feature {
+    pub available: bool,
+    pub detail: String,
+}
+
+impl Feature {
+    fn new(available: bool, detail: impl Into<String>) -> Self {
+        Feature {
+            available,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Where `program` is: the path itself if it has a folder in it, otherwise
+/// the first match on `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let given = Path::new(program);
+    if given.components().count() > 1 {
+        return given.is_file().then(|| given.to_path_buf());
+    }
+    let extensions: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{}{}", program, ext)))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Previews of HEIC and AVIF photos. Their EXIF data is always read; the
+/// photos without a JPEG thumbnail of their own need the system's decoder.
+pub fn heif_previews() -> Feature {
+    match heif::DECODER {
+        None => Feature::new(
+            false,
+            "only photos with a thumbnail of their own get previews on this system",
+        ),
+        Some(program) => match find_program(program) {
+            Some(path) => Feature::new(true, format!("decoded with {}", path.display())),
+            None => Feature::new(
+                false,
+                format!(
+                    "{} is not installed; only photos with a thumbnail of their own get previews",
+                    program
+                ),
+            ),
+        },
+    }
+}
+
+/// The program `{ocr.*}` tokens run.
+pub fn ocr(command: &OcrCommand) -> Feature {
+    match find_program(&command.program) {
+        Some(path) => Feature::new(true, format!("reads with {}", path.display())),
+        None => Feature::new(false, format!("{} is not installed", command.program)),
+    }
+}
+
+/// Writes, then removes, a file whose path is longer than 260 characters
+/// in a folder made for it in `dir`.
+pub fn long_paths(dir: &Path) -> Feature {
+    let root = dir.join(format!(".long-path-test-{}", Uuid::new_v4().simple()));
+    let mut path = root.clone();
+    while path.as_os_str().len() <= 300 {
+        path.push("x".repeat(40));
+    }
+    let file = path.join("test.txt");
+    let written =
+        fs::create_dir_all(fsutil::long(&path)).and_then(|_| fs::write(fsutil::long(&file), b"ok"));
+    let _ = fs::remove_dir_all(fsutil::long(&root));
+    match written {
+        Ok(()) => Feature::new(
+            true,
+            format!("wrote a path of {} characters", file.as_os_str().len()),
+        ),
+        Err(e) => Feature::new(false, format!("{}: {}", dir.display(), e)),
+    }
+}
@@ -0,0 +1,163 @@
+//! Recognising TV episodes and movies in release-style names, for the
+//! `{show}`, `{season}`, `{episode}`, `{episode_title}` and `{year}` template
+//! tokens.
+//!
+//! Episodes are marked `S01E02` (also `S01E02E03` for several in one file),
+//! `1x02` or `Season 1 Episode 2`; the show is whatever comes before the
+//! marker. Without a marker, a name with a year such as `Heat (1995)` or
+//! `Heat.1995.1080p` is read as a movie. Release details (`1080p`, `WEB-DL`,
+//! `x264`, the group after them) and `[bracketed]` tags are dropped, and dots
+//! or underscores used as spaces become spaces.
+
+use std::sync::OnceLock;
+
+use regex::{Regex, RegexBuilder};
+
+/// What a name says about the show or movie in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Episode {
+    /// The show, or the movie title.
+    pub show: String,
+    pub season: Option<u32>,
+    /// The first episode, for files holding several.
+    pub episode: Option<u32>,
+    /// Release year, e.g. from `Doctor Who (2005)`.
+    pub year: Option<u32>,
+    /// The episode's own title, written after the marker.
+    pub title: Option<String>,
+}
+
+fn regex(pattern: &str) -> Regex {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .unwrap()
+}
+
+/// Episode markers, each between non-alphanumeric characters.
+fn markers() -> &'static [Regex] {
+    static MARKERS: OnceLock<Vec<Regex>> = OnceLock::new();
+    MARKERS.get_or_init(|| {
+        [
+            r"s(?P<s>\d{1,2})[ ._-]?e(?P<e>\d{1,3})(?:[ ._-]?-?e\d{1,3})*",
+            r"(?P<s>\d{1,2})x(?P<e>\d{2,3})",
+            r"season[ ._-]?(?P<s>\d{1,2})[ ._-]*episode[ ._-]?(?P<e>\d{1,3})",
+        ]
+        .iter()
+        .map(|m| regex(&format!(r"(?:^|[^a-z0-9])(?P<marker>{})(?:[^a-z0-9]|$)", m)))
+        .collect()
+    })
+}
+
+/// The first release detail, where everything after is technical.
+fn junk() -> &'static Regex {
+    static JUNK: OnceLock<Regex> = OnceLock::new();
+    JUNK.get_or_init(|| {
+        regex(
+            r"(?:^|[^a-z0-9])(?P<junk>2160p|1080[pi]|720p|576p|480p|4k|uhd|hdr(?:10)?|blu-?ray|bdrip|brrip|web-?dl|webrip|hdtv|dvdrip|hdrip|remux|[xh]\.?26[45]|hevc|avc|xvid|divx|aac|ac3|dts|ddp?5\.1|10bit|proper|repack|unrated|internal)(?:[^a-z0-9]|$)",
+        )
+    })
+}
+
+/// Years in `text`, leaving out ones run into a longer word or number.
+fn years(text: &str) -> impl Iterator<Item = regex::Match<'_>> {
+    static YEAR: OnceLock<Regex> = OnceLock::new();
+    // The character after is checked by hand, so that in `2049.2017` the
+    // dot can still start the second match.
+    YEAR.get_or_init(|| regex(r"(?:^|[^a-z0-9])\(?(?P<year>(?:19|20)\d{2})"))
+        .captures_iter(text)
+        .filter_map(|c| c.name("year"))
+        .filter(move |m| !text[m.end()..].starts_with(|c: char| c.is_alphanumeric()))
+}
+
+fn bracketed() -> &'static Regex {
+    static BRACKETED: OnceLock<Regex> = OnceLock::new();
+    BRACKETED.get_or_init(|| regex(r"\[[^\]]*\]|\{[^}]*\}"))
+}
+
+/// Turns separators into spaces and trims leftover punctuation. Dots stay
+/// when the name already uses spaces (`Mr. Robot`).
+fn clean(text: &str) -> String {
+    let text = text.replace('_', " ");
+    let text = if text.contains(' ') {
+        text
+    } else {
+        text.replace('.', " ")
+    };
+    let text = text.replace("()", " ");
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| matches!(c, '-' | '–' | ' ' | '.' | '('))
+        .to_string()
+}
+
+/// Splits a trailing year off a show name: `Doctor Who (2005)`.
+fn split_year(show: &str) -> (&str, Option<u32>) {
+    let found = years(show).last().filter(|m| {
+        m.start() > 0
+            && show[m.end()..]
+                .trim_matches([')', ' ', '.', '_', '-'])
+                .is_empty()
+    });
+    match found {
+        Some(m) => (&show[..m.start()], m.as_str().parse().ok()),
+        None => (show, None),
+    }
+}
+
+fn stem(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty()
+                && (1..=4).contains(&ext.len())
+                && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            stem
+        }
+        _ => name,
+    }
+}
+
+/// Reads the show, season and episode, or a movie's title and year, from
+/// `name`. `None` if the name has neither an episode marker nor a year.
+pub fn parse(name: &str) -> Option<Episode> {
+    let stem = bracketed().replace_all(stem(name), " ");
+    let stem = stem.trim();
+
+    let marked = markers().iter().find_map(|re| re.captures(stem));
+    if let Some(caps) = marked {
+        let marker = caps.name("marker")?;
+        let (show, year) = split_year(&stem[..marker.start()]);
+        let rest = &stem[marker.end()..];
+        let rest = match junk().captures(rest).and_then(|c| c.name("junk")) {
+            Some(m) => &rest[..m.start()],
+            None => rest,
+        };
+        let title = clean(rest);
+        return Some(Episode {
+            show: clean(show),
+            season: caps.name("s")?.as_str().parse().ok(),
+            episode: caps.name("e")?.as_str().parse().ok(),
+            year,
+            title: (!title.is_empty()).then_some(title),
+        });
+    }
+
+    let end = junk()
+        .captures(stem)
+        .and_then(|c| c.name("junk"))
+        .map_or(stem.len(), |m| m.start());
+    let found = years(&stem[..end]).filter(|m| m.start() > 0).last()?;
+    let show = clean(&stem[..found.start()]);
+    if show.is_empty() {
+        return None;
+    }
+    Some(Episode {
+        show,
+        season: None,
+        episode: None,
+        year: found.as_str().parse().ok(),
+        title: None,
+    })
+}
@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::executor::StaleReason;
+use crate::{locks, share};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid pattern `{pattern}`: {source}")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    #[error("invalid glob `{pattern}`: {source}")]
+    InvalidGlob {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+
+    #[error("invalid ignore pattern `{pattern}`: {message}")]
+    InvalidIgnorePattern { pattern: String, message: String },
+
+    #[error("invalid condition: {message}")]
+    InvalidCondition { message: String },
+
+    #[error("invalid script: {message}")]
+    InvalidScript { message: String },
+
+    #[error("{}: not a usable plugin: {message}", path.display())]
+    InvalidPlugin { path: PathBuf, message: String },
+
+    #[error("no enabled plugin `{plugin}` provides the rule `{rule}`")]
+    UnknownPluginRule { plugin: String, rule: String },
+
+    #[error("invalid template `{template}`: {message}")]
+    InvalidTemplate { template: String, message: String },
+
+    #[error(
+        "replacement `{replacement}` contains characters the target filesystem does not allow"
+    )]
+    InvalidReplacement { replacement: String },
+
+    #[error("invalid naming policy `{policy}`: {message}")]
+    InvalidPolicy { policy: String, message: String },
+
+    #[error("the plan has conflicts and its collision strategy is `fail`, or names that break a naming policy")]
+    PlanBlocked,
+
+    #[error("the plan overwrites existing files and overwriting was not confirmed")]
+    OverwriteNotConfirmed,
+
+    #[error("the plan changes file extensions and extension changes were not allowed")]
+    ExtensionChangeNotConfirmed,
+
+    #[error("safe mode is on: files are not renamed, moved or trashed")]
+    SafeMode,
+
+    #[error("{}: the target is on another volume and cross-volume moves are not allowed", path.display())]
+    CrossVolume { path: PathBuf },
+
+    #[error("{}: the network share did not answer within {secs}s", path.display())]
+    ShareUnreachable { path: PathBuf, secs: u64 },
+
+    #[error("{}: another batch ({batch_id}) is still renaming it", path.display())]
+    TargetInFlight { path: PathBuf, batch_id: Uuid },
+
+    #[error("{}: {reason}", path.display())]
+    Stale { path: PathBuf, reason: StaleReason },
+
+    #[error("batch {batch_id} has no file {id}")]
+    NotInBatch { batch_id: Uuid, id: usize },
+
+    #[error("{}: written by a newer version (format {version})", path.display())]
+    UnsupportedVersion { path: PathBuf, version: u32 },
+
+    #[error("preset names cannot be empty")]
+    InvalidPresetName,
+
+    #[error("{}: outside the folders opened in this session", path.display())]
+    OutOfScope { path: PathBuf },
+
+    #[error("{}: outside the folders preset `{preset}` may rename in", path.display())]
+    PresetScope { preset: String, path: PathBuf },
+
+    #[error("unknown locale `{locale}`")]
+    UnknownLocale { locale: String },
+
+    #[error("{}: cannot make a preview: {message}", path.display())]
+    Thumbnail { path: PathBuf, message: String },
+
+    #[error("{}: cannot watch the folder: {source}", path.display())]
+    Watch {
+        path: PathBuf,
+        #[source]
+        source: notify::Error,
+    },
+
+    #[error("{host}: {message}")]
+    Remote { host: String, message: String },
+
+    #[error("{}: not a usable archive: {message}", path.display())]
+    Archive { path: PathBuf, message: String },
+
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// What kind of error this is, for frontends to localize and handle it
+    /// by and the CLI to pick its exit code by; unlike the message, it stays
+    /// the same across versions.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io { source, .. } if locks::is_locked(source) => ErrorCode::FileLocked,
+            Error::Io { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => ErrorCode::NotFound,
+                io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+                io::ErrorKind::AlreadyExists => ErrorCode::AlreadyExists,
+                _ => ErrorCode::Io,
+            },
+            Error::InvalidPattern { .. } => ErrorCode::InvalidPattern,
+            Error::InvalidGlob { .. } => ErrorCode::InvalidGlob,
+            Error::InvalidIgnorePattern { .. } => ErrorCode::InvalidIgnorePattern,
+            Error::InvalidCondition { .. } => ErrorCode::InvalidCondition,
+            Error::InvalidScript { .. } => ErrorCode::InvalidScript,
+            Error::InvalidPlugin { .. } => ErrorCode::InvalidPlugin,
+            Error::UnknownPluginRule { .. } => ErrorCode::UnknownPluginRule,
+            Error::InvalidTemplate { .. } => ErrorCode::InvalidTemplate,
+            Error::InvalidReplacement { .. } => ErrorCode::InvalidReplacement,
+            Error::InvalidPolicy { .. } => ErrorCode::InvalidPolicy,
+            Error::PlanBlocked => ErrorCode::PlanBlocked,
+            Error::OverwriteNotConfirmed => ErrorCode::OverwriteNotConfirmed,
+            Error::ExtensionChangeNotConfirmed => ErrorCode::ExtensionChangeNotConfirmed,
+            Error::SafeMode => ErrorCode::SafeMode,
+            Error::CrossVolume { .. } => ErrorCode::CrossVolume,
+            Error::ShareUnreachable { .. } => ErrorCode::ShareUnreachable,
+            Error::TargetInFlight { .. } => ErrorCode::TargetInFlight,
+            Error::Stale { .. } => ErrorCode::Stale,
+            Error::NotInBatch { .. } => ErrorCode::NotInBatch,
+            Error::UnsupportedVersion { .. } => ErrorCode::UnsupportedVersion,
+            Error::InvalidPresetName => ErrorCode::InvalidPresetName,
+            Error::OutOfScope { .. } => ErrorCode::OutOfScope,
+            Error::PresetScope { .. } => ErrorCode::PresetScope,
+            Error::UnknownLocale { .. } => ErrorCode::UnknownLocale,
+            Error::Thumbnail { .. } => ErrorCode::Thumbnail,
+            Error::Watch { .. } => ErrorCode::Watch,
+            Error::Remote { .. } => ErrorCode::Remote,
+            Error::Archive { .. } => ErrorCode::Archive,
+            Error::Database(_) => ErrorCode::Database,
+            Error::Csv(_) => ErrorCode::Csv,
+            Error::Json(_) => ErrorCode::Json,
+        }
+    }
+
+    /// The parts of the message a translation of it needs, by name: `path`,
+    /// `pattern`, `template`, `version` and so on, and `detail` for the
+    /// underlying error's own message, which is not translated.
+    pub fn params(&self) -> BTreeMap<&'static str, String> {
+        let mut params = BTreeMap::new();
+        let mut add = |name, value: String| {
+            params.insert(name, value);
+        };
+        match self {
+            Error::Io { path, source } => {
+                add("path", path.display().to_string());
+                add("detail", source.to_string());
+            }
+            Error::InvalidPattern { pattern, source } => {
+                add("pattern", pattern.clone());
+                add("detail", source.to_string());
+            }
+            Error::InvalidGlob { pattern, source } => {
+                add("pattern", pattern.clone());
+                add("detail", source.to_string());
+            }
+            Error::InvalidIgnorePattern { pattern, message } => {
+                add("pattern", pattern.clone());
+                add("detail", message.clone());
+            }
+            Error::InvalidCondition { message } | Error::InvalidScript { message } => {
+                add("detail", message.clone());
+            }
+            Error::InvalidPlugin { path, message }
+            | Error::Thumbnail { path, message }
+            | Error::Archive { path, message } => {
+                add("path", path.display().to_string());
+                add("detail", message.clone());
+            }
+            Error::UnknownPluginRule { plugin, rule } => {
+                add("plugin", plugin.clone());
+                add("rule", rule.clone());
+            }
+            Error::InvalidTemplate { template, message } => {
+                add("template", template.clone());
+                add("detail", message.clone());
+            }
+            Error::InvalidReplacement { replacement } => add("replacement", replacement.clone()),
+            Error::InvalidPolicy { policy, message } => {
+                add("policy", policy.clone());
+                add("detail", message.clone());
+            }
+            Error::CrossVolume { path } | Error::OutOfScope { path } => {
+                add("path", path.display().to_string());
+            }
+            Error::PresetScope { preset, path } => {
+                add("preset", preset.clone());
+                add("path", path.display().to_string());
+            }
+            Error::UnknownLocale { locale } => add("locale", locale.clone()),
+            Error::ShareUnreachable { path, secs } => {
+                add("path", path.display().to_string());
+                add("secs", secs.to_string());
+            }
+            Error::TargetInFlight { path, batch_id } => {
+                add("path", path.display().to_string());
+                add("batch_id", batch_id.to_string());
+            }
+            Error::Stale { path, reason } => {
+                add("path", path.display().to_string());
+                add("reason", reason.code().to_string());
+            }
+            Error::NotInBatch { batch_id, id } => {
+                add("batch_id", batch_id.to_string());
+                add("id", id.to_string());
+            }
+            Error::UnsupportedVersion { path, version } => {
+                add("path", path.display().to_string());
+                add("version", version.to_string());
+            }
+            Error::Watch { path, source } => {
+                add("path", path.display().to_string());
+                add("detail", source.to_string());
+            }
+            Error::Remote { host, message } => {
+                add("host", host.clone());
+                add("detail", message.clone());
+            }
+            Error::Database(e) => add("detail", e.to_string()),
+            Error::Csv(e) => add("detail", e.to_string()),
+            Error::Json(e) => add("detail", e.to_string()),
+            Error::PlanBlocked
+            | Error::OverwriteNotConfirmed
+            | Error::ExtensionChangeNotConfirmed
+            | Error::SafeMode
+            | Error::InvalidPresetName => {}
+        }
+        params
+    }
+
+    /// The file on a network share this failed on, if it failed because the
+    /// share did not answer or the connection to it dropped.
+    pub(crate) fn unreachable_path(&self) -> Option<&Path> {
+        match self {
+            Error::ShareUnreachable { path, .. } => Some(path),
+            Error::Io { path, source } if share::is_dropped(source) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The file another program has open, if that is why this failed.
+    pub(crate) fn locked_path(&self) -> Option<&Path> {
+        match self {
+            Error::Io { path, source } if locks::is_locked(source) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of an [`Error`]; see [`Error::code`]. An I/O error is split
+/// by its cause, as that is what the user can do something about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    /// Another program has the file open.
+    FileLocked,
+    Io,
+    InvalidPattern,
+    InvalidGlob,
+    InvalidIgnorePattern,
+    InvalidCondition,
+    InvalidScript,
+    InvalidPlugin,
+    UnknownPluginRule,
+    InvalidTemplate,
+    InvalidReplacement,
+    /// A naming policy with a pattern that is not a valid regex; see
+    /// `renamer_core::policy`.
+    InvalidPolicy,
+    PlanBlocked,
+    OverwriteNotConfirmed,
+    ExtensionChangeNotConfirmed,
+    /// Refused because safe mode is on; see `ApplyOptions::safe_mode`.
+    SafeMode,
+    CrossVolume,
+    /// A network share stopped answering, or the connection to it dropped.
+    ShareUnreachable,
+    /// A file, or the place it was to move to, changed after the plan was
+    /// made; see `ApplyOptions::recheck`.
+    Stale,
+    /// Another batch running in this process renames the same files or
+    /// into the same names and did not finish in time; see
+    /// `ApplyOptions::in_flight_wait_secs`.
+    TargetInFlight,
+    /// A file asked for is not part of the batch; see
+    /// `HistoryStore::undo_files`.
+    NotInBatch,
+    UnsupportedVersion,
+    InvalidPresetName,
+    OutOfScope,
+    /// A file, or where it would go, is outside the folders a preset may
+    /// rename in; see `Preset::allowed_scope`.
+    PresetScope,
+    /// A language tag no locale data is known for.
+    UnknownLocale,
+    Thumbnail,
+    Watch,
+    /// A remote host could not be reached or refused a request.
+    Remote,
+    Archive,
+    Database,
+    Csv,
+    Json,
+}
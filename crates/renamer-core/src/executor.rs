@@ -0,0 +1,1235 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::fsutil::{self, hidden_sibling};
+use crate::git::{self, GitReport};
+use crate::in_flight;
+use crate::journal::{Journal, JournalStep};
+use crate::locks::{self, LockHolder};
+use crate::manifest::{write_manifests, ManifestFormat, ManifestReport};
+use crate::metadata;
+use crate::plan::{PlanEntry, RenamePlan, Severity};
+use crate::power::SleepGuard;
+use crate::share;
+use crate::transfer::{self, CopyProgress, CrossVolume};
+use crate::volume::Volumes;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApplyOptions {
+    /// Required before a plan that replaces existing files is executed.
+    pub confirm_overwrite: bool,
+    /// Lets renames whose target is on another volume go through as a copy,
+    /// verified against the original before the original is deleted. Without
+    /// it such entries fail and the batch is rolled back.
+    pub allow_cross_volume: bool,
+    /// Required before a plan that changes or strips file extensions is
+    /// executed.
+    pub allow_extension_changes: bool,
+    /// Keeps access and modification times on files copied to another
+    /// volume. A plain rename never changes them.
+    pub preserve_timestamps: bool,
+    /// How many more times to try a rename that failed because another
+    /// program has the file open, waiting a quarter second before the first
+    /// retry and twice as long before each next one.
+    pub lock_retries: u32,
+    /// Leaves files still in use after the retries alone and goes on with
+    /// the rest of the batch, instead of rolling it back. Only renames no
+    /// other rename of the batch depends on can be left out this way.
+    pub skip_locked: bool,
+    /// Leaves out ready entries whose worst diagnostic is more severe than
+    /// this, so `Info` renames only entries without warnings. Entries that
+    /// would then collide with one left out, or move into a folder that is
+    /// no longer renamed, are left out with it.
+    pub skip_above: Option<Severity>,
+    /// Refuses every batch with [`Error::SafeMode`] before a file is
+    /// touched. Set in the settings, it makes the app, the CLI and folder
+    /// watchers read-only, e.g. while someone is learning the app on a
+    /// shared archive.
+    pub safe_mode: bool,
+    /// Writes a checksum manifest of the renamed files into each folder
+    /// they were renamed into once the batch has gone through; see
+    /// [`write_manifests`].
+    pub manifest: Option<ManifestFormat>,
+    /// Moves the git index entries of renamed files that git tracks along
+    /// with them, as `git mv` would, so that in a work tree the batch shows
+    /// up as renames; see [`crate::git`]. With it in the settings, the app
+    /// and the CLI stage undo and redo the same way.
+    pub git: bool,
+    /// Once the batch has gone through, removes the folders it moved the
+    /// last files out of, and their parents left empty in turn, up to the
+    /// folder a file's old and new place share, so a batch of
+    /// [`crate::Rule::ReplacePath`] renames folders rather than emptying
+    /// them. Undoing the batch creates them again.
+    pub remove_emptied_folders: bool,
+    /// How many seconds a move on a network share may go without progress
+    /// before the share counts as unreachable and the batch is paused, see
+    /// [`ApplyReport::unreachable`]; `None` waits as long as the OS does.
+    /// 30 by default.
+    pub network_timeout_secs: Option<u64>,
+    /// How many more times to try a move on a network share whose
+    /// connection dropped, waiting a second before the first retry and
+    /// twice as long before each next one. 3 by default.
+    pub network_retries: u32,
+    /// Folders whose moves are treated like moves on a network share, on
+    /// top of the SMB, NFS and other network mounts, which are found on
+    /// their own: a folder a cloud drive syncs, say, whose files may have to
+    /// be downloaded before they can be moved.
+    pub network_folders: Vec<PathBuf>,
+    /// Looks at each entry again just before it is renamed: whether its file
+    /// is still there and unchanged since the plan was made, and its new
+    /// name still free. An entry that is not is left out as
+    /// [`OutcomeStatus::Stale`] if no other rename of the batch depends on
+    /// it, and fails the batch if one does.
+    pub recheck: bool,
+    /// How many seconds to wait for another batch of this process that
+    /// renames the same files, or into the same names, a folder watcher's
+    /// say, before failing with [`Error::TargetInFlight`]. A batch that had
+    /// to wait is rechecked as with [`ApplyOptions::recheck`]. 0 fails at
+    /// once; 30 by default.
+    pub in_flight_wait_secs: u64,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        ApplyOptions {
+            confirm_overwrite: false,
+            allow_cross_volume: false,
+            allow_extension_changes: false,
+            preserve_timestamps: false,
+            lock_retries: 0,
+            skip_locked: false,
+            skip_above: None,
+            safe_mode: false,
+            manifest: None,
+            git: false,
+            remove_emptied_folders: false,
+            network_timeout_secs: Some(30),
+            network_retries: 3,
+            network_folders: Vec::new(),
+            recheck: false,
+            in_flight_wait_secs: 30,
+        }
+    }
+}
+
+impl ApplyOptions {
+    fn cross_volume<'a>(&self, progress: CopyProgress<'a>) -> Option<CrossVolume<'a>> {
+        self.allow_cross_volume.then_some(CrossVolume {
+            progress,
+            preserve_times: self.preserve_timestamps,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutcomeStatus {
+    Renamed,
+    /// The entry was not ready in the plan and was left alone.
+    Skipped,
+    Failed,
+    /// Renamed, then moved back after a later entry failed.
+    RolledBack,
+    /// Renamed, but moving it back after a failure also failed.
+    RollbackFailed,
+    /// Never attempted because the batch was aborted first.
+    Aborted,
+    /// Never attempted because the batch was cancelled; entries renamed
+    /// before that stay renamed.
+    Cancelled,
+    /// Not renamed yet because a network share stopped answering; resuming
+    /// the batch renames it. See [`ApplyReport::unreachable`].
+    Paused,
+    /// In use by another program and left alone because of
+    /// [`ApplyOptions::skip_locked`].
+    Locked,
+    /// Left alone because the file or its new name changed after the plan
+    /// was made; see [`ApplyOptions::recheck`].
+    Stale,
+}
+
+/// What changed about an entry between making the plan and applying it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleReason {
+    /// The file is no longer there.
+    SourceGone,
+    /// The file was modified.
+    SourceModified,
+    /// Another file took the new name.
+    TargetTaken,
+}
+
+impl StaleReason {
+    pub fn code(self) -> &'static str {
+        match self {
+            StaleReason::SourceGone => "source_gone",
+            StaleReason::SourceModified => "source_modified",
+            StaleReason::TargetTaken => "target_taken",
+        }
+    }
+}
+
+impl std::fmt::Display for StaleReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StaleReason::SourceGone => "the file is gone since the plan was made",
+            StaleReason::SourceModified => "the file was modified after the plan was made",
+            StaleReason::TargetTaken => "another file took the new name after the plan was made",
+        })
+    }
+}
+
+/// How an entry left out of the batch with `error` is reported.
+fn left_out_status(error: &Error) -> OutcomeStatus {
+    match error {
+        Error::Stale { .. } => OutcomeStatus::Stale,
+        _ => OutcomeStatus::Locked,
+    }
+}
+
+/// What keeps `entry` from being renamed as planned, if anything. A target
+/// another move of the batch frees up is not taken. Folders change their
+/// modification time whenever what is in them is renamed, so only files
+/// are compared by it.
+fn stale(entry: &PlanEntry, vacated: &HashSet<&Path>) -> Option<StaleReason> {
+    let Ok(metadata) = fs::symlink_metadata(fsutil::long(&entry.source)) else {
+        return Some(StaleReason::SourceGone);
+    };
+    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+    if !metadata.is_dir() && entry.source_modified.is_some() && modified != entry.source_modified {
+        return Some(StaleReason::SourceModified);
+    }
+    if entry.source != entry.target
+        && !entry.overwrites()
+        && !vacated.contains(entry.target.as_path())
+        && fsutil::exists(&entry.target)
+        && !fsutil::is_case_only_rename(&entry.source, &entry.target)
+    {
+        return Some(StaleReason::TargetTaken);
+    }
+    None
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryOutcome {
+    pub id: usize,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub status: OutcomeStatus,
+    pub error: Option<String>,
+    /// Symlink re-pointed at `target` along with the rename.
+    pub link: Option<PathBuf>,
+    pub is_dir: bool,
+    /// Modification time given to the file, and the one it had before.
+    pub modified: Option<DateTime<Utc>>,
+    pub previous_modified: Option<DateTime<Utc>>,
+    /// Why the entry's title could not be written into the file. The rename
+    /// itself stands.
+    pub title_error: Option<String>,
+    /// The programs that had the file open, when it was in use.
+    pub locked_by: Option<Vec<LockHolder>>,
+    /// Renamed, and found at its new path right after with the size it had
+    /// before. When this is false for a renamed entry, `error` says what was
+    /// wrong and the entry is kept out of the undo history. Copies to another
+    /// volume are also checked against the original's SHA-256 before the
+    /// original is deleted.
+    #[serde(default)]
+    pub verified: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplyReport {
+    pub batch_id: Uuid,
+    /// True when the renames that ran are kept: every ready entry was renamed,
+    /// or the batch was cancelled part way. False means the batch was rolled
+    /// back (check `rollback_failed` for leftovers).
+    pub committed: bool,
+    /// The batch was stopped by its [`CancelToken`] before it finished.
+    pub cancelled: bool,
+    pub outcomes: Vec<EntryOutcome>,
+    pub renamed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub rolled_back: usize,
+    pub rollback_failed: usize,
+    /// Renamed entries that failed the check after the batch.
+    #[serde(default)]
+    pub unverified: usize,
+    /// The manifests [`ApplyOptions::manifest`] had written.
+    #[serde(default)]
+    pub manifests: Vec<ManifestReport>,
+    /// The git indexes [`ApplyOptions::git`] updated.
+    #[serde(default)]
+    pub git: Vec<GitReport>,
+    /// The file a network share stopped answering on, if that paused the
+    /// batch. What was renamed by then stays renamed, the entries left are
+    /// [`OutcomeStatus::Paused`], and the batch's journal is kept, so the
+    /// batch can be finished with [`Journal::recover`] once the share is
+    /// back. Titles, manifests and git are left until then too.
+    #[serde(default)]
+    pub unreachable: Option<PathBuf>,
+}
+
+impl ApplyReport {
+    pub(crate) fn new(batch_id: Uuid) -> Self {
+        ApplyReport {
+            batch_id,
+            committed: false,
+            cancelled: false,
+            outcomes: Vec::new(),
+            renamed: 0,
+            skipped: 0,
+            failed: 0,
+            rolled_back: 0,
+            rollback_failed: 0,
+            unverified: 0,
+            manifests: Vec::new(),
+            git: Vec::new(),
+            unreachable: None,
+        }
+    }
+
+    fn push(&mut self, entry: &PlanEntry, status: OutcomeStatus, error: Option<String>) {
+        self.record(EntryOutcome {
+            id: entry.id,
+            source: entry.source.clone(),
+            target: entry.target.clone(),
+            status,
+            error,
+            link: entry.link.clone(),
+            is_dir: entry.is_dir,
+            modified: entry.modified,
+            previous_modified: None,
+            title_error: None,
+            locked_by: None,
+            verified: false,
+        });
+    }
+
+    /// Adds `outcome` and counts it by its status.
+    pub(crate) fn record(&mut self, outcome: EntryOutcome) {
+        match outcome.status {
+            OutcomeStatus::Renamed => self.renamed += 1,
+            OutcomeStatus::Skipped
+            | OutcomeStatus::Aborted
+            | OutcomeStatus::Cancelled
+            | OutcomeStatus::Paused
+            | OutcomeStatus::Locked
+            | OutcomeStatus::Stale => self.skipped += 1,
+            OutcomeStatus::Failed => self.failed += 1,
+            OutcomeStatus::RolledBack => self.rolled_back += 1,
+            OutcomeStatus::RollbackFailed => self.rollback_failed += 1,
+        }
+        self.outcomes.push(outcome);
+    }
+}
+
+/// Moves `source` to `target`, creating missing parent directories and
+/// handling case-only renames on case-insensitive volumes. Newly
+/// created directories are appended to `created` (outermost first) so a
+/// rollback can remove them again. A target on another volume is copied over
+/// if `cross_volume` is given and fails with [`Error::CrossVolume`] if not.
+pub(crate) fn rename_file(
+    source: &Path,
+    target: &Path,
+    created: &mut Vec<PathBuf>,
+    cross_volume: Option<CrossVolume>,
+) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        let mut missing: Vec<PathBuf> = parent
+            .ancestors()
+            .take_while(|p| !p.as_os_str().is_empty() && !fsutil::exists(p))
+            .map(Path::to_path_buf)
+            .collect();
+        fs::create_dir_all(fsutil::long(parent)).map_err(|e| Error::io(parent, e))?;
+        missing.reverse();
+        created.extend(missing);
+    }
+    transfer::move_file(source, target, cross_volume).map_err(|e| {
+        if cross_volume.is_none() && transfer::is_cross_device(&e) {
+            Error::CrossVolume {
+                path: source.to_path_buf(),
+            }
+        } else {
+            Error::io(source, e)
+        }
+    })
+}
+
+/// Hidden sibling the replaced file is parked at until the batch commits.
+fn backup_path(target: &Path, batch_id: Uuid) -> PathBuf {
+    hidden_sibling(target, &format!("sortify-{}.bak", batch_id))
+}
+
+/// How deep a move reaches into the tree. Moves into or out of a folder that
+/// is renamed in the same batch are deeper than the folder's own move, so
+/// running the deepest first renames children before their parents.
+pub(crate) fn nesting(source: &Path, target: &Path) -> usize {
+    source.components().count().max(target.components().count())
+}
+
+/// One filesystem move, in execution order.
+#[derive(Debug, Clone)]
+pub(crate) struct Step {
+    /// Index into the moves passed to [`sequence`].
+    pub idx: usize,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// True when this step puts the file at its final destination; false for
+    /// a move to a temporary name.
+    pub last: bool,
+}
+
+/// Orders a set of moves so that no step lands on a path another move still
+/// has to vacate. Chains (`a→b`, `b→c`) run back to front; swaps and rotations
+/// have no such order, so one member of each cycle is first parked under a
+/// temporary name next to its source and moved to its target last. Otherwise
+/// moves run in the order given.
+pub(crate) fn sequence(moves: &[(&Path, &Path)], batch_id: Uuid) -> Vec<Step> {
+    let mut current: Vec<PathBuf> = moves.iter().map(|(s, _)| s.to_path_buf()).collect();
+    let mut by_source: HashMap<PathBuf, usize> = current
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.clone(), i))
+        .collect();
+    let mut done = vec![false; moves.len()];
+    let mut on_stack = vec![false; moves.len()];
+    let mut steps = Vec::with_capacity(moves.len());
+
+    for start in 0..moves.len() {
+        if done[start] {
+            continue;
+        }
+        let mut stack = vec![start];
+        on_stack[start] = true;
+        while let Some(&i) = stack.last() {
+            match by_source.get(moves[i].1).copied() {
+                Some(j) if j != i && on_stack[j] => {
+                    // Cycle: park `j` so `i` (and everything above it) can move.
+                    let temp =
+                        hidden_sibling(&current[j], &format!("sortify-{}-{}.tmp", batch_id, j));
+                    by_source.remove(&current[j]);
+                    steps.push(Step {
+                        idx: j,
+                        from: std::mem::replace(&mut current[j], temp.clone()),
+                        to: temp,
+                        last: false,
+                    });
+                }
+                Some(j) if j != i => {
+                    stack.push(j);
+                    on_stack[j] = true;
+                }
+                _ => {
+                    by_source.remove(&current[i]);
+                    steps.push(Step {
+                        idx: i,
+                        from: current[i].clone(),
+                        to: moves[i].1.to_path_buf(),
+                        last: true,
+                    });
+                    done[i] = true;
+                    on_stack[i] = false;
+                    stack.pop();
+                }
+            }
+        }
+    }
+    steps
+}
+
+/// Batches with fewer independent renames than this run on the calling thread;
+/// spinning up parallel work only pays off for large batches.
+const PARALLEL_THRESHOLD: usize = 256;
+
+/// Wait before the first retry of a file in use.
+const LOCK_RETRY_WAIT: Duration = Duration::from_millis(250);
+
+/// Runs `attempt` until it works, fails for another reason than a file in
+/// use, or [`ApplyOptions::lock_retries`] are used up.
+fn retry_locked(options: &ApplyOptions, mut attempt: impl FnMut() -> Result<()>) -> Result<()> {
+    let mut wait = LOCK_RETRY_WAIT;
+    for _ in 0..options.lock_retries {
+        match attempt() {
+            Err(e) if e.locked_path().is_some() => {
+                std::thread::sleep(wait);
+                wait *= 2;
+            }
+            result => return result,
+        }
+    }
+    attempt()
+}
+
+/// Wait before the first retry of a move whose connection dropped.
+const NETWORK_RETRY_WAIT: Duration = Duration::from_secs(1);
+
+/// For a move on a network share, runs `attempt` until it works, fails for
+/// another reason than a dropped connection, or
+/// [`ApplyOptions::network_retries`] are used up. A retry that finds the
+/// file gone from `from` and at `to` is not made: the move went through and
+/// only the answer was lost.
+fn retry_dropped(
+    options: &ApplyOptions,
+    network: bool,
+    (from, to): (&Path, &Path),
+    mut attempt: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    let retries = if network { options.network_retries } else { 0 };
+    let mut wait = NETWORK_RETRY_WAIT;
+    let mut result = attempt();
+    for _ in 0..retries {
+        if !result
+            .as_ref()
+            .is_err_and(|e| e.unreachable_path().is_some())
+        {
+            break;
+        }
+        std::thread::sleep(wait);
+        wait *= 2;
+        if from != to && !fsutil::exists(from) && fsutil::exists(to) {
+            return Ok(());
+        }
+        result = attempt();
+    }
+    result
+}
+
+/// Minimum time between two progress callbacks.
+pub(crate) const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress of a running batch, reported while [`apply_with_progress`] works.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    pub batch_id: Uuid,
+    /// Entries renamed so far.
+    pub completed: usize,
+    /// Entries that will be renamed in total.
+    pub total: usize,
+    /// The file that was just renamed.
+    pub current: PathBuf,
+    /// Estimated time left, once there is enough to go on.
+    pub eta_ms: Option<u64>,
+    /// While `current` is being copied to another volume, bytes copied so
+    /// far and its size; both zero otherwise.
+    pub copied_bytes: u64,
+    pub copy_total: u64,
+}
+
+/// Counts completed entries across threads and rate-limits the callback.
+pub(crate) struct Reporter<'a> {
+    batch_id: Uuid,
+    total: usize,
+    started: Instant,
+    completed: AtomicUsize,
+    /// When the callback last ran, and the count it was given.
+    last: Mutex<(Option<Instant>, usize)>,
+    callback: &'a (dyn Fn(&Progress) + Sync),
+}
+
+impl<'a> Reporter<'a> {
+    pub(crate) fn new(
+        batch_id: Uuid,
+        total: usize,
+        callback: &'a (dyn Fn(&Progress) + Sync),
+    ) -> Self {
+        Reporter {
+            batch_id,
+            total,
+            started: Instant::now(),
+            completed: AtomicUsize::new(0),
+            last: Mutex::new((None, 0)),
+            callback,
+        }
+    }
+
+    pub(crate) fn completed(&self, current: &Path) {
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        // Holding the lock through the callback keeps reported counts
+        // increasing even when threads finish out of order.
+        let mut last = self.last.lock().unwrap();
+        let due = last
+            .0
+            .map_or(true, |t| now.duration_since(t) >= PROGRESS_INTERVAL);
+        if completed <= last.1 || (!due && completed < self.total) {
+            return;
+        }
+        *last = (Some(now), completed);
+        self.report(now, completed, current, 0, 0);
+    }
+
+    /// Reports a copy in progress, to another volume or within an object
+    /// store, without counting an entry.
+    pub(crate) fn copying(&self, current: &Path, copied: u64, total: u64) {
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap();
+        if last
+            .0
+            .is_some_and(|t| now.duration_since(t) < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        last.0 = Some(now);
+        self.report(now, last.1, current, copied, total);
+    }
+
+    fn report(&self, now: Instant, completed: usize, current: &Path, copied: u64, total: u64) {
+        let elapsed = now.duration_since(self.started).as_millis() as u64;
+        (self.callback)(&Progress {
+            batch_id: self.batch_id,
+            completed,
+            total: self.total,
+            current: current.to_path_buf(),
+            eta_ms: (completed > 0)
+                .then(|| elapsed * (self.total - completed) as u64 / completed as u64),
+            copied_bytes: copied,
+            copy_total: total,
+        });
+    }
+}
+
+/// A step that went through, with what it takes to undo it.
+struct Executed {
+    idx: usize,
+    step: Step,
+    /// For a step that put the file in place, whether it was found there
+    /// right after, see [`verify`].
+    verified: Option<std::result::Result<(), String>>,
+    backup: Option<PathBuf>,
+    old_link: Option<PathBuf>,
+    old_modified: Option<SystemTime>,
+    created_dirs: Vec<PathBuf>,
+}
+
+/// A step that failed. `partial` is set when the file did move but a later
+/// part of the step (re-pointing a link, setting its time) failed, so the
+/// move needs undoing. `skipped` is set for a file in use that was left out
+/// of the batch, `paused` for one on a network share that stopped
+/// answering.
+struct Failed {
+    idx: usize,
+    partial: Option<Box<Executed>>,
+    error: Error,
+    skipped: bool,
+    paused: bool,
+}
+
+type StepResult = std::result::Result<Executed, Failed>;
+
+/// [`run_step`], made on a thread of its own for a step on a network share
+/// (`network`), so that a share that stops answering pauses the batch with
+/// [`Error::ShareUnreachable`] instead of hanging it.
+fn run_watched(
+    plan: &RenamePlan,
+    idx: usize,
+    step: &Step,
+    options: &ApplyOptions,
+    reporter: &Reporter,
+    network: bool,
+) -> StepResult {
+    let entry = &plan.entries[idx];
+    let batch_id = reporter.batch_id;
+    let copy = |copied, total| reporter.copying(&step.to, copied, total);
+    let Some(secs) = options.network_timeout_secs.filter(|_| network) else {
+        return run_step(entry, idx, step, options, batch_id, &copy, network);
+    };
+    let owned = (entry.clone(), step.clone(), options.clone());
+    share::watched(Duration::from_secs(secs), &copy, move |copy| {
+        let (entry, step, options) = owned;
+        run_step(&entry, idx, &step, &options, batch_id, copy, true)
+    })
+    .unwrap_or_else(|| {
+        Err(Failed {
+            idx,
+            partial: None,
+            error: Error::ShareUnreachable {
+                path: step.from.clone(),
+                secs,
+            },
+            skipped: false,
+            paused: false,
+        })
+    })
+}
+
+/// Runs one step of `entry`, entry `idx` of its plan: parks an overwritten
+/// target, moves the file, re-points a followed link and sets the
+/// modification time. `copy` hears how a copy to another volume is getting
+/// on.
+fn run_step(
+    entry: &PlanEntry,
+    idx: usize,
+    step: &Step,
+    options: &ApplyOptions,
+    batch_id: Uuid,
+    copy: CopyProgress,
+    network: bool,
+) -> StepResult {
+    let fail = |partial, error| Failed {
+        idx,
+        partial,
+        error,
+        skipped: false,
+        paused: false,
+    };
+    let mut done = Executed {
+        idx,
+        step: step.clone(),
+        verified: None,
+        backup: None,
+        old_link: None,
+        old_modified: None,
+        created_dirs: Vec::new(),
+    };
+    if step.last && entry.overwrites() {
+        let backup = backup_path(&entry.target, batch_id);
+        retry_locked(options, || {
+            fsutil::rename(&entry.target, &backup).map_err(|e| Error::io(&entry.target, e))
+        })
+        .map_err(|e| fail(None, e))?;
+        done.backup = Some(backup);
+    }
+    let size = fs::symlink_metadata(fsutil::long(&step.from))
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len());
+    let moved = retry_locked(options, || {
+        retry_dropped(options, network, (&step.from, &step.to), || {
+            if step.from == step.to {
+                Ok(())
+            } else if step.last {
+                let cross_volume = options.cross_volume(copy);
+                rename_file(&step.from, &step.to, &mut done.created_dirs, cross_volume)
+            } else {
+                fsutil::rename(&step.from, &step.to).map_err(|e| Error::io(&step.from, e))
+            }
+        })
+    });
+    if moved.is_ok() && step.last {
+        done.verified = Some(verify(&step.to, size));
+    }
+    if let Err(e) = moved {
+        if let Some(backup) = &done.backup {
+            let _ = fsutil::rename(backup, &entry.target);
+        }
+        for dir in done.created_dirs.iter().rev() {
+            let _ = fs::remove_dir(fsutil::long(dir));
+        }
+        return Err(fail(None, e));
+    }
+    if let (true, Some(link)) = (step.last, &entry.link) {
+        match fsutil::repoint(link, step.to.file_name().unwrap_or_default()) {
+            Ok(old) => done.old_link = Some(old),
+            Err(e) => return Err(fail(Some(Box::new(done)), Error::io(link, e))),
+        }
+    }
+    if let (true, Some(time)) = (step.last, entry.modified) {
+        match fsutil::set_modified(&step.to, time.into()) {
+            Ok(old) => done.old_modified = Some(old),
+            Err(e) => return Err(fail(Some(Box::new(done)), Error::io(&step.to, e))),
+        }
+    }
+    Ok(done)
+}
+
+/// See [`ApplyOptions::remove_emptied_folders`]. Deeper folders go first,
+/// so a parent is only tried once its children are gone.
+fn remove_emptied_folders(report: &ApplyReport) {
+    let mut emptied: Vec<(&Path, &Path)> = report
+        .outcomes
+        .iter()
+        .filter(|o| o.status == OutcomeStatus::Renamed)
+        .filter_map(|o| {
+            let (from, to) = (o.source.parent()?, o.target.parent()?);
+            let shared = from.ancestors().find(|a| to.starts_with(a))?;
+            (from != shared).then_some((from, shared))
+        })
+        .collect();
+    emptied.sort_by_key(|(from, _)| Reverse(from.components().count()));
+    for (from, shared) in emptied {
+        for dir in from.ancestors().take_while(|a| *a != shared) {
+            // Only succeeds for folders left empty.
+            if fs::remove_dir(fsutil::long(dir)).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Checks that a file just moved is at `target`, with the `size` it had
+/// before.
+fn verify(target: &Path, size: Option<u64>) -> std::result::Result<(), String> {
+    let metadata = fs::symlink_metadata(fsutil::long(target))
+        .map_err(|e| format!("{}: not found after renaming: {}", target.display(), e))?;
+    match size {
+        Some(size) if metadata.is_file() && metadata.len() != size => Err(format!(
+            "{}: has {} bytes after renaming instead of {}",
+            target.display(),
+            metadata.len(),
+            size
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Moves an executed step back, returning the first thing that went wrong.
+/// A file that was copied to another volume is copied back.
+fn undo_step(
+    plan: &RenamePlan,
+    done: &Executed,
+    options: &ApplyOptions,
+) -> std::result::Result<(), String> {
+    let step = &done.step;
+    if let Some(old) = done.old_modified {
+        fsutil::set_modified(&step.to, old).map_err(|e| Error::io(&step.to, e).to_string())?;
+    }
+    if let (Some(link), Some(old)) = (&plan.entries[done.idx].link, &done.old_link) {
+        fsutil::replace_link(link, old).map_err(|e| Error::io(link, e).to_string())?;
+    }
+    if step.from != step.to {
+        transfer::move_file(&step.to, &step.from, options.cross_volume(&|_, _| {}))
+            .map_err(|e| Error::io(&step.to, e).to_string())?;
+    }
+    if let Some(backup) = &done.backup {
+        fsutil::rename(backup, &step.to).map_err(|e| Error::io(backup, e).to_string())?;
+    }
+    Ok(())
+}
+
+/// Applies `plan` as a single transaction.
+///
+/// A journal of every move the batch makes is written to `journal_dir` and
+/// synced to disk first, so a batch cut off by a crash can be finished or
+/// undone with [`Journal::recover`](crate::Journal::recover). Entries are
+/// renamed in dependency order, files and folders inside a renamed folder
+/// before the folder itself, going through temporary names where the batch
+/// swaps or rotates names. If any rename fails, every move made so far is
+/// undone in reverse order and the report describes what happened to each
+/// row. Files replaced by overwrite entries are kept aside until commit so a
+/// rollback can restore them. The journal is removed once the batch is
+/// committed or fully rolled back, and kept if the rollback was incomplete.
+///
+/// Titles from [`crate::Rule::WriteTitle`] are written once the batch is
+/// committed; a file that cannot take one keeps its new name and reports
+/// `title_error`.
+pub fn apply(plan: &RenamePlan, options: &ApplyOptions, journal_dir: &Path) -> Result<ApplyReport> {
+    apply_with_progress(plan, options, journal_dir, &|_| {}, &CancelToken::new())
+}
+
+/// Which entries of `plan` run under `options`, by id: the ready ones
+/// within [`ApplyOptions::skip_above`], less those that depend on one left
+/// out.
+fn runnable(plan: &RenamePlan, options: &ApplyOptions) -> Vec<bool> {
+    let within = |entry: &PlanEntry| {
+        options
+            .skip_above
+            .map_or(true, |max| entry.severity().map_or(true, |s| s <= max))
+    };
+    let mut runs: Vec<bool> = plan
+        .entries
+        .iter()
+        .map(|e| e.is_ready() && within(e))
+        .collect();
+    // An entry left out keeps its name taken and, for a folder, its new
+    // path missing, which can strand further entries.
+    loop {
+        let held: Vec<&PlanEntry> = plan
+            .entries
+            .iter()
+            .filter(|e| e.is_ready() && !runs[e.id])
+            .collect();
+        let mut changed = false;
+        for entry in &plan.entries {
+            let stranded = held.iter().any(|h| {
+                entry.target == h.source || (h.is_dir && entry.target.starts_with(&h.target))
+            });
+            if runs[entry.id] && stranded {
+                runs[entry.id] = false;
+                changed = true;
+            }
+        }
+        if !changed {
+            return runs;
+        }
+    }
+}
+
+/// [`apply`], calling `progress` as entries complete (at most every 100ms,
+/// and always for the last one). In large batches, renames that don't depend
+/// on each other run on a thread pool.
+///
+/// Cancelling `cancel` stops the batch before the next rename, never while a
+/// file sits under a temporary name. What was renamed by then is kept and
+/// the report is marked `cancelled`.
+///
+/// A step on a network share that stops answering, or whose connection
+/// keeps dropping, pauses the batch instead: what was renamed is kept, the
+/// entries left are `Paused`, [`ApplyReport::unreachable`] names the path
+/// and the journal stays, so that [`Journal::recover`] can finish the batch
+/// once the share is back.
+pub fn apply_with_progress(
+    plan: &RenamePlan,
+    options: &ApplyOptions,
+    journal_dir: &Path,
+    progress: &(dyn Fn(&Progress) + Sync),
+    cancel: &CancelToken,
+) -> Result<ApplyReport> {
+    if options.safe_mode {
+        return Err(Error::SafeMode);
+    }
+    if plan.blocked {
+        return Err(Error::PlanBlocked);
+    }
+    let runs = runnable(plan, options);
+    let running = || plan.entries.iter().filter(|e| runs[e.id]);
+    if running().any(PlanEntry::overwrites) && !options.confirm_overwrite {
+        return Err(Error::OverwriteNotConfirmed);
+    }
+    if running().any(PlanEntry::changes_extension) && !options.allow_extension_changes {
+        return Err(Error::ExtensionChangeNotConfirmed);
+    }
+
+    let _awake = SleepGuard::acquire("Renaming files");
+    let mut journal = Journal::for_plan(plan);
+    journal.entries.retain(|e| runs[e.id]);
+    // Held until the batch returns, rolled back or not.
+    let claim = in_flight::claim(
+        journal.batch_id,
+        running().flat_map(|e| {
+            [
+                (e.source.as_path(), e.is_dir),
+                (e.target.as_path(), e.is_dir),
+            ]
+        }),
+        Duration::from_secs(options.in_flight_wait_secs),
+    )?;
+    let recheck = options.recheck || claim.waited;
+
+    let mut ready: Vec<usize> = (0..plan.entries.len()).filter(|&i| runs[i]).collect();
+    ready.sort_by_key(|&i| {
+        let entry = &plan.entries[i];
+        Reverse(nesting(&entry.source, &entry.target))
+    });
+    let moves: Vec<(&Path, &Path)> = ready
+        .iter()
+        .map(|&i| {
+            (
+                plan.entries[i].source.as_path(),
+                plan.entries[i].target.as_path(),
+            )
+        })
+        .collect();
+    let steps = sequence(&moves, journal.batch_id);
+    let mut volumes = Volumes::new(None);
+    let network: Vec<bool> = moves
+        .iter()
+        .map(|&(source, target)| {
+            [source, target].into_iter().any(|path| {
+                options.network_folders.iter().any(|f| path.starts_with(f))
+                    || volumes.on_network(path)
+            })
+        })
+        .collect();
+
+    // Moves that neither free up nor take another move's path can run in any
+    // order; the rest keep the order `sequence` gave them. Renaming a folder
+    // changes the path of everything in it, so moves of, in or into a folder
+    // of the batch are never reordered either.
+    let sources: HashSet<&Path> = moves.iter().map(|(s, _)| *s).collect();
+    let targets: HashSet<&Path> = moves.iter().map(|(_, t)| *t).collect();
+    let folders: HashSet<&Path> = ready
+        .iter()
+        .filter(|&&i| plan.entries[i].is_dir)
+        .map(|&i| plan.entries[i].source.as_path())
+        .collect();
+    let in_folder = |path: &Path| path.ancestors().skip(1).any(|a| folders.contains(a));
+    let (independent, dependent): (Vec<&Step>, Vec<&Step>) = steps.iter().partition(|step| {
+        let (source, target) = moves[step.idx];
+        !sources.contains(target)
+            && !targets.contains(source)
+            && !folders.contains(source)
+            && !in_folder(source)
+            && !in_folder(target)
+    });
+
+    journal.steps = independent
+        .iter()
+        .map(|step| (step, true))
+        .chain(dependent.iter().map(|step| (step, false)))
+        .map(|(step, independent)| {
+            let entry = &plan.entries[ready[step.idx]];
+            JournalStep {
+                id: entry.id,
+                from: step.from.clone(),
+                to: step.to.clone(),
+                last: step.last,
+                backup: (step.last && entry.overwrites())
+                    .then(|| backup_path(&entry.target, journal.batch_id)),
+                independent,
+            }
+        })
+        .collect();
+    let mut new_dirs: Vec<PathBuf> = moves
+        .iter()
+        .filter_map(|(_, target)| target.parent())
+        .flat_map(|parent| {
+            parent
+                .ancestors()
+                .take_while(|p| !p.as_os_str().is_empty() && !fsutil::exists(p))
+        })
+        .map(Path::to_path_buf)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    new_dirs.sort_by_key(|dir| dir.components().count());
+    journal.new_dirs = new_dirs;
+    let journal_path = journal.write(journal_dir)?;
+
+    let reporter = Reporter::new(journal.batch_id, ready.len(), progress);
+    let failed = AtomicBool::new(false);
+    let paused = AtomicBool::new(false);
+    // Entries parked under a temporary name; cancelling waits until none are.
+    let parked: Mutex<HashSet<usize>> = Mutex::new(HashSet::new());
+    let run = |step: &Step, independent: bool| -> Option<StepResult> {
+        if failed.load(Ordering::Relaxed) || paused.load(Ordering::Relaxed) {
+            return None;
+        }
+        let idx = ready[step.idx];
+        let entry = &plan.entries[idx];
+        if recheck && step.from == entry.source {
+            if let Some(reason) = stale(entry, &sources) {
+                if !independent {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                return Some(Err(Failed {
+                    idx,
+                    partial: None,
+                    error: Error::Stale {
+                        path: entry.source.clone(),
+                        reason,
+                    },
+                    skipped: independent,
+                    paused: false,
+                }));
+            }
+        }
+        {
+            let mut parked = parked.lock().unwrap();
+            if cancel.is_cancelled() && parked.is_empty() {
+                return None;
+            }
+            if step.last {
+                parked.remove(&step.idx);
+            } else {
+                parked.insert(step.idx);
+            }
+        }
+        let mut result = run_watched(plan, idx, step, options, &reporter, network[step.idx]);
+        match &mut result {
+            Ok(_) if step.last => reporter.completed(&step.to),
+            Ok(_) => {}
+            Err(failure)
+                if options.skip_locked
+                    && independent
+                    && failure.partial.is_none()
+                    && failure.error.locked_path().is_some() =>
+            {
+                failure.skipped = true;
+            }
+            Err(failure) if network[step.idx] && failure.error.unreachable_path().is_some() => {
+                failure.paused = true;
+                paused.store(true, Ordering::Relaxed);
+            }
+            Err(_) => failed.store(true, Ordering::Relaxed),
+        }
+        Some(result)
+    };
+
+    let mut results: Vec<Option<StepResult>> = if independent.len() >= PARALLEL_THRESHOLD {
+        independent.par_iter().map(|step| run(step, true)).collect()
+    } else {
+        independent.iter().map(|step| run(step, true)).collect()
+    };
+    results.extend(dependent.iter().map(|step| run(step, false)));
+
+    let mut executed: Vec<Executed> = Vec::new();
+    let mut failures: HashMap<usize, Error> = HashMap::new();
+    let mut left_out: HashMap<usize, Error> = HashMap::new();
+    let mut locked_by: HashMap<usize, Vec<LockHolder>> = HashMap::new();
+    let mut stalled: HashMap<usize, Error> = HashMap::new();
+    for result in results.into_iter().flatten() {
+        match result {
+            Ok(done) => executed.push(done),
+            Err(failure) => {
+                if let Some(path) = failure.error.locked_path() {
+                    locked_by.insert(failure.idx, locks::lock_holders(path));
+                }
+                executed.extend(failure.partial.map(|done| *done));
+                if failure.skipped {
+                    left_out.insert(failure.idx, failure.error);
+                } else if failure.paused {
+                    stalled.insert(failure.idx, failure.error);
+                } else {
+                    failures.insert(failure.idx, failure.error);
+                }
+            }
+        }
+    }
+
+    // A step that failed while others paused rolls the whole batch back.
+    if !failures.is_empty() {
+        failures.extend(stalled.drain());
+    }
+
+    let mut report = ApplyReport::new(journal.batch_id);
+    if failures.is_empty() {
+        let mut renamed = vec![false; plan.entries.len()];
+        let mut previous = vec![None; plan.entries.len()];
+        let mut checks = vec![None; plan.entries.len()];
+        for done in executed.iter().filter(|d| d.step.last) {
+            renamed[done.idx] = true;
+            previous[done.idx] = done.old_modified.map(DateTime::<Utc>::from);
+            checks[done.idx] = done.verified.clone();
+        }
+        report.cancelled = stalled.is_empty()
+            && renamed.iter().filter(|&&r| r).count() + left_out.len() < ready.len();
+        for (idx, entry) in plan.entries.iter().enumerate() {
+            if let Some(error) = left_out.remove(&idx) {
+                report.push(entry, left_out_status(&error), Some(error.to_string()));
+            } else if let Some(error) = stalled.get(&idx) {
+                report.push(entry, OutcomeStatus::Paused, Some(error.to_string()));
+            } else {
+                let status = if renamed[idx] {
+                    OutcomeStatus::Renamed
+                } else if runs[idx] && !stalled.is_empty() {
+                    OutcomeStatus::Paused
+                } else if runs[idx] {
+                    OutcomeStatus::Cancelled
+                } else {
+                    OutcomeStatus::Skipped
+                };
+                report.push(entry, status, None);
+            }
+            if let Some(outcome) = report.outcomes.last_mut() {
+                outcome.previous_modified = previous[idx];
+                outcome.locked_by = locked_by.remove(&idx);
+                match &checks[idx] {
+                    Some(Ok(())) => outcome.verified = true,
+                    Some(Err(e)) => {
+                        outcome.error = Some(e.clone());
+                        report.unverified += 1;
+                    }
+                    None => {}
+                }
+            }
+        }
+        report.committed = true;
+        let title_errors: Vec<Option<String>> = plan
+            .entries
+            .par_iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let title = entry
+                    .title
+                    .as_ref()
+                    .filter(|_| renamed[idx] && !entry.is_dir)?;
+                metadata::write_title(&entry.target, title)
+                    .err()
+                    .map(|e| Error::io(&entry.target, e).to_string())
+            })
+            .collect();
+        for (outcome, error) in report.outcomes.iter_mut().zip(title_errors) {
+            outcome.title_error = error;
+        }
+        if let Some(idx) = stalled.keys().min() {
+            // The rest waits for the share: the journal is kept to resume
+            // from, and so are backups of replaced files.
+            report.unreachable = stalled[idx].unreachable_path().map(Path::to_path_buf);
+            return Ok(report);
+        }
+        if options.git {
+            report.git = git::stage_batch(&report);
+        }
+        if let Some(format) = options.manifest {
+            report.manifests = write_manifests(&report, format);
+        }
+        for backup in executed.iter().filter_map(|d| d.backup.as_ref()) {
+            let _ = fs::remove_file(fsutil::long(backup));
+        }
+        if options.remove_emptied_folders {
+            remove_emptied_folders(&report);
+        }
+        let _ = fs::remove_file(&journal_path);
+        return Ok(report);
+    }
+
+    // Undo in reverse so chained and cyclic moves unwind correctly.
+    let mut rollback_errors: HashMap<usize, String> = HashMap::new();
+    for done in executed.iter().rev() {
+        if let Err(e) = undo_step(plan, done, options) {
+            rollback_errors.insert(done.idx, e);
+        }
+    }
+    if options.allow_cross_volume {
+        for step in steps.iter().filter(|s| s.last) {
+            transfer::discard_partial(&step.to);
+        }
+    }
+    for dir in executed
+        .iter()
+        .rev()
+        .flat_map(|d| d.created_dirs.iter().rev())
+    {
+        // Only succeeds for directories we left empty.
+        let _ = fs::remove_dir(fsutil::long(dir));
+    }
+
+    let mut was_moved = vec![false; plan.entries.len()];
+    for done in &executed {
+        was_moved[done.idx] = true;
+    }
+    for (idx, entry) in plan.entries.iter().enumerate() {
+        if let Some(failure) = failures.remove(&idx) {
+            let mut error = failure.to_string();
+            if let Some(err) = rollback_errors.remove(&idx) {
+                error = format!("{}; rollback failed: {}", error, err);
+                report.rollback_failed += 1;
+            }
+            report.push(entry, OutcomeStatus::Failed, Some(error));
+        } else if let Some(error) = left_out.remove(&idx) {
+            report.push(entry, left_out_status(&error), Some(error.to_string()));
+        } else if let Some(err) = rollback_errors.remove(&idx) {
+            report.push(entry, OutcomeStatus::RollbackFailed, Some(err));
+        } else if was_moved[idx] {
+            report.push(entry, OutcomeStatus::RolledBack, None);
+        } else if runs[idx] {
+            report.push(entry, OutcomeStatus::Aborted, None);
+        } else {
+            report.push(entry, OutcomeStatus::Skipped, None);
+        }
+        if let Some(outcome) = report.outcomes.last_mut() {
+            outcome.locked_by = locked_by.remove(&idx);
+        }
+    }
+
+    if report.rollback_failed == 0 {
+        let _ = fs::remove_file(&journal_path);
+    }
+    Ok(report)
+}
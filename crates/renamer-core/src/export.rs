@@ -0,0 +1,150 @@
+//! Writing a plan, before it runs, or the results of a batch, after, to CSV
+//! or JSON, for keeping a record of what was changed.
+//!
+//! Both formats have one row per file with its old and new path, status,
+//! error and modification times. JSON files also record when they were
+//! written and, for batches, whether the batch was committed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::executor::ApplyReport;
+use crate::plan::RenamePlan;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportRow {
+    pub batch_id: Option<Uuid>,
+    pub id: usize,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// [`crate::EntryStatus`] for a plan, [`crate::OutcomeStatus`] for a
+    /// batch.
+    pub status: String,
+    /// Why the rename failed, or for a plan, the collision it ran into.
+    pub error: Option<String>,
+    /// Plan warnings, or why the title could not be written, separated by
+    /// `; `.
+    pub warnings: Option<String>,
+    pub previous_modified: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Plan,
+    Batch,
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    kind: Kind,
+    batch_id: Option<Uuid>,
+    committed: Option<bool>,
+    exported_at: DateTime<Utc>,
+    rows: &'a [ExportRow],
+}
+
+/// The snake_case name serde gives a unit variant.
+fn label(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
+
+fn joined(parts: Vec<String>) -> Option<String> {
+    (!parts.is_empty()).then(|| parts.join("; "))
+}
+
+pub fn plan_rows(plan: &RenamePlan) -> Vec<ExportRow> {
+    plan.entries
+        .iter()
+        .map(|entry| ExportRow {
+            batch_id: None,
+            id: entry.id,
+            source: entry.source.clone(),
+            target: entry.target.clone(),
+            status: label(&entry.status),
+            error: entry
+                .conflict
+                .as_ref()
+                .map(|c| format!("{} ({})", label(&c.kind), label(&c.resolution))),
+            warnings: joined(entry.warnings.iter().map(label).collect()),
+            previous_modified: None,
+            modified: entry.modified,
+        })
+        .collect()
+}
+
+pub fn batch_rows(report: &ApplyReport) -> Vec<ExportRow> {
+    report
+        .outcomes
+        .iter()
+        .map(|outcome| ExportRow {
+            batch_id: Some(report.batch_id),
+            id: outcome.id,
+            source: outcome.source.clone(),
+            target: outcome.target.clone(),
+            status: label(&outcome.status),
+            error: outcome.error.clone(),
+            warnings: outcome.title_error.clone(),
+            previous_modified: outcome.previous_modified,
+            modified: outcome.modified,
+        })
+        .collect()
+}
+
+fn write(path: &Path, format: ExportFormat, document: Document) -> Result<()> {
+    let bytes = match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(&document)?,
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for row in document.rows {
+                writer.serialize(row)?;
+            }
+            writer
+                .into_inner()
+                .map_err(|e| Error::io(path, e.into_error()))?
+        }
+    };
+    fs::write(path, bytes).map_err(|e| Error::io(path, e))
+}
+
+/// Writes the entries of `plan` to `path`.
+pub fn export_plan(plan: &RenamePlan, format: ExportFormat, path: &Path) -> Result<()> {
+    let rows = plan_rows(plan);
+    let document = Document {
+        kind: Kind::Plan,
+        batch_id: None,
+        committed: None,
+        exported_at: Utc::now(),
+        rows: &rows,
+    };
+    write(path, format, document)
+}
+
+/// Writes the outcomes of `report` to `path`.
+pub fn export_batch(report: &ApplyReport, format: ExportFormat, path: &Path) -> Result<()> {
+    let rows = batch_rows(report);
+    let document = Document {
+        kind: Kind::Batch,
+        batch_id: Some(report.batch_id),
+        committed: Some(report.committed),
+        exported_at: Utc::now(),
+        rows: &rows,
+    };
+    write(path, format, document)
+}
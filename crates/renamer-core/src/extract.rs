@@ -0,0 +1,181 @@
+//! Reading metadata and hashes for the preview on a fixed number of
+//! threads, files on screen first.
+//!
+//! Unlike [`crate::extract_all`] and [`crate::hash_files`], which hand back
+//! everything at the end, results are passed on as each file is done, and
+//! the files the user is looking at can be moved to the front of the queue
+//! while the rest are still waiting.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::FileCache;
+use crate::cancel::CancelToken;
+use crate::hash::{hash_file, FileHash, HashAlgorithm};
+use crate::metadata::{self, Metadata};
+use crate::power::SleepGuard;
+
+/// What to read for each file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractOptions {
+    /// Read EXIF, tags and other properties.
+    pub metadata: bool,
+    /// Hash the contents with this algorithm.
+    pub hash: Option<HashAlgorithm>,
+    /// Files read at once; one per core when zero.
+    pub threads: usize,
+}
+
+/// The results for one file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extracted {
+    pub path: PathBuf,
+    pub metadata: Option<Metadata>,
+    pub hash: Option<FileHash>,
+}
+
+struct Pending {
+    /// Indices into `paths`, next first. A file moved to the front is
+    /// queued twice and only read the first time it comes up.
+    queue: VecDeque<usize>,
+    started: Vec<bool>,
+}
+
+struct Shared {
+    paths: Vec<PathBuf>,
+    index: HashMap<PathBuf, usize>,
+    pending: Mutex<Pending>,
+    cache: Option<Arc<FileCache>>,
+}
+
+/// Files waiting to be read. Clones share the queue, so one thread can
+/// [`prioritize`](Self::prioritize) while another [`run`](Self::run)s it.
+#[derive(Clone)]
+pub struct ExtractQueue(Arc<Shared>);
+
+impl ExtractQueue {
+    /// Queues `paths` in the order given.
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self::build(paths, None)
+    }
+
+    /// [`new`](Self::new), reading files unchanged since they were last
+    /// read from `cache` and adding the rest to it.
+    pub fn with_cache(paths: Vec<PathBuf>, cache: Arc<FileCache>) -> Self {
+        Self::build(paths, Some(cache))
+    }
+
+    fn build(paths: Vec<PathBuf>, cache: Option<Arc<FileCache>>) -> Self {
+        let index = paths
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.clone(), i))
+            .collect();
+        let pending = Pending {
+            queue: (0..paths.len()).collect(),
+            started: vec![false; paths.len()],
+        };
+        ExtractQueue(Arc::new(Shared {
+            paths,
+            index,
+            pending: Mutex::new(pending),
+            cache,
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.paths.is_empty()
+    }
+
+    /// Moves `paths` to the front of the queue, in the order given. Paths
+    /// that were not queued or are already being read are ignored.
+    pub fn prioritize(&self, paths: &[PathBuf]) {
+        let mut pending = self.0.pending.lock().unwrap();
+        for path in paths.iter().rev() {
+            if let Some(&i) = self.0.index.get(path) {
+                if !pending.started[i] {
+                    pending.queue.push_front(i);
+                }
+            }
+        }
+    }
+
+    fn next(&self) -> Option<usize> {
+        let mut pending = self.0.pending.lock().unwrap();
+        while let Some(i) = pending.queue.pop_front() {
+            if !pending.started[i] {
+                pending.started[i] = true;
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// Reads every queued file on `options.threads` threads, calling
+    /// `on_result` from them as each file is done. Stops starting new files
+    /// once `cancel` fires and returns how many were read.
+    pub fn run(
+        &self,
+        options: &ExtractOptions,
+        on_result: &(dyn Fn(Extracted) + Sync),
+        cancel: &CancelToken,
+    ) -> usize {
+        let _awake = options.hash.map(|_| SleepGuard::acquire("Hashing files"));
+        let threads = match options.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+        .min(self.len().max(1));
+        let done = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    while !cancel.is_cancelled() {
+                        let Some(i) = self.next() else {
+                            break;
+                        };
+                        let cache = self.0.cache.as_deref();
+                        on_result(read(&self.0.paths[i], options, cache));
+                        done.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        done.into_inner()
+    }
+}
+
+fn read(path: &Path, options: &ExtractOptions, cache: Option<&FileCache>) -> Extracted {
+    Extracted {
+        path: path.to_path_buf(),
+        metadata: options.metadata.then(|| match cache {
+            Some(cache) => cache.metadata(path),
+            None => metadata::extract(path),
+        }),
+        hash: options.hash.map(|algorithm| {
+            let found = match cache {
+                Some(cache) => cache.hash(path, algorithm),
+                None => hash_file(path, algorithm),
+            };
+            let (hash, error) = match found {
+                Ok(hash) => (Some(hash), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            FileHash {
+                path: path.to_path_buf(),
+                hash,
+                error,
+            }
+        }),
+    }
+}
@@ -0,0 +1,328 @@
+//! The files of a working session, kept on the Rust side with ids so the
+//! webview only ever holds the rows on screen, even for a million files.
+//!
+//! Files keep their id for as long as they are in the list, and ids are
+//! never reused. Sorting, filtering and selecting all happen here; the
+//! webview asks for windows of the result as it scrolls.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::fsutil;
+use crate::natural::{natural_cmp, natural_path_cmp};
+use crate::scan::{ScanEntry, ScanSort};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListedFile {
+    pub id: u64,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub is_dir: bool,
+    pub selected: bool,
+}
+
+impl ListedFile {
+    fn name(&self) -> std::borrow::Cow<'_, str> {
+        self.path.file_name().unwrap_or_default().to_string_lossy()
+    }
+}
+
+/// A window onto a [`FileList`], for virtual scrolling.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileListQuery {
+    pub sort: ScanSort,
+    pub descending: bool,
+    /// Text the name must contain, ignoring case; empty matches everything.
+    pub filter: String,
+    /// Only files that are selected.
+    pub selected_only: bool,
+    /// Matching files to skip.
+    pub offset: usize,
+    /// Most files to return; all the rest when unset.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileListWindow {
+    pub files: Vec<ListedFile>,
+    /// Files matching the query, so the scroll height is known.
+    pub matching: usize,
+    pub total: usize,
+    pub selected: usize,
+}
+
+/// The order a query last asked for, kept until the list changes.
+#[derive(Debug)]
+struct Order {
+    sort: ScanSort,
+    descending: bool,
+    filter: String,
+    selected_only: bool,
+    indices: Vec<usize>,
+}
+
+/// The session's files, in the order they were added.
+#[derive(Debug, Default)]
+pub struct FileList {
+    /// Always in id order, so ids are found by binary search.
+    files: Vec<ListedFile>,
+    next_id: u64,
+    /// Id of the file with each path hash. Hashing keeps the paths from
+    /// being stored twice; two paths with the same hash are told apart by
+    /// looking through the list.
+    by_hash: HashMap<u64, u64>,
+    selected: usize,
+    order: Option<Order>,
+}
+
+fn path_hash(path: &Path) -> u64 {
+    xxh3_64(path.as_os_str().as_encoded_bytes())
+}
+
+impl FileList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Files selected.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn get(&self, id: u64) -> Option<&ListedFile> {
+        self.index(id).map(|i| &self.files[i])
+    }
+
+    fn index(&self, id: u64) -> Option<usize> {
+        self.files.binary_search_by_key(&id, |f| f.id).ok()
+    }
+
+    fn contains(&self, path: &Path, hash: u64) -> bool {
+        match self.by_hash.get(&hash).and_then(|&id| self.get(id)) {
+            Some(file) if file.path == path => true,
+            Some(_) => self.files.iter().any(|f| f.path == path),
+            None => false,
+        }
+    }
+
+    fn push(&mut self, path: PathBuf, size: u64, modified: Option<DateTime<Utc>>, is_dir: bool) {
+        let hash = path_hash(&path);
+        if self.contains(&path, hash) {
+            return;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.by_hash.entry(hash).or_insert(id);
+        self.files.push(ListedFile {
+            id,
+            path,
+            size,
+            modified,
+            is_dir,
+            selected: false,
+        });
+    }
+
+    /// Adds `paths` with their size and modification time, skipping those
+    /// already listed, and returns how many were added. Files that cannot
+    /// be read are listed with neither.
+    pub fn add_paths(&mut self, paths: Vec<PathBuf>) -> usize {
+        let before = self.files.len();
+        let stats: Vec<_> = paths
+            .into_par_iter()
+            .map(|path| {
+                let metadata = fs::symlink_metadata(fsutil::long(&path)).ok();
+                (path, metadata)
+            })
+            .collect();
+        for (path, metadata) in stats {
+            let (size, modified, is_dir) = match metadata {
+                Some(m) => (
+                    m.len(),
+                    m.modified().ok().map(DateTime::<Utc>::from),
+                    m.is_dir(),
+                ),
+                None => (0, None, false),
+            };
+            self.push(path, size, modified, is_dir);
+        }
+        self.order = None;
+        self.files.len() - before
+    }
+
+    /// Adds what a scan listed, skipping entries already listed, and
+    /// returns how many were added.
+    pub fn add_entries(&mut self, entries: impl IntoIterator<Item = ScanEntry>) -> usize {
+        let before = self.files.len();
+        for entry in entries {
+            self.push(entry.path, entry.size, entry.modified, entry.is_dir);
+        }
+        self.order = None;
+        self.files.len() - before
+    }
+
+    /// Takes the files with `ids` out of the list and returns how many it
+    /// held.
+    pub fn remove(&mut self, ids: &[u64]) -> usize {
+        let mut gone: Vec<u64> = ids.to_vec();
+        gone.sort_unstable();
+        let before = self.files.len();
+        let by_hash = &mut self.by_hash;
+        let selected = &mut self.selected;
+        self.files.retain(|f| {
+            if gone.binary_search(&f.id).is_err() {
+                return true;
+            }
+            let hash = path_hash(&f.path);
+            if by_hash.get(&hash) == Some(&f.id) {
+                by_hash.remove(&hash);
+            }
+            *selected -= usize::from(f.selected);
+            false
+        });
+        self.order = None;
+        before - self.files.len()
+    }
+
+    pub fn clear(&mut self) {
+        *self = FileList {
+            next_id: self.next_id,
+            ..FileList::default()
+        };
+    }
+
+    /// Selects or deselects the files with `ids` and returns how many are
+    /// selected now.
+    pub fn select(&mut self, ids: &[u64], selected: bool) -> usize {
+        for &id in ids {
+            if let Some(i) = self.index(id) {
+                self.set_selected(i, selected);
+            }
+        }
+        self.selection_changed();
+        self.selected
+    }
+
+    /// Selects or deselects every file whose name contains `filter`,
+    /// ignoring case, or every file for an empty filter, and returns how
+    /// many are selected now.
+    pub fn select_matching(&mut self, filter: &str, selected: bool) -> usize {
+        let filter = filter.to_lowercase();
+        for i in 0..self.files.len() {
+            if filter.is_empty() || self.files[i].name().to_lowercase().contains(&filter) {
+                self.set_selected(i, selected);
+            }
+        }
+        self.selection_changed();
+        self.selected
+    }
+
+    fn set_selected(&mut self, i: usize, selected: bool) {
+        let file = &mut self.files[i];
+        if file.selected != selected {
+            file.selected = selected;
+            if selected {
+                self.selected += 1;
+            } else {
+                self.selected -= 1;
+            }
+        }
+    }
+
+    fn selection_changed(&mut self) {
+        if self.order.as_ref().is_some_and(|o| o.selected_only) {
+            self.order = None;
+        }
+    }
+
+    /// The paths of the list, or of its selected files, in the order added.
+    pub fn paths(&self, selected_only: bool) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .filter(|f| f.selected || !selected_only)
+            .map(|f| f.path.clone())
+            .collect()
+    }
+
+    /// The files `query` asks for. Asking for another window of the same
+    /// order is answered without sorting again.
+    pub fn query(&mut self, query: &FileListQuery) -> FileListWindow {
+        let filter = query.filter.to_lowercase();
+        let cached = self.order.as_ref().is_some_and(|o| {
+            o.sort == query.sort
+                && o.descending == query.descending
+                && o.filter == filter
+                && o.selected_only == query.selected_only
+        });
+        if !cached {
+            let indices = self.sorted(query, &filter);
+            self.order = Some(Order {
+                sort: query.sort,
+                descending: query.descending,
+                filter,
+                selected_only: query.selected_only,
+                indices,
+            });
+        }
+        let order = &self
+            .order
+            .as_ref()
+            .expect("order was just computed")
+            .indices;
+        FileListWindow {
+            files: order
+                .iter()
+                .skip(query.offset)
+                .take(query.limit.unwrap_or(usize::MAX))
+                .map(|&i| self.files[i].clone())
+                .collect(),
+            matching: order.len(),
+            total: self.files.len(),
+            selected: self.selected,
+        }
+    }
+
+    fn sorted(&self, query: &FileListQuery, filter: &str) -> Vec<usize> {
+        let files = &self.files;
+        let mut order: Vec<usize> = (0..files.len())
+            .into_par_iter()
+            .filter(|&i| {
+                let file = &files[i];
+                (file.selected || !query.selected_only)
+                    && (filter.is_empty() || file.name().to_lowercase().contains(filter))
+            })
+            .collect();
+        match query.sort {
+            ScanSort::Path => {
+                order.par_sort_by(|&a, &b| natural_path_cmp(&files[a].path, &files[b].path))
+            }
+            ScanSort::Name => order.par_sort_by(|&a, &b| {
+                let (a, b) = (&files[a], &files[b]);
+                natural_cmp(&a.name(), &b.name(), true)
+                    .then_with(|| natural_path_cmp(&a.path, &b.path))
+            }),
+            ScanSort::Size => order.par_sort_by_key(|&i| (files[i].size, &files[i].path)),
+            ScanSort::Modified => order.par_sort_by_key(|&i| (files[i].modified, &files[i].path)),
+        }
+        if query.descending {
+            order.reverse();
+        }
+        order
+    }
+}
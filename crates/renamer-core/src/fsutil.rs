@@ -0,0 +1,229 @@
+//! Filesystem helpers shared by the planner, executor and history replay.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Longest file name a single path component may have on any supported
+/// platform (UTF-16 units on Windows, bytes elsewhere).
+const MAX_COMPONENT: usize = 255;
+
+/// Longest total path, leaving room for the `\\?\` prefix on Windows.
+#[cfg(windows)]
+const MAX_PATH_LEN: usize = 32_760;
+#[cfg(not(windows))]
+const MAX_PATH_LEN: usize = 4_095;
+
+/// Beyond this length Win32 calls need the extended-length form; directory
+/// creation already fails at 248.
+#[cfg(windows)]
+const LEGACY_MAX_PATH: usize = 248;
+
+/// Returns `path` in the form the OS accepts for any length. On Windows,
+/// long absolute paths get the `\\?\` (or `\\?\UNC\`) prefix, with `.`
+/// and `..` resolved since the prefix disables that processing. Elsewhere
+/// paths are returned unchanged.
+#[cfg(windows)]
+pub(crate) fn long(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    if path.as_os_str().len() < LEGACY_MAX_PATH {
+        return Cow::Borrowed(path);
+    }
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return Cow::Borrowed(path);
+    };
+    let mut root = OsString::from(r"\\?\");
+    match prefix.kind() {
+        Prefix::Disk(_) => root.push(prefix.as_os_str()),
+        Prefix::UNC(server, share) => {
+            root.push(r"UNC\");
+            root.push(server);
+            root.push(r"\");
+            root.push(share);
+        }
+        // Already verbatim, or a device path that must not be rewritten.
+        _ => return Cow::Borrowed(path),
+    }
+    let mut out = PathBuf::from(root);
+    for component in components {
+        match component {
+            Component::RootDir => out.push(r"\"),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::Normal(part) => out.push(part),
+            Component::Prefix(_) => unreachable!("prefix only appears first"),
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(not(windows))]
+pub(crate) fn long(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// True if anything, including a dangling symlink, is at `path`. Also works
+/// past the legacy Windows path limit.
+pub(crate) fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(long(path)).is_ok()
+}
+
+/// True if `path` is a folder itself, not a link to one.
+pub(crate) fn is_dir(path: &Path) -> bool {
+    fs::symlink_metadata(long(path)).is_ok_and(|m| m.is_dir())
+}
+
+/// Sets the modification time of the file at `path`, returning the old one.
+pub(crate) fn set_modified(path: &Path, time: SystemTime) -> io::Result<SystemTime> {
+    let file = fs::OpenOptions::new().write(true).open(long(path))?;
+    let old = file.metadata()?.modified()?;
+    file.set_modified(time)?;
+    Ok(old)
+}
+
+pub(crate) fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(long(path)).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Where the link at `path` points, resolved against the link's folder.
+pub(crate) fn link_target(path: &Path) -> Option<PathBuf> {
+    let target = fs::read_link(long(path)).ok()?;
+    Some(match path.parent() {
+        Some(parent) if target.is_relative() => parent.join(target),
+        _ => target,
+    })
+}
+
+#[cfg(unix)]
+fn symlink(contents: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(contents, long(link))
+}
+
+#[cfg(windows)]
+fn symlink(contents: &Path, link: &Path) -> io::Result<()> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+    let resolved = match link.parent() {
+        Some(parent) => parent.join(contents),
+        None => contents.to_path_buf(),
+    };
+    if resolved.is_dir() {
+        symlink_dir(contents, long(link))
+    } else {
+        symlink_file(contents, long(link))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_contents: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Rewrites the link at `link` to `contents`, returning what it held before.
+pub(crate) fn replace_link(link: &Path, contents: &Path) -> io::Result<PathBuf> {
+    let old = fs::read_link(long(link))?;
+    fs::remove_file(long(link)).or_else(|_| fs::remove_dir(long(link)))?;
+    symlink(contents, link).inspect_err(|_| {
+        let _ = symlink(&old, link);
+    })?;
+    Ok(old)
+}
+
+/// Re-points the link at `link` to `new_name` in the folder it already points
+/// into, keeping relative links relative. Returns the previous contents.
+pub(crate) fn repoint(link: &Path, new_name: &std::ffi::OsStr) -> io::Result<PathBuf> {
+    let old = fs::read_link(long(link))?;
+    replace_link(link, &old.with_file_name(new_name))
+}
+
+#[cfg(windows)]
+fn units(s: &std::ffi::OsStr) -> usize {
+    use std::os::windows::ffi::OsStrExt;
+    s.encode_wide().count()
+}
+
+#[cfg(not(windows))]
+fn units(s: &std::ffi::OsStr) -> usize {
+    s.len()
+}
+
+/// True when `path` or one of its components is longer than the platform can
+/// store, even with extended-length paths.
+pub(crate) fn too_long(path: &Path) -> bool {
+    units(path.as_os_str()) > MAX_PATH_LEN
+        || path
+            .iter()
+            .any(|component| units(component) > MAX_COMPONENT)
+}
+
+/// `.name.tag` next to `path`, used for temporary and backup names.
+pub(crate) fn hidden_sibling(path: &Path, tag: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}", name, tag))
+}
+
+/// True when both paths resolve to the same file on disk.
+#[cfg(unix)]
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::symlink_metadata(long(a)), fs::symlink_metadata(long(b))) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// True when both paths resolve to the same file on disk. Canonicalizing
+/// returns the on-disk spelling, so two casings of one name compare equal.
+#[cfg(not(unix))]
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(long(a)), fs::canonicalize(long(b))) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// True when `target` only differs from `source` in letter case and the
+/// volume treats both spellings as the same existing file, as NTFS and
+/// default APFS/HFS+ volumes do. Such a target is not a collision, but a plain
+/// rename may silently keep the old spelling.
+pub(crate) fn is_case_only_rename(source: &Path, target: &Path) -> bool {
+    source != target
+        && source.to_string_lossy().to_lowercase() == target.to_string_lossy().to_lowercase()
+        && same_file(source, target)
+        && !has_entry_named(target)
+}
+
+/// True when the parent directory lists an entry spelled exactly like
+/// `path`'s file name. On a case-sensitive volume two spellings that are the
+/// same file are hard links, each with its own entry.
+pub(crate) fn has_entry_named(path: &Path) -> bool {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return false;
+    };
+    fs::read_dir(long(parent))
+        .map(|entries| entries.flatten().any(|e| e.file_name() == name))
+        .unwrap_or(false)
+}
+
+/// Renames `source` to `target`, going through a temporary name for case-only
+/// renames on case-insensitive volumes.
+pub(crate) fn rename(source: &Path, target: &Path) -> io::Result<()> {
+    let (source, target) = (long(source), long(target));
+    if !is_case_only_rename(&source, &target) {
+        return fs::rename(source, target);
+    }
+    let temp = hidden_sibling(&source, &format!("sortify-case-{}.tmp", std::process::id()));
+    fs::rename(&source, &temp)?;
+    fs::rename(&temp, &target).inspect_err(|_| {
+        let _ = fs::rename(&temp, &source);
+    })
+}
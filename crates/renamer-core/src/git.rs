@@ -0,0 +1,232 @@
+//! Keeping a git index in step with renames, so that in a work tree a batch
+//! shows up in `git status` as renames rather than as deleted and untracked
+//! files.
+//!
+//! Files are renamed on disk as always; afterwards the index entries of the
+//! tracked ones are moved to their new paths, keeping the staged contents
+//! and mode, which is what `git mv` does. Nothing else is staged, so
+//! unstaged changes to a renamed file stay unstaged. The index is updated
+//! with the `git` command, which has to be on the `PATH`; files moved into
+//! or out of a work tree, or between two, are left for git to see as
+//! deleted and new.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::executor::{nesting, ApplyReport, OutcomeStatus};
+use crate::history::{ReplayReport, ReplayStatus};
+
+/// What was updated in one repository's index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitReport {
+    /// The top of the work tree.
+    pub root: PathBuf,
+    /// Tracked files whose index entries were moved.
+    pub staged: usize,
+    /// Why the index could not be updated; it is left as it was then.
+    pub error: Option<String>,
+}
+
+fn git(root: &Path) -> Command {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(root)
+        .arg("--literal-pathspecs")
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// The top of the git work tree `path` is in, if it is in one.
+pub fn work_tree(path: &Path) -> Option<PathBuf> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let out = git(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let root = String::from_utf8(out.stdout).ok()?;
+    fs::canonicalize(root.trim_end_matches(['\r', '\n'])).ok()
+}
+
+/// `path` relative to `root`, with `/` between components as git writes
+/// it. The path may not exist, e.g. a file's name before a rename; the
+/// nearest folder above it that does is resolved, not the path itself, so a
+/// renamed symlink is not followed.
+fn relative(root: &Path, path: &Path) -> Option<String> {
+    let mut rest = vec![path.file_name()?];
+    let mut existing = path.parent()?;
+    let real = loop {
+        if let Ok(real) = fs::canonicalize(existing) {
+            break real;
+        }
+        rest.push(existing.file_name()?);
+        existing = existing.parent()?;
+    };
+    let mut parts: Vec<String> = Vec::new();
+    for component in real.strip_prefix(root).ok()?.components() {
+        let Component::Normal(part) = component else {
+            return None;
+        };
+        parts.push(part.to_str()?.to_string());
+    }
+    for part in rest.iter().rev() {
+        parts.push(part.to_str()?.to_string());
+    }
+    Some(parts.join("/"))
+}
+
+/// The stage 0 entries of the index, by path, as mode and object id.
+fn index(root: &Path) -> Result<BTreeMap<String, (String, String)>, String> {
+    let out = git(root)
+        .args(["ls-files", "--stage", "-z"])
+        .output()
+        .map_err(|e| format!("git could not be run: {}", e))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    let mut entries = BTreeMap::new();
+    for record in out.stdout.split(|&b| b == 0) {
+        let record = String::from_utf8_lossy(record);
+        let Some((head, path)) = record.split_once('\t') else {
+            continue;
+        };
+        let mut fields = head.split(' ');
+        let (Some(mode), Some(id), Some("0")) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        entries.insert(path.to_string(), (mode.to_string(), id.to_string()));
+    }
+    Ok(entries)
+}
+
+/// Moves the index entries of `moves`, each a list of moves made at the
+/// same time, in the order they were made.
+fn stage(root: &Path, rounds: &[Vec<(String, String)>]) -> Result<usize, String> {
+    let before = index(root)?;
+    let mut after = before.clone();
+    for round in rounds {
+        let mut moved = Vec::new();
+        for (from, to) in round {
+            let prefix = format!("{}/", from);
+            let keys: Vec<String> = after
+                .keys()
+                .filter(|k| *k == from || k.starts_with(&prefix))
+                .cloned()
+                .collect();
+            for key in keys {
+                let entry = after.remove(&key).expect("listed key");
+                moved.push((format!("{}{}", to, &key[from.len()..]), entry));
+            }
+        }
+        after.extend(moved);
+    }
+    let mut info = Vec::new();
+    for (path, (_, id)) in &before {
+        if !after.contains_key(path) {
+            let none = "0".repeat(id.len());
+            info.extend(format!("0 {}\t{}\0", none, path).into_bytes());
+        }
+    }
+    let mut staged = 0;
+    for (path, (mode, id)) in &after {
+        if before.get(path) != Some(&(mode.clone(), id.clone())) {
+            info.extend(format!("{} {}\t{}\0", mode, id, path).into_bytes());
+            staged += 1;
+        }
+    }
+    if info.is_empty() {
+        return Ok(0);
+    }
+    let mut child = git(root)
+        .args(["update-index", "-z", "--index-info"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("git could not be run: {}", e))?;
+    let written = child.stdin.take().expect("piped stdin").write_all(&info);
+    let out = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    written.map_err(|e| e.to_string())?;
+    Ok(staged)
+}
+
+/// Groups `moves` by the work tree both ends are in and stages them there,
+/// in rounds of the same nesting, deepest first or shallowest first.
+fn stage_moves<'a>(
+    moves: impl Iterator<Item = (&'a Path, &'a Path)>,
+    deepest_first: bool,
+) -> Vec<GitReport> {
+    let mut roots: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+    let mut repos: BTreeMap<PathBuf, BTreeMap<usize, Vec<(String, String)>>> = BTreeMap::new();
+    for (from, to) in moves {
+        let Some(parent) = to.parent() else {
+            continue;
+        };
+        let root = roots
+            .entry(parent.to_path_buf())
+            .or_insert_with(|| work_tree(parent));
+        let Some(root) = root.clone() else {
+            continue;
+        };
+        if let (Some(from_rel), Some(to_rel)) = (relative(&root, from), relative(&root, to)) {
+            repos
+                .entry(root)
+                .or_default()
+                .entry(nesting(from, to))
+                .or_default()
+                .push((from_rel, to_rel));
+        }
+    }
+    repos
+        .into_iter()
+        .map(|(root, by_nesting)| {
+            let mut rounds: Vec<Vec<(String, String)>> = by_nesting.into_values().collect();
+            if deepest_first {
+                rounds.reverse();
+            }
+            let (staged, error) = match stage(&root, &rounds) {
+                Ok(staged) => (staged, None),
+                Err(e) => (0, Some(e)),
+            };
+            GitReport {
+                root,
+                staged,
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Moves the index entries of the files `report` renamed, for every work
+/// tree they are in.
+pub fn stage_batch(report: &ApplyReport) -> Vec<GitReport> {
+    let moves = report
+        .outcomes
+        .iter()
+        .filter(|o| o.status == OutcomeStatus::Renamed)
+        .map(|o| (o.source.as_path(), o.target.as_path()));
+    // Nested renames run deepest first, each level after the one below it.
+    stage_moves(moves, true)
+}
+
+/// As [`stage_batch`], for the files an undo or a redo moved.
+pub fn stage_replay(report: &ReplayReport, undo: bool) -> Vec<GitReport> {
+    let moves = report
+        .outcomes
+        .iter()
+        .filter(|o| o.status == ReplayStatus::Done)
+        .map(|o| (o.from.as_path(), o.to.as_path()));
+    stage_moves(moves, !undo)
+}
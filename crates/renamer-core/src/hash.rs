@@ -0,0 +1,230 @@
+//! Content hashes of files, for `{hash:...}` template tokens and for
+//! checking files against each other.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use md5::Md5;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::cache::FileCache;
+use crate::cancel::CancelToken;
+use crate::executor::PROGRESS_INTERVAL;
+use crate::fsutil;
+use crate::power::SleepGuard;
+use crate::throttle;
+
+const BUFFER_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    /// 64-bit XXH3: not cryptographic, but many times faster than the others.
+    Xxh3,
+    Sha256,
+    Md5,
+}
+
+impl HashAlgorithm {
+    /// The algorithm as written in a template, e.g. `sha256` or `xxh3`.
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "xxh3" | "xxhash" => Some(HashAlgorithm::Xxh3),
+            "sha256" | "sha-256" => Some(HashAlgorithm::Sha256),
+            "md5" => Some(HashAlgorithm::Md5),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) enum Hasher {
+    Xxh3(Box<Xxh3>),
+    Sha256(Sha256),
+    Md5(Md5),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Xxh3(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Md5(h) => h.update(data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> String {
+        let digest = match self {
+            Hasher::Xxh3(h) => h.digest().to_be_bytes().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Md5(h) => h.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// The lowercase hex digest of the contents of `path`.
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+    hash_head(path, algorithm, u64::MAX)
+}
+
+/// The digest of the first `limit` bytes of `path`.
+pub(crate) fn hash_head(path: &Path, algorithm: HashAlgorithm, limit: u64) -> io::Result<String> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = vec![0; limit.min(BUFFER_SIZE as u64) as usize];
+    feed(
+        File::open(fsutil::long(path))?.take(limit),
+        &mut hasher,
+        &mut buf,
+    )?;
+    Ok(hasher.finish())
+}
+
+/// The digest of the first and the last `sample` bytes of `path`, which is
+/// `size` bytes long; of all of it when that is no more than both.
+pub(crate) fn hash_ends(
+    path: &Path,
+    size: u64,
+    algorithm: HashAlgorithm,
+    sample: u64,
+) -> io::Result<String> {
+    if size <= sample.saturating_mul(2) {
+        return hash_file(path, algorithm);
+    }
+    let mut file = File::open(fsutil::long(path))?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = vec![0; sample.min(BUFFER_SIZE as u64) as usize];
+    feed((&mut file).take(sample), &mut hasher, &mut buf)?;
+    file.seek(SeekFrom::Start(size - sample))?;
+    feed(file.take(sample), &mut hasher, &mut buf)?;
+    Ok(hasher.finish())
+}
+
+fn feed(mut reader: impl Read, hasher: &mut Hasher, buf: &mut [u8]) -> io::Result<()> {
+    loop {
+        let n = reader.read(buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+        throttle::pace(n);
+    }
+}
+
+/// Digests of one file under each of `algorithms`, leaving out the ones that
+/// could not be computed.
+pub(crate) fn hash_with(path: &Path, algorithms: &[HashAlgorithm]) -> Vec<(HashAlgorithm, String)> {
+    algorithms
+        .iter()
+        .filter_map(|&algorithm| Some((algorithm, hash_file(path, algorithm).ok()?)))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileHash {
+    pub path: PathBuf,
+    /// Lowercase hex digest; `None` if the file could not be read.
+    pub hash: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Progress of [`hash_files`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashProgress {
+    /// Files hashed so far.
+    pub completed: usize,
+    pub total: usize,
+    /// The file that was just hashed.
+    pub current: PathBuf,
+    /// Size of the files hashed so far.
+    pub bytes: u64,
+}
+
+/// Hashes `paths` on a thread pool, in the order given, reporting progress
+/// at most every 100 ms. Stops starting new files once `cancel` fires and
+/// returns the ones already hashed.
+pub fn hash_files(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    progress: &(dyn Fn(&HashProgress) + Sync),
+    cancel: &CancelToken,
+) -> Vec<FileHash> {
+    hash_all(paths, algorithm, None, progress, cancel)
+}
+
+/// [`hash_files`], taking the digests of files unchanged since they were
+/// last hashed from `cache` and adding the rest to it.
+pub fn hash_files_cached(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    cache: &FileCache,
+    progress: &(dyn Fn(&HashProgress) + Sync),
+    cancel: &CancelToken,
+) -> Vec<FileHash> {
+    hash_all(paths, algorithm, Some(cache), progress, cancel)
+}
+
+fn hash_all(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    cache: Option<&FileCache>,
+    progress: &(dyn Fn(&HashProgress) + Sync),
+    cancel: &CancelToken,
+) -> Vec<FileHash> {
+    let _awake = SleepGuard::acquire("Hashing files");
+    let completed = AtomicUsize::new(0);
+    let bytes = AtomicU64::new(0);
+    let last: Mutex<(Option<Instant>, usize)> = Mutex::new((None, 0));
+    paths
+        .par_iter()
+        .filter_map(|path| {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            let result = match cache {
+                Some(cache) => cache.hash(path, algorithm),
+                None => hash_file(path, algorithm),
+            };
+            let size = std::fs::metadata(fsutil::long(path)).map_or(0, |m| m.len());
+            let hashed = bytes.fetch_add(size, Ordering::Relaxed) + size;
+            let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+            let now = Instant::now();
+            let mut last = last.lock().unwrap();
+            let due = last
+                .0
+                .map_or(true, |t| now.duration_since(t) >= PROGRESS_INTERVAL);
+            if done > last.1 && (due || done == paths.len()) {
+                *last = (Some(now), done);
+                progress(&HashProgress {
+                    completed: done,
+                    total: paths.len(),
+                    current: path.clone(),
+                    bytes: hashed,
+                });
+            }
+            let (hash, error) = match result {
+                Ok(hash) => (Some(hash), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            Some(FileHash {
+                path: path.clone(),
+                hash,
+                error,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,448 @@
+//! HEIF images, such as the HEIC photos of iPhones and AVIF: their EXIF data
+//! and the previews stored with them.
+//!
+//! A HEIF file is an ISO base media file whose `meta` box lists items: the
+//! image, usually a grid of HEVC-coded tiles, its thumbnails and an `Exif`
+//! item, with where in the file each one's data is and how to turn it for
+//! display. Only the `meta` box and the items asked for are read. HEVC and
+//! AV1 cannot be decoded here, so previews come from JPEG-coded items when
+//! there are any and from the system's HEIF decoder otherwise: `sips` on
+//! macOS and libheif's `heif-thumbnailer` elsewhere, where it is installed.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use exif::Exif;
+
+use crate::raw::{self, boxes, read_at};
+
+/// Extensions of HEIF images.
+pub(crate) const EXTENSIONS: &[&str] = &["heic", "heics", "heif", "hif", "avif"];
+
+/// Largest `meta` box read, against files that only claim to be HEIF.
+const MAX_META: u64 = 16 << 20;
+
+/// Largest item read.
+const MAX_ITEM: u64 = 64 << 20;
+
+pub(crate) fn is_heif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Where an item's data is: extents of the file, or of the `idat` box.
+struct Location {
+    in_idat: bool,
+    extents: Vec<(u64, u64)>,
+}
+
+#[derive(Default)]
+struct Item {
+    kind: [u8; 4],
+    location: Option<Location>,
+    /// Quarter turns anticlockwise for display, from its `irot` property.
+    rotation: u8,
+}
+
+/// What the `meta` box says.
+#[derive(Default)]
+struct Meta {
+    primary: Option<u32>,
+    items: HashMap<u32, Item>,
+    /// The thumbnails of each image, from `thmb` references.
+    thumbnails: Vec<(u32, u32)>,
+    idat: Vec<u8>,
+}
+
+/// Reads big-endian numbers off the front of a box payload.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    /// An unsigned number of `n` bytes, up to 8.
+    fn uint(&mut self, n: usize) -> Option<u64> {
+        Some(
+            self.take(n)?
+                .iter()
+                .fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+        )
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u32> {
+        self.uint(2).map(|n| n as u32)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.uint(4).map(|n| n as u32)
+    }
+
+    fn kind(&mut self) -> Option<[u8; 4]> {
+        self.take(4)?.try_into().ok()
+    }
+
+    /// The version of a full box, skipping its flags.
+    fn version(&mut self) -> Option<(u8, u32)> {
+        let version = self.u8()?;
+        let flags = self.uint(3)? as u32;
+        Some((version, flags))
+    }
+}
+
+/// The child boxes of an in-memory payload.
+fn children(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut found = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let kind = [data[4], data[5], data[6], data[7]];
+        let size = if size == 0 { data.len() } else { size };
+        if size < 8 || size > data.len() {
+            break;
+        }
+        found.push((kind, &data[8..size]));
+        data = &data[size..];
+    }
+    found
+}
+
+impl Meta {
+    fn read(file: &mut File) -> Option<Meta> {
+        let len = file.metadata().ok()?.len();
+        let top = boxes(file, 0, len);
+        let ftyp = top.iter().find(|b| &b.kind == b"ftyp")?;
+        let brands = read_at(file, ftyp.start, (ftyp.end - ftyp.start).min(256)).ok()?;
+        let heif = brands
+            .chunks_exact(4)
+            .any(|b| matches!(b, b"mif1" | b"msf1" | b"heic" | b"heix" | b"avif"));
+        let meta = top.iter().find(|b| &b.kind == b"meta")?;
+        if !heif || meta.end - meta.start > MAX_META {
+            return None;
+        }
+        let data = read_at(file, meta.start, meta.end - meta.start).ok()?;
+        let mut parsed = Meta::default();
+        let mut properties: Vec<Option<u8>> = Vec::new();
+        let mut associations: Vec<(u32, Vec<u16>)> = Vec::new();
+        for (kind, body) in children(data.get(4..)?) {
+            let mut bytes = Bytes(body);
+            match &kind {
+                b"pitm" => {
+                    let (version, _) = bytes.version()?;
+                    parsed.primary = if version == 0 {
+                        bytes.u16()
+                    } else {
+                        bytes.u32()
+                    };
+                }
+                b"iinf" => parsed.read_iinf(bytes),
+                b"iloc" => parsed.read_iloc(bytes),
+                b"iref" => parsed.read_iref(bytes),
+                b"idat" => parsed.idat = body.to_vec(),
+                b"iprp" => {
+                    for (kind, body) in children(body) {
+                        match &kind {
+                            b"ipco" => {
+                                properties = children(body)
+                                    .into_iter()
+                                    .map(|(kind, body)| {
+                                        (&kind == b"irot")
+                                            .then(|| body.first().map_or(0, |b| b & 3))
+                                    })
+                                    .collect();
+                            }
+                            b"ipma" => associations.extend(read_ipma(Bytes(body))),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (id, indexes) in associations {
+            let rotation = indexes
+                .iter()
+                .find_map(|&i| properties.get(usize::from(i).checked_sub(1)?).copied()?);
+            if let (Some(rotation), Some(item)) = (rotation, parsed.items.get_mut(&id)) {
+                item.rotation = rotation;
+            }
+        }
+        Some(parsed)
+    }
+
+    fn read_iinf(&mut self, mut bytes: Bytes) {
+        let Some((version, _)) = bytes.version() else {
+            return;
+        };
+        let count = if version == 0 {
+            bytes.u16()
+        } else {
+            bytes.u32()
+        };
+        for (kind, body) in children(bytes.0)
+            .into_iter()
+            .take(count.unwrap_or(0) as usize)
+        {
+            if &kind != b"infe" {
+                continue;
+            }
+            let mut entry = Bytes(body);
+            let Some((version @ 2..=3, _)) = entry.version() else {
+                continue;
+            };
+            let id = if version == 2 {
+                entry.u16()
+            } else {
+                entry.u32()
+            };
+            let _protection = entry.u16();
+            if let (Some(id), Some(kind)) = (id, entry.kind()) {
+                self.items.entry(id).or_default().kind = kind;
+            }
+        }
+    }
+
+    fn read_iloc(&mut self, mut bytes: Bytes) {
+        let _ = (|| {
+            let (version, _) = bytes.version()?;
+            let sizes = bytes.u8()?;
+            let (offset_size, length_size) = (usize::from(sizes >> 4), usize::from(sizes & 15));
+            let sizes = bytes.u8()?;
+            let base_size = usize::from(sizes >> 4);
+            let index_size = if version > 0 {
+                usize::from(sizes & 15)
+            } else {
+                0
+            };
+            let count = if version < 2 {
+                bytes.u16()?
+            } else {
+                bytes.u32()?
+            };
+            for _ in 0..count {
+                let id = if version < 2 {
+                    bytes.u16()?
+                } else {
+                    bytes.u32()?
+                };
+                let method = if version > 0 { bytes.u16()? & 15 } else { 0 };
+                let _data_reference = bytes.u16()?;
+                let base = bytes.uint(base_size)?;
+                let extents = bytes.u16()?;
+                let mut location = Location {
+                    in_idat: method == 1,
+                    extents: Vec::new(),
+                };
+                for _ in 0..extents {
+                    bytes.uint(index_size)?;
+                    let offset = bytes.uint(offset_size)?;
+                    let length = bytes.uint(length_size)?;
+                    location.extents.push((base + offset, length));
+                }
+                // Items in other files or made from other items are left
+                // without a location.
+                if method <= 1 {
+                    self.items.entry(id).or_default().location = Some(location);
+                }
+            }
+            Some(())
+        })();
+    }
+
+    fn read_iref(&mut self, mut bytes: Bytes) {
+        let Some((version, _)) = bytes.version() else {
+            return;
+        };
+        for (kind, body) in children(bytes.0) {
+            let mut reference = Bytes(body);
+            let id = |r: &mut Bytes| if version == 0 { r.u16() } else { r.u32() };
+            let (Some(from), Some(count)) = (id(&mut reference), reference.u16()) else {
+                continue;
+            };
+            let to: Vec<u32> = (0..count).map_while(|_| id(&mut reference)).collect();
+            // A thumbnail refers to the images it is one of.
+            if &kind == b"thmb" {
+                self.thumbnails.extend(to.into_iter().map(|to| (from, to)));
+            }
+        }
+    }
+
+    /// The data of item `id`.
+    fn data(&self, file: &mut File, id: u32) -> Option<Vec<u8>> {
+        let location = self.items.get(&id)?.location.as_ref()?;
+        let mut data = Vec::new();
+        for &(offset, length) in &location.extents {
+            if data.len() as u64 + length > MAX_ITEM {
+                return None;
+            }
+            if location.in_idat {
+                let start = usize::try_from(offset).ok()?;
+                let end = start.checked_add(usize::try_from(length).ok()?)?;
+                data.extend_from_slice(self.idat.get(start..end)?);
+            } else {
+                // A length of 0 runs to the end of the file.
+                let length = if length == 0 { MAX_ITEM } else { length };
+                data.extend(read_at(file, offset, length).ok()?);
+            }
+        }
+        Some(data)
+    }
+
+    /// The image and its thumbnails, the image first.
+    fn images(&self) -> Vec<u32> {
+        let Some(primary) = self.primary else {
+            return Vec::new();
+        };
+        let thumbnails = self
+            .thumbnails
+            .iter()
+            .filter(|(_, of)| *of == primary)
+            .map(|(id, _)| *id);
+        std::iter::once(primary).chain(thumbnails).collect()
+    }
+}
+
+/// Item ids with the 1-based indexes of their properties.
+fn read_ipma(mut bytes: Bytes) -> Vec<(u32, Vec<u16>)> {
+    let mut found = Vec::new();
+    let _ = (|| {
+        let (version, flags) = bytes.version()?;
+        let count = bytes.u32()?;
+        for _ in 0..count {
+            let id = if version < 1 {
+                bytes.u16()?
+            } else {
+                bytes.u32()?
+            };
+            let associations = bytes.u8()?;
+            let indexes = (0..associations)
+                .map(|_| {
+                    Some(if flags & 1 == 1 {
+                        (bytes.u16()? & 0x7fff) as u16
+                    } else {
+                        u16::from(bytes.u8()? & 0x7f)
+                    })
+                })
+                .collect::<Option<Vec<u16>>>()?;
+            found.push((id, indexes));
+        }
+        Some(())
+    })();
+    found
+}
+
+/// The EXIF data of the HEIF image at `path`.
+pub(crate) fn exif(path: &Path) -> Option<Exif> {
+    let mut file = File::open(path).ok()?;
+    let meta = Meta::read(&mut file)?;
+    let mut ids: Vec<u32> = meta
+        .items
+        .iter()
+        .filter(|(_, item)| &item.kind == b"Exif")
+        .map(|(id, _)| *id)
+        .collect();
+    ids.sort_unstable();
+    let data = meta.data(&mut file, *ids.first()?)?;
+    // The item starts with how far past this offset the TIFF header is.
+    let skip = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let tiff = data.get(4usize.checked_add(skip)?..)?;
+    raw::parse_tiff(tiff.to_vec())
+}
+
+/// A JPEG of the image at `path` for a preview of `size`, and the quarter
+/// turns anticlockwise that show it upright; from a JPEG-coded image or
+/// thumbnail item if there is one, else from the system's decoder, which
+/// turns it itself.
+pub(crate) fn preview(path: &Path, size: u32) -> Option<(Vec<u8>, u8)> {
+    let mut file = File::open(path).ok()?;
+    let meta = Meta::read(&mut file)?;
+    let jpegs: Vec<(Vec<u8>, u8, (u32, u32))> = meta
+        .images()
+        .into_iter()
+        .filter(|id| meta.items.get(id).is_some_and(|item| &item.kind == b"jpeg"))
+        .filter_map(|id| {
+            let data = meta.data(&mut file, id)?;
+            let dimensions = raw::jpeg_size(&data)?;
+            Some((data, meta.items[&id].rotation, dimensions))
+        })
+        .collect();
+    match raw::closest(jpegs, size, |(_, _, dimensions)| *dimensions) {
+        Some((data, rotation, _)) => Some((data, rotation)),
+        None => platform::decode(path, size).map(|data| (data, 0)),
+    }
+}
+
+/// The program previews of images without a JPEG-coded item come from,
+/// where there is one to run.
+pub(crate) const DECODER: Option<&str> = platform::DECODER;
+
+#[cfg(unix)]
+mod platform {
+    use std::fs;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    use uuid::Uuid;
+
+    #[cfg(target_os = "macos")]
+    pub const DECODER: Option<&str> = Some("sips");
+    #[cfg(not(target_os = "macos"))]
+    pub const DECODER: Option<&str> = Some("heif-thumbnailer");
+
+    #[cfg(target_os = "macos")]
+    fn command(path: &Path, size: u32, out: &Path) -> Command {
+        let mut command = Command::new("sips");
+        command
+            .args(["-s", "format", "jpeg", "-Z"])
+            .arg(size.to_string())
+            .arg(path)
+            .arg("--out")
+            .arg(out);
+        command
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn command(path: &Path, size: u32, out: &Path) -> Command {
+        let mut command = Command::new("heif-thumbnailer");
+        command.arg("-s").arg(size.to_string()).arg(path).arg(out);
+        command
+    }
+
+    /// A JPEG or PNG of the image, no larger than `size`, written by the
+    /// system's decoder.
+    pub fn decode(path: &Path, size: u32) -> Option<Vec<u8>> {
+        let out = std::env::temp_dir().join(format!("sortify-heif-{}.img", Uuid::new_v4()));
+        let status = command(path, size, &out)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        let data = fs::read(&out).ok();
+        let _ = fs::remove_file(&out);
+        data.filter(|_| status.is_ok_and(|s| s.success()))
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use std::path::Path;
+
+    pub const DECODER: Option<&str> = None;
+
+    /// Windows only decodes HEIF with a store extension, and not from the
+    /// command line.
+    pub fn decode(_path: &Path, _size: u32) -> Option<Vec<u8>> {
+        None
+    }
+}
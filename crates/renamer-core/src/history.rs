@@ -0,0 +1,542 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::executor::{nesting, rename_file, sequence, ApplyReport, OutcomeStatus};
+use crate::fsutil::{self, is_case_only_rename};
+use crate::identity::{self, FileId};
+use crate::transfer::CrossVolume;
+
+/// Matches the Python sidecar's history limit.
+const MAX_BATCHES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryState {
+    /// The file currently lives at `target`.
+    Applied,
+    /// The file was moved back to `source`.
+    Undone,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: usize,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub state: EntryState,
+    pub changed_at: DateTime<Utc>,
+    /// Symlink that follows the file between `source` and `target`.
+    #[serde(default)]
+    pub link: Option<PathBuf>,
+    #[serde(default)]
+    pub is_dir: bool,
+    /// Modification time the batch gave the file, and the one it had before.
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub previous_modified: Option<DateTime<Utc>>,
+    /// The file as the batch left it at `target`, or as the post action
+    /// run on the batch left it, which [`HistoryStore::undo_files`] checks
+    /// it still is; a file without one is not undone that way.
+    #[serde(default)]
+    pub left: Option<Fingerprint>,
+}
+
+/// Enough of a file to tell it is the same one, unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub file: Option<FileId>,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+impl Fingerprint {
+    pub fn of(path: &Path) -> Option<Fingerprint> {
+        let metadata = fs::symlink_metadata(fsutil::long(path)).ok()?;
+        Some(Fingerprint {
+            file: identity::of_metadata(path, &metadata).map(|(id, _)| id),
+            size: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
+
+    /// Whether `now` is this file. A folder changes whenever its contents
+    /// do, so only its id counts.
+    fn matches(&self, now: &Fingerprint, is_dir: bool) -> bool {
+        let same_file = match (self.file, now.file) {
+            (Some(then), Some(now)) => then == now,
+            _ => true,
+        };
+        same_file && (is_dir || self.size == now.size && self.modified == now.modified)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRecord {
+    pub batch_id: Uuid,
+    pub applied_at: DateTime<Utc>,
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl BatchRecord {
+    /// Takes [`HistoryEntry::left`] of every applied entry, where the file is
+    /// now: inside the folders of the batch that moved it along.
+    fn fingerprint(&mut self) {
+        let folders = moved_folders(
+            self.entries
+                .iter()
+                .filter(|e| e.state == EntryState::Applied),
+        );
+        for entry in &mut self.entries {
+            if entry.state == EntryState::Applied {
+                entry.left = Fingerprint::of(&moved_along(&entry.target, &folders));
+            }
+        }
+    }
+
+    pub fn is_applied(&self) -> bool {
+        self.entries.iter().any(|e| e.state == EntryState::Applied)
+    }
+
+    pub fn is_undone(&self) -> bool {
+        self.entries.iter().any(|e| e.state == EntryState::Undone)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayStatus {
+    /// Moved back (undo) or forward again (redo).
+    Done,
+    /// The file is no longer where the batch left it.
+    Missing,
+    /// Something else now occupies the destination.
+    Occupied,
+    /// The file is not the one the batch left, or was changed since; see
+    /// [`HistoryStore::undo_files`].
+    Changed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayOutcome {
+    pub id: usize,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub status: ReplayStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub batch_id: Uuid,
+    pub outcomes: Vec<ReplayOutcome>,
+    pub done: usize,
+    pub problems: usize,
+}
+
+/// On-disk log of applied batches, newest first, used for undo and redo.
+#[derive(Debug)]
+pub struct HistoryStore {
+    path: PathBuf,
+    batches: Vec<BatchRecord>,
+}
+
+impl HistoryStore {
+    /// Loads the history at `path`; a missing file is an empty history.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let batches = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(Error::io(&path, e)),
+        };
+        Ok(HistoryStore { path, batches })
+    }
+
+    pub fn batches(&self) -> &[BatchRecord] {
+        &self.batches
+    }
+
+    pub fn get(&self, batch_id: Uuid) -> Option<&BatchRecord> {
+        self.batches.iter().find(|b| b.batch_id == batch_id)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let json = serde_json::to_vec_pretty(&self.batches)?;
+        fs::write(&self.path, json).map_err(|e| Error::io(&self.path, e))
+    }
+
+    /// Records the renamed and verified entries of a committed batch.
+    pub fn record(&mut self, report: &ApplyReport) -> Result<()> {
+        if !report.committed {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let entries: Vec<HistoryEntry> = report
+            .outcomes
+            .iter()
+            .filter(|o| o.status == OutcomeStatus::Renamed && o.verified)
+            .map(|o| HistoryEntry {
+                id: o.id,
+                source: o.source.clone(),
+                target: o.target.clone(),
+                state: EntryState::Applied,
+                changed_at: now,
+                link: o.link.clone(),
+                is_dir: o.is_dir,
+                modified: o.modified,
+                previous_modified: o.previous_modified,
+                left: None,
+            })
+            .collect();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut batch = BatchRecord {
+            batch_id: report.batch_id,
+            applied_at: now,
+            entries,
+        };
+        batch.fingerprint();
+        self.batches.insert(0, batch);
+        self.batches.truncate(MAX_BATCHES);
+        self.save()
+    }
+
+    /// Takes the fingerprints [`HistoryStore::undo_files`] checks files
+    /// against again, for the files of `batch_id` that are not undone. For
+    /// once the post action run on the batch is done, which may have changed
+    /// them, e.g. written their tags; run before, every file it changed would
+    /// count as changed since.
+    pub fn fingerprint_again(&mut self, batch_id: Uuid) -> Result<()> {
+        let Some(batch) = self.batches.iter_mut().find(|b| b.batch_id == batch_id) else {
+            return Ok(());
+        };
+        batch.fingerprint();
+        self.save()
+    }
+
+    /// Moves the files of the most recent applied batch back where they came from.
+    pub fn undo_last(&mut self) -> Result<Option<ReplayReport>> {
+        let Some(idx) = self.batches.iter().position(BatchRecord::is_applied) else {
+            return Ok(None);
+        };
+        let report = replay(&mut self.batches[idx], EntryState::Applied, None);
+        self.save()?;
+        Ok(Some(report))
+    }
+
+    /// Moves only the files `ids` of `batch_id` back, each entry's
+    /// [`HistoryEntry::id`], leaving the rest of the batch as it is; entries
+    /// already undone are passed over. A file that is no longer the one the
+    /// batch left at its new name, or was changed since, or has no
+    /// [`HistoryEntry::left`] to tell, stays there as
+    /// [`ReplayStatus::Changed`]. Files inside a folder the batch renamed
+    /// and that is not undone along with them go back to their old names in
+    /// the folder's new place. Returns `None` when there is no such batch.
+    pub fn undo_files(&mut self, batch_id: Uuid, ids: &[usize]) -> Result<Option<ReplayReport>> {
+        let Some(idx) = self.batches.iter().position(|b| b.batch_id == batch_id) else {
+            return Ok(None);
+        };
+        let batch = &mut self.batches[idx];
+        if let Some(&id) = ids
+            .iter()
+            .find(|id| !batch.entries.iter().any(|e| e.id == **id))
+        {
+            return Err(Error::NotInBatch { batch_id, id });
+        }
+        let ids: HashSet<usize> = ids.iter().copied().collect();
+        let report = replay(batch, EntryState::Applied, Some(&ids));
+        self.save()?;
+        Ok(Some(report))
+    }
+
+    /// Re-applies an undone batch: `batch_id`, or the most recently undone one.
+    pub fn redo(&mut self, batch_id: Option<Uuid>) -> Result<Option<ReplayReport>> {
+        let found = match batch_id {
+            Some(id) => self.batches.iter().position(|b| b.batch_id == id),
+            None => self.batches.iter().position(BatchRecord::is_undone),
+        };
+        let Some(idx) = found else {
+            return Ok(None);
+        };
+        let report = replay(&mut self.batches[idx], EntryState::Undone, None);
+        self.save()?;
+        Ok(Some(report))
+    }
+}
+
+/// The folders of `entries`, from where to where they were renamed,
+/// deepest first for [`moved_along`].
+fn moved_folders<'a>(entries: impl Iterator<Item = &'a HistoryEntry>) -> Vec<(PathBuf, PathBuf)> {
+    let mut folders: Vec<(PathBuf, PathBuf)> = entries
+        .filter(|e| e.is_dir)
+        .map(|e| (e.source.clone(), e.target.clone()))
+        .collect();
+    folders.sort_by_key(|(source, _)| Reverse(source.components().count()));
+    folders
+}
+
+/// Where `path`, in the tree as it was before `folders` were renamed, is
+/// once they are: what was in a folder went along with it, under its new
+/// name, so a folder within a folder gives both names.
+fn moved_along(path: &Path, folders: &[(PathBuf, PathBuf)]) -> PathBuf {
+    let mut path = path.to_path_buf();
+    for (source, target) in folders {
+        if let Some(rest) = path
+            .strip_prefix(source)
+            .ok()
+            .filter(|r| !r.as_os_str().is_empty())
+        {
+            path = target.join(rest);
+        }
+    }
+    path
+}
+
+/// Flips every entry of `batch` currently in state `from`, or those of
+/// them in `only`, which are checked against [`HistoryEntry::left`] first.
+/// Moves are ordered with the executor's sequencing, so chains and swaps
+/// replay correctly in either direction. Outcomes are listed backwards for
+/// undo, forwards for redo.
+fn replay(
+    batch: &mut BatchRecord,
+    from: EntryState,
+    only: Option<&HashSet<usize>>,
+) -> ReplayReport {
+    let undo = from == EntryState::Applied;
+    let replayed = |entry: &HistoryEntry| {
+        entry.state == from && only.map_or(true, |ids| ids.contains(&entry.id))
+    };
+    let mut report = ReplayReport {
+        batch_id: batch.batch_id,
+        outcomes: Vec::new(),
+        done: 0,
+        problems: 0,
+    };
+
+    // Folders of the batch that stay where it put them, when only part of
+    // it is replayed: what was in them is found, and goes, under their new
+    // names.
+    let kept = moved_folders(
+        batch
+            .entries
+            .iter()
+            .filter(|e| e.state == EntryState::Applied && !replayed(e)),
+    );
+    let in_kept = |path: &Path| moved_along(path, &kept);
+    let endpoints = |entry: &HistoryEntry| {
+        let (from, to) = if undo {
+            (&entry.target, &entry.source)
+        } else {
+            (&entry.source, &entry.target)
+        };
+        (in_kept(from), in_kept(to))
+    };
+    // Paths of a batch that renamed folders refer to the tree before it ran.
+    // Redo renames children before their folders, as the batch did; undo puts
+    // folders back first, so until then a path inside one is found at the
+    // folder's current location.
+    let folders: Vec<(PathBuf, PathBuf)> = batch
+        .entries
+        .iter()
+        .filter(|e| undo && e.is_dir && replayed(e))
+        .map(endpoints)
+        .collect();
+    let locate = |path: &Path| -> PathBuf {
+        let mut path = path.to_path_buf();
+        for _ in 0..folders.len() {
+            let moved = folders
+                .iter()
+                .filter_map(|(now, restored)| {
+                    let rest = path.strip_prefix(restored).ok()?;
+                    (!rest.as_os_str().is_empty()).then(|| (restored, now.join(rest)))
+                })
+                .max_by_key(|(restored, _)| restored.components().count());
+            match moved {
+                Some((_, now)) => path = now,
+                None => break,
+            }
+        }
+        path
+    };
+
+    let mut results: HashMap<usize, (ReplayStatus, Option<String>)> = HashMap::new();
+    let mut movable: Vec<usize> = Vec::new();
+    for (i, entry) in batch.entries.iter().enumerate() {
+        if !replayed(entry) {
+            continue;
+        }
+        let at = locate(&endpoints(entry).0);
+        // Without a fingerprint to go by, e.g. for batches recorded before
+        // they were taken, nothing tells the file is the one left there.
+        let changed = only.is_some()
+            && match (entry.left, Fingerprint::of(&at)) {
+                (Some(left), Some(now)) => !left.matches(&now, entry.is_dir),
+                _ => true,
+            };
+        if !fsutil::exists(&at) {
+            results.insert(i, (ReplayStatus::Missing, None));
+        } else if changed {
+            results.insert(i, (ReplayStatus::Changed, None));
+        } else {
+            movable.push(i);
+        }
+    }
+
+    // A destination only counts as free if nothing is there, or what is there
+    // is itself about to move.
+    loop {
+        let vacating: HashSet<PathBuf> = movable
+            .iter()
+            .map(|&i| endpoints(&batch.entries[i]).0)
+            .collect();
+        let (free, occupied): (Vec<usize>, Vec<usize>) = movable.iter().partition(|&&i| {
+            let (src, dst) = endpoints(&batch.entries[i]);
+            !fsutil::exists(&locate(&dst))
+                || vacating.contains(&dst)
+                || is_case_only_rename(&locate(&src), &locate(&dst))
+        });
+        if occupied.is_empty() {
+            break;
+        }
+        for i in occupied {
+            results.insert(i, (ReplayStatus::Occupied, None));
+        }
+        movable = free;
+    }
+    movable.sort_by_key(|&i| {
+        let entry = &batch.entries[i];
+        let depth = nesting(&entry.source, &entry.target);
+        if undo {
+            depth as isize
+        } else {
+            -(depth as isize)
+        }
+    });
+
+    let paths: Vec<(PathBuf, PathBuf)> = movable
+        .iter()
+        .map(|&i| endpoints(&batch.entries[i]))
+        .collect();
+    let moves: Vec<(&Path, &Path)> = paths
+        .iter()
+        .map(|(s, d)| (s.as_path(), d.as_path()))
+        .collect();
+    for step in sequence(&moves, batch.batch_id) {
+        let i = movable[step.idx];
+        if results.contains_key(&i) {
+            continue;
+        }
+        let outcome = if step.from == step.to {
+            Ok(())
+        } else if fsutil::exists(&step.to) && !is_case_only_rename(&step.from, &step.to) {
+            Err(None)
+        } else if step.last {
+            // A batch that was copied across volumes has to be copied back.
+            let copy_back = CrossVolume {
+                progress: &|_, _| {},
+                preserve_times: true,
+            };
+            rename_file(&step.from, &step.to, &mut Vec::new(), Some(copy_back)).map_err(Some)
+        } else {
+            fsutil::rename(&step.from, &step.to).map_err(|e| Some(Error::io(&step.from, e)))
+        };
+        match outcome {
+            Ok(()) if step.last => {
+                if undo {
+                    remove_if_empty(&paths[step.idx].0, &paths[step.idx].1);
+                }
+                // The file is back in place either way; a stale link or
+                // timestamp is only reported.
+                let entry = &batch.entries[i];
+                let link_error = entry.link.as_ref().and_then(|link| {
+                    fsutil::repoint(link, step.to.file_name().unwrap_or_default())
+                        .err()
+                        .map(|e| Error::io(link, e).to_string())
+                });
+                let time = if undo {
+                    entry.previous_modified
+                } else {
+                    entry.modified
+                };
+                let time_error = time.and_then(|time| {
+                    fsutil::set_modified(&step.to, time.into())
+                        .err()
+                        .map(|e| Error::io(&step.to, e).to_string())
+                });
+                let error = link_error.or(time_error);
+                results.insert(i, (ReplayStatus::Done, error));
+            }
+            Ok(()) => {}
+            Err(err) => {
+                // Put a parked file back where it was before giving up on it.
+                if step.from != paths[step.idx].0 {
+                    let _ = fsutil::rename(&step.from, &paths[step.idx].0);
+                }
+                let status = match err {
+                    None => ReplayStatus::Occupied,
+                    Some(_) => ReplayStatus::Failed,
+                };
+                results.insert(i, (status, err.map(|e| e.to_string())));
+            }
+        }
+    }
+
+    let order: Vec<usize> = if undo {
+        (0..batch.entries.len()).rev().collect()
+    } else {
+        (0..batch.entries.len()).collect()
+    };
+    for i in order {
+        let Some((status, error)) = results.remove(&i) else {
+            continue;
+        };
+        let entry = &mut batch.entries[i];
+        let (src, dst) = endpoints(entry);
+        if status == ReplayStatus::Done {
+            entry.state = if undo {
+                EntryState::Undone
+            } else {
+                EntryState::Applied
+            };
+            entry.changed_at = Utc::now();
+            report.done += 1;
+        } else {
+            report.problems += 1;
+        }
+        report.outcomes.push(ReplayOutcome {
+            id: entry.id,
+            from: src,
+            to: dst,
+            status,
+            error,
+        });
+    }
+    report
+}
+
+/// Drops the folder a file was moved out of if that left it empty, and
+/// those above it it was the last thing in, up to the folder it went back to
+/// or the one both are in.
+fn remove_if_empty(from: &Path, to: &Path) {
+    let (Some(from), Some(to)) = (from.parent(), to.parent()) else {
+        return;
+    };
+    for dir in from.ancestors().take_while(|a| !to.starts_with(a)) {
+        if fs::remove_dir(fsutil::long(dir)).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,599 @@
+//! A permanent, searchable record of every rename, kept in SQLite next to the
+//! undo history.
+//!
+//! [`crate::HistoryStore`] keeps only the last batches, as much as undo and
+//! redo need. This database keeps every batch with the preset it used, and
+//! every change made to a file: the rename itself and each undo and redo, so
+//! what a file used to be called can still be looked up months later.
+//! Batches that failed are kept too, without changes, so [`HistoryDb::stats`]
+//! can tell how often a preset or a watched folder goes wrong.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::executor::{ApplyReport, OutcomeStatus};
+use crate::history::{BatchRecord, ReplayReport, ReplayStatus};
+
+/// Bumped when the tables change; stored as SQLite's `user_version`.
+const SCHEMA_VERSION: u32 = 2;
+
+/// Most changes a search returns.
+const SEARCH_LIMIT: usize = 500;
+
+/// Most folders [`HistoryStats::folders`] lists.
+const STATS_FOLDERS: usize = 50;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS batches (
+    batch_id TEXT PRIMARY KEY,
+    applied_at INTEGER NOT NULL,
+    preset TEXT
+);
+CREATE TABLE IF NOT EXISTS changes (
+    id INTEGER PRIMARY KEY,
+    batch_id TEXT NOT NULL REFERENCES batches (batch_id),
+    entry_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    source TEXT NOT NULL,
+    target TEXT NOT NULL,
+    changed_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS changes_by_batch ON changes (batch_id);
+CREATE INDEX IF NOT EXISTS changes_by_time ON changes (changed_at);
+";
+
+/// Brings the tables from version 1, or as just created, to version 2:
+/// batches that were rolled back, the entries that failed, and the size of
+/// each renamed file.
+const MIGRATE_2: &str = "
+ALTER TABLE batches ADD COLUMN committed INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE batches ADD COLUMN failed INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE changes ADD COLUMN bytes INTEGER;
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The batch renamed the file.
+    Renamed,
+    /// Undo moved it back.
+    Undone,
+    /// Redo renamed it again.
+    Redone,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::Undone => "undone",
+            ChangeKind::Redone => "redone",
+        }
+    }
+
+    fn parse(text: &str) -> Self {
+        match text {
+            "undone" => ChangeKind::Undone,
+            "redone" => ChangeKind::Redone,
+            _ => ChangeKind::Renamed,
+        }
+    }
+}
+
+/// A file moved from `source` to `target`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    pub batch_id: Uuid,
+    /// The entry of the batch's plan the file was.
+    pub entry_id: usize,
+    pub kind: ChangeKind,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub changed_at: DateTime<Utc>,
+    /// The preset whose rules the batch ran, if it came from one.
+    pub preset: Option<String>,
+}
+
+/// A batch and every change it made, and undo and redo made to it since.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedBatch {
+    pub batch_id: Uuid,
+    pub applied_at: DateTime<Utc>,
+    pub preset: Option<String>,
+    /// False for a batch that was rolled back; it has no changes.
+    #[serde(default = "committed")]
+    pub committed: bool,
+    /// How many of its entries failed.
+    #[serde(default)]
+    pub failed: usize,
+    pub changes: Vec<FileChange>,
+}
+
+fn committed() -> bool {
+    true
+}
+
+/// Limits a search to changes made in a span of time; either end may be
+/// left open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DateRange {
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// What the history adds up to over a span of time; see [`HistoryDb::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub batches: usize,
+    /// Batches that failed and were rolled back.
+    pub failed_batches: usize,
+    /// Files renamed, not counting undo and redo.
+    pub renamed: usize,
+    /// Entries that failed, in batches that were rolled back or not.
+    pub failed: usize,
+    pub undone: usize,
+    pub redone: usize,
+    /// The size of the files renamed, as far as it was recorded: batches
+    /// from before the statistics, and folders, count as nothing.
+    pub bytes: u64,
+    /// Each day with a batch or a change, oldest first, in local time.
+    pub days: Vec<DayStats>,
+    /// Each preset batches ran, and `None` for batches from no preset, the
+    /// most used first.
+    pub presets: Vec<PresetStats>,
+    /// The folders files were renamed out of, the busiest first.
+    pub folders: Vec<FolderStats>,
+}
+
+impl HistoryStats {
+    /// The share of entries that failed, of those renamed or failed.
+    pub fn error_rate(&self) -> f64 {
+        error_rate(self.renamed, self.failed)
+    }
+}
+
+fn error_rate(renamed: usize, failed: usize) -> f64 {
+    match renamed + failed {
+        0 => 0.0,
+        attempted => failed as f64 / attempted as f64,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayStats {
+    pub day: NaiveDate,
+    pub batches: usize,
+    pub renamed: usize,
+    pub failed: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetStats {
+    pub preset: Option<String>,
+    pub batches: usize,
+    pub failed_batches: usize,
+    pub renamed: usize,
+    pub failed: usize,
+    pub bytes: u64,
+    /// The share of its entries that failed, of those renamed or failed.
+    pub error_rate: f64,
+    pub last_used: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FolderStats {
+    pub folder: PathBuf,
+    pub renamed: usize,
+    pub bytes: u64,
+    pub last_renamed: DateTime<Utc>,
+}
+
+fn millis(at: DateTime<Utc>) -> i64 {
+    at.timestamp_millis()
+}
+
+fn from_millis(ms: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(ms).unwrap_or_default()
+}
+
+fn text(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Escapes `%`, `_` and `\` for a `LIKE ... ESCAPE '\'` pattern.
+fn like_pattern(query: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+const CHANGE_COLUMNS: &str =
+    "c.batch_id, c.entry_id, c.kind, c.source, c.target, c.changed_at, b.preset
+     FROM changes c JOIN batches b ON b.batch_id = c.batch_id";
+
+fn change(row: &Row) -> rusqlite::Result<FileChange> {
+    let batch_id: String = row.get(0)?;
+    let kind: String = row.get(2)?;
+    Ok(FileChange {
+        batch_id: Uuid::parse_str(&batch_id).unwrap_or_default(),
+        entry_id: row.get::<_, i64>(1)? as usize,
+        kind: ChangeKind::parse(&kind),
+        source: PathBuf::from(row.get::<_, String>(3)?),
+        target: PathBuf::from(row.get::<_, String>(4)?),
+        changed_at: from_millis(row.get(5)?),
+        preset: row.get(6)?,
+    })
+}
+
+/// The history database.
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// Opens the database at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let mut conn = Connection::open(path)?;
+        let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(Error::UnsupportedVersion {
+                path: path.to_path_buf(),
+                version,
+            });
+        }
+        let tx = conn.transaction()?;
+        tx.execute_batch(SCHEMA)?;
+        if version < 2 {
+            tx.execute_batch(MIGRATE_2)?;
+        }
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+        Ok(HistoryDb { conn })
+    }
+
+    /// Records a batch that ran the rules of `preset`: the renamed entries
+    /// of one that was committed, and how many entries failed either way.
+    pub fn record(&mut self, report: &ApplyReport, preset: Option<&str>) -> Result<()> {
+        let now = millis(Utc::now());
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO batches (batch_id, applied_at, preset, committed, failed)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                report.batch_id.to_string(),
+                now,
+                preset,
+                report.committed,
+                report.failed as i64
+            ],
+        )?;
+        if !report.committed {
+            tx.commit()?;
+            return Ok(());
+        }
+        for outcome in &report.outcomes {
+            if outcome.status != OutcomeStatus::Renamed {
+                continue;
+            }
+            let bytes = std::fs::symlink_metadata(&outcome.target)
+                .ok()
+                .filter(|m| m.is_file())
+                .map(|m| m.len());
+            insert_change(
+                &tx,
+                report.batch_id,
+                outcome.id,
+                ChangeKind::Renamed,
+                (&outcome.source, &outcome.target),
+                bytes,
+                now,
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the files an undo (`undo` set) or redo moved.
+    pub fn record_replay(&mut self, report: &ReplayReport, undo: bool) -> Result<()> {
+        let kind = if undo {
+            ChangeKind::Undone
+        } else {
+            ChangeKind::Redone
+        };
+        let now = millis(Utc::now());
+        let tx = self.conn.transaction()?;
+        for outcome in &report.outcomes {
+            if outcome.status != ReplayStatus::Done {
+                continue;
+            }
+            insert_change(
+                &tx,
+                report.batch_id,
+                outcome.id,
+                kind,
+                (&outcome.from, &outcome.to),
+                None,
+                now,
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Adds batches of the undo history the database does not have yet, such
+    /// as those recorded before it existed. Returns how many it added.
+    pub fn import(&mut self, batches: &[BatchRecord]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut imported = 0;
+        for batch in batches.iter().rev() {
+            let added = tx.execute(
+                "INSERT OR IGNORE INTO batches (batch_id, applied_at, preset) VALUES (?1, ?2, NULL)",
+                params![batch.batch_id.to_string(), millis(batch.applied_at)],
+            )?;
+            if added == 0 {
+                continue;
+            }
+            imported += 1;
+            for entry in &batch.entries {
+                insert_change(
+                    &tx,
+                    batch.batch_id,
+                    entry.id,
+                    ChangeKind::Renamed,
+                    (&entry.source, &entry.target),
+                    None,
+                    millis(batch.applied_at),
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(imported)
+    }
+
+    /// Changes whose old or new path contains `query` (ignoring ASCII case),
+    /// newest first. An empty query matches every change in `range`.
+    pub fn search(&self, query: &str, range: DateRange) -> Result<Vec<FileChange>> {
+        let sql = format!(
+            "SELECT {} WHERE (c.source LIKE ?1 ESCAPE '\\' OR c.target LIKE ?1 ESCAPE '\\')
+               AND (?2 IS NULL OR c.changed_at >= ?2) AND (?3 IS NULL OR c.changed_at <= ?3)
+             ORDER BY c.changed_at DESC, c.id DESC LIMIT ?4",
+            CHANGE_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(
+            params![
+                like_pattern(query.trim()),
+                range.from.map(millis),
+                range.to.map(millis),
+                SEARCH_LIMIT as i64
+            ],
+            change,
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The batch `batch_id` with its changes in the order they were made.
+    pub fn batch(&self, batch_id: Uuid) -> Result<Option<ArchivedBatch>> {
+        let id = batch_id.to_string();
+        let found = self
+            .conn
+            .query_row(
+                "SELECT applied_at, preset, committed, failed FROM batches WHERE batch_id = ?1",
+                [&id],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, bool>(2)?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((applied_at, preset, committed, failed)) = found else {
+            return Ok(None);
+        };
+        let sql = format!(
+            "SELECT {} WHERE c.batch_id = ?1 ORDER BY c.id",
+            CHANGE_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let changes = stmt
+            .query_map([&id], change)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Some(ArchivedBatch {
+            batch_id,
+            applied_at: from_millis(applied_at),
+            preset,
+            committed,
+            failed: failed as usize,
+            changes,
+        }))
+    }
+
+    /// Forgets the batches applied before `before`, with their changes and
+    /// the undo and redo made to them since, and gives the space they took
+    /// back to the disk. Returns how many batches it forgot.
+    pub fn prune(&mut self, before: DateTime<Utc>) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "DELETE FROM changes WHERE batch_id IN
+               (SELECT batch_id FROM batches WHERE applied_at < ?1)",
+            [millis(before)],
+        )?;
+        let forgotten = tx.execute(
+            "DELETE FROM batches WHERE applied_at < ?1",
+            [millis(before)],
+        )?;
+        tx.commit()?;
+        if forgotten > 0 {
+            self.conn.execute_batch("VACUUM")?;
+        }
+        Ok(forgotten)
+    }
+
+    /// Adds up the batches applied and the changes made in `range`: by day,
+    /// by preset and by the folder files were renamed out of.
+    pub fn stats(&self, range: DateRange) -> Result<HistoryStats> {
+        let bounds = params![range.from.map(millis), range.to.map(millis)];
+        let mut stats = HistoryStats::default();
+        let mut days: BTreeMap<NaiveDate, DayStats> = BTreeMap::new();
+        let mut presets: HashMap<Option<String>, PresetStats> = HashMap::new();
+        let mut folders: HashMap<PathBuf, FolderStats> = HashMap::new();
+        let day = |at: DateTime<Utc>| at.with_timezone(&Local).date_naive();
+
+        let mut stmt = self.conn.prepare(
+            "SELECT applied_at, preset, committed, failed FROM batches
+             WHERE (?1 IS NULL OR applied_at >= ?1) AND (?2 IS NULL OR applied_at <= ?2)",
+        )?;
+        let mut rows = stmt.query(bounds)?;
+        while let Some(row) = rows.next()? {
+            let at = from_millis(row.get(0)?);
+            let preset: Option<String> = row.get(1)?;
+            let committed: bool = row.get(2)?;
+            let failed = row.get::<_, i64>(3)? as usize;
+            stats.batches += 1;
+            stats.failed_batches += usize::from(!committed);
+            stats.failed += failed;
+            let day = day_stats(&mut days, day(at));
+            day.batches += 1;
+            day.failed += failed;
+            let preset = preset_stats(&mut presets, preset, at);
+            preset.batches += 1;
+            preset.failed_batches += usize::from(!committed);
+            preset.failed += failed;
+            preset.last_used = preset.last_used.max(at);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT c.kind, c.source, c.bytes, c.changed_at, b.preset
+             FROM changes c JOIN batches b ON b.batch_id = c.batch_id
+             WHERE (?1 IS NULL OR c.changed_at >= ?1) AND (?2 IS NULL OR c.changed_at <= ?2)",
+        )?;
+        let mut rows = stmt.query(bounds)?;
+        while let Some(row) = rows.next()? {
+            let kind = ChangeKind::parse(&row.get::<_, String>(0)?);
+            if kind != ChangeKind::Renamed {
+                match kind {
+                    ChangeKind::Undone => stats.undone += 1,
+                    _ => stats.redone += 1,
+                }
+                continue;
+            }
+            let source = PathBuf::from(row.get::<_, String>(1)?);
+            let bytes = row.get::<_, Option<i64>>(2)?.unwrap_or(0) as u64;
+            let at = from_millis(row.get(3)?);
+            stats.renamed += 1;
+            stats.bytes += bytes;
+            let day = day_stats(&mut days, day(at));
+            day.renamed += 1;
+            day.bytes += bytes;
+            let preset = preset_stats(&mut presets, row.get(4)?, at);
+            preset.renamed += 1;
+            preset.bytes += bytes;
+            let folder = source.parent().map(Path::to_path_buf).unwrap_or_default();
+            let folder = folders
+                .entry(folder.clone())
+                .or_insert_with(|| FolderStats {
+                    folder,
+                    renamed: 0,
+                    bytes: 0,
+                    last_renamed: at,
+                });
+            folder.renamed += 1;
+            folder.bytes += bytes;
+            folder.last_renamed = folder.last_renamed.max(at);
+        }
+
+        stats.days = days.into_values().collect();
+        stats.presets = presets
+            .into_values()
+            .map(|mut p| {
+                p.error_rate = error_rate(p.renamed, p.failed);
+                p
+            })
+            .collect();
+        stats
+            .presets
+            .sort_by(|a, b| (b.batches, &a.preset).cmp(&(a.batches, &b.preset)));
+        stats.folders = folders.into_values().collect();
+        stats
+            .folders
+            .sort_by(|a, b| (b.renamed, &a.folder).cmp(&(a.renamed, &b.folder)));
+        stats.folders.truncate(STATS_FOLDERS);
+        Ok(stats)
+    }
+}
+
+fn day_stats(days: &mut BTreeMap<NaiveDate, DayStats>, day: NaiveDate) -> &mut DayStats {
+    days.entry(day).or_insert_with(|| DayStats {
+        day,
+        batches: 0,
+        renamed: 0,
+        failed: 0,
+        bytes: 0,
+    })
+}
+
+fn preset_stats(
+    presets: &mut HashMap<Option<String>, PresetStats>,
+    preset: Option<String>,
+    at: DateTime<Utc>,
+) -> &mut PresetStats {
+    presets
+        .entry(preset.clone())
+        .or_insert_with(|| PresetStats {
+            preset,
+            batches: 0,
+            failed_batches: 0,
+            renamed: 0,
+            failed: 0,
+            bytes: 0,
+            error_rate: 0.0,
+            last_used: at,
+        })
+}
+
+fn insert_change(
+    conn: &Connection,
+    batch_id: Uuid,
+    entry_id: usize,
+    kind: ChangeKind,
+    (source, target): (&Path, &Path),
+    bytes: Option<u64>,
+    at: i64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO changes (batch_id, entry_id, kind, source, target, bytes, changed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            batch_id.to_string(),
+            entry_id as i64,
+            kind.as_str(),
+            text(source),
+            text(target),
+            bytes.map(|b| b as i64),
+            at
+        ],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Which file a path names, beyond the name: the device and inode on Unix,
+//! the volume serial number and file index on Windows. Two paths with the
+//! same [`FileId`] are one file under two names, hard links to each other,
+//! and renaming one leaves the other alone.
+
+use std::fs::Metadata;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fsutil;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileId {
+    /// The device number, or the volume serial number on Windows.
+    pub device: u64,
+    /// The inode number, or the file index on Windows.
+    pub index: u64,
+}
+
+impl FileId {
+    /// The id of the file at `path`; a link is the link itself, not what it
+    /// points to.
+    pub fn of(path: &Path) -> Option<FileId> {
+        read(path).map(|(id, _)| id)
+    }
+}
+
+/// The id of the file at `path` and how many names it has.
+pub(crate) fn read(path: &Path) -> Option<(FileId, u64)> {
+    let metadata = std::fs::symlink_metadata(fsutil::long(path)).ok()?;
+    of_metadata(path, &metadata)
+}
+
+/// [`read`] for a file whose metadata was read already, of the link itself
+/// or of what it points to. Unix has all it takes in the metadata; Windows
+/// opens the file again.
+#[cfg(unix)]
+pub(crate) fn of_metadata(_path: &Path, metadata: &Metadata) -> Option<(FileId, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let id = FileId {
+        device: metadata.dev(),
+        index: metadata.ino(),
+    };
+    Some((id, metadata.nlink()))
+}
+
+#[cfg(windows)]
+pub(crate) fn of_metadata(path: &Path, metadata: &Metadata) -> Option<(FileId, u64)> {
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+
+    // Opens folders too.
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    // Opens a link itself, not what it points to.
+    const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ByHandleFileInformation {
+        file_attributes: u32,
+        creation_time: [u32; 2],
+        last_access_time: [u32; 2],
+        last_write_time: [u32; 2],
+        volume_serial_number: u32,
+        file_size_high: u32,
+        file_size_low: u32,
+        number_of_links: u32,
+        file_index_high: u32,
+        file_index_low: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetFileInformationByHandle(
+            file: *mut std::ffi::c_void,
+            information: *mut ByHandleFileInformation,
+        ) -> i32;
+    }
+
+    let mut flags = FILE_FLAG_BACKUP_SEMANTICS;
+    if metadata.file_type().is_symlink() {
+        flags |= FILE_FLAG_OPEN_REPARSE_POINT;
+    }
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(flags)
+        .open(fsutil::long(path))
+        .ok()?;
+    let mut info = ByHandleFileInformation::default();
+    // SAFETY: the handle is open for the call and `info` has the layout
+    // the call fills in.
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle().cast(), &mut info) } == 0 {
+        return None;
+    }
+    let id = FileId {
+        device: info.volume_serial_number.into(),
+        index: (u64::from(info.file_index_high) << 32) | u64::from(info.file_index_low),
+    };
+    Some((id, info.number_of_links.into()))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn of_metadata(_path: &Path, _metadata: &Metadata) -> Option<(FileId, u64)> {
+    None
+}
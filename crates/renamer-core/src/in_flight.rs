@@ -0,0 +1,149 @@
+//! The paths batches running in this process are renaming from and to, so
+//! that two of them, say a folder watcher's and one the user started, never
+//! move the same file or into the same name at once.
+//!
+//! A batch claims the sources and targets of its entries before its first
+//! move and gives them up when it returns; a folder it renames claims
+//! everything under it. A batch that finds a path claimed waits for the
+//! other batch to finish, for [`crate::ApplyOptions::in_flight_wait_secs`],
+//! and then goes on with each entry checked again right before it is
+//! renamed, as [`crate::ApplyOptions::recheck`] does, so one whose name the
+//! other batch took is left out rather than failing or replacing it. If
+//! the other batch is still running by then, the batch fails with
+//! [`Error::TargetInFlight`] before it has touched anything, to be planned
+//! again.
+//!
+//! Paths are compared as their volume does: with `.` and `..` resolved,
+//! and in any case on a volume whose names are not case sensitive, so
+//! `Photo.JPG` and `./photo.jpg` there are the same name.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::volume::Volumes;
+
+#[derive(Default)]
+struct Registry {
+    /// Every claimed path, by the batch claiming it.
+    paths: HashMap<PathBuf, Uuid>,
+    /// The claimed folders, whose contents are claimed with them.
+    folders: HashSet<PathBuf>,
+}
+
+impl Registry {
+    /// The batch that has `path`, or a folder above it, or, for a folder,
+    /// something under it; `path` as [`key`] gives it.
+    fn holder(&self, path: &Path, is_dir: bool) -> Option<Uuid> {
+        let at = |p: &Path| self.paths.get(p).copied();
+        if let Some(found) = path
+            .ancestors()
+            .skip(1)
+            .filter(|a| self.folders.contains(*a))
+            .find_map(at)
+        {
+            return Some(found);
+        }
+        if let Some(found) = at(path) {
+            return Some(found);
+        }
+        if is_dir {
+            return self
+                .paths
+                .iter()
+                .find(|(p, _)| p.starts_with(path))
+                .map(|(_, &id)| id);
+        }
+        None
+    }
+}
+
+/// `path` as it is claimed and looked up: without `.` and `..`, and folded
+/// to one case if its volume does not tell cases apart.
+fn key(path: &Path, volumes: &mut Volumes) -> PathBuf {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                clean.pop();
+            }
+            other => clean.push(other),
+        }
+    }
+    volumes.of(&clean).fold(&clean)
+}
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+static RELEASED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Option<Registry>> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The paths of a running batch; dropping it gives them up.
+pub(crate) struct Claim {
+    batch_id: Uuid,
+    /// Whether the batch had to wait for another one.
+    pub(crate) waited: bool,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Some(registry) = lock().as_mut() {
+            registry.paths.retain(|_, id| *id != self.batch_id);
+            let paths = &registry.paths;
+            registry.folders.retain(|f| paths.contains_key(f));
+        }
+        RELEASED.notify_all();
+    }
+}
+
+/// Claims `paths`, each with whether it is a folder, for `batch_id`,
+/// waiting up to `wait` for other batches holding any of them.
+pub(crate) fn claim<'a>(
+    batch_id: Uuid,
+    paths: impl IntoIterator<Item = (&'a Path, bool)>,
+    wait: Duration,
+) -> Result<Claim> {
+    // Probed before taking the lock, which other batches wait on.
+    let mut volumes = Volumes::new(None);
+    let paths: Vec<(&Path, PathBuf, bool)> = paths
+        .into_iter()
+        .map(|(path, is_dir)| (path, key(path, &mut volumes), is_dir))
+        .collect();
+    let deadline = Instant::now() + wait;
+    let mut waited = false;
+    let mut guard = lock();
+    loop {
+        let registry = guard.get_or_insert_with(Registry::default);
+        let held = paths.iter().find_map(|(path, key, is_dir)| {
+            registry.holder(key, *is_dir).map(|holder| (*path, holder))
+        });
+        let Some((path, holder)) = held else {
+            for (_, key, is_dir) in &paths {
+                registry.paths.insert(key.clone(), batch_id);
+                if *is_dir {
+                    registry.folders.insert(key.clone());
+                }
+            }
+            return Ok(Claim { batch_id, waited });
+        };
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(Error::TargetInFlight {
+                path: path.to_path_buf(),
+                batch_id: holder,
+            });
+        }
+        waited = true;
+        guard = RELEASED
+            .wait_timeout(guard, left)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+}
@@ -0,0 +1,547 @@
+//! Importing files from removable media, such as a camera's SD card: each
+//! file is copied into a destination folder under the name the rules give
+//! it, checked against the original's hash and only then given that name.
+//! Nothing on the card changes.
+//!
+//! An import can be picked up again after the card was pulled out or the
+//! job cancelled. Copies are written to a hidden `.part` file next to their
+//! target, which a later run continues instead of starting over, and every
+//! file that was copied and checked is noted in a state file in the
+//! destination, so a later run with the same card leaves it alone. The
+//! state file is removed once a run imports everything.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::executor::{ApplyReport, EntryOutcome, OutcomeStatus, Progress, Reporter};
+use crate::fsutil;
+use crate::hash::{self, HashAlgorithm, Hasher};
+use crate::preview::{self, PreviewRow};
+use crate::rules::Rule;
+use crate::scan::{ScanOptions, Scanner};
+use crate::throttle;
+
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// Name of the state file kept in the destination while an import is
+/// unfinished.
+const STATE_FILE: &str = ".sortify-ingest.json";
+
+/// Tag of the hidden file a copy is written to before it gets its name.
+const PARTIAL_TAG: &str = "sortify-ingest.part";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestOptions {
+    /// Folder the files are copied into. Files keep the folders they are in
+    /// below the source, or the ones a rule moves them to.
+    pub destination: PathBuf,
+    /// Rules giving the copies their names.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// How copies are checked against the originals.
+    #[serde(default = "default_algorithm")]
+    pub algorithm: HashAlgorithm,
+    /// Which files on the source are imported; folders never are.
+    #[serde(default)]
+    pub scan: ScanOptions,
+    /// Give the copies the originals' modification times.
+    #[serde(default = "default_true")]
+    pub preserve_times: bool,
+    /// Eject the source's volume once everything is imported.
+    #[serde(default)]
+    pub eject: bool,
+}
+
+fn default_algorithm() -> HashAlgorithm {
+    HashAlgorithm::Xxh3
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl IngestOptions {
+    pub fn new(destination: impl Into<PathBuf>) -> Self {
+        IngestOptions {
+            destination: destination.into(),
+            rules: Vec::new(),
+            algorithm: default_algorithm(),
+            scan: ScanOptions::default(),
+            preserve_times: true,
+            eject: false,
+        }
+    }
+}
+
+/// What an import did. Sources of the outcomes are files on the card and
+/// targets their copies; a copy that was imported counts as renamed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestReport {
+    pub report: ApplyReport,
+    /// Files an earlier, unfinished run had already imported.
+    pub resumed: usize,
+    /// The source went away part way, as when the card is pulled out; the
+    /// files not reached are aborted and a later run picks them up.
+    pub disconnected: bool,
+    /// The source's volume was ejected after the import.
+    pub ejected: bool,
+    /// Why ejecting failed. The import itself stands.
+    pub eject_error: Option<String>,
+}
+
+/// A file an earlier run copied and checked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Imported {
+    source: PathBuf,
+    target: PathBuf,
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+    hash: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    files: Vec<Imported>,
+}
+
+impl State {
+    fn path(destination: &Path) -> PathBuf {
+        destination.join(STATE_FILE)
+    }
+
+    fn load(destination: &Path) -> Result<Self> {
+        let path = State::path(destination);
+        match fs::read(fsutil::long(&path)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(Error::io(&path, e)),
+        }
+    }
+
+    /// Written to a hidden sibling first, so a crash never leaves half a
+    /// state file.
+    fn save(&self, destination: &Path) -> Result<()> {
+        let path = State::path(destination);
+        let temp = fsutil::hidden_sibling(&path, "tmp");
+        let json = serde_json::to_vec_pretty(self)?;
+        fs::write(fsutil::long(&temp), json).map_err(|e| Error::io(&temp, e))?;
+        fs::rename(fsutil::long(&temp), fsutil::long(&path)).map_err(|e| Error::io(&path, e))
+    }
+}
+
+/// The files under `source` that would be imported, with the targets in
+/// the destination their copies get.
+pub fn preview(source: &Path, options: &IngestOptions) -> Result<Vec<PreviewRow>> {
+    let page = Scanner::new(source, options.scan.clone())?.next_page(usize::MAX);
+    let paths: Vec<PathBuf> = page
+        .entries
+        .into_iter()
+        .filter(|e| !e.is_dir)
+        .map(|e| e.path)
+        .collect();
+    let mut rows = preview::preview(&paths, &options.rules)?;
+    for row in &mut rows {
+        let relative = row
+            .target
+            .strip_prefix(source)
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|_| PathBuf::from(&row.new_name));
+        row.target = options.destination.join(relative);
+        row.changed = true;
+    }
+    Ok(rows)
+}
+
+/// Copies the files under `source` into the destination under their new
+/// names. A file whose target is taken is skipped rather than replaced, and
+/// one that fails to copy does not stop the rest; the import is committed
+/// if every file was imported. When the source goes away part way, the
+/// files not reached are aborted and the rest of the run is kept for the
+/// next one to continue.
+pub fn ingest(
+    source: &Path,
+    options: &IngestOptions,
+    progress: &(dyn Fn(&Progress) + Sync),
+    cancel: &CancelToken,
+) -> Result<IngestReport> {
+    let rows = preview(source, options)?;
+    let destination = &options.destination;
+    fs::create_dir_all(fsutil::long(destination)).map_err(|e| Error::io(destination, e))?;
+    let mut state = State::load(destination)?;
+    let done: HashMap<PathBuf, Imported> = state
+        .files
+        .iter()
+        .map(|f| (f.source.clone(), f.clone()))
+        .collect();
+
+    let batch_id = Uuid::new_v4();
+    let reporter = Reporter::new(batch_id, rows.len(), progress);
+    let mut report = IngestReport {
+        report: ApplyReport::new(batch_id),
+        resumed: 0,
+        disconnected: false,
+        ejected: false,
+        eject_error: None,
+    };
+    let mut targets: HashSet<PathBuf> = HashSet::new();
+    for (i, row) in rows.iter().enumerate() {
+        let mut outcome = EntryOutcome {
+            id: i,
+            source: row.source.clone(),
+            target: row.target.clone(),
+            status: OutcomeStatus::Skipped,
+            error: None,
+            link: None,
+            is_dir: false,
+            modified: None,
+            previous_modified: None,
+            title_error: None,
+            locked_by: None,
+            verified: false,
+        };
+        if report.disconnected {
+            outcome.status = OutcomeStatus::Aborted;
+            outcome.error = Some("the source was disconnected".into());
+            report.report.record(outcome);
+            continue;
+        }
+        if cancel.is_cancelled() {
+            report.report.cancelled = true;
+            outcome.status = OutcomeStatus::Cancelled;
+            report.report.record(outcome);
+            continue;
+        }
+        let meta = match fs::metadata(fsutil::long(&row.source)) {
+            Ok(meta) => meta,
+            Err(e) => {
+                outcome.status = OutcomeStatus::Failed;
+                outcome.error = Some(e.to_string());
+                report.disconnected = !fsutil::exists(source);
+                report.report.record(outcome);
+                continue;
+            }
+        };
+        let modified = meta.modified().ok().map(DateTime::<Utc>::from);
+        if let Some(earlier) = done.get(&row.source).filter(|f| {
+            f.size == meta.len()
+                && f.modified == modified
+                && fs::metadata(fsutil::long(&f.target)).is_ok_and(|m| m.len() == f.size)
+        }) {
+            outcome.target = earlier.target.clone();
+            outcome.status = OutcomeStatus::Renamed;
+            outcome.verified = true;
+            targets.insert(earlier.target.clone());
+            report.resumed += 1;
+            reporter.completed(&earlier.target);
+            report.report.record(outcome);
+            continue;
+        }
+        if !targets.insert(row.target.clone()) {
+            outcome.error = Some("another file is imported to the same name".into());
+            report.report.record(outcome);
+            continue;
+        }
+        if fsutil::exists(&row.target) {
+            outcome.error = Some("a file already has the new name".into());
+            report.report.record(outcome);
+            continue;
+        }
+        let copying = |copied, total| reporter.copying(&row.target, copied, total);
+        let copied = import(&row.source, &row.target, options, &copying);
+        match copied {
+            Ok(hash) => {
+                outcome.status = OutcomeStatus::Renamed;
+                outcome.verified = true;
+                state.files.push(Imported {
+                    source: row.source.clone(),
+                    target: row.target.clone(),
+                    size: meta.len(),
+                    modified,
+                    hash,
+                });
+                if let Err(e) = state.save(destination) {
+                    outcome.error = Some(e.to_string());
+                }
+                reporter.completed(&row.target);
+            }
+            Err(e) => {
+                outcome.status = OutcomeStatus::Failed;
+                outcome.error = Some(e.to_string());
+                report.disconnected = !fsutil::exists(source);
+            }
+        }
+        report.report.record(outcome);
+    }
+
+    let finished = report.report.failed == 0 && !report.report.cancelled;
+    report.report.committed = finished;
+    if finished {
+        let path = State::path(destination);
+        match fs::remove_file(fsutil::long(&path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(Error::io(&path, e)),
+            _ => {}
+        }
+        if options.eject {
+            match eject(source) {
+                Ok(()) => report.ejected = true,
+                Err(e) => report.eject_error = Some(e.to_string()),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Copies `source` to `target` by way of a hidden partial file, returning
+/// the digest both have. A partial file left by an earlier run is
+/// continued; if the result does not match the original, the copy is made
+/// again from the start.
+fn import(
+    source: &Path,
+    target: &Path,
+    options: &IngestOptions,
+    progress: &dyn Fn(u64, u64),
+) -> io::Result<String> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(fsutil::long(parent))?;
+    }
+    let partial = fsutil::hidden_sibling(target, PARTIAL_TAG);
+    let meta = fs::metadata(fsutil::long(source))?;
+    let mut resume = true;
+    let hash = loop {
+        let (hash, continued) = copy(source, &partial, meta.len(), resume, options, progress)?;
+        let check = hash::hash_file(&partial, options.algorithm)?;
+        if check == hash && fs::metadata(fsutil::long(&partial))?.len() == meta.len() {
+            break hash;
+        }
+        if !continued {
+            let _ = fs::remove_file(fsutil::long(&partial));
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the copy does not match the original",
+            ));
+        }
+        resume = false;
+    };
+    if options.preserve_times {
+        let mut times = FileTimes::new().set_modified(meta.modified()?);
+        if let Ok(accessed) = meta.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        OpenOptions::new()
+            .write(true)
+            .open(fsutil::long(&partial))?
+            .set_times(times)?;
+    }
+    // Looked at again, as the copy can take long.
+    if fsutil::exists(target) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "a file already has the new name",
+        ));
+    }
+    fs::rename(fsutil::long(&partial), fsutil::long(target))?;
+    Ok(hash)
+}
+
+/// Copies `source` into `partial`, after what is already there when
+/// `resume` is set, returning the digest of the original and whether an
+/// earlier copy was continued. An interrupted copy is left for the next
+/// run.
+fn copy(
+    source: &Path,
+    partial: &Path,
+    total: u64,
+    resume: bool,
+    options: &IngestOptions,
+    progress: &dyn Fn(u64, u64),
+) -> io::Result<(String, bool)> {
+    let mut input = File::open(fsutil::long(source))?;
+    let mut output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(fsutil::long(partial))?;
+    let existing = output.metadata()?.len();
+    let start = if resume && existing <= total {
+        existing
+    } else {
+        0
+    };
+    output.set_len(start)?;
+    output.seek(SeekFrom::Start(start))?;
+
+    let mut hasher = Hasher::new(options.algorithm);
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut done = 0;
+    // What is already copied is read from the original again, for its hash.
+    let mut head = (&mut input).take(start);
+    loop {
+        let n = head.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        throttle::pace(n);
+        done += n as u64;
+    }
+    if done != start {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the original is shorter than when it was listed",
+        ));
+    }
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        output.write_all(&buf[..n])?;
+        hasher.update(&buf[..n]);
+        // Read and written.
+        throttle::pace(2 * n);
+        done += n as u64;
+        progress(done, total);
+    }
+    output.sync_all()?;
+    Ok((hasher.finish(), start > 0))
+}
+
+/// Ejects the removable volume `path` is on, so the card or drive can be
+/// pulled out safely.
+pub fn eject(path: &Path) -> Result<()> {
+    let mount = platform::mount_point(path).map_err(|e| Error::io(path, e))?;
+    if mount.parent().is_none() && !cfg!(windows) {
+        return Err(Error::io(
+            path,
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the source is not on a volume that can be ejected",
+            ),
+        ));
+    }
+    platform::eject(&mount).map_err(|e| Error::io(&mount, e))
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+
+    /// The outermost folder above `path` on the same device.
+    pub fn mount_point(path: &Path) -> io::Result<PathBuf> {
+        let path = fs::canonicalize(path)?;
+        let device = fs::metadata(&path)?.dev();
+        let mut mount = path.clone();
+        for ancestor in path.ancestors().skip(1) {
+            if fs::metadata(ancestor)?.dev() != device {
+                break;
+            }
+            mount = ancestor.to_path_buf();
+        }
+        Ok(mount)
+    }
+
+    fn run(command: &mut Command) -> io::Result<()> {
+        let out = command.stdin(Stdio::null()).output()?;
+        if out.status.success() {
+            return Ok(());
+        }
+        Err(io::Error::other(
+            String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        ))
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn eject(mount: &Path) -> io::Result<()> {
+        run(Command::new("diskutil").arg("eject").arg(mount))
+    }
+
+    /// Through GVfs, which also powers the device off where it can, or a
+    /// plain unmount without it.
+    #[cfg(not(target_os = "macos"))]
+    pub fn eject(mount: &Path) -> io::Result<()> {
+        let ejected = run(Command::new("gio")
+            .env("LC_ALL", "C")
+            .args(["mount", "-e"])
+            .arg(mount));
+        match ejected {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => run(Command::new("umount").arg(mount)),
+            ejected => ejected,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::path::{Component, Path, PathBuf};
+    use std::process::{Command, Stdio};
+
+    /// The drive `path` is on, such as `E:\`.
+    pub fn mount_point(path: &Path) -> io::Result<PathBuf> {
+        match path.components().next() {
+            Some(Component::Prefix(prefix)) => Ok(PathBuf::from(format!(
+                "{}\\",
+                prefix.as_os_str().to_string_lossy()
+            ))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the path has no drive",
+            )),
+        }
+    }
+
+    /// The Eject verb of the drive in This PC, as Explorer offers it.
+    pub fn eject(mount: &Path) -> io::Result<()> {
+        let drive = mount.to_string_lossy().replace('\'', "''");
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'\n\
+             $d = (New-Object -ComObject Shell.Application).NameSpace(17).ParseName('{}')\n\
+             if (-not $d) {{ exit 3 }}\n\
+             $d.InvokeVerb('Eject')",
+            drive
+        );
+        let out = Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .stdin(Stdio::null())
+            .output()?;
+        match out.status.code() {
+            Some(0) => Ok(()),
+            Some(3) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the drive is not in This PC",
+            )),
+            _ => Err(io::Error::other(
+                String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    pub fn mount_point(path: &Path) -> io::Result<PathBuf> {
+        Ok(path.to_path_buf())
+    }
+
+    pub fn eject(_mount: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "volumes cannot be ejected on this platform",
+        ))
+    }
+}
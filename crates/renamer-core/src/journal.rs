@@ -0,0 +1,392 @@
+//! The write-ahead journal of a batch, and recovering a batch the journal
+//! outlived.
+//!
+//! Before the first file is touched the executor writes every move it is
+//! about to make, in the order it makes them, and syncs the journal to disk.
+//! The journal is removed once the batch is committed or rolled back, so one
+//! still on disk at startup belongs to a batch that was cut off by a crash
+//! or power loss. Which of its moves happened is read back from the
+//! filesystem, and the batch can then be finished ([`Recovery::Resume`]) or
+//! undone ([`Recovery::Rollback`]).
+//!
+//! Recovery only moves files. Re-pointed links and titles are left as they
+//! are; resuming sets the modification times the batch asked for, rolling
+//! back does not restore old ones.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::executor::rename_file;
+use crate::fsutil::{self, has_entry_named};
+use crate::history::{ReplayOutcome, ReplayReport, ReplayStatus};
+use crate::plan::RenamePlan;
+use crate::transfer::{self, CrossVolume};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: usize,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// Modification time the batch gives the file.
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// One move of the batch, as the executor runs it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalStep {
+    /// The entry the move belongs to.
+    pub id: usize,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// True when this move puts the file at its target; false for a move to
+    /// a temporary name.
+    pub last: bool,
+    /// Where the file the move replaces is kept until the batch commits.
+    pub backup: Option<PathBuf>,
+    /// The move shares no path with any other, so it may have run in any
+    /// order with the other independent moves. The rest run one by one in
+    /// the order listed, after all independent ones.
+    pub independent: bool,
+}
+
+/// Record of a batch written to disk before any file is touched, so an
+/// interrupted batch can still be traced back and undone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Journal {
+    pub batch_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub entries: Vec<JournalEntry>,
+    /// The moves in execution order. Empty in journals written before steps
+    /// were recorded, which are recovered as one move per entry.
+    #[serde(default)]
+    pub steps: Vec<JournalStep>,
+    /// Folders the batch creates for its targets, outermost first.
+    #[serde(default)]
+    pub new_dirs: Vec<PathBuf>,
+}
+
+/// What to do with an interrupted batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recovery {
+    /// Make the moves that did not happen yet.
+    Resume,
+    /// Move back what was moved.
+    Rollback,
+}
+
+impl Journal {
+    /// Journals the entries of `plan` that will actually be renamed.
+    pub fn for_plan(plan: &RenamePlan) -> Self {
+        Journal {
+            batch_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            entries: plan
+                .ready()
+                .map(|e| JournalEntry {
+                    id: e.id,
+                    source: e.source.clone(),
+                    target: e.target.clone(),
+                    modified: e.modified,
+                })
+                .collect(),
+            steps: Vec::new(),
+            new_dirs: Vec::new(),
+        }
+    }
+
+    pub fn path_in(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.json", self.batch_id))
+    }
+
+    /// Writes the journal into `dir` and returns its path. The journal is
+    /// on disk when this returns: it is synced under a temporary name and
+    /// then renamed into place, so a crash never leaves half a journal.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        let path = self.path_in(dir);
+        let partial = path.with_extension("json.partial");
+        let json = serde_json::to_vec_pretty(self)?;
+        let written = File::create(&partial).and_then(|mut file| {
+            file.write_all(&json)?;
+            file.sync_all()
+        });
+        written.map_err(|e| Error::io(&partial, e))?;
+        fs::rename(&partial, &path).map_err(|e| Error::io(&path, e))?;
+        sync_dir(dir);
+        Ok(path)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// The journals left in `dir` by batches that never finished, oldest
+    /// first. Journals cut off while being written are removed: their batch
+    /// had not touched any file yet.
+    pub fn incomplete(dir: &Path) -> Result<Vec<Journal>> {
+        let listing = match fs::read_dir(dir) {
+            Ok(listing) => listing,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Error::io(dir, e)),
+        };
+        let mut journals = Vec::new();
+        for path in listing.flatten().map(|e| e.path()) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.ends_with(".json.partial") {
+                let _ = fs::remove_file(&path);
+            } else if name.ends_with(".json") {
+                journals.push(Journal::read(&path)?);
+            }
+        }
+        journals.sort_by_key(|j| j.created_at);
+        Ok(journals)
+    }
+
+    /// Removes the journals in `dir` of batches that were interrupted before
+    /// `before` and never recovered, so they can no longer be. The files of
+    /// such a batch stay where the interruption left them, and backups of
+    /// files it replaced are kept. Returns how many it removed.
+    pub fn prune(dir: &Path, before: DateTime<Utc>) -> Result<usize> {
+        let mut removed = 0;
+        for journal in Journal::incomplete(dir)? {
+            if journal.created_at >= before {
+                break;
+            }
+            let path = journal.path_in(dir);
+            fs::remove_file(&path).map_err(|e| Error::io(&path, e))?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Finishes or undoes the interrupted batch, then removes its journal
+    /// from `dir`. The journal is kept if any file could not be moved, so
+    /// recovery can be tried again.
+    pub fn recover(&self, dir: &Path, how: Recovery) -> Result<ReplayReport> {
+        let steps = self.steps();
+        let done = moved_steps(&steps);
+        let mut report = ReplayReport {
+            batch_id: self.batch_id,
+            outcomes: Vec::new(),
+            done: 0,
+            problems: 0,
+        };
+        match how {
+            Recovery::Resume => self.resume(&steps, &done, &mut report),
+            Recovery::Rollback => self.roll_back(&steps, &done, &mut report),
+        }
+        report.problems = report
+            .outcomes
+            .iter()
+            .filter(|o| o.status != ReplayStatus::Done)
+            .count();
+        report.done = report.outcomes.len() - report.problems;
+        if report.problems == 0 {
+            let path = self.path_in(dir);
+            fs::remove_file(&path).map_err(|e| Error::io(&path, e))?;
+        }
+        Ok(report)
+    }
+
+    fn steps(&self) -> Vec<JournalStep> {
+        if !self.steps.is_empty() {
+            return self.steps.clone();
+        }
+        self.entries
+            .iter()
+            .map(|e| JournalStep {
+                id: e.id,
+                from: e.source.clone(),
+                to: e.target.clone(),
+                last: true,
+                backup: None,
+                independent: false,
+            })
+            .collect()
+    }
+
+    fn resume(&self, steps: &[JournalStep], done: &[bool], report: &mut ReplayReport) {
+        for (step, _) in steps.iter().zip(done).filter(|(_, &done)| !done) {
+            let result = resume_step(step);
+            if result.is_ok() && step.last {
+                let modified = self
+                    .entries
+                    .iter()
+                    .find(|e| e.id == step.id)
+                    .and_then(|e| e.modified);
+                if let Some(time) = modified {
+                    let _ = fsutil::set_modified(&step.to, time.into());
+                }
+            }
+            let failed = result.is_err();
+            report
+                .outcomes
+                .push(outcome(step, &step.from, &step.to, result));
+            // Later dependent moves count on this one.
+            if failed && !step.independent {
+                break;
+            }
+        }
+        if report
+            .outcomes
+            .iter()
+            .all(|o| o.status == ReplayStatus::Done)
+        {
+            for backup in steps.iter().filter_map(|s| s.backup.as_ref()) {
+                let _ = fs::remove_file(fsutil::long(backup));
+            }
+        }
+    }
+
+    fn roll_back(&self, steps: &[JournalStep], done: &[bool], report: &mut ReplayReport) {
+        for (step, _) in steps.iter().zip(done).rev().filter(|(_, &done)| done) {
+            let result = move_back(step);
+            report
+                .outcomes
+                .push(outcome(step, &step.to, &step.from, result));
+        }
+        // Copies to another volume that were cut off are not continued.
+        for (step, _) in steps.iter().zip(done).filter(|(s, &done)| s.last && !done) {
+            transfer::discard_partial(&step.to);
+        }
+        // Replaced files go back once what replaced them is gone.
+        for step in steps.iter().rev() {
+            let Some(backup) = step.backup.as_ref().filter(|b| fsutil::exists(b)) else {
+                continue;
+            };
+            let result = if fsutil::exists(&step.to) {
+                Err(None)
+            } else {
+                fsutil::rename(backup, &step.to).map_err(|e| Some(Error::io(backup, e)))
+            };
+            report
+                .outcomes
+                .push(outcome(step, backup, &step.to, result));
+        }
+        for dir in self.new_dirs.iter().rev() {
+            // Only succeeds for folders left empty.
+            let _ = fs::remove_dir(fsutil::long(dir));
+        }
+    }
+}
+
+/// Flushes a directory entry just created in `dir`. Windows offers no way
+/// to open a directory for this and commits renames itself.
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+/// True if the file of `step` is at `to` and no longer at `from`. Both
+/// spellings of a case-only rename name the same file on most volumes, so
+/// those are told apart by the name the folder lists.
+fn arrived(step: &JournalStep) -> bool {
+    let case_only = step.from != step.to
+        && step.from.to_string_lossy().to_lowercase() == step.to.to_string_lossy().to_lowercase();
+    if case_only {
+        has_entry_named(&step.to) && !has_entry_named(&step.from)
+    } else {
+        !fsutil::exists(&step.from) && fsutil::exists(&step.to)
+    }
+}
+
+/// Which steps had run when the batch stopped. Independent steps are looked
+/// at one by one. Dependent steps ran in order, so they are done up to the
+/// last one whose file arrived: a later step may have put another file at
+/// an earlier one's source or taken its file on from its target, but no
+/// step after the last one to run did.
+fn moved_steps(steps: &[JournalStep]) -> Vec<bool> {
+    let mut done: Vec<bool> = steps.iter().map(|s| s.independent && arrived(s)).collect();
+    let last = steps
+        .iter()
+        .rposition(|s| !s.independent && s.from != s.to && arrived(s));
+    if let Some(last) = last {
+        for (i, step) in steps.iter().enumerate().take(last + 1) {
+            done[i] = done[i] || !step.independent;
+        }
+    }
+    done
+}
+
+/// Makes a move that had not happened, parking the file it replaces first
+/// unless that had already happened.
+fn resume_step(step: &JournalStep) -> std::result::Result<(), Option<Error>> {
+    if step.from == step.to {
+        return Ok(());
+    }
+    if !fsutil::exists(&step.from) {
+        return Err(None);
+    }
+    if let Some(backup) = &step.backup {
+        if !fsutil::exists(backup) && fsutil::exists(&step.to) {
+            fsutil::rename(&step.to, backup).map_err(|e| Some(Error::io(&step.to, e)))?;
+        }
+    }
+    if fsutil::exists(&step.to) && !fsutil::is_case_only_rename(&step.from, &step.to) {
+        return Err(None);
+    }
+    if step.last {
+        rename_file(&step.from, &step.to, &mut Vec::new(), Some(copy_times())).map_err(Some)
+    } else {
+        fsutil::rename(&step.from, &step.to).map_err(|e| Some(Error::io(&step.from, e)))
+    }
+}
+
+/// Undoes a move that happened.
+fn move_back(step: &JournalStep) -> std::result::Result<(), Option<Error>> {
+    if step.from == step.to {
+        return Ok(());
+    }
+    if !fsutil::exists(&step.to) {
+        return Err(None);
+    }
+    if fsutil::exists(&step.from) && !fsutil::is_case_only_rename(&step.to, &step.from) {
+        return Err(None);
+    }
+    transfer::move_file(&step.to, &step.from, Some(copy_times()))
+        .map_err(|e| Some(Error::io(&step.to, e)))
+}
+
+/// Moves made during recovery may cross volumes, as the batch's own could.
+fn copy_times() -> CrossVolume<'static> {
+    CrossVolume {
+        progress: &|_, _| {},
+        preserve_times: true,
+    }
+}
+
+/// `Err(None)` means the file was missing at `from` or something else is at
+/// `to`, whichever applies.
+fn outcome(
+    step: &JournalStep,
+    from: &Path,
+    to: &Path,
+    result: std::result::Result<(), Option<Error>>,
+) -> ReplayOutcome {
+    let (status, error) = match result {
+        Ok(()) => (ReplayStatus::Done, None),
+        Err(Some(e)) => (ReplayStatus::Failed, Some(e.to_string())),
+        Err(None) if !fsutil::exists(from) => (ReplayStatus::Missing, None),
+        Err(None) => (ReplayStatus::Occupied, None),
+    };
+    ReplayOutcome {
+        id: step.id,
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        status,
+        error,
+    }
+}
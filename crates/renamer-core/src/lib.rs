@@ -0,0 +1,160 @@
+//! Native rename engine for Sortify.
+//!
+//! The Tauri shell uses this crate to plan and execute renames directly instead
+//! of routing every file operation through the Python sidecar.
+
+/// This crate's version, for the app's version report.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub mod archive;
+pub mod audit;
+pub mod burst;
+pub mod cache;
+pub mod cancel;
+pub mod case;
+pub mod changes;
+pub mod condition;
+pub mod dedupe;
+pub mod environment;
+pub mod episode;
+pub mod error;
+pub mod executor;
+pub mod export;
+pub mod extract;
+pub mod file_list;
+mod fsutil;
+pub mod git;
+pub mod hash;
+mod heif;
+pub mod history;
+pub mod history_db;
+pub mod identity;
+mod in_flight;
+pub mod ingest;
+pub mod journal;
+pub mod locale;
+pub mod locks;
+pub mod maintenance;
+pub mod manifest;
+pub mod mapping;
+pub mod metadata;
+pub mod name_date;
+pub mod natural;
+pub mod normalize;
+pub mod ocr;
+pub mod plan;
+pub mod plugins;
+pub mod policy;
+pub mod post_action;
+pub mod power;
+pub mod preflight;
+pub mod presets;
+pub mod preview;
+pub mod queue;
+mod raw;
+pub mod remote;
+pub mod review;
+pub mod rules;
+pub mod sanitize;
+pub mod scan;
+pub mod scope;
+mod script;
+pub mod settings;
+mod share;
+pub mod simulate;
+pub mod sniff;
+pub mod stat;
+pub mod template;
+pub mod throttle;
+pub mod thumbnail;
+mod transfer;
+pub mod translit;
+pub mod volume;
+pub mod watch;
+pub mod webhook;
+
+pub use archive::Archive;
+pub use audit::{AuditEntry, AuditLog, AuditVerification};
+pub use burst::{find_bursts, Burst, BurstAction, BurstOptions, BurstShot};
+pub use cache::FileCache;
+pub use cancel::CancelToken;
+pub use case::CaseMode;
+pub use changes::{SourceChanges, SourceWatcher};
+pub use condition::{Condition, ConditionCheck};
+pub use dedupe::{
+    find_duplicates, find_duplicates_with_progress, DedupeOptions, DedupeProgress, DedupeStage,
+    DuplicateGroup,
+};
+pub use error::{Error, ErrorCode, Result};
+pub use executor::{
+    apply, apply_with_progress, ApplyOptions, ApplyReport, EntryOutcome, OutcomeStatus, Progress,
+    StaleReason,
+};
+pub use export::{ExportFormat, ExportRow};
+pub use extract::{ExtractOptions, ExtractQueue, Extracted};
+pub use file_list::{FileList, FileListQuery, FileListWindow, ListedFile};
+pub use git::GitReport;
+pub use hash::{hash_file, hash_files, hash_files_cached, FileHash, HashAlgorithm, HashProgress};
+pub use history::{BatchRecord, HistoryStore, ReplayReport, ReplayStatus};
+pub use history_db::{
+    ArchivedBatch, ChangeKind, DateRange, DayStats, FileChange, FolderStats, HistoryDb,
+    HistoryStats, PresetStats,
+};
+pub use identity::FileId;
+pub use ingest::{IngestOptions, IngestReport};
+pub use journal::{Journal, JournalEntry, JournalStep, Recovery};
+pub use locks::{lock_holders, LockHolder};
+pub use maintenance::{DataPaths, MaintenanceReport, Retention, StorageUsage};
+pub use manifest::{
+    write_manifests, JsonManifest, ManifestFile, ManifestFormat, ManifestReport, JSON_MANIFEST,
+    SHA256SUMS,
+};
+pub use mapping::{Mapping, MappingProblem, MappingProblemKind};
+pub use metadata::{
+    best_date, extract_all, extract_all_cached, extract_all_with_progress, DateSource, MetaValue,
+    Metadata,
+};
+pub use natural::{natural_cmp, natural_path_cmp, sort_natural};
+pub use normalize::{NormalForm, Normalization};
+pub use ocr::{OcrCommand, OcrEngine};
+pub use plan::{
+    plan, CollisionStrategy, CompanionRule, Conflict, ConflictKind, Diagnostic, EntryStatus,
+    PlanEntry, PlanOptions, PlanWarning, RenameOp, RenamePlan, Resolution, Severity, SymlinkPolicy,
+};
+pub use plugins::{PluginError, PluginHost, PluginInfo, PluginManifest};
+pub use policy::{NamingPolicy, PolicyViolation};
+pub use post_action::{PostAction, PostActionRun, PostActionScope};
+pub use power::SleepGuard;
+pub use preflight::{preflight, PreflightKind, PreflightProblem, PreflightReport};
+pub use presets::{compare_presets, PlannedName, Preset, PresetComparison, PresetStore};
+pub use preview::{apply_overrides, preview, preview_bursts, PreviewRow, PreviewWarning};
+pub use queue::{
+    Job, JobInfo, JobKind, JobOutput, JobQueue, JobStatus, Lane, LaneInfo, QueueEvent,
+    QueueSnapshot,
+};
+pub use remote::{
+    Mtp, MtpDevice, MtpTarget, RemoteEntry, RemoteFs, RemoteTarget, S3Target, Sftp, SftpTarget,
+    WebDav, WebDavTarget, S3,
+};
+pub use review::{
+    review_id, Review, ReviewChange, ReviewProblem, ReviewProblemKind, ReviewRow, ReviewStatus,
+};
+pub use rules::{Pipeline, RegexRule, Renamed, Rule, StepName};
+pub use sanitize::{SanitizeFix, TargetFs};
+pub use scan::{
+    scan, DropScanner, ScanEntry, ScanError, ScanListing, ScanOptions, ScanPage, ScanQuery,
+    ScanSort, ScanWindow, Scanner,
+};
+pub use scope::PathScope;
+pub use settings::{Settings, SettingsStore};
+pub use simulate::{simulate, Simulation, Snapshot};
+pub use sniff::{ContentType, ExtensionFix};
+pub use stat::{stat_files, FileStat};
+pub use template::{
+    assign_groups, batch_contexts, burst_contexts, BurstPlace, FileContext, Template,
+};
+pub use throttle::IoThrottle;
+pub use thumbnail::{thumbnail, Thumbnail, MAX_THUMBNAIL_SIZE};
+pub use volume::Volume;
+pub use watch::{FolderWatcher, WatchEvent, WatchOptions};
+pub use webhook::{JobSummary, Webhook, WebhookEvent};
@@ -0,0 +1,125 @@
+//! The language month and day names and decimal separators are written in
+//! by template tokens: `{date:MMMM}` gives `März` in German, `{size:human}`
+//! gives `1,5 MB`.
+//!
+//! Names come from the locale data chrono formats with, the C library's.
+//! The locale used is, first, one set for what is running on this thread
+//! with [`scoped`], as a preset with its own does; then the one set with
+//! [`set_default`] from the settings; then the system's. Without any of
+//! them, names are English.
+//!
+//! Locales are given as language tags, `de`, `de-AT` or `pt_BR.UTF-8`; a bare
+//! language means where it is mostly spoken.
+
+use std::cell::Cell;
+use std::sync::{OnceLock, RwLock};
+
+use chrono::Locale;
+
+use crate::error::{Error, Result};
+
+/// The region a bare language means, where it is not the language itself
+/// in capitals.
+const REGIONS: &[(&str, &str)] = &[
+    ("ar", "SA"),
+    ("ca", "ES"),
+    ("cs", "CZ"),
+    ("da", "DK"),
+    ("el", "GR"),
+    ("en", "US"),
+    ("et", "EE"),
+    ("fa", "IR"),
+    ("he", "IL"),
+    ("hi", "IN"),
+    ("ja", "JP"),
+    ("ko", "KR"),
+    ("nb", "NO"),
+    ("nn", "NO"),
+    ("sl", "SI"),
+    ("sv", "SE"),
+    ("uk", "UA"),
+    ("vi", "VN"),
+    ("zh", "CN"),
+];
+
+/// The locale `tag` names.
+pub fn parse(tag: &str) -> Result<Locale> {
+    let unknown = || Error::UnknownLocale {
+        locale: tag.to_string(),
+    };
+    let name = tag.trim().split(['.', '@']).next().unwrap_or_default();
+    let (language, region) = match name.split_once(['-', '_']) {
+        Some((language, region)) => (language.to_lowercase(), region.to_uppercase()),
+        None => {
+            let language = name.to_lowercase();
+            let region = REGIONS
+                .iter()
+                .find(|(l, _)| *l == language)
+                .map_or_else(|| language.to_uppercase(), |(_, r)| r.to_string());
+            (language, region)
+        }
+    };
+    if language.is_empty() {
+        return Err(unknown());
+    }
+    if language == "c" || language == "posix" {
+        return Ok(Locale::POSIX);
+    }
+    Locale::try_from(format!("{}_{}", language, region).as_str()).map_err(|_| unknown())
+}
+
+fn default() -> &'static RwLock<Option<Locale>> {
+    static DEFAULT: OnceLock<RwLock<Option<Locale>>> = OnceLock::new();
+    DEFAULT.get_or_init(|| RwLock::new(None))
+}
+
+fn system() -> Locale {
+    static SYSTEM: OnceLock<Locale> = OnceLock::new();
+    *SYSTEM.get_or_init(|| {
+        sys_locale::get_locale()
+            .and_then(|tag| parse(&tag).ok())
+            .unwrap_or(Locale::POSIX)
+    })
+}
+
+thread_local! {
+    static SCOPED: Cell<Option<Locale>> = const { Cell::new(None) };
+}
+
+/// Makes `locale` the one used from now on where none is set for the
+/// thread; `None` goes back to the system's.
+pub fn set_default(locale: Option<Locale>) {
+    *default().write().unwrap_or_else(|e| e.into_inner()) = locale;
+}
+
+/// Runs `f` with `locale`, if there is one, in place of the default on
+/// this thread.
+pub fn scoped<T>(locale: Option<Locale>, f: impl FnOnce() -> T) -> T {
+    let Some(locale) = locale else {
+        return f();
+    };
+    struct Restore(Option<Locale>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED.with(|scoped| scoped.set(self.0));
+        }
+    }
+    let _restore = Restore(SCOPED.with(|scoped| scoped.replace(Some(locale))));
+    f()
+}
+
+/// The locale tokens are written in here and now.
+pub fn current() -> Locale {
+    SCOPED
+        .with(Cell::get)
+        .or_else(|| *default().read().unwrap_or_else(|e| e.into_inner()))
+        .unwrap_or_else(system)
+}
+
+/// What separates whole numbers from fractions in `locale`.
+pub(crate) fn decimal_point(locale: Locale) -> &'static str {
+    match pure_rust_locales::locale_match!(locale => LC_NUMERIC::DECIMAL_POINT) {
+        "" => ".",
+        point => point,
+    }
+}
@@ -0,0 +1,227 @@
+//! Files another program has open and will not let go of.
+//!
+//! On Windows a file that is open in another program without delete sharing
+//! cannot be renamed. Elsewhere renaming an open file works, and only busy
+//! mount points and running executables refuse. The holders are looked up
+//! with the Restart Manager on Windows, `/proc` on Linux and `lsof` on
+//! macOS; other systems report none.
+
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// A process that has a file open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub pid: u32,
+    /// The program's name, when the system says.
+    pub name: Option<String>,
+}
+
+/// True if `error` means the file is in use by another program, so trying
+/// again later may work.
+pub(crate) fn is_locked(error: &io::Error) -> bool {
+    #[cfg(windows)]
+    const LOCKED: &[i32] = &[32, 33]; // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+    #[cfg(not(windows))]
+    const LOCKED: &[i32] = &[16, 26]; // EBUSY, ETXTBSY
+    error
+        .raw_os_error()
+        .is_some_and(|code| LOCKED.contains(&code))
+}
+
+/// The processes that have `path` open, as far as they can be found out.
+pub fn lock_holders(path: &Path) -> Vec<LockHolder> {
+    platform::lock_holders(path)
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    use super::LockHolder;
+
+    const ERROR_MORE_DATA: u32 = 234;
+    const CCH_RM_SESSION_KEY: usize = 32;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct RmUniqueProcess {
+        process_id: u32,
+        start_time: [u32; 2],
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct RmProcessInfo {
+        process: RmUniqueProcess,
+        app_name: [u16; 256],
+        service_short_name: [u16; 64],
+        application_type: u32,
+        app_status: u32,
+        ts_session_id: u32,
+        restartable: i32,
+    }
+
+    #[link(name = "rstrtmgr")]
+    extern "system" {
+        fn RmStartSession(session: *mut u32, flags: u32, key: *mut u16) -> u32;
+        fn RmRegisterResources(
+            session: u32,
+            files: u32,
+            file_names: *const *const u16,
+            applications: u32,
+            unique_processes: *const c_void,
+            services: u32,
+            service_names: *const *const u16,
+        ) -> u32;
+        fn RmGetList(
+            session: u32,
+            needed: *mut u32,
+            count: *mut u32,
+            processes: *mut RmProcessInfo,
+            reboot_reasons: *mut u32,
+        ) -> u32;
+        fn RmEndSession(session: u32) -> u32;
+    }
+
+    pub fn lock_holders(path: &Path) -> Vec<LockHolder> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        let mut session = 0;
+        let mut key = [0u16; CCH_RM_SESSION_KEY + 1];
+        // SAFETY: every pointer handed over points at a live buffer of the
+        // size the Restart Manager documents, and the session is ended
+        // before returning.
+        unsafe {
+            if RmStartSession(&mut session, 0, key.as_mut_ptr()) != 0 {
+                return Vec::new();
+            }
+            let names = [wide.as_ptr()];
+            let mut holders = Vec::new();
+            if RmRegisterResources(session, 1, names.as_ptr(), 0, ptr::null(), 0, ptr::null()) == 0
+            {
+                let mut needed = 0;
+                let mut count = 0;
+                let mut reasons = 0;
+                let mut status = RmGetList(
+                    session,
+                    &mut needed,
+                    &mut count,
+                    ptr::null_mut(),
+                    &mut reasons,
+                );
+                if status == ERROR_MORE_DATA {
+                    let mut infos: Vec<RmProcessInfo> = vec![std::mem::zeroed(); needed as usize];
+                    count = needed;
+                    status = RmGetList(
+                        session,
+                        &mut needed,
+                        &mut count,
+                        infos.as_mut_ptr(),
+                        &mut reasons,
+                    );
+                    if status == 0 {
+                        infos.truncate(count as usize);
+                        holders = infos.iter().map(holder).collect();
+                    }
+                }
+            }
+            RmEndSession(session);
+            holders
+        }
+    }
+
+    fn holder(info: &RmProcessInfo) -> LockHolder {
+        let len = info
+            .app_name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(info.app_name.len());
+        let name = String::from_utf16_lossy(&info.app_name[..len]);
+        LockHolder {
+            pid: info.process.process_id,
+            name: (!name.is_empty()).then_some(name),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+    use std::path::Path;
+
+    use super::LockHolder;
+
+    /// Every process's open descriptors that this user may look at.
+    pub fn lock_holders(path: &Path) -> Vec<LockHolder> {
+        let Ok(path) = fs::canonicalize(path) else {
+            return Vec::new();
+        };
+        let Ok(procs) = fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        let mut holders = Vec::new();
+        for proc in procs.flatten() {
+            let Some(pid) = proc.file_name().to_str().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            let Ok(fds) = fs::read_dir(proc.path().join("fd")) else {
+                continue;
+            };
+            let holds = fds
+                .flatten()
+                .any(|fd| fs::read_link(fd.path()).is_ok_and(|target| target == path));
+            if holds {
+                let name = fs::read_to_string(proc.path().join("comm"))
+                    .ok()
+                    .map(|name| name.trim_end().to_string());
+                holders.push(LockHolder { pid, name });
+            }
+        }
+        holders
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::path::Path;
+    use std::process::Command;
+
+    use super::LockHolder;
+
+    /// Asks `lsof` for the pid and command name of each holder.
+    pub fn lock_holders(path: &Path) -> Vec<LockHolder> {
+        let Ok(out) = Command::new("lsof")
+            .arg("-Fpc")
+            .arg("--")
+            .arg(path)
+            .output()
+        else {
+            return Vec::new();
+        };
+        let mut holders: Vec<LockHolder> = Vec::new();
+        for line in String::from_utf8_lossy(&out.stdout).lines() {
+            if let Some(pid) = line.strip_prefix('p').and_then(|p| p.parse().ok()) {
+                holders.push(LockHolder { pid, name: None });
+            } else if let (Some(name), Some(last)) = (line.strip_prefix('c'), holders.last_mut()) {
+                last.name = Some(name.to_string());
+            }
+        }
+        holders
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+mod platform {
+    use std::path::Path;
+
+    use super::LockHolder;
+
+    pub fn lock_holders(_path: &Path) -> Vec<LockHolder> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,158 @@
+//! Keeping what the app stores from growing without end over years of use:
+//! the history database, the journals of batches never recovered, the file
+//! cache and the thumbnail cache are pruned to what [`Retention`] keeps.
+//!
+//! Undo history, presets and settings are small and kept as they are, and
+//! so is the audit log, which is only worth anything whole.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::FileCache;
+use crate::error::Result;
+use crate::history_db::HistoryDb;
+use crate::journal::Journal;
+use crate::thumbnail;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retention {
+    /// Days of batches kept in the history database; all of them when
+    /// unset.
+    pub history_days: Option<u32>,
+    /// Days the journal of an interrupted batch is kept for recovery; until
+    /// it is recovered when unset.
+    pub journal_days: Option<u32>,
+    /// Days a cached hash, metadata or name suggestion is kept without
+    /// being used. 90 by default.
+    pub file_cache_days: u32,
+    /// Megabytes the thumbnail cache may take up, dropping the previews used
+    /// longest ago first; unlimited when unset. 2048 by default.
+    pub thumbnail_cache_mb: Option<u64>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            history_days: None,
+            journal_days: None,
+            file_cache_days: 90,
+            thumbnail_cache_mb: Some(2048),
+        }
+    }
+}
+
+impl Retention {
+    /// The oldest batch the history database keeps, if it does not keep
+    /// every one.
+    pub fn history_cutoff(&self) -> Option<DateTime<Utc>> {
+        self.history_days.map(days_ago)
+    }
+}
+
+fn days_ago(days: u32) -> DateTime<Utc> {
+    Utc::now() - Duration::days(days.into())
+}
+
+/// Where the stores maintenance looks after are.
+#[derive(Debug, Clone)]
+pub struct DataPaths {
+    pub history_db: PathBuf,
+    pub journal_dir: PathBuf,
+    pub file_cache_db: PathBuf,
+    pub thumbnail_dir: PathBuf,
+}
+
+/// Bytes each store takes up on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub history: u64,
+    pub journals: u64,
+    pub file_cache: u64,
+    pub thumbnails: u64,
+}
+
+impl StorageUsage {
+    /// Measures the stores at `paths`; one that does not exist takes up
+    /// nothing.
+    pub fn measure(paths: &DataPaths) -> StorageUsage {
+        StorageUsage {
+            history: database_size(&paths.history_db),
+            journals: dir_size(&paths.journal_dir),
+            file_cache: database_size(&paths.file_cache_db),
+            thumbnails: dir_size(&paths.thumbnail_dir),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.history + self.journals + self.file_cache + self.thumbnails
+    }
+}
+
+/// A SQLite database with its write-ahead log.
+fn database_size(path: &Path) -> u64 {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    [path, Path::new(&wal)]
+        .iter()
+        .filter_map(|p| fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(listing) = fs::read_dir(dir) else {
+        return 0;
+    };
+    listing
+        .flatten()
+        .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?)))
+        .map(|(path, m)| if m.is_dir() { dir_size(&path) } else { m.len() })
+        .sum()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+    pub batches_forgotten: usize,
+    pub journals_removed: usize,
+    pub cache_entries_dropped: usize,
+    pub thumbnails_removed: usize,
+    pub before: StorageUsage,
+    pub after: StorageUsage,
+}
+
+impl MaintenanceReport {
+    /// Bytes given back to the disk.
+    pub fn freed(&self) -> u64 {
+        self.before.total().saturating_sub(self.after.total())
+    }
+}
+
+/// Prunes `history`, `cache` and the journals and thumbnails in `paths` to
+/// what `retention` keeps.
+pub fn run(
+    retention: &Retention,
+    history: &mut HistoryDb,
+    cache: &FileCache,
+    paths: &DataPaths,
+) -> Result<MaintenanceReport> {
+    let mut report = MaintenanceReport {
+        before: StorageUsage::measure(paths),
+        ..MaintenanceReport::default()
+    };
+    if let Some(cutoff) = retention.history_cutoff() {
+        report.batches_forgotten = history.prune(cutoff)?;
+    }
+    if let Some(days) = retention.journal_days {
+        report.journals_removed = Journal::prune(&paths.journal_dir, days_ago(days))?;
+    }
+    report.cache_entries_dropped = cache.prune(retention.file_cache_days)?;
+    if let Some(mb) = retention.thumbnail_cache_mb {
+        report.thumbnails_removed = thumbnail::prune_cache(&paths.thumbnail_dir, mb * 1_000_000)?;
+    }
+    report.after = StorageUsage::measure(paths);
+    Ok(report)
+}
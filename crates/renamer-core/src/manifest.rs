@@ -0,0 +1,243 @@
+//! Checksum manifests written after a batch, for archives that keep an
+//! integrity record next to their files.
+//!
+//! Each folder files were renamed into gets one manifest with the SHA-256
+//! of those files, hashed on the same thread pool as [`hash_files`]. A
+//! manifest already there is updated rather than replaced: entries for the
+//! renamed files are written anew, entries under the names they had before
+//! are dropped, and the rest are kept, so a folder renamed into batch after
+//! batch keeps one record of all of it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::executor::{ApplyReport, OutcomeStatus};
+use crate::fsutil;
+use crate::hash::{hash_files, HashAlgorithm};
+
+/// File name of a [`ManifestFormat::Sha256Sums`] manifest.
+pub const SHA256SUMS: &str = "SHA256SUMS";
+
+/// File name of a [`ManifestFormat::Json`] manifest.
+pub const JSON_MANIFEST: &str = "checksums.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestFormat {
+    /// `SHA256SUMS`, one `<hash>  <name>` line per file, as `sha256sum`
+    /// writes and `sha256sum -c` checks.
+    Sha256Sums,
+    /// `checksums.json`, with the size of each file and the batch that
+    /// last renamed it.
+    Json,
+}
+
+/// One file in a [`ManifestFormat::Json`] manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// The file name, relative to the manifest's folder.
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+    /// The batch that renamed the file; `None` for entries read from a
+    /// `SHA256SUMS` or written by another tool.
+    #[serde(default)]
+    pub batch_id: Option<Uuid>,
+}
+
+/// The contents of a [`ManifestFormat::Json`] manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonManifest {
+    pub updated: DateTime<Utc>,
+    pub files: Vec<ManifestFile>,
+}
+
+/// A manifest written after a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestReport {
+    pub path: PathBuf,
+    /// Renamed files written to it.
+    pub files: usize,
+    /// Renamed files that could not be read, and so are not in it.
+    #[serde(default)]
+    pub unreadable: Vec<PathBuf>,
+    /// Why the manifest could not be written; nothing was changed then.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Writes a manifest into every folder `report` renamed files into, and
+/// returns what was written where. Folders renamed as a whole are not
+/// hashed.
+pub fn write_manifests(report: &ApplyReport, format: ManifestFormat) -> Vec<ManifestReport> {
+    let mut folders: BTreeMap<&Path, (Vec<PathBuf>, BTreeSet<String>)> = BTreeMap::new();
+    for outcome in &report.outcomes {
+        if outcome.status != OutcomeStatus::Renamed || outcome.is_dir {
+            continue;
+        }
+        let Some(folder) = outcome.target.parent() else {
+            continue;
+        };
+        let entry = folders.entry(folder).or_default();
+        entry.0.push(outcome.target.clone());
+        if outcome.source.parent() == Some(folder) {
+            entry.1.insert(file_name(&outcome.source));
+        }
+    }
+    folders
+        .into_iter()
+        .map(|(folder, (targets, old_names))| {
+            let path = folder.join(match format {
+                ManifestFormat::Sha256Sums => SHA256SUMS,
+                ManifestFormat::Json => JSON_MANIFEST,
+            });
+            // A file renamed to the manifest's own name is left out of it.
+            let manifest_name = file_name(&path);
+            let targets: Vec<PathBuf> = targets
+                .into_iter()
+                .filter(|t| file_name(t) != manifest_name)
+                .collect();
+            let hashes = hash_files(
+                &targets,
+                HashAlgorithm::Sha256,
+                &|_| {},
+                &CancelToken::new(),
+            );
+            let mut files = Vec::new();
+            let mut unreadable = Vec::new();
+            for hashed in hashes {
+                let size = fs::metadata(fsutil::long(&hashed.path)).map(|m| m.len());
+                match (hashed.hash, size) {
+                    (Some(sha256), Ok(size)) => files.push(ManifestFile {
+                        name: file_name(&hashed.path),
+                        size,
+                        sha256,
+                        batch_id: Some(report.batch_id),
+                    }),
+                    _ => unreadable.push(hashed.path),
+                }
+            }
+            let written = files.len();
+            let error = update(&path, format, files, &old_names)
+                .err()
+                .map(|e| e.to_string());
+            ManifestReport {
+                path,
+                files: if error.is_some() { 0 } else { written },
+                unreadable,
+                error,
+            }
+        })
+        .collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Merges `files` into the manifest at `path`, dropping the entries named
+/// in `old_names` and those of the files written anew.
+fn update(
+    path: &Path,
+    format: ManifestFormat,
+    files: Vec<ManifestFile>,
+    old_names: &BTreeSet<String>,
+) -> Result<()> {
+    let existing = match fs::read_to_string(fsutil::long(path)) {
+        Ok(text) => match format {
+            ManifestFormat::Sha256Sums => parse_sums(&text),
+            ManifestFormat::Json => serde_json::from_str::<JsonManifest>(&text)?.files,
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(Error::io(path, e)),
+    };
+    let mut merged: BTreeMap<String, ManifestFile> = existing
+        .into_iter()
+        .filter(|f| !old_names.contains(&f.name))
+        .map(|f| (f.name.clone(), f))
+        .collect();
+    merged.extend(files.into_iter().map(|f| (f.name.clone(), f)));
+    let files: Vec<ManifestFile> = merged.into_values().collect();
+    let text = match format {
+        ManifestFormat::Sha256Sums => files.iter().map(sums_line).collect(),
+        ManifestFormat::Json => {
+            let manifest = JsonManifest {
+                updated: Utc::now(),
+                files,
+            };
+            serde_json::to_string_pretty(&manifest)? + "\n"
+        }
+    };
+    fs::write(fsutil::long(path), text).map_err(|e| Error::io(path, e))
+}
+
+/// A `sha256sum` line; names with a backslash or line break are escaped and
+/// the line marked with a leading backslash, as GNU coreutils does.
+fn sums_line(file: &ManifestFile) -> String {
+    if file.name.contains(['\\', '\n', '\r']) {
+        let name = file
+            .name
+            .replace('\\', "\\\\")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r");
+        format!("\\{}  {}\n", file.sha256, name)
+    } else {
+        format!("{}  {}\n", file.sha256, file.name)
+    }
+}
+
+/// The entries of a `SHA256SUMS` file, in text or binary mode; lines that
+/// are not entries are dropped.
+fn parse_sums(text: &str) -> Vec<ManifestFile> {
+    text.lines()
+        .filter_map(|line| {
+            let (escaped, line) = match line.strip_prefix('\\') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (hash, name) = line.split_once(' ')?;
+            let name = name.strip_prefix([' ', '*'])?;
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            let name = if escaped {
+                unescape(name)
+            } else {
+                name.to_string()
+            };
+            Some(ManifestFile {
+                name,
+                size: 0,
+                sha256: hash.to_ascii_lowercase(),
+                batch_id: None,
+            })
+        })
+        .collect()
+}
+
+fn unescape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,175 @@
+//! Explicit renames from a spreadsheet: a CSV or TSV file of `old, new`
+//! pairs, matched against the files being renamed.
+//!
+//! Either column may hold a bare name or a path. An old name matches the
+//! file with that name, an old path every file whose path ends with it; a new
+//! name keeps the file in its folder, a relative new path is taken from the
+//! file's folder and an absolute one is used as it is. Tabs, semicolons (as
+//! Excel writes in some locales) and commas are recognised as separators from
+//! the first line, and a header row such as `old,new` is skipped.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::plan::RenameOp;
+
+/// First cells that mark a header row.
+const HEADERS: &[&str] = &[
+    "old",
+    "old name",
+    "old_name",
+    "old path",
+    "current",
+    "current name",
+    "original",
+    "from",
+    "source",
+    "name",
+    "path",
+    "file",
+    "filename",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingProblemKind {
+    /// No file being renamed matches the old name.
+    NotFound,
+    /// Several files match the old name; use a path to tell them apart.
+    Ambiguous,
+    /// An earlier row already renames this file.
+    Duplicate,
+    /// The row has no new name.
+    MissingNewName,
+}
+
+/// A row that could not be turned into a rename.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingProblem {
+    /// One-based line in the file.
+    pub line: u64,
+    pub old: String,
+    pub new: String,
+    pub kind: MappingProblemKind,
+}
+
+/// The renames a mapping file asks for, ready for [`crate::plan`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Mapping {
+    pub ops: Vec<RenameOp>,
+    pub problems: Vec<MappingProblem>,
+}
+
+/// The separator of a spreadsheet export, from its first line.
+pub(crate) fn delimiter(text: &str) -> u8 {
+    let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    if first.contains('\t') {
+        b'\t'
+    } else if first.matches(';').count() > first.matches(',').count() {
+        b';'
+    } else {
+        b','
+    }
+}
+
+fn is_path(text: &str) -> bool {
+    text.contains(['/', '\\'])
+}
+
+/// Where `new` sends `source`: a bare name keeps it in its folder, a relative
+/// path is taken from that folder and an absolute one is used as it is.
+pub(crate) fn target(source: &Path, new: &str) -> PathBuf {
+    if is_path(new) {
+        let folder = source.parent().unwrap_or(Path::new(""));
+        folder.join(new)
+    } else {
+        source.with_file_name(new)
+    }
+}
+
+/// Reads the mapping file at `path`, see [`parse`].
+pub fn read(path: &Path, files: &[PathBuf]) -> Result<Mapping> {
+    let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+    parse(&String::from_utf8_lossy(&bytes), files)
+}
+
+/// Turns the rows of `text` into renames of `files`, the files being renamed.
+pub fn parse(text: &str, files: &[PathBuf]) -> Result<Mapping> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut by_name: HashMap<String, Vec<&PathBuf>> = HashMap::new();
+    for file in files {
+        if let Some(name) = file.file_name() {
+            by_name
+                .entry(name.to_string_lossy().into_owned())
+                .or_default()
+                .push(file);
+        }
+    }
+    let matching = |old: &str| -> Vec<&PathBuf> {
+        if is_path(old) {
+            let old = Path::new(old);
+            files.iter().filter(|f| f.ends_with(old)).collect()
+        } else {
+            by_name.get(old).cloned().unwrap_or_default()
+        }
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .delimiter(delimiter(text))
+        .from_reader(text.as_bytes());
+    let mut mapping = Mapping::default();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line());
+        let old = record.get(0).unwrap_or("");
+        let new = record.get(1).unwrap_or("");
+        if old.is_empty() && new.is_empty() {
+            continue;
+        }
+        let found = matching(old);
+        if i == 0 && found.is_empty() && HEADERS.contains(&old.to_lowercase().as_str()) {
+            continue;
+        }
+        let problem = |kind| MappingProblem {
+            line,
+            old: old.to_string(),
+            new: new.to_string(),
+            kind,
+        };
+        let source = match found[..] {
+            _ if new.is_empty() => {
+                mapping
+                    .problems
+                    .push(problem(MappingProblemKind::MissingNewName));
+                continue;
+            }
+            [] => {
+                mapping.problems.push(problem(MappingProblemKind::NotFound));
+                continue;
+            }
+            [source] => source,
+            _ => {
+                mapping
+                    .problems
+                    .push(problem(MappingProblemKind::Ambiguous));
+                continue;
+            }
+        };
+        if !seen.insert(source.clone()) {
+            mapping
+                .problems
+                .push(problem(MappingProblemKind::Duplicate));
+            continue;
+        }
+        mapping.ops.push(RenameOp::new(source, target(source, new)));
+    }
+    Ok(mapping)
+}
@@ -0,0 +1,90 @@
+//! Tags from MP3 (ID3), FLAC and Ogg (Vorbis comments), MP4/M4A, WAV and
+//! the other formats `lofty` reads.
+
+use std::io;
+use std::path::Path;
+
+use lofty::config::WriteOptions;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use lofty::tag::{Tag, TagType};
+
+use super::{MetaValue, Metadata};
+use crate::fsutil;
+
+pub(super) fn read(path: &Path, metadata: &mut Metadata) {
+    let Ok(probe) = Probe::open(fsutil::long(path)) else {
+        return;
+    };
+    let Ok(file) = probe.read() else {
+        return;
+    };
+    let Some(tag) = file.primary_tag().or_else(|| file.first_tag()) else {
+        return;
+    };
+
+    let text_keys = [
+        ("tag.artist", artist(tag)),
+        ("tag.album", tag.album().map(|a| a.into_owned())),
+        ("tag.title", tag.title().map(|t| t.into_owned())),
+        ("tag.genre", tag.genre().map(|g| g.into_owned())),
+    ];
+    for (key, value) in text_keys {
+        if let Some(value) = value.map(|v| v.trim().to_string()) {
+            if !value.is_empty() {
+                metadata.insert(key, MetaValue::Text(value));
+            }
+        }
+    }
+    let number_keys = [
+        ("tag.track", number(tag, &ItemKey::TrackNumber)),
+        ("tag.disc", number(tag, &ItemKey::DiscNumber)),
+        ("tag.year", tag.year()),
+    ];
+    for (key, value) in number_keys {
+        if let Some(value) = value {
+            metadata.insert(key, MetaValue::Integer(value.into()));
+        }
+    }
+}
+
+/// Sets the title in every tag the file has, or in a new tag of the format's
+/// usual kind if it has none. `None` if `lofty` cannot tag the file.
+pub(super) fn write_title(path: &Path, title: &str) -> Option<io::Result<()>> {
+    let probe = Probe::open(fsutil::long(path))
+        .ok()?
+        .guess_file_type()
+        .ok()?;
+    probe.file_type()?;
+    let written = probe.read().and_then(|mut file| {
+        let mut kinds: Vec<TagType> = file.tags().iter().map(|t| t.tag_type()).collect();
+        if kinds.is_empty() {
+            let kind = file.primary_tag_type();
+            file.insert_tag(Tag::new(kind));
+            kinds.push(kind);
+        }
+        for kind in kinds {
+            if let Some(tag) = file.tag_mut(kind) {
+                tag.set_title(title.to_string());
+            }
+        }
+        file.save_to_path(fsutil::long(path), WriteOptions::default())
+    });
+    Some(written.map_err(io::Error::other))
+}
+
+/// The track artist, falling back to the album artist that compilations
+/// often set instead.
+fn artist(tag: &Tag) -> Option<String> {
+    tag.artist()
+        .map(|a| a.into_owned())
+        .filter(|a| !a.trim().is_empty())
+        .or_else(|| tag.get_string(&ItemKey::AlbumArtist).map(str::to_string))
+}
+
+/// Track and disc numbers are often stored as `3/12`; only the first part
+/// counts.
+fn number(tag: &Tag, key: &ItemKey) -> Option<u32> {
+    let text = tag.get_string(key)?;
+    text.split('/').next()?.trim().parse().ok()
+}
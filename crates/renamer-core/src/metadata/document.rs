@@ -0,0 +1,421 @@
+//! Title, author and creation date of PDFs and Office Open XML documents
+//! (`.docx`, `.xlsx`, `.pptx`).
+//!
+//! PDFs are not parsed in full: the start and end of the file are searched
+//! for the document information dictionary, which is where it lives unless
+//! it was compressed into an object stream, and for the XMP packet, which
+//! writers leave uncompressed so it can be found this way.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use flate2::read::DeflateDecoder;
+use regex::bytes::Regex as BytesRegex;
+use regex::Regex;
+
+use super::{MetaValue, Metadata};
+use crate::fsutil;
+
+/// How much of each end of a PDF is searched.
+const PDF_WINDOW: u64 = 1 << 20;
+
+/// Core properties are a few hundred bytes; anything larger is not them.
+const MAX_PART: u64 = 1 << 20;
+
+#[derive(Debug, Default)]
+struct Document {
+    title: Option<String>,
+    author: Option<String>,
+    created: Option<NaiveDateTime>,
+}
+
+impl Document {
+    /// Fills in what is still missing from `other`.
+    fn or(self, other: Document) -> Document {
+        Document {
+            title: self.title.or(other.title),
+            author: self.author.or(other.author),
+            created: self.created.or(other.created),
+        }
+    }
+}
+
+pub(super) fn read(path: &Path, metadata: &mut Metadata) {
+    let Ok(mut file) = File::open(fsutil::long(path)) else {
+        return;
+    };
+    let mut magic = [0; 5];
+    if file.read_exact(&mut magic).is_err() {
+        return;
+    }
+    let document = match &magic {
+        b"%PDF-" => pdf(&mut file),
+        [b'P', b'K', 3, 4, _] => office(&mut file),
+        _ => return,
+    };
+    let Ok(document) = document else {
+        return;
+    };
+
+    for (key, value) in [
+        ("doc.title", document.title),
+        ("doc.author", document.author),
+    ] {
+        if let Some(value) = value.map(|v| v.trim().to_string()) {
+            if !value.is_empty() {
+                metadata.insert(key, MetaValue::Text(value));
+            }
+        }
+    }
+    if let Some(created) = document.created {
+        metadata.insert("doc.date", MetaValue::Date(created));
+    }
+}
+
+fn pdf(file: &mut File) -> io::Result<Document> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut data = Vec::new();
+    file.rewind()?;
+    if len <= 2 * PDF_WINDOW {
+        file.read_to_end(&mut data)?;
+    } else {
+        file.by_ref().take(PDF_WINDOW).read_to_end(&mut data)?;
+        file.seek(SeekFrom::End(-(PDF_WINDOW as i64)))?;
+        file.read_to_end(&mut data)?;
+    }
+    let xmp = xmp_packet(&data).map(xml_properties).unwrap_or_default();
+    Ok(info_dictionary(&data).unwrap_or_default().or(xmp))
+}
+
+/// The `/Info` dictionary named by the last trailer (updates are appended,
+/// so the last one is current).
+fn info_dictionary(data: &[u8]) -> Option<Document> {
+    static INFO: OnceLock<BytesRegex> = OnceLock::new();
+    static FIELD: OnceLock<BytesRegex> = OnceLock::new();
+    let info = INFO.get_or_init(|| BytesRegex::new(r"(?-u)/Info\s+(\d+)\s+(\d+)\s+R").unwrap());
+    let reference = info.captures_iter(data).last()?;
+    let object = BytesRegex::new(&format!(
+        r"(?-u)(?:^|\s){}\s+{}\s+obj\b",
+        String::from_utf8_lossy(&reference[1]),
+        String::from_utf8_lossy(&reference[2])
+    ))
+    .ok()?;
+    let start = object.find_iter(data).last()?.end();
+    let body = &data[start..];
+    let end = find(body, b"endobj").unwrap_or(body.len());
+    let body = &body[..end];
+
+    let field = FIELD
+        .get_or_init(|| BytesRegex::new(r"(?-u)/(Title|Author|CreationDate)\s*([(<])").unwrap());
+    let mut document = Document::default();
+    for caps in field.captures_iter(body) {
+        let Some(text) = pdf_string(&body[caps.get(2)?.start()..]) else {
+            continue;
+        };
+        match &caps[1] {
+            b"Title" => document.title = Some(text),
+            b"Author" => document.author = Some(text),
+            _ => document.created = pdf_date(&text),
+        }
+    }
+    Some(document)
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Decodes a literal `(...)` or hex `<...>` string at the start of `data`.
+fn pdf_string(data: &[u8]) -> Option<String> {
+    let mut bytes = Vec::new();
+    if data.first() == Some(&b'<') {
+        let digits: Vec<u8> = data[1..]
+            .iter()
+            .take_while(|&&b| b != b'>')
+            .filter(|b| !b.is_ascii_whitespace())
+            .map(|&b| (b as char).to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        for pair in digits.chunks(2) {
+            bytes.push(pair[0] << 4 | pair.get(1).copied().unwrap_or(0));
+        }
+    } else {
+        let mut depth = 0;
+        let mut chars = data.iter().copied();
+        while let Some(b) = chars.next() {
+            match b {
+                b'(' => {
+                    depth += 1;
+                    if depth == 1 {
+                        continue;
+                    }
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                b'\\' => {
+                    let escaped = chars.next()?;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'b' => bytes.push(8),
+                        b'f' => bytes.push(12),
+                        b'\n' | b'\r' => {}
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            let mut rest = chars.clone();
+                            for _ in 0..2 {
+                                match rest.next() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(d - b'0');
+                                        chars.next();
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            bytes.push(value as u8);
+                        }
+                        other => bytes.push(other),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            bytes.push(b);
+        }
+    }
+    Some(decode_text(&bytes))
+}
+
+/// PDF text strings are UTF-16 with a byte order mark, UTF-8 with one, or
+/// else PDFDocEncoding, which is close enough to Latin-1 for names.
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// `D:YYYYMMDDHHmmSS` followed by an optional zone, of which everything but
+/// the year may be left out. The time is kept as written.
+fn pdf_date(text: &str) -> Option<NaiveDateTime> {
+    let digits = text.strip_prefix("D:").unwrap_or(text);
+    let digits: String = digits.chars().take_while(char::is_ascii_digit).collect();
+    let part = |at: usize, default: u32| {
+        digits
+            .get(at..at + 2)
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(default)
+    };
+    let year = digits.get(..4)?.parse().ok()?;
+    NaiveDate::from_ymd_opt(year, part(4, 1), part(6, 1))?.and_hms_opt(
+        part(8, 0),
+        part(10, 0),
+        part(12, 0),
+    )
+}
+
+pub(super) fn xmp_packet(data: &[u8]) -> Option<&str> {
+    let start = find(data, b"<x:xmpmeta")?;
+    let end = start + find(&data[start..], b"</x:xmpmeta>")?;
+    std::str::from_utf8(&data[start..end]).ok()
+}
+
+/// Title, author and creation date from Dublin Core properties, as used by
+/// both XMP packets and OOXML core properties. Values may be wrapped in
+/// further elements (XMP's `rdf:Alt` and `rdf:Seq` lists), in which case
+/// the first item counts.
+fn xml_properties(xml: &str) -> Document {
+    static ELEMENT: OnceLock<Regex> = OnceLock::new();
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    let element = ELEMENT.get_or_init(|| {
+        Regex::new(r"<((?:[\w.-]+:)?(title|creator|created|CreateDate))\b([^>]*)>").unwrap()
+    });
+    let attribute = ATTRIBUTE.get_or_init(|| {
+        Regex::new(r#"\s[\w.-]+:(title|creator|created|CreateDate)\s*=\s*"([^"]*)""#).unwrap()
+    });
+
+    let mut document = Document::default();
+    let mut found = |name: &str, value: &str| {
+        let value = unescape(value.trim());
+        if value.is_empty() {
+            return;
+        }
+        let slot = match name {
+            "title" => &mut document.title,
+            "creator" => &mut document.author,
+            _ => {
+                if document.created.is_none() {
+                    document.created = w3c_date(&value);
+                }
+                return;
+            }
+        };
+        slot.get_or_insert(value);
+    };
+    for caps in element.captures_iter(xml) {
+        if caps[3].ends_with('/') {
+            continue;
+        }
+        let rest = &xml[caps.get(0).unwrap().end()..];
+        let Some(end) = rest.find(&format!("</{}>", &caps[1])) else {
+            continue;
+        };
+        let first = rest[..end]
+            .split(['<', '>'])
+            .step_by(2)
+            .find(|text| !text.trim().is_empty());
+        if let Some(text) = first {
+            found(&caps[2], text);
+        }
+    }
+    for caps in attribute.captures_iter(xml) {
+        found(&caps[1], &caps[2]);
+    }
+    document
+}
+
+pub(super) fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(|n| n.ok())
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `2023-07-14T08:15:00Z` and its shorter forms. Times in UTC, as Office
+/// writes them, are shown in the local zone; other offsets are kept as
+/// written.
+pub(super) fn w3c_date(text: &str) -> Option<NaiveDateTime> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        if date.offset().local_minus_utc() == 0 {
+            return Some(date.with_timezone(&Local).naive_local());
+        }
+        return Some(date.naive_local());
+    }
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text.get(..10)?, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
+
+fn office(file: &mut File) -> io::Result<Document> {
+    let core = zip_entry(file, "docProps/core.xml")?;
+    Ok(xml_properties(&String::from_utf8_lossy(&core)))
+}
+
+fn invalid() -> io::Error {
+    io::ErrorKind::InvalidData.into()
+}
+
+fn le(data: &[u8], at: usize, len: usize) -> io::Result<u64> {
+    let bytes = data.get(at..at + len).ok_or_else(invalid)?;
+    Ok(bytes.iter().rev().fold(0, |n, &b| n << 8 | u64::from(b)))
+}
+
+/// Reads one file out of a zip archive through its central directory.
+fn zip_entry(file: &mut File, name: &str) -> io::Result<Vec<u8>> {
+    // The end of central directory record is 22 bytes plus a comment of up
+    // to 64 KiB.
+    let len = file.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 0xffff);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let eocd = tail
+        .windows(4)
+        .rposition(|w| w == b"PK\x05\x06")
+        .ok_or_else(invalid)?;
+    let count = le(&tail, eocd + 10, 2)?;
+    let size = le(&tail, eocd + 12, 4)?;
+    let offset = le(&tail, eocd + 16, 4)?;
+
+    let mut directory = vec![0; size.min(len) as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut directory)?;
+    let mut at = 0;
+    for _ in 0..count {
+        if directory.get(at..at + 4) != Some(b"PK\x01\x02") {
+            break;
+        }
+        let method = le(&directory, at + 10, 2)?;
+        let compressed = le(&directory, at + 20, 4)?;
+        let name_len = le(&directory, at + 28, 2)? as usize;
+        let extra_len = le(&directory, at + 30, 2)? as usize;
+        let comment_len = le(&directory, at + 32, 2)? as usize;
+        let local = le(&directory, at + 42, 4)?;
+        let entry_name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(invalid)?;
+        at += 46 + name_len + extra_len + comment_len;
+        if entry_name != name.as_bytes() {
+            continue;
+        }
+        if compressed > MAX_PART {
+            return Err(invalid());
+        }
+
+        let mut header = [0; 30];
+        file.seek(SeekFrom::Start(local))?;
+        file.read_exact(&mut header)?;
+        let skip = le(&header, 26, 2)? + le(&header, 28, 2)?;
+        file.seek(SeekFrom::Current(skip as i64))?;
+        let data = file.by_ref().take(compressed);
+        let mut out = Vec::new();
+        match method {
+            0 => data.take(MAX_PART).read_to_end(&mut out)?,
+            8 => DeflateDecoder::new(data)
+                .take(MAX_PART)
+                .read_to_end(&mut out)?,
+            _ => return Err(invalid()),
+        };
+        return Ok(out);
+    }
+    Err(io::ErrorKind::NotFound.into())
+}
@@ -0,0 +1,498 @@
+//! Sender, subject and date of saved email: `.eml` files, which are the
+//! message as it was sent, and Outlook's `.msg` files.
+//!
+//! An `.eml` is read up to the blank line after its headers. Header values
+//! may be folded over several lines and hold encoded words
+//! (`=?UTF-8?B?...?=`), which are decoded. A `.msg` is an OLE compound file;
+//! only the streams of the message's own properties are read, with the mail
+//! headers it may have kept filling in what they lack.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use base64::Engine;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use regex::Regex;
+
+use super::{MetaValue, Metadata};
+use crate::fsutil;
+
+/// Past this, an `.eml` has no more headers worth reading.
+const HEADER_WINDOW: u64 = 256 << 10;
+
+/// Starts every OLE compound file.
+const COMPOUND_MAGIC: [u8; 8] = [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1];
+
+/// Property streams and headers are small; anything larger is not them.
+const MAX_STREAM: u64 = 1 << 20;
+
+#[derive(Debug, Default)]
+struct Mail {
+    date: Option<NaiveDateTime>,
+    from: Option<String>,
+    from_address: Option<String>,
+    subject: Option<String>,
+}
+
+impl Mail {
+    /// Fills in what is still missing from `other`.
+    fn or(self, other: Mail) -> Mail {
+        Mail {
+            date: self.date.or(other.date),
+            from: self.from.or(other.from),
+            from_address: self.from_address.or(other.from_address),
+            subject: self.subject.or(other.subject),
+        }
+    }
+}
+
+pub(super) fn read(path: &Path, metadata: &mut Metadata) {
+    let Ok(mut file) = File::open(fsutil::long(path)) else {
+        return;
+    };
+    let mut magic = [0; 8];
+    let is_compound = file.read_exact(&mut magic).is_ok() && magic == COMPOUND_MAGIC;
+    let is_eml = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("eml"));
+    let mail = if is_compound {
+        msg(&mut file)
+    } else if is_eml {
+        eml(&mut file)
+    } else {
+        return;
+    };
+    let Ok(mail) = mail else {
+        return;
+    };
+    if let Some(date) = mail.date {
+        metadata.insert("mail.date", MetaValue::Date(date));
+    }
+    let texts = [
+        ("mail.from", mail.from),
+        ("mail.from_address", mail.from_address),
+        ("mail.subject", mail.subject),
+    ];
+    for (key, text) in texts {
+        if let Some(text) = text.filter(|t| !t.is_empty()) {
+            metadata.insert(key, MetaValue::Text(text));
+        }
+    }
+}
+
+fn eml(file: &mut File) -> io::Result<Mail> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut data = Vec::new();
+    file.take(HEADER_WINDOW).read_to_end(&mut data)?;
+    Ok(headers(&data))
+}
+
+/// The fields of a message's header block, up to the first blank line.
+fn headers(data: &[u8]) -> Mail {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        // Raw 8-bit headers from old mailers, most often Latin-1.
+        Err(_) => data.iter().map(|&b| b as char).collect(),
+    };
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        // Lines that are not fields, such as mbox's `From ` separator.
+        if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| decode_words(value))
+    };
+    let (from, from_address) = field("from").map_or((None, None), |from| mailbox(&from));
+    Mail {
+        date: field("date").as_deref().and_then(mail_date),
+        from,
+        from_address,
+        subject: field("subject"),
+    }
+}
+
+/// Decodes RFC 2047 encoded words, dropping the spaces between two of them.
+fn decode_words(value: &str) -> String {
+    static WORD: OnceLock<Regex> = OnceLock::new();
+    let word = WORD.get_or_init(|| Regex::new(r"=\?([^?\s]+)\?([BbQq])\?([^?\s]*)\?=").unwrap());
+    let mut out = String::new();
+    let mut last = 0;
+    let mut after_word = false;
+    for caps in word.captures_iter(value) {
+        let whole = caps.get(0).unwrap();
+        let between = &value[last..whole.start()];
+        if !(after_word && between.trim().is_empty()) {
+            out.push_str(between);
+        }
+        let bytes = match &caps[2] {
+            "B" | "b" => base64::engine::general_purpose::STANDARD
+                .decode(&caps[3])
+                .unwrap_or_default(),
+            _ => quoted_printable(&caps[3]),
+        };
+        out.push_str(&charset(&caps[1], &bytes));
+        last = whole.end();
+        after_word = true;
+    }
+    out.push_str(&value[last..]);
+    out
+}
+
+/// The `Q` encoding: `_` for a space and `=XX` for a byte.
+fn quoted_printable(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'_' => out.push(b' '),
+            b'=' => match bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 2;
+                }
+                None => out.push(b'='),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// `bytes` in the named character set; Latin-1 and its kin byte for byte,
+/// anything else as UTF-8.
+fn charset(name: &str, bytes: &[u8]) -> String {
+    let name = name.split('*').next().unwrap_or(name).to_ascii_lowercase();
+    match name.as_str() {
+        "iso-8859-1" | "iso-8859-15" | "latin1" | "windows-1252" | "cp1252" | "us-ascii" => {
+            bytes.iter().map(|&b| b as char).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// The display name and address of the first mailbox in `value`:
+/// `"Doe, Jane" <jane@example.com>`, `jane@example.com (Jane Doe)` or a
+/// bare address.
+fn mailbox(value: &str) -> (Option<String>, Option<String>) {
+    let unquote = |name: &str| {
+        let name = name.trim().trim_matches('"').trim().replace("\\\"", "\"");
+        Some(name).filter(|n| !n.is_empty())
+    };
+    if let Some((name, rest)) = value.split_once('<') {
+        let address = rest.split('>').next().unwrap_or(rest).trim();
+        let address = Some(address.to_string()).filter(|a| !a.is_empty());
+        return (unquote(name).or_else(|| address.clone()), address);
+    }
+    if let Some((address, rest)) = value.split_once('(') {
+        let address = address.trim().to_string();
+        let name = unquote(rest.split(')').next().unwrap_or(rest));
+        return (name.or_else(|| Some(address.clone())), Some(address));
+    }
+    let address = value.split(',').next().unwrap_or(value).trim().to_string();
+    let address = Some(address).filter(|a| !a.is_empty());
+    (address.clone(), address)
+}
+
+/// An RFC 5322 date such as `Tue, 14 Mar 2023 09:30:00 +0100`, without any
+/// comment after it. Times in UTC are shown in the local zone, as for
+/// documents; other offsets are kept as written.
+fn mail_date(text: &str) -> Option<NaiveDateTime> {
+    let text = text.split('(').next().unwrap_or(text).trim();
+    let date = DateTime::parse_from_rfc2822(text).ok()?;
+    if date.offset().local_minus_utc() == 0 {
+        return Some(date.with_timezone(&Local).naive_local());
+    }
+    Some(date.naive_local())
+}
+
+/// Top-level properties of a `.msg`, as `__substg1.0_` streams of the
+/// root storage. The ids are MAPI property tags without their type.
+const SUBJECT: u16 = 0x0037;
+const SENDER_NAME: u16 = 0x0c1a;
+const SENDER_ADDRESS: u16 = 0x0c1f;
+const SENDER_SMTP_ADDRESS: u16 = 0x5d01;
+const TRANSPORT_HEADERS: u16 = 0x007d;
+/// Times in the fixed-size `__properties_version1.0` stream.
+const SUBMIT_TIME: u32 = 0x0039_0040;
+const DELIVERY_TIME: u32 = 0x0e06_0040;
+
+fn msg(file: &mut File) -> io::Result<Mail> {
+    let mut compound = Compound::open(file)?;
+    let mut streams = compound.root_streams()?;
+    let mut text = |id: u16, compound: &mut Compound| -> Option<String> {
+        // Unicode (`001F`) strings, or 8-bit (`001E`) ones from old Outlooks.
+        for (kind, unicode) in [("001F", true), ("001E", false)] {
+            let name = format!("__substg1.0_{:04X}{}", id, kind);
+            let Some(at) = streams
+                .iter()
+                .position(|(n, _)| n.eq_ignore_ascii_case(&name))
+            else {
+                continue;
+            };
+            let (_, entry) = streams.swap_remove(at);
+            let bytes = compound.stream(&entry).ok()?;
+            let text = if unicode {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            } else {
+                String::from_utf8_lossy(&bytes).into_owned()
+            };
+            return Some(text.trim_end_matches('\0').trim().to_string());
+        }
+        None
+    };
+    let subject = text(SUBJECT, &mut compound);
+    let from = text(SENDER_NAME, &mut compound);
+    let smtp = text(SENDER_SMTP_ADDRESS, &mut compound);
+    let address = text(SENDER_ADDRESS, &mut compound);
+    let headers = text(TRANSPORT_HEADERS, &mut compound);
+    // Exchange senders have an X.500 address there instead of SMTP.
+    let from_address = smtp.or(address.filter(|a| a.contains('@')));
+    let properties = streams
+        .iter()
+        .find(|(name, _)| name == "__properties_version1.0")
+        .map(|(_, entry)| compound.stream(entry))
+        .transpose()?
+        .unwrap_or_default();
+    let date = [SUBMIT_TIME, DELIVERY_TIME]
+        .into_iter()
+        .find_map(|tag| filetime(&properties, tag));
+    let mail = Mail {
+        date,
+        from: from.or_else(|| from_address.clone()),
+        from_address,
+        subject,
+    };
+    Ok(match headers {
+        Some(headers) => mail.or(self::headers(headers.as_bytes())),
+        None => mail,
+    })
+}
+
+/// The time with property `tag` in a top-level property stream, whose 32
+/// byte header is followed by 16 byte entries of tag, flags and value.
+fn filetime(properties: &[u8], tag: u32) -> Option<NaiveDateTime> {
+    let entries = properties.get(32..)?;
+    let entry = entries
+        .chunks_exact(16)
+        .find(|e| u32::from_le_bytes([e[0], e[1], e[2], e[3]]) == tag)?;
+    let ticks = i64::from_le_bytes(entry[8..16].try_into().ok()?);
+    // 100 ns intervals since 1601, in UTC.
+    const UNIX_EPOCH: i64 = 11_644_473_600;
+    let seconds = ticks / 10_000_000 - UNIX_EPOCH;
+    let utc = DateTime::<Utc>::from_timestamp(seconds, 0)?;
+    Some(utc.with_timezone(&Local).naive_local())
+}
+
+const END_OF_CHAIN: u32 = 0xffff_fffe;
+const FREE: u32 = 0xffff_ffff;
+
+/// A directory entry of a compound file.
+#[derive(Debug, Clone)]
+struct Entry {
+    kind: u8,
+    left: u32,
+    right: u32,
+    child: u32,
+    start: u32,
+    size: u64,
+}
+
+/// Just enough of an OLE compound file to read the streams of its root
+/// storage.
+struct Compound<'a> {
+    file: &'a mut File,
+    sector_size: u64,
+    mini_cutoff: u64,
+    fat: Vec<u32>,
+    mini_fat: Vec<u32>,
+    /// Where the streams too small for sectors of their own are kept.
+    mini_stream: Vec<u32>,
+    entries: Vec<(String, Entry)>,
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not a compound file")
+}
+
+fn le32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+impl<'a> Compound<'a> {
+    fn open(file: &'a mut File) -> io::Result<Self> {
+        let mut header = [0; 512];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+        let sector_size = match u16::from_le_bytes([header[0x1e], header[0x1f]]) {
+            9 => 512,
+            12 => 4096,
+            _ => return Err(invalid()),
+        };
+        let mut compound = Compound {
+            file,
+            sector_size,
+            mini_cutoff: u64::from(le32(&header, 0x38)),
+            fat: Vec::new(),
+            mini_fat: Vec::new(),
+            mini_stream: Vec::new(),
+            entries: Vec::new(),
+        };
+        let per_sector = sector_size as usize / 4;
+        let mut fat_sectors: Vec<u32> = (0..109)
+            .map(|i| le32(&header, 0x4c + i * 4))
+            .take(le32(&header, 0x2c) as usize)
+            .collect();
+        let mut difat = le32(&header, 0x44);
+        let mut hops = 0;
+        while difat != END_OF_CHAIN && difat != FREE && hops < le32(&header, 0x48) {
+            let sector = compound.sector(difat)?;
+            fat_sectors.extend((0..per_sector - 1).map(|i| le32(&sector, i * 4)));
+            difat = le32(&sector, (per_sector - 1) * 4);
+            hops += 1;
+        }
+        fat_sectors.truncate(le32(&header, 0x2c) as usize);
+        for at in fat_sectors {
+            let sector = compound.sector(at)?;
+            compound
+                .fat
+                .extend((0..per_sector).map(|i| le32(&sector, i * 4)));
+        }
+        for at in compound.chain(le32(&header, 0x3c)) {
+            let sector = compound.sector(at)?;
+            compound
+                .mini_fat
+                .extend((0..per_sector).map(|i| le32(&sector, i * 4)));
+        }
+        let mut directory = Vec::new();
+        for at in compound.chain(le32(&header, 0x30)) {
+            directory.extend(compound.sector(at)?);
+        }
+        compound.entries = directory.chunks_exact(128).map(entry).collect();
+        let root = compound.entries.first().ok_or_else(invalid)?.1.clone();
+        compound.mini_stream = compound.chain(root.start);
+        Ok(compound)
+    }
+
+    fn sector(&mut self, at: u32) -> io::Result<Vec<u8>> {
+        let mut sector = vec![0; self.sector_size as usize];
+        self.file
+            .seek(SeekFrom::Start((u64::from(at) + 1) * self.sector_size))?;
+        self.file.read_exact(&mut sector)?;
+        Ok(sector)
+    }
+
+    /// The sectors of the chain starting at `start` in the FAT.
+    fn chain(&self, start: u32) -> Vec<u32> {
+        follow(&self.fat, start)
+    }
+
+    /// The streams directly in the root storage, by name.
+    fn root_streams(&self) -> io::Result<Vec<(String, Entry)>> {
+        let root = &self.entries.first().ok_or_else(invalid)?.1;
+        let mut streams = Vec::new();
+        let mut seen = vec![false; self.entries.len()];
+        let mut pending = vec![root.child];
+        while let Some(at) = pending.pop() {
+            let Some((name, entry)) = self.entries.get(at as usize) else {
+                continue;
+            };
+            // A broken tree may loop.
+            if std::mem::replace(&mut seen[at as usize], true) {
+                continue;
+            }
+            pending.extend([entry.left, entry.right]);
+            if entry.kind == 2 {
+                streams.push((name.clone(), entry.clone()));
+            }
+        }
+        Ok(streams)
+    }
+
+    fn stream(&mut self, entry: &Entry) -> io::Result<Vec<u8>> {
+        if entry.size > MAX_STREAM {
+            return Err(invalid());
+        }
+        let mut data = Vec::with_capacity(entry.size as usize);
+        if entry.size < self.mini_cutoff {
+            for mini in follow(&self.mini_fat, entry.start) {
+                let offset = u64::from(mini) * 64;
+                let Some(&at) = self.mini_stream.get((offset / self.sector_size) as usize) else {
+                    return Err(invalid());
+                };
+                let sector = self.sector(at)?;
+                let within = (offset % self.sector_size) as usize;
+                data.extend_from_slice(&sector[within..within + 64]);
+            }
+        } else {
+            for at in self.chain(entry.start) {
+                data.extend(self.sector(at)?);
+            }
+        }
+        if (data.len() as u64) < entry.size {
+            return Err(invalid());
+        }
+        data.truncate(entry.size as usize);
+        Ok(data)
+    }
+}
+
+/// A chain of `table` from `start`, cut short where it loops.
+fn follow(table: &[u32], start: u32) -> Vec<u32> {
+    let mut chain = Vec::new();
+    let mut at = start;
+    while at != END_OF_CHAIN && at != FREE && chain.len() <= table.len() {
+        chain.push(at);
+        match table.get(at as usize) {
+            Some(&next) => at = next,
+            None => break,
+        }
+    }
+    chain
+}
+
+fn entry(data: &[u8]) -> (String, Entry) {
+    let len = (u16::from_le_bytes([data[64], data[65]]) as usize).min(64);
+    let units: Vec<u16> = data[..len]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let name = String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_string();
+    let entry = Entry {
+        kind: data[66],
+        left: le32(data, 68),
+        right: le32(data, 72),
+        child: le32(data, 76),
+        start: le32(data, 116),
+        size: u64::from(le32(data, 120)),
+    };
+    (name, entry)
+}
@@ -0,0 +1,233 @@
+//! Properties stored inside files, such as the camera in a photo's EXIF
+//! data, the artist in a song's tags, the author of a PDF or the sender of
+//! an email, for template tokens like `{exif.camera}`, `{tag.artist}`,
+//! `{doc.author}` and `{mail.from}`.
+//!
+//! Extraction never fails: a file that cannot be read, or has nothing to
+//! offer, simply yields no values and its tokens render empty. The only
+//! thing written back is a title, see [`write_title`].
+//!
+//! Where files disagree on how well they are dated, [`best_date`] takes the
+//! first date a chain of [`DateSource`]s has, for `{best_date}`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::FileCache;
+use crate::name_date;
+
+mod audio;
+mod document;
+mod mail;
+mod photo;
+mod places;
+mod video;
+mod write;
+mod xmp;
+
+pub use write::write_title;
+
+/// What kind of value a key holds, which decides the options its token takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Text,
+    /// Takes a zero-padding width, e.g. `{exif.iso:5}`.
+    Integer,
+    /// Takes a date format, e.g. `{exif.date_taken:yyyyMMdd}`.
+    Date,
+}
+
+/// Every key a template may reference.
+pub(crate) const KEYS: &[(&str, Kind)] = &[
+    ("exif.date_taken", Kind::Date),
+    ("xmp.date_taken", Kind::Date),
+    ("exif.camera", Kind::Text),
+    ("exif.iso", Kind::Integer),
+    ("exif.lens", Kind::Text),
+    ("exif.gps.city", Kind::Text),
+    ("exif.gps.country", Kind::Text),
+    ("tag.artist", Kind::Text),
+    ("tag.album", Kind::Text),
+    ("tag.title", Kind::Text),
+    ("tag.genre", Kind::Text),
+    ("tag.track", Kind::Integer),
+    ("tag.disc", Kind::Integer),
+    ("tag.year", Kind::Integer),
+    ("video.width", Kind::Integer),
+    ("video.height", Kind::Integer),
+    ("video.duration", Kind::Integer),
+    ("video.codec", Kind::Text),
+    ("video.created", Kind::Date),
+    ("doc.title", Kind::Text),
+    ("doc.author", Kind::Text),
+    ("doc.date", Kind::Date),
+    ("mail.date", Kind::Date),
+    ("mail.from", Kind::Text),
+    ("mail.from_address", Kind::Text),
+    ("mail.subject", Kind::Text),
+];
+
+/// Shorter names for keys.
+const ALIASES: &[(&str, &str)] = &[("exif.date", "exif.date_taken")];
+
+pub(crate) fn kind(key: &str) -> Option<(&'static str, Kind)> {
+    let key = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map_or(key, |(_, key)| key);
+    KEYS.iter().find(|(k, _)| *k == key).copied()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetaValue {
+    Integer(i64),
+    /// Local time as recorded by the device; files rarely say which zone.
+    Date(NaiveDateTime),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub path: PathBuf,
+    /// Values by key, e.g. `exif.camera`. Keys the file has no value for are
+    /// left out.
+    pub values: BTreeMap<String, MetaValue>,
+}
+
+impl Metadata {
+    pub fn get(&self, key: &str) -> Option<&MetaValue> {
+        self.values.get(key)
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, value: MetaValue) {
+        self.values.insert(key.to_string(), value);
+    }
+}
+
+/// Somewhere a file's date can come from, for [`best_date`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    /// When the photo was taken as its EXIF data says, or the video
+    /// recorded.
+    Exif,
+    /// When the photo was taken as XMP says, in a sidecar or in the file.
+    Xmp,
+    /// The file's modification time.
+    Modified,
+    /// A date written in the name, see [`crate::name_date`].
+    Name,
+}
+
+impl DateSource {
+    /// The order tried unless told otherwise.
+    pub const CHAIN: [DateSource; 4] = [
+        DateSource::Exif,
+        DateSource::Xmp,
+        DateSource::Modified,
+        DateSource::Name,
+    ];
+
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        Some(match name.trim() {
+            "exif" => DateSource::Exif,
+            "xmp" => DateSource::Xmp,
+            "modified" | "mtime" => DateSource::Modified,
+            "name" => DateSource::Name,
+            _ => return None,
+        })
+    }
+
+    /// True if reading this source needs [`extract`].
+    pub(crate) fn is_embedded(self) -> bool {
+        matches!(self, DateSource::Exif | DateSource::Xmp)
+    }
+}
+
+/// The first date one of `chain` has for the file named `name`, with where
+/// it came from. `embedded` is what [`extract`] read from the file and
+/// `modified` its modification time.
+pub fn best_date(
+    chain: &[DateSource],
+    embedded: Option<&Metadata>,
+    modified: Option<DateTime<Local>>,
+    name: &str,
+) -> Option<(NaiveDateTime, DateSource)> {
+    let date = |key: &str| match embedded?.get(key)? {
+        MetaValue::Date(date) => Some(*date),
+        _ => None,
+    };
+    chain.iter().find_map(|&source| {
+        let found = match source {
+            DateSource::Exif => date("exif.date_taken").or_else(|| date("video.created")),
+            DateSource::Xmp => date("xmp.date_taken"),
+            DateSource::Modified => modified.map(|m| m.naive_local()),
+            DateSource::Name => name_date::find(name),
+        };
+        found.map(|date| (date, source))
+    })
+}
+
+/// Reads everything this module knows how to read from `path`.
+pub fn extract(path: &Path) -> Metadata {
+    let mut metadata = Metadata {
+        path: path.to_path_buf(),
+        values: BTreeMap::new(),
+    };
+    photo::read(path, &mut metadata);
+    audio::read(path, &mut metadata);
+    video::read(path, &mut metadata);
+    document::read(path, &mut metadata);
+    mail::read(path, &mut metadata);
+    xmp::read(path, &mut metadata);
+    metadata
+}
+
+/// [`extract`] for a whole batch on a thread pool, in the order given.
+pub fn extract_all(paths: &[PathBuf]) -> Vec<Metadata> {
+    extract_all_with_progress(paths, &|_, _| {})
+}
+
+/// [`extract_all`], calling `progress` with the number of files read so far
+/// and the file just read after each one.
+pub fn extract_all_with_progress(
+    paths: &[PathBuf],
+    progress: &(dyn Fn(usize, &Path) + Sync),
+) -> Vec<Metadata> {
+    extract_each(paths, None, progress)
+}
+
+/// [`extract_all_with_progress`], taking what was read before from files
+/// unchanged since out of `cache` and adding the rest to it.
+pub fn extract_all_cached(
+    paths: &[PathBuf],
+    cache: &FileCache,
+    progress: &(dyn Fn(usize, &Path) + Sync),
+) -> Vec<Metadata> {
+    extract_each(paths, Some(cache), progress)
+}
+
+fn extract_each(
+    paths: &[PathBuf],
+    cache: Option<&FileCache>,
+    progress: &(dyn Fn(usize, &Path) + Sync),
+) -> Vec<Metadata> {
+    let completed = AtomicUsize::new(0);
+    paths
+        .par_iter()
+        .map(|path| {
+            let metadata = match cache {
+                Some(cache) => cache.metadata(path),
+                None => extract(path),
+            };
+            progress(completed.fetch_add(1, Ordering::Relaxed) + 1, path);
+            metadata
+        })
+        .collect()
+}
@@ -0,0 +1,128 @@
+//! EXIF data from JPEG, TIFF, HEIF, PNG and WebP images and camera raw
+//! files.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{Reader, Tag, Value};
+
+use super::{places, MetaValue, Metadata};
+use crate::fsutil;
+use crate::heif;
+use crate::raw::{self, ExifFields};
+
+pub(super) fn read(path: &Path, metadata: &mut Metadata) {
+    let long = fsutil::long(path);
+    let exif = if raw::is_raw(path) {
+        raw::exif(&long)
+    } else {
+        heif::is_heif(path)
+            .then(|| heif::exif(&long))
+            .flatten()
+            .or_else(|| {
+                let file = File::open(&long).ok()?;
+                Reader::new()
+                    .read_from_container(&mut BufReader::new(file))
+                    .ok()
+            })
+            .map(ExifFields::Whole)
+    };
+    let Some(exif) = exif else {
+        return;
+    };
+    if let Some(date) = date_taken(&exif) {
+        metadata.insert("exif.date_taken", MetaValue::Date(date));
+    }
+    if let Some(camera) = camera(&exif) {
+        metadata.insert("exif.camera", MetaValue::Text(camera));
+    }
+    let iso = exif
+        .get(Tag::PhotographicSensitivity)
+        .and_then(|f| f.value.get_uint(0));
+    if let Some(iso) = iso {
+        metadata.insert("exif.iso", MetaValue::Integer(iso.into()));
+    }
+    if let Some(lens) = text(&exif, Tag::LensModel) {
+        metadata.insert("exif.lens", MetaValue::Text(lens));
+    }
+    if let Some(place) = position(&exif).and_then(|(lat, lon)| places::nearest(lat, lon)) {
+        metadata.insert("exif.gps.city", MetaValue::Text(place.city.to_string()));
+        if !place.country.is_empty() {
+            metadata.insert(
+                "exif.gps.country",
+                MetaValue::Text(place.country.to_string()),
+            );
+        }
+    }
+}
+
+fn text(exif: &ExifFields, tag: Tag) -> Option<String> {
+    let Value::Ascii(parts) = &exif.get(tag)?.value else {
+        return None;
+    };
+    let text = String::from_utf8_lossy(parts.first()?);
+    let text = text.trim_matches(['\0', ' ']);
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// When the shutter fired, falling back to when the file was written.
+fn date_taken(exif: &ExifFields) -> Option<NaiveDateTime> {
+    [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| {
+            let Value::Ascii(parts) = &exif.get(tag)?.value else {
+                return None;
+            };
+            let dt = exif::DateTime::from_ascii(parts.first()?).ok()?;
+            NaiveDate::from_ymd_opt(dt.year.into(), dt.month.into(), dt.day.into())?.and_hms_opt(
+                dt.hour.into(),
+                dt.minute.into(),
+                dt.second.into(),
+            )
+        })
+}
+
+/// Latitude and longitude in decimal degrees, negative to the south and west.
+fn position(exif: &ExifFields) -> Option<(f64, f64)> {
+    let lat = coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, 'S')?;
+    let lon = coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, 'W')?;
+    Some((lat, lon))
+}
+
+/// A coordinate stored as degrees, minutes and seconds.
+fn coordinate(exif: &ExifFields, tag: Tag, reference: Tag, negative: char) -> Option<f64> {
+    let Value::Rational(parts) = &exif.get(tag)?.value else {
+        return None;
+    };
+    let degrees = parts
+        .iter()
+        .take(3)
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, unit)| part.to_f64() / unit)
+        .sum::<f64>();
+    if !degrees.is_finite() {
+        return None;
+    }
+    let flip = text(exif, reference).is_some_and(|r| r.starts_with(negative));
+    Some(if flip { -degrees } else { degrees })
+}
+
+/// Make and model, without repeating the make when the model already starts
+/// with it (`Canon Canon EOS R5`).
+fn camera(exif: &ExifFields) -> Option<String> {
+    let make = text(exif, Tag::Make);
+    let model = text(exif, Tag::Model);
+    match (make, model) {
+        (Some(make), Some(model)) => {
+            let brand = make.split_whitespace().next().unwrap_or(&make);
+            if model.to_lowercase().starts_with(&brand.to_lowercase()) {
+                Some(model)
+            } else {
+                Some(format!("{} {}", make, model))
+            }
+        }
+        (make, model) => make.or(model),
+    }
+}
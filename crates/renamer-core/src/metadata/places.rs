@@ -0,0 +1,127 @@
+//! Offline reverse geocoding: the nearest town to a GPS position, from the
+//! GeoNames list of places with at least 1,000 inhabitants compiled in from
+//! `data/`.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io::Read;
+use std::sync::OnceLock;
+
+use flate2::read::GzDecoder;
+
+const CITIES: &[u8] = include_bytes!("../../data/cities.tsv.gz");
+const COUNTRIES: &str = include_str!("../../data/countries.tsv");
+
+/// Positions further than this from every known place get no name, so a
+/// photo taken at sea is not credited to a town on the nearest coast.
+const MAX_DISTANCE_KM: f64 = 100.0;
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = EARTH_RADIUS_KM * PI / 180.0;
+
+pub(super) struct Place {
+    pub city: &'static str,
+    pub country: &'static str,
+}
+
+struct Entry {
+    lat: f32,
+    lon: f32,
+    /// Offset of the place's line in [`Gazetteer::text`].
+    line: u32,
+}
+
+struct Gazetteer {
+    /// The decompressed list, one `lat lon name country-code` line per place.
+    text: String,
+    /// Places by 1° cell.
+    cells: HashMap<(i32, i32), Vec<Entry>>,
+}
+
+fn cell(lat: f64, lon: f64) -> (i32, i32) {
+    (lat.floor() as i32, lon.floor() as i32)
+}
+
+fn gazetteer() -> &'static Gazetteer {
+    static GAZETTEER: OnceLock<Gazetteer> = OnceLock::new();
+    GAZETTEER.get_or_init(|| {
+        let mut text = String::new();
+        GzDecoder::new(CITIES)
+            .read_to_string(&mut text)
+            .expect("bundled city list is valid");
+        let mut cells: HashMap<(i32, i32), Vec<Entry>> = HashMap::new();
+        let mut start = 0;
+        for line in text.split_inclusive('\n') {
+            let mut fields = line.split('\t');
+            let lat = fields.next().and_then(|f| f.parse::<f32>().ok());
+            let lon = fields.next().and_then(|f| f.parse::<f32>().ok());
+            if let (Some(lat), Some(lon)) = (lat, lon) {
+                cells
+                    .entry(cell(lat.into(), lon.into()))
+                    .or_default()
+                    .push(Entry {
+                        lat,
+                        lon,
+                        line: start as u32,
+                    });
+            }
+            start += line.len();
+        }
+        Gazetteer { text, cells }
+    })
+}
+
+/// Great-circle distance in kilometres.
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.1 - a.1).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+fn country_name(code: &str) -> &'static str {
+    COUNTRIES
+        .lines()
+        .find_map(|line| {
+            let (cc, name) = line.split_once('\t')?;
+            (cc == code).then_some(name)
+        })
+        .unwrap_or("")
+}
+
+/// The place nearest to `lat`, `lon` (decimal degrees), if one is within
+/// [`MAX_DISTANCE_KM`].
+pub(super) fn nearest(lat: f64, lon: f64) -> Option<Place> {
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return None;
+    }
+    let gazetteer = gazetteer();
+    let (row, col) = cell(lat, lon);
+    // One cell north and south covers 111 km; east and west, as many cells
+    // as that distance spans at the row furthest from the equator.
+    let widest = (lat.abs() + 1.0).min(90.0).to_radians().cos();
+    let span = MAX_DISTANCE_KM / (KM_PER_DEGREE * widest);
+    let cols = if span >= 180.0 {
+        -180..=179
+    } else {
+        (col - span.ceil() as i32)..=(col + span.ceil() as i32)
+    };
+    let mut best: Option<(f64, u32)> = None;
+    for r in row - 1..=row + 1 {
+        for c in cols.clone() {
+            let c = (c + 180).rem_euclid(360) - 180;
+            for entry in gazetteer.cells.get(&(r, c)).into_iter().flatten() {
+                let d = distance((lat, lon), (entry.lat.into(), entry.lon.into()));
+                if d <= MAX_DISTANCE_KM && best.map_or(true, |(bd, _)| d < bd) {
+                    best = Some((d, entry.line));
+                }
+            }
+        }
+    }
+    let (_, start) = best?;
+    let line = gazetteer.text[start as usize..].lines().next()?;
+    let mut fields = line.split('\t').skip(2);
+    let city = fields.next()?;
+    let country = country_name(fields.next()?);
+    Some(Place { city, country })
+}
@@ -0,0 +1,370 @@
+//! Resolution, length, codec and recording date from MP4/MOV (ISO base
+//! media) and Matroska/WebM files.
+//!
+//! Only the headers are read; the media data, which can run to gigabytes,
+//! is seeked over.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::{DateTime, Local, TimeDelta, TimeZone, Utc};
+
+use super::{MetaValue, Metadata};
+use crate::fsutil;
+
+/// Headers larger than this are assumed to be garbage rather than read into
+/// memory.
+const MAX_HEADER: u64 = 64 << 20;
+
+/// Box types an ISO media file may start with.
+const ISO_FIRST_BOXES: &[&[u8; 4]] = &[b"ftyp", b"moov", b"mdat", b"wide", b"free", b"skip"];
+
+/// Seconds from 1904-01-01, where ISO media clocks start, to 1970-01-01.
+const ISO_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+const EBML_MAGIC: [u8; 4] = [0x1a, 0x45, 0xdf, 0xa3];
+const SEGMENT: u64 = 0x1853_8067;
+const INFO: u64 = 0x1549_a966;
+const TRACKS: u64 = 0x1654_ae6b;
+const CLUSTER: u64 = 0x1f43_b675;
+const TIMECODE_SCALE: u64 = 0x2a_d7b1;
+const DURATION: u64 = 0x4489;
+const DATE_UTC: u64 = 0x4461;
+const TRACK_ENTRY: u64 = 0xae;
+const TRACK_TYPE: u64 = 0x83;
+const CODEC_ID: u64 = 0x86;
+const VIDEO: u64 = 0xe0;
+const PIXEL_WIDTH: u64 = 0xb0;
+const PIXEL_HEIGHT: u64 = 0xba;
+
+#[derive(Debug, Default)]
+struct Video {
+    width: Option<u32>,
+    height: Option<u32>,
+    /// Seconds.
+    duration: Option<f64>,
+    codec: Option<String>,
+    created: Option<DateTime<Utc>>,
+}
+
+pub(super) fn read(path: &Path, metadata: &mut Metadata) {
+    let Ok(file) = File::open(fsutil::long(path)) else {
+        return;
+    };
+    let mut file = BufReader::new(file);
+    let mut magic = [0; 8];
+    if file.read_exact(&mut magic).is_err() || file.rewind().is_err() {
+        return;
+    }
+    let video = if magic[..4] == EBML_MAGIC {
+        matroska(&mut file)
+    } else if ISO_FIRST_BOXES.iter().any(|b| magic[4..] == b[..]) {
+        iso_media(&mut file)
+    } else {
+        return;
+    };
+    // Files with sound only (M4A, MKA) have nothing to offer here.
+    let Ok(Some(video)) = video else {
+        return;
+    };
+
+    for (key, value) in [("video.width", video.width), ("video.height", video.height)] {
+        if let Some(value) = value {
+            metadata.insert(key, MetaValue::Integer(value.into()));
+        }
+    }
+    if let Some(duration) = video.duration {
+        metadata.insert(
+            "video.duration",
+            MetaValue::Integer(duration.round() as i64),
+        );
+    }
+    if let Some(codec) = video.codec.filter(|c| !c.is_empty()) {
+        metadata.insert("video.codec", MetaValue::Text(codec));
+    }
+    // Both containers store UTC; show it the way the camera's clock did,
+    // assuming it was set to the local zone.
+    if let Some(created) = video.created {
+        let local = created.with_timezone(&Local).naive_local();
+        metadata.insert("video.created", MetaValue::Date(local));
+    }
+}
+
+/// Common names for the codec identifiers of both containers.
+fn codec_name(id: &str) -> String {
+    let name = match id {
+        "avc1" | "avc3" | "V_MPEG4/ISO/AVC" => "h264",
+        "hvc1" | "hev1" | "V_MPEGH/ISO/HEVC" => "h265",
+        "vp08" | "V_VP8" => "vp8",
+        "vp09" | "V_VP9" => "vp9",
+        "av01" | "V_AV1" => "av1",
+        "apch" | "apcn" | "apcs" | "apco" | "ap4h" | "V_PRORES" => "prores",
+        other => return other.trim().trim_start_matches("V_").to_lowercase(),
+    };
+    name.to_string()
+}
+
+/// Big-endian unsigned integer of `len` bytes at `at`.
+fn be(data: &[u8], at: usize, len: usize) -> Option<u64> {
+    let bytes = data.get(at..at.checked_add(len)?)?;
+    Some(bytes.iter().fold(0, |n, &b| n << 8 | u64::from(b)))
+}
+
+fn iso_media(file: &mut (impl Read + Seek)) -> io::Result<Option<Video>> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let mut at = 0;
+    while at + 8 <= end {
+        file.seek(SeekFrom::Start(at))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let (size, header_len) = match be(&header, 0, 4) {
+            Some(0) => (end - at, 8),
+            Some(1) => {
+                let mut large = [0; 8];
+                file.read_exact(&mut large)?;
+                (u64::from_be_bytes(large), 16)
+            }
+            size => (size.unwrap_or(0), 8),
+        };
+        if size < header_len {
+            break;
+        }
+        if &header[4..] == b"moov" {
+            if size > MAX_HEADER {
+                break;
+            }
+            let mut moov = vec![0; (size - header_len) as usize];
+            file.read_exact(&mut moov)?;
+            return Ok(movie(&moov));
+        }
+        at = at.saturating_add(size);
+    }
+    Ok(None)
+}
+
+/// The child boxes of an ISO media box, as `(type, body)`.
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        let (size, header) = match be(data, 0, 4)? {
+            0 => (data.len(), 8),
+            1 => (usize::try_from(be(data, 8, 8)?).ok()?, 16),
+            size => (size as usize, 8),
+        };
+        if size < header || size > data.len() {
+            return None;
+        }
+        let found = (&data[4..8], &data[header..size]);
+        data = &data[size..];
+        Some(found)
+    })
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data).find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+fn movie(moov: &[u8]) -> Option<Video> {
+    let mut video = boxes(moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .find_map(|(_, trak)| iso_video_track(trak))?;
+    if let Some(mvhd) = child(moov, b"mvhd") {
+        movie_header(mvhd, &mut video);
+    }
+    Some(video)
+}
+
+fn movie_header(mvhd: &[u8], video: &mut Video) -> Option<()> {
+    let (created, timescale, duration, unknown) = if mvhd.first() == Some(&1) {
+        (
+            be(mvhd, 4, 8)?,
+            be(mvhd, 20, 4)?,
+            be(mvhd, 24, 8)?,
+            u64::MAX,
+        )
+    } else {
+        (
+            be(mvhd, 4, 4)?,
+            be(mvhd, 12, 4)?,
+            be(mvhd, 16, 4)?,
+            u32::MAX.into(),
+        )
+    };
+    if timescale > 0 && duration != unknown {
+        video.duration = Some(duration as f64 / timescale as f64);
+    }
+    if created > 0 {
+        let secs = i64::try_from(created).ok()? - ISO_EPOCH_OFFSET;
+        video.created = Utc.timestamp_opt(secs, 0).single();
+    }
+    Some(())
+}
+
+fn iso_video_track(trak: &[u8]) -> Option<Video> {
+    let mdia = child(trak, b"mdia")?;
+    if child(mdia, b"hdlr")?.get(8..12)? != b"vide" {
+        return None;
+    }
+    // Width and height close the track header as 16.16 fixed point, after
+    // the 3x3 display matrix.
+    let tkhd = child(trak, b"tkhd")?;
+    let end = tkhd.len().checked_sub(8)?;
+    let mut width = (be(tkhd, end, 4)? >> 16) as u32;
+    let mut height = (be(tkhd, end + 4, 4)? >> 16) as u32;
+    let matrix = end.checked_sub(36)?;
+    let (a, d) = (be(tkhd, matrix, 4)?, be(tkhd, matrix + 16, 4)?);
+    if a == 0 && d == 0 {
+        // Rotated a quarter turn, as phones record portrait video.
+        std::mem::swap(&mut width, &mut height);
+    }
+    let codec = child(mdia, b"minf")
+        .and_then(|minf| child(minf, b"stbl"))
+        .and_then(|stbl| child(stbl, b"stsd"))
+        .and_then(|stsd| stsd.get(12..16))
+        .map(|fourcc| codec_name(&String::from_utf8_lossy(fourcc)));
+    Some(Video {
+        width: Some(width),
+        height: Some(height),
+        codec,
+        ..Video::default()
+    })
+}
+
+/// An EBML variable-length integer with its marker bit, and its length.
+fn vint(input: &mut impl Read) -> io::Result<(u64, u32)> {
+    let mut byte = [0];
+    input.read_exact(&mut byte)?;
+    let len = byte[0].leading_zeros() + 1;
+    if len > 8 {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    let mut value = u64::from(byte[0]);
+    for _ in 1..len {
+        input.read_exact(&mut byte)?;
+        value = value << 8 | u64::from(byte[0]);
+    }
+    Ok((value, len))
+}
+
+/// An element's ID and body size; `None` if the size is unknown, as it is
+/// for live recordings.
+fn element(input: &mut impl Read) -> io::Result<(u64, Option<u64>)> {
+    let (id, _) = vint(input)?;
+    let (size, len) = vint(input)?;
+    let marker = 1u64 << (7 * len);
+    let size = size & (marker - 1);
+    Ok((id, (size != marker - 1).then_some(size)))
+}
+
+/// The child elements of a Matroska element, as `(id, body)`.
+fn elements(mut data: &[u8]) -> impl Iterator<Item = (u64, &[u8])> {
+    std::iter::from_fn(move || {
+        let (id, size) = element(&mut data).ok()?;
+        let size = usize::try_from(size?).ok()?;
+        if size > data.len() {
+            return None;
+        }
+        let (body, rest) = data.split_at(size);
+        data = rest;
+        Some((id, body))
+    })
+}
+
+fn uint(data: &[u8]) -> Option<u64> {
+    (data.len() <= 8).then(|| be(data, 0, data.len()).unwrap_or(0))
+}
+
+fn matroska(file: &mut (impl Read + Seek)) -> io::Result<Option<Video>> {
+    let (_, Some(header)) = element(file)? else {
+        return Ok(None);
+    };
+    file.seek(SeekFrom::Current(header as i64))?;
+    if element(file)?.0 != SEGMENT {
+        return Ok(None);
+    }
+
+    // Info and Tracks come before the first Cluster of media data.
+    let (mut info, mut tracks) = (None, None);
+    while info.is_none() || tracks.is_none() {
+        let Ok((id, Some(size))) = element(file) else {
+            break;
+        };
+        let slot = match id {
+            CLUSTER => break,
+            INFO => &mut info,
+            TRACKS => &mut tracks,
+            _ => {
+                file.seek(SeekFrom::Current(size as i64))?;
+                continue;
+            }
+        };
+        if size > MAX_HEADER {
+            break;
+        }
+        let mut body = vec![0; size as usize];
+        file.read_exact(&mut body)?;
+        *slot = Some(body);
+    }
+
+    let Some(mut video) = tracks.as_deref().and_then(matroska_video_track) else {
+        return Ok(None);
+    };
+    if let Some(info) = info {
+        segment_info(&info, &mut video);
+    }
+    Ok(Some(video))
+}
+
+fn segment_info(info: &[u8], video: &mut Video) {
+    let mut scale = 1_000_000;
+    let mut duration = None;
+    for (id, body) in elements(info) {
+        match id {
+            TIMECODE_SCALE => scale = uint(body).unwrap_or(scale),
+            DURATION => {
+                duration = match body.len() {
+                    4 => Some(f32::from_be_bytes(body.try_into().unwrap()).into()),
+                    8 => Some(f64::from_be_bytes(body.try_into().unwrap())),
+                    _ => None,
+                }
+            }
+            DATE_UTC if body.len() == 8 => {
+                let nanos = i64::from_be_bytes(body.try_into().unwrap());
+                let epoch = Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).unwrap();
+                video.created = epoch.checked_add_signed(TimeDelta::nanoseconds(nanos));
+            }
+            _ => {}
+        }
+    }
+    video.duration = duration.map(|ticks: f64| ticks * scale as f64 / 1e9);
+}
+
+fn matroska_video_track(tracks: &[u8]) -> Option<Video> {
+    elements(tracks)
+        .filter(|(id, _)| *id == TRACK_ENTRY)
+        .find_map(|(_, entry)| {
+            let mut video = Video::default();
+            let mut is_video = false;
+            for (id, body) in elements(entry) {
+                match id {
+                    TRACK_TYPE => is_video = uint(body) == Some(1),
+                    CODEC_ID => {
+                        let id = String::from_utf8_lossy(body);
+                        video.codec = Some(codec_name(id.trim_end_matches('\0')));
+                    }
+                    VIDEO => {
+                        for (id, body) in elements(body) {
+                            let value = uint(body).and_then(|n| u32::try_from(n).ok());
+                            match id {
+                                PIXEL_WIDTH => video.width = value,
+                                PIXEL_HEIGHT => video.height = value,
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            is_video.then_some(video)
+        })
+}
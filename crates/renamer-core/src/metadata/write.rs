@@ -0,0 +1,267 @@
+//! Writing a file's new name back into it as its title, so music players and
+//! photo libraries show the same thing the folder does: the title tag of a
+//! song or video (ID3, Vorbis comments, MP4 and the rest `lofty` writes), and
+//! the EXIF `ImageDescription` and XMP `dc:title` of a JPEG.
+//!
+//! JPEGs are edited without re-encoding anything. The new EXIF directory is
+//! appended after the old one instead of replacing it, so every offset the
+//! camera wrote (maker notes, thumbnails) stays valid.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use super::audio;
+use crate::fsutil;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const APP0: u8 = 0xE0;
+const APP1: u8 = 0xE1;
+const START_OF_SCAN: u8 = 0xDA;
+const IMAGE_DESCRIPTION: u16 = 0x010E;
+const ASCII: u16 = 2;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Writes `title` into the file at `path`, keeping its modification time.
+/// Files that are neither something `lofty` can tag nor a JPEG are left
+/// alone.
+pub fn write_title(path: &Path, title: &str) -> io::Result<()> {
+    let modified = fs::metadata(fsutil::long(path))?.modified()?;
+    // JPEGs first: their signature also passes for an MPEG audio frame.
+    if is_jpeg(path)? {
+        write_jpeg(path, title)?;
+    } else if let Some(written) = audio::write_title(path, title) {
+        written?;
+    } else {
+        return Ok(());
+    }
+    fsutil::set_modified(path, modified)?;
+    Ok(())
+}
+
+fn is_jpeg(path: &Path) -> io::Result<bool> {
+    use std::io::Read;
+    let mut magic = [0; 3];
+    let mut file = fs::File::open(fsutil::long(path))?;
+    Ok(file.read_exact(&mut magic).is_ok() && magic == [0xFF, 0xD8, 0xFF])
+}
+
+fn write_jpeg(path: &Path, title: &str) -> io::Result<()> {
+    let data = fs::read(fsutil::long(path))?;
+    // Marker segments up to the image data, which is copied as it is.
+    let mut segments: Vec<(u8, Cow<[u8]>)> = Vec::new();
+    let mut pos = 2;
+    loop {
+        let marker = *data.get(pos + 1).ok_or_else(|| invalid("truncated JPEG"))?;
+        if data[pos] != 0xFF {
+            return Err(invalid("malformed JPEG"));
+        }
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == START_OF_SCAN {
+            break;
+        }
+        let len = data
+            .get(pos + 2..pos + 4)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .filter(|&len| len >= 2 && pos + 2 + len <= data.len())
+            .ok_or_else(|| invalid("malformed JPEG"))?;
+        segments.push((marker, Cow::Borrowed(&data[pos + 4..pos + 2 + len])));
+        pos += 2 + len;
+    }
+
+    let exif_at = segments
+        .iter()
+        .position(|(m, p)| *m == APP1 && p.starts_with(EXIF_HEADER));
+    let tiff = match exif_at {
+        Some(i) => segments[i].1[EXIF_HEADER.len()..].to_vec(),
+        None => b"II*\0\x08\0\0\0\0\0\0\0\0\0".to_vec(),
+    };
+    let tiff = describe(&tiff, title).ok_or_else(|| invalid("malformed EXIF data"))?;
+    let exif = Cow::Owned([EXIF_HEADER, &tiff].concat());
+
+    let xmp_at = segments
+        .iter()
+        .position(|(m, p)| *m == APP1 && p.starts_with(XMP_HEADER));
+    let packet = match xmp_at {
+        Some(i) => Some(
+            std::str::from_utf8(&segments[i].1[XMP_HEADER.len()..])
+                .map_err(|_| invalid("malformed XMP packet"))?,
+        ),
+        None => None,
+    };
+    let xmp = Cow::Owned([XMP_HEADER, xmp_with_title(packet, title).as_bytes()].concat());
+
+    // New segments go after the JFIF header, EXIF first as readers expect.
+    let first_free = segments.iter().take_while(|(m, _)| *m == APP0).count();
+    match exif_at {
+        Some(i) => segments[i].1 = exif,
+        None => segments.insert(first_free, (APP1, exif)),
+    }
+    match xmp_at {
+        Some(i) => segments[i].1 = xmp,
+        None => {
+            let after_exif = segments
+                .iter()
+                .position(|(m, p)| *m == APP1 && p.starts_with(EXIF_HEADER))
+                .map_or(first_free, |i| i + 1);
+            segments.insert(after_exif, (APP1, xmp));
+        }
+    }
+
+    let mut out = Vec::with_capacity(data.len() + title.len() * 2 + 512);
+    out.extend([0xFF, 0xD8]);
+    for (marker, payload) in &segments {
+        let len = u16::try_from(payload.len() + 2)
+            .map_err(|_| invalid("metadata does not fit in a JPEG segment"))?;
+        out.extend([0xFF, *marker]);
+        out.extend(len.to_be_bytes());
+        out.extend_from_slice(payload);
+    }
+    out.extend_from_slice(&data[pos..]);
+
+    let temp = fsutil::hidden_sibling(path, "title");
+    fs::write(fsutil::long(&temp), &out)?;
+    let permissions = fs::metadata(fsutil::long(path))?.permissions();
+    let replaced = fs::set_permissions(fsutil::long(&temp), permissions)
+        .and_then(|_| fs::rename(fsutil::long(&temp), fsutil::long(path)));
+    if replaced.is_err() {
+        let _ = fs::remove_file(fsutil::long(&temp));
+    }
+    replaced
+}
+
+/// `tiff` with a copy of its first directory appended that sets
+/// `ImageDescription`, and the header pointed at the copy.
+fn describe(tiff: &[u8], title: &str) -> Option<Vec<u8>> {
+    let big_endian = match tiff.get(..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let u16_at = |at: usize| -> Option<u16> {
+        let b: [u8; 2] = tiff.get(at..at + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let b: [u8; 4] = tiff.get(at..at + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    };
+    let put16 = |out: &mut Vec<u8>, v: u16| {
+        out.extend(if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        })
+    };
+    let put32 = |out: &mut Vec<u8>, v: u32| {
+        out.extend(if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    let mut entries: Vec<(u16, &[u8])> = (0..count)
+        .map(|i| {
+            let at = ifd + 2 + i * 12;
+            Some((u16_at(at)?, tiff.get(at..at + 12)?))
+        })
+        .collect::<Option<_>>()?;
+    let next = u32_at(ifd + 2 + count * 12)?;
+    entries.retain(|(tag, _)| *tag != IMAGE_DESCRIPTION);
+
+    let mut text = title.as_bytes().to_vec();
+    text.push(0);
+    let mut out = tiff.to_vec();
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let start = out.len();
+    let new_count = entries.len() + 1;
+    let text_at = start + 2 + new_count * 12 + 4;
+    let mut description = Vec::with_capacity(12);
+    put16(&mut description, IMAGE_DESCRIPTION);
+    put16(&mut description, ASCII);
+    put32(&mut description, u32::try_from(text.len()).ok()?);
+    if text.len() <= 4 {
+        description.extend(&text);
+        description.resize(12, 0);
+    } else {
+        put32(&mut description, u32::try_from(text_at).ok()?);
+    }
+
+    put16(&mut out, u16::try_from(new_count).ok()?);
+    let at = entries.partition_point(|(tag, _)| *tag < IMAGE_DESCRIPTION);
+    entries.insert(at, (IMAGE_DESCRIPTION, &description));
+    for (_, entry) in &entries {
+        out.extend_from_slice(entry);
+    }
+    put32(&mut out, next);
+    if text.len() > 4 {
+        out.extend(&text);
+    }
+    let mut header = Vec::with_capacity(4);
+    put32(&mut header, u32::try_from(start).ok()?);
+    out[4..8].copy_from_slice(&header);
+    Some(out)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `packet` with its `dc:title` replaced, or added in a description of its
+/// own. Without a packet, a new one holding only the title.
+fn xmp_with_title(packet: Option<&str>, title: &str) -> String {
+    static TITLE: OnceLock<Regex> = OnceLock::new();
+    let title = format!(
+        r#"<dc:title><rdf:Alt><rdf:li xml:lang="x-default">{}</rdf:li></rdf:Alt></dc:title>"#,
+        escape(title)
+    );
+    let description = format!(
+        r#"<rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">{}</rdf:Description>"#,
+        title
+    );
+    if let Some(packet) = packet {
+        let re = TITLE.get_or_init(|| {
+            Regex::new(r"(?s)<dc:title\b[^>]*/>|<dc:title\b[^>]*>.*?</dc:title>").unwrap()
+        });
+        if re.is_match(packet) {
+            return re.replace(packet, regex::NoExpand(&title)).into_owned();
+        }
+        if let Some(end) = packet.find("</rdf:RDF>") {
+            return format!("{}{}{}", &packet[..end], description, &packet[end..]);
+        }
+    }
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">{}</rdf:RDF>\
+         </x:xmpmeta><?xpacket end=\"w\"?>",
+        description
+    )
+}
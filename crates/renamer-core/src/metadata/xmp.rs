@@ -0,0 +1,85 @@
+//! The date a photo was taken as XMP records it: in a sidecar next to the
+//! file (`IMG_1.xmp` or `IMG_1.jpg.xmp`), where photo editors write a date
+//! corrected by hand, or else in the packet embedded in the file itself.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use super::document::{unescape, w3c_date, xmp_packet};
+use super::{MetaValue, Metadata};
+use crate::fsutil;
+
+/// How far into a file the embedded packet is looked for; images keep it
+/// near the start.
+const SCAN_BYTES: u64 = 256 * 1024;
+
+/// The properties that hold the date, the most telling first.
+const PROPERTIES: &[&str] = &[
+    "exif:DateTimeOriginal",
+    "photoshop:DateCreated",
+    "xmp:CreateDate",
+];
+
+pub(super) fn read(path: &Path, metadata: &mut Metadata) {
+    let date = sidecars(path)
+        .into_iter()
+        .find_map(|sidecar| fs::read(fsutil::long(&sidecar)).ok())
+        .and_then(|data| date(&data))
+        .or_else(|| embedded(path).and_then(|data| date(&data)));
+    if let Some(date) = date {
+        metadata.insert("xmp.date_taken", MetaValue::Date(date));
+    }
+}
+
+fn sidecars(path: &Path) -> Vec<PathBuf> {
+    let Some(name) = path.file_name() else {
+        return Vec::new();
+    };
+    let mut whole = name.to_os_string();
+    whole.push(".xmp");
+    vec![path.with_extension("xmp"), path.with_file_name(whole)]
+        .into_iter()
+        .filter(|sidecar| sidecar != path)
+        .collect()
+}
+
+fn embedded(path: &Path) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(fsutil::long(path))
+        .ok()?
+        .take(SCAN_BYTES)
+        .read_to_end(&mut data)
+        .ok()?;
+    Some(data)
+}
+
+/// The first of [`PROPERTIES`] the packet in `data` has, as an element or
+/// as an attribute.
+fn date(data: &[u8]) -> Option<NaiveDateTime> {
+    static PROPERTY: OnceLock<Regex> = OnceLock::new();
+    let property = PROPERTY.get_or_init(|| {
+        let names = PROPERTIES
+            .iter()
+            .map(|p| regex::escape(p))
+            .collect::<Vec<_>>()
+            .join("|");
+        Regex::new(&format!(r#"({})(?:\s*=\s*"([^"]*)"|>([^<]*)</)"#, names)).unwrap()
+    });
+    let packet = xmp_packet(data)?;
+    let found: Vec<(&str, NaiveDateTime)> = property
+        .captures_iter(packet)
+        .filter_map(|caps| {
+            let value = caps.get(2).or(caps.get(3))?.as_str();
+            Some((caps.get(1)?.as_str(), w3c_date(&unescape(value.trim()))?))
+        })
+        .collect();
+    PROPERTIES
+        .iter()
+        .find_map(|p| found.iter().find(|(name, _)| name == p))
+        .map(|(_, date)| *date)
+}
@@ -0,0 +1,116 @@
+//! Finding dates written into file names, in whatever form they were
+//! written, for the `{parsed_date}` template token.
+//!
+//! Recognised forms, with or without a time after them:
+//!
+//! - year first: `20240131`, `2024-01-31`, `2024_1_31`, `IMG_20240131_101500`
+//! - with a month name: `31 Jan 2024`, `31st-January-2024`, `Jan 31, 2024`
+//! - day first: `31.01.2024`, `31-1-2024`; month first only when the day
+//!   first reading is impossible (`01-31-2024`)
+//!
+//! Years must fall in 1900-2099 and dates must exist, which keeps serial
+//! numbers from being read as dates. The leftmost date in the name wins.
+
+use std::sync::OnceLock;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use regex::{Captures, Regex, RegexBuilder};
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// A date shape and how to read its captures.
+struct Form {
+    regex: Regex,
+    read: fn(&Captures) -> Option<NaiveDate>,
+}
+
+fn number(caps: &Captures, name: &str) -> Option<u32> {
+    caps.name(name)?.as_str().parse().ok()
+}
+
+fn month_name(caps: &Captures) -> Option<u32> {
+    let name = caps.name("month")?.as_str().to_ascii_lowercase();
+    let at = MONTHS.iter().position(|m| name.starts_with(m))?;
+    Some(at as u32 + 1)
+}
+
+fn ymd(caps: &Captures, month: u32, day: u32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(number(caps, "year")? as i32, month, day)
+}
+
+/// Time written right after the date: `_101500`, `T10:15`, ` 10.15.00`.
+const TIME: &str =
+    r"(?:[T_ -]?(?P<h>[01]\d|2[0-3])[-.:h]?(?P<m>[0-5]\d)(?:[-.:m]?(?P<s>[0-5]\d))?)?";
+const YEAR: &str = r"(?P<year>(?:19|20)\d{2})";
+const MONTH_NAME: &str = r"(?P<month>jan(?:uary)?|feb(?:ruary)?|mar(?:ch)?|apr(?:il)?|may|june?|july?|aug(?:ust)?|sep(?:t(?:ember)?)?|oct(?:ober)?|nov(?:ember)?|dec(?:ember)?)\.?";
+const ORDINAL: &str = r"(?:st|nd|rd|th)?";
+
+fn forms() -> &'static [Form] {
+    static FORMS: OnceLock<Vec<Form>> = OnceLock::new();
+    FORMS.get_or_init(|| {
+        let form = |pattern: String, read: fn(&Captures) -> Option<NaiveDate>| Form {
+            // Digits right before or after would make this part of a longer
+            // number.
+            regex: RegexBuilder::new(&format!(
+                r"(?:^|[^0-9])(?P<date>{}{})(?:[^0-9]|$)",
+                pattern, TIME
+            ))
+            .case_insensitive(true)
+            .build()
+            .unwrap(),
+            read,
+        };
+        vec![
+            form(format!(r"{}(?P<mo>\d{{2}})(?P<d>\d{{2}})", YEAR), |c| {
+                ymd(c, number(c, "mo")?, number(c, "d")?)
+            }),
+            form(
+                format!(r"{}[-_. ](?P<mo>\d{{1,2}})[-_. ](?P<d>\d{{1,2}})", YEAR),
+                |c| ymd(c, number(c, "mo")?, number(c, "d")?),
+            ),
+            form(
+                format!(
+                    r"(?P<d>\d{{1,2}}){}[-_. ]*{}[-_., ]*{}",
+                    ORDINAL, MONTH_NAME, YEAR
+                ),
+                |c| ymd(c, month_name(c)?, number(c, "d")?),
+            ),
+            form(
+                format!(
+                    r"{}[-_. ]*(?P<d>\d{{1,2}}){}[-_., ]*{}",
+                    MONTH_NAME, ORDINAL, YEAR
+                ),
+                |c| ymd(c, month_name(c)?, number(c, "d")?),
+            ),
+            form(
+                format!(r"(?P<d>\d{{1,2}})[-_./ ](?P<mo>\d{{1,2}})[-_./ ]{}", YEAR),
+                |c| {
+                    let (first, second) = (number(c, "d")?, number(c, "mo")?);
+                    ymd(c, second, first).or_else(|| ymd(c, first, second))
+                },
+            ),
+        ]
+    })
+}
+
+/// The leftmost date written in `name`, with the time if one follows it.
+pub fn find(name: &str) -> Option<NaiveDateTime> {
+    forms()
+        .iter()
+        .filter_map(|form| {
+            form.regex.captures_iter(name).find_map(|caps| {
+                let date = (form.read)(&caps)?;
+                let time = match (number(&caps, "h"), number(&caps, "m")) {
+                    (Some(h), Some(m)) => {
+                        NaiveTime::from_hms_opt(h, m, number(&caps, "s").unwrap_or(0))?
+                    }
+                    _ => NaiveTime::MIN,
+                };
+                Some((caps.name("date")?.start(), date.and_time(time)))
+            })
+        })
+        .min_by_key(|(start, _)| *start)
+        .map(|(_, date)| date)
+}
@@ -0,0 +1,106 @@
+//! Natural ordering of names: runs of digits compare by their value, so
+//! `file2` comes before `file10`, the way people number files.
+//!
+//! Letters compare without their accents first, so `é` sorts with `e`
+//! rather than after `z`, and accents only decide between otherwise equal
+//! names. This is the ordering the scanner lists files in and that counters
+//! are given out in.
+
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// A piece of a name: a digit run, or a character with accents removed.
+enum Chunk<'a> {
+    Number(&'a str),
+    Char(char),
+}
+
+impl Chunk<'_> {
+    /// Numbers compare by value and sort where their first digit would
+    /// among other characters.
+    fn cmp(&self, other: &Chunk) -> Ordering {
+        match (self, other) {
+            (Chunk::Number(a), Chunk::Number(b)) => cmp_numbers(a, b),
+            (Chunk::Number(_), Chunk::Char(c)) => '0'.cmp(c).then(Ordering::Less),
+            (Chunk::Char(c), Chunk::Number(_)) => c.cmp(&'0').then(Ordering::Greater),
+            (Chunk::Char(a), Chunk::Char(b)) => a.cmp(b),
+        }
+    }
+}
+
+fn chunks(name: &str, ignore_case: bool) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut rest = name;
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            chunks.push(Chunk::Number(&rest[..end]));
+            rest = &rest[end..];
+        } else {
+            for c in std::iter::once(c).nfd().filter(|&c| !is_combining_mark(c)) {
+                if ignore_case {
+                    chunks.extend(c.to_lowercase().map(Chunk::Char));
+                } else {
+                    chunks.push(Chunk::Char(c));
+                }
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    chunks
+}
+
+/// By value: without leading zeros, the shorter run is the smaller number.
+fn cmp_numbers(a: &str, b: &str) -> Ordering {
+    let (x, y) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+    x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+}
+
+/// Compares two names naturally. With `ignore_case`, case only decides
+/// between names that are otherwise equal, so the order stays total.
+pub fn natural_cmp(a: &str, b: &str, ignore_case: bool) -> Ordering {
+    let (x, y) = (chunks(a, ignore_case), chunks(b, ignore_case));
+    for (p, q) in x.iter().zip(&y) {
+        let order = p.cmp(q);
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    // A prefix comes first. Names equal so far differ in leading zeros,
+    // accents or case, which plain order settles.
+    x.len().cmp(&y.len()).then_with(|| a.cmp(b))
+}
+
+/// Compares two paths folder by folder, each name naturally and ignoring
+/// case.
+pub fn natural_path_cmp(a: &Path, b: &Path) -> Ordering {
+    let mut x = a.components();
+    let mut y = b.components();
+    loop {
+        match (x.next(), y.next()) {
+            (Some(p), Some(q)) => {
+                let order = natural_cmp(
+                    &p.as_os_str().to_string_lossy(),
+                    &q.as_os_str().to_string_lossy(),
+                    true,
+                );
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+        }
+    }
+}
+
+/// Sorts `paths` into natural order, see [`natural_path_cmp`].
+pub fn sort_natural(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| natural_path_cmp(a, b));
+}
@@ -0,0 +1,46 @@
+//! Unicode normalization of file names.
+//!
+//! macOS tends to store names decomposed (NFD, `e` + combining acute) while
+//! Windows and Linux tools almost always produce composed names (NFC, a single
+//! `é`). Both look identical but compare, sort and match differently.
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{is_nfc, is_nfd, UnicodeNormalization};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalForm {
+    /// Composed; what Windows and Linux expect.
+    Nfc,
+    /// Decomposed; what HFS+ stores.
+    Nfd,
+}
+
+pub fn normalize(s: &str, form: NormalForm) -> String {
+    match form {
+        NormalForm::Nfc => s.nfc().collect(),
+        NormalForm::Nfd => s.nfd().collect(),
+    }
+}
+
+/// How a name that has composable characters is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    Composed,
+    Decomposed,
+    /// Parts of the name are composed and parts decomposed, typically after
+    /// renaming a macOS file on another platform.
+    Mixed,
+}
+
+/// Classifies `name`, or returns `None` when both forms are identical (e.g.
+/// plain ASCII) and there is nothing to report.
+pub fn detect(name: &str) -> Option<Normalization> {
+    match (is_nfc(name), is_nfd(name)) {
+        (true, true) => None,
+        (true, false) => Some(Normalization::Composed),
+        (false, true) => Some(Normalization::Decomposed),
+        (false, false) => Some(Normalization::Mixed),
+    }
+}
@@ -0,0 +1,98 @@
+//! Text recognized in scanned documents, for the `{ocr.first_line}` and
+//! `{ocr.match(REGEX)}` template tokens, so an invoice can be named after
+//! the number printed on it.
+//!
+//! Recognition is left to an [`OcrEngine`]. The one used unless another is
+//! set with [`set_engine`] is an [`OcrCommand`] running `tesseract`, which
+//! reads images (PNG, JPEG, TIFF) but not PDFs; a command that can, such as
+//! a script around `ocrmypdf`, can take its place, as can an engine built
+//! on native bindings. A file is read once per preview, and a file the
+//! engine cannot read renders its tokens empty, or with their fallback.
+
+use std::path::Path;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::CancelToken;
+use crate::post_action::{self, PostAction};
+
+/// Something that reads the text of a scanned page.
+pub trait OcrEngine: Send + Sync {
+    /// The text in `path`, one line of print per line, or why there is
+    /// none.
+    fn recognize(&self, path: &Path) -> Result<String, String>;
+}
+
+/// A program that prints the text of the file it is given, run directly,
+/// not through a shell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OcrCommand {
+    /// The program, found on `PATH` unless a path is given.
+    pub program: String,
+    /// Its arguments; `{path}` is the file's path.
+    pub args: Vec<String>,
+    /// How long it gets for one file before it is killed.
+    pub timeout_secs: u64,
+}
+
+impl Default for OcrCommand {
+    fn default() -> Self {
+        OcrCommand {
+            program: "tesseract".into(),
+            args: vec!["{path}".into(), "stdout".into()],
+            timeout_secs: 60,
+        }
+    }
+}
+
+impl OcrEngine for OcrCommand {
+    fn recognize(&self, path: &Path) -> Result<String, String> {
+        let path_text = path.display().to_string();
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.replace("{path}", &path_text))
+            .collect();
+        let action = PostAction {
+            timeout_secs: self.timeout_secs,
+            ..PostAction::new(&self.program, Vec::new())
+        };
+        let run = post_action::execute(&action, Some(path), args, None, &CancelToken::new());
+        if let Some(error) = run.error {
+            return Err(error);
+        }
+        if !run.succeeded() {
+            let stderr = run.stderr.trim();
+            return Err(match run.exit_code {
+                Some(code) if stderr.is_empty() => format!("{} exited with {}", self.program, code),
+                _ => stderr.to_string(),
+            });
+        }
+        Ok(run.stdout)
+    }
+}
+
+fn engine() -> &'static RwLock<Option<Arc<dyn OcrEngine>>> {
+    static ENGINE: OnceLock<RwLock<Option<Arc<dyn OcrEngine>>>> = OnceLock::new();
+    ENGINE.get_or_init(|| RwLock::new(Some(Arc::new(OcrCommand::default()))))
+}
+
+/// Makes `engine` the one `{ocr.*}` tokens read files with from now on;
+/// `None` turns recognition off, leaving the tokens empty.
+pub fn set_engine(new: Option<Arc<dyn OcrEngine>>) {
+    *engine().write().unwrap_or_else(|e| e.into_inner()) = new;
+}
+
+/// The text the current engine reads in `path`, or `None` if there is no
+/// engine or it failed.
+pub fn recognize(path: &Path) -> Option<String> {
+    let engine = engine().read().unwrap_or_else(|e| e.into_inner()).clone()?;
+    engine.recognize(path).ok()
+}
+
+/// The first line of `text` with anything on it, trimmed.
+pub(crate) fn first_line(text: &str) -> Option<&str> {
+    text.lines().map(str::trim).find(|line| !line.is_empty())
+}
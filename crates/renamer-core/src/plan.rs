@@ -0,0 +1,946 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::burst::BurstOptions;
+use crate::error::Result;
+use crate::fsutil;
+use crate::identity::FileId;
+use crate::policy::{self, Checker, NamingPolicy, PolicyViolation};
+use crate::rules::split_name;
+use crate::sanitize::{self, TargetFs};
+use crate::volume::{Volume, Volumes};
+
+/// A single requested move of `source` to `target` (both full paths).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenameOp {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// Modification time to give the file once it is in place. With this
+    /// set, `source` and `target` may be the same.
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>,
+    /// Title to write into the file once it is in place, see
+    /// [`crate::metadata::write_title`]. Also allows `source == target`.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// The rules cut the new name to fit a length, see
+    /// [`crate::Rule::Truncate`]; the entry is flagged with
+    /// [`PlanWarning::Shortened`].
+    #[serde(default)]
+    pub shortened: bool,
+}
+
+impl RenameOp {
+    pub fn new(source: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        RenameOp {
+            source: source.into(),
+            target: target.into(),
+            modified: None,
+            title: None,
+            shortened: false,
+        }
+    }
+}
+
+/// What the planner does when a target is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionStrategy {
+    /// Leave conflicting entries out of the batch.
+    #[default]
+    Skip,
+    /// Pick the first free `name (n).ext`, like the sidecar's `get_unique_path`.
+    Suffix,
+    /// Replace the existing file. Applying such a plan needs explicit
+    /// confirmation; in-batch duplicates are still skipped, and so is
+    /// anything where the source or the existing target is a folder.
+    Overwrite,
+    /// Refuse to apply the batch if anything conflicts.
+    Fail,
+}
+
+/// What the planner does with sources that are symbolic links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Rename the link itself and leave the file it points to alone.
+    #[default]
+    Link,
+    /// Rename the file the link points to (keeping it in its own folder) and
+    /// re-point the link at the new name.
+    Target,
+    /// Leave links out of the batch.
+    Skip,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlanOptions {
+    pub collision: CollisionStrategy,
+    pub symlinks: SymlinkPolicy,
+    /// Takes every target to be on a volume with these naming rules. By
+    /// default each target is checked against the volume it is on, as
+    /// [`crate::volume::probe`] finds it.
+    pub filesystem: Option<TargetFs>,
+    /// On filesystems that reserve device names, turn a target like `CON.txt`
+    /// into `CON_.txt` instead of rejecting the entry.
+    pub fix_reserved_names: bool,
+    /// Files that move along with another file of the same name, such as a
+    /// raw photo's `.xmp` or a movie's `.srt`.
+    pub companions: Vec<CompanionRule>,
+    /// Bursts and copies to number once, found before the rules run, for
+    /// presets and the app's preview; see [`crate::preview_bursts`].
+    pub bursts: BurstOptions,
+    /// A naming policy the new names must follow, on top of the one the
+    /// settings enforce; see [`crate::policy`].
+    pub policy: Option<NamingPolicy>,
+}
+
+/// Which files bring their companions along. A companion starts with the
+/// same stem and ends in one of `companions`: next to `IMG_001.CR3`, both
+/// `IMG_001.xmp` and `IMG_001.CR3.xmp` count with `xmp`, and next to
+/// `movie.mkv`, `movie.en.srt` counts with `srt`.
+///
+/// Companions found in a source's folder are added to the plan as entries
+/// of their own, after the requested ones, and take the primary file's new
+/// name (suffix included) with their own extension. If any file of a group
+/// cannot be renamed, the others stay put too, as
+/// [`EntryStatus::CompanionNotReady`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompanionRule {
+    /// Extensions, without the dot and in any case, of the files being
+    /// renamed, e.g. `cr3` or `mkv`.
+    pub primary: Vec<String>,
+    /// Extensions of the files that follow them, e.g. `xmp`, `jpg` or `srt`.
+    pub companions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    /// Will be renamed when the plan is applied.
+    Ready,
+    /// Source and target are identical; nothing to do.
+    Unchanged,
+    /// The source file no longer exists, or is a link to nothing.
+    MissingSource,
+    /// The source is a symbolic link and links are skipped.
+    Symlink,
+    /// The target path or one of its names exceeds what the filesystem can
+    /// store.
+    PathTooLong,
+    /// The source is a folder and the target lies inside it.
+    IntoItself,
+    /// The target name is a device name such as `CON` or `LPT1.txt`, which
+    /// the target filesystem cannot store.
+    ReservedName,
+    /// The target name has a character the target filesystem cannot store,
+    /// such as `:` on exFAT, or ends in a dot or space where that is refused.
+    UnsupportedName,
+    /// Something already lives at the target path.
+    TargetExists,
+    /// Another entry in the same batch maps to this target.
+    DuplicateTarget,
+    /// Another file of its companion group cannot be renamed, see
+    /// [`CompanionRule`].
+    CompanionNotReady,
+    /// The new name breaks a naming policy, see
+    /// [`PlanEntry::policy_violations`]. The plan is blocked.
+    PolicyViolation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    TargetExists,
+    DuplicateTarget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// First of several duplicates; it keeps the requested name and the
+    /// others are suffixed.
+    Kept,
+    Skipped,
+    Suffixed,
+    Overwrite,
+    Failed,
+}
+
+/// Something about an entry that is allowed but worth a look. Applying a plan
+/// that changes extensions needs explicit confirmation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanWarning {
+    /// The file's extension changes, e.g. `.jpg` to `.png`. Only the case
+    /// changing does not count.
+    ExtensionChanged,
+    /// The file had an extension and the new name has none.
+    ExtensionRemoved,
+    /// The requested name was a reserved device name and was extended, see
+    /// [`PlanOptions::fix_reserved_names`].
+    ReservedNameFixed,
+    /// The rules cut the name to fit a length limit.
+    Shortened,
+    /// Another entry of the batch is this same file under another name: the
+    /// two are hard links, see [`PlanEntry::same_file_as`].
+    HardLink,
+}
+
+/// How much a [`Diagnostic`] matters, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Nothing to do, or a change made to fit the filesystem.
+    Info,
+    /// Will be renamed, but not quite as asked or with a side effect.
+    Warning,
+    /// Cannot be renamed.
+    Error,
+}
+
+/// One finding about a plan entry, for tools that decide what to apply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable name of the finding, e.g. `target_exists` or
+    /// `extension_changed`: the [`EntryStatus`], [`PlanWarning`] or
+    /// [`Resolution`] it comes from, in snake case.
+    pub code: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, code: &str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// The diagnostics of `entry`, worst first.
+fn diagnose(entry: &PlanEntry) -> Vec<Diagnostic> {
+    use Severity::*;
+    let mut found = Vec::new();
+    let status = match entry.status {
+        EntryStatus::Ready => None,
+        EntryStatus::Unchanged => Some((Info, "unchanged", "the name does not change")),
+        EntryStatus::Symlink => Some((Info, "symlink", "links are left alone")),
+        EntryStatus::MissingSource => Some((Error, "missing_source", "the file does not exist")),
+        EntryStatus::PathTooLong => Some((
+            Error,
+            "path_too_long",
+            "the new path is too long for the filesystem",
+        )),
+        EntryStatus::IntoItself => {
+            Some((Error, "into_itself", "a folder cannot be moved into itself"))
+        }
+        EntryStatus::ReservedName => Some((
+            Error,
+            "reserved_name",
+            "the new name is reserved by the filesystem",
+        )),
+        EntryStatus::UnsupportedName => Some((
+            Error,
+            "unsupported_name",
+            "the new name has a character the filesystem cannot store",
+        )),
+        EntryStatus::TargetExists => {
+            Some((Error, "target_exists", "a file already has the new name"))
+        }
+        EntryStatus::DuplicateTarget => Some((
+            Error,
+            "duplicate_target",
+            "another file of the batch gets the same name",
+        )),
+        EntryStatus::CompanionNotReady => Some((
+            Error,
+            "companion_not_ready",
+            "a file that moves with this one cannot be renamed",
+        )),
+        EntryStatus::PolicyViolation => None,
+    };
+    found.extend(status.map(|(severity, code, message)| Diagnostic::new(severity, code, message)));
+    for violation in &entry.policy_violations {
+        found.push(Diagnostic::new(
+            Error,
+            "policy_violation",
+            format!("the new name breaks the naming policy: {}", violation),
+        ));
+    }
+    match entry.conflict.as_ref().map(|c| c.resolution) {
+        Some(Resolution::Suffixed) => found.push(Diagnostic::new(
+            Warning,
+            "suffixed",
+            "the name was taken, so a number was added",
+        )),
+        Some(Resolution::Overwrite) => found.push(Diagnostic::new(
+            Warning,
+            "overwrite",
+            "the file already at the new path will be replaced",
+        )),
+        _ => {}
+    }
+    for warning in &entry.warnings {
+        found.push(match warning {
+            PlanWarning::ExtensionChanged => {
+                Diagnostic::new(Warning, "extension_changed", "the extension changes")
+            }
+            PlanWarning::ExtensionRemoved => {
+                Diagnostic::new(Warning, "extension_removed", "the extension is removed")
+            }
+            PlanWarning::ReservedNameFixed => Diagnostic::new(
+                Info,
+                "reserved_name_fixed",
+                "the name was extended because the filesystem reserves it",
+            ),
+            PlanWarning::Shortened => {
+                Diagnostic::new(Warning, "shortened", "the name was shortened to fit")
+            }
+            PlanWarning::HardLink => Diagnostic::new(
+                Warning,
+                "hard_link",
+                "another file of the batch is the same file under another name",
+            ),
+        });
+    }
+    if let Some((from, to)) = folder_change(&entry.source, &entry.target) {
+        found.push(Diagnostic::new(
+            Info,
+            "folder_changed",
+            format!("moves from `{}` to `{}`", from, to),
+        ));
+    }
+    found.sort_by_key(|d| std::cmp::Reverse(d.severity));
+    found
+}
+
+/// The folders `source` and `target` are in, below the folder they share,
+/// if they differ; `.` for the shared folder itself.
+fn folder_change(source: &Path, target: &Path) -> Option<(String, String)> {
+    let (from, to) = (source.parent()?, target.parent()?);
+    if from == to {
+        return None;
+    }
+    let shared = from.ancestors().find(|a| to.starts_with(a))?;
+    let below = |dir: &Path| match dir.strip_prefix(shared) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel.display().to_string(),
+        _ => ".".to_string(),
+    };
+    Some((below(from), below(to)))
+}
+
+/// How a collision on this entry was detected and resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub resolution: Resolution,
+    /// The target the entry asked for before resolution changed it.
+    pub requested_target: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub id: usize,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub status: EntryStatus,
+    pub conflict: Option<Conflict>,
+    /// The source is a folder. Everything inside moves with it.
+    pub is_dir: bool,
+    /// Modification time set on the file after the move.
+    pub modified: Option<DateTime<Utc>>,
+    /// The modification time the source had when the plan was made, which
+    /// [`crate::ApplyOptions::recheck`] compares against.
+    #[serde(default)]
+    pub source_modified: Option<DateTime<Utc>>,
+    /// Title written into the file once the batch is committed.
+    pub title: Option<String>,
+    pub warnings: Vec<PlanWarning>,
+    /// With [`SymlinkPolicy::Target`], the link that pointed at `source`; it
+    /// is re-pointed at `target` once the file has moved.
+    pub link: Option<PathBuf>,
+    /// For an entry added by [`PlanOptions::companions`], the id of the file
+    /// it follows.
+    pub companion_of: Option<usize>,
+    /// The id of the first entry whose source is this entry's file under
+    /// another name, a hard link. Each name is renamed on its own, but the
+    /// modification time and title that one sets are not set again here.
+    #[serde(default)]
+    pub same_file_as: Option<usize>,
+    /// What the new name breaks of the naming policies the plan was
+    /// checked against.
+    #[serde(default)]
+    pub policy_violations: Vec<PolicyViolation>,
+    /// What the status, conflict and warnings mean, with a severity each.
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl PlanEntry {
+    pub fn is_ready(&self) -> bool {
+        self.status == EntryStatus::Ready
+    }
+
+    /// The worst severity among the entry's diagnostics, if it has any.
+    pub fn severity(&self) -> Option<Severity> {
+        self.diagnostics.iter().map(|d| d.severity).max()
+    }
+
+    pub fn overwrites(&self) -> bool {
+        self.is_ready()
+            && matches!(&self.conflict, Some(c) if c.resolution == Resolution::Overwrite)
+    }
+
+    /// True when the entry will be renamed and changes its extension.
+    pub fn changes_extension(&self) -> bool {
+        self.is_ready()
+            && self.warnings.iter().any(|w| {
+                matches!(
+                    w,
+                    PlanWarning::ExtensionChanged | PlanWarning::ExtensionRemoved
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenamePlan {
+    pub entries: Vec<PlanEntry>,
+    /// Set by [`CollisionStrategy::Fail`] when any entry conflicts, and when
+    /// a new name breaks a naming policy; a blocked plan is never applied.
+    pub blocked: bool,
+    /// The volumes the targets are on, as they were checked against.
+    #[serde(default)]
+    pub volumes: Vec<Volume>,
+}
+
+impl RenamePlan {
+    /// Entries that will actually be renamed.
+    pub fn ready(&self) -> impl Iterator<Item = &PlanEntry> {
+        self.entries.iter().filter(|e| e.is_ready())
+    }
+
+    /// True when any entry ran into a collision, however it was resolved.
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|e| e.conflict.is_some())
+    }
+
+    /// True when applying would replace existing files.
+    pub fn overwrites(&self) -> bool {
+        self.entries.iter().any(PlanEntry::overwrites)
+    }
+
+    /// True when applying would change or strip file extensions.
+    pub fn changes_extensions(&self) -> bool {
+        self.entries.iter().any(PlanEntry::changes_extension)
+    }
+}
+
+/// `dir/stem (n).ext`, matching the sidecar's naming for duplicates.
+fn suffixed(target: &Path, n: usize) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (stem, ext) = crate::rules::split_name(&name);
+    target.with_file_name(format!("{} ({}){}", stem, n, ext))
+}
+
+/// Compares the extensions of a file's old and new name. Folders have no
+/// extensions to protect.
+fn extension_warnings(source: &Path, target: &Path, is_dir: bool) -> Vec<PlanWarning> {
+    let ext = |path: &Path| {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        crate::rules::split_name(&name).1.to_lowercase()
+    };
+    let (old, new) = (ext(source), ext(target));
+    if is_dir || old.is_empty() || old == new {
+        Vec::new()
+    } else if new.is_empty() {
+        vec![PlanWarning::ExtensionRemoved]
+    } else {
+        vec![PlanWarning::ExtensionChanged]
+    }
+}
+
+fn name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// An op added to follow another, and how its name derives from the
+/// primary file's.
+struct Companion {
+    primary: usize,
+    /// What the name has after the primary's stem, e.g. `.CR3.xmp`.
+    tail: String,
+}
+
+impl Companion {
+    fn target(&self, primary_target: &Path) -> PathBuf {
+        let name = name_of(primary_target);
+        primary_target.with_file_name(format!("{}{}", split_name(&name).0, self.tail))
+    }
+}
+
+/// Appends an op for every companion of `ops` that is not part of the batch
+/// already. The result says, for each op, which one it follows.
+fn add_companions(ops: &mut Vec<RenameOp>, rules: &[CompanionRule]) -> Vec<Option<Companion>> {
+    let mut companions: Vec<Option<Companion>> = ops.iter().map(|_| None).collect();
+    if rules.is_empty() {
+        return companions;
+    }
+    let lower = |list: &[String]| -> Vec<String> {
+        list.iter()
+            .map(|e| e.trim_start_matches('.').to_lowercase())
+            .collect()
+    };
+    let mut taken: HashSet<PathBuf> = ops.iter().map(|op| op.source.clone()).collect();
+    let mut listings: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut added = Vec::new();
+    for (primary, op) in ops.iter().enumerate() {
+        if op.source == op.target || fsutil::is_symlink(&op.source) || fsutil::is_dir(&op.source) {
+            continue;
+        }
+        let name = name_of(&op.source);
+        let (stem, ext) = split_name(&name);
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        let wanted: Vec<String> = rules
+            .iter()
+            .filter(|r| lower(&r.primary).contains(&ext))
+            .flat_map(|r| lower(&r.companions))
+            .collect();
+        let Some(dir) = op.source.parent().filter(|_| !wanted.is_empty()) else {
+            continue;
+        };
+        let listing = listings.entry(dir.to_path_buf()).or_insert_with(|| {
+            fs::read_dir(fsutil::long(dir))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect()
+        });
+        for file in listing.iter() {
+            let path = dir.join(file);
+            let Some(tail) = file.get(stem.len()..).filter(|t| t.starts_with('.')) else {
+                continue;
+            };
+            let last = tail.rsplit('.').next().unwrap_or_default().to_lowercase();
+            if file[..stem.len()].to_lowercase() != stem.to_lowercase() || !wanted.contains(&last) {
+                continue;
+            }
+            let companion = Companion {
+                primary,
+                tail: tail.to_string(),
+            };
+            if taken.insert(path.clone()) {
+                let target = companion.target(&op.target);
+                added.push((RenameOp::new(path, target), companion));
+            }
+        }
+    }
+    for (op, companion) in added {
+        ops.push(op);
+        companions.push(Some(companion));
+    }
+    companions
+}
+
+/// An op after symlink handling, before any collision checks.
+struct Prepared {
+    op: RenameOp,
+    link: Option<PathBuf>,
+    /// Set when the op is settled without looking any further.
+    status: Option<EntryStatus>,
+    /// The target was changed away from a reserved device name.
+    renamed_reserved: bool,
+}
+
+impl Prepared {
+    /// True if the op will try to move its source, so that path is vacated.
+    fn moves(&self) -> bool {
+        self.status.is_none() && self.op.source != self.op.target && fsutil::exists(&self.op.source)
+    }
+}
+
+fn prepare(op: RenameOp, options: &PlanOptions, volumes: &mut Volumes) -> Prepared {
+    let mut prepared = follow_links(op, options.symlinks);
+    let moves = prepared.op.source != prepared.op.target;
+    if prepared.status.is_some() || !moves {
+        return prepared;
+    }
+    let volume = volumes.of(&prepared.op.target);
+    let mut name = name_of(&prepared.op.target);
+    if volume.rules.reserves_device_names() {
+        if options.fix_reserved_names {
+            if let Some(fixed) = sanitize::fix_device_name(&name, "_") {
+                prepared.op.target.set_file_name(&fixed);
+                prepared.renamed_reserved = true;
+                name = fixed;
+            }
+        } else if sanitize::is_device_name(&name) {
+            prepared.status = Some(EntryStatus::ReservedName);
+            return prepared;
+        }
+    }
+    if volume.refused_char(&name).is_some() {
+        prepared.status = Some(EntryStatus::UnsupportedName);
+    }
+    prepared
+}
+
+fn follow_links(op: RenameOp, policy: SymlinkPolicy) -> Prepared {
+    let settled = |op, status| Prepared {
+        op,
+        link: None,
+        status: Some(status),
+        renamed_reserved: false,
+    };
+    if policy == SymlinkPolicy::Link || !fsutil::is_symlink(&op.source) {
+        return Prepared {
+            op,
+            link: None,
+            status: None,
+            renamed_reserved: false,
+        };
+    }
+    match policy {
+        SymlinkPolicy::Skip => settled(op, EntryStatus::Symlink),
+        _ => match fsutil::link_target(&op.source).filter(|t| fsutil::exists(t)) {
+            Some(real) => {
+                let target = match op.target.file_name() {
+                    Some(name) => real.with_file_name(name),
+                    None => real.clone(),
+                };
+                Prepared {
+                    op: RenameOp {
+                        modified: op.modified,
+                        title: op.title,
+                        shortened: op.shortened,
+                        ..RenameOp::new(real, target)
+                    },
+                    link: Some(op.source),
+                    status: None,
+                    renamed_reserved: false,
+                }
+            }
+            None => settled(op, EntryStatus::MissingSource),
+        },
+    }
+}
+
+/// Validates a batch of operations against the filesystem without touching it,
+/// resolving collisions according to `options`.
+///
+/// A target that exists but is the source of another entry in the batch is not
+/// a conflict, because that file moves away first; this is what lets swaps and
+/// rotations (`a→b`, `b→a`) through. If the entry moving it away ends up not
+/// ready, the target counts as occupied after all and the batch is re-planned.
+///
+/// Folders can be renamed in the same batch as files inside them. All paths
+/// refer to the tree as it is before the batch: `photos/a.jpg → photos/b.jpg`
+/// together with `photos → pictures` leaves the file at `pictures/b.jpg`.
+pub fn plan(ops: Vec<RenameOp>, options: &PlanOptions) -> RenamePlan {
+    let mut ops = ops;
+    let companions = add_companions(&mut ops, &options.companions);
+    let mut volumes = Volumes::new(options.filesystem);
+    let ops: Vec<Prepared> = ops
+        .into_iter()
+        .map(|op| prepare(op, options, &mut volumes))
+        .collect();
+    let mut vacating: HashSet<PathBuf> = ops
+        .iter()
+        .filter(|p| p.moves())
+        .map(|p| p.op.source.clone())
+        .collect();
+    let same_file = hard_links(&ops, &mut volumes);
+    let policies: Vec<Result<Checker>> = policy::enforced_checker()
+        .into_iter()
+        .chain(options.policy.iter().map(NamingPolicy::checker))
+        .collect();
+
+    loop {
+        let mut entries = plan_pass(
+            &ops,
+            &companions,
+            &same_file,
+            options,
+            &policies,
+            &vacating,
+            &mut volumes,
+        );
+        let stuck: Vec<PathBuf> = entries
+            .iter()
+            .filter(|e| !e.is_ready() && vacating.contains(&e.source))
+            .map(|e| e.source.clone())
+            .collect();
+        let relied_on = stuck
+            .iter()
+            .any(|s| entries.iter().any(|e| e.is_ready() && &e.target == s));
+        if !relied_on {
+            let blocked = options.collision == CollisionStrategy::Fail
+                && entries.iter().any(|e| e.conflict.is_some())
+                || entries
+                    .iter()
+                    .any(|e| e.status == EntryStatus::PolicyViolation);
+            for entry in &mut entries {
+                entry.diagnostics = diagnose(entry);
+            }
+            return RenamePlan {
+                entries,
+                blocked,
+                volumes: volumes.into_vec(),
+            };
+        }
+        for source in stuck {
+            vacating.remove(&source);
+        }
+    }
+}
+
+/// For each op whose source has another name in the batch, the first op
+/// with that file; that op's own entry included. Two spellings of one name,
+/// where case does not count, are not two names.
+fn hard_links(ops: &[Prepared], volumes: &mut Volumes) -> Vec<Option<usize>> {
+    let ids: Vec<Option<FileId>> = ops
+        .iter()
+        .map(|p| {
+            let file = p.status.is_none() && !fsutil::is_dir(&p.op.source);
+            file.then(|| FileId::of(&p.op.source)).flatten()
+        })
+        .collect();
+    let mut first: HashMap<FileId, usize> = HashMap::new();
+    let mut names: HashMap<FileId, HashSet<PathBuf>> = HashMap::new();
+    for (idx, id) in ids.iter().enumerate() {
+        if let Some(id) = id {
+            let source = &ops[idx].op.source;
+            first.entry(*id).or_insert(idx);
+            names
+                .entry(*id)
+                .or_default()
+                .insert(volumes.of(source).fold(source));
+        }
+    }
+    ids.iter()
+        .map(|id| {
+            let id = id.as_ref()?;
+            (names[id].len() > 1).then(|| first[id])
+        })
+        .collect()
+}
+
+fn plan_pass(
+    ops: &[Prepared],
+    companions: &[Option<Companion>],
+    same_file: &[Option<usize>],
+    options: &PlanOptions,
+    policies: &[Result<Checker>],
+    vacating: &HashSet<PathBuf>,
+    volumes: &mut Volumes,
+) -> Vec<PlanEntry> {
+    // Targets as their volumes compare them, so `A.jpg` and `a.jpg` are one
+    // where case does not count.
+    let mut target_counts: HashMap<PathBuf, usize> = HashMap::new();
+    for prepared in ops.iter().filter(|p| p.moves()) {
+        let target = &prepared.op.target;
+        *target_counts
+            .entry(volumes.of(target).fold(target))
+            .or_default() += 1;
+    }
+    let occupied = |path: &Path| fsutil::exists(path) && !vacating.contains(path);
+
+    // Targets handed out so far, folded like `target_counts`, so suffixed
+    // names never collide with each other.
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    let mut entries: Vec<PlanEntry> = Vec::with_capacity(ops.len());
+
+    for (id, prepared) in ops.iter().enumerate() {
+        let mut entry = PlanEntry {
+            id,
+            source: prepared.op.source.clone(),
+            target: prepared.op.target.clone(),
+            status: EntryStatus::Ready,
+            conflict: None,
+            is_dir: fsutil::is_dir(&prepared.op.source),
+            modified: prepared.op.modified,
+            source_modified: fs::symlink_metadata(fsutil::long(&prepared.op.source))
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from),
+            title: prepared.op.title.clone(),
+            warnings: Vec::new(),
+            link: prepared.link.clone(),
+            companion_of: companions[id].as_ref().map(|c| c.primary),
+            same_file_as: None,
+            policy_violations: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        // A companion goes wherever its primary file actually goes.
+        let mut collision = options.collision;
+        if let Some(companion) = &companions[id] {
+            let primary = &entries[companion.primary];
+            if !primary.is_ready() {
+                entry.status = EntryStatus::CompanionNotReady;
+                entries.push(entry);
+                continue;
+            }
+            entry.target = companion.target(&primary.target);
+            // Suffixing a companion on its own would split the group up.
+            if collision == CollisionStrategy::Suffix {
+                collision = CollisionStrategy::Skip;
+            }
+        }
+        entry.warnings = extension_warnings(&entry.source, &entry.target, entry.is_dir);
+        if prepared.renamed_reserved {
+            entry.warnings.push(PlanWarning::ReservedNameFixed);
+        }
+        if prepared.op.shortened {
+            entry.warnings.push(PlanWarning::Shortened);
+        }
+        if let Some(first) = same_file[id] {
+            entry.warnings.push(PlanWarning::HardLink);
+            if first != id {
+                entry.same_file_as = Some(first);
+                // The time and title belong to the file, not to its names.
+                let first = &entries[first];
+                if first.is_ready() && first.modified.is_some() {
+                    entry.modified = None;
+                }
+                if first.is_ready() && first.title.is_some() {
+                    entry.title = None;
+                }
+            }
+        }
+
+        let volume = volumes.of(&entry.target);
+        let too_long = fsutil::too_long(&entry.target)
+            || entry
+                .target
+                .file_name()
+                .is_some_and(|name| !volume.name_fits(name));
+        let folded = volume.fold(&entry.target);
+        if let Some(status) = prepared.status {
+            entry.status = status;
+        } else if entry.source == entry.target && entry.modified.is_none() && entry.title.is_none()
+        {
+            entry.status = EntryStatus::Unchanged;
+        } else if !fsutil::exists(&entry.source) {
+            entry.status = EntryStatus::MissingSource;
+        } else if entry.source == entry.target {
+            // Only the modification time or the title changes.
+        } else if too_long {
+            entry.status = EntryStatus::PathTooLong;
+        } else if entry.is_dir && entry.target.starts_with(&entry.source) {
+            entry.status = EntryStatus::IntoItself;
+        } else {
+            let kind = if target_counts.get(&folded).is_some_and(|&n| n > 1) {
+                Some(ConflictKind::DuplicateTarget)
+            } else if occupied(&entry.target)
+                && !fsutil::is_case_only_rename(&entry.source, &entry.target)
+            {
+                Some(ConflictKind::TargetExists)
+            } else {
+                None
+            };
+            if let Some(kind) = kind {
+                let volume = volumes.of(&entry.target);
+                let claimed = |path: &Path| claimed.contains(&volume.fold(path));
+                resolve(&mut entry, kind, collision, &claimed, &occupied);
+            }
+        }
+        if entry.is_ready() && entry.source != entry.target {
+            entry.policy_violations = policy::check_all(policies, &name_of(&entry.target));
+            if !entry.policy_violations.is_empty() {
+                entry.status = EntryStatus::PolicyViolation;
+            }
+        }
+
+        if entry.is_ready() {
+            claimed.insert(volumes.of(&entry.target).fold(&entry.target));
+        }
+        entries.push(entry);
+    }
+
+    // Hold back whole groups whose companions cannot follow.
+    let failed: HashSet<usize> = companions
+        .iter()
+        .enumerate()
+        .filter_map(|(id, c)| Some(c.as_ref()?.primary).filter(|_| !entries[id].is_ready()))
+        .collect();
+    for (id, entry) in entries.iter_mut().enumerate() {
+        let group = companions[id].as_ref().map_or(id, |c| c.primary);
+        if failed.contains(&group) && entry.is_ready() {
+            entry.status = EntryStatus::CompanionNotReady;
+        }
+    }
+    entries
+}
+
+fn resolve(
+    entry: &mut PlanEntry,
+    kind: ConflictKind,
+    strategy: CollisionStrategy,
+    claimed: &dyn Fn(&Path) -> bool,
+    occupied: &dyn Fn(&Path) -> bool,
+) {
+    let requested_target = entry.target.clone();
+    let blocked_status = match kind {
+        ConflictKind::TargetExists => EntryStatus::TargetExists,
+        ConflictKind::DuplicateTarget => EntryStatus::DuplicateTarget,
+    };
+
+    let resolution = match strategy {
+        CollisionStrategy::Suffix => {
+            // The first duplicate keeps the requested name if nothing is there.
+            if kind == ConflictKind::DuplicateTarget
+                && !claimed(&entry.target)
+                && !occupied(&entry.target)
+            {
+                Resolution::Kept
+            } else {
+                let mut n = 1;
+                let mut candidate = suffixed(&requested_target, n);
+                while claimed(&candidate) || occupied(&candidate) {
+                    n += 1;
+                    candidate = suffixed(&requested_target, n);
+                }
+                entry.target = candidate;
+                Resolution::Suffixed
+            }
+        }
+        CollisionStrategy::Overwrite
+            if kind == ConflictKind::TargetExists
+                && !entry.is_dir
+                && !fsutil::is_dir(&entry.target) =>
+        {
+            Resolution::Overwrite
+        }
+        CollisionStrategy::Overwrite | CollisionStrategy::Skip => {
+            entry.status = blocked_status;
+            Resolution::Skipped
+        }
+        CollisionStrategy::Fail => {
+            entry.status = blocked_status;
+            Resolution::Failed
+        }
+    };
+
+    entry.conflict = Some(Conflict {
+        kind,
+        resolution,
+        requested_target,
+    });
+}
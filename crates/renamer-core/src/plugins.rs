@@ -0,0 +1,488 @@
+//! Third-party template tokens and rule steps, loaded from WebAssembly
+//! modules.
+//!
+//! A plugin is a `.wasm` file in the plugin folder. Plugins are off until
+//! enabled with [`PluginHost::set_enabled`]; an enabled plugin's tokens can be
+//! used as `{plugin:NAME.TOKEN}` in templates (with a `|fallback` like other
+//! tokens read from the file) and its rules as [`crate::Rule::Plugin`] steps.
+//!
+//! Plugins run in a sandbox: they have no access to the system beyond reading
+//! the file being renamed, each call has a fuel (instruction) budget and
+//! memory is capped. Values cross the boundary as UTF-8 JSON. A module
+//! exports:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`, space for the host to write a call's input
+//! - `manifest() -> i64`, a JSON [`PluginManifest`]
+//! - `token(ptr: i32, len: i32) -> i64`, if it has tokens: given
+//!   `{"token", "name", "path", "size", "metadata"}`, a string or `null`
+//! - `rule(ptr: i32, len: i32) -> i64`, if it has rules: given
+//!   `{"rule", "name", "path", "size", "metadata", "options"}`, the new name
+//!   as a string, or `{"error": "..."}`
+//!
+//! where `i64` results point at the output, packed as `ptr << 32 | len`.
+//! Modules may import `sortify.read(offset: i64, ptr: i32, len: i32) -> i32`,
+//! which reads up to `len` bytes of the file from `offset` into memory and
+//! returns how many were read, or -1.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use wasmi::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::template::FileContext;
+
+/// Instructions a plugin may run per call.
+const FUEL: u64 = 50_000_000;
+/// Largest linear memory a plugin may grow to.
+const MAX_MEMORY: usize = 64 << 20;
+/// Largest result a plugin may return.
+const MAX_OUTPUT: usize = 1 << 20;
+/// Most a single `sortify.read` may read.
+const MAX_READ: usize = 1 << 20;
+
+/// What a plugin says about itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    /// How templates and rules refer to the plugin; unique among plugins.
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// Tokens it provides, used as `{plugin:NAME.TOKEN}`.
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// Rules it provides, used as [`crate::Rule::Plugin`] steps.
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+/// A plugin found in the plugin folder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginInfo {
+    #[serde(flatten)]
+    pub manifest: PluginManifest,
+    pub path: PathBuf,
+    pub enabled: bool,
+}
+
+/// A `.wasm` file in the plugin folder that could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+struct HostData {
+    limits: StoreLimits,
+    /// The file being renamed, which `sortify.read` reads.
+    file: Option<PathBuf>,
+}
+
+/// A loaded plugin. Calls are serialized, as a module has a single memory.
+pub(crate) struct Plugin {
+    manifest: PluginManifest,
+    path: PathBuf,
+    store: Mutex<Store<HostData>>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    token: Option<TypedFunc<(i32, i32), i64>>,
+    rule: Option<TypedFunc<(i32, i32), i64>>,
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Plugin")
+            .field("name", &self.manifest.name)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Engine::new(&config)
+    })
+}
+
+/// `sortify.read`: reads part of the file being renamed into memory.
+fn host_read(mut caller: Caller<'_, HostData>, offset: i64, ptr: i32, len: i32) -> i32 {
+    let Some(path) = caller.data().file.clone() else {
+        return -1;
+    };
+    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+        return -1;
+    };
+    let (Ok(offset), Ok(len)) = (u64::try_from(offset), usize::try_from(len)) else {
+        return -1;
+    };
+    let mut buf = vec![0; len.min(MAX_READ)];
+    let read = fs::File::open(fsutil::long(&path)).and_then(|mut file| {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut filled = 0;
+        loop {
+            match file.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+            if filled == buf.len() {
+                break;
+            }
+        }
+        Ok(filled)
+    });
+    match read {
+        Ok(n)
+            if memory
+                .write(&mut caller, ptr as u32 as usize, &buf[..n])
+                .is_ok() =>
+        {
+            n as i32
+        }
+        _ => -1,
+    }
+}
+
+fn load_error(path: &Path, message: impl fmt::Display) -> Error {
+    Error::InvalidPlugin {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+    }
+}
+
+impl Plugin {
+    /// Compiles and starts the module at `path` and reads its manifest.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+        let module = Module::new(engine(), &bytes[..]).map_err(|e| load_error(path, e))?;
+        let data = HostData {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MAX_MEMORY)
+                .instances(1)
+                .build(),
+            file: None,
+        };
+        let mut store = Store::new(engine(), data);
+        store.limiter(|data| &mut data.limits);
+        store.set_fuel(FUEL).map_err(|e| load_error(path, e))?;
+        let mut linker = Linker::new(engine());
+        linker
+            .func_wrap("sortify", "read", host_read)
+            .map_err(|e| load_error(path, e))?;
+        let instance: Instance = linker
+            .instantiate_and_start(&mut store, &module)
+            .map_err(|e| load_error(path, e))?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| load_error(path, "it does not export `memory`"))?;
+        let alloc = instance
+            .get_typed_func(&store, "alloc")
+            .map_err(|e| load_error(path, format!("`alloc`: {}", e)))?;
+        let manifest_fn = instance
+            .get_typed_func::<(), i64>(&store, "manifest")
+            .map_err(|e| load_error(path, format!("`manifest`: {}", e)))?;
+        let token = instance.get_typed_func(&store, "token").ok();
+        let rule = instance.get_typed_func(&store, "rule").ok();
+
+        let packed = manifest_fn
+            .call(&mut store, ())
+            .map_err(|e| load_error(path, e))?;
+        let output = read_output(&store, memory, packed).map_err(|e| load_error(path, e))?;
+        let manifest: PluginManifest =
+            serde_json::from_slice(&output).map_err(|e| load_error(path, e))?;
+        if manifest.name.is_empty() || !manifest.name.bytes().all(is_name_byte) {
+            return Err(load_error(
+                path,
+                format!("`{}` is not a valid plugin name", manifest.name),
+            ));
+        }
+        if !manifest.tokens.is_empty() && token.is_none() {
+            return Err(load_error(path, "it lists tokens but has no `token`"));
+        }
+        if !manifest.rules.is_empty() && rule.is_none() {
+            return Err(load_error(path, "it lists rules but has no `rule`"));
+        }
+        Ok(Plugin {
+            manifest,
+            path: path.to_path_buf(),
+            store: Mutex::new(store),
+            memory,
+            alloc,
+            token,
+            rule,
+        })
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.manifest.name
+    }
+
+    pub(crate) fn has_token(&self, token: &str) -> bool {
+        self.manifest.tokens.iter().any(|t| t == token)
+    }
+
+    pub(crate) fn has_rule(&self, rule: &str) -> bool {
+        self.manifest.rules.iter().any(|r| r == rule)
+    }
+
+    /// Calls `func` with `input` as JSON, returning its JSON output.
+    fn call(
+        &self,
+        func: TypedFunc<(i32, i32), i64>,
+        input: &Value,
+        file: &Path,
+    ) -> std::result::Result<Value, String> {
+        let input = serde_json::to_vec(input).map_err(|e| e.to_string())?;
+        let mut store = self.store.lock().unwrap_or_else(|e| e.into_inner());
+        store.data_mut().file = Some(file.to_path_buf());
+        store.set_fuel(FUEL).map_err(|e| e.to_string())?;
+        let output = (|| {
+            let len = i32::try_from(input.len()).map_err(|e| e.to_string())?;
+            let ptr = self.alloc.call(&mut *store, len).map_err(trap)?;
+            self.memory
+                .write(&mut *store, ptr as u32 as usize, &input)
+                .map_err(|e| e.to_string())?;
+            let packed = func.call(&mut *store, (ptr, len)).map_err(trap)?;
+            read_output(&store, self.memory, packed)
+        })();
+        store.data_mut().file = None;
+        serde_json::from_slice(&output?).map_err(|e| format!("the plugin returned bad JSON: {}", e))
+    }
+
+    /// The value of `token` for a file now called `name`.
+    pub(crate) fn token(&self, token: &str, name: &str, ctx: &FileContext) -> Option<String> {
+        let mut input = file_input(name, ctx);
+        input["token"] = json!(token);
+        match self.call(self.token?, &input, ctx.path) {
+            Ok(Value::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The new name `rule` gives a file now called `name`, or why there is
+    /// none.
+    pub(crate) fn rule(
+        &self,
+        rule: &str,
+        options: &Value,
+        name: &str,
+        ctx: &FileContext,
+    ) -> std::result::Result<String, String> {
+        let func = self.rule.ok_or("the plugin has no rules")?;
+        let mut input = file_input(name, ctx);
+        input["rule"] = json!(rule);
+        input["options"] = options.clone();
+        let prefix = |e: String| format!("{}.{}: {}", self.manifest.name, rule, e);
+        match self.call(func, &input, ctx.path).map_err(prefix)? {
+            Value::String(name) => Ok(name),
+            Value::Object(object) => Err(prefix(
+                object
+                    .get("error")
+                    .and_then(Value::as_str)
+                    .unwrap_or("the plugin returned no name")
+                    .to_string(),
+            )),
+            _ => Err(prefix("the plugin returned no name".to_string())),
+        }
+    }
+}
+
+fn is_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+fn trap(e: wasmi::Error) -> String {
+    if e.as_trap_code() == Some(wasmi::TrapCode::OutOfFuel) {
+        "the plugin ran too long".to_string()
+    } else {
+        e.to_string()
+    }
+}
+
+fn read_output(
+    store: &Store<HostData>,
+    memory: Memory,
+    packed: i64,
+) -> std::result::Result<Vec<u8>, String> {
+    let packed = packed as u64;
+    let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+    if len > MAX_OUTPUT {
+        return Err(format!("the plugin returned {} bytes", len));
+    }
+    let mut output = vec![0; len];
+    memory
+        .read(store, ptr, &mut output)
+        .map_err(|e| e.to_string())?;
+    Ok(output)
+}
+
+/// The fields every call gets about the file.
+fn file_input(name: &str, ctx: &FileContext) -> Value {
+    json!({
+        "name": name,
+        "path": ctx.path,
+        "size": ctx.size,
+        "metadata": ctx.embedded.as_ref().map(|m| &m.values),
+    })
+}
+
+/// The enabled plugins, which templates and rules are checked against.
+fn active() -> &'static RwLock<Vec<Arc<Plugin>>> {
+    static ACTIVE: OnceLock<RwLock<Vec<Arc<Plugin>>>> = OnceLock::new();
+    ACTIVE.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// The enabled plugin called `name`.
+pub(crate) fn find(name: &str) -> Option<Arc<Plugin>> {
+    active()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|p| p.name() == name)
+        .cloned()
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Settings {
+    #[serde(default)]
+    enabled: BTreeSet<String>,
+}
+
+/// The plugins in a folder and which of them are enabled.
+///
+/// Only one host should be open at a time: opening one, or enabling or
+/// disabling a plugin, replaces the plugins templates and rules can use.
+pub struct PluginHost {
+    dir: PathBuf,
+    settings_path: PathBuf,
+    enabled: BTreeSet<String>,
+    plugins: Vec<Arc<Plugin>>,
+    errors: Vec<PluginError>,
+}
+
+impl PluginHost {
+    /// Loads every `.wasm` file in `dir`, enabling those `settings` lists. A
+    /// missing folder or settings file means there are no plugins or none
+    /// are enabled.
+    pub fn open(dir: impl Into<PathBuf>, settings: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        let settings_path = settings.into();
+        let settings: Settings = match fs::read(&settings_path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => return Err(Error::io(&settings_path, e)),
+        };
+        let mut host = PluginHost {
+            dir,
+            settings_path,
+            enabled: settings.enabled,
+            plugins: Vec::new(),
+            errors: Vec::new(),
+        };
+        host.reload()?;
+        Ok(host)
+    }
+
+    /// Loads the plugin folder again, picking up added and removed files.
+    pub fn reload(&mut self) -> Result<()> {
+        let mut paths = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.extension()
+                        .is_some_and(|e| e.eq_ignore_ascii_case("wasm"))
+                })
+                .collect::<Vec<_>>(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(Error::io(&self.dir, e)),
+        };
+        paths.sort();
+        self.plugins.clear();
+        self.errors.clear();
+        for path in paths {
+            match Plugin::load(&path) {
+                Ok(plugin) if self.plugins.iter().any(|p| p.name() == plugin.name()) => {
+                    self.errors.push(PluginError {
+                        message: format!("another plugin is already called `{}`", plugin.name()),
+                        path,
+                    })
+                }
+                Ok(plugin) => self.plugins.push(Arc::new(plugin)),
+                Err(e) => self.errors.push(PluginError {
+                    path,
+                    message: e.to_string(),
+                }),
+            }
+        }
+        self.activate();
+        Ok(())
+    }
+
+    pub fn plugins(&self) -> Vec<PluginInfo> {
+        self.plugins
+            .iter()
+            .map(|p| PluginInfo {
+                manifest: p.manifest.clone(),
+                path: p.path.clone(),
+                enabled: self.enabled.contains(p.name()),
+            })
+            .collect()
+    }
+
+    /// The `.wasm` files that could not be loaded, and why.
+    pub fn errors(&self) -> &[PluginError] {
+        &self.errors
+    }
+
+    /// Enables or disables the plugin called `name`. Returns false if there
+    /// is no such plugin.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<bool> {
+        if !self.plugins.iter().any(|p| p.name() == name) {
+            return Ok(false);
+        }
+        if enabled {
+            self.enabled.insert(name.to_string());
+        } else {
+            self.enabled.remove(name);
+        }
+        self.save()?;
+        self.activate();
+        Ok(true)
+    }
+
+    fn activate(&self) {
+        let enabled = self
+            .plugins
+            .iter()
+            .filter(|p| self.enabled.contains(p.name()))
+            .cloned()
+            .collect();
+        *active().write().unwrap_or_else(|e| e.into_inner()) = enabled;
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.settings_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let settings = Settings {
+            enabled: self.enabled.clone(),
+        };
+        let json = serde_json::to_vec_pretty(&settings)?;
+        fs::write(&self.settings_path, json).map_err(|e| Error::io(&self.settings_path, e))
+    }
+}
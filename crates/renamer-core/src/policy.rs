@@ -0,0 +1,248 @@
+//! Naming policies: the standard an organization's file names follow, which
+//! characters they may have, how they start, how long they may be and which
+//! words they may not contain.
+//!
+//! A policy is kept in a JSON file, which an IT department can hand out and
+//! the settings point to; [`set_enforced`] or [`enforce_file`] makes it the
+//! one every plan is checked against, on top of any in
+//! [`crate::PlanOptions::policy`]. A file that cannot be read is not enforced
+//! as no policy: every name breaks it until it can be. Each
+//! new name of a plan, the last part of a target, is checked. An entry whose
+//! name breaks a policy is not ready, as [`EntryStatus::PolicyViolation`],
+//! and blocks its plan, which is then never applied.
+//!
+//! [`EntryStatus::PolicyViolation`]: crate::EntryStatus::PolicyViolation
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NamingPolicy {
+    /// Shown along with what a name breaks, e.g. `Finance naming standard`.
+    pub name: String,
+    /// The characters names may have, as the inside of a regex character
+    /// class: `A-Za-z0-9_.-` allows letters without accents, digits,
+    /// underscores, dots and hyphens. Any character when empty.
+    pub allowed_characters: String,
+    /// A regex the start of every name must match, e.g.
+    /// `\d{4}-\d{2}-\d{2}_` for a date first.
+    pub required_prefix: Option<String>,
+    /// The most characters a name may have, extension included.
+    pub max_length: Option<usize>,
+    /// Words no name may contain, in any case. A word is found between
+    /// anything but letters and digits, so `copy` is in `Report copy.pdf`
+    /// but not in `copyright.pdf`.
+    pub forbidden_words: Vec<String>,
+}
+
+/// What a name breaks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PolicyViolation {
+    /// The name has a character the policy does not allow.
+    Character { character: char },
+    /// The name does not start the way the policy requires.
+    MissingPrefix,
+    /// The name is longer than the policy allows.
+    TooLong { length: usize, max: usize },
+    /// The name has a word the policy forbids.
+    ForbiddenWord { word: String },
+    /// A pattern of the policy is not a valid regex, so no name passes.
+    InvalidPolicy { message: String },
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyViolation::Character { character } => write!(f, "`{}` is not allowed", character),
+            PolicyViolation::MissingPrefix => write!(f, "it does not start as required"),
+            PolicyViolation::TooLong { length, max } => {
+                write!(f, "it is {} characters long, {} at most", length, max)
+            }
+            PolicyViolation::ForbiddenWord { word } => write!(f, "`{}` is forbidden", word),
+            PolicyViolation::InvalidPolicy { message } => f.write_str(message),
+        }
+    }
+}
+
+/// A policy with its patterns compiled, to check many names with.
+pub(crate) struct Checker {
+    character: Option<Regex>,
+    prefix: Option<Regex>,
+    max_length: Option<usize>,
+    forbidden: Vec<String>,
+}
+
+impl NamingPolicy {
+    pub(crate) fn checker(&self) -> Result<Checker> {
+        let invalid = |e: regex::Error| Error::InvalidPolicy {
+            policy: self.name.clone(),
+            message: e.to_string(),
+        };
+        let character = (!self.allowed_characters.is_empty())
+            .then(|| Regex::new(&format!("^[{}]$", self.allowed_characters)))
+            .transpose()
+            .map_err(invalid)?;
+        let prefix = self
+            .required_prefix
+            .as_ref()
+            .map(|prefix| Regex::new(&format!("^(?:{})", prefix)))
+            .transpose()
+            .map_err(invalid)?;
+        Ok(Checker {
+            character,
+            max_length: self.max_length,
+            prefix,
+            forbidden: self
+                .forbidden_words
+                .iter()
+                .map(|w| w.to_lowercase())
+                .collect(),
+        })
+    }
+
+    /// What `name` breaks, in the order the policy lists its rules.
+    pub fn check(&self, name: &str) -> Result<Vec<PolicyViolation>> {
+        Ok(self.checker()?.check(name))
+    }
+}
+
+impl Checker {
+    pub(crate) fn check(&self, name: &str) -> Vec<PolicyViolation> {
+        let mut found = Vec::new();
+        if let Some(allowed) = &self.character {
+            let mut buf = [0; 4];
+            let mut seen = Vec::new();
+            for character in name.chars() {
+                if !seen.contains(&character) && !allowed.is_match(character.encode_utf8(&mut buf))
+                {
+                    seen.push(character);
+                    found.push(PolicyViolation::Character { character });
+                }
+            }
+        }
+        if self.prefix.as_ref().is_some_and(|p| !p.is_match(name)) {
+            found.push(PolicyViolation::MissingPrefix);
+        }
+        let length = name.chars().count();
+        if let Some(max) = self.max_length.filter(|&max| length > max) {
+            found.push(PolicyViolation::TooLong { length, max });
+        }
+        let words: Vec<String> = name
+            .split(|c: char| !c.is_alphanumeric())
+            .map(str::to_lowercase)
+            .collect();
+        for word in &self.forbidden {
+            if words.contains(word) {
+                found.push(PolicyViolation::ForbiddenWord { word: word.clone() });
+            }
+        }
+        found
+    }
+}
+
+/// What `name` breaks of every policy of `checkers`; one that could not
+/// be compiled is broken by every name.
+pub(crate) fn check_all(checkers: &[Result<Checker>], name: &str) -> Vec<PolicyViolation> {
+    checkers
+        .iter()
+        .flat_map(|checker| match checker {
+            Ok(checker) => checker.check(name),
+            Err(e) => vec![PolicyViolation::InvalidPolicy {
+                message: e.to_string(),
+            }],
+        })
+        .collect()
+}
+
+/// Reads the policy in the file at `path`, checking its patterns.
+pub fn load(path: &Path) -> Result<NamingPolicy> {
+    let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+    let policy: NamingPolicy = serde_json::from_slice(&bytes)?;
+    policy.checker()?;
+    Ok(policy)
+}
+
+/// What plans are checked against on top of their own policy.
+#[derive(Clone)]
+enum Enforced {
+    Policy(NamingPolicy),
+    /// The file at `path` could not be read, or its policy is broken.
+    Unreadable {
+        path: String,
+        message: String,
+    },
+}
+
+fn enforced_policy() -> &'static RwLock<Option<Enforced>> {
+    static ENFORCED: OnceLock<RwLock<Option<Enforced>>> = OnceLock::new();
+    ENFORCED.get_or_init(|| RwLock::new(None))
+}
+
+fn set(enforced: Option<Enforced>) {
+    *enforced_policy().write().unwrap_or_else(|e| e.into_inner()) = enforced;
+}
+
+/// Makes `policy` the one every plan made from now on is checked against;
+/// `None` checks plans against their own policy only.
+pub fn set_enforced(policy: Option<NamingPolicy>) {
+    set(policy.map(Enforced::Policy));
+}
+
+/// Makes the policy in the file at `path` the enforced one, as
+/// [`set_enforced`] does. If it cannot be loaded, that is returned, and
+/// every name of every plan made from now on breaks the policy, with
+/// [`PolicyViolation::InvalidPolicy`], until one is loaded or none is
+/// enforced.
+pub fn enforce_file(path: &Path) -> Result<()> {
+    match load(path) {
+        Ok(policy) => {
+            set_enforced(Some(policy));
+            Ok(())
+        }
+        Err(e) => {
+            set(Some(Enforced::Unreadable {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            }));
+            Err(e)
+        }
+    }
+}
+
+/// The policy set with [`set_enforced`] or [`enforce_file`]; `None` also
+/// while the file could not be read.
+pub fn enforced() -> Option<NamingPolicy> {
+    match enforced_policy()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()?
+    {
+        Enforced::Policy(policy) => Some(policy),
+        Enforced::Unreadable { .. } => None,
+    }
+}
+
+/// The enforced policy compiled, or the error of the file that could not be
+/// read for it.
+pub(crate) fn enforced_checker() -> Option<Result<Checker>> {
+    let enforced = enforced_policy()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()?;
+    Some(match enforced {
+        Enforced::Policy(policy) => policy.checker(),
+        Enforced::Unreadable { path, message } => Err(Error::InvalidPolicy {
+            policy: path,
+            message,
+        }),
+    })
+}
@@ -0,0 +1,372 @@
+//! A program a preset runs after its batch, such as `exiftool` or `ffmpeg`,
+//! once for every renamed file or once for the whole batch.
+//!
+//! Arguments are templates, rendered for each file like new names are
+//! (`{name}` is then the new stem), with these tokens besides:
+//!
+//! | token        | value                                      |
+//! |--------------|--------------------------------------------|
+//! | `{path}`     | the file's new path                        |
+//! | `{old_path}` | its path before the batch                  |
+//! | `{dir}`      | the folder it is in now                    |
+//! | `{file}`     | its new file name                          |
+//! | `{batch_id}` | the batch's id                             |
+//!
+//! Once per batch, only `{batch_id}` and `{count}`, the number of renamed
+//! files, are filled in, and an argument that is just `{paths}` becomes one
+//! argument for each file's new path.
+//!
+//! The program is started directly, not through a shell, so a path with
+//! spaces stays one argument. Each run gets [`PostAction::timeout_secs`]
+//! before it is killed, and what it prints is kept for the report.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::CancelToken;
+use crate::error::Result;
+use crate::executor::{ApplyReport, OutcomeStatus};
+use crate::template::{FileContext, Template};
+
+/// Most bytes of each output stream kept.
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// How long output is still read once the program has exited or was
+/// stopped. Something it started in the background can hold its pipes open
+/// for much longer, and is not waited for.
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Tokens filled in for each file besides the template's own.
+const FILE_TOKENS: [&str; 5] = ["{path}", "{old_path}", "{dir}", "{file}", "{batch_id}"];
+
+/// How often a running program is looked at for exiting or cancelling.
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostActionScope {
+    /// Once for every renamed file.
+    #[default]
+    EachFile,
+    /// Once, after the batch, if it renamed anything.
+    Batch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostAction {
+    /// The program, found on `PATH` unless a path is given.
+    pub program: String,
+    /// Argument templates; see the module docs.
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub scope: PostActionScope,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    /// Folder the program starts in; the renamed file's, or the first
+    /// renamed file's for a batch, when not set.
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+}
+
+fn default_timeout() -> u64 {
+    60
+}
+
+impl PostAction {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        PostAction {
+            program: program.into(),
+            args,
+            scope: PostActionScope::default(),
+            timeout_secs: default_timeout(),
+            working_dir: None,
+        }
+    }
+
+    /// Fails with the first argument that is not a valid template.
+    pub fn check(&self) -> Result<()> {
+        let values = FILE_TOKENS.map(|token| (token, ""));
+        for arg in &self.args {
+            Template::parse(&substitute(arg, &values))?;
+        }
+        Ok(())
+    }
+}
+
+/// One run of the program and how it went.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostActionRun {
+    /// The renamed file the run was for; `None` for a batch run.
+    pub path: Option<PathBuf>,
+    /// Program and arguments, as run.
+    pub command: Vec<String>,
+    /// `None` if the program did not exit by itself.
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// Why the program could not be started, or was stopped.
+    pub error: Option<String>,
+}
+
+impl PostActionRun {
+    /// Exited with code 0.
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs `action` for the files `report` renamed, one after the other;
+/// nothing runs for a batch that renamed nothing. Once `cancel` fires, a
+/// running program is killed and no more are started.
+pub fn run(action: &PostAction, report: &ApplyReport, cancel: &CancelToken) -> Vec<PostActionRun> {
+    let renamed: Vec<(&Path, &Path)> = report
+        .outcomes
+        .iter()
+        .filter(|o| o.status == OutcomeStatus::Renamed && !o.is_dir)
+        .map(|o| (o.source.as_path(), o.target.as_path()))
+        .collect();
+    if renamed.is_empty() {
+        return Vec::new();
+    }
+    let batch_id = report.batch_id.to_string();
+    match action.scope {
+        PostActionScope::Batch => {
+            let count = renamed.len().to_string();
+            let mut args = Vec::new();
+            for arg in &action.args {
+                if arg == "{paths}" {
+                    args.extend(renamed.iter().map(|(_, t)| t.display().to_string()));
+                } else {
+                    args.push(
+                        arg.replace("{batch_id}", &batch_id)
+                            .replace("{count}", &count),
+                    );
+                }
+            }
+            let dir = renamed[0].1.parent();
+            vec![execute(action, None, args, dir, cancel)]
+        }
+        PostActionScope::EachFile => {
+            let mut runs = Vec::new();
+            for (index, (source, target)) in renamed.into_iter().enumerate() {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                let run = match render(action, index, source, target, &batch_id) {
+                    Ok(args) => execute(action, Some(target), args, target.parent(), cancel),
+                    Err(e) => PostActionRun {
+                        path: Some(target.to_path_buf()),
+                        command: vec![action.program.clone()],
+                        exit_code: None,
+                        timed_out: false,
+                        stdout: String::new(),
+                        stderr: String::new(),
+                        error: Some(e.to_string()),
+                    },
+                };
+                runs.push(run);
+            }
+            runs
+        }
+    }
+}
+
+/// Puts `values` in for their tokens, in one pass so a value is never
+/// searched for tokens again, with braces in them doubled so the template
+/// takes them literally.
+fn substitute(arg: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    let mut rest = arg;
+    while let Some(i) = rest.find('{') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("{{") {
+            out.push_str("{{");
+            rest = &rest[2..];
+        } else if let Some((token, value)) = values.iter().find(|(t, _)| rest.starts_with(t)) {
+            out.push_str(&value.replace('{', "{{").replace('}', "}}"));
+            rest = &rest[token.len()..];
+        } else {
+            out.push('{');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The arguments for the file renamed from `source` to `target`.
+fn render(
+    action: &PostAction,
+    index: usize,
+    source: &Path,
+    target: &Path,
+    batch_id: &str,
+) -> Result<Vec<String>> {
+    let path = target.display().to_string();
+    let old_path = source.display().to_string();
+    let dir = target
+        .parent()
+        .map(|d| d.display().to_string())
+        .unwrap_or_default();
+    let file = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let values = [
+        (FILE_TOKENS[0], path.as_str()),
+        (FILE_TOKENS[1], old_path.as_str()),
+        (FILE_TOKENS[2], dir.as_str()),
+        (FILE_TOKENS[3], file.as_str()),
+        (FILE_TOKENS[4], batch_id),
+    ];
+    let templates = action
+        .args
+        .iter()
+        .map(|arg| Template::parse(&substitute(arg, &values)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut ctx = FileContext::new(target, index);
+    if templates.iter().any(Template::needs_metadata) {
+        ctx = ctx.with_metadata();
+    }
+    if templates.iter().any(Template::needs_embedded) {
+        ctx = ctx.with_embedded();
+    }
+    if templates.iter().any(Template::needs_ocr) {
+        ctx = ctx.with_ocr();
+    }
+    let algorithms: Vec<_> = templates
+        .iter()
+        .flat_map(Template::hash_algorithms)
+        .collect();
+    if !algorithms.is_empty() {
+        ctx = ctx.with_hashes(&algorithms);
+    }
+    Ok(templates.iter().map(|t| t.render(&file, &ctx)).collect())
+}
+
+pub(crate) fn execute(
+    action: &PostAction,
+    path: Option<&Path>,
+    args: Vec<String>,
+    dir: Option<&Path>,
+    cancel: &CancelToken,
+) -> PostActionRun {
+    let mut run = PostActionRun {
+        path: path.map(Path::to_path_buf),
+        command: std::iter::once(action.program.clone())
+            .chain(args.iter().cloned())
+            .collect(),
+        exit_code: None,
+        timed_out: false,
+        stdout: String::new(),
+        stderr: String::new(),
+        error: None,
+    };
+    let mut command = Command::new(&action.program);
+    command
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = action.working_dir.as_deref().or(dir) {
+        command.current_dir(dir);
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            run.error = Some(format!("{} could not be started: {}", action.program, e));
+            return run;
+        }
+    };
+    let stdout = capture(child.stdout.take());
+    let stderr = capture(child.stderr.take());
+    let deadline = Instant::now() + Duration::from_secs(action.timeout_secs);
+    match wait(&mut child, deadline, cancel) {
+        Ok(Some(code)) => run.exit_code = code,
+        Ok(None) if cancel.is_cancelled() => run.error = Some("cancelled".into()),
+        Ok(None) => {
+            run.timed_out = true;
+            run.error = Some(format!("still running after {}s", action.timeout_secs));
+        }
+        Err(e) => run.error = Some(e.to_string()),
+    }
+    let until = Instant::now() + OUTPUT_GRACE;
+    run.stdout = stdout.take(until);
+    run.stderr = stderr.take(until);
+    run
+}
+
+/// Waits for `child` to exit, returning its exit code, or kills it at
+/// `deadline` or when `cancel` fires and returns `None`.
+fn wait(
+    child: &mut Child,
+    deadline: Instant,
+    cancel: &CancelToken,
+) -> io::Result<Option<Option<i32>>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status.code()));
+        }
+        if cancel.is_cancelled() || Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(POLL);
+    }
+}
+
+/// Output being read on a thread.
+struct Captured {
+    kept: Arc<Mutex<Vec<u8>>>,
+    /// Disconnected once the stream has ended.
+    ended: mpsc::Receiver<()>,
+}
+
+impl Captured {
+    /// What was read, once the stream ends or at `until`, whichever is
+    /// first; the thread is left to finish by itself.
+    fn take(self, until: Instant) -> String {
+        let _ = self
+            .ended
+            .recv_timeout(until.saturating_duration_since(Instant::now()));
+        let kept = self.kept.lock().unwrap_or_else(|e| e.into_inner());
+        String::from_utf8_lossy(&kept).into_owned()
+    }
+}
+
+/// Reads `stream` to its end on a thread, keeping the first
+/// [`MAX_OUTPUT`] bytes, so a chatty program never blocks on a full pipe.
+fn capture(stream: Option<impl Read + Send + 'static>) -> Captured {
+    let kept = Arc::new(Mutex::new(Vec::new()));
+    let (ended, receiver) = mpsc::channel();
+    let buffer = Arc::clone(&kept);
+    thread::spawn(move || {
+        let _ended = ended;
+        let Some(mut stream) = stream else {
+            return;
+        };
+        let mut buf = [0u8; 8192];
+        while let Ok(n) = stream.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let mut kept = buffer.lock().unwrap_or_else(|e| e.into_inner());
+            let room = MAX_OUTPUT.saturating_sub(kept.len());
+            kept.extend_from_slice(&buf[..n.min(room)]);
+        }
+    });
+    Captured {
+        kept,
+        ended: receiver,
+    }
+}
@@ -0,0 +1,122 @@
+//! Keeping the computer awake while a long batch runs, so a rename or hash
+//! of a large library is not cut short by the system going to sleep.
+//!
+//! On Windows the running thread asks for the system to stay on with
+//! `SetThreadExecutionState`. On macOS `caffeinate` holds an IOPMAssertion
+//! and on Linux `systemd-inhibit` holds a sleep lock, each for as long as
+//! its input stays open, so the lock also goes away if this process dies.
+//! Where none of that is available the guard does nothing.
+
+use std::marker::PhantomData;
+
+/// Keeps the system from sleeping until dropped. Acquiring one never fails;
+/// on a system that cannot be kept awake it does nothing.
+///
+/// The guard must be dropped on the thread that acquired it, since Windows
+/// tracks the request per thread.
+pub struct SleepGuard {
+    _inner: platform::Inhibitor,
+    _thread_bound: PhantomData<*const ()>,
+}
+
+impl SleepGuard {
+    /// `reason` is shown where the system lists what is keeping it awake.
+    pub fn acquire(reason: &str) -> SleepGuard {
+        SleepGuard {
+            _inner: platform::Inhibitor::acquire(reason),
+            _thread_bound: PhantomData,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+
+    pub struct Inhibitor {
+        /// The state before, restored on drop so nested guards behave.
+        previous: u32,
+    }
+
+    impl Inhibitor {
+        pub fn acquire(_reason: &str) -> Inhibitor {
+            // SAFETY: only sets a flag on the calling thread.
+            let previous = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+            Inhibitor { previous }
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            if self.previous != 0 {
+                // SAFETY: as above.
+                unsafe { SetThreadExecutionState(self.previous) };
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::process::{Child, Command, Stdio};
+
+    /// A helper that keeps the system awake while it waits on `cat`, which
+    /// exits once its input is closed.
+    pub struct Inhibitor(Option<Child>);
+
+    #[cfg(target_os = "macos")]
+    fn command(_reason: &str) -> Command {
+        let mut command = Command::new("caffeinate");
+        command.args(["-i", "cat"]);
+        command
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn command(reason: &str) -> Command {
+        let mut command = Command::new("systemd-inhibit");
+        command
+            .arg("--what=sleep:idle")
+            .arg("--who=Sortify")
+            .arg(format!("--why={}", reason))
+            .arg("--mode=block")
+            .arg("cat");
+        command
+    }
+
+    impl Inhibitor {
+        pub fn acquire(reason: &str) -> Inhibitor {
+            let child = command(reason)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            Inhibitor(child.ok())
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            if let Some(mut child) = self.0.take() {
+                drop(child.stdin.take());
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+#[cfg(not(any(windows, unix)))]
+mod platform {
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn acquire(_reason: &str) -> Inhibitor {
+            Inhibitor
+        }
+    }
+}
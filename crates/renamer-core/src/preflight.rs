@@ -0,0 +1,198 @@
+//! Checks run before a batch is applied, so a folder that cannot be written
+//! to stops the batch before the first rename instead of rolling it back
+//! from the middle.
+//!
+//! Every folder a rename takes a file out of or puts one into is probed by
+//! creating, renaming and removing a hidden file in it. That proves write
+//! access the way the rename will need it, including ACLs and network shares
+//! that report permissions they do not enforce. Files the batch also writes
+//! to, for a new modification time or title, must not be read-only.
+//!
+//! Problems that only come from lacking the rights, like a folder under
+//! Program Files or a mount owned by root, are marked as ones admin rights
+//! would get past, so the app can offer to apply just those entries
+//! elevated.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::fsutil::{self, hidden_sibling};
+use crate::plan::RenamePlan;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreflightKind {
+    /// The file to rename is gone since the plan was made.
+    SourceMissing,
+    /// No file can be created in the folder.
+    FolderNotWritable,
+    /// Files can be created in the folder but not renamed there.
+    RenameRefused,
+    /// The folder only lets owners rename their files (the sticky bit), and
+    /// the file belongs to someone else.
+    NotOwner,
+    /// The batch sets the file's modification time or title, but the file
+    /// is read-only.
+    ReadOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreflightProblem {
+    pub kind: PreflightKind,
+    /// The folder or file the problem is with.
+    pub path: PathBuf,
+    /// Ids of the plan entries that would fail because of it.
+    pub entries: Vec<usize>,
+    pub message: String,
+    /// Admin rights would get past it: access was denied, or the folder
+    /// only lets the file's owner rename it.
+    #[serde(default)]
+    pub needs_elevation: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreflightReport {
+    /// Applying the plan now would fail; see `problems`.
+    pub blocked: bool,
+    pub problems: Vec<PreflightProblem>,
+    /// Folders probed.
+    pub folders: usize,
+    /// Ids of the entries whose every problem needs elevation, which could
+    /// be applied with admin rights.
+    #[serde(default)]
+    pub elevatable: Vec<usize>,
+}
+
+/// The folder a file would be created in under `dir`: `dir` itself, or
+/// the closest ancestor that exists when the rename creates folders.
+fn existing_folder(dir: &Path) -> Option<&Path> {
+    dir.ancestors()
+        .find(|a| !a.as_os_str().is_empty() && fsutil::is_dir(a))
+}
+
+/// Creates, renames and removes a hidden file in `dir`.
+fn probe(dir: &Path) -> Option<(PreflightKind, io::Error)> {
+    let tag = format!("sortify-preflight-{}", Uuid::new_v4());
+    let created = hidden_sibling(&dir.join("probe"), &format!("{}.tmp", tag));
+    let renamed = hidden_sibling(&dir.join("probe"), &format!("{}.renamed.tmp", tag));
+    if let Err(e) = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(fsutil::long(&created))
+    {
+        return Some((PreflightKind::FolderNotWritable, e));
+    }
+    let problem = match fsutil::rename(&created, &renamed) {
+        Ok(()) => {
+            let _ = fs::remove_file(fsutil::long(&renamed));
+            None
+        }
+        Err(e) => Some((PreflightKind::RenameRefused, e)),
+    };
+    let _ = fs::remove_file(fsutil::long(&created));
+    problem
+}
+
+/// True if `dir` has the sticky bit and `file` is neither owned by this
+/// user nor in a folder this user owns.
+#[cfg(unix)]
+fn not_owner(dir: &Path, file: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let me = uzers::get_effective_uid();
+    let (Ok(dir), Ok(file)) = (fs::metadata(dir), fs::symlink_metadata(fsutil::long(file))) else {
+        return false;
+    };
+    me != 0 && dir.mode() & 0o1000 != 0 && dir.uid() != me && file.uid() != me
+}
+
+#[cfg(not(unix))]
+fn not_owner(_dir: &Path, _file: &Path) -> bool {
+    false
+}
+
+/// Checks that every ready entry of `plan` can be applied now. Nothing is
+/// changed apart from the probe files, which are removed again.
+pub fn preflight(plan: &RenamePlan) -> PreflightReport {
+    let mut problems = Vec::new();
+    // Folder to probe, with the entries that need it.
+    let mut folders: BTreeMap<&Path, Vec<usize>> = BTreeMap::new();
+    for entry in plan.ready() {
+        let Some(source_dir) = entry.source.parent() else {
+            continue;
+        };
+        let Ok(metadata) = fs::symlink_metadata(fsutil::long(&entry.source)) else {
+            problems.push(PreflightProblem {
+                kind: PreflightKind::SourceMissing,
+                path: entry.source.clone(),
+                entries: vec![entry.id],
+                message: "the file no longer exists".to_string(),
+                needs_elevation: false,
+            });
+            continue;
+        };
+        let writes = entry.modified.is_some() || entry.title.is_some();
+        if writes && metadata.permissions().readonly() {
+            problems.push(PreflightProblem {
+                kind: PreflightKind::ReadOnly,
+                path: entry.source.clone(),
+                entries: vec![entry.id],
+                message: "the file is read-only".to_string(),
+                needs_elevation: false,
+            });
+        }
+        if entry.source == entry.target {
+            continue;
+        }
+        if not_owner(source_dir, &entry.source) {
+            problems.push(PreflightProblem {
+                kind: PreflightKind::NotOwner,
+                path: entry.source.clone(),
+                entries: vec![entry.id],
+                message: "only the file's owner may rename it in this folder".to_string(),
+                needs_elevation: true,
+            });
+        }
+        folders.entry(source_dir).or_default().push(entry.id);
+        if let Some(target_dir) = entry.target.parent().and_then(existing_folder) {
+            if target_dir != source_dir {
+                folders.entry(target_dir).or_default().push(entry.id);
+            }
+        }
+    }
+
+    let probed: Vec<PreflightProblem> = folders
+        .par_iter()
+        .filter_map(|(dir, entries)| {
+            let (kind, error) = probe(dir)?;
+            Some(PreflightProblem {
+                kind,
+                path: dir.to_path_buf(),
+                entries: entries.clone(),
+                message: error.to_string(),
+                needs_elevation: error.kind() == io::ErrorKind::PermissionDenied,
+            })
+        })
+        .collect();
+    problems.extend(probed);
+
+    let (elevated, others): (Vec<_>, Vec<_>) = problems.iter().partition(|p| p.needs_elevation);
+    let unfixable: BTreeSet<usize> = others.iter().flat_map(|p| p.entries.clone()).collect();
+    let elevatable: BTreeSet<usize> = elevated
+        .iter()
+        .flat_map(|p| p.entries.clone())
+        .filter(|id| !unfixable.contains(id))
+        .collect();
+    PreflightReport {
+        blocked: !problems.is_empty(),
+        problems,
+        folders: folders.len(),
+        elevatable: elevatable.into_iter().collect(),
+    }
+}
@@ -0,0 +1,330 @@
+//! Named rule chains ("Photo import", "Podcast episodes") kept between
+//! sessions, so switching between them is one click instead of rebuilding
+//! the chain.
+//!
+//! Presets are stored as one JSON document with a format version next to
+//! them. Files written by a newer version are refused rather than read and
+//! then overwritten with whatever this version understands.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::executor::{apply, ApplyOptions, ApplyReport};
+use crate::locale;
+use crate::plan::{plan, EntryStatus, PlanOptions, RenamePlan};
+use crate::post_action::PostAction;
+use crate::preview::{preview_bursts, PreviewRow};
+use crate::rules::Rule;
+use crate::scan::{Filter, ScanOptions};
+use crate::scope::PathScope;
+
+/// Format of the presets file this version reads and writes.
+pub const PRESETS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    /// Unique, ignoring case.
+    pub name: String,
+    pub rules: Vec<Rule>,
+    /// Collision handling and the rest of planning, for chains that depend
+    /// on them (companion files, a target filesystem).
+    #[serde(default)]
+    pub plan_options: PlanOptions,
+    /// A program to run on what the preset renamed.
+    #[serde(default)]
+    pub post_action: Option<PostAction>,
+    /// Where renamed files go, under their new names, instead of staying
+    /// in their folders.
+    #[serde(default)]
+    pub target_dir: Option<PathBuf>,
+    /// What folders are scanned with for this preset. Its patterns also pick
+    /// which of the files handed to the preset it renames.
+    #[serde(default)]
+    pub scan_options: Option<ScanOptions>,
+    /// The folders the preset may rename in and move files into; anywhere
+    /// when empty. A file outside them fails the whole run, so a preset for
+    /// one folder cannot be run on another by mistake.
+    #[serde(default)]
+    pub allowed_scope: Vec<PathBuf>,
+    /// The language tag month names and decimal separators are written in,
+    /// in place of the one in the settings; see [`crate::locale`].
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Preset {
+    pub fn new(name: impl Into<String>, rules: Vec<Rule>) -> Self {
+        Preset {
+            name: name.into(),
+            rules,
+            plan_options: PlanOptions::default(),
+            post_action: None,
+            target_dir: None,
+            scan_options: None,
+            allowed_scope: Vec::new(),
+            locale: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Fails with [`Error::PresetScope`] on the first of `paths` outside
+    /// [`Preset::allowed_scope`]. A folder in it that does not exist allows
+    /// nothing.
+    pub fn check_scope<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> Result<()> {
+        if self.allowed_scope.is_empty() {
+            return Ok(());
+        }
+        let mut scope = PathScope::new();
+        for dir in &self.allowed_scope {
+            scope.allow(dir);
+        }
+        match paths.into_iter().find(|p| !scope.allows(p)) {
+            Some(path) => Err(Error::PresetScope {
+                preset: self.name.clone(),
+                path: path.to_path_buf(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The files of `paths` the preset renames: all of them, checked against
+    /// its scope, less those its scan patterns leave out.
+    pub fn select(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        self.check_scope(paths.iter().map(PathBuf::as_path))?;
+        let Some(options) = &self.scan_options else {
+            return Ok(paths.to_vec());
+        };
+        let filter = Filter::new(&options.patterns)?;
+        Ok(paths
+            .iter()
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                !filter.excluded(&name, &name) && filter.included(&name, &name)
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Runs `f` in [`Preset::locale`], if the preset has one.
+    pub fn localized<T>(&self, f: impl FnOnce() -> T) -> Result<T> {
+        let locale = self.locale.as_deref().map(locale::parse).transpose()?;
+        Ok(locale::scoped(locale, f))
+    }
+
+    /// Sends `row` to [`Preset::target_dir`], if the preset has one.
+    pub fn place(&self, row: &mut PreviewRow) {
+        if let Some(dir) = &self.target_dir {
+            row.target = dir.join(&row.new_name);
+            row.changed |= row.target != row.source;
+        }
+    }
+
+    /// The plan for renaming `paths` with this preset's rules and planning
+    /// options, without renaming anything. It has no entries for files the
+    /// rules leave alone, and fails if a file or where it would go is
+    /// outside the preset's scope.
+    pub fn plan(&self, paths: &[PathBuf]) -> Result<RenamePlan> {
+        let paths = self.select(paths)?;
+        let mut rows =
+            self.localized(|| preview_bursts(&paths, &self.rules, &self.plan_options.bursts))??;
+        rows.iter_mut().for_each(|row| self.place(row));
+        let ops: Vec<_> = rows
+            .iter()
+            .filter(|row| row.changed)
+            .map(PreviewRow::to_op)
+            .collect();
+        let plan = plan(ops, &self.plan_options);
+        self.check_scope(
+            plan.entries
+                .iter()
+                .flat_map(|e| [e.source.as_path(), e.target.as_path()]),
+        )?;
+        Ok(plan)
+    }
+
+    /// Renames `paths` with this preset's rules and planning options, as
+    /// one batch. `None` if the rules change nothing.
+    pub fn run(
+        &self,
+        paths: &[PathBuf],
+        apply_options: &ApplyOptions,
+        journal_dir: &Path,
+    ) -> Result<Option<ApplyReport>> {
+        let plan = self.plan(paths)?;
+        if plan.entries.is_empty() {
+            return Ok(None);
+        }
+        apply(&plan, apply_options, journal_dir).map(Some)
+    }
+}
+
+/// What one preset's plan does with a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedName {
+    /// Where the file would end up; its own path if the preset leaves it
+    /// alone.
+    pub target: PathBuf,
+    /// The entry's status in the plan, `None` if the preset's rules do not
+    /// change the name.
+    pub status: Option<EntryStatus>,
+}
+
+/// One file of [`compare_presets`], with what each preset does with it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PresetComparison {
+    pub source: PathBuf,
+    pub a: PlannedName,
+    pub b: PlannedName,
+    /// Both presets move the file to the same place, or both leave it.
+    pub same: bool,
+}
+
+/// Plans `paths` with both presets and lines up the results by file, in
+/// the order of `paths`, followed by the files only a plan brought in, such
+/// as companions. Nothing is renamed; a preset rewritten to do the same as
+/// another has every row `same`.
+pub fn compare_presets(a: &Preset, b: &Preset, paths: &[PathBuf]) -> Result<Vec<PresetComparison>> {
+    let (plan_a, plan_b) = (a.plan(paths)?, b.plan(paths)?);
+    let planned = |plan: &RenamePlan| -> HashMap<PathBuf, PlannedName> {
+        plan.entries
+            .iter()
+            .map(|e| {
+                let name = PlannedName {
+                    target: e.target.clone(),
+                    status: Some(e.status),
+                };
+                (e.source.clone(), name)
+            })
+            .collect()
+    };
+    let (by_a, by_b) = (planned(&plan_a), planned(&plan_b));
+
+    let mut sources: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+    sources.extend(
+        plan_a
+            .entries
+            .iter()
+            .chain(&plan_b.entries)
+            .map(|e| e.source.as_path()),
+    );
+    let mut seen = HashSet::new();
+    sources.retain(|source| seen.insert(*source));
+
+    let unchanged = |source: &Path| PlannedName {
+        target: source.to_path_buf(),
+        status: None,
+    };
+    Ok(sources
+        .into_iter()
+        .map(|source| {
+            let a = by_a
+                .get(source)
+                .cloned()
+                .unwrap_or_else(|| unchanged(source));
+            let b = by_b
+                .get(source)
+                .cloned()
+                .unwrap_or_else(|| unchanged(source));
+            PresetComparison {
+                source: source.to_path_buf(),
+                same: a == b,
+                a,
+                b,
+            }
+        })
+        .collect())
+}
+
+#[derive(Serialize, Deserialize)]
+struct PresetsFile {
+    version: u32,
+    presets: Vec<Preset>,
+}
+
+/// On-disk collection of presets, sorted by name.
+#[derive(Debug)]
+pub struct PresetStore {
+    path: PathBuf,
+    presets: Vec<Preset>,
+}
+
+impl PresetStore {
+    /// Loads the presets at `path`; a missing file is an empty store.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let presets = match fs::read(&path) {
+            Ok(bytes) => {
+                let file: PresetsFile = serde_json::from_slice(&bytes)?;
+                if file.version > PRESETS_VERSION {
+                    return Err(Error::UnsupportedVersion {
+                        path,
+                        version: file.version,
+                    });
+                }
+                file.presets
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(Error::io(&path, e)),
+        };
+        Ok(PresetStore { path, presets })
+    }
+
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let json = serde_json::to_vec_pretty(&PresetsFile {
+            version: PRESETS_VERSION,
+            presets: self.presets.clone(),
+        })?;
+        fs::write(&self.path, json).map_err(|e| Error::io(&self.path, e))
+    }
+
+    /// Stores `preset`, replacing the one with the same name if there is
+    /// one. The name is trimmed and must not be empty, and the arguments of
+    /// its post action must be valid templates.
+    pub fn insert(&mut self, mut preset: Preset) -> Result<()> {
+        preset.name = preset.name.trim().to_string();
+        if preset.name.is_empty() {
+            return Err(Error::InvalidPresetName);
+        }
+        if let Some(action) = &preset.post_action {
+            action.check()?;
+        }
+        preset.updated_at = Utc::now();
+        self.presets
+            .retain(|p| !p.name.eq_ignore_ascii_case(&preset.name));
+        self.presets.push(preset);
+        self.presets.sort_by_key(|p| p.name.to_lowercase());
+        self.save()
+    }
+
+    /// Removes the preset called `name`; false if there was none.
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        let before = self.presets.len();
+        self.presets
+            .retain(|p| !p.name.eq_ignore_ascii_case(name.trim()));
+        if self.presets.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+}
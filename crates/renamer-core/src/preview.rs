@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::burst::{self, BurstAction, BurstOptions, BurstShot};
+use crate::condition::ConditionCheck;
+use crate::error::Result;
+use crate::hash;
+use crate::metadata::{self, DateSource};
+use crate::normalize::{self, Normalization};
+use crate::ocr;
+use crate::plan::RenameOp;
+use crate::rules::{Pipeline, Renamed, Rule, StepName};
+use crate::sanitize::{self, SanitizeFix, TargetFs};
+use crate::scan::ScanEntry;
+use crate::sniff::{self, ExtensionFix};
+use crate::template::{assign_groups, burst_contexts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewWarning {
+    /// The rules produced an empty name.
+    EmptyName,
+    /// The name contains a path separator and would move the file.
+    ContainsSeparator,
+    /// Leading or trailing whitespace, usually a rule mistake.
+    SurroundingWhitespace,
+    /// `.` or `..`, which can never be a file name.
+    ReservedDotName,
+    /// A device name such as `CON` or `com1.txt`, which Windows cannot store.
+    ReservedDeviceName,
+    /// A character this platform's filesystems refuse, such as `:` on
+    /// Windows.
+    ForbiddenCharacter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewRow {
+    pub id: usize,
+    pub source: PathBuf,
+    pub new_name: String,
+    pub target: PathBuf,
+    /// The name or folder changes, or a rule sets the modification time or
+    /// title.
+    pub changed: bool,
+    /// Another row in the same preview ends up at this target.
+    pub conflict: bool,
+    pub warnings: Vec<PreviewWarning>,
+    /// Unicode form of the current name, if it contains characters that can
+    /// be composed or decomposed. `mixed` usually means the name was edited
+    /// on a different platform than it was created on.
+    pub normalization: Option<Normalization>,
+    pub is_symlink: bool,
+    /// What the link points to, as stored in the link.
+    pub link_target: Option<PathBuf>,
+    /// Modification time a [`Rule::SetModified`] found in the name.
+    pub set_modified: Option<DateTime<Utc>>,
+    /// Changes a [`Rule::Sanitize`] made to fit the target filesystem.
+    pub sanitized: Vec<SanitizeFix>,
+    /// The extension a [`Rule::FixExtension`] replaced to match the contents.
+    pub extension_fix: Option<ExtensionFix>,
+    /// A [`Rule::Truncate`] cut the name to fit.
+    #[serde(default)]
+    pub shortened: bool,
+    /// Where the rules' `{best_date}` took the date from, e.g. `exif` or
+    /// `modified`.
+    #[serde(default)]
+    pub date_source: Option<DateSource>,
+    /// The burst the file is a shot of; see [`preview_bursts`].
+    #[serde(default)]
+    pub burst: Option<BurstShot>,
+    /// Title a [`Rule::WriteTitle`] writes into the file once renamed.
+    pub write_title: Option<String>,
+    /// Which conditions of each [`Rule::When`] held, to debug rule chains.
+    pub conditions: Vec<ConditionCheck>,
+    /// Why [`Rule::Script`] and [`Rule::Plugin`] steps left the name as it
+    /// was.
+    pub step_errors: Vec<String>,
+    /// The name after each rule, to show how the chain got to `new_name`.
+    pub steps: Vec<StepName>,
+    /// `new_name` was typed in by hand instead of coming from the rules;
+    /// see [`apply_overrides`].
+    #[serde(default)]
+    pub overridden: bool,
+}
+
+impl PreviewRow {
+    pub fn to_op(&self) -> RenameOp {
+        RenameOp {
+            modified: self.set_modified,
+            title: self.write_title.clone(),
+            shortened: self.shortened,
+            ..RenameOp::new(&self.source, &self.target)
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn validate(name: &str) -> Vec<PreviewWarning> {
+    let mut warnings = Vec::new();
+    if name.is_empty() {
+        warnings.push(PreviewWarning::EmptyName);
+        return warnings;
+    }
+    if name.contains(['/', '\\']) {
+        warnings.push(PreviewWarning::ContainsSeparator);
+    }
+    if name.trim() != name {
+        warnings.push(PreviewWarning::SurroundingWhitespace);
+    }
+    if name == "." || name == ".." {
+        warnings.push(PreviewWarning::ReservedDotName);
+    }
+    if sanitize::is_device_name(name) {
+        warnings.push(PreviewWarning::ReservedDeviceName);
+    }
+    let filesystem = TargetFs::host();
+    if name
+        .chars()
+        .any(|c| !matches!(c, '/' | '\\') && filesystem.forbids(c))
+    {
+        warnings.push(PreviewWarning::ForbiddenCharacter);
+    }
+    warnings
+}
+
+/// Marks the rows that end up at the same target as another.
+fn mark_conflicts(rows: &mut [PreviewRow]) {
+    let mut seen: HashMap<PathBuf, usize> = HashMap::new();
+    for row in rows.iter() {
+        *seen.entry(row.target.clone()).or_default() += 1;
+    }
+    for row in rows {
+        row.conflict = seen[&row.target] > 1;
+    }
+}
+
+/// Gives the rows whose source is in `overrides` the name typed in for it
+/// in place of the rules', in the folder the rules chose. The names are
+/// checked like the rules' are, and conflicts between rows are worked out
+/// again, so a hand edit that collides is flagged like any other; planning
+/// then checks them against the filesystem.
+pub fn apply_overrides(rows: &mut [PreviewRow], overrides: &HashMap<PathBuf, String>) {
+    if overrides.is_empty() {
+        return;
+    }
+    for row in rows.iter_mut() {
+        let Some(name) = overrides.get(&row.source) else {
+            continue;
+        };
+        row.target = row.target.with_file_name(name);
+        row.changed =
+            row.target != row.source || row.set_modified.is_some() || row.write_title.is_some();
+        row.warnings = validate(name);
+        row.new_name = name.clone();
+        row.shortened = false;
+        row.overridden = true;
+    }
+    mark_conflicts(rows);
+}
+
+/// Computes new names for `paths` by running `rules` over each file name.
+///
+/// Nothing is ever written to disk, and file metadata is only read when a rule
+/// needs it (e.g. `{size}` in a template, read in parallel for `{exif.*}`,
+/// `{hash}` and `{ocr.*}`, and for [`Rule::FixExtension`]);
+/// symlinks are always reported. Conflicts are only detected between
+/// rows of the preview itself; use [`crate::plan`] to check the result against
+/// the filesystem. Fails only if a rule cannot be compiled.
+pub fn preview(paths: &[PathBuf], rules: &[Rule]) -> Result<Vec<PreviewRow>> {
+    rows(paths, rules, None, None)
+}
+
+/// [`preview`] with the bursts among `paths` found first (see
+/// [`crate::burst`]), each numbered once. Their shots get a letter after
+/// every counter, or, with [`BurstAction::Skip`], all but the first are left
+/// as they are; either way [`PreviewRow::burst`] tells which burst a row is
+/// in, for the UI to show them together.
+pub fn preview_bursts(
+    paths: &[PathBuf],
+    rules: &[Rule],
+    options: &BurstOptions,
+) -> Result<Vec<PreviewRow>> {
+    let options = Some(options).filter(|o| !o.is_off());
+    rows(paths, rules, None, options)
+}
+
+/// [`preview`] of files listed elsewhere, such as on a remote host: their
+/// size, modification time and link target are taken from `entries`, and
+/// nothing is read from inside them, so tokens that need their contents
+/// come out empty.
+pub(crate) fn preview_listed(entries: &[ScanEntry], rules: &[Rule]) -> Result<Vec<PreviewRow>> {
+    let paths: Vec<PathBuf> = entries.iter().map(|e| e.path.clone()).collect();
+    rows(&paths, rules, Some(entries), None)
+}
+
+fn rows(
+    paths: &[PathBuf],
+    rules: &[Rule],
+    listed: Option<&[ScanEntry]>,
+    bursts: Option<&BurstOptions>,
+) -> Result<Vec<PreviewRow>> {
+    let pipeline = Pipeline::new(rules)?;
+    let local = listed.is_none();
+    let by_time = bursts.is_some_and(|b| b.within_secs.is_some());
+    let mut embedded = if local && (pipeline.needs_embedded() || by_time) {
+        metadata::extract_all(paths).into_iter().map(Some).collect()
+    } else {
+        vec![None; paths.len()]
+    };
+    let mut content_types: Vec<_> = if local && pipeline.needs_content_type() {
+        paths.par_iter().map(|path| sniff::sniff(path)).collect()
+    } else {
+        vec![None; paths.len()]
+    };
+    let mut texts: Vec<_> = if local && pipeline.needs_ocr() {
+        paths.par_iter().map(|path| ocr::recognize(path)).collect()
+    } else {
+        vec![None; paths.len()]
+    };
+    let algorithms = pipeline.hash_algorithms();
+    let mut hashes: Vec<_> = if !local || algorithms.is_empty() {
+        vec![Vec::new(); paths.len()]
+    } else {
+        paths
+            .par_iter()
+            .map(|path| hash::hash_with(path, &algorithms))
+            .collect()
+    };
+    let found = match bursts {
+        Some(options) => burst::group(paths, &embedded, options),
+        None => Vec::new(),
+    };
+    let skip = bursts.is_some_and(|b| b.action == BurstAction::Skip);
+    let mut contexts: Vec<_> = burst_contexts(paths, &found, !skip)
+        .into_iter()
+        .enumerate()
+        .map(|(id, mut ctx)| {
+            ctx.embedded = embedded[id].take();
+            ctx.hashes = std::mem::take(&mut hashes[id]);
+            ctx.content_type = content_types[id].take();
+            ctx.ocr = texts[id].take();
+            match listed {
+                Some(entries) => {
+                    ctx.size = Some(entries[id].size);
+                    ctx.modified = entries[id].modified.map(DateTime::<Local>::from);
+                }
+                None if pipeline.needs_metadata() => ctx = ctx.with_metadata(),
+                None => {}
+            }
+            ctx
+        })
+        .collect();
+    assign_groups(&mut contexts, &pipeline.templates());
+    let groups: HashMap<usize, usize> = contexts
+        .iter()
+        .filter_map(|ctx| ctx.burst)
+        .filter(|place| place.shot == 0)
+        .enumerate()
+        .map(|(group, place)| (place.first, group))
+        .collect();
+    let mut rows: Vec<PreviewRow> = contexts
+        .into_iter()
+        .enumerate()
+        .map(|(id, ctx)| {
+            let source = ctx.path;
+            let original = file_name(source);
+            let burst = ctx.burst.map(|place| BurstShot {
+                group: groups[&place.first],
+                shot: place.shot,
+                skipped: skip && place.shot > 0,
+            });
+            let renamed = match burst {
+                Some(BurstShot { skipped: true, .. }) => Renamed {
+                    name: original.clone(),
+                    ..Renamed::default()
+                },
+                _ => pipeline.run(&original, &ctx),
+            };
+            let new_name = renamed.name;
+            let set_modified = renamed.modified.map(DateTime::<Utc>::from);
+            let link_target = match listed {
+                Some(entries) => entries[id].link_target.clone(),
+                None => fs::read_link(source).ok(),
+            };
+            let target = match &renamed.folder {
+                Some(folder) => folder.join(&new_name),
+                None => source.with_file_name(&new_name),
+            };
+            PreviewRow {
+                id,
+                normalization: normalize::detect(&original),
+                is_symlink: link_target.is_some(),
+                link_target,
+                source: source.to_path_buf(),
+                changed: target != source || set_modified.is_some() || renamed.title.is_some(),
+                set_modified,
+                sanitized: renamed.sanitized,
+                extension_fix: renamed.extension_fix,
+                shortened: renamed.shortened,
+                date_source: renamed.date_source,
+                burst,
+                write_title: renamed.title,
+                conditions: renamed.conditions,
+                step_errors: renamed.step_errors,
+                steps: renamed.steps,
+                warnings: validate(&new_name),
+                new_name,
+                target,
+                conflict: false,
+                overridden: false,
+            }
+        })
+        .collect();
+    mark_conflicts(&mut rows);
+    Ok(rows)
+}
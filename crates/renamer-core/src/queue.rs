@@ -0,0 +1,555 @@
+//! A queue of rename, scan, hash and import jobs that run in the background, a few at
+//! a time, and of previews and thumbnails the user is waiting for.
+//!
+//! Jobs run in one of two [`Lane`]s. Background jobs start in the order they
+//! are queued, at most [`QueueSnapshot::max_parallel`] at once; interactive
+//! ones, previews and thumbnails unless queued otherwise, have slots of
+//! their own and go first: no background job starts while one waits, and
+//! running ones make way for them at their next read or write, see
+//! [`throttle::interactive`]. Pausing the queue, or holding a
+//! single job, keeps jobs from starting but lets running ones finish: a batch
+//! of renames is never stopped half way except by cancelling it. Waiting jobs
+//! can be moved to change which starts next.
+//!
+//! Every change to the queue is reported as a [`QueueEvent::Updated`] with the
+//! whole queue, and each finished job's results as [`QueueEvent::Finished`].
+//! Background jobs keep to [`crate::throttle`].
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::cancel::CancelToken;
+use crate::executor::{self, ApplyOptions, ApplyReport};
+use crate::hash::{self, FileHash, HashAlgorithm};
+use crate::ingest::{self, IngestOptions, IngestReport};
+use crate::plan::{self, PlanOptions, RenameOp};
+use crate::preview::{self, PreviewRow};
+use crate::rules::Rule;
+use crate::scan::{ScanOptions, ScanPage, Scanner};
+use crate::throttle;
+use crate::thumbnail::{self, Thumbnail};
+
+/// Interactive jobs running at once, besides background ones.
+pub const INTERACTIVE_PARALLEL: usize = 2;
+
+/// Something to do in the background.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Job {
+    /// Plans and applies `ops` as one batch.
+    Rename {
+        ops: Vec<RenameOp>,
+        #[serde(default)]
+        plan_options: PlanOptions,
+        #[serde(default)]
+        apply_options: ApplyOptions,
+    },
+    /// Lists the files under `root`.
+    Scan {
+        root: PathBuf,
+        #[serde(default)]
+        options: ScanOptions,
+    },
+    /// Hashes the contents of `paths`.
+    Hash {
+        paths: Vec<PathBuf>,
+        algorithm: HashAlgorithm,
+    },
+    /// Copies the files under `source`, such as a memory card, into a
+    /// folder under new names.
+    Ingest {
+        source: PathBuf,
+        options: IngestOptions,
+    },
+    /// The new names `rules` give `paths`.
+    Preview {
+        paths: Vec<PathBuf>,
+        rules: Vec<Rule>,
+    },
+    /// Previews of the images at `paths`, at most `size` pixels on either
+    /// side, cached in `cache_dir`.
+    Thumbnails {
+        paths: Vec<PathBuf>,
+        size: u32,
+        #[serde(default)]
+        cache_dir: PathBuf,
+    },
+}
+
+impl Job {
+    fn kind(&self) -> JobKind {
+        match self {
+            Job::Rename { .. } => JobKind::Rename,
+            Job::Scan { .. } => JobKind::Scan,
+            Job::Hash { .. } => JobKind::Hash,
+            Job::Ingest { .. } => JobKind::Ingest,
+            Job::Preview { .. } => JobKind::Preview,
+            Job::Thumbnails { .. } => JobKind::Thumbnails,
+        }
+    }
+
+    /// The lane the job runs in unless queued in another.
+    fn lane(&self) -> Lane {
+        match self {
+            Job::Preview { .. } | Job::Thumbnails { .. } => Lane::Interactive,
+            _ => Lane::Background,
+        }
+    }
+
+    /// How many steps the job has, when that is known up front.
+    fn total(&self) -> Option<usize> {
+        match self {
+            Job::Rename { ops, .. } => Some(ops.len()),
+            Job::Scan { .. } => None,
+            Job::Hash { paths, .. } => Some(paths.len()),
+            Job::Ingest { .. } => None,
+            Job::Preview { paths, .. } | Job::Thumbnails { paths, .. } => Some(paths.len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Rename,
+    Scan,
+    Hash,
+    Ingest,
+    Preview,
+    Thumbnails,
+}
+
+/// How soon a job runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lane {
+    /// Something the user is looking at waits for it.
+    Interactive,
+    /// Nobody waits for it; it makes way for interactive jobs.
+    Background,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for its turn.
+    Queued,
+    /// Waiting, but kept from starting until released.
+    Held,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_waiting(self) -> bool {
+        matches!(self, JobStatus::Queued | JobStatus::Held)
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Done | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// A job as the queue panel shows it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub job_id: Uuid,
+    pub label: String,
+    pub kind: JobKind,
+    pub lane: Lane,
+    pub status: JobStatus,
+    /// Steps (files) finished so far, and in total when known.
+    pub completed: usize,
+    pub total: Option<usize>,
+    pub queued_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+/// How busy a lane is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaneInfo {
+    pub lane: Lane,
+    pub running: usize,
+    /// Jobs queued and not held.
+    pub waiting: usize,
+    pub max_parallel: usize,
+}
+
+/// The whole queue: running jobs, then waiting ones in the order they will
+/// start, then finished ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub paused: bool,
+    /// Background jobs running at once.
+    pub max_parallel: usize,
+    /// The interactive lane, then the background one.
+    pub lanes: Vec<LaneInfo>,
+    /// Background jobs are making way for interactive work, the queue's or
+    /// any other.
+    pub yielding: bool,
+    pub jobs: Vec<JobInfo>,
+}
+
+/// What a finished job produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "result", rename_all = "snake_case")]
+pub enum JobOutput {
+    Renamed(ApplyReport),
+    Scanned(ScanPage),
+    Hashed(Vec<FileHash>),
+    Ingested(IngestReport),
+    Previewed(Vec<PreviewRow>),
+    /// One for each path, `None` where there is no preview.
+    Thumbnails(Vec<Option<Thumbnail>>),
+}
+
+#[derive(Debug, Clone)]
+pub enum QueueEvent {
+    Updated(QueueSnapshot),
+    Finished { job_id: Uuid, output: JobOutput },
+}
+
+struct Entry {
+    info: JobInfo,
+    /// Taken by the worker when the job starts.
+    job: Option<Job>,
+    cancel: CancelToken,
+}
+
+struct State {
+    paused: bool,
+    max_parallel: usize,
+    entries: Vec<Entry>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    journal_dir: PathBuf,
+    on_event: Box<dyn Fn(QueueEvent) + Send + Sync>,
+}
+
+/// Handle to a job queue; clones share the queue.
+#[derive(Clone)]
+pub struct JobQueue {
+    shared: Arc<Shared>,
+}
+
+impl JobQueue {
+    /// An empty queue running up to `max_parallel` jobs at once, journaling
+    /// rename batches in `journal_dir`.
+    pub fn new(
+        journal_dir: impl Into<PathBuf>,
+        max_parallel: usize,
+        on_event: impl Fn(QueueEvent) + Send + Sync + 'static,
+    ) -> Self {
+        JobQueue {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    paused: false,
+                    max_parallel: max_parallel.max(1),
+                    entries: Vec::new(),
+                }),
+                journal_dir: journal_dir.into(),
+                on_event: Box::new(on_event),
+            }),
+        }
+    }
+
+    /// Runs `change` on the queue, starts whatever may start now and reports
+    /// the new queue.
+    fn update<R>(&self, change: impl FnOnce(&mut State) -> R) -> R {
+        let (result, snapshot) = {
+            let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+            let result = change(&mut state);
+            self.start_ready(&mut state);
+            (result, snapshot(&state))
+        };
+        (self.shared.on_event)(QueueEvent::Updated(snapshot));
+        result
+    }
+
+    fn start_ready(&self, state: &mut State) {
+        if state.paused {
+            return;
+        }
+        self.start_lane(state, Lane::Interactive, INTERACTIVE_PARALLEL);
+        if count(state, Lane::Interactive, JobStatus::Queued) == 0 {
+            let max_parallel = state.max_parallel;
+            self.start_lane(state, Lane::Background, max_parallel);
+        }
+    }
+
+    fn start_lane(&self, state: &mut State, lane: Lane, max_parallel: usize) {
+        let mut running = count(state, lane, JobStatus::Running);
+        for entry in &mut state.entries {
+            if running >= max_parallel {
+                break;
+            }
+            if entry.info.lane != lane || entry.info.status != JobStatus::Queued {
+                continue;
+            }
+            let Some(job) = entry.job.take() else {
+                continue;
+            };
+            entry.info.status = JobStatus::Running;
+            entry.info.started_at = Some(Utc::now());
+            running += 1;
+            let queue = self.clone();
+            let job_id = entry.info.job_id;
+            let cancel = entry.cancel.clone();
+            thread::spawn(move || queue.run(job_id, job, lane, cancel));
+        }
+    }
+
+    fn run(&self, job_id: Uuid, job: Job, lane: Lane, cancel: CancelToken) {
+        let progress = |completed: usize| {
+            self.update(|state| {
+                if let Some(entry) = find(state, job_id) {
+                    entry.info.completed = completed;
+                }
+            })
+        };
+        let work = || match job {
+            Job::Rename {
+                ops,
+                plan_options,
+                apply_options,
+            } => {
+                let plan = plan::plan(ops, &plan_options);
+                executor::apply_with_progress(
+                    &plan,
+                    &apply_options,
+                    &self.shared.journal_dir,
+                    &|p| progress(p.completed),
+                    &cancel,
+                )
+                .map(JobOutput::Renamed)
+                .map_err(|e| e.to_string())
+            }
+            Job::Scan { root, options } => Scanner::new(root, options)
+                .map(|scanner| {
+                    JobOutput::Scanned(scanner.with_cancel(cancel.clone()).next_page(usize::MAX))
+                })
+                .map_err(|e| e.to_string()),
+            Job::Hash { paths, algorithm } => Ok(JobOutput::Hashed(hash::hash_files(
+                &paths,
+                algorithm,
+                &|p| progress(p.completed),
+                &cancel,
+            ))),
+            Job::Ingest { source, options } => {
+                ingest::ingest(&source, &options, &|p| progress(p.completed), &cancel)
+                    .map(JobOutput::Ingested)
+                    .map_err(|e| e.to_string())
+            }
+            Job::Preview { paths, rules } => preview::preview(&paths, &rules)
+                .map(JobOutput::Previewed)
+                .map_err(|e| e.to_string()),
+            Job::Thumbnails {
+                paths,
+                size,
+                cache_dir,
+            } => {
+                let mut thumbnails = Vec::new();
+                for (i, path) in paths.iter().enumerate() {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    thumbnails.push(thumbnail::thumbnail(path, size, &cache_dir).ok());
+                    progress(i + 1);
+                }
+                Ok(JobOutput::Thumbnails(thumbnails))
+            }
+        };
+        let output = match lane {
+            Lane::Interactive => throttle::interactive(work),
+            Lane::Background => throttle::background(work),
+        };
+        self.update(|state| {
+            let Some(entry) = find(state, job_id) else {
+                return;
+            };
+            entry.info.finished_at = Some(Utc::now());
+            entry.info.status = match &output {
+                Err(e) => {
+                    entry.info.error = Some(e.clone());
+                    JobStatus::Failed
+                }
+                Ok(_) if cancel.is_cancelled() => JobStatus::Cancelled,
+                Ok(_) => JobStatus::Done,
+            };
+            if let Ok(JobOutput::Scanned(page)) = &output {
+                entry.info.completed = page.entries.len();
+            }
+        });
+        if let Ok(output) = output {
+            (self.shared.on_event)(QueueEvent::Finished { job_id, output });
+        }
+    }
+
+    /// Adds `job` to the end of its lane.
+    pub fn enqueue(&self, label: impl Into<String>, job: Job) -> Uuid {
+        let lane = job.lane();
+        self.enqueue_in(label, job, lane)
+    }
+
+    /// Adds `job` to the end of `lane`, such as a scan of the folder in view
+    /// to the interactive one.
+    pub fn enqueue_in(&self, label: impl Into<String>, job: Job, lane: Lane) -> Uuid {
+        let info = JobInfo {
+            job_id: Uuid::new_v4(),
+            label: label.into(),
+            kind: job.kind(),
+            lane,
+            status: JobStatus::Queued,
+            completed: 0,
+            total: job.total(),
+            queued_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+        };
+        let job_id = info.job_id;
+        self.update(|state| {
+            state.entries.push(Entry {
+                info,
+                job: Some(job),
+                cancel: CancelToken::new(),
+            })
+        });
+        job_id
+    }
+
+    /// Stops starting jobs; running ones finish.
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|state| state.paused = false);
+    }
+
+    /// Keeps a waiting job from starting (`held` set) or releases it. Returns
+    /// false if the job is not waiting.
+    pub fn hold(&self, job_id: Uuid, held: bool) -> bool {
+        self.update(|state| match find(state, job_id) {
+            Some(entry) if entry.info.status.is_waiting() => {
+                entry.info.status = if held {
+                    JobStatus::Held
+                } else {
+                    JobStatus::Queued
+                };
+                true
+            }
+            _ => false,
+        })
+    }
+
+    /// Moves a waiting job to `position` among the waiting jobs, 0 starting
+    /// next. Returns false if the job is not waiting.
+    pub fn reorder(&self, job_id: Uuid, position: usize) -> bool {
+        self.update(|state| {
+            let Some(from) = state
+                .entries
+                .iter()
+                .position(|e| e.info.job_id == job_id && e.info.status.is_waiting())
+            else {
+                return false;
+            };
+            let entry = state.entries.remove(from);
+            let waiting: Vec<usize> = (0..state.entries.len())
+                .filter(|&i| state.entries[i].info.status.is_waiting())
+                .collect();
+            let to = match waiting.get(position) {
+                Some(&i) => i,
+                None => waiting.last().map_or(state.entries.len(), |&i| i + 1),
+            };
+            state.entries.insert(to, entry);
+            true
+        })
+    }
+
+    /// Cancels a waiting job, or asks a running one to stop at its next safe
+    /// point. Returns false if the job has already finished.
+    pub fn cancel(&self, job_id: Uuid) -> bool {
+        self.update(|state| match find(state, job_id) {
+            Some(entry) if entry.info.status.is_waiting() => {
+                entry.info.status = JobStatus::Cancelled;
+                entry.info.finished_at = Some(Utc::now());
+                entry.job = None;
+                true
+            }
+            Some(entry) if entry.info.status == JobStatus::Running => {
+                entry.cancel.cancel();
+                true
+            }
+            _ => false,
+        })
+    }
+
+    /// How many background jobs run at once.
+    pub fn set_max_parallel(&self, max_parallel: usize) {
+        self.update(|state| state.max_parallel = max_parallel.max(1));
+    }
+
+    /// Forgets finished jobs.
+    pub fn clear_finished(&self) {
+        self.update(|state| state.entries.retain(|e| !e.info.status.is_finished()));
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        snapshot(&self.shared.state.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+fn find(state: &mut State, job_id: Uuid) -> Option<&mut Entry> {
+    state.entries.iter_mut().find(|e| e.info.job_id == job_id)
+}
+
+/// The jobs in `lane` with `status`.
+fn count(state: &State, lane: Lane, status: JobStatus) -> usize {
+    state
+        .entries
+        .iter()
+        .filter(|e| e.info.lane == lane && e.info.status == status)
+        .count()
+}
+
+fn snapshot(state: &State) -> QueueSnapshot {
+    let group = |job: &JobInfo| match (job.status, job.lane) {
+        (JobStatus::Running, _) => 0,
+        (JobStatus::Queued | JobStatus::Held, Lane::Interactive) => 1,
+        (JobStatus::Queued | JobStatus::Held, Lane::Background) => 2,
+        _ => 3,
+    };
+    let mut jobs: Vec<JobInfo> = state.entries.iter().map(|e| e.info.clone()).collect();
+    jobs.sort_by_key(group);
+    let lane = |lane, max_parallel| LaneInfo {
+        lane,
+        running: count(state, lane, JobStatus::Running),
+        waiting: count(state, lane, JobStatus::Queued),
+        max_parallel,
+    };
+    QueueSnapshot {
+        paused: state.paused,
+        max_parallel: state.max_parallel,
+        lanes: vec![
+            lane(Lane::Interactive, INTERACTIVE_PARALLEL),
+            lane(Lane::Background, state.max_parallel),
+        ],
+        yielding: throttle::yielding(),
+        jobs,
+    }
+}
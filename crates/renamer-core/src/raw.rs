@@ -0,0 +1,487 @@
+//! Camera raw files: their EXIF data and the JPEG previews cameras embed in
+//! them, read without loading the sensor data.
+//!
+//! Most formats (CR2, NEF, ARW, DNG, PEF, SRW and others) are TIFF files
+//! underneath, ORF and RW2 with a header of their own, holding the EXIF data
+//! near the start and the sensor data after it, so only the start is read
+//! for EXIF; previews are found by walking the IFDs and their sub-IFDs. CR3
+//! is an ISO base media file like MP4 instead, with the EXIF IFDs in boxes
+//! of their own under `moov` and a preview in a `PRVW` box, and RAF starts
+//! with a header pointing at a JPEG preview that carries the EXIF data.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use exif::{Context, Exif, Field, In, Reader, Tag};
+
+/// Extensions of camera raw files.
+pub(crate) const EXTENSIONS: &[&str] = &[
+    "cr2", "cr3", "nef", "nrw", "arw", "srf", "sr2", "dng", "orf", "rw2", "raf", "pef", "srw",
+    "x3f",
+];
+
+/// How much of a TIFF-based raw file is read for its EXIF data.
+const EXIF_PREFIX: u64 = 1 << 20;
+
+/// How much of a JPEG is read to find its size.
+const JPEG_HEAD: u64 = 64 << 10;
+
+/// Most IFDs walked in one file, against loops.
+const MAX_IFDS: usize = 32;
+
+/// The box under `moov` Canon keeps a CR3's metadata in.
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// The top-level box holding a CR3's `PRVW` preview.
+const PREVIEW_UUID: [u8; 16] = [
+    0xea, 0xf4, 0x2b, 0x5e, 0x1c, 0x98, 0x4b, 0x88, 0xb9, 0xfb, 0xb7, 0xdc, 0x40, 0x6e, 0x4d, 0x16,
+];
+
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW ";
+
+pub(crate) fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// The EXIF fields of a photo, looked up in IFD0 as `Exif::get_field`
+/// would.
+pub(crate) enum ExifFields {
+    /// The EXIF data of an image, read as a whole.
+    Whole(Exif),
+    /// The blocks a CR3 keeps IFD0, the EXIF IFD and the GPS IFD in. Each
+    /// is a TIFF of its own, so all their fields come out as IFD0 fields.
+    Blocks(Vec<(Context, Exif)>),
+}
+
+impl ExifFields {
+    pub(crate) fn get(&self, tag: Tag) -> Option<&Field> {
+        match self {
+            ExifFields::Whole(exif) => exif.get_field(tag, In::PRIMARY),
+            ExifFields::Blocks(blocks) => blocks
+                .iter()
+                .filter(|(context, _)| *context == tag.context())
+                .find_map(|(_, exif)| {
+                    exif.get_field(Tag(Context::Tiff, tag.number()), In::PRIMARY)
+                }),
+        }
+    }
+}
+
+enum Layout {
+    /// TIFF, from the header on; ORF and RW2 only differ in the magic
+    /// number.
+    Tiff {
+        little_endian: bool,
+    },
+    Cr3,
+    Raf,
+}
+
+fn layout(header: &[u8]) -> Option<Layout> {
+    match header {
+        [b'I', b'I', ..] => Some(Layout::Tiff {
+            little_endian: true,
+        }),
+        [b'M', b'M', ..] => Some(Layout::Tiff {
+            little_endian: false,
+        }),
+        [_, _, _, _, b'f', b't', b'y', b'p', b'c', b'r', b'x', b' ', ..] => Some(Layout::Cr3),
+        _ if header.starts_with(RAF_MAGIC) => Some(Layout::Raf),
+        _ => None,
+    }
+}
+
+pub(crate) fn read_at(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::new();
+    file.take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Parses TIFF data, keeping what could be read of data cut short.
+pub(crate) fn parse_tiff(mut data: Vec<u8>) -> Option<Exif> {
+    // The magic number of ORF and RW2, in place of TIFF's 42.
+    if data.len() >= 4 {
+        let forty_two = if data[0] == b'I' { [42, 0] } else { [0, 42] };
+        data[2..4].copy_from_slice(&forty_two);
+    }
+    match Reader::new().continue_on_error(true).read_raw(data) {
+        Ok(exif) => Some(exif),
+        Err(exif::Error::PartialResult(partial)) => Some(partial.into_inner().0),
+        Err(_) => None,
+    }
+}
+
+/// The EXIF data of the raw file at `path`.
+pub(crate) fn exif(path: &Path) -> Option<ExifFields> {
+    let mut file = File::open(path).ok()?;
+    let header = read_at(&mut file, 0, 16).ok()?;
+    match layout(&header)? {
+        Layout::Tiff { .. } => {
+            let exif = parse_tiff(read_at(&mut file, 0, EXIF_PREFIX).ok()?)?;
+            // Written with the EXIF IFD after the sensor data.
+            let len = file.metadata().ok()?.len();
+            if exif.get_field(Tag::ExifVersion, In::PRIMARY).is_none() && len > EXIF_PREFIX {
+                return parse_tiff(read_at(&mut file, 0, len).ok()?).map(ExifFields::Whole);
+            }
+            Some(ExifFields::Whole(exif))
+        }
+        Layout::Cr3 => {
+            let canon = cr3_canon_box(&mut file)?;
+            let blocks: Vec<(Context, Exif)> = [
+                (b"CMT1", Context::Tiff),
+                (b"CMT2", Context::Exif),
+                (b"CMT4", Context::Gps),
+            ]
+            .into_iter()
+            .filter_map(|(kind, context)| {
+                let found = boxes(&mut file, canon.0, canon.1)
+                    .into_iter()
+                    .find(|b| &b.kind == kind)?;
+                let data = read_at(&mut file, found.start, found.end - found.start).ok()?;
+                Some((context, parse_tiff(data)?))
+            })
+            .collect();
+            (!blocks.is_empty()).then_some(ExifFields::Blocks(blocks))
+        }
+        Layout::Raf => {
+            let (offset, len) = raf_preview(&mut file)?;
+            let jpeg = read_at(&mut file, offset, len.min(JPEG_HEAD * 4)).ok()?;
+            Reader::new()
+                .read_from_container(&mut Cursor::new(jpeg))
+                .ok()
+                .map(ExifFields::Whole)
+        }
+    }
+}
+
+/// A JPEG inside a raw file.
+#[derive(Debug, Clone, Copy)]
+struct Embedded {
+    offset: u64,
+    len: u64,
+    width: u32,
+    height: u32,
+}
+
+/// The embedded JPEG best suited to a preview `size` pixels on its longest
+/// side: the smallest that is at least that large, or the largest there is.
+pub(crate) fn preview(path: &Path, size: u32) -> Option<Vec<u8>> {
+    let mut file = File::open(path).ok()?;
+    let header = read_at(&mut file, 0, 16).ok()?;
+    let candidates = match layout(&header)? {
+        Layout::Tiff { little_endian } => tiff_jpegs(&mut file, little_endian),
+        Layout::Cr3 => cr3_jpegs(&mut file),
+        Layout::Raf => raf_preview(&mut file).into_iter().collect(),
+    };
+    let previews: Vec<Embedded> = candidates
+        .into_iter()
+        .filter_map(|(offset, len)| {
+            let head = read_at(&mut file, offset, len.min(JPEG_HEAD)).ok()?;
+            let (width, height) = jpeg_size(&head)?;
+            Some(Embedded {
+                offset,
+                len,
+                width,
+                height,
+            })
+        })
+        .collect();
+    let chosen = closest(previews, size, |p| (p.width, p.height))?;
+    read_at(&mut file, chosen.offset, chosen.len).ok()
+}
+
+/// The smallest of `previews` at least `size` pixels on its longest side,
+/// or the largest there is.
+pub(crate) fn closest<T>(
+    mut previews: Vec<T>,
+    size: u32,
+    dimensions: impl Fn(&T) -> (u32, u32),
+) -> Option<T> {
+    previews.sort_by_key(|p| {
+        let (width, height) = dimensions(p);
+        u64::from(width) * u64::from(height)
+    });
+    let at = previews
+        .iter()
+        .position(|p| {
+            let (width, height) = dimensions(p);
+            width.max(height) >= size
+        })
+        .unwrap_or(previews.len().checked_sub(1)?);
+    Some(previews.swap_remove(at))
+}
+
+/// The width and height of a JPEG from the frame header at its start, if
+/// it is one the `image` crate decodes: baseline or progressive, not the
+/// lossless JPEG some formats keep their sensor data in.
+pub(crate) fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut at = 2;
+    while at + 4 <= data.len() {
+        if data[at] != 0xff {
+            return None;
+        }
+        let marker = data[at + 1];
+        if marker == 0xff {
+            at += 1;
+            continue;
+        }
+        let len = usize::from(u16::from_be_bytes([data[at + 2], data[at + 3]]));
+        match marker {
+            0xc0..=0xc2 => {
+                let frame = data.get(at + 4..at + 9)?;
+                let height = u16::from_be_bytes([frame[1], frame[2]]);
+                let width = u16::from_be_bytes([frame[3], frame[4]]);
+                return (width > 0 && height > 0).then_some((width.into(), height.into()));
+            }
+            // Other frame types, e.g. lossless.
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return None,
+            _ => at += 2 + len,
+        }
+    }
+    None
+}
+
+/// Reads one IFD entry at a time from a TIFF file.
+struct Tiff<'a> {
+    file: &'a mut File,
+    little_endian: bool,
+}
+
+/// An IFD entry, with its value or value offset as stored.
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: [u8; 4],
+}
+
+impl Tiff<'_> {
+    fn u16(&self, bytes: [u8; 2]) -> u16 {
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: [u8; 4]) -> u32 {
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    /// The first value of a SHORT or LONG entry.
+    fn uint(&self, entry: &Entry) -> u32 {
+        match entry.kind {
+            3 => self.u16([entry.value[0], entry.value[1]]).into(),
+            _ => self.u32(entry.value),
+        }
+    }
+
+    /// The entries of the IFD at `offset` and the offset of the next one.
+    fn ifd(&mut self, offset: u64) -> io::Result<(Vec<Entry>, u32)> {
+        let count = read_at(self.file, offset, 2)?;
+        let count = self.u16(count.try_into().map_err(|_| io::ErrorKind::UnexpectedEof)?);
+        let data = read_at(self.file, offset + 2, u64::from(count) * 12 + 4)?;
+        if data.len() < usize::from(count) * 12 + 4 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let at = |i: usize| [data[i], data[i + 1], data[i + 2], data[i + 3]];
+        let entries = data
+            .chunks_exact(12)
+            .take(count.into())
+            .map(|e| Entry {
+                tag: self.u16([e[0], e[1]]),
+                kind: self.u16([e[2], e[3]]),
+                count: self.u32([e[4], e[5], e[6], e[7]]),
+                value: [e[8], e[9], e[10], e[11]],
+            })
+            .collect();
+        Ok((entries, self.u32(at(usize::from(count) * 12))))
+    }
+
+    /// The IFD offsets a SubIFDs entry lists.
+    fn sub_ifds(&mut self, entry: &Entry) -> Vec<u64> {
+        if entry.count == 1 {
+            return vec![self.uint(entry).into()];
+        }
+        let count = entry.count.min(MAX_IFDS as u32);
+        let Ok(data) = read_at(
+            self.file,
+            self.u32(entry.value).into(),
+            u64::from(count) * 4,
+        ) else {
+            return Vec::new();
+        };
+        data.chunks_exact(4)
+            .map(|c| self.u32([c[0], c[1], c[2], c[3]]).into())
+            .collect()
+    }
+}
+
+/// Where the JPEGs of a TIFF-based raw file are, as offset and length: the
+/// `JPEGInterchangeFormat` of any IFD, the strip of a JPEG-compressed one
+/// and the `JpgFromRaw` of RW2.
+fn tiff_jpegs(file: &mut File, little_endian: bool) -> Vec<(u64, u64)> {
+    let mut tiff = Tiff {
+        file,
+        little_endian,
+    };
+    let Ok(first) = read_at(tiff.file, 4, 4) else {
+        return Vec::new();
+    };
+    let Ok(first) = first.try_into() else {
+        return Vec::new();
+    };
+    let mut pending = vec![u64::from(tiff.u32(first))];
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || seen.len() >= MAX_IFDS || !seen.insert(offset) {
+            continue;
+        }
+        let Ok((entries, next)) = tiff.ifd(offset) else {
+            continue;
+        };
+        pending.push(next.into());
+        let value = |tag: u16| entries.iter().find(|e| e.tag == tag);
+        if let (Some(start), Some(len)) = (value(0x201), value(0x202)) {
+            found.push((tiff.uint(start).into(), tiff.uint(len).into()));
+        }
+        let compression = value(0x103).map(|e| tiff.uint(e));
+        if let (Some(6 | 7), Some(start), Some(len)) = (compression, value(0x111), value(0x117)) {
+            if start.count == 1 {
+                found.push((tiff.uint(start).into(), tiff.uint(len).into()));
+            }
+        }
+        if let Some(jpeg) = value(0x2e).filter(|e| e.kind == 7) {
+            found.push((tiff.u32(jpeg.value).into(), jpeg.count.into()));
+        }
+        if let Some(subs) = value(0x14a) {
+            let subs = tiff.sub_ifds(subs);
+            pending.extend(subs);
+        }
+    }
+    found.retain(|&(_, len)| len > 0);
+    found
+}
+
+/// A box of an ISO base media file, by where its payload starts and ends.
+pub(crate) struct Mp4Box {
+    pub(crate) kind: [u8; 4],
+    pub(crate) uuid: Option<[u8; 16]>,
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+}
+
+/// The boxes between `start` and `end`.
+pub(crate) fn boxes(file: &mut File, start: u64, end: u64) -> Vec<Mp4Box> {
+    let mut found = Vec::new();
+    let mut at = start;
+    while at + 8 <= end {
+        let Ok(header) = read_at(file, at, 32) else {
+            break;
+        };
+        if header.len() < 8 {
+            break;
+        }
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = [header[4], header[5], header[6], header[7]];
+        let (mut payload, size) = match size {
+            0 => (at + 8, end - at),
+            1 if header.len() >= 16 => {
+                let large = u64::from_be_bytes(header[8..16].try_into().unwrap());
+                (at + 16, large)
+            }
+            size => (at + 8, u64::from(size)),
+        };
+        let box_end = at.saturating_add(size).min(end);
+        if size < 8 || payload > box_end {
+            break;
+        }
+        let uuid = if &kind == b"uuid" {
+            let offset = (payload - at) as usize;
+            let uuid = header
+                .get(offset..offset + 16)
+                .and_then(|u| u.try_into().ok());
+            payload += 16;
+            uuid
+        } else {
+            None
+        };
+        found.push(Mp4Box {
+            kind,
+            uuid,
+            start: payload.min(box_end),
+            end: box_end,
+        });
+        at = box_end;
+    }
+    found
+}
+
+/// The payload of the Canon box under `moov`.
+fn cr3_canon_box(file: &mut File) -> Option<(u64, u64)> {
+    let len = file.metadata().ok()?.len();
+    let moov = boxes(file, 0, len)
+        .into_iter()
+        .find(|b| &b.kind == b"moov")?;
+    let canon = boxes(file, moov.start, moov.end)
+        .into_iter()
+        .find(|b| b.uuid == Some(CANON_UUID))?;
+    Some((canon.start, canon.end))
+}
+
+/// The JPEG in the payload of a `THMB` or `PRVW` box, after a header of a
+/// few sizes.
+fn jpeg_in(file: &mut File, payload: &Mp4Box) -> Option<(u64, u64)> {
+    let head = read_at(file, payload.start, 32).ok()?;
+    let skip = head.windows(3).position(|w| w == [0xff, 0xd8, 0xff])? as u64;
+    Some((payload.start + skip, payload.end - payload.start - skip))
+}
+
+fn cr3_jpegs(file: &mut File) -> Vec<(u64, u64)> {
+    let mut found = Vec::new();
+    if let Some((start, end)) = cr3_canon_box(file) {
+        if let Some(thumb) = boxes(file, start, end)
+            .into_iter()
+            .find(|b| &b.kind == b"THMB")
+        {
+            found.extend(jpeg_in(file, &thumb));
+        }
+    }
+    let len = file.metadata().map_or(0, |m| m.len());
+    let preview = boxes(file, 0, len)
+        .into_iter()
+        .find(|b| b.uuid == Some(PREVIEW_UUID));
+    if let Some(preview) = preview {
+        // Eight bytes of the uuid box itself, then the `PRVW` box.
+        let prvw = boxes(file, preview.start + 8, preview.end)
+            .into_iter()
+            .find(|b| &b.kind == b"PRVW");
+        if let Some(prvw) = prvw {
+            found.extend(jpeg_in(file, &prvw));
+        }
+    }
+    found
+}
+
+/// Where the JPEG preview of a RAF is, from its header.
+fn raf_preview(file: &mut File) -> Option<(u64, u64)> {
+    let header = read_at(file, 84, 8).ok()?;
+    let offset = u32::from_be_bytes(header.get(0..4)?.try_into().ok()?);
+    let len = u32::from_be_bytes(header.get(4..8)?.try_into().ok()?);
+    (len > 0).then_some((offset.into(), len.into()))
+}
@@ -0,0 +1,169 @@
+//! Passwords for remote hosts, kept in the OS keychain: the login keychain
+//! on macOS, the Secret Service (GNOME Keyring, KWallet) through
+//! `secret-tool` on other Unix systems, and the Credential Locker on
+//! Windows.
+
+use std::io;
+
+/// The keychain service the passwords are saved under, by account.
+const SERVICE: &str = "Sortify SSH";
+
+/// The password saved for `account`, if there is one.
+pub(crate) fn get(account: &str) -> Option<String> {
+    let password = platform::get(account)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    (!password.is_empty()).then(|| password.to_string())
+}
+
+/// Saves `password` for `account`, replacing one saved before.
+pub(crate) fn set(account: &str, password: &str) -> io::Result<()> {
+    platform::set(account, password)
+}
+
+/// Fails with what the command said if it did not succeed.
+fn check(out: std::process::Output) -> io::Result<()> {
+    if out.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(
+        String::from_utf8_lossy(&out.stderr).trim().to_string(),
+    ))
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::io::{self, Write};
+    use std::process::{Command, Stdio};
+
+    use super::{check, SERVICE};
+
+    pub fn get(account: &str) -> Option<String> {
+        let out = Command::new("security")
+            .args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+            .output()
+            .ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    pub fn set(account: &str, password: &str) -> io::Result<()> {
+        if password.contains(['\r', '\n']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the password has a line break",
+            ));
+        }
+        // `-w` last has it ask for the password, and again to confirm it, on
+        // stdin, so it never shows in `ps`.
+        let mut child = Command::new("security")
+            .args(["add-generic-password", "-U", "-s", SERVICE, "-a", account])
+            .arg("-w")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let written = child
+            .stdin
+            .take()
+            .expect("piped stdin")
+            .write_all(format!("{0}\n{0}\n", password).as_bytes());
+        check(child.wait_with_output()?)?;
+        written
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::io::{self, Write};
+    use std::process::{Command, Stdio};
+
+    use super::{check, SERVICE};
+
+    pub fn get(account: &str) -> Option<String> {
+        let out = Command::new("secret-tool")
+            .args(["lookup", "service", SERVICE, "account", account])
+            .stdin(Stdio::null())
+            .output()
+            .ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    pub fn set(account: &str, password: &str) -> io::Result<()> {
+        // The password is read from stdin, so it never shows in `ps`.
+        let mut child = Command::new("secret-tool")
+            .arg("store")
+            .arg(format!("--label={} {}", SERVICE, account))
+            .args(["service", SERVICE, "account", account])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let written = child
+            .stdin
+            .take()
+            .expect("piped stdin")
+            .write_all(password.as_bytes());
+        check(child.wait_with_output()?)?;
+        written
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io::{self, Write};
+    use std::process::{Command, Stdio};
+
+    use super::{check, SERVICE};
+
+    const VAULT: &str = "[void][Windows.Security.Credentials.PasswordVault,Windows.Security.Credentials,ContentType=WindowsRuntime]; $v = New-Object Windows.Security.Credentials.PasswordVault";
+
+    /// A PowerShell string literal.
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', "''"))
+    }
+
+    fn powershell(script: &str) -> Command {
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", script]);
+        command
+    }
+
+    pub fn get(account: &str) -> Option<String> {
+        let script = format!(
+            "{}; $c = $v.Retrieve({}, {}); $c.RetrievePassword(); [Console]::Out.Write($c.Password)",
+            VAULT,
+            quote(SERVICE),
+            quote(account)
+        );
+        let out = powershell(&script).stdin(Stdio::null()).output().ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    pub fn set(account: &str, password: &str) -> io::Result<()> {
+        // The password is read from stdin, so it never shows on a command
+        // line.
+        let script = format!(
+            "{}; $v.Add((New-Object Windows.Security.Credentials.PasswordCredential({}, {}, [Console]::In.ReadToEnd())))",
+            VAULT,
+            quote(SERVICE),
+            quote(account)
+        );
+        let mut child = powershell(&script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let written = child
+            .stdin
+            .take()
+            .expect("piped stdin")
+            .write_all(password.as_bytes());
+        check(child.wait_with_output()?)?;
+        written
+    }
+}
@@ -0,0 +1,567 @@
+//! Renaming files on another machine, such as a NAS only reachable over
+//! SSH, or on a phone or camera connected over USB.
+//!
+//! A [`RemoteFs`] is the little a batch needs of the other side: listing a
+//! folder, looking a path up and moving a file. [`Sftp`] provides it over
+//! the `ssh` command, so the keys, agent and `~/.ssh/config` already set up
+//! for the host are used as they are, and [`WebDav`] for Nextcloud, ownCloud
+//! and other WebDAV shares. [`S3`] renames keys in an object store bucket,
+//! where folders are key prefixes and a rename is a copy and a delete, and
+//! [`Mtp`] reaches phones and cameras through the system's MTP support. A
+//! password or secret key, when the host wants one, comes from the OS
+//! keychain, saved there with [`save_password`].
+//!
+//! Remote folders are listed with [`scan`], named with [`preview`] and
+//! renamed with [`apply`], the same way and with the same types as local
+//! ones. What cannot be done without reading the files themselves is left
+//! out: tokens from inside files are empty, and a batch writes no titles,
+//! modification times, manifests or history. Files can also be copied off
+//! a host, under their new names, into local folders with [`import`].
+
+mod keychain;
+mod mtp;
+mod s3;
+mod sftp;
+mod webdav;
+mod xml;
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::executor::{
+    nesting, sequence, ApplyReport, EntryOutcome, OutcomeStatus, Progress, Reporter,
+};
+use crate::fsutil;
+use crate::normalize;
+use crate::plan::RenameOp;
+use crate::preview::{preview_listed, PreviewRow};
+use crate::rules::Rule;
+use crate::scan::{Filter, Ignores, ScanEntry, ScanError, ScanOptions, ScanPage};
+
+pub use mtp::{devices, Mtp, MtpDevice, MtpTarget};
+pub use s3::{S3Target, S3};
+pub use sftp::{Sftp, SftpTarget};
+pub use webdav::{WebDav, WebDavTarget};
+
+/// Set by [`Sftp::connect`] for the copy of the program ssh starts to ask
+/// for a password, to the keychain account to answer with.
+const ASKPASS_ACCOUNT: &str = "SORTIFY_ASKPASS_ACCOUNT";
+
+/// A file or folder listed on a remote host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// What a symlink points to, as stored in the link.
+    pub link_target: Option<PathBuf>,
+}
+
+/// The file operations remote renaming is built on. Paths are the host's
+/// own, absolute and `/`-separated.
+pub trait RemoteFs {
+    /// The absolute form of `path`, which may be relative to the folder a
+    /// login starts in.
+    fn absolute(&mut self, path: &Path) -> io::Result<PathBuf> {
+        Ok(Path::new("/").join(path))
+    }
+
+    /// The entries of the folder `dir`, without `.` and `..`, in no
+    /// particular order.
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>>;
+
+    /// What is at `path`, without following a symlink; `None` if nothing
+    /// is.
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>>;
+
+    /// Moves `from` to `to`, failing rather than replacing anything already
+    /// at `to`.
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// [`RemoteFs::rename`], calling `progress` with the bytes copied so far
+    /// and in total where the host renames by copying.
+    fn rename_with_progress(
+        &mut self,
+        from: &Path,
+        to: &Path,
+        progress: &dyn Fn(u64, u64),
+    ) -> io::Result<()> {
+        let _ = progress;
+        self.rename(from, to)
+    }
+
+    /// Copies the file `from` to the local path `to`, where nothing is yet,
+    /// calling `progress` with the bytes copied so far and in total. Hosts
+    /// that cannot fail with [`io::ErrorKind::Unsupported`].
+    fn download(&mut self, from: &Path, to: &Path, progress: &dyn Fn(u64, u64)) -> io::Result<()> {
+        let _ = (from, to, progress);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "files cannot be copied off this host",
+        ))
+    }
+}
+
+/// A remote host to log in to, of any kind.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RemoteTarget {
+    Mtp(MtpTarget),
+    #[serde(rename = "s3")]
+    S3(S3Target),
+    Sftp(SftpTarget),
+    #[serde(rename = "webdav")]
+    WebDav(WebDavTarget),
+}
+
+impl RemoteTarget {
+    /// Logs in to the host.
+    pub fn connect(&self) -> Result<Box<dyn RemoteFs + Send>> {
+        Ok(match self {
+            RemoteTarget::Mtp(target) => Box::new(Mtp::connect(target)?),
+            RemoteTarget::S3(target) => Box::new(S3::connect(target)?),
+            RemoteTarget::Sftp(target) => Box::new(Sftp::connect(target)?),
+            RemoteTarget::WebDav(target) => Box::new(WebDav::connect(target)?),
+        })
+    }
+
+    fn account(&self) -> String {
+        match self {
+            RemoteTarget::Mtp(target) => target.device.clone(),
+            RemoteTarget::S3(target) => target.account(),
+            RemoteTarget::Sftp(target) => target.account(),
+            RemoteTarget::WebDav(target) => target.account(),
+        }
+    }
+
+    fn host(&self) -> &str {
+        match self {
+            RemoteTarget::Mtp(target) => target.host(),
+            RemoteTarget::S3(target) => target.host(),
+            RemoteTarget::Sftp(target) => &target.host,
+            RemoteTarget::WebDav(target) => target.host(),
+        }
+    }
+}
+
+/// Lists the folder `root` on a remote host the way [`crate::scan`] lists
+/// a local one. Symlinks are listed as links and never followed, and
+/// `.renamerignore` files are not read; [`ScanOptions::ignore`] still
+/// applies. Fails if a pattern is not valid or `root` cannot be listed;
+/// folders below it that cannot be are reported in the page.
+pub fn scan(
+    fs: &mut dyn RemoteFs,
+    root: &Path,
+    options: &ScanOptions,
+    cancel: &CancelToken,
+) -> Result<ScanPage> {
+    let filter = Filter::new(&options.patterns)?;
+    let mut ignores = Ignores::new(root, &options.ignore)?;
+    let mut page = ScanPage {
+        entries: Vec::new(),
+        errors: Vec::new(),
+        done: true,
+        cancelled: false,
+    };
+    let top = fs.read_dir(root).map_err(|e| Error::io(root, e))?;
+    // Folders being listed, innermost last, with their path relative to the
+    // root and the entries still to visit.
+    let mut stack = vec![(root.to_path_buf(), String::new(), sorted(top))];
+    loop {
+        if cancel.is_cancelled() {
+            page.cancelled = true;
+            break;
+        }
+        let depth = stack.len();
+        let Some((dir, rel_dir, entries)) = stack.last_mut() else {
+            break;
+        };
+        let Some(entry) = entries.next() else {
+            stack.pop();
+            continue;
+        };
+        let path = dir.join(&entry.name);
+        let rel = if rel_dir.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", rel_dir, entry.name)
+        };
+        let skip = (!options.include_hidden && entry.name.starts_with('.'))
+            || filter.excluded(&entry.name, &rel)
+            || ignores.ignored(&path, depth, entry.is_dir);
+        let deeper = options.max_depth.map_or(true, |max| depth <= max);
+        if entry.is_dir && !skip && deeper {
+            match fs.read_dir(&path) {
+                Ok(children) => stack.push((path.clone(), rel.clone(), sorted(children))),
+                Err(e) => page.errors.push(ScanError {
+                    path: Some(path.clone()),
+                    message: e.to_string(),
+                }),
+            }
+        }
+        if (entry.is_dir && !options.include_dirs) || skip || !filter.included(&entry.name, &rel) {
+            continue;
+        }
+        page.entries.push(ScanEntry {
+            path,
+            normalization: normalize::detect(&entry.name),
+            depth,
+            size: if entry.is_dir { 0 } else { entry.size },
+            modified: entry.modified,
+            is_symlink: entry.is_symlink,
+            link_target: entry.link_target,
+            is_dir: entry.is_dir,
+            name: entry.name,
+            file_id: None,
+            links: 0,
+        });
+    }
+    Ok(page)
+}
+
+/// Entries in the order a local scan lists them.
+fn sorted(mut entries: Vec<RemoteEntry>) -> std::vec::IntoIter<RemoteEntry> {
+    entries.sort_by(|a, b| crate::natural_cmp(&a.name, &b.name, true));
+    entries.into_iter()
+}
+
+/// Computes new names for files a remote [`scan`] listed, as
+/// [`crate::preview`] does for local ones, from the size and modification
+/// time the listing has; tokens read from inside files, such as `{exif.*}`
+/// and `{hash}`, come out empty.
+pub fn preview(entries: &[ScanEntry], rules: &[Rule]) -> Result<Vec<PreviewRow>> {
+    preview_listed(entries, rules)
+}
+
+/// Renames files on a remote host as `ops` say, as [`crate::apply`] does
+/// locally: every op is checked first, chains and swaps are ordered so no
+/// file is overwritten, and if a rename fails the ones before it are moved
+/// back. Ops whose file is gone or whose target is taken are skipped;
+/// target folders are not created, and modification times and titles are
+/// not written.
+pub fn apply(fs: &mut dyn RemoteFs, ops: &[RenameOp], cancel: &CancelToken) -> ApplyReport {
+    apply_with_progress(fs, ops, &|_| {}, cancel)
+}
+
+/// [`apply`], calling `progress` as files are renamed and, on hosts where a
+/// rename is a copy, as they are copied.
+pub fn apply_with_progress(
+    fs: &mut dyn RemoteFs,
+    ops: &[RenameOp],
+    progress: &(dyn Fn(&Progress) + Sync),
+    cancel: &CancelToken,
+) -> ApplyReport {
+    let batch_id = Uuid::new_v4();
+    // Why each op is skipped, if it is; `None` for an unchanged name.
+    let mut checks: Vec<std::result::Result<(), Option<String>>> = vec![Ok(()); ops.len()];
+    let mut is_dir = vec![false; ops.len()];
+    let mut sizes = vec![None; ops.len()];
+    let mut targets: HashSet<&Path> = HashSet::new();
+    for (i, op) in ops.iter().enumerate() {
+        if op.source == op.target {
+            checks[i] = Err(None);
+            continue;
+        }
+        if !targets.insert(&op.target) {
+            checks[i] = Err(Some("another file is renamed to the same name".into()));
+            continue;
+        }
+        match fs.lstat(&op.source) {
+            Ok(Some(found)) => {
+                is_dir[i] = found.is_dir;
+                sizes[i] = (!found.is_dir).then_some(found.size);
+            }
+            Ok(None) => checks[i] = Err(Some("the file no longer exists".into())),
+            Err(e) => checks[i] = Err(Some(e.to_string())),
+        }
+    }
+    let mut taken: HashMap<usize, bool> = HashMap::new();
+    for (i, op) in ops.iter().enumerate() {
+        if checks[i].is_ok() {
+            let case_only = op.source.to_string_lossy().to_lowercase()
+                == op.target.to_string_lossy().to_lowercase();
+            match fs.lstat(&op.target) {
+                Ok(found) => {
+                    taken.insert(i, found.is_some() && !case_only);
+                }
+                Err(e) => checks[i] = Err(Some(e.to_string())),
+            }
+        }
+    }
+    // A target that is taken is fine if another op moves its file away, as
+    // long as that op runs; skipping one can leave others blocked.
+    loop {
+        let vacated: HashSet<&Path> = (0..ops.len())
+            .filter(|&i| checks[i].is_ok())
+            .map(|i| ops[i].source.as_path())
+            .collect();
+        let blocked: Vec<usize> = (0..ops.len())
+            .filter(|&i| checks[i].is_ok() && taken[&i])
+            .filter(|&i| !vacated.contains(ops[i].target.as_path()))
+            .collect();
+        if blocked.is_empty() {
+            break;
+        }
+        for i in blocked {
+            checks[i] = Err(Some("a file already has the new name".into()));
+        }
+    }
+
+    let mut ready: Vec<usize> = (0..ops.len()).filter(|&i| checks[i].is_ok()).collect();
+    ready.sort_by_key(|&i| Reverse(nesting(&ops[i].source, &ops[i].target)));
+    let moves: Vec<(&Path, &Path)> = ready
+        .iter()
+        .map(|&i| (ops[i].source.as_path(), ops[i].target.as_path()))
+        .collect();
+    let steps = sequence(&moves, batch_id);
+    let reporter = Reporter::new(batch_id, moves.len(), progress);
+
+    let mut executed = Vec::new();
+    let mut parked: HashSet<usize> = HashSet::new();
+    let mut finished = vec![false; moves.len()];
+    let mut failure = None;
+    let mut cancelled = false;
+    for step in &steps {
+        // A file parked under a temporary name is always moved on, so a
+        // cancelled batch never leaves one behind.
+        if parked.is_empty() && cancel.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+        let copying = |copied, total| reporter.copying(&step.to, copied, total);
+        if let Err(e) = fs.rename_with_progress(&step.from, &step.to, &copying) {
+            failure = Some((step.idx, e.to_string()));
+            break;
+        }
+        if step.last {
+            parked.remove(&step.idx);
+            finished[step.idx] = true;
+            reporter.completed(&step.to);
+        } else {
+            parked.insert(step.idx);
+        }
+        executed.push(step);
+    }
+
+    let mut status: Vec<(OutcomeStatus, Option<String>)> =
+        vec![(OutcomeStatus::Renamed, None); moves.len()];
+    match &failure {
+        Some((failed, message)) => {
+            for (idx, slot) in status.iter_mut().enumerate() {
+                *slot = (OutcomeStatus::Aborted, None);
+                if finished[idx] {
+                    slot.0 = OutcomeStatus::RolledBack;
+                }
+                if idx == *failed {
+                    *slot = (OutcomeStatus::Failed, Some(message.clone()));
+                }
+            }
+            for step in executed.iter().rev() {
+                if let Err(e) = fs.rename(&step.to, &step.from) {
+                    status[step.idx] = (
+                        OutcomeStatus::RollbackFailed,
+                        Some(format!("could not be moved back: {}", e)),
+                    );
+                }
+            }
+        }
+        None => {
+            for (idx, slot) in status.iter_mut().enumerate() {
+                if !finished[idx] {
+                    *slot = (OutcomeStatus::Cancelled, None);
+                }
+            }
+        }
+    }
+
+    let mut report = ApplyReport::new(batch_id);
+    report.committed = failure.is_none();
+    report.cancelled = cancelled;
+    let mut runs: HashMap<usize, usize> = HashMap::new();
+    for (idx, &i) in ready.iter().enumerate() {
+        runs.insert(i, idx);
+    }
+    for (i, op) in ops.iter().enumerate() {
+        let (status, error) = match runs.get(&i) {
+            Some(&idx) => status[idx].clone(),
+            None => (OutcomeStatus::Skipped, checks[i].clone().err().flatten()),
+        };
+        let mut outcome = EntryOutcome {
+            id: i,
+            source: op.source.clone(),
+            target: op.target.clone(),
+            status,
+            error,
+            link: None,
+            is_dir: is_dir[i],
+            modified: None,
+            previous_modified: None,
+            title_error: None,
+            locked_by: None,
+            verified: false,
+        };
+        if status == OutcomeStatus::Renamed {
+            match fs.lstat(&op.target) {
+                Ok(Some(found)) if found.is_dir || Some(found.size) == sizes[i] => {
+                    outcome.verified = true;
+                }
+                Ok(Some(_)) => outcome.error = Some("the file changed size".into()),
+                Ok(None) => outcome.error = Some("the file is not at its new name".into()),
+                Err(e) => outcome.error = Some(e.to_string()),
+            }
+        }
+        report.record(outcome);
+    }
+    report
+}
+
+/// Copies files off a remote host, such as a phone, into local folders
+/// under new names: each op's source is a path on the host and its target a
+/// local path. Nothing on the host changes. A file whose target is taken is
+/// skipped rather than replaced, and one that fails to copy does not stop
+/// the rest, as nothing has to be undone; the batch is committed if every
+/// copy succeeded. Missing folders are created.
+pub fn import(
+    fs: &mut dyn RemoteFs,
+    ops: &[RenameOp],
+    progress: &(dyn Fn(&Progress) + Sync),
+    cancel: &CancelToken,
+) -> ApplyReport {
+    let batch_id = Uuid::new_v4();
+    let reporter = Reporter::new(batch_id, ops.len(), progress);
+    let mut report = ApplyReport::new(batch_id);
+    let mut targets: HashSet<&Path> = HashSet::new();
+    for (i, op) in ops.iter().enumerate() {
+        let mut outcome = EntryOutcome {
+            id: i,
+            source: op.source.clone(),
+            target: op.target.clone(),
+            status: OutcomeStatus::Skipped,
+            error: None,
+            link: None,
+            is_dir: false,
+            modified: None,
+            previous_modified: None,
+            title_error: None,
+            locked_by: None,
+            verified: false,
+        };
+        if cancel.is_cancelled() {
+            report.cancelled = true;
+            outcome.status = OutcomeStatus::Cancelled;
+            report.record(outcome);
+            continue;
+        }
+        let found = match fs.lstat(&op.source) {
+            Ok(Some(found)) if found.is_dir => Err("folders are not imported".to_string()),
+            Ok(Some(found)) => Ok(found),
+            Ok(None) => Err("the file no longer exists".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let checked = found.and_then(|found| {
+            if !targets.insert(&op.target) {
+                Err("another file is imported to the same name".to_string())
+            } else if fsutil::exists(&op.target) {
+                Err("a file already has the new name".to_string())
+            } else {
+                Ok(found)
+            }
+        });
+        let found = match checked {
+            Ok(found) => found,
+            Err(reason) => {
+                outcome.error = Some(reason);
+                report.record(outcome);
+                continue;
+            }
+        };
+        let copying = |copied, total| reporter.copying(&op.target, copied, total);
+        match download(fs, &op.source, &op.target, batch_id, &copying) {
+            Ok(()) => {
+                outcome.status = OutcomeStatus::Renamed;
+                match std::fs::metadata(fsutil::long(&op.target)) {
+                    Ok(meta) if meta.len() == found.size => outcome.verified = true,
+                    Ok(_) => outcome.error = Some("the copy has another size".into()),
+                    Err(e) => outcome.error = Some(e.to_string()),
+                }
+                reporter.completed(&op.target);
+            }
+            Err(e) => {
+                outcome.status = OutcomeStatus::Failed;
+                outcome.error = Some(e.to_string());
+            }
+        }
+        report.record(outcome);
+    }
+    report.committed = report.failed == 0 && !report.cancelled;
+    report
+}
+
+/// Downloads `from` next to `to` under a hidden name, then gives it its
+/// name, so an interrupted copy never takes it.
+fn download(
+    fs: &mut dyn RemoteFs,
+    from: &Path,
+    to: &Path,
+    batch_id: Uuid,
+    progress: &dyn Fn(u64, u64),
+) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(fsutil::long(parent))?;
+    }
+    let partial = fsutil::hidden_sibling(to, &format!("sortify-{}.part", batch_id));
+    let downloaded = fs.download(from, &partial, progress).and_then(|()| {
+        // Looked at again, as the copy can take long.
+        if fsutil::exists(to) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a file already has the new name",
+            ));
+        }
+        std::fs::rename(fsutil::long(&partial), fsutil::long(to))
+    });
+    if downloaded.is_err() {
+        let _ = std::fs::remove_file(fsutil::long(&partial));
+    }
+    downloaded
+}
+
+/// Saves the password to log in to `target` with in the OS keychain, for
+/// logins with `password` set, or an S3 bucket's secret access key.
+pub fn save_password(target: &RemoteTarget, password: &str) -> Result<()> {
+    keychain::set(&target.account(), password).map_err(|e| Error::Remote {
+        host: target.host().to_string(),
+        message: format!("the password could not be saved: {}", e),
+    })
+}
+
+/// Answers ssh's password prompt with the password saved for the host, if
+/// the program was started by [`Sftp::connect`]'s ssh to ask for it, and
+/// returns the exit code then; `None` for a normal start. Call it first
+/// thing in `main`.
+pub fn askpass_main() -> Option<i32> {
+    let account = std::env::var(ASKPASS_ACCOUNT).ok()?;
+    let prompt = std::env::args().nth(1).unwrap_or_default().to_lowercase();
+    // Anything else, such as whether to trust an unknown host key, is
+    // refused, and the connection fails.
+    if !prompt.contains("password") {
+        return Some(1);
+    }
+    match keychain::get(&account) {
+        Some(password) => {
+            println!("{}", password);
+            Some(0)
+        }
+        None => Some(1),
+    }
+}
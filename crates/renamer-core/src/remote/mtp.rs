@@ -0,0 +1,545 @@
+//! Phones, cameras and media players connected over USB with MTP, the
+//! protocol Android devices use. They are reached through the system's own
+//! support, so no driver or library has to be installed: GVfs through the
+//! `gio` command on Linux, and the Windows shell, which shows them under
+//! This PC, through PowerShell. macOS has no MTP support of its own.
+//!
+//! Besides renaming in place, files can be copied off the device under
+//! their new names with [`super::import`], as a camera import would.
+
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{RemoteEntry, RemoteFs};
+use crate::error::{Error, Result};
+
+/// A connected device to open.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MtpTarget {
+    /// The device's name or location, as [`devices`] lists them.
+    pub device: String,
+}
+
+impl MtpTarget {
+    /// The device's name, for messages.
+    pub(crate) fn host(&self) -> &str {
+        &self.device
+    }
+}
+
+/// A device connected over MTP.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MtpDevice {
+    /// The name the device gives itself, e.g. `Pixel 7`.
+    pub name: String,
+    /// Where the system reaches it: a `mtp://` URI on Linux, and the name
+    /// again on Windows.
+    pub location: String,
+}
+
+/// The devices connected now. Empty where MTP is not supported.
+pub fn devices() -> Result<Vec<MtpDevice>> {
+    platform::devices().map_err(|e| Error::Remote {
+        host: "mtp".into(),
+        message: format!("devices could not be listed: {}", e),
+    })
+}
+
+/// An open device.
+pub struct Mtp {
+    /// The device's location, to which paths on it are added.
+    base: String,
+}
+
+impl Mtp {
+    /// Opens the device `target` names, mounting it first if it is
+    /// connected but not yet in use. A location that is not a device's, such
+    /// as any other `gio` URI, is opened as it is.
+    pub fn connect(target: &MtpTarget) -> Result<Self> {
+        let remote = |message: String| Error::Remote {
+            host: target.host().to_string(),
+            message,
+        };
+        let base = if target.device.contains("://") {
+            target.device.trim_end_matches('/').to_string()
+        } else {
+            devices()?
+                .into_iter()
+                .find(|d| d.name == target.device || d.location == target.device)
+                .map(|d| d.location.trim_end_matches('/').to_string())
+                .ok_or_else(|| remote("no such device is connected".into()))?
+        };
+        match platform::open(&base) {
+            Ok(()) => Ok(Mtp { base }),
+            Err(e) => Err(remote(e.to_string())),
+        }
+    }
+}
+
+impl RemoteFs for Mtp {
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        platform::list(&self.base, dir)
+    }
+
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        platform::stat(&self.base, path)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        // Moves on devices replace what is at the target without a word.
+        let case_only =
+            from.to_string_lossy().to_lowercase() == to.to_string_lossy().to_lowercase();
+        if !case_only && platform::stat(&self.base, to)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a file already has the new name",
+            ));
+        }
+        platform::rename(&self.base, from, to)
+    }
+
+    fn download(&mut self, from: &Path, to: &Path, progress: &dyn Fn(u64, u64)) -> io::Result<()> {
+        platform::download(&self.base, from, to)?;
+        let size = std::fs::metadata(to)?.len();
+        progress(size, size);
+        Ok(())
+    }
+}
+
+/// The name of the last component of `path`; empty for the top.
+#[cfg(not(target_os = "macos"))]
+fn base_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The names of `path`'s components below the top.
+#[cfg(not(target_os = "macos"))]
+fn parts(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::io;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    use chrono::DateTime;
+    use percent_encoding::{percent_decode_str, utf8_percent_encode};
+
+    use super::{base_name, parts, MtpDevice};
+    use crate::remote::webdav::SEGMENT;
+    use crate::remote::RemoteEntry;
+
+    /// Runs `gio` with `args`, returning what it printed.
+    fn gio<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> io::Result<String> {
+        let out = Command::new("gio")
+            .env("LC_ALL", "C")
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "gio is not installed; it comes with GLib and GVfs",
+                ),
+                _ => e,
+            })?;
+        if out.status.success() {
+            return Ok(String::from_utf8_lossy(&out.stdout).into_owned());
+        }
+        let message = String::from_utf8_lossy(&out.stderr);
+        let message = message.trim().trim_start_matches("gio: ");
+        let lower = message.to_lowercase();
+        let kind = if lower.contains("no such file") || lower.contains("not found") {
+            io::ErrorKind::NotFound
+        } else if lower.contains("exists") {
+            io::ErrorKind::AlreadyExists
+        } else if lower.contains("permission denied") {
+            io::ErrorKind::PermissionDenied
+        } else {
+            io::ErrorKind::Other
+        };
+        Err(io::Error::new(kind, message.to_string()))
+    }
+
+    fn uri(base: &str, path: &Path) -> String {
+        let mut uri = base.to_string();
+        for part in parts(path) {
+            uri.push('/');
+            uri.extend(utf8_percent_encode(&part, SEGMENT));
+        }
+        uri
+    }
+
+    pub fn devices() -> io::Result<Vec<MtpDevice>> {
+        let listed = match gio(&["mount", "-li"]) {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(Vec::new()),
+            listed => listed?,
+        };
+        let mut devices: Vec<MtpDevice> = Vec::new();
+        let mut volume = None;
+        for line in listed.lines() {
+            let line = line.trim();
+            if let Some(rest) = line.strip_prefix("Volume(") {
+                volume = rest.split_once("): ").map(|(_, name)| name.to_string());
+            } else if let Some(root) = line.strip_prefix("activation_root=") {
+                if let (Some(name), true) = (volume.take(), is_device(root)) {
+                    devices.push(MtpDevice {
+                        name,
+                        location: root.to_string(),
+                    });
+                }
+            } else if let Some((name, root)) = line
+                .strip_prefix("Mount(")
+                .and_then(|rest| rest.split_once("): "))
+                .and_then(|(_, rest)| rest.split_once(" -> "))
+            {
+                if is_device(root) && !devices.iter().any(|d| d.location == root) {
+                    devices.push(MtpDevice {
+                        name: name.to_string(),
+                        location: root.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(devices)
+    }
+
+    fn is_device(location: &str) -> bool {
+        location.starts_with("mtp://") || location.starts_with("gphoto2://")
+    }
+
+    pub fn open(base: &str) -> io::Result<()> {
+        if base.starts_with("mtp://") || base.starts_with("gphoto2://") {
+            // Fails when the device is mounted already.
+            let _ = gio(&["mount", base]);
+        }
+        match stat(base, Path::new("/"))? {
+            Some(top) if top.is_dir => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the device has no storage to open",
+            )),
+        }
+    }
+
+    pub fn list(base: &str, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        let listed = gio(&["list", "-u", "-a", "time::modified", "--", &uri(base, dir)])?;
+        let mut entries = Vec::new();
+        // `<uri>\t<size>\t(<type>)\t<attribute>=<value> ...`
+        for line in listed.lines() {
+            let mut fields = line.split('\t');
+            let (Some(uri), Some(size), Some(kind)) = (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let name = uri.trim_end_matches('/').rsplit('/').next().unwrap_or(uri);
+            let is_dir = kind == "(directory)";
+            let modified = fields
+                .flat_map(|f| f.split(' '))
+                .find_map(|a| a.strip_prefix("time::modified="))
+                .and_then(|t| t.parse().ok())
+                .and_then(|t| DateTime::from_timestamp(t, 0));
+            entries.push(RemoteEntry {
+                name: percent_decode_str(name).decode_utf8_lossy().into_owned(),
+                is_dir,
+                is_symlink: kind == "(symbolic link)",
+                size: if is_dir { 0 } else { size.parse().unwrap_or(0) },
+                modified,
+                link_target: None,
+            });
+        }
+        Ok(entries)
+    }
+
+    pub fn stat(base: &str, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        let attributes = "standard::type,standard::size,time::modified";
+        let info = match gio(&["info", "-a", attributes, "--", &uri(base, path)]) {
+            Ok(info) => info,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let value = |name: &str| {
+            info.lines()
+                .find_map(|l| l.trim().strip_prefix(name)?.strip_prefix(": "))
+                .map(str::trim)
+        };
+        // 1 is a file, 2 a folder and 3 a symlink.
+        let kind = value("standard::type").unwrap_or("1");
+        Ok(Some(RemoteEntry {
+            name: base_name(path),
+            is_dir: kind == "2",
+            is_symlink: kind == "3",
+            size: if kind == "2" {
+                0
+            } else {
+                value("standard::size")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0)
+            },
+            modified: value("time::modified")
+                .and_then(|t| t.parse().ok())
+                .and_then(|t| DateTime::from_timestamp(t, 0)),
+            link_target: None,
+        }))
+    }
+
+    pub fn rename(base: &str, from: &Path, to: &Path) -> io::Result<()> {
+        if from.parent() == to.parent() {
+            // Never replaces anything.
+            gio(&["rename", "--", &uri(base, from), &base_name(to)]).map(drop)
+        } else {
+            gio(&["move", "-T", "--", &uri(base, from), &uri(base, to)]).map(drop)
+        }
+    }
+
+    pub fn download(base: &str, from: &Path, to: &Path) -> io::Result<()> {
+        let source = uri(base, from);
+        gio(&[
+            "copy".as_ref(),
+            "-T".as_ref(),
+            "--".as_ref(),
+            source.as_ref(),
+            to.as_os_str(),
+        ])
+        .map(drop)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    use chrono::{DateTime, Utc};
+    use serde::Deserialize;
+
+    use super::{base_name, parts, MtpDevice};
+    use crate::remote::RemoteEntry;
+
+    /// Finds items by the path of names below a device in This PC. Shell
+    /// names may leave the extension out, so the file name is preferred.
+    const PRELUDE: &str = r#"$ErrorActionPreference = 'Stop'
+$shell = New-Object -ComObject Shell.Application
+function NameOf($i) { $n = $i.ExtendedProperty('System.FileName'); if ($n) { $n } else { $i.Name } }
+function Find($device, $path) {
+  $f = $shell.NameSpace(17).Items() | Where-Object { $_.Name -eq $device } | Select-Object -First 1
+  foreach ($p in $path) {
+    if (-not $f -or -not $f.IsFolder) { return $null }
+    $f = $f.GetFolder.Items() | Where-Object { (NameOf $_) -eq $p } | Select-Object -First 1
+  }
+  $f
+}
+function Entry($i) { [pscustomobject]@{ name = (NameOf $i); dir = $i.IsFolder; size = [uint64]$i.Size; modified = $i.ModifyDate.ToUniversalTime().ToString('o') } }
+"#;
+
+    /// A PowerShell string literal.
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', "''"))
+    }
+
+    /// A PowerShell array of the names in `path`.
+    fn array(path: &Path) -> String {
+        let names: Vec<String> = parts(path).iter().map(|p| quote(p)).collect();
+        format!("@({})", names.join(", "))
+    }
+
+    /// Runs `script` after the prelude, returning what it printed. Exit
+    /// code 3 means the item was not found.
+    fn powershell(script: &str) -> io::Result<String> {
+        let out = Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command"])
+            .arg(format!("{}{}", PRELUDE, script))
+            .stdin(Stdio::null())
+            .output()?;
+        match out.status.code() {
+            Some(0) => Ok(String::from_utf8_lossy(&out.stdout).into_owned()),
+            Some(3) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no such file on the device",
+            )),
+            _ => Err(io::Error::other(
+                String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            )),
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Item {
+        name: String,
+        dir: bool,
+        size: u64,
+        modified: Option<String>,
+    }
+
+    impl Item {
+        fn entry(self) -> RemoteEntry {
+            RemoteEntry {
+                modified: self
+                    .modified
+                    .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                    .map(|t| t.with_timezone(&Utc)),
+                is_dir: self.dir,
+                is_symlink: false,
+                size: if self.dir { 0 } else { self.size },
+                name: self.name,
+                link_target: None,
+            }
+        }
+    }
+
+    fn items(json: &str) -> io::Result<Vec<Item>> {
+        serde_json::from_str(json.trim()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn devices() -> io::Result<Vec<MtpDevice>> {
+        // Portable devices are the folders in This PC outside the file
+        // system.
+        let listed = powershell(
+            "ConvertTo-Json -Compress -InputObject @($shell.NameSpace(17).Items() | Where-Object { $_.IsFolder -and -not $_.IsFileSystem } | ForEach-Object { $_.Name })",
+        )?;
+        let names: Vec<String> = serde_json::from_str(listed.trim())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(names
+            .into_iter()
+            .map(|name| MtpDevice {
+                location: name.clone(),
+                name,
+            })
+            .collect())
+    }
+
+    pub fn open(base: &str) -> io::Result<()> {
+        powershell(&format!(
+            "if (-not (Find {} @())) {{ exit 3 }}",
+            quote(base)
+        ))
+        .map(drop)
+    }
+
+    pub fn list(base: &str, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        let listed = powershell(&format!(
+            "$f = Find {} {}; if (-not $f -or -not $f.IsFolder) {{ exit 3 }}; ConvertTo-Json -Compress -InputObject @($f.GetFolder.Items() | ForEach-Object {{ Entry $_ }})",
+            quote(base),
+            array(dir)
+        ))?;
+        Ok(items(&listed)?.into_iter().map(Item::entry).collect())
+    }
+
+    pub fn stat(base: &str, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        if parts(path).is_empty() {
+            open(base)?;
+            return Ok(Some(RemoteEntry {
+                name: String::new(),
+                is_dir: true,
+                is_symlink: false,
+                size: 0,
+                modified: None,
+                link_target: None,
+            }));
+        }
+        let found = powershell(&format!(
+            "$f = Find {} {}; if (-not $f) {{ exit 3 }}; ConvertTo-Json -Compress -InputObject @(Entry $f)",
+            quote(base),
+            array(path)
+        ));
+        match found {
+            Ok(json) => Ok(items(&json)?.pop().map(Item::entry)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn rename(base: &str, from: &Path, to: &Path) -> io::Result<()> {
+        let parent = |p: &Path| p.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut script = format!(
+            "$i = Find {} {}; if (-not $i) {{ exit 3 }}; ",
+            quote(base),
+            array(from)
+        );
+        if parent(from) != parent(to) {
+            // Moved under its old name first, then renamed there.
+            let moved = parent(to).join(base_name(from));
+            script.push_str(&format!(
+                "$d = Find {} {}; if (-not $d) {{ exit 3 }}; $d.GetFolder.MoveHere($i, 1556); $i = Find {} {}; if (-not $i) {{ exit 3 }}; ",
+                quote(base),
+                array(&parent(to)),
+                quote(base),
+                array(&moved)
+            ));
+        }
+        if base_name(from) != base_name(to) {
+            script.push_str(&format!("$i.Name = {}", quote(&base_name(to))));
+        }
+        powershell(&script).map(drop)
+    }
+
+    pub fn download(base: &str, from: &Path, to: &Path) -> io::Result<()> {
+        // The shell copies into a folder under the file's own name, and
+        // returns before it is done.
+        let staging = to.with_file_name(format!(".sortify-import-{}", std::process::id()));
+        std::fs::create_dir_all(&staging)?;
+        let copied = powershell(&format!(
+            "$i = Find {} {}; if (-not $i) {{ exit 3 }}; $size = [uint64]$i.Size; $shell.NameSpace({}).CopyHere($i, 1556); $dest = Join-Path {} (NameOf $i); while (-not (Test-Path -LiteralPath $dest) -or (Get-Item -LiteralPath $dest).Length -lt $size) {{ Start-Sleep -Milliseconds 200 }}; [Console]::Out.Write($dest)",
+            quote(base),
+            array(from),
+            quote(&staging.to_string_lossy()),
+            quote(&staging.to_string_lossy())
+        ))
+        .and_then(|dest| std::fs::rename(dest.trim(), to));
+        let _ = std::fs::remove_dir_all(&staging);
+        copied
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::io;
+    use std::path::Path;
+
+    use super::MtpDevice;
+    use crate::remote::RemoteEntry;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "macOS has no support for MTP devices",
+        )
+    }
+
+    pub fn devices() -> io::Result<Vec<MtpDevice>> {
+        Ok(Vec::new())
+    }
+
+    pub fn open(_: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn list(_: &str, _: &Path) -> io::Result<Vec<RemoteEntry>> {
+        Err(unsupported())
+    }
+
+    pub fn stat(_: &str, _: &Path) -> io::Result<Option<RemoteEntry>> {
+        Err(unsupported())
+    }
+
+    pub fn rename(_: &str, _: &Path, _: &Path) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn download(_: &str, _: &Path, _: &Path) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
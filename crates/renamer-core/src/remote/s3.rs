@@ -0,0 +1,730 @@
+//! An S3 client for Amazon S3 and the stores that speak its API, such as
+//! MinIO, Backblaze B2, Cloudflare R2 and Wasabi.
+//!
+//! A bucket has no folders, only keys, so `/` in keys is taken as the
+//! separator: a folder is a prefix that keys start with, and is listed
+//! with `ListObjectsV2` and a delimiter. Nor can an object be renamed; it
+//! is copied to its new key and the original deleted once the copy is
+//! made, in parts for objects too large to copy at once. Renaming a folder
+//! copies every object under it before deleting any, and the originals
+//! are deleted in batches of a thousand.
+
+use std::io;
+use std::path::{Component, Path};
+use std::time::Duration;
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use md5::{Digest as _, Md5};
+use reqwest::blocking::{Client, Response};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{keychain, xml, RemoteEntry, RemoteFs};
+use crate::error::{Error, Result};
+
+/// The largest object S3 copies in one request.
+const MAX_COPY: u64 = 5 << 30;
+
+/// The most parts an upload can have.
+const MAX_PARTS: u64 = 10_000;
+
+/// The most keys one `DeleteObjects` request takes.
+const DELETE_BATCH: usize = 1000;
+
+/// A bucket to log in to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct S3Target {
+    /// The store's address, e.g. `https://s3.eu-central-1.amazonaws.com`
+    /// or `http://nas.local:9000`.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    /// Address the bucket as `<endpoint>/<bucket>` rather than as
+    /// `<bucket>.<endpoint>`, as MinIO and most self-hosted stores want.
+    pub path_style: bool,
+}
+
+impl Default for S3Target {
+    fn default() -> Self {
+        S3Target {
+            endpoint: String::new(),
+            region: "us-east-1".into(),
+            bucket: String::new(),
+            access_key_id: String::new(),
+            path_style: false,
+        }
+    }
+}
+
+impl S3Target {
+    /// The keychain account of the target's secret access key.
+    pub(crate) fn account(&self) -> String {
+        format!("{}@{}/{}", self.access_key_id, self.endpoint, self.bucket)
+    }
+
+    /// The store's name, for messages.
+    pub(crate) fn host(&self) -> &str {
+        let rest = self
+            .endpoint
+            .split_once("://")
+            .map_or(&*self.endpoint, |(_, r)| r);
+        rest.split('/').next().unwrap_or(rest)
+    }
+}
+
+/// An object in a bucket.
+struct Object {
+    key: String,
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+    /// Only known when the object was looked up on its own.
+    content_type: Option<String>,
+}
+
+/// A bucket logged in to, with the secret access key saved for it by
+/// [`super::save_password`].
+pub struct S3 {
+    client: Client,
+    target: S3Target,
+    secret: String,
+    /// `scheme://host` requests go to, and the path before keys.
+    origin: String,
+    prefix: String,
+    /// Objects larger than this are copied in parts of this size.
+    part_size: u64,
+}
+
+impl S3 {
+    /// Logs in to `target` with the secret access key saved for it,
+    /// checking that the bucket can be listed.
+    pub fn connect(target: &S3Target) -> Result<Self> {
+        let secret = keychain::get(&target.account()).ok_or_else(|| Error::Remote {
+            host: target.host().to_string(),
+            message: "no secret access key is saved for this bucket".into(),
+        })?;
+        Self::login(target, secret)
+    }
+
+    /// Logs in to `target` with `secret`, such as one from the
+    /// environment, checking that the bucket can be listed.
+    pub fn login(target: &S3Target, secret: String) -> Result<Self> {
+        let remote = |message: String| Error::Remote {
+            host: target.host().to_string(),
+            message,
+        };
+        let client = Client::builder()
+            .timeout(Duration::from_secs(300))
+            .build()
+            .map_err(|e| remote(e.to_string()))?;
+        let endpoint = target.endpoint.trim_end_matches('/');
+        let (scheme, host) = endpoint.split_once("://").unwrap_or(("https", endpoint));
+        let (origin, prefix) = if target.path_style {
+            (
+                format!("{}://{}", scheme, host),
+                format!("/{}", target.bucket),
+            )
+        } else {
+            (
+                format!("{}://{}.{}", scheme, target.bucket, host),
+                String::new(),
+            )
+        };
+        let s3 = S3 {
+            client,
+            target: target.clone(),
+            secret,
+            origin,
+            prefix,
+            part_size: MAX_COPY,
+        };
+        s3.list(String::new(), true, Some(1))
+            .map_err(|e| remote(e.to_string()))?;
+        Ok(s3)
+    }
+
+    /// Copies objects larger than `size` in parts of that size; the
+    /// default is 5 GiB, the most S3 copies at once, and S3 wants at least
+    /// 5 MiB.
+    pub fn with_part_size(mut self, size: u64) -> Self {
+        self.part_size = size.max(1);
+        self
+    }
+
+    /// Sends a signed request for `key`, with `query` and `headers`, and
+    /// fails unless the store answers with success.
+    fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        headers: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> io::Result<Response> {
+        let uri = format!("{}/{}", self.prefix, encode(key, false));
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (encode(k, true), encode(v, true)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+        let now = Utc::now();
+        let host = self.origin.split_once("://").map_or("", |(_, h)| h);
+        let mut signed: Vec<(String, String)> = headers
+            .iter()
+            .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+            .collect();
+        signed.push(("host".into(), host.to_string()));
+        signed.push(("x-amz-content-sha256".into(), hex(&Sha256::digest(&body))));
+        signed.push((
+            "x-amz-date".into(),
+            now.format("%Y%m%dT%H%M%SZ").to_string(),
+        ));
+        signed.sort();
+        let authorization = authorization(
+            &self.target,
+            &self.secret,
+            now,
+            method.as_str(),
+            &uri,
+            &query,
+            &signed,
+        );
+        let mut url = format!("{}{}", self.origin, uri);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, query);
+        }
+        let mut request = self.client.request(method, url).body(body);
+        for (name, value) in signed.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        let response = request
+            .header("authorization", authorization)
+            .send()
+            .map_err(|e| {
+                let kind = if e.is_timeout() {
+                    io::ErrorKind::TimedOut
+                } else {
+                    io::ErrorKind::Other
+                };
+                io::Error::new(kind, e.to_string())
+            })?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().unwrap_or_default();
+            return Err(status_error(status, &body));
+        }
+        Ok(response)
+    }
+
+    /// Sends a request whose success is told by its body, as S3 may answer
+    /// a copy with 200 and an error in the body, and returns the body.
+    fn send_xml(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        headers: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> io::Result<Vec<(Vec<String>, String)>> {
+        let response = self.send(method, key, query, headers, body)?;
+        let text = response.text().map_err(io::Error::other)?;
+        let leaves = xml::leaves(&text)?;
+        if leaves.first().is_some_and(|(path, _)| path[0] == "Error") {
+            return Err(status_error(StatusCode::INTERNAL_SERVER_ERROR, &text));
+        }
+        Ok(leaves)
+    }
+
+    /// The objects whose keys start with `prefix`, and with `delimit`, the
+    /// folders directly below it instead of the objects in them.
+    fn list(
+        &self,
+        prefix: String,
+        delimit: bool,
+        limit: Option<usize>,
+    ) -> io::Result<(Vec<Object>, Vec<String>)> {
+        let mut objects: Vec<Object> = Vec::new();
+        let mut folders = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_string()), ("prefix", prefix.clone())];
+            if delimit {
+                query.push(("delimiter", "/".into()));
+            }
+            if let Some(limit) = limit {
+                query.push(("max-keys", limit.to_string()));
+            }
+            if let Some(token) = token.take() {
+                query.push(("continuation-token", token));
+            }
+            let mut truncated = false;
+            for (path, text) in self.send_xml(Method::GET, "", &query, &[], Vec::new())? {
+                match path.iter().map(String::as_str).collect::<Vec<_>>()[1..] {
+                    ["Contents", "Key"] => objects.push(Object {
+                        key: text,
+                        size: 0,
+                        modified: None,
+                        content_type: None,
+                    }),
+                    ["Contents", "Size"] => {
+                        if let Some(object) = objects.last_mut() {
+                            object.size = text.parse().unwrap_or(0);
+                        }
+                    }
+                    ["Contents", "LastModified"] => {
+                        if let Some(object) = objects.last_mut() {
+                            object.modified = DateTime::parse_from_rfc3339(&text)
+                                .ok()
+                                .map(|t| t.with_timezone(&Utc));
+                        }
+                    }
+                    ["CommonPrefixes", "Prefix"] => folders.push(text),
+                    ["IsTruncated"] => truncated = text == "true",
+                    ["NextContinuationToken"] => token = Some(text),
+                    _ => {}
+                }
+            }
+            let full = limit.is_some_and(|l| objects.len() + folders.len() >= l);
+            if !truncated || token.is_none() || full {
+                return Ok((objects, folders));
+            }
+        }
+    }
+
+    /// The object at `key`, if there is one.
+    fn head(&self, key: &str) -> io::Result<Option<Object>> {
+        let response = match self.send(Method::HEAD, key, &[], &[], Vec::new()) {
+            Ok(response) => response,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let size = header("content-length")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        let modified = header("last-modified")
+            .and_then(|t| DateTime::parse_from_rfc2822(&t).ok())
+            .map(|t| t.with_timezone(&Utc));
+        Ok(Some(Object {
+            key: key.to_string(),
+            size,
+            modified,
+            content_type: header("content-type"),
+        }))
+    }
+
+    /// Whether any object's key starts with `prefix`.
+    fn has_objects(&self, prefix: String) -> io::Result<bool> {
+        let (objects, folders) = self.list(prefix, true, Some(1))?;
+        Ok(!objects.is_empty() || !folders.is_empty())
+    }
+
+    /// Copies the object at `from` to `to`, calling `progress` with the
+    /// bytes of it copied so far on top of `done`, out of `total`.
+    fn copy(
+        &self,
+        from: &str,
+        to: &str,
+        size: u64,
+        content_type: Option<&str>,
+        progress: &dyn Fn(u64, u64),
+        (done, total): (u64, u64),
+    ) -> io::Result<()> {
+        let source = format!("/{}/{}", self.target.bucket, encode(from, false));
+        if size <= self.part_size {
+            let headers = [
+                ("x-amz-copy-source", source),
+                ("x-amz-metadata-directive", "COPY".to_string()),
+            ];
+            self.send_xml(Method::PUT, to, &[], &headers, Vec::new())?;
+            progress(done + size, total);
+            return Ok(());
+        }
+        let mut headers = Vec::new();
+        if let Some(content_type) = content_type {
+            headers.push(("content-type", content_type.to_string()));
+        }
+        let created = self.send_xml(
+            Method::POST,
+            to,
+            &[("uploads", String::new())],
+            &headers,
+            Vec::new(),
+        )?;
+        let upload = created
+            .into_iter()
+            .find(|(path, _)| path.last().is_some_and(|n| n == "UploadId"))
+            .map(|(_, id)| id)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "no upload id in the reply")
+            })?;
+        let part_size = self.part_size.max(size.div_ceil(MAX_PARTS));
+        let copied = (|| {
+            let mut parts = String::new();
+            let mut start = 0;
+            let mut number = 1;
+            while start < size {
+                let end = (start + part_size).min(size) - 1;
+                let query = [
+                    ("partNumber", number.to_string()),
+                    ("uploadId", upload.clone()),
+                ];
+                let headers = [
+                    ("x-amz-copy-source", source.clone()),
+                    (
+                        "x-amz-copy-source-range",
+                        format!("bytes={}-{}", start, end),
+                    ),
+                ];
+                let reply = self.send_xml(Method::PUT, to, &query, &headers, Vec::new())?;
+                let etag = reply
+                    .into_iter()
+                    .find(|(path, _)| path.last().is_some_and(|n| n == "ETag"))
+                    .map(|(_, etag)| etag)
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "no ETag in the reply")
+                    })?;
+                parts.push_str(&format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    number,
+                    quick_xml::escape::escape(&etag)
+                ));
+                progress(done + end + 1, total);
+                start = end + 1;
+                number += 1;
+            }
+            let body = format!(
+                "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+                parts
+            );
+            self.send_xml(
+                Method::POST,
+                to,
+                &[("uploadId", upload.clone())],
+                &[],
+                body.into_bytes(),
+            )
+            .map(drop)
+        })();
+        if copied.is_err() {
+            let _ = self.send(
+                Method::DELETE,
+                to,
+                &[("uploadId", upload.clone())],
+                &[],
+                Vec::new(),
+            );
+        }
+        copied
+    }
+
+    /// Deletes the objects at `keys`, a thousand at a time.
+    fn delete(&self, keys: &[String]) -> io::Result<()> {
+        if let [key] = keys {
+            return self
+                .send(Method::DELETE, key, &[], &[], Vec::new())
+                .map(drop);
+        }
+        for batch in keys.chunks(DELETE_BATCH) {
+            let mut body = String::from("<Delete><Quiet>true</Quiet>");
+            for key in batch {
+                body.push_str(&format!(
+                    "<Object><Key>{}</Key></Object>",
+                    quick_xml::escape::escape(key.as_str())
+                ));
+            }
+            body.push_str("</Delete>");
+            let md5 = base64::engine::general_purpose::STANDARD.encode(Md5::digest(&body));
+            let reply = self.send_xml(
+                Method::POST,
+                "",
+                &[("delete", String::new())],
+                &[("content-md5", md5)],
+                body.into_bytes(),
+            )?;
+            // With `Quiet`, only the keys that could not be deleted are
+            // listed.
+            if let Some((_, key)) = reply
+                .iter()
+                .find(|(path, _)| path.ends_with(&["Error".to_string(), "Key".to_string()]))
+            {
+                return Err(io::Error::other(format!("{} could not be deleted", key)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RemoteFs for S3 {
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        let prefix = folder(dir);
+        let (objects, folders) = self.list(prefix.clone(), true, None)?;
+        if objects.is_empty() && folders.is_empty() && !prefix.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such folder"));
+        }
+        let mut entries = Vec::new();
+        for object in objects {
+            let name = &object.key[prefix.len()..];
+            // A folder's marker object, as consoles create for empty ones.
+            if name.is_empty() {
+                continue;
+            }
+            entries.push(RemoteEntry {
+                name: name.to_string(),
+                is_dir: false,
+                is_symlink: false,
+                size: object.size,
+                modified: object.modified,
+                link_target: None,
+            });
+        }
+        for folder in folders {
+            let name = folder[prefix.len()..].trim_end_matches('/');
+            if name.is_empty() {
+                continue;
+            }
+            entries.push(RemoteEntry {
+                name: name.to_string(),
+                is_dir: true,
+                is_symlink: false,
+                size: 0,
+                modified: None,
+                link_target: None,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        let key = key(path);
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let entry = |is_dir, size, modified| RemoteEntry {
+            name: name.clone(),
+            is_dir,
+            is_symlink: false,
+            size,
+            modified,
+            link_target: None,
+        };
+        if key.is_empty() {
+            return Ok(Some(entry(true, 0, None)));
+        }
+        if let Some(object) = self.head(&key)? {
+            return Ok(Some(entry(false, object.size, object.modified)));
+        }
+        Ok(self
+            .has_objects(folder(path))?
+            .then(|| entry(true, 0, None)))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.rename_with_progress(from, to, &|_, _| {})
+    }
+
+    fn rename_with_progress(
+        &mut self,
+        from: &Path,
+        to: &Path,
+        progress: &dyn Fn(u64, u64),
+    ) -> io::Result<()> {
+        let (from_key, to_key) = (key(from), key(to));
+        if self.head(&to_key)?.is_some() || self.has_objects(folder(to))? {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a file already has the new name",
+            ));
+        }
+        if let Some(object) = self.head(&from_key)? {
+            self.copy(
+                &from_key,
+                &to_key,
+                object.size,
+                object.content_type.as_deref(),
+                progress,
+                (0, object.size),
+            )?;
+            if let Err(e) = self.delete(&[from_key]) {
+                // Two copies are worse than none renamed.
+                let _ = self.delete(&[to_key]);
+                return Err(e);
+            }
+            return Ok(());
+        }
+        // A folder: every object under it moves.
+        let prefix = folder(from);
+        let (objects, _) = self.list(prefix.clone(), false, None)?;
+        if objects.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        }
+        let total = objects.iter().map(|o| o.size).sum();
+        let mut done = 0;
+        let mut copied = Vec::new();
+        for object in &objects {
+            let target = format!("{}{}", folder(to), &object.key[prefix.len()..]);
+            let result = self.copy(
+                &object.key,
+                &target,
+                object.size,
+                None,
+                progress,
+                (done, total),
+            );
+            if let Err(e) = result {
+                let _ = self.delete(&copied);
+                return Err(e);
+            }
+            copied.push(target);
+            done += object.size;
+        }
+        let originals: Vec<String> = objects.into_iter().map(|o| o.key).collect();
+        self.delete(&originals)
+    }
+}
+
+/// The key of a remote path: its components joined by `/`, without a
+/// leading one.
+fn key(path: &Path) -> String {
+    let parts: Vec<String> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+/// The prefix of the keys in the folder at `path`; empty for the top.
+fn folder(path: &Path) -> String {
+    let key = key(path);
+    if key.is_empty() {
+        key
+    } else {
+        key + "/"
+    }
+}
+
+/// `text` percent-encoded as SigV4 wants it: everything but unreserved
+/// characters, and `/` too if `slash`.
+fn encode(text: &str, slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if !slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// The `Authorization` header of a request, signed with AWS Signature
+/// Version 4; `headers` are the signed ones, lowercased and sorted.
+fn authorization(
+    target: &S3Target,
+    secret: &str,
+    now: DateTime<Utc>,
+    method: &str,
+    uri: &str,
+    query: &str,
+    headers: &[(String, String)],
+) -> String {
+    let date = now.format("%Y%m%d").to_string();
+    let payload = headers
+        .iter()
+        .find(|(name, _)| name == "x-amz-content-sha256")
+        .map_or("", |(_, v)| v.as_str());
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, uri, query, canonical_headers, signed_headers, payload
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, target.region);
+    let to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        now.format("%Y%m%dT%H%M%SZ"),
+        scope,
+        hex(&Sha256::digest(canonical))
+    );
+    let key = hmac(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+    let key = hmac(&key, target.region.as_bytes());
+    let key = hmac(&key, b"s3");
+    let key = hmac(&key, b"aws4_request");
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        target.access_key_id,
+        scope,
+        signed_headers,
+        hex(&hmac(&key, to_sign.as_bytes()))
+    )
+}
+
+/// The error a failed request stands for, with the store's own message.
+fn status_error(status: StatusCode, body: &str) -> io::Error {
+    let leaves = xml::leaves(body).unwrap_or_default();
+    let field = |name: &str| {
+        leaves
+            .iter()
+            .find(|(path, _)| path.last().is_some_and(|n| n == name))
+            .map(|(_, text)| text.clone())
+    };
+    let code = field("Code").unwrap_or_default();
+    let kind = match (status.as_u16(), code.as_str()) {
+        (404, _) | (_, "NoSuchKey") => io::ErrorKind::NotFound,
+        (401 | 403, _) => io::ErrorKind::PermissionDenied,
+        (412, _) => io::ErrorKind::AlreadyExists,
+        _ => io::ErrorKind::Other,
+    };
+    let message = match field("Message") {
+        Some(message) => format!("{} ({})", message, code),
+        None => format!("the store answered {}", status),
+    };
+    io::Error::new(kind, message)
+}
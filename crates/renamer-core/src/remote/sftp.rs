@@ -0,0 +1,483 @@
+//! A client for version 3 of the SFTP protocol, the one every OpenSSH
+//! server speaks, run over the `ssh` command's `sftp` subsystem.
+
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use super::{RemoteEntry, RemoteFs, ASKPASS_ACCOUNT};
+use crate::error::{Error, Result};
+
+const INIT: u8 = 1;
+const VERSION: u8 = 2;
+const CLOSE: u8 = 4;
+const LSTAT: u8 = 7;
+const OPENDIR: u8 = 11;
+const READDIR: u8 = 12;
+const REALPATH: u8 = 16;
+const RENAME: u8 = 18;
+const READLINK: u8 = 19;
+const STATUS: u8 = 101;
+const HANDLE: u8 = 102;
+const NAME: u8 = 104;
+const ATTRS: u8 = 105;
+
+const ATTR_SIZE: u32 = 0x1;
+const ATTR_UIDGID: u32 = 0x2;
+const ATTR_PERMISSIONS: u32 = 0x4;
+const ATTR_ACMODTIME: u32 = 0x8;
+const ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const FX_OK: u32 = 0;
+const FX_EOF: u32 = 1;
+const FX_NO_SUCH_FILE: u32 = 2;
+const FX_PERMISSION_DENIED: u32 = 3;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Largest reply accepted; servers send a few dozen KiB at most.
+const MAX_PACKET: usize = 16 << 20;
+
+/// Where and how to log in to a remote host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SftpTarget {
+    /// A host name, address or `~/.ssh/config` alias.
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    /// Private key to log in with; otherwise ssh's configuration and agent
+    /// decide.
+    pub identity: Option<PathBuf>,
+    /// Answer a password prompt with the password [`super::save_password`]
+    /// saved for this target. Without it ssh is never allowed to ask, so a
+    /// host that wants a password fails to connect.
+    pub password: bool,
+}
+
+impl SftpTarget {
+    /// The keychain account of the target's password.
+    pub(crate) fn account(&self) -> String {
+        let mut account = self.destination();
+        if let Some(port) = self.port {
+            account = format!("{}:{}", account, port);
+        }
+        account
+    }
+
+    fn destination(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        }
+    }
+}
+
+/// An open SFTP session; the connection is closed when it is dropped.
+pub struct Sftp {
+    reader: Box<dyn Read + Send>,
+    writer: Box<dyn Write + Send>,
+    child: Option<Child>,
+    next_id: u32,
+}
+
+impl Sftp {
+    /// Logs in to `target` with `ssh`, which has to be on the `PATH`, and
+    /// opens an SFTP session. An unknown host key is never accepted, whatever
+    /// the user's ssh_config says; connect once with `ssh` itself to trust it.
+    pub fn connect(target: &SftpTarget) -> Result<Self> {
+        let remote = |message: String| Error::Remote {
+            host: target.host.clone(),
+            message,
+        };
+        let mut command = Command::new("ssh");
+        command.args([
+            "-o",
+            "ServerAliveInterval=15",
+            "-o",
+            "StrictHostKeyChecking=yes",
+        ]);
+        if let Some(port) = target.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity) = &target.identity {
+            command.arg("-i").arg(identity);
+        }
+        if target.password {
+            let exe = std::env::current_exe()
+                .map_err(|e| remote(format!("cannot ask for the password: {}", e)))?;
+            command
+                .args(["-o", "NumberOfPasswordPrompts=1"])
+                .env("SSH_ASKPASS", exe)
+                .env("SSH_ASKPASS_REQUIRE", "force")
+                .env(ASKPASS_ACCOUNT, target.account());
+        } else {
+            command.args(["-o", "BatchMode=yes"]);
+        }
+        let mut child = command
+            .args(["-s", "--"])
+            .arg(target.destination())
+            .arg("sftp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| remote(format!("ssh could not be run: {}", e)))?;
+        let reader = child.stdout.take().expect("piped stdout");
+        let writer = child.stdin.take().expect("piped stdin");
+        let mut sftp = Sftp::over(reader, writer);
+        sftp.child = Some(child);
+        if let Err(e) = sftp.init() {
+            // ssh says on stderr why it could not log in.
+            let child = sftp.child.as_mut().expect("child set above");
+            let _ = child.kill();
+            let mut why = String::new();
+            if let Some(mut stderr) = child.stderr.take() {
+                let _ = stderr.read_to_string(&mut why);
+            }
+            let why = why.trim();
+            return Err(remote(if why.is_empty() {
+                e.to_string()
+            } else {
+                why.to_string()
+            }));
+        }
+        Ok(sftp)
+    }
+
+    /// A session over an already open channel to an SFTP server, such as
+    /// one a proxy provides. Nothing is sent until the first request.
+    pub fn over(reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        Sftp {
+            reader: Box::new(BufReader::new(reader)),
+            writer: Box::new(BufWriter::new(writer)),
+            child: None,
+            next_id: 0,
+        }
+    }
+
+    /// The absolute form of `path` on the host; `.` is the login folder.
+    pub fn realpath(&mut self, path: &Path) -> io::Result<PathBuf> {
+        self.ensure_init()?;
+        let reply = self.request(REALPATH, &[&wire(path)])?;
+        let mut names = names(reply)?;
+        match names.pop() {
+            Some((name, _)) => Ok(PathBuf::from(name)),
+            None => Err(malformed()),
+        }
+    }
+
+    fn init(&mut self) -> io::Result<()> {
+        self.next_id = 1;
+        self.send(INIT, &3u32.to_be_bytes())?;
+        let (kind, body) = self.receive()?;
+        let mut body = Packet::new(&body);
+        if kind != VERSION || body.u32()? < 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the server does not speak SFTP version 3",
+            ));
+        }
+        Ok(())
+    }
+
+    fn ensure_init(&mut self) -> io::Result<()> {
+        if self.next_id == 0 {
+            self.init()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, kind: u8, body: &[u8]) -> io::Result<()> {
+        let length = u32::try_from(body.len() + 1).map_err(|_| malformed())?;
+        self.writer.write_all(&length.to_be_bytes())?;
+        self.writer.write_all(&[kind])?;
+        self.writer.write_all(body)?;
+        self.writer.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut length = [0; 4];
+        self.reader.read_exact(&mut length)?;
+        let length = u32::from_be_bytes(length) as usize;
+        if length == 0 || length > MAX_PACKET {
+            return Err(malformed());
+        }
+        let mut packet = vec![0; length];
+        self.reader.read_exact(&mut packet)?;
+        let body = packet.split_off(1);
+        Ok((packet[0], body))
+    }
+
+    /// Sends a request made of `fields`, each a string, and returns the
+    /// reply to it.
+    fn request(&mut self, kind: u8, fields: &[&[u8]]) -> io::Result<Reply> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let mut body = id.to_be_bytes().to_vec();
+        for field in fields {
+            let length = u32::try_from(field.len()).map_err(|_| malformed())?;
+            body.extend(length.to_be_bytes());
+            body.extend(*field);
+        }
+        self.send(kind, &body)?;
+        let (reply_kind, reply) = self.receive()?;
+        let mut packet = Packet::new(&reply);
+        if packet.u32()? != id {
+            return Err(malformed());
+        }
+        let rest = reply[4..].to_vec();
+        if reply_kind == STATUS {
+            let mut status = Packet::new(&rest);
+            let code = status.u32()?;
+            let message = status
+                .string()
+                .map(|m| String::from_utf8_lossy(m).into_owned())
+                .unwrap_or_default();
+            return Ok(Reply::Status(code, message));
+        }
+        Ok(Reply::Data(reply_kind, rest))
+    }
+
+    fn read_link(&mut self, path: &Path) -> io::Result<PathBuf> {
+        let reply = self.request(READLINK, &[&wire(path)])?;
+        match names(reply)?.pop() {
+            Some((name, _)) => Ok(PathBuf::from(name)),
+            None => Err(malformed()),
+        }
+    }
+}
+
+impl Drop for Sftp {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl RemoteFs for Sftp {
+    fn absolute(&mut self, path: &Path) -> io::Result<PathBuf> {
+        self.realpath(path)
+    }
+
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        self.ensure_init()?;
+        let handle = match self.request(OPENDIR, &[&wire(dir)])? {
+            Reply::Data(HANDLE, body) => Packet::new(&body).string()?.to_vec(),
+            reply => return Err(unexpected(reply)),
+        };
+        let mut listed = Vec::new();
+        let read = loop {
+            match self.request(READDIR, &[&handle]) {
+                Ok(Reply::Status(FX_EOF, _)) => break Ok(()),
+                Ok(reply) => match names(reply) {
+                    Ok(names) => listed.extend(names),
+                    Err(e) => break Err(e),
+                },
+                Err(e) => break Err(e),
+            }
+        };
+        let closed = self.request(CLOSE, &[&handle]).and_then(status);
+        read?;
+        closed?;
+        let mut entries = Vec::new();
+        for (name, attrs) in listed {
+            if name == "." || name == ".." {
+                continue;
+            }
+            let mut entry = attrs.entry(name);
+            if entry.is_symlink {
+                entry.link_target = self.read_link(&dir.join(&entry.name)).ok();
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        self.ensure_init()?;
+        match self.request(LSTAT, &[&wire(path)])? {
+            Reply::Data(ATTRS, body) => {
+                let attrs = Attrs::read(&mut Packet::new(&body))?;
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let mut entry = attrs.entry(name);
+                if entry.is_symlink {
+                    entry.link_target = self.read_link(path).ok();
+                }
+                Ok(Some(entry))
+            }
+            Reply::Status(FX_NO_SUCH_FILE, _) => Ok(None),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.ensure_init()?;
+        // Version 3 servers refuse to rename onto an existing file.
+        status(self.request(RENAME, &[&wire(from), &wire(to)])?)
+    }
+}
+
+enum Reply {
+    Status(u32, String),
+    /// Any other reply, with its type and what follows the request id.
+    Data(u8, Vec<u8>),
+}
+
+/// Fails unless `reply` reports success.
+fn status(reply: Reply) -> io::Result<()> {
+    match reply {
+        Reply::Status(FX_OK, _) => Ok(()),
+        reply => Err(unexpected(reply)),
+    }
+}
+
+/// The error a reply other than the expected one stands for.
+fn unexpected(reply: Reply) -> io::Error {
+    match reply {
+        Reply::Status(code, message) => {
+            let kind = match code {
+                FX_NO_SUCH_FILE => io::ErrorKind::NotFound,
+                FX_PERMISSION_DENIED => io::ErrorKind::PermissionDenied,
+                _ => io::ErrorKind::Other,
+            };
+            let message = if message.is_empty() {
+                format!("the server refused with status {}", code)
+            } else {
+                message
+            };
+            io::Error::new(kind, message)
+        }
+        Reply::Data(..) => malformed(),
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "malformed reply from the server",
+    )
+}
+
+/// The names and attributes of a `NAME` reply.
+fn names(reply: Reply) -> io::Result<Vec<(String, Attrs)>> {
+    let body = match reply {
+        Reply::Data(NAME, body) => body,
+        reply => return Err(unexpected(reply)),
+    };
+    let mut packet = Packet::new(&body);
+    let count = packet.u32()?;
+    let mut names = Vec::new();
+    for _ in 0..count {
+        let name = String::from_utf8_lossy(packet.string()?).into_owned();
+        // The `ls -l` style long name, which is not needed.
+        packet.string()?;
+        names.push((name, Attrs::read(&mut packet)?));
+    }
+    Ok(names)
+}
+
+/// A path as the protocol sends it: its bytes, with `/` between components.
+fn wire(path: &Path) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_string_lossy().replace('\\', "/").into_bytes()
+    }
+}
+
+struct Packet<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Packet { data }
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(malformed());
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().expect("four bytes")))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().expect("eight bytes")))
+    }
+
+    fn string(&mut self) -> io::Result<&'a [u8]> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Attrs {
+    size: Option<u64>,
+    permissions: Option<u32>,
+    mtime: Option<u32>,
+}
+
+impl Attrs {
+    fn read(packet: &mut Packet) -> io::Result<Self> {
+        let flags = packet.u32()?;
+        let mut attrs = Attrs::default();
+        if flags & ATTR_SIZE != 0 {
+            attrs.size = Some(packet.u64()?);
+        }
+        if flags & ATTR_UIDGID != 0 {
+            packet.take(8)?;
+        }
+        if flags & ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(packet.u32()?);
+        }
+        if flags & ATTR_ACMODTIME != 0 {
+            packet.u32()?;
+            attrs.mtime = Some(packet.u32()?);
+        }
+        if flags & ATTR_EXTENDED != 0 {
+            for _ in 0..packet.u32()? {
+                packet.string()?;
+                packet.string()?;
+            }
+        }
+        Ok(attrs)
+    }
+
+    fn entry(&self, name: String) -> RemoteEntry {
+        let kind = self.permissions.map(|p| p & S_IFMT);
+        RemoteEntry {
+            name,
+            is_dir: kind == Some(S_IFDIR),
+            is_symlink: kind == Some(S_IFLNK),
+            size: self.size.unwrap_or(0),
+            modified: self
+                .mtime
+                .and_then(|t| DateTime::from_timestamp(i64::from(t), 0)),
+            link_target: None,
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! A WebDAV client for the shares Nextcloud, ownCloud and most NAS web
+//! interfaces offer: folders are listed with `PROPFIND` and files renamed
+//! with `MOVE`, never overwriting.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{keychain, xml, RemoteEntry, RemoteFs};
+use crate::error::{Error, Result};
+
+/// What a listing asks the server for.
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop></d:propfind>"#;
+
+/// Characters escaped in a path segment of a URL.
+pub(super) const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'+')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// A WebDAV share to log in to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebDavTarget {
+    /// The address of the share's top folder, which remote paths are
+    /// relative to; for Nextcloud and ownCloud
+    /// `https://<server>/remote.php/dav/files/<user>`.
+    pub url: String,
+    pub user: Option<String>,
+    /// Log in with the password [`super::save_password`] saved for this
+    /// target; for Nextcloud, an app password. Without it no password is
+    /// sent.
+    pub password: bool,
+}
+
+impl WebDavTarget {
+    /// The keychain account of the target's password.
+    pub(crate) fn account(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.url),
+            None => self.url.clone(),
+        }
+    }
+
+    /// The server's name, for messages.
+    pub(crate) fn host(&self) -> &str {
+        let rest = self.url.split_once("://").map_or(&*self.url, |(_, r)| r);
+        rest.split('/').next().unwrap_or(rest)
+    }
+}
+
+/// A logged in WebDAV share.
+pub struct WebDav {
+    client: Client,
+    /// The share's URL, without a trailing `/`.
+    base: String,
+    /// The path part of `base`, decoded, to take off the paths the server
+    /// answers with.
+    base_path: String,
+    login: Option<(String, Option<String>)>,
+}
+
+impl WebDav {
+    /// Logs in to `target`, checking that its URL is a WebDAV folder.
+    pub fn connect(target: &WebDavTarget) -> Result<Self> {
+        let remote = |message: String| Error::Remote {
+            host: target.host().to_string(),
+            message,
+        };
+        let password = if target.password {
+            Some(
+                keychain::get(&target.account())
+                    .ok_or_else(|| remote("no password is saved for this share".into()))?,
+            )
+        } else {
+            None
+        };
+        let client = Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .map_err(|e| remote(e.to_string()))?;
+        let base = target.url.trim_end_matches('/').to_string();
+        let after_scheme = base.split_once("://").map_or(&*base, |(_, r)| r);
+        let base_path = after_scheme
+            .find('/')
+            .map_or("", |i| &after_scheme[i..])
+            .to_string();
+        let dav = WebDav {
+            client,
+            base_path: percent_decode_str(&base_path)
+                .decode_utf8_lossy()
+                .into_owned(),
+            base,
+            login: target.user.clone().map(|user| (user, password)),
+        };
+        match dav.lstat_inner(Path::new("/")) {
+            Ok(Some(top)) if top.is_dir => Ok(dav),
+            Ok(_) => Err(remote("the address is not a WebDAV folder".into())),
+            Err(e) => Err(remote(e.to_string())),
+        }
+    }
+
+    fn url(&self, path: &Path) -> String {
+        let mut url = self.base.clone();
+        for component in path.components() {
+            if let Component::Normal(part) = component {
+                url.push('/');
+                url.extend(utf8_percent_encode(&part.to_string_lossy(), SEGMENT));
+            }
+        }
+        url
+    }
+
+    /// The remote path an `href` in a reply stands for.
+    fn path(&self, href: &str) -> Option<PathBuf> {
+        let href = match href.split_once("://") {
+            Some((_, rest)) => &rest[rest.find('/')?..],
+            None => href,
+        };
+        let href = percent_decode_str(href).decode_utf8_lossy();
+        let rest = href.strip_prefix(&self.base_path)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        Some(Path::new("/").join(rest.trim_matches('/')))
+    }
+
+    fn request(&self, method: &str, url: &str) -> RequestBuilder {
+        let method = Method::from_bytes(method.as_bytes()).expect("valid method");
+        let request = self.client.request(method, url);
+        match &self.login {
+            Some((user, password)) => request.basic_auth(user, password.as_ref()),
+            None => request,
+        }
+    }
+
+    fn send(request: RequestBuilder) -> io::Result<Response> {
+        request.send().map_err(|e| {
+            let kind = if e.is_timeout() {
+                io::ErrorKind::TimedOut
+            } else {
+                io::ErrorKind::Other
+            };
+            io::Error::new(kind, e.to_string())
+        })
+    }
+
+    /// The entries a `PROPFIND` of `depth` finds at `path`; `None` if
+    /// there is nothing there.
+    fn propfind(
+        &self,
+        path: &Path,
+        depth: &str,
+    ) -> io::Result<Option<Vec<(PathBuf, RemoteEntry)>>> {
+        let mut url = self.url(path);
+        if depth != "0" {
+            // Servers redirect folders without their trailing slash.
+            url.push('/');
+        }
+        let response = Self::send(
+            self.request("PROPFIND", &url)
+                .header("Depth", depth)
+                .header("Content-Type", "application/xml; charset=utf-8")
+                .body(PROPFIND),
+        )?;
+        match response.status() {
+            StatusCode::MULTI_STATUS => {}
+            StatusCode::NOT_FOUND => return Ok(None),
+            status => return Err(status_error(status)),
+        }
+        let body = response.text().map_err(io::Error::other)?;
+        let mut entries = Vec::new();
+        for (href, props) in multistatus(&body)? {
+            let Some(path) = self.path(&href) else {
+                continue;
+            };
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let entry = RemoteEntry {
+                name,
+                is_dir: props.collection,
+                is_symlink: false,
+                size: props.length.unwrap_or(0),
+                modified: props.modified,
+                link_target: None,
+            };
+            entries.push((path, entry));
+        }
+        Ok(Some(entries))
+    }
+
+    fn lstat_inner(&self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        let Some(entries) = self.propfind(path, "0")? else {
+            return Ok(None);
+        };
+        let wanted = Path::new("/").join(path);
+        Ok(entries
+            .into_iter()
+            .find(|(p, _)| *p == wanted)
+            .map(|(_, entry)| entry))
+    }
+}
+
+impl RemoteFs for WebDav {
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        let listed = self
+            .propfind(dir, "1")?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such folder"))?;
+        // The folder itself is listed along with what is in it.
+        let dir = Path::new("/").join(dir);
+        Ok(listed
+            .into_iter()
+            .filter(|(path, _)| *path != dir)
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        self.lstat_inner(path)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let response = Self::send(
+            self.request("MOVE", &self.url(from))
+                .header("Destination", self.url(to))
+                .header("Overwrite", "F"),
+        )?;
+        match response.status() {
+            StatusCode::CREATED | StatusCode::NO_CONTENT => Ok(()),
+            // A folder only partly moved; the rest is reported per file.
+            StatusCode::MULTI_STATUS => {
+                Err(io::Error::other("the server moved only part of the folder"))
+            }
+            status => Err(status_error(status)),
+        }
+    }
+}
+
+/// The error a WebDAV status other than success stands for.
+fn status_error(status: StatusCode) -> io::Error {
+    let (kind, message) = match status.as_u16() {
+        401 => (
+            io::ErrorKind::PermissionDenied,
+            "the server refused the login",
+        ),
+        403 => (
+            io::ErrorKind::PermissionDenied,
+            "the server does not allow this",
+        ),
+        404 => (io::ErrorKind::NotFound, "no such file on the server"),
+        409 => (
+            io::ErrorKind::NotFound,
+            "the folder to move it into does not exist",
+        ),
+        412 => (
+            io::ErrorKind::AlreadyExists,
+            "a file already has the new name",
+        ),
+        423 => (io::ErrorKind::Other, "the file is locked on the server"),
+        507 => (io::ErrorKind::Other, "the server is out of space"),
+        _ => {
+            return io::Error::other(format!("the server answered {}", status));
+        }
+    };
+    io::Error::new(kind, message)
+}
+
+#[derive(Debug, Default)]
+struct Props {
+    collection: bool,
+    length: Option<u64>,
+    modified: Option<DateTime<Utc>>,
+}
+
+/// The `href` and properties of each response in a `multistatus` reply.
+fn multistatus(xml: &str) -> io::Result<Vec<(String, Props)>> {
+    let mut responses: Vec<(String, Props)> = Vec::new();
+    for (path, text) in xml::leaves(xml)? {
+        let names: Vec<&str> = path.iter().map(String::as_str).collect();
+        match names.as_slice() {
+            [.., "response", "href"] => responses.push((text, Props::default())),
+            _ if !names.contains(&"prop") => {}
+            [.., "resourcetype", "collection"] => {
+                if let Some((_, props)) = responses.last_mut() {
+                    props.collection = true;
+                }
+            }
+            [.., "getcontentlength"] => {
+                if let Some((_, props)) = responses.last_mut() {
+                    props.length = text.parse().ok();
+                }
+            }
+            [.., "getlastmodified"] => {
+                if let Some((_, props)) = responses.last_mut() {
+                    props.modified = DateTime::parse_from_rfc2822(&text)
+                        .ok()
+                        .map(|t| t.with_timezone(&Utc));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(responses)
+}
@@ -0,0 +1,56 @@
+//! Reading the XML replies of WebDAV servers and object stores, which only
+//! need the text of a few elements.
+
+use std::io;
+
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+/// Every element without child elements, in document order, as the local
+/// names of the elements leading to it, outermost first, and its text.
+/// Namespace prefixes are dropped, so `<d:href>` is `href`.
+pub(crate) fn leaves(xml: &str) -> io::Result<Vec<(Vec<String>, String)>> {
+    let malformed = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut reader = Reader::from_str(xml);
+    let mut path: Vec<String> = Vec::new();
+    let mut leaves = Vec::new();
+    let mut text = String::new();
+    // Whether the innermost open element has had a child.
+    let mut parent = false;
+    loop {
+        match reader.read_event().map_err(|e| malformed(e.to_string()))? {
+            Event::Start(e) => {
+                path.push(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+                text.clear();
+                parent = false;
+            }
+            Event::Empty(e) => {
+                path.push(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+                leaves.push((path.clone(), String::new()));
+                path.pop();
+                parent = true;
+            }
+            Event::Text(e) => text.push_str(&e.decode().map_err(|e| malformed(e.to_string()))?),
+            Event::CData(e) => text.push_str(&e.decode().map_err(|e| malformed(e.to_string()))?),
+            Event::GeneralRef(e) => {
+                let name = e.decode().map_err(|e| malformed(e.to_string()))?;
+                match e.resolve_char_ref().map_err(|e| malformed(e.to_string()))? {
+                    Some(c) => text.push(c),
+                    None => text.push_str(resolve_predefined_entity(&name).unwrap_or_default()),
+                }
+            }
+            Event::End(_) => {
+                if !parent {
+                    leaves.push((path.clone(), text.trim().to_string()));
+                }
+                text.clear();
+                path.pop();
+                parent = true;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(leaves)
+}
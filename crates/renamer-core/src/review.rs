@@ -0,0 +1,222 @@
+//! Plans reviewed in a spreadsheet: a plan written to a CSV file someone
+//! else can edit, then read back and checked against the plan before any of
+//! it runs.
+//!
+//! Each row has an `id`, made from the file's path so that it stays the same
+//! however the rows are sorted or filtered, the `source`, the `proposed` name
+//! as the plan has it and a `new_name` column to edit. The new name is read
+//! as a mapping file's is: a bare name keeps the file in its folder, a
+//! relative path is taken from that folder. Deleting a row leaves its file
+//! alone. Companions are not written; they follow their file when the ops are
+//! planned again.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::mapping;
+use crate::plan::{PlanEntry, PlanWarning, RenameOp, RenamePlan};
+
+/// A line of the review file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewRow {
+    pub id: String,
+    pub source: PathBuf,
+    pub proposed: String,
+    pub new_name: String,
+}
+
+/// What became of a file of the plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    /// The row still has the proposed name.
+    Kept,
+    /// The reviewer gave the file another name.
+    Edited,
+    /// The row was deleted; the file is left alone.
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewChange {
+    pub id: String,
+    pub source: PathBuf,
+    /// Where the plan sent the file.
+    pub proposed: PathBuf,
+    /// Where the reviewed file sends it; `None` once removed.
+    pub target: Option<PathBuf>,
+    pub status: ReviewStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewProblemKind {
+    /// No file of the plan has the row's id, or the row has none.
+    UnknownId,
+    /// The row's source is not the file its id stands for; it was edited
+    /// instead of the new name.
+    SourceChanged,
+    /// An earlier row has the same id.
+    Duplicate,
+    /// The new name was cleared; delete the row to leave the file alone.
+    MissingNewName,
+}
+
+/// A row that was not taken; its file is left alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewProblem {
+    /// One-based line in the file.
+    pub line: u64,
+    pub id: String,
+    pub kind: ReviewProblemKind,
+}
+
+/// A reviewed plan, ready for [`crate::plan`] once the problems are dealt
+/// with.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Review {
+    pub ops: Vec<RenameOp>,
+    /// The files of the plan in its order, less those whose row is a
+    /// problem.
+    pub changes: Vec<ReviewChange>,
+    pub problems: Vec<ReviewProblem>,
+}
+
+/// The id of the row for `source`.
+pub fn review_id(source: &Path) -> String {
+    let digest = Sha256::digest(source.to_string_lossy().as_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `target` as the `new_name` column has it, from the folder of `source`.
+fn new_name(source: &Path, target: &Path) -> String {
+    let folder = source.parent().unwrap_or(Path::new(""));
+    match target.strip_prefix(folder) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => target.to_string_lossy().into_owned(),
+    }
+}
+
+fn reviewed(plan: &RenamePlan) -> impl Iterator<Item = &PlanEntry> {
+    plan.entries.iter().filter(|e| e.companion_of.is_none())
+}
+
+pub fn review_rows(plan: &RenamePlan) -> Vec<ReviewRow> {
+    reviewed(plan)
+        .map(|entry| {
+            let name = new_name(&entry.source, &entry.target);
+            ReviewRow {
+                id: review_id(&entry.source),
+                source: entry.source.clone(),
+                proposed: name.clone(),
+                new_name: name,
+            }
+        })
+        .collect()
+}
+
+/// Writes the files of `plan` to `path` for review.
+pub fn export(plan: &RenamePlan, path: &Path) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in review_rows(plan) {
+        writer.serialize(row)?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| Error::io(path, e.into_error()))?;
+    fs::write(path, bytes).map_err(|e| Error::io(path, e))
+}
+
+/// Reads the reviewed file at `path`, see [`parse`].
+pub fn read(path: &Path, plan: &RenamePlan) -> Result<Review> {
+    let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+    parse(&String::from_utf8_lossy(&bytes), plan)
+}
+
+/// Matches the rows of `text`, a reviewed file, to the files of `plan`.
+/// Columns may be moved and rows sorted; a spreadsheet that saved it with
+/// tabs or semicolons is read as well.
+pub fn parse(text: &str, plan: &RenamePlan) -> Result<Review> {
+    let text = text.trim_start_matches('\u{feff}');
+    let entries: HashMap<String, &PlanEntry> = reviewed(plan)
+        .map(|entry| (review_id(&entry.source), entry))
+        .collect();
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .delimiter(mapping::delimiter(text))
+        .from_reader(text.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut targets: HashMap<String, PathBuf> = HashMap::new();
+    let mut problems = Vec::new();
+    let mut seen = HashSet::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line());
+        let row: ReviewRow = record.deserialize(Some(&headers))?;
+        if row == ReviewRow::default() {
+            continue;
+        }
+        let problem = |kind| ReviewProblem {
+            line,
+            id: row.id.clone(),
+            kind,
+        };
+        let Some(entry) = entries.get(&row.id) else {
+            problems.push(problem(ReviewProblemKind::UnknownId));
+            continue;
+        };
+        let kind = if !seen.insert(row.id.clone()) {
+            ReviewProblemKind::Duplicate
+        } else if !row.source.as_os_str().is_empty() && row.source != entry.source {
+            ReviewProblemKind::SourceChanged
+        } else if row.new_name.is_empty() {
+            ReviewProblemKind::MissingNewName
+        } else {
+            targets.insert(row.id, mapping::target(&entry.source, &row.new_name));
+            continue;
+        };
+        problems.push(problem(kind));
+    }
+
+    let mut review = Review {
+        problems,
+        ..Review::default()
+    };
+    for entry in reviewed(plan) {
+        let id = review_id(&entry.source);
+        if !targets.contains_key(&id) && seen.contains(&id) {
+            continue;
+        }
+        let target = targets.remove(&id);
+        let status = match &target {
+            None => ReviewStatus::Removed,
+            Some(target) if *target == entry.target => ReviewStatus::Kept,
+            Some(_) => ReviewStatus::Edited,
+        };
+        if let Some(target) = &target {
+            review.ops.push(RenameOp {
+                source: entry.source.clone(),
+                target: target.clone(),
+                modified: entry.modified,
+                title: entry.title.clone(),
+                shortened: status == ReviewStatus::Kept
+                    && entry.warnings.contains(&PlanWarning::Shortened),
+            });
+        }
+        review.changes.push(ReviewChange {
+            id,
+            source: entry.source.clone(),
+            proposed: entry.target.clone(),
+            target,
+            status,
+        });
+    }
+    Ok(review)
+}
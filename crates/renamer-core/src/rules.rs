@@ -0,0 +1,791 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::case::{self, CaseLocale, CaseMode};
+use crate::condition::{Check, Condition, ConditionCheck};
+use crate::error::{Error, Result};
+use crate::hash::HashAlgorithm;
+use crate::metadata::DateSource;
+use crate::normalize::{self, NormalForm};
+use crate::plugins::{self, Plugin};
+use crate::sanitize::{self, SanitizeFix, TargetFs};
+use crate::script::Script;
+use crate::sniff::{self, ExtensionFix};
+use crate::template::{DatePattern, FileContext, Template};
+use crate::translit::{self, TranslitLocale};
+
+/// Splits a file name into stem and extension, keeping the dot on the
+/// extension. Dotfiles such as `.bashrc` have no extension.
+pub fn split_name(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(0) | None => (name, ""),
+        Some(i) => name.split_at(i),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_replacement() -> String {
+    "_".to_string()
+}
+
+fn default_script_timeout() -> u64 {
+    200
+}
+
+/// Regex find/replace. `replacement` may reference capture groups as `$1`,
+/// `${1}`, `$name` or `${name}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegexRule {
+    pub pattern: String,
+    pub replacement: String,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// Replace every match instead of only the first.
+    #[serde(default = "default_true")]
+    pub replace_all: bool,
+}
+
+impl RegexRule {
+    pub fn compile(&self) -> Result<Regex> {
+        RegexBuilder::new(&self.pattern)
+            .case_insensitive(self.case_insensitive)
+            .build()
+            .map_err(|source| Error::InvalidPattern {
+                pattern: self.pattern.clone(),
+                source,
+            })
+    }
+}
+
+/// One step of a rename pipeline. Rules operate on the full file name,
+/// extension included, and are applied in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+    /// Literal find/replace of every occurrence.
+    Replace {
+        find: String,
+        replace: String,
+    },
+    /// Prepends text to the name.
+    Prefix {
+        text: String,
+    },
+    /// Appends text to the stem, before the extension.
+    Suffix {
+        text: String,
+    },
+    Regex(RegexRule),
+    /// Find/replace over the file's path, folders included, so
+    /// `2023_draft/` becomes `2023_final/` for every file in that folder.
+    /// The path is taken below `root` if the file is in it, and whole
+    /// otherwise, with `/` between folders on every platform; after the
+    /// replacement, what comes after the last `/` is the name and the rest
+    /// the folder the file moves to, created when the batch runs. With
+    /// `regex`, `find` is a regular expression and `replace` may use `$1`.
+    /// Empty folder names, `.` and `..` are dropped.
+    ReplacePath {
+        find: String,
+        replace: String,
+        #[serde(default)]
+        regex: bool,
+        #[serde(default)]
+        root: Option<PathBuf>,
+    },
+    /// Replaces the whole name with a rendered `{token}` template.
+    Template {
+        template: String,
+    },
+    /// Leaves the name alone and moves the file into `folder`, a template in
+    /// which `/` separates folders, e.g. `{date:yyyy}/{date:MM}` or `{ext}`.
+    /// The folder is taken from `root`, or from the file's own folder if
+    /// there is none (a relative `root` is too), and created when the batch
+    /// runs. Empty folder names, `.` and `..` are dropped, so files cannot
+    /// be moved above the root. Moving to another volume needs
+    /// [`crate::ApplyOptions::allow_cross_volume`].
+    Organize {
+        folder: String,
+        #[serde(default)]
+        root: Option<PathBuf>,
+    },
+    /// Changes letter case. Only the stem is converted unless
+    /// `include_extension` is set.
+    Case {
+        mode: CaseMode,
+        /// BCP 47 language tag; `tr`/`az` enable Turkish dotted/dotless i.
+        #[serde(default)]
+        locale: Option<String>,
+        #[serde(default)]
+        include_extension: bool,
+    },
+    /// Rewrites the whole name in the given Unicode normalization form.
+    Normalize {
+        form: NormalForm,
+    },
+    /// Spells the whole name in ASCII, see [`translit::transliterate`].
+    Transliterate {
+        /// BCP 47 language tag for its own spellings, e.g. `de` for `ü` as
+        /// `ue`.
+        #[serde(default)]
+        locale: Option<String>,
+        /// What characters with no ASCII spelling become; kept if unset.
+        #[serde(default)]
+        unknown: Option<String>,
+    },
+    /// Leaves the name alone and sets the file's modification time to a date
+    /// found in it, e.g. `IMG_20230714_101500.jpg` with `yyyyMMdd_HHmmss`.
+    /// Uses the template date syntax; the name is read as it is at this
+    /// point of the chain.
+    SetModified {
+        format: String,
+    },
+    /// Replaces characters `filesystem` does not allow and fixes names it
+    /// would refuse, see [`sanitize::sanitize`].
+    Sanitize {
+        filesystem: TargetFs,
+        #[serde(default = "default_replacement")]
+        replacement: String,
+    },
+    /// Cuts the name to at most `max` bytes, or, with `path`, cuts it so the
+    /// whole new path is at most `max` bytes; see [`sanitize::shorten`] for
+    /// what is kept. Names cut are reported in [`Renamed::shortened`].
+    Truncate {
+        max: usize,
+        #[serde(default)]
+        path: bool,
+    },
+    /// Replaces the extension with the one the file's contents call for, or
+    /// adds it if there is none, see [`sniff::fix_extension`].
+    FixExtension,
+    /// Leaves the name alone and, once the batch is renamed, writes the name
+    /// as it is at this point of the chain, without its extension, into the
+    /// file as its title; see [`crate::metadata::write_title`] for the
+    /// formats. Undoing the batch restores the name but not the old title.
+    WriteTitle,
+    /// Runs `rules` only for files all `conditions` hold for, or any one of
+    /// them if `any` is set. Which conditions held is reported in
+    /// [`Renamed::conditions`].
+    When {
+        conditions: Vec<Condition>,
+        #[serde(default)]
+        any: bool,
+        rules: Vec<Rule>,
+    },
+    /// Replaces the name with what a [Rhai](https://rhai.rs) script returns.
+    /// The script sees the name as it is at this point of the chain:
+    ///
+    /// - `name`, and `stem` and `ext` its two halves (`ext` keeps its dot,
+    ///   so `stem + ext == name`)
+    /// - `meta`, a map of `size` in bytes, `modified` and every metadata key
+    ///   the file has a value for, e.g. `meta["exif.camera"]`; dates are
+    ///   `yyyy-mm-ddThh:mm:ss` strings
+    ///
+    /// Returning `()` keeps the name. A script that fails, or runs longer
+    /// than `timeout_ms`, keeps the name too and its error is reported in
+    /// [`Renamed::step_errors`].
+    Script {
+        script: String,
+        #[serde(default = "default_script_timeout")]
+        timeout_ms: u64,
+    },
+    /// Replaces the name with what `rule` of an enabled [`crate::plugins`]
+    /// plugin returns, passing it `options`. Errors are reported in
+    /// [`Renamed::step_errors`] and keep the name.
+    Plugin {
+        plugin: String,
+        rule: String,
+        #[serde(default)]
+        options: Value,
+    },
+}
+
+/// What [`Rule::ReplacePath`] looks for.
+#[derive(Debug, Clone)]
+enum PathFind {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl PathFind {
+    fn replace(&self, path: &str, replace: &str) -> String {
+        match self {
+            PathFind::Literal(find) if find.is_empty() => path.to_string(),
+            PathFind::Literal(find) => path.replace(find.as_str(), replace),
+            PathFind::Regex(re) => re.replace_all(path, replace).into_owned(),
+        }
+    }
+}
+
+/// A rule with everything expensive (regexes) prepared up front.
+#[derive(Debug, Clone)]
+enum Step {
+    Replace {
+        find: String,
+        replace: String,
+    },
+    Prefix(String),
+    Suffix(String),
+    Regex {
+        re: Regex,
+        replacement: String,
+        replace_all: bool,
+    },
+    Template(Template),
+    ReplacePath {
+        find: PathFind,
+        replace: String,
+        root: Option<PathBuf>,
+    },
+    Organize {
+        folder: Template,
+        root: Option<PathBuf>,
+    },
+    Case {
+        mode: CaseMode,
+        locale: CaseLocale,
+        include_extension: bool,
+    },
+    Normalize(NormalForm),
+    Transliterate {
+        locale: TranslitLocale,
+        unknown: Option<String>,
+    },
+    SetModified(DatePattern),
+    Sanitize {
+        filesystem: TargetFs,
+        replacement: String,
+    },
+    Truncate {
+        max: usize,
+        path: bool,
+    },
+    FixExtension,
+    WriteTitle,
+    When {
+        checks: Vec<Check>,
+        any: bool,
+        steps: Vec<Step>,
+    },
+    Script(Script),
+    Plugin {
+        plugin: Arc<Plugin>,
+        rule: String,
+        options: Value,
+    },
+}
+
+impl Step {
+    fn compile(rule: &Rule) -> Result<Self> {
+        Ok(match rule {
+            Rule::Replace { find, replace } => Step::Replace {
+                find: find.clone(),
+                replace: replace.clone(),
+            },
+            Rule::Prefix { text } => Step::Prefix(text.clone()),
+            Rule::Suffix { text } => Step::Suffix(text.clone()),
+            Rule::Regex(r) => Step::Regex {
+                re: r.compile()?,
+                replacement: r.replacement.clone(),
+                replace_all: r.replace_all,
+            },
+            Rule::Template { template } => Step::Template(Template::parse(template)?),
+            Rule::ReplacePath {
+                find,
+                replace,
+                regex,
+                root,
+            } => Step::ReplacePath {
+                find: if *regex {
+                    PathFind::Regex(Regex::new(find).map_err(|source| Error::InvalidPattern {
+                        pattern: find.clone(),
+                        source,
+                    })?)
+                } else {
+                    PathFind::Literal(find.clone())
+                },
+                replace: replace.clone(),
+                root: root.clone(),
+            },
+            Rule::Organize { folder, root } => Step::Organize {
+                folder: Template::parse(folder)?,
+                root: root.clone(),
+            },
+            Rule::Case {
+                mode,
+                locale,
+                include_extension,
+            } => Step::Case {
+                mode: *mode,
+                locale: CaseLocale::from_tag(locale.as_deref()),
+                include_extension: *include_extension,
+            },
+            Rule::Normalize { form } => Step::Normalize(*form),
+            Rule::Transliterate { locale, unknown } => Step::Transliterate {
+                locale: TranslitLocale::from_tag(locale.as_deref()),
+                unknown: unknown.clone(),
+            },
+            Rule::SetModified { format } => Step::SetModified(DatePattern::parse(format)?),
+            Rule::Sanitize {
+                filesystem,
+                replacement,
+            } => {
+                if replacement.chars().any(|c| filesystem.forbids(c)) {
+                    return Err(Error::InvalidReplacement {
+                        replacement: replacement.clone(),
+                    });
+                }
+                Step::Sanitize {
+                    filesystem: *filesystem,
+                    replacement: replacement.clone(),
+                }
+            }
+            Rule::Truncate { max, path } => Step::Truncate {
+                max: *max,
+                path: *path,
+            },
+            Rule::FixExtension => Step::FixExtension,
+            Rule::WriteTitle => Step::WriteTitle,
+            Rule::When {
+                conditions,
+                any,
+                rules,
+            } => Step::When {
+                checks: conditions
+                    .iter()
+                    .map(Check::compile)
+                    .collect::<Result<_>>()?,
+                any: *any,
+                steps: rules.iter().map(Step::compile).collect::<Result<_>>()?,
+            },
+            Rule::Script { script, timeout_ms } => {
+                Step::Script(Script::compile(script, Duration::from_millis(*timeout_ms))?)
+            }
+            Rule::Plugin {
+                plugin,
+                rule,
+                options,
+            } => match plugins::find(plugin) {
+                Some(found) if found.has_rule(rule) => Step::Plugin {
+                    plugin: found,
+                    rule: rule.clone(),
+                    options: options.clone(),
+                },
+                _ => {
+                    return Err(Error::UnknownPluginRule {
+                        plugin: plugin.clone(),
+                        rule: rule.clone(),
+                    })
+                }
+            },
+        })
+    }
+
+    fn apply(&self, name: &str, ctx: &FileContext) -> String {
+        match self {
+            Step::Replace { find, replace } => {
+                if find.is_empty() {
+                    name.to_string()
+                } else {
+                    name.replace(find.as_str(), replace)
+                }
+            }
+            Step::Prefix(text) => format!("{}{}", text, name),
+            Step::Suffix(text) => {
+                let (stem, ext) = split_name(name);
+                format!("{}{}{}", stem, text, ext)
+            }
+            Step::Regex {
+                re,
+                replacement,
+                replace_all,
+            } => {
+                if *replace_all {
+                    re.replace_all(name, replacement.as_str()).into_owned()
+                } else {
+                    re.replace(name, replacement.as_str()).into_owned()
+                }
+            }
+            Step::Template(template) => template.render(name, ctx),
+            Step::Case {
+                mode,
+                locale,
+                include_extension,
+            } => {
+                if *include_extension {
+                    case::convert(name, *mode, *locale)
+                } else {
+                    let (stem, ext) = split_name(name);
+                    format!("{}{}", case::convert(stem, *mode, *locale), ext)
+                }
+            }
+            Step::Normalize(form) => normalize::normalize(name, *form),
+            Step::Transliterate { locale, unknown } => {
+                translit::transliterate(name, *locale, unknown.as_deref())
+            }
+            Step::SetModified(_)
+            | Step::WriteTitle
+            | Step::When { .. }
+            | Step::Organize { .. }
+            | Step::ReplacePath { .. } => name.to_string(),
+            Step::Sanitize {
+                filesystem,
+                replacement,
+            } => sanitize::sanitize(name, *filesystem, replacement).0,
+            Step::Truncate { max, path } => {
+                let folder = ctx.path.parent().unwrap_or(Path::new(""));
+                sanitize::shorten(name, name_budget(*max, *path, folder))
+                    .unwrap_or_else(|| name.to_string())
+            }
+            Step::FixExtension => fixed_extension(name, ctx)
+                .map(|(name, _)| name)
+                .unwrap_or_else(|| name.to_string()),
+            Step::Script(script) => script.run(name, ctx).unwrap_or_else(|_| name.to_string()),
+            Step::Plugin {
+                plugin,
+                rule,
+                options,
+            } => plugin
+                .rule(rule, options, name, ctx)
+                .unwrap_or_else(|_| name.to_string()),
+        }
+    }
+}
+
+/// The bytes a [`Rule::Truncate`] leaves the name of a file in `folder`:
+/// with `path`, what the folder and the separator after it leave of `max`.
+fn name_budget(max: usize, path: bool, folder: &Path) -> usize {
+    let len = folder.as_os_str().len();
+    match path {
+        true => max.saturating_sub(len + usize::from(len > 0)),
+        false => max,
+    }
+}
+
+fn fixed_extension(name: &str, ctx: &FileContext) -> Option<(String, ExtensionFix)> {
+    sniff::fix_extension(name, ctx.content_type.as_ref()?)
+}
+
+/// Every step in `steps`, including those inside conditional rules.
+fn walk<'a>(steps: &'a [Step], out: &mut Vec<&'a Step>) {
+    for step in steps {
+        out.push(step);
+        if let Step::When { steps, .. } = step {
+            walk(steps, out);
+        }
+    }
+}
+
+/// `base` joined with each folder of a rendered [`Rule::Organize`] template.
+fn subfolder(mut base: PathBuf, rendered: &str) -> PathBuf {
+    for part in rendered.split(['/', '\\']).map(str::trim) {
+        if !matches!(part, "" | "." | "..") {
+            base.push(part);
+        }
+    }
+    base
+}
+
+/// Splits `folder` into where [`Rule::ReplacePath`] starts from and the
+/// path below it, `/`-separated: below `root` when `folder` is in it, and
+/// below the drive or filesystem root otherwise.
+fn path_below(folder: &Path, root: Option<&Path>) -> (PathBuf, String) {
+    let (base, below) = match root.and_then(|root| Some((root, folder.strip_prefix(root).ok()?))) {
+        Some((root, below)) => (root.to_path_buf(), below.to_path_buf()),
+        None => {
+            let base: PathBuf = folder
+                .components()
+                .take_while(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+                .collect();
+            let below = folder.strip_prefix(&base).unwrap_or(folder).to_path_buf();
+            (base, below)
+        }
+    };
+    let parts: Vec<String> = below
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    (base, parts.join("/"))
+}
+
+/// Runs `steps` over `renamed`; `at` is the position of the conditional rule
+/// holding them, empty at the top of the chain.
+fn run_steps(steps: &[Step], at: &mut Vec<usize>, renamed: &mut Renamed, ctx: &FileContext) {
+    for (i, step) in steps.iter().enumerate() {
+        at.push(i);
+        run_step(step, at, renamed, ctx);
+        renamed.steps.push(StepName {
+            rule: at.clone(),
+            name: renamed.name.clone(),
+        });
+        at.pop();
+    }
+}
+
+/// Runs one step; `at` is where it is in the chain.
+fn run_step(step: &Step, at: &mut Vec<usize>, renamed: &mut Renamed, ctx: &FileContext) {
+    match step {
+        Step::SetModified(pattern) => {
+            renamed.modified = pattern.find(&renamed.name).or(renamed.modified);
+        }
+        Step::Sanitize {
+            filesystem,
+            replacement,
+        } => {
+            let (name, fixes) = sanitize::sanitize(&renamed.name, *filesystem, replacement);
+            renamed.name = name;
+            renamed.sanitized.extend(fixes);
+            return;
+        }
+        Step::Truncate { max, path } => {
+            let folder = match &renamed.folder {
+                Some(folder) => folder.as_path(),
+                None => ctx.path.parent().unwrap_or(Path::new("")),
+            };
+            let max = name_budget(*max, *path, folder);
+            if let Some(name) = sanitize::shorten(&renamed.name, max) {
+                renamed.name = name;
+                renamed.shortened = true;
+            }
+            return;
+        }
+        Step::FixExtension => {
+            if let Some((name, fix)) = fixed_extension(&renamed.name, ctx) {
+                renamed.name = name;
+                renamed.extension_fix = Some(fix);
+            }
+            return;
+        }
+        Step::WriteTitle => {
+            renamed.title = Some(split_name(&renamed.name).0.to_string());
+        }
+        Step::ReplacePath {
+            find,
+            replace,
+            root,
+        } => {
+            let folder = match &renamed.folder {
+                Some(folder) => folder.clone(),
+                None => ctx.path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            };
+            let (base, below) = path_below(&folder, root.as_deref());
+            let path = match below.is_empty() {
+                true => renamed.name.clone(),
+                false => format!("{}/{}", below, renamed.name),
+            };
+            let replaced = find.replace(&path, replace);
+            let (new_folder, name) = match replaced.rfind('/') {
+                Some(i) => (subfolder(base, &replaced[..i]), &replaced[i + 1..]),
+                None => (base, replaced.as_str()),
+            };
+            if new_folder != folder {
+                renamed.folder = Some(new_folder);
+            }
+            renamed.name = name.to_string();
+            return;
+        }
+        Step::Organize { folder, root } => {
+            let base = ctx.path.parent().unwrap_or(Path::new(""));
+            let base = match root {
+                Some(root) => base.join(root),
+                None => base.to_path_buf(),
+            };
+            let source = folder.date_source(&renamed.name, ctx);
+            renamed.date_source = source.or(renamed.date_source);
+            renamed.folder = Some(subfolder(base, &folder.render(&renamed.name, ctx)));
+            return;
+        }
+        Step::When { checks, any, steps } => {
+            let matched: Vec<bool> = checks.iter().map(|c| c.holds(&renamed.name, ctx)).collect();
+            let applied = if *any {
+                matched.contains(&true)
+            } else {
+                !matched.contains(&false)
+            };
+            renamed.conditions.push(ConditionCheck {
+                rule: at.clone(),
+                matched,
+                applied,
+            });
+            if applied {
+                run_steps(steps, at, renamed, ctx);
+            }
+            return;
+        }
+        Step::Script(script) => {
+            match script.run(&renamed.name, ctx) {
+                Ok(name) => renamed.name = name,
+                Err(e) => renamed.step_errors.push(e),
+            }
+            return;
+        }
+        Step::Plugin {
+            plugin,
+            rule,
+            options,
+        } => {
+            match plugin.rule(rule, options, &renamed.name, ctx) {
+                Ok(name) => renamed.name = name,
+                Err(e) => renamed.step_errors.push(e),
+            }
+            return;
+        }
+        Step::Template(template) => {
+            let source = template.date_source(&renamed.name, ctx);
+            renamed.date_source = source.or(renamed.date_source);
+        }
+        _ => {}
+    }
+    renamed.name = step.apply(&renamed.name, ctx);
+}
+
+/// A compiled rule chain, built once and reused for every file in a batch.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn new(rules: &[Rule]) -> Result<Self> {
+        let steps = rules.iter().map(Step::compile).collect::<Result<_>>()?;
+        Ok(Pipeline { steps })
+    }
+
+    fn all_steps(&self) -> Vec<&Step> {
+        let mut all = Vec::new();
+        walk(&self.steps, &mut all);
+        all
+    }
+
+    /// True if any step or condition reads file metadata (size, dates).
+    pub fn needs_metadata(&self) -> bool {
+        self.all_steps().iter().any(|s| match s {
+            Step::Template(t) | Step::Organize { folder: t, .. } => t.needs_metadata(),
+            Step::Script(_) | Step::Plugin { .. } => true,
+            Step::When { checks, .. } => checks.iter().any(Check::needs_metadata),
+            _ => false,
+        })
+    }
+
+    /// True if any step or condition reads properties from inside the file
+    /// (EXIF).
+    pub fn needs_embedded(&self) -> bool {
+        self.all_steps().iter().any(|s| match s {
+            Step::Template(t) | Step::Organize { folder: t, .. } => t.needs_embedded(),
+            Step::Script(_) | Step::Plugin { .. } => true,
+            Step::When { checks, .. } => checks.iter().any(Check::needs_embedded),
+            _ => false,
+        })
+    }
+
+    /// True if a template reads the text recognized in the file.
+    pub fn needs_ocr(&self) -> bool {
+        self.all_steps().iter().any(|s| match s {
+            Step::Template(t) | Step::Organize { folder: t, .. } => t.needs_ocr(),
+            _ => false,
+        })
+    }
+
+    /// True if a step needs to know the file's format from its contents.
+    pub fn needs_content_type(&self) -> bool {
+        self.all_steps()
+            .iter()
+            .any(|s| matches!(s, Step::FixExtension))
+    }
+
+    /// The templates of the steps, for [`crate::template::assign_groups`].
+    pub fn templates(&self) -> Vec<&Template> {
+        self.all_steps()
+            .into_iter()
+            .filter_map(|s| match s {
+                Step::Template(t) | Step::Organize { folder: t, .. } => Some(t),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The hash algorithms the steps' `{hash}` tokens use.
+    pub fn hash_algorithms(&self) -> Vec<HashAlgorithm> {
+        let mut algorithms = Vec::new();
+        for step in self.all_steps() {
+            if let Step::Template(t) | Step::Organize { folder: t, .. } = step {
+                for algorithm in t.hash_algorithms() {
+                    if !algorithms.contains(&algorithm) {
+                        algorithms.push(algorithm);
+                    }
+                }
+            }
+        }
+        algorithms
+    }
+
+    /// Runs `name` through every step in order.
+    pub fn apply(&self, name: &str, ctx: &FileContext) -> String {
+        self.run(name, ctx).name
+    }
+
+    /// Like [`Pipeline::apply`], also collecting what the rules want changed
+    /// besides the name.
+    pub fn run(&self, name: &str, ctx: &FileContext) -> Renamed {
+        let mut renamed = Renamed {
+            name: name.to_string(),
+            modified: None,
+            sanitized: Vec::new(),
+            extension_fix: None,
+            title: None,
+            folder: None,
+            conditions: Vec::new(),
+            step_errors: Vec::new(),
+            steps: Vec::new(),
+            shortened: false,
+            date_source: None,
+        };
+        run_steps(&self.steps, &mut Vec::new(), &mut renamed, ctx);
+        renamed
+    }
+}
+
+/// The outcome of running a [`Pipeline`] over one file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Renamed {
+    pub name: String,
+    /// Modification time to give the file, from [`Rule::SetModified`].
+    pub modified: Option<DateTime<Local>>,
+    /// What [`Rule::Sanitize`] had to change.
+    pub sanitized: Vec<SanitizeFix>,
+    /// The extension [`Rule::FixExtension`] corrected.
+    pub extension_fix: Option<ExtensionFix>,
+    /// A [`Rule::Truncate`] cut the name.
+    pub shortened: bool,
+    /// Where the date of the last `{best_date}` token rendered came from.
+    pub date_source: Option<DateSource>,
+    /// Title to write into the file, from [`Rule::WriteTitle`].
+    pub title: Option<String>,
+    /// Folder to move the file into, from [`Rule::Organize`].
+    pub folder: Option<PathBuf>,
+    /// The [`Rule::When`] conditions checked, in the order they ran.
+    pub conditions: Vec<ConditionCheck>,
+    /// Why [`Rule::Script`] and [`Rule::Plugin`] steps kept the name.
+    pub step_errors: Vec<String>,
+    /// The name after each rule that ran, in order, to find the rule that
+    /// broke it.
+    pub steps: Vec<StepName>,
+}
+
+/// The name of a file once one rule of the chain ran.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepName {
+    /// Where the rule is, as in [`ConditionCheck::rule`]. The rules inside a
+    /// [`Rule::When`] come before the `When` itself, which holds the name
+    /// once they all ran.
+    pub rule: Vec<usize>,
+    pub name: String,
+}
@@ -0,0 +1,197 @@
+//! Making names legal on the filesystem they are headed for.
+//!
+//! Linux filesystems only refuse `/` and NUL. Windows refuses a lot more:
+//! `<>:"/\|?*`, control characters, names ending in a dot or space and the old
+//! DOS device names (`CON`, `COM1.txt`, ...). exFAT drives and network shares
+//! are usually read from Windows as well, so they get the Windows rules.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetFs {
+    /// ext4, btrfs, XFS and other Linux filesystems.
+    Ext4,
+    Ntfs,
+    Exfat,
+    /// SMB/CIFS network shares.
+    Smb,
+}
+
+impl TargetFs {
+    /// The filesystem rules of the platform we run on.
+    pub fn host() -> Self {
+        if cfg!(windows) {
+            TargetFs::Ntfs
+        } else {
+            TargetFs::Ext4
+        }
+    }
+
+    pub(crate) fn windows_rules(self) -> bool {
+        self != TargetFs::Ext4
+    }
+
+    /// True if DOS device names such as `CON` are off limits.
+    pub fn reserves_device_names(self) -> bool {
+        self.windows_rules()
+    }
+
+    /// True if `c` may not appear in a name at all.
+    pub fn forbids(self, c: char) -> bool {
+        match c {
+            '\0' | '/' => true,
+            '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' => self.windows_rules(),
+            c if c.is_ascii_control() && c != '\x7f' => self.windows_rules(),
+            _ => false,
+        }
+    }
+}
+
+/// A change [`sanitize`] made to a name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SanitizeFix {
+    /// Every `character` in the name was replaced.
+    Replaced { character: char, count: usize },
+    /// Dots and spaces at the end of the name were dropped.
+    TrimmedTrailing { removed: String },
+    /// The stem is a reserved device name and was extended to make it usable.
+    ReservedName { name: String },
+}
+
+const DEVICE_NAMES: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+
+/// `CON`, `com1` and the like, with or without an extension.
+pub fn is_device_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    let upper = stem.to_ascii_uppercase();
+    if DEVICE_NAMES.contains(&upper.as_str()) {
+        return true;
+    }
+    match upper
+        .strip_prefix("COM")
+        .or_else(|| upper.strip_prefix("LPT"))
+    {
+        Some(n) => n.len() == 1 && matches!(n.as_bytes()[0], b'1'..=b'9'),
+        None => false,
+    }
+}
+
+/// Extends the stem of a device name with `fill` (`_` if empty), so `con.txt`
+/// becomes `con_.txt`. `None` if `name` is not a device name.
+pub fn fix_device_name(name: &str, fill: &str) -> Option<String> {
+    if !is_device_name(name) {
+        return None;
+    }
+    let mut fixed = name.to_string();
+    let at = fixed.find('.').unwrap_or(fixed.len());
+    fixed.insert_str(at, if fill.is_empty() { "_" } else { fill });
+    Some(fixed)
+}
+
+/// What separates words in a name, for [`shorten`].
+const WORD_SEPARATORS: &[char] = &[' ', '_', '-', '.', ','];
+
+/// The last word of `stem` with what separates it, if it looks like a
+/// counter or a hash: digits, `(2)`, or six or more hex digits.
+fn numbered_tail(stem: &str) -> &str {
+    let Some(at) = stem.rfind(WORD_SEPARATORS) else {
+        return "";
+    };
+    let word = &stem[at + 1..];
+    let digits = word
+        .strip_prefix('(')
+        .and_then(|w| w.strip_suffix(')'))
+        .unwrap_or(word);
+    let numbered = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+    let hashed = word.len() >= 6
+        && word.bytes().all(|b| b.is_ascii_hexdigit())
+        && word.bytes().any(|b| b.is_ascii_digit());
+    if numbered || hashed {
+        &stem[at..]
+    } else {
+        ""
+    }
+}
+
+/// Cuts `name` to at most `max` bytes of UTF-8, which no filesystem counts
+/// more of. The extension and a counter or hash at the end of the stem are
+/// kept; the rest is cut after the last whole word that fits, or inside a
+/// word if the first one does not. `None` if `name` already fits, or if
+/// `max` leaves nothing of it.
+pub fn shorten(name: &str, max: usize) -> Option<String> {
+    if name.len() <= max || max == 0 {
+        return None;
+    }
+    let (stem, ext) = crate::rules::split_name(name);
+    let tail = numbered_tail(stem);
+    let (head, room) = match max.checked_sub(ext.len() + tail.len()) {
+        Some(room) if room > 0 => (&stem[..stem.len() - tail.len()], room),
+        // Too long to keep what follows the words: cut what does not fit.
+        _ => return Some(cut(name, max).to_string()),
+    };
+    let hard = cut(head, room);
+    let mut short = hard;
+    let next = head[hard.len()..].chars().next();
+    if next.is_some_and(|c| !WORD_SEPARATORS.contains(&c)) {
+        if let Some(at) = hard.rfind(WORD_SEPARATORS) {
+            short = &hard[..at];
+        }
+    }
+    let mut short = short.trim_end_matches(WORD_SEPARATORS);
+    if short.is_empty() {
+        short = hard.trim_end_matches(WORD_SEPARATORS);
+    }
+    Some(format!("{}{}{}", short, tail, ext))
+}
+
+/// The longest start of `s` of at most `max` bytes that ends between
+/// characters.
+fn cut(s: &str, max: usize) -> &str {
+    let mut end = max.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Makes `name` legal on `fs`, replacing forbidden characters with
+/// `replacement` (which may be empty to drop them), and lists what changed.
+pub fn sanitize(name: &str, fs: TargetFs, replacement: &str) -> (String, Vec<SanitizeFix>) {
+    let mut fixes: Vec<SanitizeFix> = Vec::new();
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if !fs.forbids(c) {
+            out.push(c);
+            continue;
+        }
+        out.push_str(replacement);
+        let seen = fixes.iter_mut().find_map(|f| match f {
+            SanitizeFix::Replaced { character, count } if *character == c => Some(count),
+            _ => None,
+        });
+        match seen {
+            Some(count) => *count += 1,
+            None => fixes.push(SanitizeFix::Replaced {
+                character: c,
+                count: 1,
+            }),
+        }
+    }
+
+    if fs.windows_rules() {
+        let kept = out.trim_end_matches(['.', ' ']).len();
+        if kept < out.len() {
+            fixes.push(SanitizeFix::TrimmedTrailing {
+                removed: out.split_off(kept),
+            });
+        }
+        if let Some(fixed) = fix_device_name(&out, replacement) {
+            fixes.push(SanitizeFix::ReservedName {
+                name: std::mem::replace(&mut out, fixed),
+            });
+        }
+    }
+    (out, fixes)
+}
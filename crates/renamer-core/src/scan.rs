@@ -0,0 +1,642 @@
+//! Recursive directory listing for building rename batches.
+//!
+//! A [`Scanner`] walks the tree lazily and hands out entries a page at a time,
+//! so the UI can render the first files of a huge tree while the rest is still
+//! being read. A [`DropScanner`] does the same for files and folders dropped
+//! onto the window.
+//!
+//! Files and folders listed in a `.renamerignore` file, in gitignore syntax,
+//! are left out of the scan, as are those matching
+//! [`ScanOptions::ignore`]. An ignore file applies to the folder it is in and
+//! everything below it, and patterns in deeper files take precedence, so a
+//! `!pattern` there can bring back what a parent folder ignored.
+
+use std::collections::HashSet;
+use std::fs::{self, Metadata};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use serde::{Deserialize, Serialize};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::identity::{self, FileId};
+use crate::natural::{natural_cmp, natural_path_cmp};
+use crate::normalize::{self, Normalization};
+
+/// Name of the per-folder ignore files.
+pub const IGNORE_FILE: &str = ".renamerignore";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Folder levels to descend below the root; `0` lists only the root's own
+    /// files. Unlimited when unset.
+    pub max_depth: Option<usize>,
+    /// Case-insensitive globs matched against the file name and against the
+    /// `/`-separated path relative to the root. Patterns starting with `!` exclude
+    /// (and prune matching folders); if there are no plain patterns, every
+    /// file is included. E.g. `["*.jpg", "!*thumb*"]`.
+    pub patterns: Vec<String>,
+    pub include_hidden: bool,
+    /// Descend into linked folders and report linked files as what they
+    /// point to.
+    pub follow_symlinks: bool,
+    /// Also list folders, so they can be renamed. Patterns apply to them as
+    /// to files; a folder that is not listed is still searched.
+    pub include_dirs: bool,
+    /// Gitignore-style lines applied as if they were in a `.renamerignore`
+    /// file in the root, e.g. `["node_modules/", "target/", "*.tmp"]`.
+    pub ignore: Vec<String>,
+    /// Do not read `.renamerignore` files.
+    pub skip_ignore_files: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanEntry {
+    pub path: PathBuf,
+    pub name: String,
+    /// Folder levels below the root, `1` for entries directly in it.
+    pub depth: usize,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub is_symlink: bool,
+    /// What the link points to, as stored in the link.
+    pub link_target: Option<PathBuf>,
+    pub normalization: Option<Normalization>,
+    pub is_dir: bool,
+    /// Which file this is; entries with the same id are hard links to one
+    /// file. Not known for every filesystem.
+    #[serde(default)]
+    pub file_id: Option<FileId>,
+    /// How many names the file has, more than one if it has hard links;
+    /// `0` when not known.
+    #[serde(default)]
+    pub links: u64,
+}
+
+/// A folder or file that could not be read; scanning carries on without it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanError {
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanPage {
+    pub entries: Vec<ScanEntry>,
+    pub errors: Vec<ScanError>,
+    /// True once the whole tree has been listed, or the scan was cancelled.
+    pub done: bool,
+    /// The scan's [`CancelToken`] stopped it early; no more pages follow.
+    pub cancelled: bool,
+}
+
+/// Include and exclude globs compiled from [`ScanOptions::patterns`].
+pub(crate) struct Filter {
+    include: GlobSet,
+    has_includes: bool,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    pub(crate) fn new(patterns: &[String]) -> Result<Self> {
+        let mut include = GlobSetBuilder::new();
+        let mut exclude = GlobSetBuilder::new();
+        let mut has_includes = false;
+        for pattern in patterns {
+            let (builder, glob) = match pattern.strip_prefix('!') {
+                Some(rest) => (&mut exclude, rest),
+                None => {
+                    has_includes = true;
+                    (&mut include, pattern.as_str())
+                }
+            };
+            let glob = GlobBuilder::new(glob)
+                .case_insensitive(true)
+                .literal_separator(true)
+                .build()
+                .map_err(|source| Error::InvalidGlob {
+                    pattern: pattern.clone(),
+                    source,
+                })?;
+            builder.add(glob);
+        }
+        let build = |builder: GlobSetBuilder| {
+            builder.build().map_err(|source| Error::InvalidGlob {
+                pattern: patterns.join(", "),
+                source,
+            })
+        };
+        Ok(Filter {
+            include: build(include)?,
+            has_includes,
+            exclude: build(exclude)?,
+        })
+    }
+
+    fn matches(set: &GlobSet, name: &str, relative: &str) -> bool {
+        set.is_match(name) || set.is_match(relative)
+    }
+
+    pub(crate) fn excluded(&self, name: &str, relative: &str) -> bool {
+        Self::matches(&self.exclude, name, relative)
+    }
+
+    pub(crate) fn included(&self, name: &str, relative: &str) -> bool {
+        !self.has_includes || Self::matches(&self.include, name, relative)
+    }
+}
+
+/// The ignore patterns in effect where the walk is.
+pub(crate) struct Ignores {
+    /// [`ScanOptions::ignore`], rooted at the scan root.
+    base: Gitignore,
+    /// Ignore files of the folders the walk is in, with their depth,
+    /// innermost last.
+    stack: Vec<(usize, Gitignore)>,
+}
+
+impl Ignores {
+    pub(crate) fn new(root: &Path, lines: &[String]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(root);
+        for line in lines {
+            builder
+                .add_line(None, line)
+                .map_err(|e| Error::InvalidIgnorePattern {
+                    pattern: line.clone(),
+                    message: e.to_string(),
+                })?;
+        }
+        let base = builder.build().map_err(|e| Error::InvalidIgnorePattern {
+            pattern: lines.join(", "),
+            message: e.to_string(),
+        })?;
+        Ok(Ignores {
+            base,
+            stack: Vec::new(),
+        })
+    }
+
+    /// Reads the ignore file of `dir`, a folder `depth` levels below the
+    /// root, if it has one. Lines that cannot be used are reported in
+    /// `errors` and the rest of the file still applies.
+    fn enter(&mut self, dir: &Path, depth: usize, errors: &mut Vec<ScanError>) {
+        let file = dir.join(IGNORE_FILE);
+        if !file.is_file() {
+            return;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut report = |message: String| {
+            errors.push(ScanError {
+                path: Some(file.clone()),
+                message,
+            })
+        };
+        if let Some(e) = builder.add(&file) {
+            report(e.to_string());
+        }
+        match builder.build() {
+            Ok(ignore) => self.stack.push((depth, ignore)),
+            Err(e) => report(e.to_string()),
+        }
+    }
+
+    pub(crate) fn ignored(&mut self, path: &Path, depth: usize, is_dir: bool) -> bool {
+        // Ignore files of folders the walk has left no longer apply.
+        while self.stack.last().is_some_and(|&(d, _)| d >= depth) {
+            self.stack.pop();
+        }
+        let innermost_first = self.stack.iter().rev().map(|(_, i)| i);
+        for ignore in innermost_first.chain(iter::once(&self.base)) {
+            match ignore.matched(path, is_dir) {
+                Match::None => continue,
+                found => return found.is_ignore(),
+            }
+        }
+        false
+    }
+}
+
+#[cfg(windows)]
+fn hidden_attribute(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    entry
+        .metadata()
+        .is_ok_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(not(windows))]
+fn hidden_attribute(_entry: &DirEntry) -> bool {
+    false
+}
+
+fn is_hidden(entry: &DirEntry, name: &str) -> bool {
+    name.starts_with('.') || hidden_attribute(entry)
+}
+
+/// Lazily walks a directory tree. Create one with [`Scanner::new`] and call
+/// [`Scanner::next_page`] until it reports `done`.
+pub struct Scanner {
+    root: PathBuf,
+    walker: walkdir::IntoIter,
+    filter: Filter,
+    ignores: Ignores,
+    options: ScanOptions,
+    cancel: CancelToken,
+    done: bool,
+}
+
+impl Scanner {
+    /// Fails if a pattern is not a valid glob or ignore line; unreadable
+    /// folders and ignore files are reported in the pages instead.
+    pub fn new(root: impl Into<PathBuf>, options: ScanOptions) -> Result<Self> {
+        let root = root.into();
+        let filter = Filter::new(&options.patterns)?;
+        let ignores = Ignores::new(&fsutil::long(&root), &options.ignore)?;
+        let mut walk = WalkDir::new(fsutil::long(&root))
+            .follow_links(options.follow_symlinks)
+            .sort_by(|a, b| {
+                natural_cmp(
+                    &a.file_name().to_string_lossy(),
+                    &b.file_name().to_string_lossy(),
+                    true,
+                )
+            });
+        if let Some(depth) = options.max_depth {
+            walk = walk.max_depth(depth + 1);
+        }
+        Ok(Scanner {
+            root,
+            walker: walk.into_iter(),
+            filter,
+            ignores,
+            options,
+            cancel: CancelToken::new(),
+            done: false,
+        })
+    }
+
+    /// Stops the walk at the next entry once `cancel` is cancelled.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Returns up to `size` more entries.
+    pub fn next_page(&mut self, size: usize) -> ScanPage {
+        let mut page = ScanPage {
+            entries: Vec::new(),
+            errors: Vec::new(),
+            done: false,
+            cancelled: false,
+        };
+        while page.entries.len() < size.max(1) {
+            if self.cancel.is_cancelled() {
+                self.done = true;
+                page.cancelled = true;
+                break;
+            }
+            let Some(next) = self.walker.next() else {
+                self.done = true;
+                break;
+            };
+            let entry = match next {
+                Ok(entry) => entry,
+                Err(e) => {
+                    page.errors.push(ScanError {
+                        path: e.path().map(Path::to_path_buf),
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let is_dir = entry.file_type().is_dir();
+            let read_ignores = is_dir && !self.options.skip_ignore_files;
+            if entry.depth() == 0 {
+                if read_ignores {
+                    self.ignores.enter(entry.path(), 0, &mut page.errors);
+                }
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = entry
+                .path()
+                .strip_prefix(fsutil::long(&self.root).as_ref())
+                .unwrap_or(entry.path())
+                .to_path_buf();
+            // Globs always use `/`, whatever the platform separator.
+            let mut rel = relative.to_string_lossy().into_owned();
+            if cfg!(windows) {
+                rel = rel.replace('\\', "/");
+            }
+            let skip = (!self.options.include_hidden && is_hidden(&entry, &name))
+                || (!self.options.skip_ignore_files && name == IGNORE_FILE)
+                || self.filter.excluded(&name, &rel)
+                || self.ignores.ignored(entry.path(), entry.depth(), is_dir);
+            if is_dir && skip {
+                self.walker.skip_current_dir();
+            } else if read_ignores {
+                self.ignores
+                    .enter(entry.path(), entry.depth(), &mut page.errors);
+            }
+            if is_dir && !self.options.include_dirs {
+                continue;
+            }
+            if skip || !self.filter.included(&name, &rel) {
+                continue;
+            }
+            page.entries.push(self.to_entry(&entry, name, &relative));
+        }
+        page.done = self.done;
+        page
+    }
+
+    fn to_entry(&self, entry: &DirEntry, name: String, relative: &Path) -> ScanEntry {
+        // Report paths the way the caller spelled the root, without any
+        // extended-length prefix.
+        make_entry(
+            self.root.join(relative),
+            entry.path(),
+            name,
+            entry.depth(),
+            entry.metadata().ok(),
+            entry.path_is_symlink(),
+            entry.file_type().is_dir(),
+        )
+    }
+}
+
+fn make_entry(
+    path: PathBuf,
+    read_at: &Path,
+    name: String,
+    depth: usize,
+    metadata: Option<Metadata>,
+    is_symlink: bool,
+    is_dir: bool,
+) -> ScanEntry {
+    let identity = metadata
+        .as_ref()
+        .and_then(|m| identity::of_metadata(read_at, m));
+    ScanEntry {
+        path,
+        normalization: normalize::detect(&name),
+        name,
+        depth,
+        size: metadata
+            .as_ref()
+            .filter(|m| !m.is_dir())
+            .map_or(0, |m| m.len()),
+        modified: metadata
+            .and_then(|m| m.modified().ok())
+            .map(DateTime::<Utc>::from),
+        is_symlink,
+        link_target: is_symlink.then(|| fs::read_link(read_at).ok()).flatten(),
+        is_dir,
+        file_id: identity.map(|(id, _)| id),
+        links: identity.map_or(0, |(_, links)| links),
+    }
+}
+
+/// Turns paths dropped onto the window into entries: dropped files as they
+/// are, dropped folders listed the way a [`Scanner`] with the same options
+/// lists them. Paths already loaded, or reached twice within the drop, are
+/// left out, so dropping a folder and a file inside it lists the file once.
+pub struct DropScanner {
+    dropped: std::vec::IntoIter<PathBuf>,
+    folder: Option<Scanner>,
+    options: ScanOptions,
+    seen: HashSet<PathBuf>,
+    cancel: CancelToken,
+    done: bool,
+}
+
+impl DropScanner {
+    /// `loaded` are the paths already in the batch. Fails if a pattern is
+    /// not a valid glob or ignore line.
+    pub fn new(
+        dropped: Vec<PathBuf>,
+        options: ScanOptions,
+        loaded: impl IntoIterator<Item = PathBuf>,
+    ) -> Result<Self> {
+        Filter::new(&options.patterns)?;
+        Ignores::new(Path::new(""), &options.ignore)?;
+        Ok(DropScanner {
+            dropped: dropped.into_iter(),
+            folder: None,
+            options,
+            seen: loaded.into_iter().collect(),
+            cancel: CancelToken::new(),
+            done: false,
+        })
+    }
+
+    /// Stops at the next entry once `cancel` is cancelled.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Returns up to `size` more new entries.
+    pub fn next_page(&mut self, size: usize) -> ScanPage {
+        let size = size.max(1);
+        let mut page = ScanPage {
+            entries: Vec::new(),
+            errors: Vec::new(),
+            done: false,
+            cancelled: false,
+        };
+        while !self.done && page.entries.len() < size {
+            if self.cancel.is_cancelled() {
+                self.done = true;
+                page.cancelled = true;
+                break;
+            }
+            if let Some(folder) = &mut self.folder {
+                let listed = folder.next_page(size - page.entries.len());
+                page.errors.extend(listed.errors);
+                if listed.done {
+                    self.folder = None;
+                }
+                for entry in listed.entries {
+                    if self.seen.insert(entry.path.clone()) {
+                        page.entries.push(entry);
+                    }
+                }
+                continue;
+            }
+            let Some(path) = self.dropped.next() else {
+                self.done = true;
+                break;
+            };
+            match self.open(&path) {
+                Ok(Some(entry)) => {
+                    if self.seen.insert(entry.path.clone()) {
+                        page.entries.push(entry);
+                    }
+                }
+                Ok(None) => {}
+                Err(message) => page.errors.push(ScanError {
+                    path: Some(path),
+                    message,
+                }),
+            }
+        }
+        page.done = self.done;
+        page
+    }
+
+    /// The entry for a dropped file, or `None` after starting to list a
+    /// dropped folder.
+    fn open(&mut self, path: &Path) -> std::result::Result<Option<ScanEntry>, String> {
+        let link = fs::symlink_metadata(path).map_err(|e| e.to_string())?;
+        let is_symlink = link.file_type().is_symlink();
+        let metadata = if is_symlink && self.options.follow_symlinks {
+            fs::metadata(path).map_err(|e| e.to_string())?
+        } else {
+            link
+        };
+        if metadata.is_dir() {
+            let scanner = Scanner::new(path, self.options.clone()).map_err(|e| e.to_string())?;
+            self.folder = Some(scanner.with_cancel(self.cancel.clone()));
+            return Ok(None);
+        }
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Some(make_entry(
+            path.to_path_buf(),
+            path,
+            name,
+            1,
+            Some(metadata),
+            is_symlink,
+            false,
+        )))
+    }
+}
+
+/// Lists the whole tree in one go; convenient for small folders and tests.
+pub fn scan(root: impl Into<PathBuf>, options: ScanOptions) -> Result<ScanPage> {
+    Ok(Scanner::new(root, options)?.next_page(usize::MAX))
+}
+
+/// What [`ScanListing::query`] and [`crate::FileList::query`] order entries
+/// by; ties are broken by path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanSort {
+    /// Folder by folder, in natural order.
+    #[default]
+    Path,
+    /// File name, in natural order ignoring case.
+    Name,
+    Size,
+    Modified,
+}
+
+/// A window onto a [`ScanListing`], for virtual scrolling.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanQuery {
+    pub sort: ScanSort,
+    pub descending: bool,
+    /// Text the name must contain, ignoring case; empty matches everything.
+    pub filter: String,
+    /// Matching entries to skip.
+    pub offset: usize,
+    /// Most entries to return; all the rest when unset.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanWindow {
+    pub entries: Vec<ScanEntry>,
+    /// Entries matching the query's filter, so the scroll height is known.
+    pub matching: usize,
+    /// Entries listed so far.
+    pub total: usize,
+}
+
+/// Entries of a scan kept on the Rust side, so the webview only holds the
+/// rows on screen. Sorting an unchanged listing again is answered from the
+/// last order computed.
+#[derive(Debug, Default)]
+pub struct ScanListing {
+    entries: Vec<ScanEntry>,
+    /// The sort, direction and filter of the last query, and its order.
+    order: Option<(ScanSort, bool, String, Vec<usize>)>,
+}
+
+impl ScanListing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = ScanEntry>) {
+        self.entries.extend(entries);
+        self.order = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn query(&mut self, query: &ScanQuery) -> ScanWindow {
+        let filter = query.filter.to_lowercase();
+        let cached = matches!(&self.order,
+            Some((sort, descending, f, _))
+                if *sort == query.sort && *descending == query.descending && *f == filter);
+        if !cached {
+            let order = self.sorted(query.sort, query.descending, &filter);
+            self.order = Some((query.sort, query.descending, filter, order));
+        }
+        let order = &self.order.as_ref().expect("order was just computed").3;
+        let limit = query.limit.unwrap_or(usize::MAX);
+        ScanWindow {
+            entries: order
+                .iter()
+                .skip(query.offset)
+                .take(limit)
+                .map(|&i| self.entries[i].clone())
+                .collect(),
+            matching: order.len(),
+            total: self.entries.len(),
+        }
+    }
+
+    fn sorted(&self, sort: ScanSort, descending: bool, filter: &str) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| filter.is_empty() || self.entries[i].name.to_lowercase().contains(filter))
+            .collect();
+        match sort {
+            ScanSort::Path => order
+                .sort_by(|&a, &b| natural_path_cmp(&self.entries[a].path, &self.entries[b].path)),
+            ScanSort::Name => order.sort_by(|&a, &b| {
+                let (a, b) = (&self.entries[a], &self.entries[b]);
+                natural_cmp(&a.name, &b.name, true).then_with(|| natural_path_cmp(&a.path, &b.path))
+            }),
+            ScanSort::Size => order.sort_by_key(|&i| (self.entries[i].size, &self.entries[i].path)),
+            ScanSort::Modified => {
+                order.sort_by_key(|&i| (self.entries[i].modified, &self.entries[i].path))
+            }
+        }
+        if descending {
+            order.reverse();
+        }
+        order
+    }
+}
@@ -0,0 +1,91 @@
+//! The folders a session may touch, so paths handed in from outside (the
+//! webview, a sidecar reply) cannot reach files the user never opened.
+//!
+//! Paths are compared after resolving links and `..` against what is on
+//! disk: a link inside an allowed folder that points out of it leads out of
+//! scope. Parts of a path that do not exist yet, such as a rename target in
+//! a new subfolder, may not contain `..`, since where it leads cannot be
+//! known until the folders are made.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// Allowed folders and files; everything under an allowed folder is
+/// allowed too.
+#[derive(Debug, Clone, Default)]
+pub struct PathScope {
+    roots: Vec<PathBuf>,
+}
+
+/// `path` with links and `..` resolved as far as it exists, and the rest
+/// appended. `None` for relative paths and for `..` in the part that does
+/// not exist.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+    let mut existing = path;
+    let mut rest = Vec::new();
+    let mut resolved = loop {
+        match fs::canonicalize(existing) {
+            Ok(resolved) => break resolved,
+            Err(_) => {
+                match existing.components().next_back()? {
+                    Component::Normal(name) => rest.push(name),
+                    Component::CurDir => {}
+                    _ => return None,
+                }
+                existing = existing.parent()?;
+            }
+        }
+    };
+    for name in rest.into_iter().rev() {
+        resolved.push(name);
+    }
+    Some(resolved)
+}
+
+impl PathScope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The folders and files allowed, resolved.
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Allows `path` and, if it is a folder, everything under it. Paths that
+    /// cannot be resolved are ignored.
+    pub fn allow(&mut self, path: &Path) {
+        let Some(root) = resolve(path) else {
+            return;
+        };
+        if self.roots.iter().any(|r| root.starts_with(r)) {
+            return;
+        }
+        self.roots.retain(|r| !r.starts_with(&root));
+        self.roots.push(root);
+    }
+
+    pub fn clear(&mut self) {
+        self.roots.clear();
+    }
+
+    /// Whether `path` is an allowed file or folder or lies under one.
+    pub fn allows(&self, path: &Path) -> bool {
+        resolve(path).is_some_and(|p| self.roots.iter().any(|r| p.starts_with(r)))
+    }
+
+    /// Fails on the first of `paths` that is out of scope.
+    pub fn check<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> Result<()> {
+        match paths.into_iter().find(|p| !self.allows(p)) {
+            Some(path) => Err(Error::OutOfScope {
+                path: path.to_path_buf(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
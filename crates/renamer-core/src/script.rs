@@ -0,0 +1,138 @@
+//! Running [`Rule::Script`] steps in a sandboxed Rhai engine.
+//!
+//! Scripts get no module resolver, so they cannot load files, and `print`
+//! and `debug` go nowhere. Strings, arrays, maps and recursion are capped, and
+//! a deadline checked every [`CHECK_EVERY`] operations stops scripts that run
+//! too long.
+//!
+//! [`Rule::Script`]: crate::rules::Rule::Script
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::error::{Error, Result};
+use crate::metadata::MetaValue;
+use crate::rules::split_name;
+use crate::template::FileContext;
+
+/// How often the time limit is checked, in Rhai operations.
+const CHECK_EVERY: u64 = 1024;
+
+thread_local! {
+    /// When the script running on this thread has to stop.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// A compiled script and the engine that runs it.
+#[derive(Clone)]
+pub(crate) struct Script {
+    engine: Arc<Engine>,
+    ast: AST,
+    timeout: Duration,
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Script")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(4096)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000)
+        .on_print(|_| {})
+        .on_debug(|_, _, _| {})
+        .on_progress(|ops| {
+            if ops % CHECK_EVERY != 0 {
+                return None;
+            }
+            DEADLINE.with(|deadline| match deadline.get() {
+                Some(at) if Instant::now() >= at => Some(Dynamic::UNIT),
+                _ => None,
+            })
+        });
+    engine
+}
+
+fn meta_value(value: &MetaValue) -> Dynamic {
+    match value {
+        MetaValue::Integer(n) => Dynamic::from(*n),
+        MetaValue::Date(date) => Dynamic::from(date.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        MetaValue::Text(text) => Dynamic::from(text.clone()),
+    }
+}
+
+impl Script {
+    pub(crate) fn compile(source: &str, timeout: Duration) -> Result<Self> {
+        let engine = engine();
+        let ast = engine.compile(source).map_err(|e| Error::InvalidScript {
+            message: e.to_string(),
+        })?;
+        Ok(Script {
+            engine: Arc::new(engine),
+            ast,
+            timeout,
+        })
+    }
+
+    /// The new name for a file now called `name`, or why there is none.
+    pub(crate) fn run(&self, name: &str, ctx: &FileContext) -> std::result::Result<String, String> {
+        let (stem, ext) = split_name(name);
+        let mut meta = Map::new();
+        if let Some(size) = ctx.size {
+            meta.insert("size".into(), Dynamic::from(size as i64));
+        }
+        if let Some(modified) = ctx.modified {
+            meta.insert(
+                "modified".into(),
+                Dynamic::from(modified.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            );
+        }
+        if let Some(embedded) = &ctx.embedded {
+            for (key, value) in &embedded.values {
+                meta.insert(key.as_str().into(), meta_value(value));
+            }
+        }
+        let mut scope = Scope::new();
+        scope
+            .push("name", name.to_string())
+            .push("stem", stem.to_string())
+            .push("ext", ext.to_string())
+            .push("meta", meta);
+
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + self.timeout)));
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        DEADLINE.with(|deadline| deadline.set(None));
+
+        match result {
+            Ok(value) if value.is_unit() => Ok(name.to_string()),
+            Ok(value) if value.is_string() => Ok(value.into_string().unwrap_or_default()),
+            Ok(value) => Err(format!(
+                "the script returned {} instead of a name",
+                value.type_name()
+            )),
+            Err(e) => match *e {
+                EvalAltResult::ErrorTerminated(..) => Err(format!(
+                    "the script ran longer than {} ms",
+                    self.timeout.as_millis()
+                )),
+                e => Err(e.to_string()),
+            },
+        }
+    }
+}
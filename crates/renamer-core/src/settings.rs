@@ -0,0 +1,180 @@
+//! Settings shared by the app, `renamer-cli` and folder watchers, kept in
+//! `settings.json` in the app's data folder.
+//!
+//! The file carries a format version. Older files are migrated one version
+//! at a time when read and are written back in the current format on the
+//! next save; files written by a newer version are refused, as for presets.
+//! What only the app reads is kept under `app`, typed by the app and carried
+//! along as plain JSON by readers that do not know it, such as the CLI.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::error::{Error, Result};
+use crate::executor::ApplyOptions;
+use crate::fsutil::hidden_sibling;
+use crate::maintenance::Retention;
+use crate::ocr::OcrCommand;
+use crate::throttle::IoThrottle;
+use crate::webhook::Webhook;
+
+/// Format of the settings file this version reads and writes.
+pub const SETTINGS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, bound(deserialize = "A: Deserialize<'de> + Default"))]
+pub struct Settings<A = Value> {
+    /// How batches are applied where nobody was asked, e.g. by a folder
+    /// watcher, and what the CLI's flags add to.
+    pub apply: ApplyOptions,
+    /// Whether every change made to a file is also written to the audit
+    /// log, `audit.jsonl` next to the settings; see `renamer_core::audit`.
+    pub audit_log: bool,
+    /// Told about every batch that finishes; see `renamer_core::webhook`.
+    pub webhooks: Vec<Webhook>,
+    /// The program `{ocr.*}` tokens read scanned documents with; see
+    /// `renamer_core::ocr`.
+    pub ocr: OcrCommand,
+    /// How much of the disks background jobs and folder watchers may use;
+    /// see `renamer_core::throttle`.
+    pub io_throttle: IoThrottle,
+    /// How much history, and how many journals and cached files, are kept;
+    /// see `renamer_core::maintenance`.
+    pub retention: Retention,
+    /// The language tag template tokens write month names and numbers in;
+    /// the system's when not set. See `renamer_core::locale`.
+    pub locale: Option<String>,
+    /// A naming policy file every new name must follow, such as one an IT
+    /// department hands out; see `renamer_core::policy`.
+    pub naming_policy: Option<PathBuf>,
+    /// The app's own settings.
+    pub app: A,
+}
+
+impl<A: Serialize + DeserializeOwned + Default> Settings<A> {
+    /// These settings changed by the JSON merge patch `patch`, e.g.
+    /// `{"app": {"sidebar_open": true}}`, in which `null` puts a setting
+    /// back to its default. What `patch` has at the JSON pointers `kept`,
+    /// e.g. `/app/recent`, is left out: settings only other commands may
+    /// change, which check what goes in them.
+    pub fn patched(&self, mut patch: Value, kept: &[&str]) -> Result<Self> {
+        for pointer in kept {
+            if let Some((parent, key)) = pointer.rsplit_once('/') {
+                if let Some(Value::Object(map)) = patch.pointer_mut(parent) {
+                    map.remove(key);
+                }
+            }
+        }
+        let mut value = serde_json::to_value(self)?;
+        merge(&mut value, patch);
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// Applies `patch` to `target` as a JSON merge patch: objects are merged
+/// key by key, `null` removes a key and anything else replaces it.
+fn merge(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(&key);
+                } else {
+                    merge(target.entry(key).or_insert(Value::Null), value);
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+#[derive(Serialize)]
+struct SettingsFile<'a, A> {
+    version: u32,
+    #[serde(flatten)]
+    settings: &'a Settings<A>,
+}
+
+/// Turns settings of format `version` into the next format.
+fn migrate(version: u32, settings: Value) -> Value {
+    match version {
+        // Before it had a version, the file held the app's settings alone.
+        0 => json!({ "app": settings }),
+        _ => settings,
+    }
+}
+
+/// Where the settings live.
+#[derive(Debug, Clone)]
+pub struct SettingsStore {
+    path: PathBuf,
+}
+
+impl SettingsStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SettingsStore { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the settings, migrated to the current format; a missing file
+    /// gives the defaults.
+    pub fn load<A: DeserializeOwned + Default>(&self) -> Result<Settings<A>> {
+        let mut value: Value = match fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Settings::default()),
+            Err(e) => return Err(Error::io(&self.path, e)),
+        };
+        let mut version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .map_or(0, |v| v as u32);
+        if version > SETTINGS_VERSION {
+            return Err(Error::UnsupportedVersion {
+                path: self.path.clone(),
+                version,
+            });
+        }
+        while version < SETTINGS_VERSION {
+            value = migrate(version, value);
+            version += 1;
+        }
+        if let Value::Object(map) = &mut value {
+            map.remove("version");
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Writes the settings in the current format. The file is replaced in
+    /// one step, so another reader never sees half of it.
+    pub fn save<A: Serialize>(&self, settings: &Settings<A>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let json = serde_json::to_vec_pretty(&SettingsFile {
+            version: SETTINGS_VERSION,
+            settings,
+        })?;
+        let temp = hidden_sibling(&self.path, "tmp");
+        fs::write(&temp, json).map_err(|e| Error::io(&temp, e))?;
+        fs::rename(&temp, &self.path).map_err(|e| Error::io(&self.path, e))
+    }
+
+    /// Reads the settings, changes them and writes them back, so a change
+    /// made meanwhile by another program is kept. Returns them as written.
+    pub fn update<A: Serialize + DeserializeOwned + Default>(
+        &self,
+        change: impl FnOnce(&mut Settings<A>),
+    ) -> Result<Settings<A>> {
+        let mut settings = self.load()?;
+        change(&mut settings);
+        self.save(&settings)?;
+        Ok(settings)
+    }
+}
@@ -0,0 +1,90 @@
+//! Moves on network shares, which can stall for minutes when the server or
+//! the connection goes away, or fail because the connection dropped and
+//! work again a moment later.
+//!
+//! A stalled call cannot be interrupted: the thread making it is stuck until
+//! the OS gives up. So a move on a share is made on a thread of its own while
+//! the batch waits for it, and stops waiting once it has gone too long
+//! without progress; see [`crate::ApplyOptions::network_timeout_secs`].
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::transfer::CopyProgress;
+
+/// How often a move being waited for is looked in on.
+const POLL: Duration = Duration::from_millis(100);
+
+/// True if `error` means the connection to the share dropped or timed out,
+/// so trying again once it is back may work.
+pub(crate) fn is_dropped(error: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    if matches!(
+        error.kind(),
+        TimedOut
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | HostUnreachable
+            | NetworkUnreachable
+            | NetworkDown
+            | StaleNetworkFileHandle
+    ) {
+        return true;
+    }
+    // ERROR_BAD_NETPATH, ERROR_UNEXP_NET_ERR, ERROR_NETNAME_DELETED,
+    // ERROR_BAD_NET_NAME, ERROR_SEM_TIMEOUT, ERROR_NO_NETWORK,
+    // ERROR_NETWORK_UNREACHABLE, ERROR_HOST_UNREACHABLE,
+    // ERROR_CONNECTION_ABORTED and ERROR_NOT_CONNECTED.
+    #[cfg(windows)]
+    const DROPPED: &[i32] = &[53, 59, 64, 67, 121, 1222, 1231, 1232, 1236, 2250];
+    // EIO, which SMB clients give while they reconnect.
+    #[cfg(not(windows))]
+    const DROPPED: &[i32] = &[5];
+    error
+        .raw_os_error()
+        .is_some_and(|code| DROPPED.contains(&code))
+}
+
+/// Runs `op` on a thread of its own and returns what it returns, unless it
+/// goes `timeout` without reporting progress, counting from the start; then
+/// `None`, and `op` is left to finish or fail on its own. Progress `op`
+/// reports is passed on to `progress`.
+pub(crate) fn watched<T: Send + 'static>(
+    timeout: Duration,
+    progress: CopyProgress,
+    op: impl FnOnce(CopyProgress) -> T + Send + 'static,
+) -> Option<T> {
+    let copied = Arc::new((AtomicU64::new(0), AtomicU64::new(0)));
+    let (done, result) = mpsc::channel();
+    let reported = copied.clone();
+    thread::spawn(move || {
+        let report = move |bytes: u64, total: u64| {
+            reported.0.store(bytes, Ordering::Relaxed);
+            reported.1.store(total, Ordering::Relaxed);
+        };
+        let _ = done.send(op(&report));
+    });
+    let mut last = (0, Instant::now());
+    loop {
+        let waited = last.1.elapsed();
+        if waited >= timeout {
+            return None;
+        }
+        match result.recv_timeout(POLL.min(timeout - waited)) {
+            Ok(value) => return Some(value),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // `op` panicked.
+            Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+        }
+        let bytes = copied.0.load(Ordering::Relaxed);
+        if bytes != last.0 {
+            last = (bytes, Instant::now());
+            progress(bytes, copied.1.load(Ordering::Relaxed));
+        }
+    }
+}
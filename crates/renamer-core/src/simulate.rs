@@ -0,0 +1,239 @@
+//! What-if runs of a preset: the batch is applied to a stand-in for the
+//! scanned tree instead of the tree, so a complicated preset can be checked
+//! for collisions, failures and rollbacks before it touches an archive.
+//!
+//! A [`Snapshot`] is the scan, held in memory. [`simulate`] works out the
+//! new names from the real files, which are only read, then lays the
+//! snapshot out as empty files with the same names and times in a scratch
+//! folder, plans and applies the batch there with the preset's options, and
+//! removes the folder again. Planning and applying so run exactly as they
+//! would for real; the report's paths are given back as the real ones.
+//!
+//! What the stand-in cannot show: files that were not scanned (only those
+//! already at a target are laid out too), links, which are laid out as
+//! plain files, and anything that depends on the files' contents after the
+//! rename, so titles are not written and manifests and git are left out.
+
+use std::collections::HashSet;
+use std::fs::{self, File, FileTimes};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::executor::{apply, ApplyOptions, ApplyReport};
+use crate::plan::{plan, RenameOp, RenamePlan};
+use crate::presets::Preset;
+use crate::preview::{preview_bursts, PreviewRow};
+use crate::scan::{scan, ScanEntry, ScanOptions};
+
+/// The scanned tree a simulation runs against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub root: PathBuf,
+    /// The files, and folders if the scan listed them, that the preset is
+    /// run on; all of them are laid out.
+    pub entries: Vec<ScanEntry>,
+}
+
+impl Snapshot {
+    /// Scans `root` to its end.
+    pub fn scan(root: impl Into<PathBuf>, options: ScanOptions) -> Result<Self> {
+        let root = root.into();
+        let page = scan(&root, options)?;
+        Ok(Snapshot {
+            root,
+            entries: page.entries,
+        })
+    }
+}
+
+/// How the preset's batch would go.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Simulation {
+    pub preview: Vec<PreviewRow>,
+    pub plan: RenamePlan,
+    pub report: ApplyReport,
+}
+
+/// The scratch folder, removed when dropped. The snapshot's root is laid
+/// out as `root`, and targets outside it under `outside`, by their path.
+struct Sandbox {
+    dir: PathBuf,
+    root: PathBuf,
+}
+
+impl Sandbox {
+    fn new(root: &Path) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("sortify-simulation-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+        Ok(Sandbox {
+            dir,
+            root: root.to_path_buf(),
+        })
+    }
+
+    fn root(&self) -> PathBuf {
+        self.dir.join("root")
+    }
+
+    fn outside(&self) -> PathBuf {
+        self.dir.join("outside")
+    }
+
+    /// Where `path` is in the sandbox.
+    fn inside(&self, path: &Path) -> PathBuf {
+        if let Ok(rel) = path.strip_prefix(&self.root) {
+            return self.root().join(rel);
+        }
+        let mut inside = self.outside();
+        for component in path.components() {
+            match component {
+                Component::Prefix(prefix) => {
+                    let drive = prefix.as_os_str().to_string_lossy();
+                    inside.push(drive.replace([':', '\\', '?', '.'], "_"));
+                }
+                Component::RootDir | Component::CurDir => {}
+                Component::ParentDir => {
+                    inside.pop();
+                }
+                Component::Normal(name) => inside.push(name),
+            }
+        }
+        inside
+    }
+
+    /// The real path of `path` in the sandbox, given the real paths of
+    /// the folders laid out outside the root.
+    fn real(&self, path: &Path, outside: &[(PathBuf, PathBuf)]) -> PathBuf {
+        if let Ok(rel) = path.strip_prefix(self.root()) {
+            return self.root.join(rel);
+        }
+        outside
+            .iter()
+            .filter_map(|(real, inside)| Some(real.join(path.strip_prefix(inside).ok()?)))
+            .next()
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// An empty file at `path`'s place, with its modification time.
+    fn lay_out(&self, path: &Path, is_dir: bool, modified: Option<SystemTime>) -> Result<()> {
+        let inside = self.inside(path);
+        if is_dir {
+            return fs::create_dir_all(&inside).map_err(|e| Error::io(&inside, e));
+        }
+        if let Some(dir) = inside.parent() {
+            fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        }
+        let file = File::create(&inside).map_err(|e| Error::io(&inside, e))?;
+        if let Some(modified) = modified {
+            let _ = file.set_times(FileTimes::new().set_modified(modified));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Runs `preset` on the snapshot's entries as [`Preset::run`] would, against
+/// a stand-in for the tree; see the module docs. `None` if the rules change
+/// nothing. Nothing in the tree is changed, safe mode or not.
+pub fn simulate(
+    snapshot: &Snapshot,
+    preset: &Preset,
+    apply_options: &ApplyOptions,
+) -> Result<Option<Simulation>> {
+    let paths: Vec<PathBuf> = snapshot.entries.iter().map(|e| e.path.clone()).collect();
+    let rows = preview_bursts(&paths, &preset.rules, &preset.plan_options.bursts)?;
+    let ops: Vec<RenameOp> = rows
+        .iter()
+        .filter(|row| row.changed)
+        .map(PreviewRow::to_op)
+        .collect();
+    if ops.is_empty() {
+        return Ok(None);
+    }
+
+    let sandbox = Sandbox::new(&snapshot.root)?;
+    fs::create_dir_all(sandbox.root()).map_err(|e| Error::io(sandbox.root(), e))?;
+    let mut laid_out = HashSet::new();
+    for entry in &snapshot.entries {
+        let modified = entry.modified.map(SystemTime::from);
+        sandbox.lay_out(&entry.path, entry.is_dir, modified)?;
+        laid_out.insert(entry.path.clone());
+    }
+    let mut outside = Vec::new();
+    for op in &ops {
+        // What already lives at a target or in the way of its folder
+        // counts, scanned or not, up to the first folder that exists.
+        for path in op.target.ancestors() {
+            if laid_out.contains(path) {
+                break;
+            }
+            let Ok(meta) = fs::symlink_metadata(path) else {
+                continue;
+            };
+            sandbox.lay_out(path, meta.is_dir(), meta.modified().ok())?;
+            laid_out.insert(path.to_path_buf());
+            if meta.is_dir() {
+                break;
+            }
+        }
+        if let Some(dir) = op
+            .target
+            .parent()
+            .filter(|d| !d.starts_with(&snapshot.root))
+        {
+            outside.push((dir.to_path_buf(), sandbox.inside(dir)));
+        }
+    }
+
+    let inside_ops = ops
+        .iter()
+        .map(|op| RenameOp {
+            source: sandbox.inside(&op.source),
+            target: sandbox.inside(&op.target),
+            modified: op.modified,
+            title: None,
+            shortened: op.shortened,
+        })
+        .collect();
+    let mut planned = plan(inside_ops, &preset.plan_options);
+    let options = ApplyOptions {
+        safe_mode: false,
+        manifest: None,
+        git: false,
+        ..apply_options.clone()
+    };
+    let mut report = apply(&planned, &options, &sandbox.dir.join("journal"))?;
+
+    let real = |path: &mut PathBuf| *path = sandbox.real(path, &outside);
+    for entry in &mut planned.entries {
+        real(&mut entry.source);
+        real(&mut entry.target);
+        if let Some(conflict) = &mut entry.conflict {
+            real(&mut conflict.requested_target);
+        }
+        if let Some(link) = &mut entry.link {
+            real(link);
+        }
+    }
+    for outcome in &mut report.outcomes {
+        real(&mut outcome.source);
+        real(&mut outcome.target);
+        if let Some(link) = &mut outcome.link {
+            real(link);
+        }
+    }
+    Ok(Some(Simulation {
+        preview: rows,
+        plan: planned,
+        report,
+    }))
+}
@@ -0,0 +1,111 @@
+//! Telling a file's format from its first bytes, to fix extensions that are
+//! wrong or missing.
+//!
+//! Many formats share a signature with more specific ones: Office documents
+//! and Java archives are zip files, camera RAW files are TIFFs. Those
+//! formats are only reported, never used to change a name, and each format
+//! accepts the usual spellings of its extension (`.jpeg` for a JPEG).
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fsutil;
+use crate::rules::split_name;
+
+/// Formats other formats are built on, or that are only a guess, so their
+/// extension could be more specific than anything sniffing can tell.
+const CONTAINERS: &[&str] = &[
+    "zip", "tar", "gz", "tif", "xml", "html", "sh", "exe", "dll", "elf", "mach", "ar", "der",
+    "pem", "obj", "bc",
+];
+
+/// Other extensions files of a format commonly have.
+const ALIASES: &[(&str, &[&str])] = &[
+    ("jpg", &["jpeg", "jpe", "jfif", "jif", "mpo"]),
+    ("heif", &["heic", "heics", "hif"]),
+    ("mp4", &["m4v", "m4a", "m4b", "f4v", "3gp", "3g2"]),
+    ("m4a", &["m4b", "m4p", "mp4"]),
+    ("mov", &["qt"]),
+    ("mkv", &["mka", "mks", "mk3d"]),
+    ("mpg", &["mpeg", "m2v", "vob"]),
+    ("ogg", &["oga", "ogv", "ogx", "spx"]),
+    ("midi", &["mid"]),
+    ("aiff", &["aif", "aifc"]),
+    ("wav", &["bwf"]),
+    ("pdf", &["ai"]),
+    ("ps", &["eps"]),
+    ("docx", &["docm", "dotx", "dotm"]),
+    ("xlsx", &["xlsm", "xltx", "xltm"]),
+    ("pptx", &["pptm", "potx", "ppsx"]),
+    ("doc", &["dot"]),
+    ("xls", &["xlt", "xla"]),
+    ("ppt", &["pps", "pot"]),
+];
+
+/// A file format recognised from contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentType {
+    pub mime: String,
+    /// The usual extension, without the dot.
+    pub extension: String,
+}
+
+impl ContentType {
+    /// True if a file with this content may have extension `ext` (without
+    /// the dot, any case).
+    pub fn matches(&self, ext: &str) -> bool {
+        let ext = ext.to_ascii_lowercase();
+        ext == self.extension
+            || ALIASES
+                .iter()
+                .any(|(format, aliases)| *format == self.extension && aliases.contains(&&*ext))
+    }
+
+    fn is_container(&self) -> bool {
+        CONTAINERS.contains(&self.extension.as_str())
+    }
+}
+
+/// An extension [`fix_extension`] replaced or added.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtensionFix {
+    /// The extension the name had, without the dot; empty if it had none.
+    pub from: String,
+    pub to: String,
+    /// What the contents turned out to be.
+    pub mime: String,
+}
+
+/// The format of the file at `path`, if its first bytes give it away.
+pub fn sniff(path: &Path) -> Option<ContentType> {
+    let found = infer::get_from_path(fsutil::long(path)).ok()??;
+    Some(ContentType {
+        mime: found.mime_type().to_string(),
+        extension: found.extension().to_string(),
+    })
+}
+
+/// Gives `name` the extension of `content` unless it already has a fitting
+/// one. Upper case extensions stay upper case. `None` if nothing changes.
+pub fn fix_extension(name: &str, content: &ContentType) -> Option<(String, ExtensionFix)> {
+    let (stem, ext) = split_name(name);
+    let ext = ext.trim_start_matches('.');
+    if content.is_container() || content.matches(ext) {
+        return None;
+    }
+    let upper = !ext.is_empty() && !ext.chars().any(|c| c.is_lowercase());
+    let to = if upper {
+        content.extension.to_ascii_uppercase()
+    } else {
+        content.extension.clone()
+    };
+    Some((
+        format!("{}.{}", stem, to),
+        ExtensionFix {
+            from: ext.to_string(),
+            to,
+            mime: content.mime.clone(),
+        },
+    ))
+}
@@ -0,0 +1,124 @@
+//! File attributes for many paths at once, so the UI can show and filter on
+//! them without asking for each file separately.
+
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::fsutil;
+
+/// Attributes of one file. Links are described themselves, not what they
+/// point to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    pub path: PathBuf,
+    /// Why the file could not be read; every other field is then empty.
+    pub error: Option<String>,
+    pub size: u64,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    /// Not available on every filesystem.
+    pub created: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+    pub accessed: Option<DateTime<Utc>>,
+    pub readonly: bool,
+    /// Unix permission bits, e.g. `0o644`.
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// The owning user's name, when the system knows it.
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+fn time(at: std::io::Result<SystemTime>) -> Option<DateTime<Utc>> {
+    at.ok().map(DateTime::<Utc>::from)
+}
+
+#[cfg(unix)]
+fn owner_ids(metadata: &Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        Some(metadata.mode() & 0o7777),
+        Some(metadata.uid()),
+        Some(metadata.gid()),
+    )
+}
+
+#[cfg(not(unix))]
+fn owner_ids(_metadata: &Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    (None, None, None)
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    uzers::get_user_by_uid(uid).map(|u| u.name().to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    uzers::get_group_by_gid(gid).map(|g| g.name().to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+fn group_name(_gid: u32) -> Option<String> {
+    None
+}
+
+fn stat(path: &Path) -> FileStat {
+    let metadata = match fs::symlink_metadata(fsutil::long(path)) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            return FileStat {
+                path: path.to_path_buf(),
+                error: Some(e.to_string()),
+                ..FileStat::default()
+            }
+        }
+    };
+    let (mode, uid, gid) = owner_ids(&metadata);
+    FileStat {
+        path: path.to_path_buf(),
+        error: None,
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        is_dir: metadata.is_dir(),
+        is_symlink: metadata.file_type().is_symlink(),
+        created: time(metadata.created()),
+        modified: time(metadata.modified()),
+        accessed: time(metadata.accessed()),
+        readonly: metadata.permissions().readonly(),
+        mode,
+        uid,
+        gid,
+        owner: None,
+        group: None,
+    }
+}
+
+/// Reads the attributes of `paths` in parallel, in the order given. Files
+/// that cannot be read get an entry with `error` set.
+pub fn stat_files(paths: &[PathBuf]) -> Vec<FileStat> {
+    let mut stats: Vec<FileStat> = paths.par_iter().map(|path| stat(path)).collect();
+    // A batch usually has a handful of owners; look each up once.
+    let mut users = HashMap::new();
+    let mut groups = HashMap::new();
+    for stat in &mut stats {
+        if let Some(uid) = stat.uid {
+            stat.owner = users.entry(uid).or_insert_with(|| user_name(uid)).clone();
+        }
+        if let Some(gid) = stat.gid {
+            stat.group = groups.entry(gid).or_insert_with(|| group_name(gid)).clone();
+        }
+    }
+    stats
+}
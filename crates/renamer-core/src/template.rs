@@ -0,0 +1,1213 @@
+//! `{token}` templates for building new file names.
+//!
+//! Supported tokens:
+//!
+//! | token            | value                                             |
+//! |------------------|---------------------------------------------------|
+//! | `{name}`         | original stem (file name without extension)       |
+//! | `{ext}`          | original extension, without the dot               |
+//! | `{counter}`      | sequence number, see below                        |
+//! | `{date:FORMAT}`  | modification date, `FORMAT` defaults to `yyyy-MM-dd` |
+//! | `{parsed_date:FORMAT}` | date written in the name, see [`crate::name_date`] |
+//! | `{show}`, `{year}` | show or movie title and year written in the name, see [`crate::episode`] |
+//! | `{season:PAD}`, `{episode:PAD}` | season and episode number written in the name |
+//! | `{episode_title}` | episode title written after the episode number   |
+//! | `{parent}`       | name of the containing folder                     |
+//! | `{size}`         | size in bytes; `{size:human}` gives e.g. `1.5 MB` |
+//! | `{exif.date_taken:FORMAT}` | when the photo was taken, `{exif.date}` for short |
+//! | `{xmp.date_taken:FORMAT}` | the same as XMP has it, from a sidecar or the file |
+//! | `{best_date:FORMAT}` | the first date a file has, see below          |
+//! | `{exif.camera}`, `{exif.lens}` | camera and lens model               |
+//! | `{exif.iso:PAD}` | ISO speed                                         |
+//! | `{exif.gps.city}`, `{exif.gps.country}` | nearest town to where it was taken |
+//! | `{tag.artist}`, `{tag.album}`, `{tag.title}`, `{tag.genre}` | song tags |
+//! | `{tag.track:PAD}`, `{tag.disc:PAD}`, `{tag.year}` | track and disc number, release year |
+//! | `{video.width}`, `{video.height}` | video resolution, as displayed      |
+//! | `{video.duration:PAD}` | video length in whole seconds               |
+//! | `{video.codec}`  | e.g. `h264`, `h265`, `vp9`                        |
+//! | `{video.created:FORMAT}` | when the video was recorded               |
+//! | `{doc.title}`, `{doc.author}` | PDF and Office document properties   |
+//! | `{doc.date:FORMAT}` | when the document was created                  |
+//! | `{mail.from}`, `{mail.from_address}` | sender of an `.eml` or `.msg`, by name and address |
+//! | `{mail.subject}` | subject of the email                              |
+//! | `{mail.date:FORMAT}` | when the email was sent                       |
+//! | `{hash:ALGO:LEN}` | content hash, see below                          |
+//! | `{ocr.first_line}` | first line of text in a scanned document, see [`crate::ocr`] |
+//! | `{ocr.match(REGEX)}` | the first match in that text, or its first group |
+//! | `{plugin:NAME.TOKEN}` | a token of an enabled [`crate::plugins`] plugin |
+//!
+//! `{counter}` takes comma-separated options, e.g.
+//! `{counter:start=1,step=1,pad=4,reset=folder}`: `start` (default 1), `step`
+//! (default 1), `pad` zero-pads to a width, and `reset` restarts numbering for
+//! each `folder` or `ext`ension. A bare number is shorthand for `pad`, so
+//! `IMG_{counter:4}` gives `IMG_0001`, `IMG_0002`, ...
+//!
+//! `style` counts in other ways: `roman` (`i`, `ii`, `iii`, ...), `ROMAN`
+//! (`I`, `II`, ...), `alpha` (`a`, `b`, ..., `z`, `aa`, ...) and `ALPHA`, so
+//! `Part {counter:style=ROMAN}` gives `Part I`, `Part II`. `words=` takes its
+//! own sequence, separated by `;`: `{counter:words=Intro;Verse;Chorus}`.
+//! Numbers the style has no way to write (zero or less, or past the end of
+//! the words) are written as plain numbers; `pad` only pads plain numbers.
+//!
+//! `@group(KEY)` after the options numbers files within each value of
+//! another token instead, in batch order: `{counter:3@group(exif.date_taken:yyyy-MM-dd)}`
+//! restarts at 001 for every shooting day, `{counter@group(tag.album|none)}`
+//! for every album. The key is rendered from the file's name before any rule
+//! ran; see [`assign_groups`].
+//!
+//! In a batch with bursts (see [`crate::burst`]), the shots of each burst
+//! share one number, and counters add a letter for the shot: `IMG_0042a`,
+//! `IMG_0042b`; see [`burst_contexts`].
+//!
+//! Tokens read from inside the file (see [`crate::metadata`]), from plugins
+//! or from the name (`{parsed_date}`, `{show}` and the other episode tokens) render empty
+//! when there is no such value, or the text after a `|` if one is given:
+//! `{tag.artist|Unknown Artist}`, `{tag.track:02|00}`,
+//! `{parsed_date:yyyy|undated}`, `{season:02|00}`.
+//!
+//! The pattern of `{ocr.match(...)}` runs to its last `)` and may hold `|`,
+//! but not braces: `Invoice {ocr.match(Invoice No\.?\s*(\w+))|unknown}`
+//! takes the invoice number printed on a scan.
+//!
+//! `{best_date}` tries the EXIF date, then XMP, then the modification time,
+//! then a date in the name, and takes the first one there is, so a batch of
+//! photos from many sources is dated as well as each can be. `@from(...)`
+//! picks the sources and their order: `{best_date:yyyy-MM-dd@from(exif,name)|undated}`
+//! leaves files with neither undated. Which source each file's date came
+//! from is in the preview, see [`Template::date_source`].
+//!
+//! `{hash}` is the SHA-256 of the file's contents in hex. `ALGO` may also be
+//! `md5` or `xxh3` (fast, but not cryptographic), and `LEN` keeps only the
+//! first digits: `{name}-{hash:sha256:8}` gives `app-3a7bd3e2.js`. A bare
+//! number is shorthand for the length, so `{hash:8}` is the same.
+//!
+//! Month and day names (`MMMM`, `MMM`, `dddd`, `ddd`, `tt`) and the decimal
+//! separator of `{size:human}` follow the current locale, see
+//! [`crate::locale`].
+//!
+//! `{{` and `}}` produce literal braces.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::format::{self, Parsed, StrftimeItems};
+use chrono::{DateTime, Local, NaiveTime, TimeZone};
+use regex::Regex;
+
+use crate::burst::Burst;
+use crate::episode;
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::hash::{self, HashAlgorithm};
+use crate::locale;
+use crate::metadata::{self, DateSource, Kind, MetaValue, Metadata};
+use crate::name_date;
+use crate::ocr;
+use crate::plugins;
+use crate::rules::split_name;
+use crate::sniff::{self, ContentType};
+
+/// Everything a template may need to know about the file being renamed.
+#[derive(Debug, Clone)]
+pub struct FileContext<'a> {
+    pub path: &'a Path,
+    /// Zero-based position of the file in the batch.
+    pub index: usize,
+    /// Zero-based position among files in the same folder.
+    pub folder_index: usize,
+    /// Zero-based position among files with the same extension.
+    pub ext_index: usize,
+    pub size: Option<u64>,
+    pub modified: Option<DateTime<Local>>,
+    /// Properties read from inside the file, for `{exif.*}` style tokens.
+    pub embedded: Option<Metadata>,
+    /// Content hashes for `{hash}` tokens, by algorithm.
+    pub hashes: Vec<(HashAlgorithm, String)>,
+    /// The file's format as told by its contents.
+    pub content_type: Option<ContentType>,
+    /// Zero-based position among files with the same value of each
+    /// `@group(KEY)` counter key, by key; see [`assign_groups`].
+    pub groups: HashMap<String, usize>,
+    /// The file's place in a burst numbered once; see [`burst_contexts`].
+    pub burst: Option<BurstPlace>,
+    /// The text recognized in the file, for `{ocr.*}` tokens.
+    pub ocr: Option<String>,
+}
+
+/// Where a file is in a burst of shots that share their numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BurstPlace {
+    /// Position in the batch of the burst's first shot.
+    pub first: usize,
+    /// Zero-based position in the burst.
+    pub shot: usize,
+    /// Counters add a letter for the shot, `a` for the first.
+    pub lettered: bool,
+}
+
+impl<'a> FileContext<'a> {
+    /// A context without filesystem metadata, treating the file as the only
+    /// member of its folder and extension groups beyond `index`.
+    pub fn new(path: &'a Path, index: usize) -> Self {
+        FileContext {
+            path,
+            index,
+            folder_index: index,
+            ext_index: index,
+            size: None,
+            modified: None,
+            embedded: None,
+            hashes: Vec::new(),
+            content_type: None,
+            groups: HashMap::new(),
+            burst: None,
+            ocr: None,
+        }
+    }
+
+    /// Fills in size and modification time from the file's metadata, leaving
+    /// them empty if it cannot be read.
+    pub fn with_metadata(mut self) -> Self {
+        if let Ok(meta) = fs::metadata(fsutil::long(self.path)) {
+            self.size = Some(meta.len());
+            self.modified = meta.modified().ok().map(DateTime::<Local>::from);
+        }
+        self
+    }
+
+    /// Fills in [`FileContext::embedded`] by reading the file.
+    pub fn with_embedded(mut self) -> Self {
+        self.embedded = Some(metadata::extract(self.path));
+        self
+    }
+
+    /// Fills in [`FileContext::content_type`] by reading the start of the
+    /// file.
+    pub fn with_content_type(mut self) -> Self {
+        self.content_type = sniff::sniff(self.path);
+        self
+    }
+
+    /// Fills in [`FileContext::ocr`] with what [`crate::ocr`] recognizes in
+    /// the file.
+    pub fn with_ocr(mut self) -> Self {
+        self.ocr = ocr::recognize(self.path);
+        self
+    }
+
+    /// Fills in [`FileContext::hashes`] by reading the file once for each of
+    /// `algorithms`.
+    pub fn with_hashes(mut self, algorithms: &[HashAlgorithm]) -> Self {
+        self.hashes = hash::hash_with(self.path, algorithms);
+        self
+    }
+}
+
+/// Builds contexts for a whole batch, assigning per-folder and per-extension
+/// positions in batch order.
+pub fn batch_contexts(paths: &[PathBuf]) -> Vec<FileContext<'_>> {
+    burst_contexts(paths, &[], false)
+}
+
+/// [`batch_contexts`] counting each of `bursts` as one file: its later
+/// shots take the positions of its first, so counters give them all the
+/// same number, followed by a letter for the shot if `lettered`.
+pub fn burst_contexts<'a>(
+    paths: &'a [PathBuf],
+    bursts: &[Burst],
+    lettered: bool,
+) -> Vec<FileContext<'a>> {
+    let shots: HashMap<&Path, (usize, usize)> = bursts
+        .iter()
+        .enumerate()
+        .flat_map(|(group, burst)| {
+            let shots = burst.paths.iter().enumerate();
+            shots.map(move |(shot, path)| (path.as_path(), (group, shot)))
+        })
+        .collect();
+    let mut firsts: HashMap<usize, usize> = HashMap::new();
+    let mut folders: HashMap<&Path, usize> = HashMap::new();
+    let mut exts: HashMap<String, usize> = HashMap::new();
+    let mut next = 0;
+    let mut contexts: Vec<FileContext<'a>> = Vec::with_capacity(paths.len());
+    for (position, path) in paths.iter().enumerate() {
+        if let Some(&(group, shot)) = shots.get(path.as_path()) {
+            let first = *firsts.entry(group).or_insert(position);
+            if first != position {
+                let lead = &contexts[first];
+                let ctx = FileContext {
+                    folder_index: lead.folder_index,
+                    ext_index: lead.ext_index,
+                    burst: Some(BurstPlace {
+                        first,
+                        shot,
+                        lettered,
+                    }),
+                    ..FileContext::new(path, lead.index)
+                };
+                contexts.push(ctx);
+                continue;
+            }
+        }
+        let mut ctx = numbered(path, next, &mut folders, &mut exts);
+        ctx.burst = shots.get(path.as_path()).map(|&(_, shot)| BurstPlace {
+            first: position,
+            shot,
+            lettered,
+        });
+        contexts.push(ctx);
+        next += 1;
+    }
+    contexts
+}
+
+/// The context of the `index`th file numbered in a batch, taking the next
+/// positions in its folder and among its extension.
+fn numbered<'a>(
+    path: &'a Path,
+    index: usize,
+    folders: &mut HashMap<&'a Path, usize>,
+    exts: &mut HashMap<String, usize>,
+) -> FileContext<'a> {
+    let folder = path.parent().unwrap_or(Path::new(""));
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let folder_slot = folders.entry(folder).or_default();
+    let ext_slot = exts.entry(ext).or_default();
+    let ctx = FileContext {
+        folder_index: *folder_slot,
+        ext_index: *ext_slot,
+        ..FileContext::new(path, index)
+    };
+    *folder_slot += 1;
+    *ext_slot += 1;
+    ctx
+}
+
+/// Fills in [`FileContext::groups`] for the `@group(KEY)` counters of
+/// `templates`. Each key is rendered for every file from its current name,
+/// so the contexts should already hold what the keys read, and files are
+/// numbered among those with the same value in the order of `contexts`.
+pub fn assign_groups(contexts: &mut [FileContext], templates: &[&Template]) {
+    let mut keys: Vec<(&str, &Template)> = Vec::new();
+    for template in templates {
+        for (key, by) in template.group_keys() {
+            if !keys.iter().any(|(k, _)| *k == key) {
+                keys.push((key, by));
+            }
+        }
+    }
+    for (key, by) in keys {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for i in 0..contexts.len() {
+            // The later shots of a burst share its first's number.
+            if let Some(place) = contexts[i].burst.filter(|place| place.first != i) {
+                if let Some(&slot) = contexts[place.first].groups.get(key) {
+                    contexts[i].groups.insert(key.to_string(), slot);
+                    continue;
+                }
+            }
+            let ctx = &mut contexts[i];
+            let name = ctx.path.file_name().unwrap_or_default().to_string_lossy();
+            let slot = seen.entry(by.render(&name, ctx)).or_default();
+            ctx.groups.insert(key.to_string(), *slot);
+            *slot += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CounterReset {
+    Never,
+    Folder,
+    Ext,
+    /// Per value of the key, a single token; `key` is its text as written.
+    Group {
+        key: String,
+        by: Template,
+    },
+}
+
+/// How a counter writes its numbers.
+#[derive(Debug, Clone, PartialEq)]
+enum CounterStyle {
+    Decimal,
+    Roman {
+        upper: bool,
+    },
+    Alpha {
+        upper: bool,
+    },
+    /// The first word for 1, and so on.
+    Words(Vec<String>),
+}
+
+impl CounterStyle {
+    /// `value` in this style, or `None` if it cannot be written in it.
+    fn write(&self, value: i64) -> Option<String> {
+        let n = u64::try_from(value).ok().filter(|&n| n > 0)?;
+        match self {
+            CounterStyle::Decimal => None,
+            CounterStyle::Roman { upper } => {
+                let roman = roman(n)?;
+                Some(if *upper { roman } else { roman.to_lowercase() })
+            }
+            CounterStyle::Alpha { upper } => {
+                let base = if *upper { b'A' } else { b'a' };
+                let mut letters = Vec::new();
+                let mut n = n;
+                while n > 0 {
+                    n -= 1;
+                    letters.push((base + (n % 26) as u8) as char);
+                    n /= 26;
+                }
+                Some(letters.into_iter().rev().collect())
+            }
+            CounterStyle::Words(words) => words.get(n as usize - 1).cloned(),
+        }
+    }
+}
+
+/// `n` in upper-case Roman numerals, up to 3999.
+fn roman(n: u64) -> Option<String> {
+    const NUMERALS: &[(u64, &str)] = &[
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    if n >= 4000 {
+        return None;
+    }
+    let mut out = String::new();
+    let mut n = n;
+    for &(value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    Some(out)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CounterSpec {
+    start: i64,
+    step: i64,
+    pad: usize,
+    reset: CounterReset,
+    style: CounterStyle,
+}
+
+impl Default for CounterSpec {
+    fn default() -> Self {
+        CounterSpec {
+            start: 1,
+            step: 1,
+            pad: 0,
+            reset: CounterReset::Never,
+            style: CounterStyle::Decimal,
+        }
+    }
+}
+
+impl CounterSpec {
+    fn parse(template: &str, args: &str) -> Result<Self> {
+        let mut spec = CounterSpec::default();
+        let (args, group) = match args.split_once("@group(") {
+            Some((args, group)) => (args, Some(group)),
+            None => (args, None),
+        };
+        for part in args.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let bad = || invalid(template, format!("bad counter option `{}`", part));
+            match part.split_once('=') {
+                None => spec.pad = part.parse().map_err(|_| bad())?,
+                Some(("start", v)) => spec.start = v.trim().parse().map_err(|_| bad())?,
+                Some(("step", v)) => spec.step = v.trim().parse().map_err(|_| bad())?,
+                Some(("pad", v)) => spec.pad = v.trim().parse().map_err(|_| bad())?,
+                Some(("reset", v)) => {
+                    spec.reset = match v.trim() {
+                        "none" => CounterReset::Never,
+                        "folder" | "dir" => CounterReset::Folder,
+                        "ext" => CounterReset::Ext,
+                        _ => return Err(bad()),
+                    }
+                }
+                Some(("style", v)) => {
+                    spec.style = match v.trim() {
+                        "decimal" => CounterStyle::Decimal,
+                        "roman" => CounterStyle::Roman { upper: false },
+                        "ROMAN" => CounterStyle::Roman { upper: true },
+                        "alpha" => CounterStyle::Alpha { upper: false },
+                        "ALPHA" => CounterStyle::Alpha { upper: true },
+                        _ => return Err(bad()),
+                    }
+                }
+                Some(("words", v)) => {
+                    let words: Vec<String> = v
+                        .split(';')
+                        .map(|w| w.trim().to_string())
+                        .filter(|w| !w.is_empty())
+                        .collect();
+                    if words.is_empty() {
+                        return Err(bad());
+                    }
+                    spec.style = CounterStyle::Words(words);
+                }
+                Some(_) => return Err(bad()),
+            }
+        }
+        if let Some(group) = group {
+            let key = group
+                .strip_suffix(')')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| invalid(template, "`@group(` needs a key and a closing `)`"))?;
+            if spec.reset != CounterReset::Never {
+                return Err(invalid(
+                    template,
+                    "a counter takes `reset` or `@group`, not both",
+                ));
+            }
+            let by = Template {
+                segments: vec![Segment::Token(parse_token(template, key)?)],
+            };
+            spec.reset = CounterReset::Group {
+                key: key.to_string(),
+                by,
+            };
+        }
+        Ok(spec)
+    }
+
+    /// The key token of an `@group` counter.
+    fn group(&self) -> Option<&Template> {
+        match &self.reset {
+            CounterReset::Group { by, .. } => Some(by),
+            _ => None,
+        }
+    }
+
+    fn render(&self, ctx: &FileContext) -> String {
+        let position = match &self.reset {
+            CounterReset::Never => ctx.index,
+            CounterReset::Folder => ctx.folder_index,
+            CounterReset::Ext => ctx.ext_index,
+            // Unassigned, as for a context not built for a batch, the
+            // position in the batch stands in, as for folders.
+            CounterReset::Group { key, .. } => ctx.groups.get(key).copied().unwrap_or(ctx.index),
+        };
+        let value = self.start + self.step * position as i64;
+        let mut written = self.style.write(value).unwrap_or_else(|| {
+            let digits = format!("{:0width$}", value.unsigned_abs(), width = self.pad);
+            if value < 0 {
+                format!("-{}", digits)
+            } else {
+                digits
+            }
+        });
+        if let Some(place) = ctx.burst.filter(|place| place.lettered) {
+            let letter = CounterStyle::Alpha { upper: false }.write(place.shot as i64 + 1);
+            written.extend(letter);
+        }
+        written
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name,
+    Ext,
+    Counter(CounterSpec),
+    /// chrono strftime format converted from the user's pattern.
+    Date(String),
+    /// The first of `chain` that has a date, see [`metadata::best_date`].
+    BestDate {
+        format: String,
+        chain: Vec<DateSource>,
+        fallback: String,
+    },
+    /// A date found in the current name, rendered with a strftime format.
+    ParsedDate {
+        format: String,
+        fallback: String,
+    },
+    /// Part of the show or movie named in the current name.
+    Episode {
+        part: EpisodePart,
+        pad: usize,
+        fallback: String,
+    },
+    Parent,
+    Size {
+        human: bool,
+    },
+    /// Hex digest of the contents, cut to `len` digits.
+    Hash {
+        algorithm: HashAlgorithm,
+        len: Option<usize>,
+    },
+    /// A value from [`crate::metadata`].
+    Embedded {
+        key: &'static str,
+        format: EmbeddedFormat,
+        /// Rendered when the file has no value for `key`.
+        fallback: String,
+    },
+    /// A token of an enabled plugin.
+    Plugin {
+        plugin: String,
+        token: String,
+        fallback: String,
+    },
+    /// Part of the text [`crate::ocr`] recognized in the file.
+    Ocr {
+        part: OcrPart,
+        fallback: String,
+    },
+}
+
+/// What of the recognized text an `{ocr.*}` token takes.
+#[derive(Debug, Clone)]
+enum OcrPart {
+    FirstLine,
+    /// The first match, or what its first group matched if it has groups.
+    Match(Regex),
+}
+
+impl OcrPart {
+    fn find<'t>(&self, text: &'t str) -> Option<&'t str> {
+        match self {
+            OcrPart::FirstLine => ocr::first_line(text),
+            OcrPart::Match(regex) => {
+                let caps = regex.captures(text)?;
+                let found = caps.get(1).or_else(|| caps.get(0))?;
+                Some(found.as_str().trim())
+            }
+        }
+    }
+}
+
+impl PartialEq for OcrPart {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (OcrPart::FirstLine, OcrPart::FirstLine) => true,
+            (OcrPart::Match(a), OcrPart::Match(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EpisodePart {
+    Show,
+    Season,
+    Episode,
+    Year,
+    Title,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum EmbeddedFormat {
+    Plain,
+    Padded(usize),
+    /// chrono strftime format.
+    Date(String),
+}
+
+impl Token {
+    fn needs_metadata(&self) -> bool {
+        match self {
+            Token::Counter(spec) => spec.group().is_some_and(Template::needs_metadata),
+            Token::BestDate { chain, .. } => chain.contains(&DateSource::Modified),
+            _ => matches!(self, Token::Date(_) | Token::Size { .. }),
+        }
+    }
+
+    fn needs_embedded(&self) -> bool {
+        match self {
+            Token::Counter(spec) => spec.group().is_some_and(Template::needs_embedded),
+            Token::BestDate { chain, .. } => chain.iter().any(|s| s.is_embedded()),
+            _ => matches!(self, Token::Embedded { .. } | Token::Plugin { .. }),
+        }
+    }
+
+    fn needs_ocr(&self) -> bool {
+        match self {
+            Token::Counter(spec) => spec.group().is_some_and(Template::needs_ocr),
+            _ => matches!(self, Token::Ocr { .. }),
+        }
+    }
+}
+
+/// Parses the options of `{hash:ALGO:LEN}`.
+fn hash_token(template: &str, body: &str, arg: Option<&str>) -> Result<Token> {
+    let (name, len) = match arg.map(str::trim) {
+        None | Some("") => ("sha256", None),
+        Some(arg) => match arg.split_once(':') {
+            Some((name, len)) => (name, Some(len)),
+            None if arg.bytes().all(|b| b.is_ascii_digit()) => ("sha256", Some(arg)),
+            None => (arg, None),
+        },
+    };
+    let algorithm = HashAlgorithm::parse(name).ok_or_else(|| {
+        invalid(
+            template,
+            format!("unknown hash algorithm `{}`", name.trim()),
+        )
+    })?;
+    let len = match len {
+        Some(len) => Some(
+            len.trim()
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| invalid(template, format!("bad length in `{{{}}}`", body)))?,
+        ),
+        None => None,
+    };
+    Ok(Token::Hash { algorithm, len })
+}
+
+fn render_embedded(value: &MetaValue, format: &EmbeddedFormat) -> String {
+    match (value, format) {
+        (MetaValue::Integer(n), EmbeddedFormat::Padded(pad)) => {
+            format!("{:0width$}", n, width = *pad)
+        }
+        (MetaValue::Date(date), EmbeddedFormat::Date(fmt)) => localized(&date.and_utc(), fmt),
+        (MetaValue::Integer(n), _) => n.to_string(),
+        (MetaValue::Date(date), _) => date.format("%Y-%m-%d").to_string(),
+        (MetaValue::Text(text), _) => text.clone(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Token(Token),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+fn invalid(template: &str, message: impl Into<String>) -> Error {
+    Error::InvalidTemplate {
+        template: template.to_string(),
+        message: message.into(),
+    }
+}
+
+/// Converts `yyyy-MM-dd HH:mm:ss` style patterns to chrono's strftime syntax.
+fn date_format(pattern: &str) -> String {
+    const SPECIFIERS: &[(&str, &str)] = &[
+        ("yyyy", "%Y"),
+        ("yy", "%y"),
+        ("MMMM", "%B"),
+        ("MMM", "%b"),
+        ("MM", "%m"),
+        ("dddd", "%A"),
+        ("ddd", "%a"),
+        ("dd", "%d"),
+        ("HH", "%H"),
+        ("hh", "%I"),
+        ("mm", "%M"),
+        ("ss", "%S"),
+        ("tt", "%p"),
+    ];
+    let mut out = String::new();
+    let mut rest = pattern;
+    'outer: while let Some(c) = rest.chars().next() {
+        for (from, to) in SPECIFIERS {
+            if let Some(tail) = rest.strip_prefix(from) {
+                out.push_str(to);
+                rest = tail;
+                continue 'outer;
+            }
+        }
+        if c == '%' {
+            out.push_str("%%");
+        } else {
+            out.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// What each date specifier can look like inside a file name.
+const DATE_FIELDS: &[(&str, &str)] = &[
+    ("yyyy", r"\d{4}"),
+    ("yy", r"\d{2}"),
+    ("MMMM", r"\p{L}+"),
+    ("MMM", r"\p{L}+"),
+    ("MM", r"\d{2}"),
+    ("dddd", r"\p{L}+"),
+    ("ddd", r"\p{L}+"),
+    ("dd", r"\d{2}"),
+    ("HH", r"\d{2}"),
+    ("hh", r"\d{2}"),
+    ("mm", r"\d{2}"),
+    ("ss", r"\d{2}"),
+    ("tt", r"[AaPp][Mm]"),
+];
+
+/// A date pattern in template syntax (`yyyyMMdd_HHmmss`) used to read a date
+/// back out of a file name. Missing time fields mean midnight, local time.
+#[derive(Debug, Clone)]
+pub(crate) struct DatePattern {
+    regex: Regex,
+    format: String,
+}
+
+impl DatePattern {
+    pub(crate) fn parse(pattern: &str) -> Result<Self> {
+        let format = date_format(pattern);
+        let has = |specs: &[&str]| specs.iter().any(|s| format.contains(s));
+        if !(has(&["%Y", "%y"]) && has(&["%m", "%b", "%B"]) && has(&["%d"])) {
+            return Err(invalid(
+                pattern,
+                "a date pattern needs a year, month and day",
+            ));
+        }
+        let mut regex = String::new();
+        let mut rest = pattern;
+        'outer: while let Some(c) = rest.chars().next() {
+            for (from, field) in DATE_FIELDS {
+                if let Some(tail) = rest.strip_prefix(from) {
+                    regex.push_str(field);
+                    rest = tail;
+                    continue 'outer;
+                }
+            }
+            regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+            rest = &rest[c.len_utf8()..];
+        }
+        let regex = Regex::new(&regex).map_err(|e| invalid(pattern, e.to_string()))?;
+        Ok(DatePattern { regex, format })
+    }
+
+    /// The first valid date in `name` written in this pattern.
+    pub(crate) fn find(&self, name: &str) -> Option<DateTime<Local>> {
+        self.regex.find_iter(name).find_map(|m| {
+            let mut parsed = Parsed::new();
+            format::parse(&mut parsed, m.as_str(), StrftimeItems::new(&self.format)).ok()?;
+            let date = parsed.to_naive_date().ok()?;
+            let time = parsed.to_naive_time().unwrap_or(NaiveTime::MIN);
+            Local.from_local_datetime(&date.and_time(time)).earliest()
+        })
+    }
+}
+
+fn parse_token(template: &str, body: &str) -> Result<Token> {
+    // A group key is a token of its own, with its own options and fallback.
+    if let Some(args) = body
+        .trim_start()
+        .strip_prefix("counter")
+        .filter(|rest| rest.contains("@group(") && rest.starts_with([':', '@']))
+    {
+        let args = args.strip_prefix(':').unwrap_or(args);
+        return Ok(Token::Counter(CounterSpec::parse(template, args)?));
+    }
+    // The pattern may hold `|` and `:` itself, and runs to the last `)`.
+    if let Some(rest) = body.trim_start().strip_prefix("ocr.match(") {
+        let (pattern, after) = rest
+            .rsplit_once(')')
+            .ok_or_else(|| invalid(template, "`ocr.match(` needs a closing `)`"))?;
+        let after = after.trim();
+        let fallback = match after.strip_prefix('|') {
+            Some(fallback) => fallback,
+            None if after.is_empty() => "",
+            None => return Err(invalid(template, format!("unexpected `{}`", after))),
+        };
+        let regex = Regex::new(pattern).map_err(|e| invalid(template, e.to_string()))?;
+        return Ok(Token::Ocr {
+            part: OcrPart::Match(regex),
+            fallback: fallback.to_string(),
+        });
+    }
+    let (spec, fallback) = match body.split_once('|') {
+        Some((spec, fallback)) => (spec, Some(fallback)),
+        None => (body, None),
+    };
+    let (spec, from) = match spec.split_once("@from(") {
+        Some((spec, from)) => (spec, Some(from)),
+        None => (spec, None),
+    };
+    let (key, arg) = match spec.split_once(':') {
+        Some((k, a)) => (k.trim(), Some(a)),
+        None => (spec.trim(), None),
+    };
+    let token = match (key, arg) {
+        ("name", None) => Token::Name,
+        ("ext", None) => Token::Ext,
+        ("counter", arg) => Token::Counter(CounterSpec::parse(template, arg.unwrap_or(""))?),
+        ("parent", None) => Token::Parent,
+        ("date", arg) => Token::Date(date_format(arg.unwrap_or("yyyy-MM-dd"))),
+        ("best_date", arg) => {
+            let chain = match from {
+                None => DateSource::CHAIN.to_vec(),
+                Some(from) => from
+                    .strip_suffix(')')
+                    .filter(|from| !from.trim().is_empty())
+                    .ok_or_else(|| invalid(template, "`@from(` needs sources and a closing `)`"))?
+                    .split(',')
+                    .map(|name| {
+                        DateSource::parse(name).ok_or_else(|| {
+                            invalid(template, format!("unknown date source `{}`", name.trim()))
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            };
+            Token::BestDate {
+                format: date_format(arg.unwrap_or("yyyy-MM-dd")),
+                chain,
+                fallback: fallback.unwrap_or_default().to_string(),
+            }
+        }
+        ("parsed_date", arg) => Token::ParsedDate {
+            format: date_format(arg.unwrap_or("yyyy-MM-dd")),
+            fallback: fallback.unwrap_or_default().to_string(),
+        },
+        ("ocr.first_line", None) => Token::Ocr {
+            part: OcrPart::FirstLine,
+            fallback: fallback.unwrap_or_default().to_string(),
+        },
+        ("size", None) => Token::Size { human: false },
+        ("size", Some("human")) => Token::Size { human: true },
+        ("hash", arg) => hash_token(template, body, arg)?,
+        ("plugin", Some(arg)) => {
+            let (plugin, token) = arg.trim().split_once('.').unwrap_or((arg.trim(), ""));
+            if !plugins::find(plugin).is_some_and(|p| p.has_token(token)) {
+                return Err(invalid(
+                    template,
+                    format!("no enabled plugin provides `{{{}}}`", spec),
+                ));
+            }
+            Token::Plugin {
+                plugin: plugin.to_string(),
+                token: token.to_string(),
+                fallback: fallback.unwrap_or_default().to_string(),
+            }
+        }
+        ("show" | "year" | "episode_title", None) | ("season" | "episode", _) => {
+            let part = match key {
+                "show" => EpisodePart::Show,
+                "year" => EpisodePart::Year,
+                "episode_title" => EpisodePart::Title,
+                "season" => EpisodePart::Season,
+                _ => EpisodePart::Episode,
+            };
+            let pad = match arg {
+                Some(pad) => pad
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid(template, format!("bad padding in `{{{}}}`", body)))?,
+                None => 0,
+            };
+            Token::Episode {
+                part,
+                pad,
+                fallback: fallback.unwrap_or_default().to_string(),
+            }
+        }
+        (key, arg) => {
+            let Some((key, kind)) = metadata::kind(key) else {
+                return Err(invalid(template, format!("unknown token `{{{}}}`", body)));
+            };
+            let format = match (kind, arg) {
+                (Kind::Date, arg) => EmbeddedFormat::Date(date_format(arg.unwrap_or("yyyy-MM-dd"))),
+                (Kind::Integer, Some(pad)) => EmbeddedFormat::Padded(
+                    pad.trim()
+                        .parse()
+                        .map_err(|_| invalid(template, format!("bad padding in `{{{}}}`", body)))?,
+                ),
+                (_, None) => EmbeddedFormat::Plain,
+                (Kind::Text, Some(_)) => {
+                    return Err(invalid(template, format!("`{}` takes no options", key)))
+                }
+            };
+            Token::Embedded {
+                key,
+                format,
+                fallback: fallback.unwrap_or_default().to_string(),
+            }
+        }
+    };
+    if from.is_some() && !matches!(token, Token::BestDate { .. }) {
+        return Err(invalid(
+            template,
+            format!("`{{{}}}` cannot take `@from`", key),
+        ));
+    }
+    let takes_fallback = matches!(
+        token,
+        Token::Embedded { .. }
+            | Token::BestDate { .. }
+            | Token::ParsedDate { .. }
+            | Token::Episode { .. }
+            | Token::Plugin { .. }
+            | Token::Ocr { .. }
+    );
+    if fallback.is_some() && !takes_fallback {
+        return Err(invalid(
+            template,
+            format!("`{{{}}}` cannot take a fallback", key),
+        ));
+    }
+    Ok(token)
+}
+
+/// `date` in `format`, with month and day names in the current locale.
+fn localized<Tz: TimeZone>(date: &DateTime<Tz>, format: &str) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    date.format_localized(format, locale::current()).to_string()
+}
+
+/// `bytes` in the largest unit that keeps the number at least one, with the
+/// current locale's decimal separator.
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        let point = locale::decimal_point(locale::current());
+        format!("{:.1} {}", value, UNITS[unit]).replacen('.', point, 1)
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|&(_, n)| n) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().map(|&(_, n)| n) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let start = i + 1;
+                    let end = template[start..]
+                        .find('}')
+                        .map(|off| start + off)
+                        .ok_or_else(|| invalid(template, "unclosed `{`"))?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Token(parse_token(
+                        template,
+                        &template[start..end],
+                    )?));
+                    while chars.peek().is_some_and(|&(j, _)| j <= end) {
+                        chars.next();
+                    }
+                }
+                '}' => {
+                    return Err(invalid(
+                        template,
+                        "unmatched `}`, use `}}` for a literal brace",
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    /// True if rendering reads size or dates, i.e. the caller should supply
+    /// a context built with [`FileContext::with_metadata`].
+    pub fn needs_metadata(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Token(t) if t.needs_metadata()))
+    }
+
+    /// True if rendering reads properties from inside the file, i.e. the
+    /// caller should supply a context built with [`FileContext::with_embedded`].
+    pub fn needs_embedded(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Token(t) if t.needs_embedded()))
+    }
+
+    /// True if rendering reads the file's text, i.e. the caller should
+    /// supply a context built with [`FileContext::with_ocr`].
+    pub fn needs_ocr(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Token(t) if t.needs_ocr()))
+    }
+
+    /// The algorithms of the template's `{hash}` tokens; the caller should
+    /// supply a context built with [`FileContext::with_hashes`] for them.
+    pub fn hash_algorithms(&self) -> Vec<HashAlgorithm> {
+        let mut algorithms = Vec::new();
+        for segment in &self.segments {
+            let found = match segment {
+                Segment::Token(Token::Hash { algorithm, .. }) => vec![*algorithm],
+                Segment::Token(Token::Counter(spec)) => spec
+                    .group()
+                    .map(Template::hash_algorithms)
+                    .unwrap_or_default(),
+                _ => Vec::new(),
+            };
+            for algorithm in found {
+                if !algorithms.contains(&algorithm) {
+                    algorithms.push(algorithm);
+                }
+            }
+        }
+        algorithms
+    }
+
+    /// Where the first `{best_date}` of the template takes its date from for
+    /// a file whose current name is `name`, or `None` if the template has
+    /// none or no source had a date.
+    pub fn date_source(&self, name: &str, ctx: &FileContext) -> Option<DateSource> {
+        self.segments.iter().find_map(|s| match s {
+            Segment::Token(Token::BestDate { chain, .. }) => {
+                metadata::best_date(chain, ctx.embedded.as_ref(), ctx.modified, name)
+                    .map(|(_, source)| source)
+            }
+            _ => None,
+        })
+    }
+
+    /// The keys of the template's `@group(KEY)` counters, as written, with
+    /// the token each renders.
+    pub(crate) fn group_keys(&self) -> Vec<(&str, &Template)> {
+        self.segments
+            .iter()
+            .filter_map(|s| match s {
+                Segment::Token(Token::Counter(CounterSpec {
+                    reset: CounterReset::Group { key, by },
+                    ..
+                })) => Some((key.as_str(), by)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Renders the template for a file whose current name is `name`.
+    pub fn render(&self, name: &str, ctx: &FileContext) -> String {
+        let (stem, ext) = split_name(name);
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Token(Token::Name) => out.push_str(stem),
+                Segment::Token(Token::Ext) => out.push_str(ext.trim_start_matches('.')),
+                Segment::Token(Token::Counter(spec)) => out.push_str(&spec.render(ctx)),
+                Segment::Token(Token::Date(fmt)) => {
+                    if let Some(date) = ctx.modified {
+                        out.push_str(&localized(&date, fmt));
+                    }
+                }
+                Segment::Token(Token::BestDate {
+                    format,
+                    chain,
+                    fallback,
+                }) => match metadata::best_date(chain, ctx.embedded.as_ref(), ctx.modified, name) {
+                    Some((date, _)) => out.push_str(&localized(&date.and_utc(), format)),
+                    None => out.push_str(fallback),
+                },
+                Segment::Token(Token::ParsedDate { format, fallback }) => {
+                    match name_date::find(name) {
+                        Some(date) => out.push_str(&localized(&date.and_utc(), format)),
+                        None => out.push_str(fallback),
+                    }
+                }
+                Segment::Token(Token::Episode {
+                    part,
+                    pad,
+                    fallback,
+                }) => {
+                    let found = episode::parse(name);
+                    let value = found.and_then(|e| match part {
+                        EpisodePart::Show => Some(e.show),
+                        EpisodePart::Title => e.title,
+                        EpisodePart::Season => e.season.map(|n| format!("{:0pad$}", n, pad = *pad)),
+                        EpisodePart::Episode => {
+                            e.episode.map(|n| format!("{:0pad$}", n, pad = *pad))
+                        }
+                        EpisodePart::Year => e.year.map(|n| n.to_string()),
+                    });
+                    out.push_str(value.as_deref().unwrap_or(fallback));
+                }
+                Segment::Token(Token::Parent) => {
+                    if let Some(parent) = ctx.path.parent().and_then(Path::file_name) {
+                        out.push_str(&parent.to_string_lossy());
+                    }
+                }
+                Segment::Token(Token::Hash { algorithm, len }) => {
+                    if let Some((_, hash)) = ctx.hashes.iter().find(|(a, _)| a == algorithm) {
+                        let len = len.unwrap_or(hash.len()).min(hash.len());
+                        out.push_str(&hash[..len]);
+                    }
+                }
+                Segment::Token(Token::Embedded {
+                    key,
+                    format,
+                    fallback,
+                }) => match ctx.embedded.as_ref().and_then(|m| m.get(key)) {
+                    Some(value) => out.push_str(&render_embedded(value, format)),
+                    None => out.push_str(fallback),
+                },
+                Segment::Token(Token::Plugin {
+                    plugin,
+                    token,
+                    fallback,
+                }) => match plugins::find(plugin).and_then(|p| p.token(token, name, ctx)) {
+                    Some(value) => out.push_str(&value),
+                    None => out.push_str(fallback),
+                },
+                Segment::Token(Token::Ocr { part, fallback }) => {
+                    let found = ctx.ocr.as_deref().and_then(|text| part.find(text));
+                    out.push_str(found.filter(|f| !f.is_empty()).unwrap_or(fallback));
+                }
+                Segment::Token(Token::Size { human }) => {
+                    if let Some(size) = ctx.size {
+                        if *human {
+                            out.push_str(&human_size(size));
+                        } else {
+                            out.push_str(&size.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,202 @@
+//! Keeping background jobs from starving the rest of the system of disk
+//! time.
+//!
+//! Work run through [`background`], as the job queue and folder watchers
+//! run theirs, reads and writes at most [`IoThrottle::max_mb_per_sec`]
+//! between all of it, and with [`IoThrottle::low_priority`] at the lowest
+//! I/O priority the OS offers a thread, where it has one (Windows and
+//! macOS). What the user waits for, like applying a batch from the window,
+//! is never held back.
+//!
+//! Work run through [`interactive`], such as a preview being recomputed or
+//! the thumbnails of the rows in view, goes further: while any runs,
+//! background work stops at its next read or write until it is done, or
+//! for [`MAX_YIELD`] at a time, so that it is not starved for good.
+
+use std::cell::Cell;
+use std::sync::{Condvar, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Waits shorter than this are saved up rather than slept, since sleeping
+/// that briefly is not precise.
+const MIN_SLEEP: Duration = Duration::from_millis(20);
+
+/// The longest background work waits for interactive work at one read or
+/// write.
+pub const MAX_YIELD: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IoThrottle {
+    /// Megabytes a second that background jobs read and write together at
+    /// most; unlimited when unset.
+    pub max_mb_per_sec: Option<u32>,
+    /// Runs background jobs at background I/O priority, so the disks serve
+    /// everything else first.
+    pub low_priority: bool,
+}
+
+fn current() -> &'static RwLock<IoThrottle> {
+    static CURRENT: OnceLock<RwLock<IoThrottle>> = OnceLock::new();
+    CURRENT.get_or_init(|| RwLock::new(IoThrottle::default()))
+}
+
+/// Makes `throttle` the one background jobs keep to from now on, including
+/// those already running.
+pub fn set(throttle: IoThrottle) {
+    *current().write().unwrap_or_else(|e| e.into_inner()) = throttle;
+}
+
+/// The throttle background jobs keep to.
+pub fn get() -> IoThrottle {
+    *current().read().unwrap_or_else(|e| e.into_inner())
+}
+
+thread_local! {
+    /// The thread does background work.
+    static BACKGROUND: Cell<bool> = const { Cell::new(false) };
+    /// The thread runs at low I/O priority.
+    static LOWERED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// When the reads and writes allowed so far are used up.
+static NEXT_FREE: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// How much interactive work is running, and a signal for when that
+/// changes.
+static INTERACTIVE: Mutex<usize> = Mutex::new(0);
+static INTERACTIVE_DONE: Condvar = Condvar::new();
+
+/// The threads background work runs on, so that what it does on a thread
+/// pool is background work too.
+fn pool() -> Option<&'static rayon::ThreadPool> {
+    static POOL: OnceLock<Option<rayon::ThreadPool>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .thread_name(|i| format!("sortify-background-{}", i))
+            .start_handler(|_| BACKGROUND.with(|b| b.set(true)))
+            .build()
+            .ok()
+    })
+    .as_ref()
+}
+
+/// Runs `work` as background work, on a thread of the background pool.
+pub fn background<R: Send>(work: impl FnOnce() -> R + Send) -> R {
+    if BACKGROUND.with(Cell::get) {
+        return work();
+    }
+    match pool() {
+        Some(pool) => pool.install(work),
+        None => {
+            BACKGROUND.with(|b| b.set(true));
+            let result = work();
+            BACKGROUND.with(|b| b.set(false));
+            if LOWERED.with(Cell::get) {
+                platform::set_low_priority(false);
+                LOWERED.with(|l| l.set(false));
+            }
+            result
+        }
+    }
+}
+
+/// Runs `work` as interactive work, which background work makes way for.
+pub fn interactive<R>(work: impl FnOnce() -> R) -> R {
+    struct Running;
+    impl Drop for Running {
+        fn drop(&mut self) {
+            *INTERACTIVE.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+            INTERACTIVE_DONE.notify_all();
+        }
+    }
+    *INTERACTIVE.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+    let _running = Running;
+    work()
+}
+
+/// Whether background work is making way for interactive work now.
+pub fn yielding() -> bool {
+    *INTERACTIVE.lock().unwrap_or_else(|e| e.into_inner()) > 0
+}
+
+/// Called after reading or writing `bytes`; in background work, waits
+/// for interactive work and until the throttle allows them.
+pub(crate) fn pace(bytes: usize) {
+    if !BACKGROUND.with(Cell::get) {
+        return;
+    }
+    let running = INTERACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    drop(INTERACTIVE_DONE.wait_timeout_while(running, MAX_YIELD, |n| *n > 0));
+    let throttle = get();
+    if LOWERED.with(Cell::get) != throttle.low_priority {
+        platform::set_low_priority(throttle.low_priority);
+        LOWERED.with(|l| l.set(throttle.low_priority));
+    }
+    let Some(rate) = throttle.max_mb_per_sec.filter(|&r| r > 0) else {
+        return;
+    };
+    let cost = Duration::from_secs_f64(bytes as f64 / (f64::from(rate) * 1e6));
+    let now = Instant::now();
+    let ready = {
+        let mut next_free = NEXT_FREE.lock().unwrap_or_else(|e| e.into_inner());
+        let start = next_free.filter(|&at| at > now).unwrap_or(now);
+        *next_free = Some(start + cost);
+        start + cost
+    };
+    let wait = ready.saturating_duration_since(now);
+    if wait >= MIN_SLEEP {
+        std::thread::sleep(wait);
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    const THREAD_MODE_BACKGROUND_BEGIN: i32 = 0x0001_0000;
+    const THREAD_MODE_BACKGROUND_END: i32 = 0x0002_0000;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut std::ffi::c_void;
+        fn SetThreadPriority(thread: *mut std::ffi::c_void, priority: i32) -> i32;
+    }
+
+    /// Background mode lowers the thread's I/O and memory priority along
+    /// with its CPU priority.
+    pub fn set_low_priority(low: bool) {
+        let mode = if low {
+            THREAD_MODE_BACKGROUND_BEGIN
+        } else {
+            THREAD_MODE_BACKGROUND_END
+        };
+        // SAFETY: only changes the calling thread's priority.
+        unsafe { SetThreadPriority(GetCurrentThread(), mode) };
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    const IOPOL_TYPE_DISK: i32 = 0;
+    const IOPOL_SCOPE_THREAD: i32 = 1;
+    const IOPOL_DEFAULT: i32 = 0;
+    const IOPOL_THROTTLE: i32 = 3;
+
+    extern "C" {
+        fn setiopolicy_np(kind: i32, scope: i32, policy: i32) -> i32;
+    }
+
+    pub fn set_low_priority(low: bool) {
+        let policy = if low { IOPOL_THROTTLE } else { IOPOL_DEFAULT };
+        // SAFETY: only changes the calling thread's I/O policy.
+        unsafe { setiopolicy_np(IOPOL_TYPE_DISK, IOPOL_SCOPE_THREAD, policy) };
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod platform {
+    /// No I/O priority to set for a thread here; the rate limit still
+    /// applies.
+    pub fn set_low_priority(_low: bool) {}
+}
@@ -0,0 +1,217 @@
+//! Small previews of images, so a rename list can show what each file is.
+//!
+//! JPEG, PNG, GIF, WebP, BMP and TIFF images are decoded and scaled down.
+//! Camera raw files and HEIC photos cannot be decoded here, so a preview
+//! stored with them is used instead: the embedded JPEG closest to the size
+//! asked for, or for HEIC without one, what the system's HEIF decoder makes
+//! of it, and failing those the thumbnail in the EXIF data. Previews are
+//! turned the way the EXIF orientation says and cached by path, size and
+//! modification time, so asking again for an unchanged file is a file read.
+//! A cached preview's modification time is when it was last used, which
+//! [`prune_cache`] goes by.
+
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use exif::{In, Reader, Tag};
+use image::codecs::jpeg::JpegEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::{heif, raw};
+
+/// Largest preview edge that can be asked for.
+pub const MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Thumbnail {
+    /// The cached preview file.
+    pub path: PathBuf,
+    /// `image/jpeg`, or `image/png` for images with transparency.
+    pub media_type: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A preview of the image at `path` no larger than `size` pixels on either
+/// side, cached in `cache_dir`.
+pub fn thumbnail(path: &Path, size: u32, cache_dir: &Path) -> Result<Thumbnail> {
+    let size = size.clamp(1, MAX_THUMBNAIL_SIZE);
+    let long = fsutil::long(path);
+    let stat = fs::metadata(&long).map_err(|e| Error::io(path, e))?;
+    let modified = stat
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    let key =
+        xxh3_64(format!("{}\0{}\0{}\0{}", path.display(), size, modified, stat.len()).as_bytes());
+    for (extension, media_type) in [("jpg", "image/jpeg"), ("png", "image/png")] {
+        let cached = cache_dir.join(format!("{:016x}.{}", key, extension));
+        if let Ok((width, height)) = image::image_dimensions(&cached) {
+            let _ = fs::File::options()
+                .write(true)
+                .open(&cached)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            return Ok(Thumbnail {
+                path: cached,
+                media_type: media_type.to_string(),
+                width,
+                height,
+            });
+        }
+    }
+
+    let image = decode(path, &long, size)?.thumbnail(size, size);
+    let (bytes, extension, media_type) = encode(&image).map_err(|message| Error::Thumbnail {
+        path: path.to_path_buf(),
+        message,
+    })?;
+    fs::create_dir_all(cache_dir).map_err(|e| Error::io(cache_dir, e))?;
+    let cached = cache_dir.join(format!("{:016x}.{}", key, extension));
+    fs::write(&cached, bytes).map_err(|e| Error::io(&cached, e))?;
+    Ok(Thumbnail {
+        path: cached,
+        media_type: media_type.to_string(),
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// Removes the previews in `cache_dir` used longest ago until the rest take
+/// up at most `max_bytes`. Returns how many it removed.
+pub fn prune_cache(cache_dir: &Path, max_bytes: u64) -> Result<usize> {
+    let listing = match fs::read_dir(cache_dir) {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(Error::io(cache_dir, e)),
+    };
+    let mut previews: Vec<(SystemTime, u64, PathBuf)> = listing
+        .flatten()
+        .filter_map(|entry| {
+            let stat = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((
+                stat.modified().unwrap_or(UNIX_EPOCH),
+                stat.len(),
+                entry.path(),
+            ))
+        })
+        .collect();
+    previews.sort_by_key(|p| std::cmp::Reverse(p.0));
+    let (mut kept, mut removed) = (0, 0);
+    for (_, len, path) in previews {
+        if kept + len <= max_bytes {
+            kept += len;
+        } else if fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn decode(path: &Path, long: &Path, size: u32) -> Result<DynamicImage> {
+    let (raw, heif) = (raw::is_raw(path), heif::is_heif(path));
+    let stored = if raw {
+        raw_preview(long, size)
+    } else if heif {
+        heif_preview(long, size)
+    } else {
+        None
+    };
+    let decoded = if let Some(image) = stored {
+        Ok(image)
+    } else if raw || heif {
+        Err("the format cannot be decoded".to_string())
+    } else {
+        decode_image(long)
+    };
+    decoded
+        .or_else(|e| embedded_preview(long).ok_or(e))
+        .map_err(|message| Error::Thumbnail {
+            path: path.to_path_buf(),
+            message,
+        })
+}
+
+fn decode_image(path: &Path) -> std::result::Result<DynamicImage, String> {
+    let mut decoder = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .map_err(|e| e.to_string())?
+        .into_decoder()
+        .map_err(|e| e.to_string())?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// The embedded JPEG of a raw file for a preview of `size`, turned upright.
+fn raw_preview(path: &Path, size: u32) -> Option<DynamicImage> {
+    let jpeg = raw::preview(path, size)?;
+    let mut image = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg).ok()?;
+    let orientation = raw::exif(path)
+        .and_then(|exif| exif.get(Tag::Orientation)?.value.get_uint(0))
+        .and_then(|o| Orientation::from_exif(o as u8))
+        .unwrap_or(Orientation::NoTransforms);
+    image.apply_orientation(orientation);
+    Some(image)
+}
+
+/// The preview of a HEIF image for a preview of `size`, turned upright.
+fn heif_preview(path: &Path, size: u32) -> Option<DynamicImage> {
+    let (data, rotation) = heif::preview(path, size)?;
+    let image = image::load_from_memory(&data).ok()?;
+    Some(match rotation {
+        1 => image.rotate270(),
+        2 => image.rotate180(),
+        3 => image.rotate90(),
+        _ => image,
+    })
+}
+
+/// The JPEG preview stored in the EXIF thumbnail directory, turned upright.
+fn embedded_preview(path: &Path) -> Option<DynamicImage> {
+    let file = File::open(path).ok()?;
+    let exif = Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let length = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let jpeg = exif.buf().get(offset..offset.checked_add(length)?)?;
+    let mut image = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg).ok()?;
+    let orientation = exif
+        .get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .and_then(|o| Orientation::from_exif(o as u8))
+        .unwrap_or(Orientation::NoTransforms);
+    image.apply_orientation(orientation);
+    Some(image)
+}
+
+fn encode(image: &DynamicImage) -> std::result::Result<(Vec<u8>, &str, &str), String> {
+    let mut bytes = Vec::new();
+    if image.color().has_alpha() {
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+        return Ok((bytes, "png", "image/png"));
+    }
+    JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+        .encode_image(&image.to_rgb8())
+        .map_err(|e| e.to_string())?;
+    Ok((bytes, "jpg", "image/jpeg"))
+}
@@ -0,0 +1,307 @@
+//! Moving files between volumes, where a plain rename is impossible.
+//!
+//! The copy is written to a hidden partial file next to the target and only
+//! gets the target's name once it is complete and checked, so a copy of a
+//! large video that is cut off, by a crash or a share going away, is
+//! continued where it stopped the next time the move is made instead of
+//! starting over. Holes of a sparse file stay holes in the copy.
+
+use std::fs::{self, File, FileTimes, Metadata, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::{fsutil, share, throttle};
+
+const BUFFER_SIZE: usize = 1 << 20;
+/// The blocks a sparse file's copy is made of: blocks of zeros are left as
+/// holes.
+const SPARSE_BLOCK: usize = 64 << 10;
+/// Tag of the hidden file a copy is written to before it gets its name.
+const PARTIAL_TAG: &str = "sortify-move.part";
+
+/// Called with bytes copied so far and the file's total size.
+pub(crate) type CopyProgress<'a> = &'a (dyn Fn(u64, u64) + Sync);
+
+/// How to copy a file that cannot simply be renamed.
+#[derive(Clone, Copy)]
+pub(crate) struct CrossVolume<'a> {
+    pub progress: CopyProgress<'a>,
+    /// Give the copy the original's access and modification times.
+    pub preserve_times: bool,
+}
+
+/// True for the "not on the same device" error `rename` gives for a target on
+/// another volume.
+pub(crate) fn is_cross_device(e: &io::Error) -> bool {
+    // EXDEV on Linux and macOS, ERROR_NOT_SAME_DEVICE on Windows.
+    #[cfg(unix)]
+    const CODE: i32 = 18;
+    #[cfg(windows)]
+    const CODE: i32 = 17;
+    #[cfg(not(any(unix, windows)))]
+    const CODE: i32 = -1;
+    e.raw_os_error() == Some(CODE)
+}
+
+fn hash_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = File::open(fsutil::long(path))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; BUFFER_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        throttle::pace(n);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// The hidden file a copy to `target` is written to.
+pub(crate) fn partial_of(target: &Path) -> PathBuf {
+    fsutil::hidden_sibling(target, PARTIAL_TAG)
+}
+
+/// Removes what an interrupted copy to `target` left, once the move it was
+/// for is given up on.
+pub(crate) fn discard_partial(target: &Path) {
+    let _ = fs::remove_file(fsutil::long(&partial_of(target)));
+}
+
+/// Copies `source` to a new file at `target` by way of [`partial_of`],
+/// hashing it on the way, checks that size and SHA-256 of the copy match
+/// and only then deletes `source`. A partial copy left by an earlier try is
+/// continued; if the result does not match, the copy is made again from
+/// the start. A mismatching or failed copy is removed, unless the failure
+/// is a dropped connection that a later try can continue after, and
+/// `source` is left untouched.
+pub(crate) fn copy_and_delete(source: &Path, target: &Path, how: CrossVolume) -> io::Result<()> {
+    let meta = fs::metadata(fsutil::long(source))?;
+    if !meta.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only files can be moved to another volume",
+        ));
+    }
+    if fsutil::exists(target) {
+        return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+    }
+    let partial = partial_of(target);
+
+    let copied = (|| {
+        let mut resume = true;
+        loop {
+            let (hash, continued) = copy(source, &partial, &meta, resume, how)?;
+            let copy_len = fs::metadata(fsutil::long(&partial))?.len();
+            if copy_len == meta.len() && hash_file(&partial)? == hash {
+                break;
+            }
+            if !continued {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "copy on the other volume does not match the original",
+                ));
+            }
+            resume = false;
+        }
+        fs::set_permissions(fsutil::long(&partial), meta.permissions())?;
+        // Looked at again, as the copy can take long.
+        if fsutil::exists(target) {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+        }
+        fs::rename(fsutil::long(&partial), fsutil::long(target))
+    })();
+
+    if let Err(e) = copied {
+        if !share::is_dropped(&e) {
+            let _ = fs::remove_file(fsutil::long(&partial));
+        }
+        return Err(e);
+    }
+    fs::remove_file(fsutil::long(source))
+}
+
+/// Copies `source`, described by `meta`, into `partial`, after what is
+/// already there when `resume` is set, returning the SHA-256 of the
+/// original and whether an earlier copy was continued.
+fn copy(
+    source: &Path,
+    partial: &Path,
+    meta: &Metadata,
+    resume: bool,
+    how: CrossVolume,
+) -> io::Result<(Vec<u8>, bool)> {
+    let total = meta.len();
+    let mut input = File::open(fsutil::long(source))?;
+    let mut output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(fsutil::long(partial))?;
+    let existing = output.metadata()?.len();
+    let start = if resume && existing <= total {
+        existing
+    } else {
+        0
+    };
+    output.set_len(start)?;
+    output.seek(SeekFrom::Start(start))?;
+    let sparse = platform::is_sparse(meta) && platform::make_sparse(&output).is_ok();
+
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut done = 0;
+    // What is already copied is read from the original again, for its hash.
+    let mut head = (&mut input).take(start);
+    loop {
+        let n = head.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        throttle::pace(n);
+        done += n as u64;
+    }
+    if done != start {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the original is shorter than when the copy began",
+        ));
+    }
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if sparse {
+            for block in buf[..n].chunks(SPARSE_BLOCK) {
+                if block.iter().all(|&b| b == 0) {
+                    output.seek(SeekFrom::Current(block.len() as i64))?;
+                } else {
+                    output.write_all(block)?;
+                }
+            }
+        } else {
+            output.write_all(&buf[..n])?;
+        }
+        hasher.update(&buf[..n]);
+        // Read and written.
+        throttle::pace(2 * n);
+        done += n as u64;
+        (how.progress)(done, total);
+    }
+    // A hole at the end is only there once the length says so.
+    output.set_len(done)?;
+    if how.preserve_times {
+        let mut times = FileTimes::new().set_modified(meta.modified()?);
+        if let Ok(accessed) = meta.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        output.set_times(times)?;
+    }
+    output.sync_all()?;
+    Ok((hasher.finalize().to_vec(), start > 0))
+}
+
+/// Renames `source` to `target`, falling back to [`copy_and_delete`] when they
+/// are on different volumes and `cross_volume` allows it.
+pub(crate) fn move_file(
+    source: &Path,
+    target: &Path,
+    cross_volume: Option<CrossVolume>,
+) -> io::Result<()> {
+    match fsutil::rename(source, target) {
+        Err(e) if is_cross_device(&e) => match cross_volume {
+            Some(how) => copy_and_delete(source, target, how),
+            None => Err(e),
+        },
+        result => result,
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs::{File, Metadata};
+    use std::io;
+    use std::os::unix::fs::MetadataExt;
+
+    /// Fewer blocks are stored than the length takes: the file has holes.
+    pub fn is_sparse(meta: &Metadata) -> bool {
+        meta.blocks().saturating_mul(512) < meta.len()
+    }
+
+    /// Unix filesystems leave what is seeked over unwritten as a hole.
+    pub fn make_sparse(_file: &File) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs::{File, Metadata};
+    use std::io;
+    use std::os::windows::fs::MetadataExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+
+    const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x200;
+    const FSCTL_SET_SPARSE: u32 = 0x0009_00C4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn DeviceIoControl(
+            device: *mut std::ffi::c_void,
+            code: u32,
+            input: *const std::ffi::c_void,
+            input_size: u32,
+            output: *mut std::ffi::c_void,
+            output_size: u32,
+            returned: *mut u32,
+            overlapped: *mut std::ffi::c_void,
+        ) -> i32;
+    }
+
+    pub fn is_sparse(meta: &Metadata) -> bool {
+        meta.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE != 0
+    }
+
+    /// NTFS only leaves holes in files marked sparse; in others, what is
+    /// seeked over is written as zeros.
+    pub fn make_sparse(file: &File) -> io::Result<()> {
+        let mut returned = 0;
+        // SAFETY: the handle is open for the call, which takes no buffers.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle().cast(),
+                FSCTL_SET_SPARSE,
+                ptr::null(),
+                0,
+                ptr::null_mut(),
+                0,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::fs::{File, Metadata};
+    use std::io;
+
+    pub fn is_sparse(_meta: &Metadata) -> bool {
+        false
+    }
+
+    pub fn make_sparse(_file: &File) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
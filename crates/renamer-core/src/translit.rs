@@ -0,0 +1,198 @@
+//! Transliteration of file names to plain ASCII, for files headed for
+//! systems and tools that cannot take anything else: `Łódź_фото.jpg`
+//! becomes `Lodz_foto.jpg`.
+//!
+//! Accents and other marks are dropped by decomposing each letter (NFD), and
+//! letters that do not decompose (`ł`, `ß`, `ø`) and the Cyrillic and Greek
+//! alphabets are spelled out from tables. A few languages spell some letters
+//! their own way, and a BCP 47 tag picks those: German writes `ü` as `ue`,
+//! Danish and Norwegian `å` as `aa`, Ukrainian `г` as `h`, Bulgarian `щ` as
+//! `sht`.
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Language {
+    #[default]
+    Other,
+    German,
+    /// Danish and Norwegian.
+    Nordic,
+    Ukrainian,
+    Bulgarian,
+}
+
+/// Language rules that change how individual letters are spelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TranslitLocale {
+    language: Language,
+}
+
+impl TranslitLocale {
+    /// Parses a BCP 47 tag such as `de` or `uk-UA`; unknown tags fall back
+    /// to the default spellings.
+    pub fn from_tag(tag: Option<&str>) -> Self {
+        let lang = tag
+            .and_then(|t| t.split(['-', '_']).next())
+            .map(str::to_ascii_lowercase);
+        let language = match lang.as_deref() {
+            Some("de") => Language::German,
+            Some("da" | "no" | "nb" | "nn") => Language::Nordic,
+            Some("uk") => Language::Ukrainian,
+            Some("bg") => Language::Bulgarian,
+            _ => Language::Other,
+        };
+        TranslitLocale { language }
+    }
+
+    /// Spells `c` onto `out`, capitalized if `c` is, and says if it could.
+    fn push(&self, c: char, out: &mut String) -> bool {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let Some(spelled) = self.spell(lower).or_else(|| spell(lower)) else {
+            return false;
+        };
+        let mut chars = spelled.chars();
+        if c != lower {
+            out.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+        }
+        out.push_str(chars.as_str());
+        true
+    }
+
+    /// The spelling of the lower-case letter `c` in this language, if it has
+    /// one of its own.
+    fn spell(&self, c: char) -> Option<&'static str> {
+        Some(match (self.language, c) {
+            (Language::German, 'ä') => "ae",
+            (Language::German, 'ö') => "oe",
+            (Language::German, 'ü') => "ue",
+            (Language::Nordic, 'å') => "aa",
+            (Language::Nordic, 'ø') => "oe",
+            (Language::Ukrainian, 'г') => "h",
+            (Language::Ukrainian, 'и') => "y",
+            (Language::Bulgarian, 'щ') => "sht",
+            (Language::Bulgarian, 'ъ') => "a",
+            (Language::Bulgarian, 'ь') => "y",
+            (Language::Bulgarian, 'х') => "h",
+            _ => return None,
+        })
+    }
+}
+
+/// The spelling of the lower-case letter or sign `c` where decomposing it
+/// does not give ASCII.
+fn spell(c: char) -> Option<&'static str> {
+    Some(match c {
+        // Latin letters that do not decompose.
+        'ł' => "l",
+        'ø' => "o",
+        'đ' | 'ð' => "d",
+        'þ' => "th",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'ı' => "i",
+        'ħ' => "h",
+        'ŀ' => "l",
+        'ŧ' => "t",
+        'ƒ' => "f",
+        // Cyrillic.
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' | 'ґ' => "g",
+        'д' => "d",
+        'е' => "e",
+        'ё' => "yo",
+        'є' => "ye",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'і' => "i",
+        'ї' => "yi",
+        'й' => "y",
+        'ј' => "j",
+        'к' => "k",
+        'л' => "l",
+        'љ' => "lj",
+        'м' => "m",
+        'н' => "n",
+        'њ' => "nj",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'ћ' => "c",
+        'ђ' => "dj",
+        'у' | 'ў' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'џ' => "dz",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ы' => "y",
+        'э' => "e",
+        'ю' => "yu",
+        'я' => "ya",
+        // Greek, whose accents decompose.
+        'α' => "a",
+        'β' => "v",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' => "e",
+        'ζ' => "z",
+        'η' | 'ι' => "i",
+        'θ' => "th",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' | 'ω' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' | 'ς' => "s",
+        'τ' => "t",
+        'υ' => "y",
+        'φ' => "f",
+        'χ' => "ch",
+        'ψ' => "ps",
+        // Punctuation, kept to what file systems allow.
+        '‘' | '’' | '‚' | '‛' | '“' | '”' | '„' | '«' | '»' | '‹' | '›' => "'",
+        '‐' | '‑' | '‒' | '–' | '—' | '―' | '−' => "-",
+        '…' => "...",
+        '×' => "x",
+        '\u{a0}' | '\u{2007}' | '\u{202f}' => " ",
+        _ => return None,
+    })
+}
+
+/// Spells `s` in ASCII. What has no ASCII spelling, such as Chinese or
+/// emoji, is replaced with `unknown`, or kept if there is none.
+pub fn transliterate(s: &str, locale: TranslitLocale, unknown: Option<&str>) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+        if locale.push(c, &mut out) {
+            continue;
+        }
+        for d in c.nfd().filter(|&d| !is_combining_mark(d)) {
+            if d.is_ascii() {
+                out.push(d);
+            } else if !locale.push(d, &mut out) {
+                match unknown {
+                    Some(unknown) => out.push_str(unknown),
+                    None => out.push(d),
+                }
+            }
+        }
+    }
+    out
+}
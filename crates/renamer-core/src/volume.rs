@@ -0,0 +1,481 @@
+//! What the volume a file is headed for can store. An exFAT SD card refuses
+//! `:` and does not tell `A.jpg` from `a.jpg`, an ext4 disk takes both, and
+//! a name's length is counted in UTF-16 units on one and in bytes on the
+//! other, so the planner checks each target against the volume it is on
+//! instead of against the rules of the platform; see
+//! [`crate::PlanOptions::filesystem`].
+//!
+//! The filesystem is looked up in the mount table on Unix and asked of the
+//! drive on Windows. Whether names are case sensitive is then worked out
+//! from names already on the volume, without writing anything: APFS, NTFS
+//! folders and Samba shares can be set up either way. Everything else
+//! follows from the filesystem.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fsutil;
+use crate::sanitize::TargetFs;
+
+/// Longest name most filesystems store.
+const MAX_NAME: usize = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Volume {
+    /// Where the volume is mounted, e.g. `/media/me/SD` or `E:\`; empty if
+    /// it was not probed.
+    pub mount_point: PathBuf,
+    /// The filesystem as the OS names it, in lowercase, e.g. `ext4`,
+    /// `exfat`, `ntfs` or `cifs`; empty if it is not known.
+    pub filesystem: String,
+    /// The naming rules the volume follows.
+    pub rules: TargetFs,
+    /// `A.jpg` and `a.jpg` are two different files.
+    pub case_sensitive: bool,
+    /// The longest name the volume stores, in UTF-16 units if
+    /// `utf16_names`, in bytes of UTF-8 otherwise.
+    pub max_name_len: usize,
+    pub utf16_names: bool,
+    /// Keeps a journal, so a crash in the middle of a batch leaves every
+    /// file either renamed or not. False where that is not known, as for
+    /// network shares.
+    pub journaled: bool,
+}
+
+impl Volume {
+    /// What a volume following `rules` is taken to be when it is not probed.
+    pub fn assumed(rules: TargetFs) -> Self {
+        let (case_sensitive, utf16_names, journaled) = match rules {
+            TargetFs::Ext4 => (true, false, true),
+            TargetFs::Ntfs => (false, true, true),
+            TargetFs::Exfat => (false, true, false),
+            TargetFs::Smb => (false, true, false),
+        };
+        Volume {
+            mount_point: PathBuf::new(),
+            filesystem: String::new(),
+            rules,
+            case_sensitive,
+            max_name_len: MAX_NAME,
+            utf16_names,
+            journaled,
+        }
+    }
+
+    /// What a volume formatted with `filesystem`, as the OS names it, is
+    /// like. Filesystems not known here are taken to be like the platform's
+    /// own.
+    pub fn of_filesystem(filesystem: &str) -> Self {
+        let filesystem = filesystem.to_ascii_lowercase();
+        let mut volume = match filesystem.as_str() {
+            "ext2" => Volume {
+                journaled: false,
+                ..Volume::assumed(TargetFs::Ext4)
+            },
+            "ext3" | "ext4" | "xfs" | "btrfs" | "zfs" | "jfs" | "reiserfs" | "f2fs" => {
+                Volume::assumed(TargetFs::Ext4)
+            }
+            // eCryptfs keeps names encrypted, which makes them longer.
+            "ecryptfs" => Volume {
+                max_name_len: 143,
+                ..Volume::assumed(TargetFs::Ext4)
+            },
+            "tmpfs" | "ramfs" | "overlay" | "nfs" | "nfs4" | "9p" => Volume {
+                journaled: false,
+                ..Volume::assumed(TargetFs::Ext4)
+            },
+            // Names go through the POSIX layer, which maps `:` for Finder.
+            "apfs" => Volume {
+                case_sensitive: false,
+                ..Volume::assumed(TargetFs::Ext4)
+            },
+            "hfs" => Volume {
+                case_sensitive: false,
+                utf16_names: true,
+                ..Volume::assumed(TargetFs::Ext4)
+            },
+            // `fuseblk` is how ntfs-3g shows up on Linux.
+            "ntfs" | "ntfs3" | "fuseblk" | "refs" => Volume::assumed(TargetFs::Ntfs),
+            "exfat" | "vfat" | "msdos" | "fat" | "fat32" | "fat16" => {
+                Volume::assumed(TargetFs::Exfat)
+            }
+            "cifs" | "smb3" | "smbfs" | "smb" => Volume::assumed(TargetFs::Smb),
+            _ => Volume::assumed(TargetFs::host()),
+        };
+        volume.filesystem = filesystem;
+        volume
+    }
+
+    /// True if a name as long as `name` can be stored.
+    pub fn name_fits(&self, name: &OsStr) -> bool {
+        let name = name.to_string_lossy();
+        let len = if self.utf16_names {
+            name.encode_utf16().count()
+        } else {
+            name.len()
+        };
+        len <= self.max_name_len
+    }
+
+    /// The first character of `name` the volume refuses: one its rules
+    /// forbid anywhere, or a dot or space it refuses at the end.
+    pub fn refused_char(&self, name: &str) -> Option<char> {
+        if let Some(c) = name.chars().find(|&c| self.rules.forbids(c)) {
+            return Some(c);
+        }
+        match name.chars().last() {
+            Some(c @ ('.' | ' ')) if self.rules.windows_rules() && name != "." && name != ".." => {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    /// True for a network share, reached over SMB, NFS, SSH or WebDAV, whose
+    /// moves can stall or drop with the connection.
+    pub fn is_network(&self) -> bool {
+        matches!(
+            self.filesystem.as_str(),
+            "cifs"
+                | "smb3"
+                | "smbfs"
+                | "smb"
+                | "nfs"
+                | "nfs4"
+                | "afpfs"
+                | "webdav"
+                | "davfs"
+                | "fuse.sshfs"
+                | "fuse.rclone"
+        )
+    }
+
+    /// `path` as the volume compares it: in lowercase unless names are
+    /// case sensitive.
+    pub(crate) fn fold(&self, path: &Path) -> PathBuf {
+        if self.case_sensitive {
+            path.to_path_buf()
+        } else {
+            PathBuf::from(path.to_string_lossy().to_lowercase())
+        }
+    }
+}
+
+/// The volume `path` is on, or would be created on: its closest existing
+/// folder's.
+pub fn probe(path: &Path) -> Volume {
+    let mut volumes = Volumes::new(None);
+    let i = volumes.probe(path);
+    volumes.found.swap_remove(i)
+}
+
+/// The volumes of a batch's targets, each probed once.
+pub(crate) struct Volumes {
+    /// Set to take every target to be on such a volume, unprobed.
+    assumed: Option<TargetFs>,
+    mounts: Option<platform::MountTable>,
+    /// The volume of each folder asked about, by its position in `found`.
+    folders: HashMap<PathBuf, usize>,
+    found: Vec<Volume>,
+}
+
+impl Volumes {
+    pub(crate) fn new(assumed: Option<TargetFs>) -> Self {
+        Volumes {
+            assumed,
+            mounts: None,
+            folders: HashMap::new(),
+            found: Vec::new(),
+        }
+    }
+
+    /// The volume of the folder `target` goes in.
+    pub(crate) fn of(&mut self, target: &Path) -> &Volume {
+        let folder = target.parent().unwrap_or(target);
+        if let Some(&i) = self.folders.get(folder) {
+            return &self.found[i];
+        }
+        let i = match self.assumed {
+            Some(rules) => {
+                if self.found.is_empty() {
+                    self.found.push(Volume::assumed(rules));
+                }
+                0
+            }
+            None => self.probe(folder),
+        };
+        self.folders.insert(folder.to_path_buf(), i);
+        &self.found[i]
+    }
+
+    /// True if `path` is on a network share. Only the mount table is read,
+    /// never the share, so a share that stopped answering cannot hold this
+    /// up.
+    pub(crate) fn on_network(&mut self, path: &Path) -> bool {
+        let mounts = self.mounts.get_or_insert_with(platform::MountTable::read);
+        mounts
+            .find(path)
+            .is_some_and(|m| Volume::of_filesystem(&m.filesystem).is_network())
+    }
+
+    fn probe(&mut self, folder: &Path) -> usize {
+        let existing = folder
+            .ancestors()
+            .find(|a| fsutil::exists(a))
+            .unwrap_or(folder);
+        let real = fs::canonicalize(fsutil::long(existing)).unwrap_or_else(|_| existing.into());
+        let mount = self
+            .mounts
+            .get_or_insert_with(platform::MountTable::read)
+            .find(&real);
+        let point = mount.as_ref().map(|m| m.point.clone()).unwrap_or_default();
+        if let Some(i) = self.found.iter().position(|v| v.mount_point == point) {
+            return i;
+        }
+        let mut volume = match &mount {
+            Some(mount) => Volume::of_filesystem(&mount.filesystem),
+            None => Volume::assumed(TargetFs::host()),
+        };
+        if let Some(sensitive) = probe_case(&real, &point) {
+            volume.case_sensitive = sensitive;
+        }
+        volume.mount_point = point;
+        self.found.push(volume);
+        self.found.len() - 1
+    }
+
+    /// The volumes asked about, in the order they were first.
+    pub(crate) fn into_vec(self) -> Vec<Volume> {
+        self.found
+    }
+}
+
+/// Whether names in `folder`'s volume are case sensitive, judged by a name
+/// below its mount point `mount` that looks different in another case. `None`
+/// if there is none.
+fn probe_case(folder: &Path, mount: &Path) -> Option<bool> {
+    let inside = folder
+        .ancestors()
+        .take_while(|a| *a != mount && a.starts_with(mount) && a.parent().is_some())
+        .map(Path::to_path_buf);
+    let entries = fs::read_dir(fsutil::long(folder))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .take(32)
+        .map(|e| e.path());
+    inside
+        .chain(entries)
+        .find_map(|path| case_sensitive_at(&path))
+}
+
+/// Whether the volume of `path`, which exists, tells its name apart from
+/// the same name in another case; `None` if the name has no letters that
+/// change.
+fn case_sensitive_at(path: &Path) -> Option<bool> {
+    let name = path.file_name()?.to_str()?;
+    let swapped: String = name
+        .chars()
+        .map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                c.to_uppercase().next().unwrap_or(c)
+            }
+        })
+        .collect();
+    if swapped == name {
+        return None;
+    }
+    let other = path.with_file_name(&swapped);
+    // Listed under both spellings, they are two files.
+    Some(fsutil::has_entry_named(&other) || !fsutil::exists(&other))
+}
+
+/// Where a filesystem is mounted, and which one it is.
+struct Mount {
+    point: PathBuf,
+    filesystem: String,
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::path::{Path, PathBuf};
+
+    use super::Mount;
+
+    pub struct MountTable(Vec<Mount>);
+
+    impl MountTable {
+        pub fn read() -> Self {
+            MountTable(mounts())
+        }
+
+        /// The mount `path`, which is canonical, is below; the innermost
+        /// one if they are nested.
+        pub fn find(&mut self, path: &Path) -> Option<Mount> {
+            let mount = self
+                .0
+                .iter()
+                .filter(|m| path.starts_with(&m.point))
+                .max_by_key(|m| m.point.components().count())?;
+            Some(Mount {
+                point: mount.point.clone(),
+                filesystem: mount.filesystem.clone(),
+            })
+        }
+    }
+
+    /// From `/proc/self/mountinfo`: the mount point is the fifth field, the
+    /// filesystem is the first after the `-`.
+    #[cfg(target_os = "linux")]
+    fn mounts() -> Vec<Mount> {
+        let Ok(info) = std::fs::read_to_string("/proc/self/mountinfo") else {
+            return Vec::new();
+        };
+        info.lines()
+            .filter_map(|line| {
+                let (mounted, described) = line.split_once(" - ")?;
+                Some(Mount {
+                    point: PathBuf::from(unescape(mounted.split(' ').nth(4)?)),
+                    filesystem: described.split(' ').next()?.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Spaces and the like are written as `\040`.
+    #[cfg(target_os = "linux")]
+    fn unescape(field: &str) -> String {
+        let bytes = field.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes
+                .get(i + 1..i + 4)
+                .and_then(|d| std::str::from_utf8(d).ok())
+                .and_then(|d| u8::from_str_radix(d, 8).ok());
+            match octal {
+                Some(byte) if bytes[i] == b'\\' => {
+                    out.push(byte);
+                    i += 4;
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// From `mount`, whose lines read `/dev/disk4s1 on /Volumes/SD (exfat,
+    /// local, nodev)`.
+    #[cfg(not(target_os = "linux"))]
+    fn mounts() -> Vec<Mount> {
+        let Ok(out) = std::process::Command::new("mount")
+            .stdin(std::process::Stdio::null())
+            .output()
+        else {
+            return Vec::new();
+        };
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|line| {
+                let (_, rest) = line.split_once(" on ")?;
+                let (point, options) = rest.rsplit_once(" (")?;
+                Some(Mount {
+                    point: PathBuf::from(point),
+                    filesystem: options.split([',', ')']).next()?.trim().to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::collections::HashMap;
+    use std::path::{Component, Path, PathBuf, Prefix};
+    use std::process::{Command, Stdio};
+
+    use super::Mount;
+
+    /// The drives asked about so far, by their root.
+    pub struct MountTable(HashMap<PathBuf, String>);
+
+    impl MountTable {
+        pub fn read() -> Self {
+            MountTable(HashMap::new())
+        }
+
+        pub fn find(&mut self, path: &Path) -> Option<Mount> {
+            let Some(Component::Prefix(prefix)) = path.components().next() else {
+                return None;
+            };
+            let (point, filesystem) = match prefix.kind() {
+                Prefix::Disk(d) | Prefix::VerbatimDisk(d) => {
+                    let point = PathBuf::from(format!("{}:\\", d as char));
+                    let filesystem = match self.0.get(&point) {
+                        Some(filesystem) => filesystem.clone(),
+                        None => {
+                            let filesystem = drive_format(&point)?;
+                            self.0.insert(point.clone(), filesystem.clone());
+                            filesystem
+                        }
+                    };
+                    (point, filesystem)
+                }
+                Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                    let point = format!(
+                        "\\\\{}\\{}\\",
+                        server.to_string_lossy(),
+                        share.to_string_lossy()
+                    );
+                    (PathBuf::from(point), "smb".to_string())
+                }
+                _ => return None,
+            };
+            Some(Mount { point, filesystem })
+        }
+    }
+
+    /// The drive's format as .NET reports it, `NTFS` or `exFAT`; `smb` for
+    /// a mapped network drive.
+    fn drive_format(root: &Path) -> Option<String> {
+        let script = format!(
+            "$d = [System.IO.DriveInfo]::new('{}'); if ($d.DriveType -eq 'Network') {{ 'smb' }} else {{ $d.DriveFormat }}",
+            root.to_string_lossy().replace('\'', "''")
+        );
+        let out = Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .stdin(Stdio::null())
+            .output()
+            .ok()?;
+        let format = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        (out.status.success() && !format.is_empty()).then_some(format)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::path::Path;
+
+    use super::Mount;
+
+    pub struct MountTable;
+
+    impl MountTable {
+        pub fn read() -> Self {
+            MountTable
+        }
+
+        pub fn find(&mut self, _path: &Path) -> Option<Mount> {
+            None
+        }
+    }
+}
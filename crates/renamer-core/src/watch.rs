@@ -0,0 +1,230 @@
+//! Renaming files as they arrive in a folder, with the rules of a preset.
+//!
+//! A file counts as arrived when it is created in the folder or moved into it,
+//! and is renamed once nothing has written to it for [`WatchOptions::settle`],
+//! so downloads and copies still in progress are left alone. Subfolders,
+//! hidden files and partial downloads (`.part`, `.crdownload`) are ignored,
+//! and so are the names the watcher itself produced, which would otherwise be
+//! renamed again. While a watch is paused, files that arrive are left as they
+//! are, even after it resumes.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::error::{Error, Result};
+use crate::executor::{ApplyOptions, ApplyReport, OutcomeStatus};
+use crate::presets::Preset;
+use crate::throttle;
+
+/// Extensions browsers and download tools give files they are still writing.
+const PARTIAL: &[&str] = &["part", "partial", "crdownload", "download", "tmp"];
+
+/// How long the names of renamed files are remembered.
+const FORGET_AFTER: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub folder: PathBuf,
+    pub preset: Preset,
+    pub apply_options: ApplyOptions,
+    pub journal_dir: PathBuf,
+    /// How long a new file must go unchanged before it is renamed.
+    pub settle: Duration,
+}
+
+impl WatchOptions {
+    pub fn new(
+        folder: impl Into<PathBuf>,
+        preset: Preset,
+        journal_dir: impl Into<PathBuf>,
+    ) -> Self {
+        WatchOptions {
+            folder: folder.into(),
+            preset,
+            apply_options: ApplyOptions::default(),
+            journal_dir: journal_dir.into(),
+            settle: Duration::from_secs(2),
+        }
+    }
+}
+
+/// What a [`FolderWatcher`] reports as it works.
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// A batch of arrived files went through the preset's rules.
+    Renamed(ApplyReport),
+    /// Watching or renaming failed; the watcher carries on.
+    Failed(String),
+}
+
+/// A running watch. Dropping it, or calling [`FolderWatcher::stop`], stops
+/// watching and waits for a batch in progress to finish.
+pub struct FolderWatcher {
+    watcher: Option<RecommendedWatcher>,
+    worker: Option<JoinHandle<()>>,
+    paused: Arc<AtomicBool>,
+}
+
+impl FolderWatcher {
+    /// Starts watching `options.folder`, calling `on_event` from a background
+    /// thread.
+    pub fn start(
+        options: WatchOptions,
+        on_event: impl FnMut(WatchEvent) + Send + 'static,
+    ) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|source| Error::Watch {
+            path: options.folder.clone(),
+            source,
+        })?;
+        watcher
+            .watch(&options.folder, RecursiveMode::NonRecursive)
+            .map_err(|source| Error::Watch {
+                path: options.folder.clone(),
+                source,
+            })?;
+        let paused = Arc::new(AtomicBool::new(false));
+        let flag = paused.clone();
+        let worker = thread::spawn(move || run(options, rx, &flag, on_event));
+        Ok(FolderWatcher {
+            watcher: Some(watcher),
+            worker: Some(worker),
+            paused,
+        })
+    }
+
+    /// Stops renaming until [`FolderWatcher::resume`]. Files waiting to
+    /// settle, and those arriving meanwhile, are not renamed.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for FolderWatcher {
+    fn drop(&mut self) {
+        // Dropping the watcher closes the channel, which ends the worker.
+        self.watcher.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Subfolders come through too, but fail the `is_file` check made when the
+/// batch is renamed.
+fn is_candidate(path: &Path) -> bool {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+        return false;
+    };
+    let partial = Path::new(&*name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| PARTIAL.contains(&e.as_str()));
+    !name.starts_with('.') && !partial
+}
+
+fn run(
+    options: WatchOptions,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    paused: &AtomicBool,
+    mut on_event: impl FnMut(WatchEvent),
+) {
+    // Arrived files by when they were last written to.
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    // Names the watcher gave files, by when.
+    let mut ours: HashMap<PathBuf, Instant> = HashMap::new();
+    let tick = (options.settle / 4).clamp(Duration::from_millis(10), Duration::from_millis(250));
+    loop {
+        match events.recv_timeout(tick) {
+            Ok(Ok(event)) => {
+                let now = Instant::now();
+                let arrived = matches!(
+                    event.kind,
+                    EventKind::Create(_)
+                        | EventKind::Modify(ModifyKind::Name(
+                            RenameMode::To | RenameMode::Both | RenameMode::Any
+                        ))
+                );
+                // A rename reported as one event lists the old path first.
+                let paths = match event.kind {
+                    EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => &event.paths[1..],
+                    _ => &event.paths[..],
+                };
+                for path in paths {
+                    if let Some(last) = pending.get_mut(path) {
+                        *last = now;
+                    } else if arrived && !ours.contains_key(path) && is_candidate(path) {
+                        pending.insert(path.clone(), now);
+                    }
+                }
+            }
+            Ok(Err(e)) => on_event(WatchEvent::Failed(e.to_string())),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if paused.load(Ordering::Relaxed) {
+            pending.clear();
+            continue;
+        }
+
+        let now = Instant::now();
+        let mut ready: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, last)| now.duration_since(**last) >= options.settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        if ready.is_empty() {
+            continue;
+        }
+        for path in &ready {
+            pending.remove(path);
+        }
+        ready.retain(|path| path.is_file());
+        ready.sort();
+        ours.retain(|_, at| now.duration_since(*at) < FORGET_AFTER);
+        if let Some(event) = rename(&options, &ready) {
+            if let WatchEvent::Renamed(report) = &event {
+                for outcome in &report.outcomes {
+                    if outcome.status == OutcomeStatus::Renamed {
+                        ours.insert(outcome.target.clone(), now);
+                    }
+                }
+            }
+            on_event(event);
+        }
+    }
+}
+
+/// Runs `paths` through the preset, as background work; `None` if the
+/// rules change nothing.
+fn rename(options: &WatchOptions, paths: &[PathBuf]) -> Option<WatchEvent> {
+    let ran = throttle::background(|| {
+        options
+            .preset
+            .run(paths, &options.apply_options, &options.journal_dir)
+    });
+    match ran {
+        Ok(report) => report.map(WatchEvent::Renamed),
+        Err(e) => Some(WatchEvent::Failed(e.to_string())),
+    }
+}
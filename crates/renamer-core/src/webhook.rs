@@ -0,0 +1,169 @@
+//! Webhooks: URLs told about every batch that finishes, so a media
+//! pipeline can pick up the renamed files. Each gets a JSON
+//! [`JobSummary`] POSTed to it; which batches a hook hears about is set by
+//! its [`WebhookEvent`]s. They are kept in the shared settings, so batches
+//! from the app, its folder watchers and the CLI all reach them.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::executor::{ApplyReport, OutcomeStatus};
+
+/// How long a hook gets to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most failed entries listed in a summary; the counts cover all of them.
+const MAX_ERRORS: usize = 100;
+
+/// Where a batch came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A batch applied by hand, from the app, its queue or the CLI.
+    Batch,
+    /// A batch a folder watcher applied to new files.
+    Watch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// The batches to tell the hook about; all when empty.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Sent with every request, e.g. an `Authorization` header.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Webhook {
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// An entry that failed, in a [`JobSummary`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummaryError {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub error: String,
+}
+
+/// What a webhook is sent about a finished batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSummary {
+    pub event: WebhookEvent,
+    pub batch_id: Uuid,
+    /// The preset the batch used, or the watched folder's.
+    pub preset: Option<String>,
+    pub finished_at: DateTime<Utc>,
+    pub committed: bool,
+    pub cancelled: bool,
+    pub renamed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub rolled_back: usize,
+    pub rollback_failed: usize,
+    pub unverified: usize,
+    /// The first entries that failed or could not be moved back.
+    pub errors: Vec<SummaryError>,
+}
+
+impl JobSummary {
+    pub fn new(event: WebhookEvent, report: &ApplyReport, preset: Option<&str>) -> Self {
+        let errors = report
+            .outcomes
+            .iter()
+            .filter(|o| {
+                matches!(
+                    o.status,
+                    OutcomeStatus::Failed | OutcomeStatus::RollbackFailed
+                )
+            })
+            .take(MAX_ERRORS)
+            .map(|o| SummaryError {
+                source: o.source.clone(),
+                target: o.target.clone(),
+                error: o.error.clone().unwrap_or_default(),
+            })
+            .collect();
+        JobSummary {
+            event,
+            batch_id: report.batch_id,
+            preset: preset.map(str::to_string),
+            finished_at: Utc::now(),
+            committed: report.committed,
+            cancelled: report.cancelled,
+            renamed: report.renamed,
+            skipped: report.skipped,
+            failed: report.failed,
+            rolled_back: report.rolled_back,
+            rollback_failed: report.rollback_failed,
+            unverified: report.unverified,
+            errors,
+        }
+    }
+}
+
+/// A hook that could not be reached or did not take the summary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookFailure {
+    pub url: String,
+    pub message: String,
+}
+
+/// POSTs `summary` to each of `hooks` that wants its event, one after the
+/// other, and returns those that failed. Nothing is retried.
+pub fn send(hooks: &[Webhook], summary: &JobSummary) -> Vec<WebhookFailure> {
+    let hooks: Vec<&Webhook> = hooks.iter().filter(|h| h.wants(summary.event)).collect();
+    if hooks.is_empty() {
+        return Vec::new();
+    }
+    let all_failed = |message: String| {
+        hooks
+            .iter()
+            .map(|h| WebhookFailure {
+                url: h.url.clone(),
+                message: message.clone(),
+            })
+            .collect()
+    };
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("Sortify/", env!("CARGO_PKG_VERSION")))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return all_failed(e.to_string()),
+    };
+    let body = match serde_json::to_vec(summary) {
+        Ok(body) => body,
+        Err(e) => return all_failed(e.to_string()),
+    };
+    let mut failures = Vec::new();
+    for hook in hooks {
+        let mut request = client
+            .post(&hook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        for (name, value) in &hook.headers {
+            request = request.header(name, value);
+        }
+        let message = match request.send() {
+            Ok(response) if response.status().is_success() => continue,
+            Ok(response) => format!("answered {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        failures.push(WebhookFailure {
+            url: hook.url.clone(),
+            message,
+        });
+    }
+    failures
+}
@@ -0,0 +1,164 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use renamer_core::{archive, CancelToken, ErrorCode, OutcomeStatus, RenameOp, ScanOptions};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+fn names(page: &renamer_core::ScanPage) -> Vec<String> {
+    page.entries
+        .iter()
+        .map(|e| e.path.to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn zip_members_are_listed_and_renamed_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("delivery.zip");
+    let mut zip = ZipWriter::new(File::create(&path).unwrap());
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.add_directory("docs/", deflated).unwrap();
+    zip.start_file("docs/Read Me.txt", deflated).unwrap();
+    zip.write_all(&b"read me ".repeat(100)).unwrap();
+    // No entry for `docs/old/` itself.
+    zip.start_file("docs/old/a.txt", deflated).unwrap();
+    zip.write_all(b"a").unwrap();
+    zip.start_file("top.txt", deflated).unwrap();
+    zip.write_all(b"top").unwrap();
+    zip.set_comment("delivered");
+    zip.finish().unwrap();
+
+    let options = ScanOptions {
+        include_dirs: true,
+        ..ScanOptions::default()
+    };
+    let page = archive::scan(&path, &options, &CancelToken::new()).unwrap();
+    assert_eq!(
+        names(&page),
+        [
+            "/docs",
+            "/docs/old",
+            "/docs/old/a.txt",
+            "/docs/Read Me.txt",
+            "/top.txt"
+        ]
+    );
+    assert_eq!(page.entries[3].size, 800);
+    assert!(page.entries[1].is_dir);
+
+    let ops = vec![
+        RenameOp::new("/docs/Read Me.txt", "/docs/readme.txt"),
+        RenameOp::new("/docs/old", "/docs/archive"),
+        RenameOp::new("/top.txt", "/docs/readme.txt"),
+    ];
+    let report = archive::apply(&path, &ops, &CancelToken::new()).unwrap();
+    assert!(report.committed);
+    let statuses: Vec<OutcomeStatus> = report.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            OutcomeStatus::Renamed,
+            OutcomeStatus::Renamed,
+            OutcomeStatus::Skipped
+        ]
+    );
+
+    let mut zip = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+    let stored: Vec<&str> = zip.file_names().collect();
+    assert_eq!(
+        stored,
+        ["docs/", "docs/readme.txt", "docs/archive/a.txt", "top.txt"]
+    );
+    assert_eq!(zip.comment(), b"delivered");
+    let mut readme = zip.by_name("docs/readme.txt").unwrap();
+    assert_eq!(readme.compression(), CompressionMethod::Deflated);
+    let mut text = String::new();
+    readme.read_to_string(&mut text).unwrap();
+    assert_eq!(text, "read me ".repeat(100));
+    let leftovers = std::fs::read_dir(dir.path()).unwrap().count();
+    assert_eq!(leftovers, 1);
+}
+
+#[test]
+fn tar_members_keep_their_links_and_long_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("site.tar.gz");
+    let long = format!("./{}.txt", "x".repeat(120));
+    let mut tar = tar::Builder::new(GzEncoder::new(
+        File::create(&path).unwrap(),
+        flate2::Compression::default(),
+    ));
+    let mut file = |name: &str, data: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        tar.append_data(&mut header, name, data).unwrap();
+    };
+    file("./index.html", b"<h1>hi</h1>");
+    file(&long, b"long");
+    let mut link = tar::Header::new_gnu();
+    link.set_entry_type(tar::EntryType::Link);
+    link.set_size(0);
+    tar.append_link(&mut link, "./home.html", "./index.html")
+        .unwrap();
+    tar.into_inner().unwrap().finish().unwrap();
+
+    let page = archive::scan(&path, &ScanOptions::default(), &CancelToken::new()).unwrap();
+    assert_eq!(page.entries.len(), 3);
+    assert_eq!(
+        page.entries
+            .iter()
+            .find(|e| e.name == "index.html")
+            .and_then(|e| e.modified)
+            .map(|t| t.timestamp()),
+        Some(1_700_000_000)
+    );
+
+    let renamed_long = format!("/{}.md", "y".repeat(120));
+    let ops = vec![
+        RenameOp::new("/index.html", "/start.html"),
+        RenameOp::new(Path::new("/").join(&long[2..]), &renamed_long),
+    ];
+    let report = archive::apply(&path, &ops, &CancelToken::new()).unwrap();
+    assert!(report.committed);
+
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(File::open(&path).unwrap()));
+    let mut seen = Vec::new();
+    for entry in tar.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().to_string_lossy().into_owned();
+        let link = entry
+            .link_name()
+            .unwrap()
+            .map(|l| l.to_string_lossy().into_owned());
+        let mut data = String::new();
+        entry.read_to_string(&mut data).unwrap();
+        seen.push((name, link, data));
+    }
+    assert_eq!(
+        seen,
+        [
+            ("start.html".to_string(), None, "<h1>hi</h1>".to_string()),
+            (format!(".{}", renamed_long), None, "long".to_string()),
+            (
+                "home.html".to_string(),
+                Some("./start.html".to_string()),
+                String::new()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn files_that_are_not_archives_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("photos.7z");
+    std::fs::write(&path, b"7z\xbc\xaf\x27\x1c\x00\x04").unwrap();
+    let err = archive::scan(&path, &ScanOptions::default(), &CancelToken::new()).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Archive);
+    assert!(err.to_string().contains("7z archives are not supported"));
+}
@@ -0,0 +1,90 @@
+use std::fs;
+
+use renamer_core::{
+    apply, plan, ApplyOptions, AuditLog, ChangeKind, HistoryStore, PlanOptions, RenameOp,
+};
+
+#[test]
+fn renames_and_undo_are_chained_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("IMG_0042.jpg");
+    let new = dir.path().join("Beach.jpg");
+    fs::write(&old, "jpg").unwrap();
+    let log = AuditLog::new(dir.path().join("data/audit.jsonl"));
+    assert!(log.verify().unwrap().intact);
+
+    let report = apply(
+        &plan(vec![RenameOp::new(&old, &new)], &PlanOptions::default()),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    assert_eq!(log.record(&report).unwrap(), 1);
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+    log.record_replay(&store.undo_last().unwrap().unwrap(), true)
+        .unwrap();
+
+    let entries = log.entries().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].kind, ChangeKind::Renamed);
+    assert_eq!(entries[1].kind, ChangeKind::Undone);
+    assert_eq!(entries[1].seq, 2);
+    assert_eq!(entries[1].prev_hash, entries[0].hash);
+
+    let verified = log.verify().unwrap();
+    assert!(verified.intact);
+    assert_eq!(verified.entries, 2);
+    assert_eq!(verified.last_hash.as_ref(), Some(&entries[1].hash));
+}
+
+fn log_with_three(dir: &std::path::Path) -> AuditLog {
+    let log = AuditLog::new(dir.join("audit.jsonl"));
+    let ops = ["a", "b", "c"]
+        .iter()
+        .map(|name| {
+            let path = dir.join(format!("{}.txt", name));
+            fs::write(&path, name).unwrap();
+            RenameOp::new(&path, dir.join(format!("{}-renamed.txt", name)))
+        })
+        .collect();
+    let report = apply(
+        &plan(ops, &PlanOptions::default()),
+        &ApplyOptions::default(),
+        &dir.join("j"),
+    )
+    .unwrap();
+    assert_eq!(log.record(&report).unwrap(), 3);
+    log
+}
+
+#[test]
+fn edited_removed_or_reordered_lines_break_the_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = log_with_three(dir.path());
+    let original = fs::read_to_string(log.path()).unwrap();
+    let lines: Vec<&str> = original.lines().collect();
+
+    // An edited target.
+    let edited = original.replacen("b-renamed", "b-other", 1);
+    fs::write(log.path(), &edited).unwrap();
+    let verified = log.verify().unwrap();
+    assert!(!verified.intact);
+    assert_eq!(verified.broken_at, Some(2));
+
+    // A line taken out.
+    fs::write(log.path(), format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    assert_eq!(log.verify().unwrap().broken_at, Some(2));
+
+    // Two lines swapped.
+    fs::write(
+        log.path(),
+        format!("{}\n{}\n{}\n", lines[1], lines[0], lines[2]),
+    )
+    .unwrap();
+    assert_eq!(log.verify().unwrap().broken_at, Some(1));
+
+    // Put back, it checks out, and later changes follow on.
+    fs::write(log.path(), &original).unwrap();
+    assert!(log.verify().unwrap().intact);
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use renamer_core::{
+    find_bursts, preview_bursts, BurstAction, BurstOptions, BurstShot, Preset, Rule,
+};
+
+/// A JPEG whose embedded XMP says it was taken at `taken`.
+fn write_shot(path: &Path, taken: &str) {
+    let xmp = format!(
+        concat!(
+            "\u{ff}\u{d8}<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF><rdf:Description ",
+            "exif:DateTimeOriginal=\"{}\"/></rdf:RDF></x:xmpmeta>",
+        ),
+        taken
+    );
+    fs::write(path, xmp).unwrap();
+}
+
+fn numbered() -> Vec<Rule> {
+    vec![Rule::Template {
+        template: "IMG_{counter:4}.{ext}".into(),
+    }]
+}
+
+#[test]
+fn shots_within_seconds_share_a_number_and_get_letters() {
+    let dir = tempfile::tempdir().unwrap();
+    let shots = [
+        ("p.jpg", "2024-05-01T10:00:00"),
+        ("q.jpg", "2024-05-01T10:00:02"),
+        ("r.jpg", "2024-05-01T10:05:00"),
+        ("s.jpg", "2024-05-01T10:00:03"),
+    ];
+    let mut paths: Vec<PathBuf> = shots
+        .iter()
+        .map(|(name, taken)| {
+            let path = dir.path().join(name);
+            write_shot(&path, taken);
+            path
+        })
+        .collect();
+    let undated = dir.path().join("t.jpg");
+    fs::write(&undated, "no date").unwrap();
+    paths.push(undated);
+
+    let options = BurstOptions {
+        within_secs: Some(2),
+        ..BurstOptions::default()
+    };
+    let bursts = find_bursts(&paths, &options);
+    assert_eq!(bursts.len(), 1);
+    assert_eq!(
+        bursts[0].paths,
+        [paths[0].clone(), paths[1].clone(), paths[3].clone()]
+    );
+
+    let rows = preview_bursts(&paths, &numbered(), &options).unwrap();
+    let names: Vec<_> = rows.iter().map(|r| r.new_name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "IMG_0001a.jpg",
+            "IMG_0001b.jpg",
+            "IMG_0002.jpg",
+            "IMG_0001c.jpg",
+            "IMG_0003.jpg"
+        ]
+    );
+    assert_eq!(
+        rows[3].burst,
+        Some(BurstShot {
+            group: 0,
+            shot: 2,
+            skipped: false
+        })
+    );
+    assert_eq!(rows[2].burst, None);
+
+    // Without the pass, every file takes a number of its own.
+    let plain = preview_bursts(&paths, &numbered(), &BurstOptions::default()).unwrap();
+    assert_eq!(plain[3].new_name, "IMG_0004.jpg");
+    assert!(plain.iter().all(|r| r.burst.is_none()));
+}
+
+#[test]
+fn copies_can_be_skipped_so_only_the_first_is_renamed() {
+    let dir = tempfile::tempdir().unwrap();
+    let names = ["a.png", "b.png", "c.png", "d.png"];
+    let contents = ["one", "two", "one", "three"];
+    let paths: Vec<PathBuf> = names
+        .iter()
+        .zip(contents)
+        .map(|(name, contents)| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        })
+        .collect();
+    let options = BurstOptions {
+        same_content: true,
+        action: BurstAction::Skip,
+        ..BurstOptions::default()
+    };
+
+    let rows = preview_bursts(&paths, &numbered(), &options).unwrap();
+    let names: Vec<_> = rows.iter().map(|r| r.new_name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["IMG_0001.png", "IMG_0002.png", "c.png", "IMG_0003.png"]
+    );
+    assert!(!rows[2].changed);
+    assert_eq!(rows[2].target, paths[2]);
+    assert_eq!(
+        rows[2].burst,
+        Some(BurstShot {
+            group: 0,
+            shot: 1,
+            skipped: true
+        })
+    );
+    assert!(rows[0].burst.is_some_and(|b| !b.skipped));
+
+    // Presets run with the pass from their planning options.
+    let mut preset = Preset::new("Numbered", numbered());
+    preset.plan_options.bursts = options;
+    let journal = tempfile::tempdir().unwrap();
+    let report = preset
+        .run(&paths, &Default::default(), journal.path())
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.renamed, 3);
+    assert!(paths[2].exists());
+    assert!(dir.path().join("IMG_0003.png").exists());
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use renamer_core::{hash_file, FileCache, HashAlgorithm};
+
+fn set_modified(path: &Path, at: SystemTime) {
+    let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+    file.set_modified(at).unwrap();
+}
+
+#[test]
+fn answers_from_the_cache_until_the_file_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = dir.path().join("photo.jpg");
+    fs::write(&photo, "abc").unwrap();
+    let stamp = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    set_modified(&photo, stamp);
+
+    let cache = FileCache::open(dir.path().join("cache").join("files.db")).unwrap();
+    let abc = cache.hash(&photo, HashAlgorithm::Md5).unwrap();
+    assert_eq!(abc, hash_file(&photo, HashAlgorithm::Md5).unwrap());
+
+    // Same size and time: the stale digest is what the cache remembers.
+    fs::write(&photo, "xyz").unwrap();
+    set_modified(&photo, stamp);
+    assert_eq!(cache.hash(&photo, HashAlgorithm::Md5).unwrap(), abc);
+    assert_ne!(cache.hash(&photo, HashAlgorithm::Sha256).unwrap(), abc);
+
+    // A new modification time reads the file again.
+    set_modified(&photo, stamp + Duration::from_secs(1));
+    let xyz = cache.hash(&photo, HashAlgorithm::Md5).unwrap();
+    assert_eq!(xyz, hash_file(&photo, HashAlgorithm::Md5).unwrap());
+    assert_ne!(xyz, abc);
+
+    // Entries follow a rename and survive reopening.
+    let renamed = dir.path().join("renamed.jpg");
+    fs::rename(&photo, &renamed).unwrap();
+    cache.moved([(photo.as_path(), renamed.as_path())]).unwrap();
+    drop(cache);
+    let cache = FileCache::open(dir.path().join("cache").join("files.db")).unwrap();
+    fs::write(&renamed, "abc").unwrap();
+    set_modified(&renamed, stamp + Duration::from_secs(1));
+    assert_eq!(cache.hash(&renamed, HashAlgorithm::Md5).unwrap(), xyz);
+    assert_eq!(cache.metadata(&renamed).path, renamed);
+
+    cache.clear().unwrap();
+    assert_eq!(cache.hash(&renamed, HashAlgorithm::Md5).unwrap(), abc);
+    assert!(cache.hash(&photo, HashAlgorithm::Md5).is_err());
+}
+
+#[test]
+fn keeps_lookups_by_kind_until_cleared() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("files.db");
+    let cache = FileCache::open(&path).unwrap();
+    assert_eq!(cache.lookup("suggestion", "abc"), None);
+
+    cache.store_lookup("suggestion", "abc", "[1]").unwrap();
+    cache.store_lookup("suggestion", "abc", "[2]").unwrap();
+    cache.store_lookup("other", "abc", "[3]").unwrap();
+    drop(cache);
+    let cache = FileCache::open(&path).unwrap();
+    assert_eq!(cache.lookup("suggestion", "abc").as_deref(), Some("[2]"));
+
+    cache.clear_lookups("suggestion").unwrap();
+    assert_eq!(cache.lookup("suggestion", "abc"), None);
+    assert_eq!(cache.lookup("other", "abc").as_deref(), Some("[3]"));
+    cache.clear().unwrap();
+    assert_eq!(cache.lookup("other", "abc"), None);
+}
@@ -0,0 +1,26 @@
+use renamer_core::case::{convert, CaseLocale, CaseMode};
+
+fn default(s: &str, mode: CaseMode) -> String {
+    convert(s, mode, CaseLocale::default())
+}
+
+#[test]
+fn word_based_modes() {
+    let input = "my holidayPhoto-2024";
+    assert_eq!(default(input, CaseMode::Camel), "myHolidayPhoto2024");
+    assert_eq!(default(input, CaseMode::Snake), "my_holiday_photo_2024");
+    assert_eq!(default(input, CaseMode::Kebab), "my-holiday-photo-2024");
+    assert_eq!(default("the CAT's hat", CaseMode::Title), "The Cat's Hat");
+    assert_eq!(default("THE cat. x", CaseMode::Sentence), "The cat. x");
+}
+
+#[test]
+fn unicode_special_cases() {
+    assert_eq!(default("straße", CaseMode::Upper), "STRASSE");
+    assert_eq!(default("ÉCOLE", CaseMode::Lower), "école");
+
+    let tr = CaseLocale::from_tag(Some("tr-TR"));
+    assert_eq!(convert("istanbul", CaseMode::Upper, tr), "İSTANBUL");
+    assert_eq!(convert("DIŞ İŞLERİ", CaseMode::Lower, tr), "dış işleri");
+    assert_eq!(convert("ırmak", CaseMode::Title, tr), "Irmak");
+}
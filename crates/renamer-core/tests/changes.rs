@@ -0,0 +1,42 @@
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use renamer_core::{preview, Rule, SourceChanges, SourceWatcher};
+
+#[test]
+fn files_removed_or_taking_a_target_name_make_rows_stale() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let (a, b, c) = (root.join("a.txt"), root.join("b.txt"), root.join("c.txt"));
+    for path in [&a, &b, &c] {
+        fs::write(path, "x").unwrap();
+    }
+    let rows = preview(
+        &[a.clone(), b.clone(), c.clone()],
+        &[Rule::Prefix { text: "x-".into() }],
+    )
+    .unwrap();
+    let (tx, rx) = mpsc::channel();
+    let watcher = SourceWatcher::start(&[a.clone(), b.clone(), c.clone()], move |changes| {
+        let _ = tx.send(changes);
+    })
+    .unwrap();
+
+    fs::remove_file(&a).unwrap();
+    fs::write(root.join("x-b.txt"), "y").unwrap();
+    fs::write(root.join(".c.txt.sortify-1.tmp"), "ours").unwrap();
+
+    let mut seen = SourceChanges::default();
+    while !(seen.removed.contains(&a) && seen.added.contains(&root.join("x-b.txt"))) {
+        let changes = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        seen.added.extend(changes.added);
+        seen.removed.extend(changes.removed);
+    }
+    drop(watcher);
+    assert!(seen
+        .added
+        .iter()
+        .all(|p| !p.to_string_lossy().contains("sortify")));
+    assert_eq!(seen.stale_rows(&rows), [0, 1]);
+}
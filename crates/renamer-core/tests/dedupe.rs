@@ -0,0 +1,99 @@
+use std::fs;
+use std::sync::Mutex;
+
+use renamer_core::{
+    find_duplicates, find_duplicates_with_progress, CancelToken, DedupeOptions, DedupeStage,
+};
+
+#[test]
+fn groups_files_with_identical_contents() {
+    let dir = tempfile::tempdir().unwrap();
+    let big = vec![7u8; 100_000];
+    let mut changed_tail = big.clone();
+    *changed_tail.last_mut().unwrap() = 8;
+    let files: [(&str, &[u8]); 7] = [
+        ("a.bin", &big),
+        ("b.txt", b"hello"),
+        ("c.bin", &changed_tail),
+        ("copy of a.bin", &big),
+        ("d.txt", b"hellO"),
+        ("empty1", b""),
+        ("empty2", b""),
+    ];
+    let mut paths: Vec<_> = files
+        .iter()
+        .map(|(name, contents)| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        })
+        .collect();
+    fs::write(dir.path().join("b copy.txt"), "hello").unwrap();
+    paths.push(dir.path().join("b copy.txt"));
+    paths.push(dir.path().to_path_buf());
+    paths.push(dir.path().join("missing.txt"));
+
+    let groups = find_duplicates(&paths, &CancelToken::new());
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].paths, [paths[0].clone(), paths[3].clone()]);
+    assert_eq!(groups[0].size, 100_000);
+    assert_eq!(groups[1].paths, [paths[1].clone(), paths[7].clone()]);
+    assert_eq!(
+        groups[1].hash,
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    assert!(find_duplicates(&paths, &cancel).is_empty());
+}
+
+#[test]
+fn the_quick_round_reads_both_ends_and_the_full_round_can_be_left_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = vec![1u8; 300_000];
+    let mut middle = original.clone();
+    middle[150_000] = 2;
+    let mut end = original.clone();
+    *end.last_mut().unwrap() = 2;
+    let paths: Vec<_> = [
+        ("a", &original),
+        ("b", &middle),
+        ("c", &end),
+        ("d", &original),
+    ]
+    .iter()
+    .map(|(name, contents)| {
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    })
+    .collect();
+
+    let events = Mutex::new(Vec::new());
+    let groups = find_duplicates_with_progress(
+        &paths,
+        &DedupeOptions::default(),
+        &|p| events.lock().unwrap().push(p.clone()),
+        &CancelToken::new(),
+    );
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].paths, [paths[0].clone(), paths[3].clone()]);
+    let events = events.into_inner().unwrap();
+    let last = |stage| events.iter().rfind(|p| p.stage == stage).unwrap();
+    assert_eq!(last(DedupeStage::Size).total, 4);
+    // The file differing at its end is ruled out by the samples, the one
+    // differing in the middle only by the full hash.
+    assert_eq!(last(DedupeStage::Sample).total, 4);
+    assert_eq!(last(DedupeStage::Full).total, 3);
+    assert_eq!(last(DedupeStage::Full).completed, 3);
+
+    let quick = DedupeOptions {
+        full_hash: false,
+        ..DedupeOptions::default()
+    };
+    let groups = find_duplicates_with_progress(&paths, &quick, &|_| {}, &CancelToken::new());
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].paths.len(), 3);
+    assert_eq!(groups[0].hash.len(), 16);
+}
@@ -0,0 +1,24 @@
+use std::fs;
+
+use renamer_core::environment;
+use renamer_core::OcrCommand;
+
+#[test]
+fn a_long_path_is_written_and_cleaned_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let feature = environment::long_paths(dir.path());
+    assert!(feature.available, "{}", feature.detail);
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn a_missing_ocr_program_is_unavailable() {
+    let command = OcrCommand {
+        program: "no-such-ocr-program".into(),
+        ..OcrCommand::default()
+    };
+    let feature = environment::ocr(&command);
+    assert!(!feature.available);
+    assert!(feature.detail.contains("no-such-ocr-program"));
+    assert!(environment::find_program("no-such-ocr-program").is_none());
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use renamer_core::episode::{parse, Episode};
+use renamer_core::{FileContext, Template};
+
+fn episode(show: &str, season: u32, episode: u32) -> Episode {
+    Episode {
+        show: show.into(),
+        season: Some(season),
+        episode: Some(episode),
+        year: None,
+        title: None,
+    }
+}
+
+#[test]
+fn reads_episode_markers() {
+    assert_eq!(
+        parse("The.Office.US.S02E01.720p.WEB-DL.x264-GROUP.mkv"),
+        Some(episode("The Office US", 2, 1))
+    );
+    assert_eq!(
+        parse("Breaking_Bad_s05e14e15_1080p.mkv"),
+        Some(episode("Breaking Bad", 5, 14))
+    );
+    assert_eq!(
+        parse("[SubGroup] Frasier 3x07 [480p].avi"),
+        Some(episode("Frasier", 3, 7))
+    );
+    assert_eq!(
+        parse("Lost - Season 1 Episode 4.mp4"),
+        Some(episode("Lost", 1, 4))
+    );
+    assert_eq!(
+        parse("Doctor Who (2005) - S01E06 - Dalek.mkv"),
+        Some(Episode {
+            year: Some(2005),
+            title: Some("Dalek".into()),
+            ..episode("Doctor Who", 1, 6)
+        })
+    );
+    assert_eq!(
+        parse("Mr. Robot S01E01 eps1.0_hellofriend.mov HDTV.mkv").map(|e| e.show),
+        Some("Mr. Robot".into())
+    );
+}
+
+#[test]
+fn reads_movies_by_their_year() {
+    let movie = |show: &str, year: u32| Episode {
+        show: show.into(),
+        season: None,
+        episode: None,
+        year: Some(year),
+        title: None,
+    };
+    assert_eq!(parse("Heat (1995).mkv"), Some(movie("Heat", 1995)));
+    assert_eq!(
+        parse("Blade.Runner.2049.2017.2160p.UHD.BluRay.x265-GROUP.mkv"),
+        Some(movie("Blade Runner 2049", 2017))
+    );
+    assert_eq!(
+        parse("2001 A Space Odyssey (1968) [1080p].mp4"),
+        Some(movie("2001 A Space Odyssey", 1968))
+    );
+    // Resolutions and numbers are not episodes or years.
+    assert_eq!(parse("holiday 1920x1080.mp4"), None);
+    assert_eq!(parse("IMG_0001.jpg"), None);
+    assert_eq!(parse("1999.mp3"), None);
+}
+
+#[test]
+fn episode_tokens_render_padded_with_fallbacks() {
+    let template =
+        Template::parse("{show} - S{season:02|00}E{episode:02|00} - {episode_title|Episode}.{ext}")
+            .unwrap();
+    let ctx = FileContext::new(Path::new("/tv/a.mkv"), 0);
+    assert_eq!(
+        template.render("the.expanse.3x05.triple.point.720p.hdtv.mkv", &ctx),
+        "the expanse - S03E05 - triple point.mkv"
+    );
+    assert_eq!(
+        template.render("Heat (1995).mkv", &ctx),
+        "Heat - S00E00 - Episode.mkv"
+    );
+    assert!(Template::parse("{show:2}").is_err());
+    assert!(Template::parse("{season:two}").is_err());
+}
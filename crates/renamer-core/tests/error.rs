@@ -0,0 +1,50 @@
+use std::io;
+
+use renamer_core::{Error, ErrorCode, StaleReason};
+use serde_json::json;
+
+#[test]
+fn io_errors_are_coded_by_their_cause() {
+    let missing = Error::io("/photos/a.jpg", io::Error::from(io::ErrorKind::NotFound));
+    assert_eq!(missing.code(), ErrorCode::NotFound);
+    assert_eq!(missing.params()["path"], "/photos/a.jpg");
+    assert!(missing.params().contains_key("detail"));
+
+    let denied = Error::io("a", io::Error::from(io::ErrorKind::PermissionDenied));
+    assert_eq!(denied.code(), ErrorCode::PermissionDenied);
+
+    let other = Error::io("a", io::Error::other("disk on fire"));
+    assert_eq!(other.code(), ErrorCode::Io);
+}
+
+#[test]
+fn codes_and_params_name_what_a_translation_needs() {
+    let error = Error::UnsupportedVersion {
+        path: "settings.json".into(),
+        version: 7,
+    };
+    assert_eq!(error.code(), ErrorCode::UnsupportedVersion);
+    assert_eq!(error.params()["version"], "7");
+    assert_eq!(
+        serde_json::to_value(error.code()).unwrap(),
+        json!("unsupported_version")
+    );
+
+    assert!(Error::PlanBlocked.params().is_empty());
+
+    let stalled = Error::ShareUnreachable {
+        path: "/mnt/nas/a.jpg".into(),
+        secs: 30,
+    };
+    assert_eq!(stalled.code(), ErrorCode::ShareUnreachable);
+    assert_eq!(stalled.params()["secs"], "30");
+    assert_eq!(stalled.params()["path"], "/mnt/nas/a.jpg");
+
+    let stale = Error::Stale {
+        path: "/photos/a.jpg".into(),
+        reason: StaleReason::TargetTaken,
+    };
+    assert_eq!(stale.code(), ErrorCode::Stale);
+    assert_eq!(stale.params()["reason"], "target_taken");
+    assert_eq!(stale.params()["path"], "/photos/a.jpg");
+}
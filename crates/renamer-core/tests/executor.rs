@@ -0,0 +1,624 @@
+use std::fs;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use renamer_core::{
+    apply, apply_with_progress, plan, ApplyOptions, CancelToken, Error, HistoryStore, Journal,
+    OutcomeStatus, PlanOptions, Recovery, RenameOp, StaleReason,
+};
+
+#[test]
+fn renames_ready_entries_and_creates_parents() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let target = dir.path().join("nested/dir/b.txt");
+
+    let report = apply(
+        &plan(vec![RenameOp::new(&a, &target)], &PlanOptions::default()),
+        &ApplyOptions::default(),
+        &journal,
+    )
+    .unwrap();
+
+    assert!(report.committed);
+    assert_eq!(report.renamed, 1);
+    assert_eq!(report.outcomes[0].status, OutcomeStatus::Renamed);
+    assert!(report.outcomes[0].verified);
+    assert_eq!(report.unverified, 0);
+    assert!(!a.exists());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "a");
+    assert_eq!(fs::read_dir(&journal).unwrap().count(), 0);
+}
+
+#[test]
+fn safe_mode_refuses_the_batch_before_touching_anything() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let options = ApplyOptions {
+        safe_mode: true,
+        ..ApplyOptions::default()
+    };
+
+    let result = apply(
+        &plan(
+            vec![RenameOp::new(&a, dir.path().join("b.txt"))],
+            &PlanOptions::default(),
+        ),
+        &options,
+        &journal,
+    );
+    assert!(matches!(result, Err(Error::SafeMode)));
+    assert!(a.exists());
+    assert!(!journal.exists());
+}
+
+#[test]
+fn skips_entries_that_are_not_ready() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let taken = dir.path().join("taken.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&taken, "t").unwrap();
+
+    let report = apply(
+        &plan(vec![RenameOp::new(&a, &taken)], &PlanOptions::default()),
+        &ApplyOptions::default(),
+        &dir.path().join("journal"),
+    )
+    .unwrap();
+
+    assert_eq!(report.skipped, 1);
+    assert!(!report.outcomes[0].verified);
+    assert_eq!(fs::read_to_string(&taken).unwrap(), "t");
+    assert!(a.exists());
+}
+
+#[test]
+fn rolls_back_when_a_rename_fails_midway() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    let c = dir.path().join("c.txt");
+    for p in [&a, &b, &c] {
+        fs::write(p, "x").unwrap();
+    }
+
+    let batch = plan(
+        vec![
+            RenameOp::new(&a, dir.path().join("out/a2.txt")),
+            RenameOp::new(&b, dir.path().join("b2.txt")),
+            RenameOp::new(&c, dir.path().join("c2.txt")),
+        ],
+        &PlanOptions::default(),
+    );
+    // Source disappears between planning and applying.
+    fs::remove_file(&b).unwrap();
+
+    let report = apply(&batch, &ApplyOptions::default(), &journal).unwrap();
+
+    assert!(!report.committed);
+    let statuses: Vec<_> = report.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            OutcomeStatus::RolledBack,
+            OutcomeStatus::Failed,
+            OutcomeStatus::Aborted,
+        ]
+    );
+    assert!(a.exists());
+    assert!(c.exists());
+    assert!(!dir.path().join("out").exists());
+    assert_eq!(fs::read_dir(&journal).unwrap().count(), 0);
+}
+
+#[test]
+fn rechecking_leaves_out_entries_that_changed_since_the_plan() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let names = ["gone", "modified", "taken", "fine"];
+    let sources: Vec<_> = names
+        .iter()
+        .map(|n| dir.path().join(format!("{}.txt", n)))
+        .collect();
+    for p in &sources {
+        fs::write(p, "x").unwrap();
+    }
+    let targets: Vec<_> = names
+        .iter()
+        .map(|n| dir.path().join(format!("{}-2.txt", n)))
+        .collect();
+    let batch = plan(
+        sources
+            .iter()
+            .zip(&targets)
+            .map(|(s, t)| RenameOp::new(s, t))
+            .collect(),
+        &PlanOptions::default(),
+    );
+
+    fs::remove_file(&sources[0]).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&sources[1])
+        .unwrap()
+        .set_modified(std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(86_400))
+        .unwrap();
+    fs::write(&targets[2], "someone else's").unwrap();
+
+    let options = ApplyOptions {
+        recheck: true,
+        ..ApplyOptions::default()
+    };
+    let report = apply(&batch, &options, &journal).unwrap();
+
+    assert!(report.committed);
+    assert_eq!(report.renamed, 1);
+    assert_eq!(report.skipped, 3);
+    let statuses: Vec<_> = report.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            OutcomeStatus::Stale,
+            OutcomeStatus::Stale,
+            OutcomeStatus::Stale,
+            OutcomeStatus::Renamed,
+        ]
+    );
+    let reasons = [
+        StaleReason::SourceGone,
+        StaleReason::SourceModified,
+        StaleReason::TargetTaken,
+    ];
+    for (outcome, reason) in report.outcomes.iter().zip(reasons) {
+        assert_eq!(
+            outcome.error.as_deref(),
+            Some(format!("{}: {}", outcome.source.display(), reason).as_str())
+        );
+    }
+    assert!(sources[1].exists());
+    assert_eq!(fs::read_to_string(&targets[2]).unwrap(), "someone else's");
+    assert!(sources[2].exists());
+    assert!(targets[3].exists());
+}
+
+#[test]
+fn batches_with_the_same_target_run_one_after_the_other() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let [a, b, c] = ["a.txt", "b.txt", "c.txt"].map(|n| dir.path().join(n));
+    for p in [&a, &b, &c] {
+        fs::write(p, "x").unwrap();
+    }
+    let target = dir.path().join("report.txt");
+    let batch = |source, target: &std::path::Path| {
+        plan(vec![RenameOp::new(source, target)], &PlanOptions::default())
+    };
+    // The same name, spelled another way.
+    let respelled = dir.path().join("sub").join("..").join("report.txt");
+    let (first, second, third) = (
+        batch(&a, &target),
+        batch(&b, &respelled),
+        batch(&c, &target),
+    );
+
+    // The first batch stops after its rename until told to go on, holding
+    // its paths.
+    let (renamed, wait_renamed) = std::sync::mpsc::channel();
+    let (go, wait_go) = std::sync::mpsc::channel::<()>();
+    let (renamed, wait_go) = (Mutex::new(renamed), Mutex::new(wait_go));
+    thread::scope(|s| {
+        let running = s.spawn(|| {
+            let progress = |_: &_| {
+                renamed.lock().unwrap().send(()).unwrap();
+                wait_go.lock().unwrap().recv().unwrap();
+            };
+            apply_with_progress(
+                &first,
+                &ApplyOptions::default(),
+                &journal,
+                &progress,
+                &CancelToken::new(),
+            )
+        });
+        wait_renamed.recv().unwrap();
+
+        let at_once = ApplyOptions {
+            in_flight_wait_secs: 0,
+            ..ApplyOptions::default()
+        };
+        match apply(&second, &at_once, &journal) {
+            Err(Error::TargetInFlight { path, .. }) => assert_eq!(path, respelled),
+            other => panic!(
+                "expected the target to be in flight, got {:?}",
+                other.is_ok()
+            ),
+        }
+        assert!(b.exists());
+
+        let waiting = s.spawn(|| apply(&third, &ApplyOptions::default(), &journal));
+        thread::sleep(Duration::from_millis(250));
+        go.send(()).unwrap();
+        assert!(running.join().unwrap().unwrap().committed);
+
+        // By the time it ran, the name was taken.
+        let report = waiting.join().unwrap().unwrap();
+        assert_eq!(report.outcomes[0].status, OutcomeStatus::Stale);
+    });
+    assert!(c.exists());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 4);
+}
+
+#[test]
+fn applies_swaps_and_rotations_through_temporary_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let p = |n: &str| dir.path().join(n);
+    for n in ["a", "b", "x", "y", "z"] {
+        fs::write(p(n), n).unwrap();
+    }
+
+    let batch = plan(
+        vec![
+            RenameOp::new(p("a"), p("b")),
+            RenameOp::new(p("b"), p("a")),
+            RenameOp::new(p("x"), p("y")),
+            RenameOp::new(p("y"), p("z")),
+            RenameOp::new(p("z"), p("x")),
+        ],
+        &PlanOptions::default(),
+    );
+    assert_eq!(batch.ready().count(), 5);
+
+    let report = apply(&batch, &ApplyOptions::default(), &journal).unwrap();
+
+    assert!(report.committed);
+    for (name, content) in [("a", "b"), ("b", "a"), ("x", "z"), ("y", "x"), ("z", "y")] {
+        assert_eq!(fs::read_to_string(p(name)).unwrap(), content);
+    }
+    // No temporary names are left behind.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 6);
+}
+
+#[test]
+fn large_batches_run_in_parallel_and_report_progress() {
+    let dir = tempfile::tempdir().unwrap();
+    let ops: Vec<_> = (0..600)
+        .map(|i| {
+            let source = dir.path().join(format!("{}.txt", i));
+            fs::write(&source, i.to_string()).unwrap();
+            RenameOp::new(source, dir.path().join(format!("out/{}.txt", i)))
+        })
+        .collect();
+    let batch = plan(ops, &PlanOptions::default());
+    let seen = Mutex::new(Vec::new());
+
+    let report = apply_with_progress(
+        &batch,
+        &ApplyOptions::default(),
+        &dir.path().join("journal"),
+        &|p| seen.lock().unwrap().push(p.completed),
+        &CancelToken::new(),
+    )
+    .unwrap();
+
+    assert!(report.committed);
+    assert_eq!(report.renamed, 600);
+    let seen = seen.into_inner().unwrap();
+    assert_eq!(seen.last(), Some(&600));
+    assert!(seen.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out/599.txt")).unwrap(),
+        "599"
+    );
+}
+
+#[test]
+fn cancelling_keeps_what_was_renamed_and_stops_the_rest() {
+    let dir = tempfile::tempdir().unwrap();
+    let ops: Vec<_> = (0..10)
+        .map(|i| {
+            let source = dir.path().join(format!("{}.txt", i));
+            fs::write(&source, "x").unwrap();
+            RenameOp::new(source, dir.path().join(format!("new-{}.txt", i)))
+        })
+        .collect();
+    let batch = plan(ops, &PlanOptions::default());
+    let cancel = CancelToken::new();
+
+    // The first progress callback always fires, right after the first rename.
+    let report = apply_with_progress(
+        &batch,
+        &ApplyOptions::default(),
+        &dir.path().join("journal"),
+        &|_| cancel.cancel(),
+        &cancel,
+    )
+    .unwrap();
+
+    assert!(report.committed && report.cancelled);
+    assert_eq!(report.renamed, 1);
+    assert_eq!(report.outcomes[1].status, OutcomeStatus::Cancelled);
+    assert!(dir.path().join("new-0.txt").exists());
+    assert!(dir.path().join("1.txt").exists());
+
+    // The partial batch can still be undone.
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+    assert_eq!(store.undo_last().unwrap().unwrap().done, 1);
+    assert!(dir.path().join("0.txt").exists());
+}
+
+#[test]
+fn a_share_that_stops_answering_pauses_the_batch_until_it_is_resumed() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let ops: Vec<_> = (0..3)
+        .map(|i| {
+            let source = dir.path().join(format!("{}.txt", i));
+            fs::write(&source, "x").unwrap();
+            RenameOp::new(source, dir.path().join(format!("new-{}.txt", i)))
+        })
+        .collect();
+    let batch = plan(ops, &PlanOptions::default());
+    let on_share = ApplyOptions {
+        network_folders: vec![dir.path().to_path_buf()],
+        ..ApplyOptions::default()
+    };
+
+    // A share that answers just works.
+    let report = apply(&batch, &on_share, &journal).unwrap();
+    assert_eq!(report.renamed, 3);
+    assert!(report.unreachable.is_none());
+    for i in 0..3 {
+        fs::rename(
+            dir.path().join(format!("new-{}.txt", i)),
+            dir.path().join(format!("{}.txt", i)),
+        )
+        .unwrap();
+    }
+
+    // With no time to answer, the first move is given up on and the rest
+    // wait for it.
+    let impatient = ApplyOptions {
+        network_timeout_secs: Some(0),
+        ..on_share
+    };
+    let report = apply(&batch, &impatient, &journal).unwrap();
+    assert!(report.committed && !report.cancelled);
+    assert_eq!(
+        report.unreachable.as_deref(),
+        Some(dir.path().join("0.txt").as_path())
+    );
+    assert!(report
+        .outcomes
+        .iter()
+        .all(|o| o.status == OutcomeStatus::Paused));
+    assert!(report.outcomes[0]
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("did not answer"));
+    let found = Journal::incomplete(&journal).unwrap();
+    assert_eq!(found.len(), 1);
+
+    // The move given up on still lands; resuming finishes the rest.
+    let started = Instant::now();
+    while !dir.path().join("new-0.txt").exists() && started.elapsed() < Duration::from_secs(10) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let resumed = found[0].recover(&journal, Recovery::Resume).unwrap();
+    assert_eq!(resumed.problems, 0);
+    for i in 0..3 {
+        assert!(dir.path().join(format!("new-{}.txt", i)).exists());
+    }
+    assert!(Journal::incomplete(&journal).unwrap().is_empty());
+}
+
+#[test]
+fn renames_folders_after_their_contents_and_undoes_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let trip = dir.path().join("trip");
+    let days = trip.join("days");
+    fs::create_dir_all(&days).unwrap();
+    fs::write(days.join("a.jpg"), "a").unwrap();
+    let loose = dir.path().join("b.jpg");
+    fs::write(&loose, "b").unwrap();
+
+    // Paths refer to the tree before the batch, whatever order they come in.
+    let ops = vec![
+        RenameOp::new(&trip, dir.path().join("holiday")),
+        RenameOp::new(&loose, trip.join("b.jpg")),
+        RenameOp::new(&days, trip.join("day-1")),
+        RenameOp::new(days.join("a.jpg"), days.join("001.jpg")),
+    ];
+    let batch = plan(ops, &PlanOptions::default());
+    assert!(batch.entries.iter().all(|e| e.is_ready()));
+
+    let report = apply(
+        &batch,
+        &ApplyOptions::default(),
+        &dir.path().join("journal"),
+    )
+    .unwrap();
+    assert!(report.committed);
+    assert_eq!(report.renamed, 4);
+    let holiday = dir.path().join("holiday");
+    assert_eq!(
+        fs::read_to_string(holiday.join("day-1/001.jpg")).unwrap(),
+        "a"
+    );
+    assert_eq!(fs::read_to_string(holiday.join("b.jpg")).unwrap(), "b");
+    assert!(!trip.exists());
+
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+    let undone = store.undo_last().unwrap().unwrap();
+    assert_eq!((undone.done, undone.problems), (4, 0));
+    assert_eq!(fs::read_to_string(days.join("a.jpg")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(&loose).unwrap(), "b");
+    assert!(!holiday.exists());
+
+    let id = store.batches()[0].batch_id;
+    assert_eq!(store.redo(Some(id)).unwrap().unwrap().done, 4);
+    assert!(holiday.join("day-1/001.jpg").exists());
+}
+
+#[test]
+fn sets_modification_times_and_restores_them_on_undo() {
+    use chrono::{TimeZone, Utc};
+    use std::time::SystemTime;
+
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+    let mtime = |p: &std::path::Path| fs::metadata(p).unwrap().modified().unwrap();
+    let original = mtime(&b);
+    let date = Utc.with_ymd_and_hms(2020, 5, 17, 12, 0, 0).unwrap();
+
+    // A rename with a new date, and a date change alone.
+    let ops = vec![
+        RenameOp {
+            modified: Some(date),
+            ..RenameOp::new(&a, dir.path().join("c.txt"))
+        },
+        RenameOp {
+            modified: Some(date),
+            ..RenameOp::new(&b, &b)
+        },
+    ];
+    let batch = plan(ops, &PlanOptions::default());
+    assert!(batch.entries.iter().all(|e| e.is_ready()));
+    let report = apply(
+        &batch,
+        &ApplyOptions::default(),
+        &dir.path().join("journal"),
+    )
+    .unwrap();
+    assert_eq!(report.renamed, 2);
+    assert_eq!(mtime(&dir.path().join("c.txt")), SystemTime::from(date));
+    assert_eq!(mtime(&b), SystemTime::from(date));
+
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+    assert_eq!(store.undo_last().unwrap().unwrap().done, 2);
+    assert!(a.exists());
+    assert_eq!(mtime(&b), original);
+}
+
+/// Needs a second filesystem; `/dev/shm` is a tmpfs on most Linux systems.
+#[cfg(unix)]
+#[test]
+fn cross_volume_moves_need_permission_and_copy_the_file_with_its_times() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let Ok(other) = tempfile::tempdir_in("/dev/shm") else {
+        return;
+    };
+    let device = |p: &std::path::Path| fs::metadata(p).unwrap().dev();
+    if device(dir.path()) == device(other.path()) {
+        return;
+    }
+    let journal = dir.path().join("journal");
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "contents").unwrap();
+    let target = other.path().join("moved/a.txt");
+    let plan = plan(vec![RenameOp::new(&a, &target)], &PlanOptions::default());
+
+    let refused = apply(&plan, &ApplyOptions::default(), &journal).unwrap();
+    assert!(!refused.committed);
+    assert_eq!(refused.outcomes[0].status, OutcomeStatus::Failed);
+    assert!(refused.outcomes[0]
+        .error
+        .as_ref()
+        .unwrap()
+        .contains("another volume"));
+    assert!(a.exists());
+    assert!(!other.path().join("moved").exists());
+
+    let modified = fs::metadata(&a).unwrap().modified().unwrap();
+    let options = ApplyOptions {
+        allow_cross_volume: true,
+        preserve_timestamps: true,
+        ..ApplyOptions::default()
+    };
+    let report = apply(&plan, &options, &journal).unwrap();
+    assert!(report.committed);
+    assert_eq!(report.renamed, 1);
+    assert!(!a.exists());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "contents");
+    assert_eq!(fs::metadata(&target).unwrap().modified().unwrap(), modified);
+}
+
+/// Needs a second filesystem, as above.
+#[cfg(unix)]
+#[test]
+fn cross_volume_copies_keep_holes_and_continue_where_they_were_cut_off() {
+    use std::io::{Seek, SeekFrom, Write};
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let Ok(other) = tempfile::tempdir_in("/dev/shm") else {
+        return;
+    };
+    let device = |p: &std::path::Path| fs::metadata(p).unwrap().dev();
+    if device(dir.path()) == device(other.path()) {
+        return;
+    }
+    let options = ApplyOptions {
+        allow_cross_volume: true,
+        ..ApplyOptions::default()
+    };
+    let journal = dir.path().join("journal");
+
+    // 16 MiB with data at the start and the end only.
+    let video = dir.path().join("video.mkv");
+    let mut file = fs::File::create(&video).unwrap();
+    file.write_all(b"header").unwrap();
+    file.seek(SeekFrom::Start(16 << 20)).unwrap();
+    file.write_all(b"trailer").unwrap();
+    drop(file);
+    let sparse = |p: &std::path::Path| {
+        let meta = fs::metadata(p).unwrap();
+        meta.blocks() * 512 < meta.len()
+    };
+    if !sparse(&video) {
+        return;
+    }
+    let original = fs::read(&video).unwrap();
+    let target = other.path().join("video.mkv");
+    let batch = plan(
+        vec![RenameOp::new(&video, &target)],
+        &PlanOptions::default(),
+    );
+    let report = apply(&batch, &options, &journal).unwrap();
+    assert_eq!(report.renamed, 1);
+    assert_eq!(fs::read(&target).unwrap(), original);
+    assert!(sparse(&target));
+
+    // A copy cut off halfway is continued, one that went wrong redone.
+    for (left, name) in [(&original[..8 << 20], "a.mkv"), (&b"garbage"[..], "b.mkv")] {
+        let source = dir.path().join(name);
+        fs::write(&source, &original).unwrap();
+        let target = other.path().join(name);
+        let partial = other.path().join(format!(".{}.sortify-move.part", name));
+        fs::write(&partial, left).unwrap();
+        let batch = plan(
+            vec![RenameOp::new(&source, &target)],
+            &PlanOptions::default(),
+        );
+        let report = apply(&batch, &options, &journal).unwrap();
+        assert_eq!(report.renamed, 1);
+        assert_eq!(fs::read(&target).unwrap(), original);
+        assert!(!partial.exists());
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs;
+
+use renamer_core::export::{export_batch, export_plan};
+use renamer_core::{apply, plan, ApplyOptions, ExportFormat, PlanOptions, RenameOp};
+
+#[test]
+fn plans_and_batches_export_to_csv_and_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+    let plan = plan(
+        vec![
+            RenameOp::new(&a, dir.path().join("a, renamed.txt")),
+            RenameOp::new(dir.path().join("gone.txt"), &b),
+        ],
+        &PlanOptions::default(),
+    );
+
+    let csv_path = dir.path().join("plan.csv");
+    export_plan(&plan, ExportFormat::Csv, &csv_path).unwrap();
+    let csv = fs::read_to_string(&csv_path).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "batch_id,id,source,target,status,error,warnings,previous_modified,modified"
+    );
+    assert!(
+        lines[1].contains(r#"a, renamed.txt",ready,"#),
+        "{}",
+        lines[1]
+    );
+    assert!(lines[2].contains(",missing_source,"), "{}", lines[2]);
+
+    let report = apply(&plan, &ApplyOptions::default(), &dir.path().join("j")).unwrap();
+    let json_path = dir.path().join("batch.json");
+    export_batch(&report, ExportFormat::Json, &json_path).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(&json_path).unwrap()).unwrap();
+    assert_eq!(json["kind"], "batch");
+    assert_eq!(json["committed"], true);
+    assert_eq!(json["batch_id"], report.batch_id.to_string());
+    assert_eq!(json["rows"][0]["status"], "renamed");
+    assert_eq!(json["rows"][1]["status"], "skipped");
+    assert!(json["exported_at"].is_string());
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use renamer_core::{CancelToken, ExtractOptions, ExtractQueue, HashAlgorithm};
+
+#[test]
+fn reads_every_file_once_with_the_prioritized_ones_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<PathBuf> = (0..20)
+        .map(|i| {
+            let path = dir.path().join(format!("{}.txt", i));
+            fs::write(&path, i.to_string()).unwrap();
+            path
+        })
+        .collect();
+
+    let queue = ExtractQueue::new(paths.clone());
+    queue.prioritize(&[
+        paths[17].clone(),
+        paths[5].clone(),
+        dir.path().join("other"),
+    ]);
+    let seen = Mutex::new(Vec::new());
+    let options = ExtractOptions {
+        hash: Some(HashAlgorithm::Sha256),
+        threads: 1,
+        ..ExtractOptions::default()
+    };
+    let read = queue.run(
+        &options,
+        &|extracted| seen.lock().unwrap().push(extracted),
+        &CancelToken::new(),
+    );
+    let seen = seen.into_inner().unwrap();
+    assert_eq!(read, 20);
+    assert_eq!(seen[0].path, paths[17]);
+    assert_eq!(seen[1].path, paths[5]);
+    assert!(seen[0].metadata.is_none());
+    assert!(seen.iter().all(|e| e.hash.as_ref().unwrap().hash.is_some()));
+    let mut order: Vec<&PathBuf> = seen.iter().map(|e| &e.path).collect();
+    order.sort();
+    order.dedup();
+    assert_eq!(order.len(), 20);
+
+    // Several threads, cancelled before they start, read nothing.
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let options = ExtractOptions {
+        metadata: true,
+        threads: 4,
+        ..ExtractOptions::default()
+    };
+    assert_eq!(ExtractQueue::new(paths).run(&options, &|_| {}, &cancel), 0);
+}
@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::PathBuf;
+
+use renamer_core::{FileList, FileListQuery, ScanSort};
+
+fn names(list: &mut FileList, query: &FileListQuery) -> Vec<String> {
+    list.query(query)
+        .files
+        .iter()
+        .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn lists_files_once_and_hands_out_sorted_windows() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<PathBuf> = ["b10.txt", "b2.txt", "a.txt"]
+        .iter()
+        .map(|name| dir.path().join(name))
+        .collect();
+    for (path, size) in paths.iter().zip([3, 1, 2]) {
+        fs::write(path, "x".repeat(size)).unwrap();
+    }
+
+    let mut list = FileList::new();
+    assert_eq!(list.add_paths(paths.clone()), 3);
+    assert_eq!(list.add_paths(vec![paths[0].clone()]), 0);
+    assert_eq!(list.len(), 3);
+    assert_eq!(list.paths(false), paths);
+
+    let by_name = FileListQuery {
+        sort: ScanSort::Name,
+        ..FileListQuery::default()
+    };
+    assert_eq!(names(&mut list, &by_name), ["a.txt", "b2.txt", "b10.txt"]);
+    let page = FileListQuery {
+        sort: ScanSort::Size,
+        descending: true,
+        offset: 1,
+        limit: Some(1),
+        ..FileListQuery::default()
+    };
+    let window = list.query(&page);
+    assert_eq!((window.matching, window.total), (3, 3));
+    assert_eq!(window.files[0].path, paths[2]);
+    let filtered = FileListQuery {
+        filter: "B".into(),
+        ..by_name.clone()
+    };
+    assert_eq!(names(&mut list, &filtered), ["b2.txt", "b10.txt"]);
+}
+
+#[test]
+fn selects_and_removes_by_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<PathBuf> = ["a.jpg", "b.jpg", "c.md"]
+        .iter()
+        .map(|name| dir.path().join(name))
+        .collect();
+    let mut list = FileList::new();
+    list.add_paths(paths.clone());
+    let ids: Vec<u64> = list
+        .query(&FileListQuery::default())
+        .files
+        .iter()
+        .map(|f| f.id)
+        .collect();
+
+    assert_eq!(list.select_matching(".jpg", true), 2);
+    assert_eq!(list.select(&[ids[0]], false), 1);
+    assert_eq!(list.paths(true), [paths[1].clone()]);
+    let selected = FileListQuery {
+        selected_only: true,
+        ..FileListQuery::default()
+    };
+    assert_eq!(names(&mut list, &selected), ["b.jpg"]);
+
+    assert_eq!(list.remove(&[ids[1], 999]), 1);
+    assert_eq!(list.selected(), 0);
+    assert!(names(&mut list, &selected).is_empty());
+    assert!(list.get(ids[1]).is_none());
+    // A file added again gets a new id.
+    list.add_paths(vec![paths[1].clone()]);
+    assert!(list.query(&FileListQuery::default()).files[1].id > ids[2]);
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use renamer_core::{apply, git, plan, ApplyOptions, HistoryStore, PlanOptions, RenameOp};
+
+fn run(dir: &Path, args: &[&str]) -> String {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    String::from_utf8(out.stdout).unwrap()
+}
+
+fn status(dir: &Path) -> Vec<String> {
+    let mut lines: Vec<String> = run(dir, &["status", "--porcelain", "--untracked-files=all"])
+        .lines()
+        .map(str::to_string)
+        .collect();
+    lines.sort();
+    lines
+}
+
+#[test]
+fn renames_in_a_work_tree_are_staged_as_git_mv_would() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    run(root, &["init", "-q"]);
+    run(root, &["config", "user.email", "dev@example.com"]);
+    run(root, &["config", "user.name", "Dev"]);
+    fs::create_dir(root.join("src")).unwrap();
+    fs::write(root.join("src/util.rs"), "fn util() {}\n").unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(root.join("notes.txt"), "notes\n").unwrap();
+    run(root, &["add", "."]);
+    run(root, &["commit", "-q", "-m", "start"]);
+    // Unstaged changes stay unstaged, and untracked files untracked.
+    fs::write(root.join("main.rs"), "fn main() { util(); }\n").unwrap();
+    fs::write(root.join("scratch.txt"), "scratch\n").unwrap();
+    assert_eq!(
+        git::work_tree(&root.join("src/util.rs")),
+        Some(fs::canonicalize(root).unwrap())
+    );
+
+    let ops = vec![
+        RenameOp::new(root.join("main.rs"), root.join("app.rs")),
+        RenameOp::new(root.join("src"), root.join("lib")),
+        RenameOp::new(root.join("src/util.rs"), root.join("src/helpers.rs")),
+        RenameOp::new(root.join("scratch.txt"), root.join("draft.txt")),
+    ];
+    let options = ApplyOptions {
+        git: true,
+        ..ApplyOptions::default()
+    };
+    let report = apply(
+        &plan(ops, &PlanOptions::default()),
+        &options,
+        &root.join(".git/renamer-journal"),
+    )
+    .unwrap();
+    assert!(report.committed);
+    assert_eq!(report.git.len(), 1);
+    assert_eq!(report.git[0].error, None);
+    assert_eq!(report.git[0].staged, 2);
+    assert_eq!(
+        status(root),
+        [
+            "?? draft.txt",
+            "R  src/util.rs -> lib/helpers.rs",
+            "RM main.rs -> app.rs",
+        ]
+    );
+
+    let mut store = HistoryStore::open(root.join(".git/renamer-history.json")).unwrap();
+    store.record(&report).unwrap();
+    let undone = store.undo_last().unwrap().unwrap();
+    assert_eq!(git::stage_replay(&undone, true)[0].error, None);
+    assert_eq!(status(root), [" M main.rs", "?? scratch.txt"]);
+}
+
+#[test]
+fn folders_outside_a_work_tree_are_left_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    let report = apply(
+        &plan(
+            vec![RenameOp::new(
+                dir.path().join("a.txt"),
+                dir.path().join("b.txt"),
+            )],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions {
+            git: true,
+            ..ApplyOptions::default()
+        },
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    assert!(report.committed);
+    assert!(report.git.is_empty());
+}
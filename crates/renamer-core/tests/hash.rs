@@ -0,0 +1,94 @@
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use renamer_core::{hash_file, hash_files, preview, CancelToken, HashAlgorithm, Rule};
+
+#[test]
+fn hashes_with_each_algorithm() {
+    let dir = tempfile::tempdir().unwrap();
+    let abc = dir.path().join("abc.txt");
+    let empty = dir.path().join("empty.txt");
+    fs::write(&abc, "abc").unwrap();
+    fs::write(&empty, "").unwrap();
+
+    assert_eq!(
+        hash_file(&abc, HashAlgorithm::Sha256).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hash_file(&abc, HashAlgorithm::Md5).unwrap(),
+        "900150983cd24fb0d6963f7d28e17f72"
+    );
+    assert_eq!(
+        hash_file(&empty, HashAlgorithm::Xxh3).unwrap(),
+        "2d06800538d394c2"
+    );
+}
+
+#[test]
+fn hash_files_reports_progress_errors_and_cancellation() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut paths: Vec<_> = (0..20)
+        .map(|i| {
+            let path = dir.path().join(format!("{}.txt", i));
+            fs::write(&path, i.to_string()).unwrap();
+            path
+        })
+        .collect();
+    paths.push(dir.path().join("missing.txt"));
+
+    let reports = AtomicUsize::new(0);
+    let last = AtomicUsize::new(0);
+    let hashes = hash_files(
+        &paths,
+        HashAlgorithm::Md5,
+        &|p| {
+            reports.fetch_add(1, Ordering::Relaxed);
+            last.store(p.completed, Ordering::Relaxed);
+        },
+        &CancelToken::new(),
+    );
+    assert_eq!(hashes.len(), 21);
+    assert_eq!(hashes[1].path, paths[1]);
+    assert_eq!(
+        hashes[1].hash.as_deref(),
+        Some("c4ca4238a0b923820dcc509a6f75849b")
+    );
+    assert!(hashes[20].hash.is_none() && hashes[20].error.is_some());
+    assert!(reports.load(Ordering::Relaxed) >= 1);
+    assert_eq!(last.load(Ordering::Relaxed), 21);
+
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    assert!(hash_files(&paths, HashAlgorithm::Md5, &|_| {}, &cancel).is_empty());
+}
+
+#[test]
+fn templates_append_short_hashes() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = dir.path().join("app.js");
+    fs::write(&app, "abc").unwrap();
+
+    let rows = preview(
+        &[app],
+        &[Rule::Template {
+            template: "{name}-{hash:sha256:8}-{hash:md5:4}.{ext}".into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "app-ba7816bf-9001.js");
+
+    for (template, message) in [
+        ("{hash:crc32}", "unknown hash algorithm `crc32`"),
+        ("{hash:sha256:0}", "bad length"),
+    ] {
+        let err = preview(
+            &[],
+            &[Rule::Template {
+                template: template.into(),
+            }],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(message), "{}", err);
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs;
+
+use renamer_core::{
+    apply, plan, ApplyOptions, Error, HistoryStore, PlanOptions, RenameOp, ReplayStatus,
+};
+
+#[test]
+fn undo_and_redo_round_trip_and_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let history_path = dir.path().join("history.json");
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("sorted/b.txt");
+    fs::write(&a, "a").unwrap();
+
+    let report = apply(
+        &plan(vec![RenameOp::new(&a, &b)], &PlanOptions::default()),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    let mut store = HistoryStore::open(&history_path).unwrap();
+    store.record(&report).unwrap();
+
+    let undo = store.undo_last().unwrap().unwrap();
+    assert_eq!(undo.done, 1);
+    assert!(a.exists());
+    assert!(!dir.path().join("sorted").exists());
+
+    // Nothing left to undo, but the batch can be redone after reopening.
+    assert!(store.undo_last().unwrap().is_none());
+    let mut store = HistoryStore::open(&history_path).unwrap();
+    let redo = store.redo(None).unwrap().unwrap();
+    assert_eq!(redo.batch_id, report.batch_id);
+    assert!(b.exists() && !a.exists());
+}
+
+#[test]
+fn undo_reports_missing_and_occupied_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+
+    let report = apply(
+        &plan(
+            vec![
+                RenameOp::new(&a, dir.path().join("a2.txt")),
+                RenameOp::new(&b, dir.path().join("b2.txt")),
+            ],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+
+    fs::remove_file(dir.path().join("a2.txt")).unwrap();
+    fs::write(&b, "squatter").unwrap();
+
+    let undo = store.undo_last().unwrap().unwrap();
+    let statuses: Vec<_> = undo.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(
+        statuses,
+        vec![ReplayStatus::Occupied, ReplayStatus::Missing]
+    );
+    assert_eq!(undo.problems, 2);
+}
+
+#[test]
+fn undo_reverses_a_swap() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+
+    let report = apply(
+        &plan(
+            vec![RenameOp::new(&a, &b), RenameOp::new(&b, &a)],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    assert_eq!(fs::read_to_string(&a).unwrap(), "b");
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+
+    let undo = store.undo_last().unwrap().unwrap();
+    assert_eq!(undo.done, 2);
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "b");
+}
+
+#[test]
+fn undoing_some_files_leaves_the_rest_and_changed_ones_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let [a, b, c] = ["a.txt", "b.txt", "c.txt"].map(|n| dir.path().join(n));
+    for p in [&a, &b, &c] {
+        fs::write(p, "x").unwrap();
+    }
+    let renamed = |p: &std::path::Path| p.with_extension("md");
+    let report = apply(
+        &plan(
+            [&a, &b, &c]
+                .iter()
+                .map(|p| RenameOp::new(p, renamed(p)))
+                .collect(),
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions {
+            allow_extension_changes: true,
+            ..ApplyOptions::default()
+        },
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+
+    assert!(matches!(
+        store.undo_files(report.batch_id, &[0, 7]),
+        Err(Error::NotInBatch { id: 7, .. })
+    ));
+    fs::write(renamed(&b), "edited since").unwrap();
+    let undo = store.undo_files(report.batch_id, &[0, 1]).unwrap().unwrap();
+    let statuses: Vec<_> = undo.outcomes.iter().map(|o| (o.id, o.status)).collect();
+    assert_eq!(
+        statuses,
+        [(1, ReplayStatus::Changed), (0, ReplayStatus::Done)]
+    );
+    assert!(a.exists());
+    assert!(renamed(&b).exists() && renamed(&c).exists());
+
+    // The rest of the batch is still there to undo.
+    let undo = store.undo_last().unwrap().unwrap();
+    assert_eq!(undo.done, 2);
+    assert!(b.exists() && c.exists());
+}
+
+#[test]
+fn files_are_undone_against_the_fingerprint_taken_after_the_post_action() {
+    let dir = tempfile::tempdir().unwrap();
+    let [a, b] = ["a.txt", "b.txt"].map(|n| dir.path().join(n));
+    for p in [&a, &b] {
+        fs::write(p, "x").unwrap();
+    }
+    let report = apply(
+        &plan(
+            vec![
+                RenameOp::new(&a, dir.path().join("a2.txt")),
+                RenameOp::new(&b, dir.path().join("b2.txt")),
+            ],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    let history = dir.path().join("history.json");
+    let mut store = HistoryStore::open(&history).unwrap();
+    store.record(&report).unwrap();
+
+    // A post action writing tags changes the file; taken again, it is not.
+    fs::write(dir.path().join("a2.txt"), "tagged").unwrap();
+    store.fingerprint_again(report.batch_id).unwrap();
+    let undo = store.undo_files(report.batch_id, &[0]).unwrap().unwrap();
+    assert_eq!(undo.outcomes[0].status, ReplayStatus::Done);
+    assert!(a.exists());
+
+    // A file recorded without a fingerprint could be anything.
+    let mut json: serde_json::Value = serde_json::from_slice(&fs::read(&history).unwrap()).unwrap();
+    for entry in json[0]["entries"].as_array_mut().unwrap() {
+        entry.as_object_mut().unwrap().remove("left");
+    }
+    fs::write(&history, serde_json::to_vec(&json).unwrap()).unwrap();
+    let mut store = HistoryStore::open(&history).unwrap();
+    let undo = store.undo_files(report.batch_id, &[1]).unwrap().unwrap();
+    assert_eq!(undo.outcomes[0].status, ReplayStatus::Changed);
+    assert!(dir.path().join("b2.txt").exists());
+}
+
+#[test]
+fn a_file_undone_without_its_folder_stays_in_the_renamed_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let photos = dir.path().join("photos");
+    let pictures = dir.path().join("pictures");
+    fs::create_dir(&photos).unwrap();
+    fs::write(photos.join("a.jpg"), "a").unwrap();
+    let report = apply(
+        &plan(
+            vec![
+                RenameOp::new(&photos, &pictures),
+                RenameOp::new(photos.join("a.jpg"), photos.join("b.jpg")),
+            ],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    assert!(pictures.join("b.jpg").exists());
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+
+    let undo = store.undo_files(report.batch_id, &[1]).unwrap().unwrap();
+    assert_eq!(undo.done, 1);
+    assert!(pictures.join("a.jpg").exists());
+    assert!(!photos.exists());
+}
@@ -0,0 +1,158 @@
+use std::fs;
+
+use chrono::{Duration, Local, Utc};
+use renamer_core::{
+    apply, plan, ApplyOptions, ChangeKind, DateRange, HistoryDb, HistoryStore, PlanOptions,
+    RenameOp,
+};
+
+#[test]
+fn renames_undo_and_redo_can_be_searched_later() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("data/history.db");
+    let old = dir.path().join("IMG_0042.jpg");
+    let new = dir.path().join("2024-06-01 Beach.jpg");
+    fs::write(&old, "jpg").unwrap();
+
+    let report = apply(
+        &plan(vec![RenameOp::new(&old, &new)], &PlanOptions::default()),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    let mut db = HistoryDb::open(&db_path).unwrap();
+    db.record(&report, Some("Holiday photos")).unwrap();
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+    db.record_replay(&store.undo_last().unwrap().unwrap(), true)
+        .unwrap();
+    db.record_replay(&store.redo(None).unwrap().unwrap(), false)
+        .unwrap();
+
+    // What was the beach photo called?
+    let db = HistoryDb::open(&db_path).unwrap();
+    let found = db.search("beach", DateRange::default()).unwrap();
+    let kinds: Vec<_> = found.iter().map(|c| c.kind).collect();
+    assert_eq!(
+        kinds,
+        [ChangeKind::Redone, ChangeKind::Undone, ChangeKind::Renamed]
+    );
+    assert_eq!(found[2].source, old);
+    assert_eq!(found[2].target, new);
+    assert_eq!(found[2].preset.as_deref(), Some("Holiday photos"));
+    assert_eq!(found[1].source, new);
+    assert!(db.search("100%", DateRange::default()).unwrap().is_empty());
+
+    let tomorrow = DateRange {
+        from: Some(Utc::now() + Duration::days(1)),
+        to: None,
+    };
+    assert!(db.search("beach", tomorrow).unwrap().is_empty());
+
+    let batch = db.batch(report.batch_id).unwrap().unwrap();
+    assert_eq!(batch.preset.as_deref(), Some("Holiday photos"));
+    assert_eq!(batch.changes.len(), 3);
+    assert_eq!(batch.changes[0].kind, ChangeKind::Renamed);
+    assert!(db.batch(uuid::Uuid::nil()).unwrap().is_none());
+}
+
+#[test]
+fn batches_from_the_undo_history_are_imported_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let report = apply(
+        &plan(
+            vec![RenameOp::new(&a, dir.path().join("b.txt"))],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+
+    let mut db = HistoryDb::open(dir.path().join("history.db")).unwrap();
+    assert_eq!(db.import(store.batches()).unwrap(), 1);
+    assert_eq!(db.import(store.batches()).unwrap(), 0);
+    let batch = db.batch(report.batch_id).unwrap().unwrap();
+    assert_eq!(batch.changes.len(), 1);
+    assert_eq!(batch.preset, None);
+    assert_eq!(db.search("", DateRange::default()).unwrap().len(), 1);
+}
+
+#[test]
+fn stats_add_up_renames_failures_and_sizes_by_day_preset_and_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let inbox = dir.path().join("inbox");
+    fs::create_dir(&inbox).unwrap();
+    let [a, b, c] = ["a.txt", "b.txt", "c.txt"].map(|n| inbox.join(n));
+    fs::write(&a, "1234").unwrap();
+    fs::write(&b, "123456").unwrap();
+    fs::write(&c, "x").unwrap();
+    let journal = dir.path().join("j");
+    let mut db = HistoryDb::open(dir.path().join("history.db")).unwrap();
+
+    let renamed = apply(
+        &plan(
+            vec![
+                RenameOp::new(&a, dir.path().join("a2.txt")),
+                RenameOp::new(&b, dir.path().join("b2.txt")),
+            ],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &journal,
+    )
+    .unwrap();
+    db.record(&renamed, Some("Inbox")).unwrap();
+
+    // The second batch fails on a file gone before it ran.
+    let gone = inbox.join("gone.txt");
+    fs::write(&gone, "x").unwrap();
+    let batch = plan(
+        vec![
+            RenameOp::new(&c, dir.path().join("c2.txt")),
+            RenameOp::new(&gone, dir.path().join("d2.txt")),
+        ],
+        &PlanOptions::default(),
+    );
+    fs::remove_file(&gone).unwrap();
+    let failed = apply(&batch, &ApplyOptions::default(), &journal).unwrap();
+    assert!(!failed.committed);
+    db.record(&failed, Some("Inbox")).unwrap();
+    db.record(&failed, Some("Inbox")).unwrap();
+    assert!(db
+        .batch(failed.batch_id)
+        .unwrap()
+        .unwrap()
+        .changes
+        .is_empty());
+
+    let stats = db.stats(DateRange::default()).unwrap();
+    assert_eq!((stats.batches, stats.failed_batches), (2, 1));
+    assert_eq!((stats.renamed, stats.failed), (2, 1));
+    assert_eq!(stats.bytes, 10);
+    assert!((stats.error_rate() - 1.0 / 3.0).abs() < 1e-9);
+
+    assert_eq!(stats.days.len(), 1);
+    assert_eq!(stats.days[0].day, Local::now().date_naive());
+    assert_eq!(stats.days[0].renamed, 2);
+
+    assert_eq!(stats.presets.len(), 1);
+    let inbox_preset = &stats.presets[0];
+    assert_eq!(inbox_preset.preset.as_deref(), Some("Inbox"));
+    assert_eq!(inbox_preset.batches, 2);
+    assert_eq!(inbox_preset.failed_batches, 1);
+
+    assert_eq!(stats.folders.len(), 1);
+    assert_eq!(stats.folders[0].folder, inbox);
+    assert_eq!(stats.folders[0].bytes, 10);
+
+    let yesterday = DateRange {
+        from: None,
+        to: Some(Utc::now() - Duration::days(1)),
+    };
+    assert_eq!(db.stats(yesterday).unwrap().batches, 0);
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::Path;
+
+use renamer_core::ingest::{self, IngestOptions};
+use renamer_core::{CancelToken, OutcomeStatus, Rule};
+
+fn card(root: &Path) {
+    let dcim = root.join("DCIM/100CANON");
+    fs::create_dir_all(&dcim).unwrap();
+    fs::write(dcim.join("IMG_0001.JPG"), b"first photo ".repeat(1000)).unwrap();
+    fs::write(dcim.join("IMG_0002.JPG"), b"second photo".repeat(1000)).unwrap();
+}
+
+fn options(destination: &Path) -> IngestOptions {
+    IngestOptions {
+        rules: vec![Rule::Prefix {
+            text: "trip_".into(),
+        }],
+        ..IngestOptions::new(destination)
+    }
+}
+
+#[test]
+fn files_are_copied_under_their_new_names_and_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("card");
+    let destination = dir.path().join("photos");
+    card(&source);
+    let taken = destination.join("DCIM/100CANON/trip_IMG_0002.JPG");
+    fs::create_dir_all(taken.parent().unwrap()).unwrap();
+    fs::write(&taken, b"taken").unwrap();
+
+    let rows = ingest::preview(&source, &options(&destination)).unwrap();
+    assert_eq!(
+        rows[0].target,
+        destination.join("DCIM/100CANON/trip_IMG_0001.JPG")
+    );
+
+    let report = ingest::ingest(
+        &source,
+        &options(&destination),
+        &|_| {},
+        &CancelToken::new(),
+    )
+    .unwrap();
+    assert!(report.report.committed);
+    let statuses: Vec<OutcomeStatus> = report.report.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(statuses, [OutcomeStatus::Renamed, OutcomeStatus::Skipped]);
+    assert!(report.report.outcomes[0].verified);
+
+    let original = source.join("DCIM/100CANON/IMG_0001.JPG");
+    let copy = destination.join("DCIM/100CANON/trip_IMG_0001.JPG");
+    assert_eq!(fs::read(&copy).unwrap(), fs::read(&original).unwrap());
+    assert_eq!(
+        fs::metadata(&copy).unwrap().modified().unwrap(),
+        fs::metadata(&original).unwrap().modified().unwrap()
+    );
+    assert_eq!(fs::read(&taken).unwrap(), b"taken");
+    assert!(!destination.join(".sortify-ingest.json").exists());
+}
+
+#[test]
+fn an_interrupted_import_picks_up_where_it_stopped() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("card");
+    let destination = dir.path().join("photos");
+    card(&source);
+    let folder = destination.join("DCIM/100CANON");
+    fs::create_dir_all(&folder).unwrap();
+    // Left by a run that stopped while copying: one partial copy that can be
+    // continued, and one that does not match its original.
+    fs::write(
+        folder.join(".trip_IMG_0001.JPG.sortify-ingest.part"),
+        &b"first photo ".repeat(1000)[..5000],
+    )
+    .unwrap();
+    fs::write(
+        folder.join(".trip_IMG_0002.JPG.sortify-ingest.part"),
+        b"something else",
+    )
+    .unwrap();
+
+    let cancel = CancelToken::new();
+    let report = ingest::ingest(
+        &source,
+        &options(&destination),
+        &|p| {
+            if p.copied_bytes > 0 {
+                cancel.cancel();
+            }
+        },
+        &cancel,
+    )
+    .unwrap();
+    assert!(report.report.cancelled);
+    assert!(!report.report.committed);
+    assert_eq!(report.report.renamed, 1);
+    assert!(destination.join(".sortify-ingest.json").exists());
+
+    let report = ingest::ingest(
+        &source,
+        &options(&destination),
+        &|_| {},
+        &CancelToken::new(),
+    )
+    .unwrap();
+    assert!(report.report.committed);
+    assert_eq!(report.resumed, 1);
+    assert_eq!(report.report.renamed, 2);
+    assert_eq!(
+        fs::read(folder.join("trip_IMG_0001.JPG")).unwrap(),
+        b"first photo ".repeat(1000)
+    );
+    assert_eq!(
+        fs::read(folder.join("trip_IMG_0002.JPG")).unwrap(),
+        b"second photo".repeat(1000)
+    );
+    let left: Vec<_> = fs::read_dir(&folder).unwrap().collect();
+    assert_eq!(left.len(), 2);
+    assert!(!destination.join(".sortify-ingest.json").exists());
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::Utc;
+use renamer_core::{
+    apply, plan, ApplyOptions, Journal, JournalEntry, JournalStep, PlanOptions, Recovery, RenameOp,
+    ReplayStatus,
+};
+use uuid::Uuid;
+
+/// The journal of a batch renaming `x→y` on its own and the chain `b→c`,
+/// `a→b`, with nothing moved yet.
+fn journal_of_chain(dir: &Path) -> Journal {
+    for name in ["a", "b", "x"] {
+        fs::write(dir.join(name), name).unwrap();
+    }
+    let entry = |id, from: &str, to: &str| JournalEntry {
+        id,
+        source: dir.join(from),
+        target: dir.join(to),
+        modified: None,
+    };
+    let step = |id, from: &str, to: &str, independent| JournalStep {
+        id,
+        from: dir.join(from),
+        to: dir.join(to),
+        last: true,
+        backup: None,
+        independent,
+    };
+    Journal {
+        batch_id: Uuid::new_v4(),
+        created_at: Utc::now(),
+        entries: vec![entry(0, "a", "b"), entry(1, "b", "c"), entry(2, "x", "y")],
+        steps: vec![
+            step(2, "x", "y", true),
+            step(1, "b", "c", false),
+            step(0, "a", "b", false),
+        ],
+        new_dirs: Vec::new(),
+    }
+}
+
+fn read(dir: &Path, name: &str) -> String {
+    fs::read_to_string(dir.join(name)).unwrap()
+}
+
+#[test]
+fn interrupted_batches_are_found_and_resumed() {
+    let dir = tempfile::tempdir().unwrap();
+    let journals = dir.path().join("journal");
+    let journal = journal_of_chain(dir.path());
+    journal.write(&journals).unwrap();
+    fs::write(journals.join("cut-off.json.partial"), "{").unwrap();
+    // Cut off after the first move of the chain.
+    fs::rename(dir.path().join("b"), dir.path().join("c")).unwrap();
+
+    let found = Journal::incomplete(&journals).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].batch_id, journal.batch_id);
+    assert!(!journals.join("cut-off.json.partial").exists());
+
+    let report = found[0].recover(&journals, Recovery::Resume).unwrap();
+    assert_eq!((report.done, report.problems), (2, 0));
+    assert_eq!(read(dir.path(), "b"), "a");
+    assert_eq!(read(dir.path(), "c"), "b");
+    assert_eq!(read(dir.path(), "y"), "x");
+    assert!(!dir.path().join("a").exists());
+    assert!(Journal::incomplete(&journals).unwrap().is_empty());
+}
+
+#[test]
+fn interrupted_batches_roll_back_and_keep_the_journal_on_problems() {
+    let dir = tempfile::tempdir().unwrap();
+    let journals = dir.path().join("journal");
+    let journal = journal_of_chain(dir.path());
+    journal.write(&journals).unwrap();
+    // The whole chain ran, which refilled `b`; `x→y` did not.
+    fs::rename(dir.path().join("b"), dir.path().join("c")).unwrap();
+    fs::rename(dir.path().join("a"), dir.path().join("b")).unwrap();
+    // Someone took `c` elsewhere in the meantime.
+    fs::rename(dir.path().join("c"), dir.path().join("elsewhere")).unwrap();
+
+    let report = journal.recover(&journals, Recovery::Rollback).unwrap();
+    assert_eq!((report.done, report.problems), (1, 1));
+    assert_eq!(report.outcomes[1].status, ReplayStatus::Missing);
+    assert_eq!(read(dir.path(), "a"), "a");
+    assert_eq!(Journal::incomplete(&journals).unwrap().len(), 1);
+
+    fs::rename(dir.path().join("elsewhere"), dir.path().join("c")).unwrap();
+    let report = journal.recover(&journals, Recovery::Rollback).unwrap();
+    assert_eq!((report.done, report.problems), (1, 0));
+    assert_eq!(read(dir.path(), "a"), "a");
+    assert_eq!(read(dir.path(), "b"), "b");
+    assert_eq!(read(dir.path(), "x"), "x");
+    assert!(!dir.path().join("c").exists());
+    assert!(Journal::incomplete(&journals).unwrap().is_empty());
+}
+
+#[test]
+fn finished_batches_leave_no_journal() {
+    let dir = tempfile::tempdir().unwrap();
+    let journals = dir.path().join("journal");
+    fs::write(dir.path().join("a"), "a").unwrap();
+    let batch = plan(
+        vec![RenameOp::new(dir.path().join("a"), dir.path().join("b"))],
+        &PlanOptions::default(),
+    );
+    assert!(
+        apply(&batch, &ApplyOptions::default(), &journals)
+            .unwrap()
+            .committed
+    );
+    assert!(Journal::incomplete(&journals).unwrap().is_empty());
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use chrono::{Local, Locale, TimeZone};
+use renamer_core::{locale, Error, FileContext, Preset, Rule};
+
+fn ctx(path: &Path) -> FileContext<'_> {
+    let mut ctx = FileContext::new(path, 0);
+    ctx.size = Some(1536);
+    ctx.modified = Some(Local.with_ymd_and_hms(2024, 3, 5, 9, 0, 0).unwrap());
+    ctx
+}
+
+#[test]
+fn language_tags_name_locales() {
+    assert!(locale::parse("de").unwrap() == Locale::de_DE);
+    assert!(locale::parse("de-AT").unwrap() == Locale::de_AT);
+    assert!(locale::parse("pt_BR.UTF-8").unwrap() == Locale::pt_BR);
+    assert!(locale::parse("sv").unwrap() == Locale::sv_SE);
+    assert!(locale::parse("C").unwrap() == Locale::POSIX);
+    match locale::parse("xx-YY") {
+        Err(Error::UnknownLocale { locale }) => assert_eq!(locale, "xx-YY"),
+        other => panic!("expected an unknown locale, got {:?}", other.is_ok()),
+    }
+}
+
+#[test]
+fn tokens_are_written_in_the_scoped_locale() {
+    let path = Path::new("/scans/a.pdf");
+    let template = renamer_core::Template::parse("{date:dd MMMM yyyy} ({size:human})").unwrap();
+    let german = locale::scoped(Some(Locale::de_DE), || template.render("a.pdf", &ctx(path)));
+    assert_eq!(german, "05 März 2024 (1,5 KB)");
+    let english = locale::scoped(Some(Locale::en_US), || template.render("a.pdf", &ctx(path)));
+    assert_eq!(english, "05 March 2024 (1.5 KB)");
+}
+
+#[test]
+fn a_preset_renames_in_its_own_locale() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    std::fs::write(&file, "a").unwrap();
+    let mut preset = Preset::new(
+        "Rechnungen",
+        vec![Rule::Template {
+            template: "{date:MMMM}.{ext}".into(),
+        }],
+    );
+    preset.locale = Some("fr".into());
+    let month = Local::now()
+        .format_localized("%B", Locale::fr_FR)
+        .to_string();
+    let plan = preset.plan(&[file]).unwrap();
+    assert_eq!(
+        plan.entries[0].target,
+        dir.path().join(format!("{}.txt", month))
+    );
+
+    preset.locale = Some("nowhere".into());
+    assert!(matches!(
+        preset.plan(&[dir.path().join("a.txt")]),
+        Err(Error::UnknownLocale { .. })
+    ));
+}
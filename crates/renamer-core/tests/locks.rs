@@ -0,0 +1,18 @@
+#[cfg(target_os = "linux")]
+#[test]
+fn lock_holders_include_a_process_with_the_file_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("open.txt");
+    let _open = std::fs::File::create(&path).unwrap();
+
+    let holders = renamer_core::lock_holders(&path);
+    let me = holders
+        .iter()
+        .find(|h| h.pid == std::process::id())
+        .expect("this process holds the file");
+    assert!(me.name.is_some());
+
+    let closed = dir.path().join("closed.txt");
+    std::fs::write(&closed, "x").unwrap();
+    assert!(renamer_core::lock_holders(&closed).is_empty());
+}
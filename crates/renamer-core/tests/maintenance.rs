@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use renamer_core::{
+    apply, maintenance, plan, ApplyOptions, DataPaths, DateRange, FileCache, HashAlgorithm,
+    HistoryDb, Journal, PlanOptions, RenameOp, Retention, StorageUsage,
+};
+
+fn set_modified(path: &Path, at: SystemTime) {
+    let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+    file.set_modified(at).unwrap();
+}
+
+#[test]
+fn maintenance_prunes_each_store_to_what_is_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = DataPaths {
+        history_db: dir.path().join("history.db"),
+        journal_dir: dir.path().join("journal"),
+        file_cache_db: dir.path().join("cache/file-cache.db"),
+        thumbnail_dir: dir.path().join("cache/thumbnails"),
+    };
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let batch = plan(
+        vec![RenameOp::new(&a, dir.path().join("b.txt"))],
+        &PlanOptions::default(),
+    );
+    let mut history = HistoryDb::open(&paths.history_db).unwrap();
+    history
+        .record(
+            &apply(&batch, &ApplyOptions::default(), &paths.journal_dir).unwrap(),
+            None,
+        )
+        .unwrap();
+    // A batch cut off before it finished.
+    Journal::for_plan(&batch).write(&paths.journal_dir).unwrap();
+    let cache = FileCache::open(&paths.file_cache_db).unwrap();
+    cache
+        .hash(&dir.path().join("b.txt"), HashAlgorithm::Xxh3)
+        .unwrap();
+    fs::create_dir_all(&paths.thumbnail_dir).unwrap();
+    let now = SystemTime::now();
+    for (i, name) in ["old.jpg", "older.jpg", "newest.jpg"].iter().enumerate() {
+        let preview = paths.thumbnail_dir.join(name);
+        fs::write(&preview, [0u8; 600_000]).unwrap();
+        let age = [60, 120, 0][i];
+        set_modified(&preview, now - Duration::from_secs(age));
+    }
+    std::thread::sleep(Duration::from_millis(5));
+
+    let usage = StorageUsage::measure(&paths);
+    assert_eq!(usage.thumbnails, 1_800_000);
+    assert!(usage.history > 0 && usage.journals > 0 && usage.file_cache > 0);
+
+    let kept = maintenance::run(&Retention::default(), &mut history, &cache, &paths).unwrap();
+    assert_eq!(kept.batches_forgotten, 0);
+    assert_eq!(kept.journals_removed, 0);
+    assert_eq!(kept.cache_entries_dropped, 0);
+    assert_eq!(kept.thumbnails_removed, 0);
+
+    let retention = Retention {
+        history_days: Some(0),
+        journal_days: Some(0),
+        file_cache_days: 0,
+        thumbnail_cache_mb: Some(1),
+    };
+    let report = maintenance::run(&retention, &mut history, &cache, &paths).unwrap();
+    assert_eq!(report.batches_forgotten, 1);
+    assert_eq!(report.journals_removed, 1);
+    assert_eq!(report.cache_entries_dropped, 1);
+    // The previews used longest ago go first.
+    assert_eq!(report.thumbnails_removed, 2);
+    assert!(paths.thumbnail_dir.join("newest.jpg").exists());
+    assert!(report.freed() >= 1_200_000);
+    assert_eq!(report.after, StorageUsage::measure(&paths));
+
+    assert!(history.stats(DateRange::default()).unwrap().batches == 0);
+    assert!(Journal::incomplete(&paths.journal_dir).unwrap().is_empty());
+}
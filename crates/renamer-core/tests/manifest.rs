@@ -0,0 +1,110 @@
+use std::fs;
+
+use renamer_core::{
+    apply, plan, ApplyOptions, JsonManifest, ManifestFormat, PlanOptions, RenameOp, JSON_MANIFEST,
+    SHA256SUMS,
+};
+use sha2::{Digest, Sha256};
+
+fn sha256(data: &str) -> String {
+    Sha256::digest(data.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[test]
+fn sha256sums_are_updated_with_the_renamed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("IMG_0001.jpg"), "first").unwrap();
+    fs::write(dir.path().join("IMG_0002.jpg"), "second").unwrap();
+    let kept = format!("{}  notes.txt\n", sha256("notes"));
+    fs::write(
+        dir.path().join(SHA256SUMS),
+        format!("{}{}  IMG_0001.jpg\n", kept, sha256("first")),
+    )
+    .unwrap();
+
+    let ops = vec![
+        RenameOp::new(
+            dir.path().join("IMG_0001.jpg"),
+            dir.path().join("Beach 1.jpg"),
+        ),
+        RenameOp::new(
+            dir.path().join("IMG_0002.jpg"),
+            dir.path().join("Beach 2.jpg"),
+        ),
+    ];
+    let options = ApplyOptions {
+        manifest: Some(ManifestFormat::Sha256Sums),
+        ..ApplyOptions::default()
+    };
+    let report = apply(
+        &plan(ops, &PlanOptions::default()),
+        &options,
+        &dir.path().join("j"),
+    )
+    .unwrap();
+
+    assert_eq!(report.manifests.len(), 1);
+    assert_eq!(report.manifests[0].path, dir.path().join(SHA256SUMS));
+    assert_eq!(report.manifests[0].files, 2);
+    assert_eq!(report.manifests[0].error, None);
+    // The old name is gone, the other entry kept, and the lines sorted.
+    assert_eq!(
+        fs::read_to_string(dir.path().join(SHA256SUMS)).unwrap(),
+        format!(
+            "{}  Beach 1.jpg\n{}  Beach 2.jpg\n{}",
+            sha256("first"),
+            sha256("second"),
+            kept
+        )
+    );
+}
+
+#[test]
+fn json_manifests_record_size_and_batch() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("scan.pdf"), "contract").unwrap();
+    let options = ApplyOptions {
+        manifest: Some(ManifestFormat::Json),
+        ..ApplyOptions::default()
+    };
+    let report = apply(
+        &plan(
+            vec![RenameOp::new(
+                dir.path().join("scan.pdf"),
+                dir.path().join("2024-05 Lease.pdf"),
+            )],
+            &PlanOptions::default(),
+        ),
+        &options,
+        &dir.path().join("j"),
+    )
+    .unwrap();
+
+    let manifest: JsonManifest =
+        serde_json::from_str(&fs::read_to_string(dir.path().join(JSON_MANIFEST)).unwrap()).unwrap();
+    assert_eq!(manifest.files.len(), 1);
+    assert_eq!(manifest.files[0].name, "2024-05 Lease.pdf");
+    assert_eq!(manifest.files[0].size, 8);
+    assert_eq!(manifest.files[0].sha256, sha256("contract"));
+    assert_eq!(manifest.files[0].batch_id, Some(report.batch_id));
+
+    // Without the option nothing is written.
+    fs::remove_file(dir.path().join(JSON_MANIFEST)).unwrap();
+    let report = apply(
+        &plan(
+            vec![RenameOp::new(
+                dir.path().join("2024-05 Lease.pdf"),
+                dir.path().join("Lease.pdf"),
+            )],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &dir.path().join("j"),
+    )
+    .unwrap();
+    assert!(report.manifests.is_empty());
+    assert!(!dir.path().join(JSON_MANIFEST).exists());
+}
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use renamer_core::mapping;
+use renamer_core::{MappingProblemKind, RenameOp};
+
+fn files() -> Vec<PathBuf> {
+    [
+        "/scans/a/invoice 1.pdf",
+        "/scans/b/invoice 1.pdf",
+        "/scans/a/receipt.pdf",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect()
+}
+
+#[test]
+fn rows_become_renames_of_the_matching_files() {
+    let csv = "\u{feff}Old Name,New Name\n\
+               receipt.pdf,\"Receipt, March.pdf\"\n\
+               b/invoice 1.pdf,2024/INV-0001.pdf\n\
+               invoice 1.pdf,INV-0002.pdf\n\
+               missing.pdf,x.pdf\n\
+               receipt.pdf,again.pdf\n\
+               a/invoice 1.pdf\n";
+    let found = mapping::parse(csv, &files()).unwrap();
+    assert_eq!(
+        found.ops,
+        [
+            RenameOp::new("/scans/a/receipt.pdf", "/scans/a/Receipt, March.pdf"),
+            RenameOp::new("/scans/b/invoice 1.pdf", "/scans/b/2024/INV-0001.pdf"),
+        ]
+    );
+    let problems: Vec<_> = found.problems.iter().map(|p| (p.line, p.kind)).collect();
+    assert_eq!(
+        problems,
+        [
+            (4, MappingProblemKind::Ambiguous),
+            (5, MappingProblemKind::NotFound),
+            (6, MappingProblemKind::Duplicate),
+            (7, MappingProblemKind::MissingNewName),
+        ]
+    );
+}
+
+#[test]
+fn tabs_and_semicolons_are_recognised() {
+    let tsv = mapping::parse("receipt.pdf\tr;1.pdf\n", &files()).unwrap();
+    assert_eq!(tsv.ops[0].target, PathBuf::from("/scans/a/r;1.pdf"));
+
+    let excel = mapping::parse("old;new\nreceipt.pdf;r,1.pdf\n", &files()).unwrap();
+    assert_eq!(excel.ops[0].target, PathBuf::from("/scans/a/r,1.pdf"));
+}
@@ -0,0 +1,922 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{TimeZone, Utc};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Rational, Tag, Value};
+use renamer_core::{extract_all, preview, DateSource, MetaValue, Rule};
+
+fn ascii(tag: Tag, text: &str) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+    }
+}
+
+/// A bare TIFF carrying only EXIF fields.
+fn write_photo(path: &Path) {
+    let fields = [
+        ascii(Tag::Make, "Canon"),
+        ascii(Tag::Model, "Canon EOS R5"),
+        ascii(Tag::DateTimeOriginal, "2023:07:14 10:15:00"),
+        ascii(Tag::LensModel, "RF24-105mm F4 L IS USM"),
+        Field {
+            tag: Tag::PhotographicSensitivity,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![400]),
+        },
+    ];
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut buf = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buf, false).unwrap();
+    fs::write(path, buf.into_inner()).unwrap();
+}
+
+/// A bare TIFF with only a GPS position, given as degrees, minutes and
+/// seconds.
+fn write_geotagged(path: &Path, lat: ([u32; 3], &str), lon: ([u32; 3], &str)) {
+    let dms = |tag: Tag, parts: [u32; 3]| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Rational(
+            parts
+                .iter()
+                .map(|&n| Rational { num: n, denom: 1 })
+                .collect(),
+        ),
+    };
+    let fields = [
+        dms(Tag::GPSLatitude, lat.0),
+        ascii(Tag::GPSLatitudeRef, lat.1),
+        dms(Tag::GPSLongitude, lon.0),
+        ascii(Tag::GPSLongitudeRef, lon.1),
+    ];
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut buf = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buf, false).unwrap();
+    fs::write(path, buf.into_inner()).unwrap();
+}
+
+fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend((body.len() as u32).to_le_bytes());
+    out.extend(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+    out
+}
+
+/// A tenth of a second of silence with a RIFF INFO tag.
+fn write_song(path: &Path, info: &[(&[u8], &str)]) {
+    let mut format = Vec::new();
+    for (value, size) in [(1u32, 2), (1, 2), (8000, 4), (16000, 4), (2, 2), (16, 2)] {
+        format.extend(&value.to_le_bytes()[..size]);
+    }
+    let mut list = b"INFO".to_vec();
+    for (id, text) in info {
+        list.extend(chunk(id, format!("{}\0", text).as_bytes()));
+    }
+    let mut wave = b"WAVE".to_vec();
+    wave.extend(chunk(b"fmt ", &format));
+    wave.extend(chunk(b"data", &[0; 1600]));
+    wave.extend(chunk(b"LIST", &list));
+    fs::write(path, chunk(b"RIFF", &wave)).unwrap();
+}
+
+fn atom(kind: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let body = parts.concat();
+    let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend(kind);
+    out.extend(body);
+    out
+}
+
+/// A 65 second 1920x1080 H.264 movie with a sound track listed first, and
+/// no actual media.
+fn write_movie(path: &Path) {
+    let created = 1_689_336_000u32 + 2_082_844_800; // 2023-07-14 12:00 UTC
+    let mut mvhd = vec![0; 4];
+    for field in [created, created, 1000, 65_400] {
+        mvhd.extend(field.to_be_bytes());
+    }
+    mvhd.extend([0; 80]);
+    let mut tkhd = vec![0; 40];
+    for field in [0x1_0000u32, 0, 0, 0, 0x1_0000, 0, 0, 0, 0x4000_0000] {
+        tkhd.extend(field.to_be_bytes());
+    }
+    tkhd.extend((1920u32 << 16).to_be_bytes());
+    tkhd.extend((1080u32 << 16).to_be_bytes());
+    let hdlr = |kind: &[u8]| atom(b"hdlr", &[&[0; 8], kind, &[0; 13]]);
+    let stsd = atom(
+        b"stsd",
+        &[&[0, 0, 0, 0, 0, 0, 0, 1], &atom(b"avc1", &[&[0; 8]])],
+    );
+    let sound = atom(b"trak", &[&atom(b"mdia", &[&hdlr(b"soun")])]);
+    let picture = atom(
+        b"trak",
+        &[
+            &atom(b"tkhd", &[&tkhd]),
+            &atom(
+                b"mdia",
+                &[&hdlr(b"vide"), &atom(b"minf", &[&atom(b"stbl", &[&stsd])])],
+            ),
+        ],
+    );
+    let moov = atom(b"moov", &[&atom(b"mvhd", &[&mvhd]), &sound, &picture]);
+    let file = [atom(b"ftyp", &[b"isom"]), atom(b"mdat", &[&[0; 64]]), moov];
+    fs::write(path, file.concat()).unwrap();
+}
+
+fn ebml(id: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let body = parts.concat();
+    let mut out = id.to_vec();
+    out.push(0x01);
+    out.extend(&(body.len() as u64).to_be_bytes()[1..]);
+    out.extend(body);
+    out
+}
+
+/// A 90 second 1280x720 VP9 Matroska file of unknown length, as live
+/// recordings are written.
+fn write_matroska(path: &Path) {
+    let epoch = Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).unwrap();
+    let created = Utc.with_ymd_and_hms(2024, 6, 2, 12, 0, 0).unwrap();
+    let nanos = (created - epoch).num_nanoseconds().unwrap();
+    let info = ebml(
+        &[0x15, 0x49, 0xa9, 0x66],
+        &[
+            &ebml(&[0x2a, 0xd7, 0xb1], &[&[0x0f, 0x42, 0x40]]),
+            &ebml(&[0x44, 0x89], &[&90_400f64.to_be_bytes()]),
+            &ebml(&[0x44, 0x61], &[&nanos.to_be_bytes()]),
+        ],
+    );
+    let video = ebml(
+        &[0xe0],
+        &[
+            &ebml(&[0xb0], &[&1280u16.to_be_bytes()]),
+            &ebml(&[0xba], &[&720u16.to_be_bytes()]),
+        ],
+    );
+    let tracks = ebml(
+        &[0x16, 0x54, 0xae, 0x6b],
+        &[&ebml(
+            &[0xae],
+            &[&ebml(&[0x83], &[&[1]]), &ebml(&[0x86], &[b"V_VP9"]), &video],
+        )],
+    );
+    let mut file = ebml(
+        &[0x1a, 0x45, 0xdf, 0xa3],
+        &[&ebml(&[0x42, 0x82], &[b"webm"])],
+    );
+    file.extend([
+        0x18, 0x53, 0x80, 0x67, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    ]);
+    file.extend(ebml(&[0xec], &[&[0; 4]]));
+    file.extend(info);
+    file.extend(tracks);
+    file.extend(ebml(&[0x1f, 0x43, 0xb6, 0x75], &[&[0; 64]]));
+    fs::write(path, file).unwrap();
+}
+
+fn write_pdf(path: &Path, objects: &[&str], trailer: &str) {
+    let mut pdf = String::from("%PDF-1.7\n");
+    for (n, object) in objects.iter().enumerate() {
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", n + 1, object));
+    }
+    pdf.push_str(&format!("trailer\n<< {} >>\n%%EOF\n", trailer));
+    fs::write(path, pdf).unwrap();
+}
+
+/// A zip archive with `[Content_Types].xml` stored and `docProps/core.xml`
+/// deflated, like the documents Office writes.
+fn write_office(path: &Path, core: &str) {
+    let mut deflated = DeflateEncoder::new(Vec::new(), Compression::default());
+    deflated.write_all(core.as_bytes()).unwrap();
+    let entries = [
+        ("[Content_Types].xml", 0u16, b"<Types/>".to_vec(), 8),
+        (
+            "docProps/core.xml",
+            8,
+            deflated.finish().unwrap(),
+            core.len(),
+        ),
+    ];
+    let (mut file, mut directory) = (Vec::new(), Vec::new());
+    for (name, method, data, size) in entries {
+        let offset = file.len() as u32;
+        let sizes = [
+            (data.len() as u32).to_le_bytes(),
+            (size as u32).to_le_bytes(),
+        ]
+        .concat();
+        file.extend(b"PK\x03\x04\x14\0\0\0");
+        file.extend(method.to_le_bytes());
+        file.extend([0; 8]);
+        file.extend(&sizes);
+        file.extend((name.len() as u16).to_le_bytes());
+        file.extend([0; 2]);
+        file.extend(name.as_bytes());
+        file.extend(&data);
+
+        directory.extend(b"PK\x01\x02\x14\0\x14\0\0\0");
+        directory.extend(method.to_le_bytes());
+        directory.extend([0; 8]);
+        directory.extend(&sizes);
+        directory.extend((name.len() as u16).to_le_bytes());
+        directory.extend([0; 12]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+    let offset = file.len() as u32;
+    file.extend(&directory);
+    file.extend(b"PK\x05\x06\0\0\0\0\x02\0\x02\0");
+    file.extend((directory.len() as u32).to_le_bytes());
+    file.extend(offset.to_le_bytes());
+    file.extend([0; 2]);
+    fs::write(path, file).unwrap();
+}
+
+#[test]
+fn reads_exif_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = dir.path().join("IMG_0001.tif");
+    let notes = dir.path().join("notes.txt");
+    write_photo(&photo);
+    fs::write(&notes, "not a photo").unwrap();
+
+    let found = extract_all(&[photo.clone(), notes]);
+    assert_eq!(found[0].path, photo);
+    assert_eq!(
+        found[0].get("exif.camera"),
+        Some(&MetaValue::Text("Canon EOS R5".into()))
+    );
+    assert_eq!(found[0].get("exif.iso"), Some(&MetaValue::Integer(400)));
+    assert!(found[1].values.is_empty());
+}
+
+/// The TIFF [`Writer`] makes of `fields`.
+fn tiff(fields: &[Field]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let mut buf = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buf, false).unwrap();
+    buf.into_inner()
+}
+
+/// A CR3 holding only its metadata boxes, which keep EXIF fields in IFD0
+/// of a TIFF of their own.
+fn write_cr3(path: &Path) {
+    let in_ifd0 = |field: Field| Field {
+        tag: Tag(Context::Tiff, field.tag.number()),
+        ..field
+    };
+    let cmt1 = tiff(&[ascii(Tag::Make, "Canon"), ascii(Tag::Model, "Canon EOS R6")]);
+    let cmt2 = tiff(&[in_ifd0(ascii(Tag::DateTimeOriginal, "2024:03:02 08:30:00"))]);
+    let canon: &[u8] = &[
+        0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a,
+        0x48,
+    ];
+    let file = [
+        atom(b"ftyp", &[b"crx ", &[0, 0, 0, 1], b"crx isom"]),
+        atom(
+            b"moov",
+            &[&atom(
+                b"uuid",
+                &[canon, &atom(b"CMT1", &[&cmt1]), &atom(b"CMT2", &[&cmt2])],
+            )],
+        ),
+        atom(b"mdat", &[&[0; 16]]),
+    ]
+    .concat();
+    fs::write(path, file).unwrap();
+}
+
+#[test]
+fn reads_exif_fields_from_camera_raws() {
+    let dir = tempfile::tempdir().unwrap();
+    let nef = dir.path().join("DSC_0001.NEF");
+    write_photo(&nef);
+    // ORF is TIFF with a magic number of its own.
+    let orf = dir.path().join("P0001.ORF");
+    let mut olympus = fs::read(&nef).unwrap();
+    olympus[2..4].copy_from_slice(b"OR");
+    fs::write(&orf, olympus).unwrap();
+    let cr3 = dir.path().join("IMG_0001.CR3");
+    write_cr3(&cr3);
+
+    let found = extract_all(&[nef, orf, cr3]);
+    for raw in &found[..2] {
+        assert_eq!(
+            raw.get("exif.camera"),
+            Some(&MetaValue::Text("Canon EOS R5".into()))
+        );
+        assert_eq!(raw.get("exif.iso"), Some(&MetaValue::Integer(400)));
+    }
+    assert_eq!(
+        found[2].get("exif.camera"),
+        Some(&MetaValue::Text("Canon EOS R6".into()))
+    );
+    let taken = chrono::NaiveDate::from_ymd_opt(2024, 3, 2)
+        .unwrap()
+        .and_hms_opt(8, 30, 0)
+        .unwrap();
+    assert_eq!(
+        found[2].get("exif.date_taken"),
+        Some(&MetaValue::Date(taken))
+    );
+}
+
+/// An iPhone-style HEIC holding an image item without data and an `Exif`
+/// item with `tiff` in `mdat`.
+fn write_heic(path: &Path, tiff: &[u8]) {
+    let infe = |id: u8, kind: &[u8]| atom(b"infe", &[&[2, 0, 0, 0, 0, id, 0, 0], kind]);
+    let ftyp = atom(b"ftyp", &[b"heic", &[0; 4], b"mif1heic"]);
+    let iloc = |offset: u32| {
+        let entry = [
+            [0, 2, 0, 0, 0, 1].as_slice(),
+            &offset.to_be_bytes(),
+            &(tiff.len() as u32 + 4).to_be_bytes(),
+        ]
+        .concat();
+        atom(b"iloc", &[&[0; 4], &[0x44, 0, 0, 1], &entry])
+    };
+    let meta = |offset: u32| {
+        atom(
+            b"meta",
+            &[
+                &[0; 4],
+                &atom(b"hdlr", &[&[0; 8], b"pict", &[0; 13]]),
+                &atom(b"pitm", &[&[0; 4], &[0, 1]]),
+                &atom(
+                    b"iinf",
+                    &[&[0; 4], &[0, 2], &infe(1, b"hvc1"), &infe(2, b"Exif")],
+                ),
+                &iloc(offset),
+            ],
+        )
+    };
+    let offset = (ftyp.len() + meta(0).len() + 8) as u32;
+    // The Exif item starts with the offset of the TIFF header after it.
+    let mdat = atom(b"mdat", &[&[0; 4], tiff]);
+    fs::write(path, [ftyp, meta(offset), mdat].concat()).unwrap();
+}
+
+#[test]
+fn reads_exif_fields_from_heic_photos() {
+    let dir = tempfile::tempdir().unwrap();
+    let heic = dir.path().join("IMG_4021.HEIC");
+    write_heic(
+        &heic,
+        &tiff(&[
+            ascii(Tag::Make, "Apple"),
+            ascii(Tag::Model, "iPhone 15 Pro"),
+            ascii(Tag::DateTimeOriginal, "2024:08:09 19:45:12"),
+        ]),
+    );
+
+    let found = extract_all(&[heic]);
+    assert_eq!(
+        found[0].get("exif.camera"),
+        Some(&MetaValue::Text("Apple iPhone 15 Pro".into()))
+    );
+    let taken = chrono::NaiveDate::from_ymd_opt(2024, 8, 9)
+        .unwrap()
+        .and_hms_opt(19, 45, 12)
+        .unwrap();
+    assert_eq!(
+        found[0].get("exif.date_taken"),
+        Some(&MetaValue::Date(taken))
+    );
+}
+
+#[test]
+fn templates_render_exif_tokens_and_leave_missing_ones_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = dir.path().join("IMG_0001.tif");
+    let notes = dir.path().join("notes.txt");
+    write_photo(&photo);
+    fs::write(&notes, "not a photo").unwrap();
+
+    let rows = preview(
+        &[photo, notes],
+        &[Rule::Template {
+            template:
+                "{exif.date_taken:yyyyMMdd-HHmm}_{exif.camera}_{exif.iso:5}_{exif.lens}.{ext}"
+                    .into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(
+        rows[0].new_name,
+        "20230714-1015_Canon EOS R5_00400_RF24-105mm F4 L IS USM.tif"
+    );
+    assert_eq!(rows[1].new_name, "___.txt");
+
+    let err = preview(
+        &[],
+        &[Rule::Template {
+            template: "{exif.camera:upper}".into(),
+        }],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("takes no options"));
+}
+
+#[test]
+fn best_date_falls_back_through_xmp_modification_time_and_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = dir.path().join("a.tif");
+    write_photo(&photo);
+    let embedded = dir.path().join("b.jpg");
+    fs::write(
+        &embedded,
+        concat!(
+            "\u{ff}\u{d8}<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF><rdf:Description ",
+            "xmp:CreateDate=\"2021-03-04T05:06:07\"/></rdf:RDF></x:xmpmeta>",
+        ),
+    )
+    .unwrap();
+    let sidecar = dir.path().join("c.png");
+    fs::write(&sidecar, "not quite a png").unwrap();
+    fs::write(
+        dir.path().join("c.xmp"),
+        concat!(
+            "<x:xmpmeta><rdf:Description><xmp:CreateDate>2018-01-01</xmp:CreateDate>",
+            "<exif:DateTimeOriginal>2020-01-02T03:04:05</exif:DateTimeOriginal>",
+            "</rdf:Description></x:xmpmeta>",
+        ),
+    )
+    .unwrap();
+    let named = dir.path().join("holiday 2019-08-09.txt");
+    let file = fs::File::create(&named).unwrap();
+    let modified = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    file.set_modified(modified.into()).unwrap();
+    let paths = [photo, embedded, sidecar, named];
+
+    let dates = |template: &str| {
+        let rows = preview(
+            &paths,
+            &[Rule::Template {
+                template: template.into(),
+            }],
+        )
+        .unwrap();
+        rows.into_iter()
+            .map(|r| (r.new_name, r.date_source))
+            .collect::<Vec<_>>()
+    };
+    let local = modified.with_timezone(&chrono::Local).format("%Y-%m-%d");
+    assert_eq!(
+        dates("{best_date:yyyy-MM-dd}"),
+        [
+            ("2023-07-14".to_string(), Some(DateSource::Exif)),
+            ("2021-03-04".to_string(), Some(DateSource::Xmp)),
+            ("2020-01-02".to_string(), Some(DateSource::Xmp)),
+            (local.to_string(), Some(DateSource::Modified)),
+        ]
+    );
+    assert_eq!(
+        dates("{best_date:yyyy@from(exif, name)|undated}"),
+        [
+            ("2023".to_string(), Some(DateSource::Exif)),
+            ("undated".to_string(), None),
+            ("undated".to_string(), None),
+            ("2019".to_string(), Some(DateSource::Name)),
+        ]
+    );
+    for bad in [
+        "{best_date@from(film)}",
+        "{best_date@from()}",
+        "{name@from(exif)}",
+    ] {
+        let rules = [Rule::Template {
+            template: bad.into(),
+        }];
+        assert!(preview(&[], &rules).is_err(), "{bad} should fail");
+    }
+}
+
+#[test]
+fn names_the_place_a_photo_was_taken_without_network_access() {
+    let dir = tempfile::tempdir().unwrap();
+    let paris = dir.path().join("paris.tif");
+    let sydney = dir.path().join("sydney.tif");
+    let ocean = dir.path().join("ocean.tif");
+    write_geotagged(&paris, ([48, 51, 24], "N"), ([2, 21, 7], "E"));
+    write_geotagged(&sydney, ([33, 52, 7], "S"), ([151, 12, 33], "E"));
+    write_geotagged(&ocean, ([30, 0, 0], "S"), ([100, 0, 0], "W"));
+
+    let rows = preview(
+        &[paris, sydney, ocean],
+        &[Rule::Template {
+            template: "{exif.gps.city|Somewhere}, {exif.gps.country|Nowhere}".into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "Paris, France");
+    assert_eq!(rows[1].new_name, "Sydney, Australia");
+    assert_eq!(rows[2].new_name, "Somewhere, Nowhere");
+}
+
+#[test]
+fn reads_song_tags_with_fallbacks_for_missing_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let tagged = dir.path().join("track03.wav");
+    let bare = dir.path().join("untitled.wav");
+    write_song(
+        &tagged,
+        &[
+            (b"IART", "Nina Simone"),
+            (b"IPRD", "Pastel Blues"),
+            (b"INAM", "Sinnerman"),
+            (b"IPRT", "3/7"),
+        ],
+    );
+    write_song(&bare, &[(b"INAM", "Demo")]);
+
+    let found = extract_all(std::slice::from_ref(&tagged));
+    assert_eq!(found[0].get("tag.track"), Some(&MetaValue::Integer(3)));
+
+    let rows = preview(
+        &[tagged, bare],
+        &[Rule::Template {
+            template: "{tag.track:02|00} {tag.artist|Unknown Artist} - {tag.title}.{ext}".into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "03 Nina Simone - Sinnerman.wav");
+    assert_eq!(rows[1].new_name, "00 Unknown Artist - Demo.wav");
+
+    let err = preview(
+        &[],
+        &[Rule::Template {
+            template: "{name|untitled}".into(),
+        }],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("cannot take a fallback"));
+}
+
+#[test]
+fn reads_video_headers_from_mp4_and_matroska() {
+    let dir = tempfile::tempdir().unwrap();
+    let movie = dir.path().join("MVI_0001.mp4");
+    let clip = dir.path().join("clip.webm");
+    write_movie(&movie);
+    write_matroska(&clip);
+
+    let found = extract_all(std::slice::from_ref(&movie));
+    assert_eq!(found[0].get("video.width"), Some(&MetaValue::Integer(1920)));
+    assert_eq!(
+        found[0].get("video.duration"),
+        Some(&MetaValue::Integer(65))
+    );
+
+    let rows = preview(
+        &[movie, clip],
+        &[Rule::Template {
+            template: "{video.created:yyyy-MM}_{video.width}x{video.height}_{video.duration:4}s_{video.codec}.{ext}"
+                .into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "2023-07_1920x1080_0065s_h264.mp4");
+    assert_eq!(rows[1].new_name, "2024-06_1280x720_0090s_vp9.webm");
+}
+
+#[test]
+fn reads_pdf_and_office_document_properties() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = dir.path().join("scan0001.pdf");
+    let notes = dir.path().join("scan0002.pdf");
+    let minutes = dir.path().join("Document1.docx");
+    write_pdf(
+        &report,
+        &[
+            "<< /Type /Catalog /Pages 2 0 R /Outlines 3 0 R >>",
+            "<< /Type /Pages /Kids [] /Count 0 >>",
+            "<< /Title (Chapter 1) /Parent 2 0 R >>",
+            "<< /Title (Quarterly Report \\(Q3\\)) /Author <FEFF005A006F00EB0020004E0067> \
+             /CreationDate (D:20230714101500+02'00') >>",
+        ],
+        "/Root 1 0 R /Info 4 0 R",
+    );
+    write_pdf(
+        &notes,
+        &[
+            "<< /Type /Catalog /Metadata 2 0 R >>",
+            "<< /Type /Metadata /Subtype /XML >>\nstream\n<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+             <rdf:RDF><rdf:Description xmp:CreateDate=\"2022-01-05T09:00:00+01:00\">\
+             <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">Field Notes &amp; Sketches</rdf:li>\
+             </rdf:Alt></dc:title><dc:creator><rdf:Seq><rdf:li>Ada</rdf:li><rdf:li>Ben</rdf:li>\
+             </rdf:Seq></dc:creator></rdf:Description></rdf:RDF></x:xmpmeta>\nendstream",
+        ],
+        "/Root 1 0 R",
+    );
+    write_office(
+        &minutes,
+        "<?xml version=\"1.0\"?><cp:coreProperties><dc:title>Minutes</dc:title>\
+         <dc:creator>Sam Lee</dc:creator><cp:lastModifiedBy>Someone</cp:lastModifiedBy>\
+         <dcterms:created xsi:type=\"dcterms:W3CDTF\">2024-06-14T12:00:00Z</dcterms:created>\
+         </cp:coreProperties>",
+    );
+
+    let rows = preview(
+        &[report, notes, minutes],
+        &[Rule::Template {
+            template: "{doc.date:yyyy-MM} - {doc.author} - {doc.title}.{ext}".into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(
+        rows[0].new_name,
+        "2023-07 - Zoë Ng - Quarterly Report (Q3).pdf"
+    );
+    assert_eq!(
+        rows[1].new_name,
+        "2022-01 - Ada - Field Notes & Sketches.pdf"
+    );
+    assert_eq!(rows[2].new_name, "2024-06 - Sam Lee - Minutes.docx");
+}
+
+/// An Outlook `.msg`: a compound file with 512 byte sectors holding
+/// `streams` in its root storage, all in the mini stream.
+fn write_msg(path: &Path, streams: &[(&str, Vec<u8>)]) {
+    const END: u32 = 0xffff_fffe;
+    const NONE: u32 = 0xffff_ffff;
+    let dir_sectors = (streams.len() + 1).div_ceil(4) as u32;
+    let mini_fat_at = 1 + dir_sectors;
+    let mut mini_stream = Vec::new();
+    let mut mini_fat = Vec::new();
+    let mut starts = Vec::new();
+    for (_, data) in streams {
+        let first = (mini_stream.len() / 64) as u32;
+        let count = data.len().div_ceil(64) as u32;
+        starts.push(first);
+        mini_fat.extend((1..=count).map(|i| if i == count { END } else { first + i }));
+        mini_stream.extend_from_slice(data);
+        mini_stream.resize(mini_stream.len().div_ceil(64) * 64, 0);
+    }
+    mini_fat.resize(128, NONE);
+    let mini_at = mini_fat_at + 1;
+    let mini_sectors = mini_stream.len().div_ceil(512) as u32;
+
+    let mut fat = vec![0xffff_fffd];
+    fat.extend((1..=dir_sectors).map(|i| if i == dir_sectors { END } else { i + 1 }));
+    fat.push(END);
+    fat.extend((0..mini_sectors).map(|i| {
+        if i + 1 == mini_sectors {
+            END
+        } else {
+            mini_at + i + 1
+        }
+    }));
+    fat.resize(128, NONE);
+
+    let entry = |name: &str, kind: u8, right: u32, child: u32, start: u32, size: usize| {
+        let mut e = vec![0u8; 128];
+        let units: Vec<u16> = name.encode_utf16().chain([0]).collect();
+        for (i, unit) in units.iter().enumerate() {
+            e[i * 2..i * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        e[64..66].copy_from_slice(&((units.len() * 2) as u16).to_le_bytes());
+        e[66] = kind;
+        e[68..72].copy_from_slice(&NONE.to_le_bytes());
+        e[72..76].copy_from_slice(&right.to_le_bytes());
+        e[76..80].copy_from_slice(&child.to_le_bytes());
+        e[116..120].copy_from_slice(&start.to_le_bytes());
+        e[120..124].copy_from_slice(&(size as u32).to_le_bytes());
+        e
+    };
+    let mut directory = entry("Root Entry", 5, NONE, 1, mini_at, mini_stream.len());
+    for (i, (name, data)) in streams.iter().enumerate() {
+        let right = if i + 1 < streams.len() {
+            i as u32 + 2
+        } else {
+            NONE
+        };
+        directory.extend(entry(name, 2, right, NONE, starts[i], data.len()));
+    }
+    directory.resize(dir_sectors as usize * 512, 0);
+
+    let mut file = vec![0u8; 512];
+    file[..8].copy_from_slice(&[0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1]);
+    let fields: [(usize, u32); 8] = [
+        (0x2c, 1),
+        (0x30, 1),
+        (0x38, 4096),
+        (0x3c, mini_fat_at),
+        (0x40, 1),
+        (0x44, END),
+        (0x48, 0),
+        (0x4c, 0),
+    ];
+    for (at, value) in fields {
+        file[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    for at in (0x50..512).step_by(4) {
+        file[at..at + 4].copy_from_slice(&NONE.to_le_bytes());
+    }
+    file[0x18..0x20].copy_from_slice(&[0x3e, 0, 3, 0, 0xfe, 0xff, 9, 0]);
+    file[0x20] = 6;
+    file.extend(fat.iter().flat_map(|v| v.to_le_bytes()));
+    file.extend(directory);
+    file.extend(mini_fat.iter().flat_map(|v| v.to_le_bytes()));
+    mini_stream.resize(mini_sectors as usize * 512, 0);
+    file.extend(mini_stream);
+    fs::write(path, file).unwrap();
+}
+
+fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+#[test]
+fn reads_sender_subject_and_date_of_saved_email() {
+    let dir = tempfile::tempdir().unwrap();
+    let eml = dir.path().join("message.eml");
+    fs::write(
+        &eml,
+        concat!(
+            "From ada@example.org Tue Mar 14 09:30:00 2023\r\n",
+            "Received: from mail.example.org\r\n",
+            "From: =?UTF-8?Q?J=C3=BCrgen_M=C3=BCller?= <jm@example.org>\r\n",
+            "Subject: =?UTF-8?B?UXVhcnRlcmx5?=\r\n",
+            " =?UTF-8?B?IHLDqXN1bcOp?=\r\n",
+            "Date: Tue, 14 Mar 2023 09:30:00 +0100 (CET)\r\n",
+            "\r\n",
+            "Subject: not a header\r\n",
+        ),
+    )
+    .unwrap();
+    let bare = dir.path().join("bare.eml");
+    fs::write(&bare, "From: ops@example.org\nSubject: Alert\n\nbody\n").unwrap();
+    let not_mail = dir.path().join("notes.txt");
+    fs::write(&not_mail, "Subject: looks like one\n\n").unwrap();
+
+    let submitted = Utc.with_ymd_and_hms(2024, 2, 1, 16, 45, 0).unwrap();
+    let ticks = (submitted.timestamp() + 11_644_473_600) * 10_000_000;
+    let mut properties = vec![0u8; 32];
+    properties.extend(0x0039_0040u32.to_le_bytes());
+    properties.extend(6u32.to_le_bytes());
+    properties.extend(ticks.to_le_bytes());
+    let msg = dir.path().join("saved.msg");
+    write_msg(
+        &msg,
+        &[
+            ("__substg1.0_0037001F", utf16("Contract signed")),
+            ("__substg1.0_0C1A001F", utf16("Jane Doe")),
+            ("__substg1.0_0C1F001F", utf16("/O=EXCHANGE/CN=JDOE")),
+            ("__substg1.0_5D01001F", utf16("jane@example.com")),
+            ("__properties_version1.0", properties),
+        ],
+    );
+
+    let rows = preview(
+        &[eml, bare, not_mail, msg],
+        &[Rule::Template {
+            template: "{mail.date:yyyy-MM-dd HHmm} {mail.from} ({mail.from_address}) - {mail.subject}.{ext}"
+                .into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(
+        rows[0].new_name,
+        "2023-03-14 0930 Jürgen Müller (jm@example.org) - Quarterly résumé.eml"
+    );
+    assert_eq!(
+        rows[1].new_name,
+        " ops@example.org (ops@example.org) - Alert.eml"
+    );
+    assert_eq!(rows[2].new_name, "  () - .txt");
+    let sent = submitted
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H%M");
+    assert_eq!(
+        rows[3].new_name,
+        format!("{} Jane Doe (jane@example.com) - Contract signed.msg", sent)
+    );
+}
+
+/// A JPEG-shaped file: JFIF header, the EXIF of [`write_photo`] and a stub
+/// of image data.
+fn write_jpeg(path: &Path) {
+    let tiff_path = path.with_extension("tif");
+    write_photo(&tiff_path);
+    let tiff = fs::read(&tiff_path).unwrap();
+    fs::remove_file(&tiff_path).unwrap();
+    let segment = |marker: u8, payload: &[u8]| {
+        let mut out = vec![0xFF, marker];
+        out.extend((payload.len() as u16 + 2).to_be_bytes());
+        out.extend(payload);
+        out
+    };
+    let mut file = vec![0xFF, 0xD8];
+    file.extend(segment(0xE0, b"JFIF\0\x01\x02\0\0\x01\0\x01\0\0"));
+    file.extend(segment(0xE1, &[b"Exif\0\0".as_slice(), &tiff].concat()));
+    file.extend(segment(0xDA, &[1, 1, 0, 0, 63, 0]));
+    file.extend([0x12, 0x34, 0xFF, 0xD9]);
+    fs::write(path, file).unwrap();
+}
+
+#[test]
+fn write_title_puts_the_new_name_into_songs_and_photos() {
+    use chrono::TimeZone;
+    use renamer_core::{apply, plan, ApplyOptions, PlanOptions};
+
+    let dir = tempfile::tempdir().unwrap();
+    let song = dir.path().join("track03.wav");
+    let photo = dir.path().join("IMG_0001.jpg");
+    let notes = dir.path().join("notes.txt");
+    write_song(&song, &[(b"INAM", "Untitled"), (b"IART", "Nina Simone")]);
+    write_jpeg(&photo);
+    fs::write(&notes, "no title here").unwrap();
+    let noon = Utc.with_ymd_and_hms(2021, 6, 1, 12, 0, 0).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&photo)
+        .unwrap()
+        .set_modified(noon.into())
+        .unwrap();
+
+    let rows = preview(
+        &[song, photo, notes],
+        &[
+            Rule::Prefix {
+                text: "Sinnerman & co ".into(),
+            },
+            Rule::WriteTitle,
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        rows[0].write_title.as_deref(),
+        Some("Sinnerman & co track03")
+    );
+    let ops = rows.iter().map(|r| r.to_op()).collect();
+    let report = apply(
+        &plan(ops, &PlanOptions::default()),
+        &ApplyOptions::default(),
+        dir.path(),
+    )
+    .unwrap();
+    assert!(report.committed);
+    let errors: Vec<_> = report
+        .outcomes
+        .iter()
+        .map(|o| o.title_error.clone())
+        .collect();
+    assert!(errors.iter().all(Option::is_none), "{:?}", errors);
+
+    let song = dir.path().join("Sinnerman & co track03.wav");
+    let photo = dir.path().join("Sinnerman & co IMG_0001.jpg");
+    let found = extract_all(&[song, photo.clone()]);
+    assert_eq!(
+        found[0].get("tag.title"),
+        Some(&MetaValue::Text("Sinnerman & co track03".into()))
+    );
+    assert_eq!(
+        found[0].get("tag.artist"),
+        Some(&MetaValue::Text("Nina Simone".into()))
+    );
+    // The camera's own fields survive next to the description.
+    assert_eq!(
+        found[1].get("exif.camera"),
+        Some(&MetaValue::Text("Canon EOS R5".into()))
+    );
+    let bytes = fs::read(&photo).unwrap();
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(&bytes))
+        .unwrap();
+    let description = exif
+        .get_field(Tag::ImageDescription, In::PRIMARY)
+        .unwrap()
+        .display_value()
+        .to_string();
+    assert_eq!(description, "\"Sinnerman & co IMG_0001\"");
+    let text = String::from_utf8_lossy(&bytes);
+    assert!(text.contains(r#"<rdf:li xml:lang="x-default">Sinnerman &amp; co IMG_0001</rdf:li>"#));
+    assert!(bytes.ends_with(&[0x12, 0x34, 0xFF, 0xD9]));
+    assert_eq!(
+        fs::metadata(&photo).unwrap().modified().unwrap(),
+        std::time::SystemTime::from(noon)
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("Sinnerman & co notes.txt")).unwrap(),
+        "no title here"
+    );
+}
@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use renamer_core::name_date::find;
+use renamer_core::{FileContext, Template};
+
+fn at(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(y, m, d)
+        .unwrap()
+        .and_hms_opt(h, min, s)
+}
+
+#[test]
+fn finds_dates_in_common_forms() {
+    let midnight = at(2024, 1, 31, 0, 0, 0);
+    for name in [
+        "20240131.jpg",
+        "scan 2024-01-31.pdf",
+        "2024_1_31 notes.txt",
+        "Invoice 31 Jan 2024.pdf",
+        "meeting-31st-January-2024.docx",
+        "Jan 31, 2024 - minutes.txt",
+        "receipt 31.01.2024.pdf",
+        "receipt 01-31-2024.pdf",
+    ] {
+        assert_eq!(find(name), midnight, "{}", name);
+    }
+    assert_eq!(find("IMG_20240131_101500.jpg"), at(2024, 1, 31, 10, 15, 0));
+    assert_eq!(find("2024-01-31T10:15.log"), at(2024, 1, 31, 10, 15, 0));
+    assert_eq!(find("05.03.2024"), at(2024, 3, 5, 0, 0, 0));
+}
+
+#[test]
+fn ignores_numbers_that_are_not_dates() {
+    for name in [
+        "IMG_0001.jpg",
+        "order 120240131.txt",
+        "20241301.txt",
+        "1850-01-01.txt",
+        "31 Foo 2024",
+    ] {
+        assert_eq!(find(name), None, "{}", name);
+    }
+    // The leftmost date wins.
+    assert_eq!(find("v 2023-02-01 of 15 Mar 2024"), at(2023, 2, 1, 0, 0, 0));
+}
+
+#[test]
+fn parsed_date_token_normalizes_names() {
+    let template = Template::parse("{parsed_date:yyyy-MM-dd|undated} {name}").unwrap();
+    let ctx = FileContext::new(Path::new("/a/Invoice 31 Jan 2024.pdf"), 0);
+    assert_eq!(
+        template.render("Invoice 31 Jan 2024.pdf", &ctx),
+        "2024-01-31 Invoice 31 Jan 2024"
+    );
+    assert_eq!(template.render("notes.txt", &ctx), "undated notes");
+}
@@ -0,0 +1,62 @@
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use renamer_core::{natural_cmp, sort_natural, ScanOptions, Scanner};
+
+#[test]
+fn numbers_compare_by_value_and_accents_sort_with_their_letter() {
+    let mut names = vec![
+        "file10.txt",
+        "File2.txt",
+        "file2.txt",
+        "file02.txt",
+        "file1.txt",
+        "file.txt",
+        "zebra",
+        "éclair",
+        "eclair",
+        "Echo",
+    ];
+    names.sort_by(|a, b| natural_cmp(a, b, true));
+    assert_eq!(
+        names,
+        [
+            "Echo",
+            "eclair",
+            "éclair",
+            "file.txt",
+            "file1.txt",
+            "File2.txt",
+            "file02.txt",
+            "file2.txt",
+            "file10.txt",
+            "zebra",
+        ]
+    );
+    assert_eq!(natural_cmp("B", "a", false), Ordering::Less);
+    assert_eq!(natural_cmp("B", "a", true), Ordering::Greater);
+    assert_eq!(natural_cmp("v1.10", "v1.9", true), Ordering::Greater);
+}
+
+#[test]
+fn paths_sort_folder_by_folder_and_scans_list_files_that_way() {
+    let mut paths: Vec<PathBuf> = ["/b/1.jpg", "/a10/1.jpg", "/a2/3.jpg", "/a2/20.jpg"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    sort_natural(&mut paths);
+    assert_eq!(
+        paths,
+        ["/a2/3.jpg", "/a2/20.jpg", "/a10/1.jpg", "/b/1.jpg"].map(PathBuf::from)
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["IMG_10.jpg", "IMG_9.jpg", "img_100.jpg"] {
+        std::fs::write(dir.path().join(name), "x").unwrap();
+    }
+    let page = Scanner::new(dir.path(), ScanOptions::default())
+        .unwrap()
+        .next_page(10);
+    let names: Vec<_> = page.entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["IMG_9.jpg", "IMG_10.jpg", "img_100.jpg"]);
+}
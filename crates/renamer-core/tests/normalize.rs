@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use renamer_core::normalize::detect;
+use renamer_core::{preview, NormalForm, Normalization, Rule};
+
+const COMPOSED: &str = "Caf\u{e9}.txt";
+const DECOMPOSED: &str = "Cafe\u{301}.txt";
+
+#[test]
+fn normalize_rule_converts_between_forms() {
+    let rows = preview(
+        &[PathBuf::from(format!("/mac/{}", DECOMPOSED))],
+        &[Rule::Normalize {
+            form: NormalForm::Nfc,
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, COMPOSED);
+    assert!(rows[0].changed);
+
+    let rows = preview(
+        &[PathBuf::from(format!("/win/{}", COMPOSED))],
+        &[Rule::Normalize {
+            form: NormalForm::Nfd,
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, DECOMPOSED);
+}
+
+#[test]
+fn detects_how_names_are_encoded() {
+    assert_eq!(detect("plain.txt"), None);
+    assert_eq!(detect(COMPOSED), Some(Normalization::Composed));
+    assert_eq!(detect(DECOMPOSED), Some(Normalization::Decomposed));
+    assert_eq!(
+        detect("re\u{301}sume\u{301} \u{e9}t\u{e9}.txt"),
+        Some(Normalization::Mixed)
+    );
+
+    let rows = preview(&[PathBuf::from(format!("/mac/{}", DECOMPOSED))], &[]).unwrap();
+    assert_eq!(rows[0].normalization, Some(Normalization::Decomposed));
+}
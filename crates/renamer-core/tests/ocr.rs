@@ -0,0 +1,71 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use renamer_core::ocr::{self, OcrCommand, OcrEngine};
+use renamer_core::{preview, Rule, Template};
+
+/// Reads the text of a "scan" that is plain text already.
+struct Cat;
+
+impl OcrEngine for Cat {
+    fn recognize(&self, path: &Path) -> Result<String, String> {
+        fs::read_to_string(path).map_err(|e| e.to_string())
+    }
+}
+
+fn template(template: &str) -> Vec<Rule> {
+    vec![Rule::Template {
+        template: template.into(),
+    }]
+}
+
+#[test]
+fn invoices_are_named_from_the_text_printed_on_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let invoice = dir.path().join("scan001.png");
+    fs::write(
+        &invoice,
+        "\n   ACME Supplies Ltd  \nInvoice No. INV-2024-0042\nTotal: 12.00\n",
+    )
+    .unwrap();
+    let blank = dir.path().join("scan002.png");
+    fs::write(&blank, "\n\n").unwrap();
+    let paths = [invoice, blank];
+
+    // The default engine runs a program: here one that prints the file.
+    ocr::set_engine(Some(Arc::new(OcrCommand {
+        program: "cat".into(),
+        args: vec!["{path}".into()],
+        ..OcrCommand::default()
+    })));
+    let rows = preview(
+        &paths,
+        &template("{ocr.match(Invoice No\\.?\\s*([A-Z]+-[0-9-]+))|unnumbered} {ocr.first_line|blank}.{ext}"),
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "INV-2024-0042 ACME Supplies Ltd.png");
+    assert_eq!(rows[1].new_name, "unnumbered blank.png");
+
+    // Without groups, the whole match is taken.
+    ocr::set_engine(Some(Arc::new(Cat)));
+    let rows = preview(&paths[..1], &template("{ocr.match(Total: \\d+)}.{ext}")).unwrap();
+    assert_eq!(rows[0].new_name, "Total: 12.png");
+
+    // A program that fails, or none at all, leaves the fallback.
+    ocr::set_engine(Some(Arc::new(OcrCommand {
+        program: "no-such-ocr-for-sortify".into(),
+        ..OcrCommand::default()
+    })));
+    let rows = preview(&paths[..1], &template("{ocr.first_line|unread}.{ext}")).unwrap();
+    assert_eq!(rows[0].new_name, "unread.png");
+    ocr::set_engine(None);
+    let rows = preview(&paths[..1], &template("{ocr.first_line|off}.{ext}")).unwrap();
+    assert_eq!(rows[0].new_name, "off.png");
+
+    assert!(Template::parse("{ocr.match(unclosed}").is_err());
+    assert!(Template::parse("{ocr.match([)}").is_err());
+    assert!(Template::parse("{ocr.first_line:4}").is_err());
+}
@@ -0,0 +1,577 @@
+use std::fs;
+
+use renamer_core::{
+    apply, plan, ApplyOptions, CollisionStrategy, CompanionRule, EntryStatus, Error, FileId,
+    OutcomeStatus, PlanOptions, PlanWarning, RenameOp, Resolution, Severity, TargetFs,
+};
+
+#[test]
+fn flags_conflicts_and_noops() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    let taken = dir.path().join("taken.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+    fs::write(&taken, "t").unwrap();
+
+    let plan = plan(
+        vec![
+            RenameOp::new(&a, dir.path().join("new.txt")),
+            RenameOp::new(&b, &b),
+            RenameOp::new(dir.path().join("ghost.txt"), dir.path().join("x.txt")),
+            RenameOp::new(&a, &taken),
+        ],
+        &PlanOptions::default(),
+    );
+
+    let statuses: Vec<_> = plan.entries.iter().map(|e| e.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            EntryStatus::Ready,
+            EntryStatus::Unchanged,
+            EntryStatus::MissingSource,
+            EntryStatus::TargetExists,
+        ]
+    );
+    assert!(plan.has_conflicts());
+}
+
+#[test]
+fn entries_carry_graded_diagnostics_and_apply_can_skip_by_severity() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    for name in ["a.txt", "b.txt", "notes.txt", "x.txt", "y.txt"] {
+        fs::write(path(name), name).unwrap();
+    }
+
+    let plan = plan(
+        vec![
+            RenameOp::new(path("a.txt"), path("first.txt")),
+            RenameOp::new(path("b.txt"), path("b.txt")),
+            RenameOp::new(path("ghost.txt"), path("g.txt")),
+            RenameOp::new(path("notes.txt"), path("notes.md")),
+            // Only free because y.txt moves away, which is skipped below.
+            RenameOp::new(path("x.txt"), path("y.txt")),
+            RenameOp::new(path("y.txt"), path("y.md")),
+        ],
+        &PlanOptions::default(),
+    );
+    let codes: Vec<Vec<(Severity, &str)>> = plan
+        .entries
+        .iter()
+        .map(|e| {
+            e.diagnostics
+                .iter()
+                .map(|d| (d.severity, d.code.as_str()))
+                .collect()
+        })
+        .collect();
+    assert_eq!(codes[0], vec![]);
+    assert_eq!(codes[1], vec![(Severity::Info, "unchanged")]);
+    assert_eq!(codes[2], vec![(Severity::Error, "missing_source")]);
+    assert_eq!(codes[3], vec![(Severity::Warning, "extension_changed")]);
+    assert_eq!(plan.entries[3].severity(), Some(Severity::Warning));
+    assert_eq!(plan.entries[0].severity(), None);
+
+    let options = ApplyOptions {
+        skip_above: Some(Severity::Info),
+        ..ApplyOptions::default()
+    };
+    let report = apply(&plan, &options, &dir.path().join("journal")).unwrap();
+    assert!(report.committed);
+    assert_eq!(report.renamed, 1);
+    let statuses: Vec<_> = report.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(statuses[0], OutcomeStatus::Renamed);
+    assert!(statuses[1..].iter().all(|&s| s == OutcomeStatus::Skipped));
+    assert!(path("first.txt").exists());
+    assert!(path("notes.txt").exists());
+    assert_eq!(fs::read_to_string(path("y.txt")).unwrap(), "y.txt");
+    assert!(path("x.txt").exists());
+}
+
+#[test]
+fn detects_duplicate_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+    let target = dir.path().join("same.txt");
+
+    let plan = plan(
+        vec![RenameOp::new(&a, &target), RenameOp::new(&b, &target)],
+        &PlanOptions::default(),
+    );
+
+    assert!(plan
+        .entries
+        .iter()
+        .all(|e| e.status == EntryStatus::DuplicateTarget));
+    assert_eq!(plan.ready().count(), 0);
+}
+
+fn with(collision: CollisionStrategy) -> PlanOptions {
+    PlanOptions {
+        collision,
+        ..PlanOptions::default()
+    }
+}
+
+#[test]
+fn suffix_strategy_picks_free_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    let c = dir.path().join("c.txt");
+    for p in [&a, &b, &c] {
+        fs::write(p, "x").unwrap();
+    }
+    fs::write(dir.path().join("taken (1).txt"), "t").unwrap();
+    let taken = dir.path().join("taken.txt");
+    fs::write(&taken, "t").unwrap();
+    let same = dir.path().join("same.txt");
+
+    let plan = plan(
+        vec![
+            RenameOp::new(&a, &taken),
+            RenameOp::new(&b, &same),
+            RenameOp::new(&c, &same),
+        ],
+        &with(CollisionStrategy::Suffix),
+    );
+
+    let targets: Vec<_> = plan.entries.iter().map(|e| e.target.clone()).collect();
+    assert_eq!(
+        targets,
+        vec![
+            dir.path().join("taken (2).txt"),
+            same.clone(),
+            dir.path().join("same (1).txt"),
+        ]
+    );
+    let resolutions: Vec<_> = plan
+        .entries
+        .iter()
+        .map(|e| e.conflict.as_ref().unwrap().resolution)
+        .collect();
+    assert_eq!(
+        resolutions,
+        vec![Resolution::Suffixed, Resolution::Kept, Resolution::Suffixed]
+    );
+    assert_eq!(plan.ready().count(), 3);
+    assert_eq!(
+        plan.entries[0].conflict.as_ref().unwrap().requested_target,
+        taken
+    );
+}
+
+#[test]
+fn fail_strategy_blocks_and_overwrite_needs_confirmation() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let taken = dir.path().join("taken.txt");
+    fs::write(&a, "new").unwrap();
+    fs::write(&taken, "old").unwrap();
+    let ops = vec![RenameOp::new(&a, &taken)];
+    let journal = dir.path().join("journal");
+
+    let blocked = plan(ops.clone(), &with(CollisionStrategy::Fail));
+    assert!(blocked.blocked);
+    assert!(matches!(
+        apply(&blocked, &ApplyOptions::default(), &journal),
+        Err(Error::PlanBlocked)
+    ));
+
+    let overwrite = plan(ops, &with(CollisionStrategy::Overwrite));
+    assert!(overwrite.overwrites());
+    assert!(matches!(
+        apply(&overwrite, &ApplyOptions::default(), &journal),
+        Err(Error::OverwriteNotConfirmed)
+    ));
+    assert_eq!(fs::read_to_string(&taken).unwrap(), "old");
+
+    let report = apply(
+        &overwrite,
+        &ApplyOptions {
+            confirm_overwrite: true,
+            ..ApplyOptions::default()
+        },
+        &journal,
+    )
+    .unwrap();
+    assert!(report.committed);
+    assert_eq!(fs::read_to_string(&taken).unwrap(), "new");
+    // Only the renamed file is left; the backup of the old one is gone.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn targets_vacated_in_the_same_batch_are_free() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = |n: &str| dir.path().join(n);
+    for n in ["a", "b", "c", "x", "y"] {
+        fs::write(p(n), n).unwrap();
+    }
+
+    // `b` cannot move onto `c`, so `a` cannot take its place either.
+    let plan = plan(
+        vec![
+            RenameOp::new(p("x"), p("y")),
+            RenameOp::new(p("y"), p("x")),
+            RenameOp::new(p("a"), p("b")),
+            RenameOp::new(p("b"), p("c")),
+        ],
+        &PlanOptions::default(),
+    );
+
+    let statuses: Vec<_> = plan.entries.iter().map(|e| e.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            EntryStatus::Ready,
+            EntryStatus::Ready,
+            EntryStatus::TargetExists,
+            EntryStatus::TargetExists,
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn case_only_changes_are_not_mistaken_for_hard_links() {
+    let dir = tempfile::tempdir().unwrap();
+    let lower = dir.path().join("photo.jpg");
+    let upper = dir.path().join("Photo.jpg");
+    let other = dir.path().join("notes.txt");
+    fs::write(&lower, "x").unwrap();
+    fs::write(&other, "y").unwrap();
+
+    let case_only = plan(vec![RenameOp::new(&lower, &upper)], &PlanOptions::default());
+    assert_eq!(case_only.entries[0].status, EntryStatus::Ready);
+
+    // Two spellings of one file with separate entries are hard links on a
+    // case-sensitive volume, and the target really is taken.
+    if fs::hard_link(&lower, &upper).is_ok() && fs::read_dir(dir.path()).unwrap().count() == 3 {
+        let linked = plan(vec![RenameOp::new(&lower, &upper)], &PlanOptions::default());
+        assert_eq!(linked.entries[0].status, EntryStatus::TargetExists);
+    }
+}
+
+#[test]
+fn rejects_targets_the_filesystem_cannot_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let long_name = format!("{}.txt", "x".repeat(300));
+
+    let plan = plan(
+        vec![RenameOp::new(&a, dir.path().join(long_name))],
+        &PlanOptions::default(),
+    );
+
+    assert_eq!(plan.entries[0].status, EntryStatus::PathTooLong);
+}
+
+#[test]
+fn folders_cannot_move_into_themselves_or_be_overwritten() {
+    let dir = tempfile::tempdir().unwrap();
+    let photos = dir.path().join("photos");
+    let taken = dir.path().join("taken");
+    fs::create_dir(&photos).unwrap();
+    fs::create_dir(&taken).unwrap();
+
+    let plan = plan(
+        vec![
+            RenameOp::new(&photos, photos.join("inner")),
+            RenameOp::new(&taken, dir.path().join("photos")),
+        ],
+        &with(CollisionStrategy::Overwrite),
+    );
+
+    assert!(plan.entries[0].is_dir);
+    assert_eq!(plan.entries[0].status, EntryStatus::IntoItself);
+    assert_eq!(plan.entries[1].status, EntryStatus::TargetExists);
+    assert!(!plan.overwrites());
+}
+
+#[test]
+fn extension_changes_are_flagged_and_need_permission() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    for name in ["a.jpg", "b.jpg", "c.JPG"] {
+        fs::write(dir.path().join(name), "x").unwrap();
+    }
+    let ops = vec![
+        RenameOp::new(dir.path().join("a.jpg"), dir.path().join("a.png")),
+        RenameOp::new(dir.path().join("b.jpg"), dir.path().join("b_jpg")),
+        RenameOp::new(dir.path().join("c.JPG"), dir.path().join("c.jpg")),
+    ];
+    let plan = plan(ops, &PlanOptions::default());
+
+    assert_eq!(
+        plan.entries[0].warnings,
+        vec![PlanWarning::ExtensionChanged]
+    );
+    assert_eq!(
+        plan.entries[1].warnings,
+        vec![PlanWarning::ExtensionRemoved]
+    );
+    assert!(plan.entries[2].warnings.is_empty());
+    assert!(plan.changes_extensions());
+
+    let err = apply(&plan, &ApplyOptions::default(), &journal).unwrap_err();
+    assert!(matches!(err, Error::ExtensionChangeNotConfirmed));
+    assert!(dir.path().join("a.jpg").exists());
+
+    let allowed = ApplyOptions {
+        allow_extension_changes: true,
+        ..ApplyOptions::default()
+    };
+    assert_eq!(apply(&plan, &allowed, &journal).unwrap().renamed, 3);
+    assert!(dir.path().join("b_jpg").exists());
+}
+
+#[test]
+fn shortened_names_are_flagged() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("a very long name.txt");
+    fs::write(&source, "x").unwrap();
+    let op = RenameOp {
+        shortened: true,
+        ..RenameOp::new(&source, dir.path().join("a very.txt"))
+    };
+    let batch = plan(vec![op], &PlanOptions::default());
+    let entry = &batch.entries[0];
+    assert!(entry.is_ready());
+    assert_eq!(entry.warnings, [PlanWarning::Shortened]);
+    assert_eq!(entry.diagnostics[0].code, "shortened");
+    assert_eq!(entry.diagnostics[0].severity, Severity::Warning);
+}
+
+#[test]
+fn hard_links_in_one_batch_are_flagged_and_their_file_is_touched_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, c) = (
+        dir.path().join("a.txt"),
+        dir.path().join("b.txt"),
+        dir.path().join("c.txt"),
+    );
+    fs::write(&a, "a").unwrap();
+    fs::hard_link(&a, &b).unwrap();
+    fs::write(&c, "c").unwrap();
+    let at = |secs| chrono::DateTime::from_timestamp(secs, 0);
+    let op = |from: &std::path::Path, to: &str, secs| RenameOp {
+        modified: at(secs),
+        ..RenameOp::new(from, dir.path().join(to))
+    };
+
+    let batch = plan(
+        vec![
+            op(&a, "x.txt", 1_000_000),
+            op(&b, "y.txt", 2_000_000),
+            op(&c, "z.txt", 3_000_000),
+            op(&b, "b.txt", 4_000_000),
+        ],
+        &PlanOptions::default(),
+    );
+    let entries = &batch.entries;
+    assert_eq!(entries[0].warnings, [PlanWarning::HardLink]);
+    assert_eq!(entries[0].same_file_as, None);
+    assert_eq!(entries[1].same_file_as, Some(0));
+    assert_eq!(entries[1].modified, None);
+    assert!(entries[1].diagnostics.iter().any(|d| d.code == "hard_link"));
+    assert!(entries[2].warnings.is_empty());
+    assert_eq!(entries[2].modified, at(3_000_000));
+    // Only the time would change, and it is set through the first name.
+    assert_eq!(entries[3].status, EntryStatus::Unchanged);
+
+    let report = apply(
+        &batch,
+        &ApplyOptions::default(),
+        &dir.path().join("journal"),
+    )
+    .unwrap();
+    assert_eq!(report.renamed, 3);
+    let (x, y) = (dir.path().join("x.txt"), dir.path().join("y.txt"));
+    assert_eq!(FileId::of(&x), FileId::of(&y));
+    assert_eq!(
+        fs::metadata(&y).unwrap().modified().unwrap(),
+        at(1_000_000).unwrap().into()
+    );
+}
+
+#[test]
+fn reserved_device_names_are_rejected_or_fixed_for_windows_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let ops = || vec![RenameOp::new(&a, dir.path().join("Con.txt"))];
+    let ntfs = PlanOptions {
+        filesystem: Some(TargetFs::Ntfs),
+        ..PlanOptions::default()
+    };
+
+    let rejected = plan(ops(), &ntfs);
+    assert_eq!(rejected.entries[0].status, EntryStatus::ReservedName);
+
+    let fixed = plan(
+        ops(),
+        &PlanOptions {
+            fix_reserved_names: true,
+            ..ntfs
+        },
+    );
+    assert!(fixed.entries[0].is_ready());
+    assert_eq!(fixed.entries[0].target, dir.path().join("Con_.txt"));
+    assert_eq!(
+        fixed.entries[0].warnings,
+        vec![PlanWarning::ReservedNameFixed]
+    );
+    assert!(!fixed.changes_extensions());
+
+    let ext4 = PlanOptions {
+        filesystem: Some(TargetFs::Ext4),
+        ..PlanOptions::default()
+    };
+    assert!(plan(ops(), &ext4).entries[0].is_ready());
+}
+
+#[test]
+fn companions_follow_their_primary_file_or_hold_it_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path();
+    for name in [
+        "IMG_001.CR3",
+        "IMG_001.xmp",
+        "IMG_001.CR3.xmp",
+        "IMG_001.JPG",
+        "IMG_0011.xmp",
+        "Trip.CR3",
+        "movie.mkv",
+        "movie.en.srt",
+        "Film.en.srt",
+        "notes.txt",
+    ] {
+        fs::write(p.join(name), name).unwrap();
+    }
+    let options = |collision| PlanOptions {
+        collision,
+        companions: vec![
+            CompanionRule {
+                primary: vec!["cr3".into()],
+                companions: vec!["xmp".into(), "jpg".into()],
+            },
+            CompanionRule {
+                primary: vec!["MKV".into()],
+                companions: vec!["srt".into()],
+            },
+        ],
+        ..PlanOptions::default()
+    };
+    let ops = vec![
+        RenameOp::new(p.join("IMG_001.CR3"), p.join("Trip.CR3")),
+        RenameOp::new(p.join("movie.mkv"), p.join("Film.mkv")),
+    ];
+
+    let skipped = plan(ops.clone(), &options(CollisionStrategy::Skip));
+    let status = |plan: &renamer_core::RenamePlan, name: &str| {
+        let entry = plan
+            .entries
+            .iter()
+            .find(|e| e.source == p.join(name))
+            .unwrap();
+        (entry.status, entry.companion_of)
+    };
+    assert_eq!(skipped.entries.len(), 6);
+    assert_eq!(status(&skipped, "IMG_001.CR3").0, EntryStatus::TargetExists);
+    assert_eq!(
+        status(&skipped, "IMG_001.xmp"),
+        (EntryStatus::CompanionNotReady, Some(0))
+    );
+    assert_eq!(
+        status(&skipped, "movie.mkv").0,
+        EntryStatus::CompanionNotReady
+    );
+    assert_eq!(
+        status(&skipped, "movie.en.srt"),
+        (EntryStatus::TargetExists, Some(1))
+    );
+
+    fs::remove_file(p.join("Film.en.srt")).unwrap();
+    let suffixed = plan(ops, &options(CollisionStrategy::Suffix));
+    assert_eq!(suffixed.ready().count(), 6);
+    apply(&suffixed, &ApplyOptions::default(), p).unwrap();
+    for (name, was) in [
+        ("Trip (1).CR3", "IMG_001.CR3"),
+        ("Trip (1).xmp", "IMG_001.xmp"),
+        ("Trip (1).CR3.xmp", "IMG_001.CR3.xmp"),
+        ("Trip (1).JPG", "IMG_001.JPG"),
+        ("IMG_0011.xmp", "IMG_0011.xmp"),
+        ("Film.mkv", "movie.mkv"),
+        ("Film.en.srt", "movie.en.srt"),
+    ] {
+        assert_eq!(fs::read_to_string(p.join(name)).unwrap(), was);
+    }
+}
+
+#[test]
+fn targets_are_checked_against_what_their_volume_can_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+    let on = |filesystem| PlanOptions {
+        filesystem: Some(filesystem),
+        ..PlanOptions::default()
+    };
+    let exfat = on(TargetFs::Exfat);
+
+    // exFAT refuses `:` and trailing dots, and folds case.
+    let refused = plan(
+        vec![
+            RenameOp::new(&a, dir.path().join("10:30.txt")),
+            RenameOp::new(&b, dir.path().join("notes.")),
+        ],
+        &exfat,
+    );
+    assert!(refused
+        .entries
+        .iter()
+        .all(|e| e.status == EntryStatus::UnsupportedName));
+    assert_eq!(refused.entries[0].diagnostics[0].code, "unsupported_name");
+    assert_eq!(refused.volumes.len(), 1);
+    assert!(!refused.volumes[0].case_sensitive);
+    assert!(!refused.volumes[0].journaled);
+
+    let ops = || {
+        vec![
+            RenameOp::new(&a, dir.path().join("Photo.jpg")),
+            RenameOp::new(&b, dir.path().join("photo.jpg")),
+        ]
+    };
+    let folded = plan(ops(), &exfat);
+    assert!(folded
+        .entries
+        .iter()
+        .all(|e| e.status == EntryStatus::DuplicateTarget));
+    let suffixed = plan(
+        ops(),
+        &PlanOptions {
+            collision: CollisionStrategy::Suffix,
+            ..exfat
+        },
+    );
+    assert_eq!(suffixed.entries[1].target, dir.path().join("photo (1).jpg"));
+    assert!(plan(ops(), &on(TargetFs::Ext4)).ready().count() == 2);
+
+    // Probing the temporary folder finds a volume that takes both.
+    let probed = plan(ops(), &PlanOptions::default());
+    let volume = &probed.volumes[0];
+    assert_eq!(
+        probed.ready().count(),
+        if volume.case_sensitive { 2 } else { 0 }
+    );
+}
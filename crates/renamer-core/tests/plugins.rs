@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::Path;
+
+use renamer_core::{FileContext, Pipeline, PluginHost, Rule, Template};
+
+/// A plugin whose `header` token is the first four bytes of the file and
+/// whose `tidy` rule names every file `tidy.txt`.
+const MAGIC: &str = r#"
+(module
+  (import "sortify" "read" (func $read (param i64 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 8192))
+  (data (i32.const 1024) "{\"name\":\"magic\",\"version\":\"1.0\",\"tokens\":[\"header\"],\"rules\":[\"tidy\"]}")
+  (data (i32.const 2048) "\"tidy.txt\"")
+  (data (i32.const 2999) "\"")
+  (data (i32.const 3004) "\"")
+  (func $pack (param i32 i32) (result i64)
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get 0)) (i64.const 32))
+      (i64.extend_i32_u (local.get 1))))
+  (func (export "alloc") (param $len i32) (result i32)
+    (global.get $next)
+    (global.set $next (i32.add (global.get $next) (local.get $len))))
+  (func (export "manifest") (result i64)
+    (call $pack (i32.const 1024) (i32.const 69)))
+  (func (export "token") (param i32 i32) (result i64)
+    (if (i32.ne (call $read (i64.const 0) (i32.const 3000) (i32.const 4)) (i32.const 4))
+      (then (return (call $pack (i32.const 0) (i32.const 0)))))
+    (call $pack (i32.const 2999) (i32.const 6)))
+  (func (export "rule") (param i32 i32) (result i64)
+    (call $pack (i32.const 2048) (i32.const 10))))
+"#;
+
+/// A plugin whose only rule never returns.
+const SPINNER: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{\"name\":\"spinner\",\"rules\":[\"spin\"]}")
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "manifest") (result i64) (i64.const 35))
+  (func (export "rule") (param i32 i32) (result i64)
+    (loop $forever (br $forever))
+    (i64.const 0)))
+"#;
+
+fn install(dir: &Path, file: &str, wat: &str) {
+    fs::write(dir.join(file), wat::parse_str(wat).unwrap()).unwrap();
+}
+
+// One test, as the enabled plugins are shared by the whole process.
+#[test]
+fn enabled_plugins_provide_tokens_and_rules() {
+    let dir = tempfile::tempdir().unwrap();
+    let plugins = dir.path().join("plugins");
+    let settings = dir.path().join("plugins.json");
+    fs::create_dir(&plugins).unwrap();
+    install(&plugins, "magic.wasm", MAGIC);
+    install(&plugins, "spinner.wasm", SPINNER);
+    fs::write(plugins.join("broken.wasm"), "not wasm").unwrap();
+
+    let mut host = PluginHost::open(&plugins, &settings).unwrap();
+    let names: Vec<_> = host
+        .plugins()
+        .iter()
+        .map(|p| (p.manifest.name.clone(), p.enabled))
+        .collect();
+    assert_eq!(
+        names,
+        [("magic".to_string(), false), ("spinner".to_string(), false)]
+    );
+    assert_eq!(host.errors().len(), 1);
+    assert!(host.errors()[0].path.ends_with("broken.wasm"));
+
+    let template = "{plugin:magic.header|none}-{name}.{ext}";
+    assert!(Template::parse(template).is_err());
+    assert!(host.set_enabled("magic", true).unwrap());
+    assert!(!host.set_enabled("missing", true).unwrap());
+
+    let file = dir.path().join("scan.bin");
+    fs::write(&file, "MGK1 and the rest").unwrap();
+    let ctx = FileContext::new(&file, 0);
+    let template = Template::parse(template).unwrap();
+    assert!(template.needs_embedded());
+    assert_eq!(template.render("scan.bin", &ctx), "MGK1-scan.bin");
+    let missing = dir.path().join("gone.bin");
+    assert_eq!(
+        template.render("gone.bin", &FileContext::new(&missing, 0)),
+        "none-gone.bin"
+    );
+    assert!(Template::parse("{plugin:magic.footer}").is_err());
+
+    let tidy = Pipeline::new(&[Rule::Plugin {
+        plugin: "magic".into(),
+        rule: "tidy".into(),
+        options: serde_json::Value::Null,
+    }])
+    .unwrap();
+    assert_eq!(tidy.run("scan.bin", &ctx).name, "tidy.txt");
+
+    // Settings persist, and runaway plugins are stopped.
+    let mut host = PluginHost::open(&plugins, &settings).unwrap();
+    assert!(host.plugins()[0].enabled);
+    host.set_enabled("spinner", true).unwrap();
+    let spin = Pipeline::new(&[Rule::Plugin {
+        plugin: "spinner".into(),
+        rule: "spin".into(),
+        options: serde_json::json!({"fast": true}),
+    }])
+    .unwrap();
+    let renamed = spin.run("scan.bin", &ctx);
+    assert_eq!(renamed.name, "scan.bin");
+    assert_eq!(
+        renamed.step_errors,
+        ["spinner.spin: the plugin ran too long"]
+    );
+
+    host.set_enabled("magic", false).unwrap();
+    assert!(Template::parse("{plugin:magic.header}").is_err());
+    assert!(Pipeline::new(&[Rule::Plugin {
+        plugin: "magic".into(),
+        rule: "tidy".into(),
+        options: serde_json::Value::Null,
+    }])
+    .is_err());
+}
@@ -0,0 +1,102 @@
+use std::fs;
+
+use renamer_core::policy::{self, NamingPolicy, PolicyViolation};
+use renamer_core::{apply, plan, ApplyOptions, EntryStatus, Error, PlanOptions, RenameOp};
+
+fn standard() -> NamingPolicy {
+    NamingPolicy {
+        name: "Finance".into(),
+        allowed_characters: "A-Za-z0-9_.-".into(),
+        required_prefix: Some(r"\d{4}-\d{2}-\d{2}_".into()),
+        max_length: Some(24),
+        forbidden_words: vec!["Final".into(), "copy".into()],
+    }
+}
+
+#[test]
+fn names_are_checked_against_each_rule() {
+    let policy = standard();
+    assert!(policy.check("2024-03-05_invoice.pdf").unwrap().is_empty());
+    assert!(policy.check("2024-03-05_copyright.pdf").unwrap().is_empty());
+    assert_eq!(
+        policy.check("Invoice final (copy) ä.pdf").unwrap(),
+        [
+            PolicyViolation::Character { character: ' ' },
+            PolicyViolation::Character { character: '(' },
+            PolicyViolation::Character { character: ')' },
+            PolicyViolation::Character { character: 'ä' },
+            PolicyViolation::MissingPrefix,
+            PolicyViolation::TooLong {
+                length: 26,
+                max: 24
+            },
+            PolicyViolation::ForbiddenWord {
+                word: "final".into()
+            },
+            PolicyViolation::ForbiddenWord {
+                word: "copy".into()
+            },
+        ]
+    );
+
+    let broken = NamingPolicy {
+        required_prefix: Some("(".into()),
+        ..standard()
+    };
+    assert!(matches!(
+        broken.check("a"),
+        Err(Error::InvalidPolicy { policy, .. }) if policy == "Finance"
+    ));
+}
+
+#[test]
+fn a_name_breaking_a_policy_blocks_the_plan() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("journal");
+    let [a, b] = ["a.pdf", "b.pdf"].map(|n| dir.path().join(n));
+    for p in [&a, &b] {
+        fs::write(p, "x").unwrap();
+    }
+    let ops = || {
+        vec![
+            RenameOp::new(&a, dir.path().join("2024-03-05_a.pdf")),
+            RenameOp::new(&b, dir.path().join("b final.pdf")),
+        ]
+    };
+    let options = PlanOptions {
+        policy: Some(standard()),
+        ..PlanOptions::default()
+    };
+    let planned = plan(ops(), &options);
+    assert!(planned.blocked);
+    assert!(planned.entries[0].is_ready());
+    assert_eq!(planned.entries[1].status, EntryStatus::PolicyViolation);
+    assert_eq!(planned.entries[1].diagnostics.len(), 3);
+    assert!(matches!(
+        apply(&planned, &ApplyOptions::default(), &journal),
+        Err(Error::PlanBlocked)
+    ));
+    assert!(a.exists() && b.exists());
+
+    // The enforced policy applies to plans without one of their own.
+    let file = dir.path().join("policy.json");
+    fs::write(&file, serde_json::to_vec(&standard()).unwrap()).unwrap();
+    policy::set_enforced(Some(policy::load(&file).unwrap()));
+    let planned = plan(ops(), &PlanOptions::default());
+    policy::set_enforced(None);
+    assert!(planned.blocked);
+    assert!(!plan(ops(), &PlanOptions::default()).blocked);
+
+    // A policy file that cannot be read blocks every name until it can be.
+    let missing = dir.path().join("missing.json");
+    assert!(policy::enforce_file(&missing).is_err());
+    let planned = plan(ops(), &PlanOptions::default());
+    assert!(planned.blocked);
+    assert!(planned.entries.iter().all(|e| matches!(
+        e.policy_violations[..],
+        [PolicyViolation::InvalidPolicy { .. }]
+    )));
+    policy::enforce_file(&file).unwrap();
+    assert!(plan(ops(), &PlanOptions::default()).entries[0].is_ready());
+    policy::set_enforced(None);
+}
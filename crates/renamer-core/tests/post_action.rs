@@ -0,0 +1,133 @@
+#![cfg(unix)]
+
+use std::fs;
+
+use renamer_core::{
+    apply, plan, post_action, ApplyOptions, ApplyReport, CancelToken, Error, PlanOptions,
+    PostAction, PostActionScope, Preset, PresetStore, RenameOp,
+};
+
+fn renamed(dir: &std::path::Path) -> ApplyReport {
+    let ops = ["a", "b"]
+        .iter()
+        .map(|name| {
+            let source = dir.join(format!("{}.txt", name));
+            fs::write(&source, name).unwrap();
+            RenameOp::new(source, dir.join(format!("{} {{1}}.txt", name)))
+        })
+        .collect();
+    apply(
+        &plan(ops, &PlanOptions::default()),
+        &ApplyOptions::default(),
+        &dir.join("journal"),
+    )
+    .unwrap()
+}
+
+#[test]
+fn each_renamed_file_gets_its_own_run_with_rendered_arguments() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = renamed(dir.path());
+    let action = PostAction::new(
+        "sh",
+        vec![
+            "-c".into(),
+            r#"printf '%s|%s|%s' "$1" "$2" "$3"; echo oops >&2"#.into(),
+            "sh".into(),
+            "{path}".into(),
+            "{name}.{ext}".into(),
+            "{file} in {parent}".into(),
+        ],
+    );
+    let runs = post_action::run(&action, &report, &CancelToken::new());
+    assert_eq!(runs.len(), 2);
+    let target = dir.path().join("a {1}.txt");
+    assert_eq!(runs[0].path.as_deref(), Some(target.as_path()));
+    assert!(runs[0].succeeded());
+    let parent = dir.path().file_name().unwrap().to_string_lossy();
+    assert_eq!(
+        runs[0].stdout,
+        format!("{}|a {{1}}.txt|a {{1}}.txt in {}", target.display(), parent)
+    );
+    assert_eq!(runs[0].stderr, "oops\n");
+}
+
+#[test]
+fn a_batch_run_gets_every_path_and_slow_programs_are_stopped() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = renamed(dir.path());
+    let action = PostAction {
+        scope: PostActionScope::Batch,
+        ..PostAction::new(
+            "sh",
+            vec![
+                "-c".into(),
+                r#"echo "$#:$1"; exit 3"#.into(),
+                "{count}".into(),
+                "{paths}".into(),
+            ],
+        )
+    };
+    let runs = post_action::run(&action, &report, &CancelToken::new());
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].path, None);
+    assert_eq!(runs[0].exit_code, Some(3));
+    assert_eq!(
+        runs[0].stdout,
+        format!("2:{}\n", dir.path().join("a {1}.txt").display())
+    );
+
+    let slow = PostAction {
+        timeout_secs: 0,
+        ..PostAction::new("sleep", vec!["5".into()])
+    };
+    let runs = post_action::run(&slow, &report, &CancelToken::new());
+    assert!(runs.iter().all(|r| r.timed_out && r.exit_code.is_none()));
+
+    // What a stopped program left running keeps its pipes, but not the batch.
+    let started = std::time::Instant::now();
+    let orphaning = PostAction {
+        timeout_secs: 0,
+        ..PostAction::new("sh", vec!["-c".into(), "sleep 30; echo".into()])
+    };
+    let runs = post_action::run(&orphaning, &report, &CancelToken::new());
+    assert!(runs.iter().all(|r| r.timed_out));
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+
+    let missing = PostAction::new("no-such-program-for-sortify", Vec::new());
+    let runs = post_action::run(&missing, &report, &CancelToken::new());
+    assert!(runs[0]
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("could not be started"));
+}
+
+#[test]
+fn presets_with_broken_argument_templates_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = PresetStore::open(dir.path().join("presets.json")).unwrap();
+    let mut preset = Preset::new("Convert", Vec::new());
+    preset.post_action = Some(PostAction::new("ffmpeg", vec!["-i".into(), "{path".into()]));
+    assert!(matches!(
+        store.insert(preset.clone()),
+        Err(Error::InvalidTemplate { .. })
+    ));
+
+    preset.post_action = Some(PostAction::new(
+        "ffmpeg",
+        vec!["-i".into(), "{path}".into(), "{dir}/{name}.mp4".into()],
+    ));
+    store.insert(preset).unwrap();
+    let reopened = PresetStore::open(dir.path().join("presets.json")).unwrap();
+    assert_eq!(
+        reopened
+            .get("Convert")
+            .unwrap()
+            .post_action
+            .as_ref()
+            .unwrap()
+            .args[1],
+        "{path}"
+    );
+}
@@ -0,0 +1,12 @@
+use renamer_core::SleepGuard;
+
+#[test]
+fn guards_nest_and_release() {
+    // Whether or not the system lets itself be kept awake here, taking and
+    // dropping guards must neither fail nor hang.
+    let outer = SleepGuard::acquire("outer");
+    let inner = SleepGuard::acquire("inner");
+    drop(inner);
+    drop(outer);
+    let _again = SleepGuard::acquire("again");
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+use chrono::Utc;
+use renamer_core::{plan, preflight, PlanOptions, PreflightKind, RenameOp};
+
+#[test]
+fn a_writable_batch_passes_and_leaves_no_probe_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let ops = vec![RenameOp::new(&a, dir.path().join("sub/new/b.txt"))];
+
+    let report = preflight(&plan(ops, &PlanOptions::default()));
+    assert!(!report.blocked, "{:?}", report.problems);
+    assert_eq!(report.folders, 1);
+    let left: Vec<_> = fs::read_dir(dir.path()).unwrap().flatten().collect();
+    assert_eq!(left.len(), 1);
+}
+
+#[test]
+fn reports_vanished_and_read_only_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let gone = dir.path().join("gone.txt");
+    let locked = dir.path().join("locked.txt");
+    fs::write(&gone, "g").unwrap();
+    fs::write(&locked, "l").unwrap();
+    let batch = plan(
+        vec![
+            RenameOp::new(&gone, dir.path().join("g2.txt")),
+            RenameOp {
+                modified: Some(Utc::now()),
+                ..RenameOp::new(&locked, &locked)
+            },
+        ],
+        &PlanOptions::default(),
+    );
+    fs::remove_file(&gone).unwrap();
+    let mut permissions = fs::metadata(&locked).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&locked, permissions).unwrap();
+
+    let report = preflight(&batch);
+    assert!(report.blocked);
+    let kinds: Vec<_> = report
+        .problems
+        .iter()
+        .map(|p| (p.kind, p.entries.clone()))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (PreflightKind::SourceMissing, vec![0]),
+            (PreflightKind::ReadOnly, vec![1])
+        ]
+    );
+    assert!(report.elevatable.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn reports_folders_that_cannot_be_written() {
+    use std::os::unix::fs::PermissionsExt;
+
+    if uzers::get_effective_uid() == 0 {
+        // Permissions do not apply to root.
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let closed = dir.path().join("closed");
+    fs::create_dir(&closed).unwrap();
+    let a = closed.join("a.txt");
+    fs::write(&a, "a").unwrap();
+    fs::set_permissions(&closed, fs::Permissions::from_mode(0o555)).unwrap();
+
+    let report = preflight(&plan(
+        vec![RenameOp::new(&a, closed.join("b.txt"))],
+        &PlanOptions::default(),
+    ));
+    fs::set_permissions(&closed, fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(report.blocked);
+    assert_eq!(report.problems[0].kind, PreflightKind::FolderNotWritable);
+    assert_eq!(report.problems[0].path, closed);
+    assert!(report.problems[0].needs_elevation);
+    assert_eq!(report.elevatable, [0]);
+}
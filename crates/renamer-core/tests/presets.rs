@@ -0,0 +1,173 @@
+use std::fs;
+
+use renamer_core::{
+    compare_presets, EntryStatus, Error, Preset, PresetStore, RegexRule, Rule, ScanOptions,
+};
+
+fn prefix(text: &str) -> Vec<Rule> {
+    vec![Rule::Prefix { text: text.into() }]
+}
+
+#[test]
+fn presets_are_replaced_by_name_and_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data/presets.json");
+
+    let mut store = PresetStore::open(&path).unwrap();
+    assert!(store.presets().is_empty());
+    store
+        .insert(Preset::new("Podcast episodes", prefix("ep-")))
+        .unwrap();
+    store
+        .insert(Preset::new(" Invoice scans ", prefix("inv-")))
+        .unwrap();
+    store
+        .insert(Preset::new("podcast EPISODES", prefix("pod-")))
+        .unwrap();
+
+    let store = PresetStore::open(&path).unwrap();
+    let names: Vec<_> = store.presets().iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["Invoice scans", "podcast EPISODES"]);
+    assert_eq!(store.get("Podcast Episodes").unwrap().rules, prefix("pod-"));
+
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(json["version"], 1);
+
+    let mut store = store;
+    assert!(store.remove("invoice scans").unwrap());
+    assert!(!store.remove("invoice scans").unwrap());
+    assert!(matches!(
+        store.insert(Preset::new("  ", Vec::new())),
+        Err(Error::InvalidPresetName)
+    ));
+    assert_eq!(PresetStore::open(&path).unwrap().presets().len(), 1);
+}
+
+#[test]
+fn files_from_a_newer_version_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("presets.json");
+    fs::write(&path, r#"{"version": 2, "presets": []}"#).unwrap();
+
+    assert!(matches!(
+        PresetStore::open(&path),
+        Err(Error::UnsupportedVersion { version: 2, .. })
+    ));
+}
+
+#[test]
+fn running_a_preset_renames_the_files_it_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let named = dir.path().join("x-b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&named, "b").unwrap();
+    let preset = Preset::new(
+        "Tidy",
+        vec![Rule::Regex(RegexRule {
+            pattern: "^(?:x-)?".into(),
+            replacement: "x-".into(),
+            case_insensitive: false,
+            replace_all: false,
+        })],
+    );
+    let journal = dir.path().join("journal");
+    let report = preset
+        .run(&[a.clone(), named.clone()], &Default::default(), &journal)
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.renamed, 1);
+    assert!(dir.path().join("x-a.txt").exists() && named.exists());
+    assert!(preset
+        .run(&[named], &Default::default(), &journal)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn a_preset_keeps_to_its_scope_filters_and_target_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let invoices = dir.path().join("invoices");
+    let photos = dir.path().join("photos");
+    let filed = invoices.join("filed");
+    for folder in [&invoices, &photos, &filed] {
+        fs::create_dir(folder).unwrap();
+    }
+    let pdf = invoices.join("march.pdf");
+    let note = invoices.join("notes.txt");
+    let photo = photos.join("beach.jpg");
+    for file in [&pdf, &note, &photo] {
+        fs::write(file, "x").unwrap();
+    }
+    let mut preset = Preset::new("Invoices", prefix("inv-"));
+    preset.allowed_scope = vec![invoices.clone()];
+    preset.scan_options = Some(ScanOptions {
+        patterns: vec!["*.pdf".into()],
+        ..Default::default()
+    });
+    preset.target_dir = Some(filed.clone());
+
+    let plan = preset.plan(&[pdf.clone(), note.clone()]).unwrap();
+    assert_eq!(plan.entries.len(), 1);
+    assert_eq!(plan.entries[0].source, pdf);
+    assert_eq!(plan.entries[0].target, filed.join("inv-march.pdf"));
+
+    match preset.plan(&[pdf.clone(), photo.clone()]) {
+        Err(Error::PresetScope { preset, path }) => {
+            assert_eq!(preset, "Invoices");
+            assert_eq!(path, photo);
+        }
+        other => panic!("expected a scope error, got {:?}", other),
+    }
+    preset.target_dir = Some(photos);
+    assert!(matches!(
+        preset.plan(&[pdf]),
+        Err(Error::PresetScope { .. })
+    ));
+}
+
+#[test]
+fn comparing_presets_lines_up_what_each_would_do_with_each_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<_> = ["a.txt", "x-b.txt", "c.md"]
+        .iter()
+        .map(|n| dir.path().join(n))
+        .collect();
+    for p in &paths {
+        fs::write(p, "x").unwrap();
+    }
+    let old = Preset::new(
+        "Old",
+        vec![Rule::Regex(RegexRule {
+            pattern: r"^(?:x-)?(.*)\.txt$".into(),
+            replacement: "x-$1.txt".into(),
+            case_insensitive: false,
+            replace_all: false,
+        })],
+    );
+    let new = Preset::new("New", prefix("x-"));
+
+    let rows = compare_presets(&old, &new, &paths).unwrap();
+    assert_eq!(rows.len(), 3);
+    assert!(rows.iter().all(|r| r.a.target.parent() == Some(dir.path())));
+
+    assert!(rows[0].same);
+    assert_eq!(rows[0].a.target, dir.path().join("x-a.txt"));
+    assert_eq!(rows[0].a.status, Some(EntryStatus::Ready));
+
+    // The old preset leaves the prefix alone; the new one doubles it.
+    assert!(!rows[1].same);
+    assert_eq!(rows[1].a.target, paths[1]);
+    assert_eq!(rows[1].a.status, None);
+    assert_eq!(rows[1].b.target, dir.path().join("x-x-b.txt"));
+
+    assert!(!rows[2].same);
+    assert_eq!(rows[2].a.status, None);
+    assert_eq!(rows[2].b.target, dir.path().join("x-c.md"));
+
+    assert!(compare_presets(&new, &new, &paths)
+        .unwrap()
+        .iter()
+        .all(|r| r.same));
+    assert!(paths.iter().all(|p| p.exists()));
+}
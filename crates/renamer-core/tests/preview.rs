@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use renamer_core::{apply_overrides, preview, ApplyOptions, HistoryStore, PreviewWarning, Rule};
+
+#[test]
+fn applies_rules_in_order_without_touching_disk() {
+    let paths = vec![PathBuf::from("/nowhere/holiday photo.jpg")];
+    let rules = vec![
+        Rule::Replace {
+            find: " ".into(),
+            replace: "_".into(),
+        },
+        Rule::Prefix {
+            text: "2024_".into(),
+        },
+        Rule::Suffix {
+            text: "_edit".into(),
+        },
+    ];
+
+    let rows = preview(&paths, &rules).unwrap();
+
+    assert_eq!(rows[0].new_name, "2024_holiday_photo_edit.jpg");
+    assert_eq!(
+        rows[0].target,
+        PathBuf::from("/nowhere/2024_holiday_photo_edit.jpg")
+    );
+    assert!(rows[0].changed);
+    assert!(rows[0].warnings.is_empty());
+}
+
+#[test]
+fn flags_in_batch_conflicts_and_invalid_names() {
+    let paths = vec![
+        PathBuf::from("/d/a-1.txt"),
+        PathBuf::from("/d/a_1.txt"),
+        PathBuf::from("/d/x.txt"),
+    ];
+    let rules = vec![
+        Rule::Replace {
+            find: "-".into(),
+            replace: "_".into(),
+        },
+        Rule::Replace {
+            find: "x.txt".into(),
+            replace: "sub/x.txt".into(),
+        },
+    ];
+
+    let rows = preview(&paths, &rules).unwrap();
+
+    assert!(rows[0].conflict && rows[1].conflict);
+    assert!(!rows[1].changed);
+    assert_eq!(rows[2].warnings, vec![PreviewWarning::ContainsSeparator]);
+}
+
+#[test]
+fn flags_windows_device_names() {
+    let rows = preview(
+        &[PathBuf::from("/d/printer.txt")],
+        &[Rule::Replace {
+            find: "printer".into(),
+            replace: "LPT1".into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].warnings, vec![PreviewWarning::ReservedDeviceName]);
+}
+
+#[test]
+fn organize_moves_files_into_rendered_folders() {
+    let dir = tempfile::tempdir().unwrap();
+    let photo = dir.path().join("inbox/beach.JPG");
+    let notes = dir.path().join("inbox/notes.txt");
+    std::fs::create_dir(dir.path().join("inbox")).unwrap();
+    std::fs::write(&photo, "jpg").unwrap();
+    std::fs::write(&notes, "txt").unwrap();
+    let rules = vec![
+        Rule::Organize {
+            folder: "{ext}/../ {name} /".into(),
+            root: Some(PathBuf::from("../sorted")),
+        },
+        Rule::Prefix { text: "x-".into() },
+    ];
+
+    let rows = preview(&[photo.clone(), notes], &rules).unwrap();
+    let sorted = dir.path().join("inbox/../sorted");
+    assert_eq!(rows[0].new_name, "x-beach.JPG");
+    assert_eq!(rows[0].target, sorted.join("JPG/beach/x-beach.JPG"));
+    assert_eq!(rows[1].target, sorted.join("txt/notes/x-notes.txt"));
+    assert!(rows.iter().all(|r| r.changed && r.warnings.is_empty()));
+
+    let ops = rows.iter().map(|r| r.to_op()).collect();
+    let plan = renamer_core::plan(ops, &Default::default());
+    let report =
+        renamer_core::apply(&plan, &Default::default(), &dir.path().join("journal")).unwrap();
+    assert_eq!(report.renamed, 2);
+    assert!(dir.path().join("sorted/JPG/beach/x-beach.JPG").exists());
+    assert!(!photo.exists());
+}
+
+#[test]
+fn path_replacements_move_files_between_folders_and_tidy_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let draft = dir.path().join("2023_draft");
+    std::fs::create_dir_all(draft.join("scans")).unwrap();
+    let paths = vec![draft.join("cover.txt"), draft.join("scans/draft-1.txt")];
+    for path in &paths {
+        std::fs::write(path, "x").unwrap();
+    }
+    let rules = vec![Rule::ReplacePath {
+        find: "_draft/".into(),
+        replace: "_final/".into(),
+        regex: false,
+        root: Some(dir.path().to_path_buf()),
+    }];
+
+    let rows = preview(&paths, &rules).unwrap();
+    let fin = dir.path().join("2023_final");
+    assert_eq!(rows[0].target, fin.join("cover.txt"));
+    assert_eq!(rows[1].target, fin.join("scans/draft-1.txt"));
+
+    let regex = vec![Rule::ReplacePath {
+        find: r"^(\d{4})_draft/scans/draft-".into(),
+        replace: "${1}/page-".into(),
+        regex: true,
+        root: Some(dir.path().to_path_buf()),
+    }];
+    let moved = preview(&paths[1..], &regex).unwrap();
+    assert_eq!(moved[0].target, dir.path().join("2023/page-1.txt"));
+
+    let ops = rows.iter().map(|r| r.to_op()).collect();
+    let plan = renamer_core::plan(ops, &Default::default());
+    let codes: Vec<_> = plan.entries[1]
+        .diagnostics
+        .iter()
+        .map(|d| (d.code.as_str(), d.message.as_str()))
+        .collect();
+    let message = format!(
+        "moves from `{}` to `{}`",
+        Path::new("2023_draft/scans").display(),
+        Path::new("2023_final/scans").display()
+    );
+    assert_eq!(codes, [("folder_changed", message.as_str())]);
+    let options = ApplyOptions {
+        remove_emptied_folders: true,
+        ..ApplyOptions::default()
+    };
+    let journal = dir.path().join("journal");
+    let report = renamer_core::apply(&plan, &options, &journal).unwrap();
+    assert_eq!(report.renamed, 2);
+    assert!(fin.join("scans/draft-1.txt").exists());
+    assert!(!draft.exists());
+
+    let mut history = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    history.record(&report).unwrap();
+    history.undo_last().unwrap().unwrap();
+    assert!(paths.iter().all(|p| p.exists()));
+    assert!(!fin.exists());
+}
+
+#[test]
+fn hand_edited_names_win_over_the_rules_and_are_checked_again() {
+    let paths = vec![
+        PathBuf::from("/d/a.txt"),
+        PathBuf::from("/d/b.txt"),
+        PathBuf::from("/d/c.txt"),
+    ];
+    let rules = vec![Rule::Prefix { text: "x-".into() }];
+    let mut rows = preview(&paths, &rules).unwrap();
+    let overrides = HashMap::from([
+        (PathBuf::from("/d/a.txt"), "x-b.txt".to_string()),
+        (PathBuf::from("/d/c.txt"), "c\0.txt".to_string()),
+    ]);
+
+    apply_overrides(&mut rows, &overrides);
+
+    assert_eq!(rows[0].new_name, "x-b.txt");
+    assert!(rows[0].overridden && rows[0].conflict);
+    assert!(!rows[1].overridden && rows[1].conflict);
+    assert_eq!(rows[2].target, PathBuf::from("/d/c\0.txt"));
+    assert_eq!(rows[2].warnings, vec![PreviewWarning::ForbiddenCharacter]);
+
+    // Typing the old name back in leaves the file alone.
+    let overrides = HashMap::from([(PathBuf::from("/d/b.txt"), "b.txt".to_string())]);
+    apply_overrides(&mut rows, &overrides);
+    assert!(!rows[1].changed);
+    assert!(!rows[0].conflict);
+}
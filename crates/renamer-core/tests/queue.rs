@@ -0,0 +1,206 @@
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use renamer_core::{
+    ApplyOptions, HashAlgorithm, Job, JobOutput, JobQueue, JobStatus, Lane, QueueEvent, RenameOp,
+    Rule, ScanOptions,
+};
+use uuid::Uuid;
+
+fn finished(events: &mpsc::Receiver<QueueEvent>) -> (Uuid, JobOutput) {
+    loop {
+        match events.recv_timeout(Duration::from_secs(10)).unwrap() {
+            QueueEvent::Finished { job_id, output } => return (job_id, output),
+            QueueEvent::Updated(_) => {}
+        }
+    }
+}
+
+#[test]
+fn paused_jobs_wait_and_start_in_their_new_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+    let (tx, events) = mpsc::channel();
+    let queue = JobQueue::new(dir.path().join("journal"), 1, move |e| {
+        let _ = tx.send(e);
+    });
+    let rename = |from: &std::path::Path, to: &str| Job::Rename {
+        ops: vec![RenameOp::new(from, dir.path().join(to))],
+        plan_options: Default::default(),
+        apply_options: Default::default(),
+    };
+
+    queue.pause();
+    let first = queue.enqueue("a", rename(&a, "a2.txt"));
+    let second = queue.enqueue("b", rename(&b, "b2.txt"));
+    let held = queue.enqueue(
+        "scan",
+        Job::Scan {
+            root: dir.path().to_path_buf(),
+            options: ScanOptions::default(),
+        },
+    );
+    assert!(queue.hold(held, true));
+    assert!(queue.reorder(second, 0));
+    let order: Vec<_> = queue.snapshot().jobs.iter().map(|j| j.job_id).collect();
+    assert_eq!(order, [second, first, held]);
+    assert!(a.exists() && b.exists());
+
+    queue.resume();
+    assert_eq!(finished(&events).0, second);
+    let (job_id, output) = finished(&events);
+    assert_eq!(job_id, first);
+    assert!(matches!(output, JobOutput::Renamed(report) if report.renamed == 1));
+    assert!(dir.path().join("a2.txt").exists() && dir.path().join("b2.txt").exists());
+
+    let snapshot = queue.snapshot();
+    assert_eq!(snapshot.jobs[0].job_id, held);
+    assert_eq!(snapshot.jobs[0].status, JobStatus::Held);
+    assert!(queue.cancel(held));
+    assert!(!queue.cancel(held));
+    queue.clear_finished();
+    assert!(queue.snapshot().jobs.is_empty());
+}
+
+#[test]
+fn jobs_run_side_by_side_and_report_their_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("song.mp3");
+    fs::write(&file, "mp3").unwrap();
+    let (tx, events) = mpsc::channel();
+    let queue = JobQueue::new(dir.path().join("journal"), 2, move |e| {
+        let _ = tx.send(e);
+    });
+
+    let hash = queue.enqueue(
+        "hash",
+        Job::Hash {
+            paths: vec![file.clone()],
+            algorithm: HashAlgorithm::Sha256,
+        },
+    );
+    let scan = queue.enqueue(
+        "scan",
+        Job::Scan {
+            root: dir.path().join("missing"),
+            options: ScanOptions {
+                patterns: vec!["[".into()],
+                ..ScanOptions::default()
+            },
+        },
+    );
+    let (job_id, output) = finished(&events);
+    assert_eq!(job_id, hash);
+    match output {
+        JobOutput::Hashed(hashes) => assert_eq!(hashes.len(), 1),
+        other => panic!("unexpected output {:?}", other),
+    }
+
+    // A bad pattern fails the scan without a Finished event.
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while queue
+        .snapshot()
+        .jobs
+        .iter()
+        .any(|j| j.job_id == scan && j.status != JobStatus::Failed)
+    {
+        assert!(std::time::Instant::now() < deadline);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let info = queue.snapshot().jobs.into_iter().find(|j| j.job_id == scan);
+    assert!(info.unwrap().error.is_some());
+}
+
+#[test]
+fn interactive_jobs_go_ahead_of_background_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    fs::write(&file, "a").unwrap();
+    let (tx, events) = mpsc::channel();
+    let queue = JobQueue::new(dir.path().join("journal"), 1, move |e| {
+        let _ = tx.send(e);
+    });
+
+    queue.pause();
+    let hash = queue.enqueue(
+        "hash",
+        Job::Hash {
+            paths: vec![file.clone()],
+            algorithm: HashAlgorithm::Sha256,
+        },
+    );
+    let preview = queue.enqueue(
+        "preview",
+        Job::Preview {
+            paths: vec![file.clone()],
+            rules: vec![Rule::Prefix { text: "x-".into() }],
+        },
+    );
+    let scan = queue.enqueue_in(
+        "scan",
+        Job::Scan {
+            root: dir.path().to_path_buf(),
+            options: ScanOptions::default(),
+        },
+        Lane::Interactive,
+    );
+    let snapshot = queue.snapshot();
+    let order: Vec<_> = snapshot.jobs.iter().map(|j| (j.job_id, j.lane)).collect();
+    assert_eq!(
+        order,
+        [
+            (preview, Lane::Interactive),
+            (scan, Lane::Interactive),
+            (hash, Lane::Background)
+        ]
+    );
+    let waiting: Vec<_> = snapshot.lanes.iter().map(|l| (l.lane, l.waiting)).collect();
+    assert_eq!(waiting, [(Lane::Interactive, 2), (Lane::Background, 1)]);
+
+    queue.resume();
+    let mut outputs = Vec::new();
+    for _ in 0..3 {
+        outputs.push(finished(&events));
+    }
+    let rows = outputs.iter().find_map(|(job_id, output)| match output {
+        JobOutput::Previewed(rows) if *job_id == preview => Some(rows),
+        _ => None,
+    });
+    assert_eq!(rows.unwrap()[0].new_name, "x-a.txt");
+}
+
+#[test]
+fn renames_queued_in_safe_mode_move_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let queue = JobQueue::new(dir.path().join("journal"), 1, |_| {});
+    let job = queue.enqueue(
+        "rename",
+        Job::Rename {
+            ops: vec![RenameOp::new(&a, dir.path().join("b.txt"))],
+            plan_options: Default::default(),
+            apply_options: ApplyOptions {
+                safe_mode: true,
+                ..ApplyOptions::default()
+            },
+        },
+    );
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    let info = loop {
+        let info = queue.snapshot().jobs.into_iter().find(|j| j.job_id == job);
+        match info {
+            Some(info) if info.status == JobStatus::Failed => break info,
+            _ => {
+                assert!(std::time::Instant::now() < deadline);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+    assert!(info.error.is_some());
+    assert!(a.exists() && !dir.path().join("b.txt").exists());
+}
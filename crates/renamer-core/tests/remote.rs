@@ -0,0 +1,855 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use renamer_core::{
+    remote, CancelToken, Mtp, MtpTarget, OutcomeStatus, RemoteEntry, RemoteFs, RenameOp, Rule,
+    S3Target, ScanOptions, Sftp, WebDav, WebDavTarget, S3,
+};
+
+/// A host with files in memory; folders end in `/`.
+struct FakeHost {
+    files: BTreeMap<PathBuf, (bool, u64)>,
+    /// Moves onto this path fail.
+    broken: Option<PathBuf>,
+}
+
+impl FakeHost {
+    fn new(paths: &[&str]) -> Self {
+        let mut files = BTreeMap::new();
+        files.insert(PathBuf::from("/"), (true, 0));
+        for path in paths {
+            let is_dir = path.ends_with('/');
+            files.insert(
+                PathBuf::from(path.trim_end_matches('/')),
+                (is_dir, 1 + path.len() as u64),
+            );
+        }
+        FakeHost {
+            files,
+            broken: None,
+        }
+    }
+
+    fn paths(&self) -> Vec<&str> {
+        self.files.keys().filter_map(|p| p.to_str()).collect()
+    }
+
+    fn entry(&self, path: &Path) -> Option<RemoteEntry> {
+        let &(is_dir, size) = self.files.get(path)?;
+        Some(RemoteEntry {
+            name: path.file_name()?.to_string_lossy().into_owned(),
+            is_dir,
+            is_symlink: false,
+            size,
+            modified: None,
+            link_target: None,
+        })
+    }
+}
+
+impl RemoteFs for FakeHost {
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        if !self.files.get(dir).is_some_and(|f| f.0) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such folder"));
+        }
+        Ok(self
+            .files
+            .keys()
+            .filter(|p| p.parent() == Some(dir))
+            .filter_map(|p| self.entry(p))
+            .collect())
+    }
+
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        Ok(self.entry(path))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.broken.as_deref() == Some(to) {
+            return Err(io::Error::other("disk full"));
+        }
+        if self.files.contains_key(to) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "exists"));
+        }
+        let moved: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|p| p.starts_with(from))
+            .cloned()
+            .collect();
+        if moved.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        }
+        for path in moved {
+            let file = self.files.remove(&path).unwrap();
+            let rest = path.strip_prefix(from).unwrap();
+            let moved_to = if rest.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(rest)
+            };
+            self.files.insert(moved_to, file);
+        }
+        Ok(())
+    }
+
+    fn download(&mut self, from: &Path, to: &Path, progress: &dyn Fn(u64, u64)) -> io::Result<()> {
+        if self.broken.as_deref() == Some(from) {
+            return Err(io::Error::other("device disconnected"));
+        }
+        let size = self.files[from].1;
+        std::fs::write(to, vec![0; size as usize])?;
+        progress(size, size);
+        Ok(())
+    }
+}
+
+#[test]
+fn remote_folders_are_listed_as_local_scans_list_them() {
+    let mut host = FakeHost::new(&[
+        "/photos/",
+        "/photos/img10.jpg",
+        "/photos/img9.jpg",
+        "/photos/.thumbs/",
+        "/photos/.thumbs/img9.jpg",
+        "/photos/2023/",
+        "/photos/2023/a.jpg",
+        "/photos/2023/notes.txt",
+        "/photos/cache/",
+        "/photos/cache/b.jpg",
+    ]);
+    let page = remote::scan(
+        &mut host,
+        Path::new("/photos"),
+        &ScanOptions::default(),
+        &CancelToken::new(),
+    )
+    .unwrap();
+    let paths: Vec<&Path> = page.entries.iter().map(|e| e.path.as_path()).collect();
+    assert_eq!(
+        paths,
+        [
+            Path::new("/photos/2023/a.jpg"),
+            Path::new("/photos/2023/notes.txt"),
+            Path::new("/photos/cache/b.jpg"),
+            Path::new("/photos/img9.jpg"),
+            Path::new("/photos/img10.jpg"),
+        ]
+    );
+    assert_eq!(page.entries[0].depth, 2);
+    assert_eq!(page.entries[3].size, 17);
+    assert!(page.done && !page.cancelled);
+
+    let options = ScanOptions {
+        patterns: vec!["*.jpg".into()],
+        ignore: vec!["cache/".into()],
+        include_dirs: true,
+        max_depth: Some(0),
+        ..ScanOptions::default()
+    };
+    let page = remote::scan(
+        &mut host,
+        Path::new("/photos"),
+        &options,
+        &CancelToken::new(),
+    )
+    .unwrap();
+    let names: Vec<&str> = page.entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["img9.jpg", "img10.jpg"]);
+
+    let err = remote::scan(
+        &mut host,
+        Path::new("/missing"),
+        &ScanOptions::default(),
+        &CancelToken::new(),
+    )
+    .unwrap_err();
+    assert_eq!(err.code(), renamer_core::ErrorCode::NotFound);
+
+    // Names come from the listing, sizes included, without reading files.
+    let rows = remote::preview(
+        &page.entries,
+        &[Rule::Template {
+            template: "{name}-{size}.{ext}".into(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "img9-17.jpg");
+    assert_eq!(rows[0].target, Path::new("/photos/img9-17.jpg"));
+}
+
+#[test]
+fn remote_batches_order_swaps_and_skip_taken_targets() {
+    let mut host = FakeHost::new(&[
+        "/a.txt",
+        "/b.txt",
+        "/c.txt",
+        "/taken.txt",
+        "/d/",
+        "/d/e.txt",
+    ]);
+    let ops = vec![
+        RenameOp::new("/a.txt", "/b.txt"),
+        RenameOp::new("/b.txt", "/a.txt"),
+        RenameOp::new("/c.txt", "/taken.txt"),
+        RenameOp::new("/gone.txt", "/here.txt"),
+        RenameOp::new("/d", "/f"),
+        RenameOp::new("/d/e.txt", "/d/g.txt"),
+    ];
+    let report = remote::apply(&mut host, &ops, &CancelToken::new());
+    assert!(report.committed);
+    let statuses: Vec<OutcomeStatus> = report.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            OutcomeStatus::Renamed,
+            OutcomeStatus::Renamed,
+            OutcomeStatus::Skipped,
+            OutcomeStatus::Skipped,
+            OutcomeStatus::Renamed,
+            OutcomeStatus::Renamed,
+        ]
+    );
+    assert_eq!(
+        report.outcomes[2].error.as_deref(),
+        Some("a file already has the new name")
+    );
+    assert!(report.outcomes[0].verified);
+    assert_eq!(
+        host.paths(),
+        [
+            "/",
+            "/a.txt",
+            "/b.txt",
+            "/c.txt",
+            "/f",
+            "/f/g.txt",
+            "/taken.txt"
+        ]
+    );
+}
+
+#[test]
+fn a_failed_remote_rename_moves_the_batch_back() {
+    let mut host = FakeHost::new(&["/1.txt", "/2.txt", "/3.txt"]);
+    host.broken = Some(PathBuf::from("/three.txt"));
+    let ops = vec![
+        RenameOp::new("/1.txt", "/one.txt"),
+        RenameOp::new("/2.txt", "/two.txt"),
+        RenameOp::new("/3.txt", "/three.txt"),
+    ];
+    let report = remote::apply(&mut host, &ops, &CancelToken::new());
+    assert!(!report.committed);
+    assert_eq!(report.rolled_back, 2);
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.outcomes[2].error.as_deref(), Some("disk full"));
+    assert_eq!(host.paths(), ["/", "/1.txt", "/2.txt", "/3.txt"]);
+}
+
+fn string(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend((bytes.len() as u32).to_be_bytes());
+    out.extend(bytes);
+}
+
+fn attrs(out: &mut Vec<u8>, size: u64, mode: u32, mtime: u32) {
+    out.extend(0xdu32.to_be_bytes());
+    out.extend(size.to_be_bytes());
+    out.extend(mode.to_be_bytes());
+    out.extend(mtime.to_be_bytes());
+    out.extend(mtime.to_be_bytes());
+}
+
+/// Answers the requests a listing, a lookup and a rename make, as an
+/// OpenSSH server would, and sends back the renames it was asked for.
+fn serve(mut stream: TcpStream, renames: mpsc::Sender<(String, String)>) {
+    let mut listed = false;
+    loop {
+        let mut length = [0; 4];
+        if stream.read_exact(&mut length).is_err() {
+            return;
+        }
+        let mut packet = vec![0; u32::from_be_bytes(length) as usize];
+        stream.read_exact(&mut packet).unwrap();
+        let kind = packet[0];
+        let mut reply = Vec::new();
+        if kind == 1 {
+            reply.push(2);
+            reply.extend(3u32.to_be_bytes());
+        } else {
+            let mut fields = Vec::new();
+            let mut rest = &packet[5..];
+            while rest.len() >= 4 {
+                let n = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+                fields.push(String::from_utf8(rest[4..4 + n].to_vec()).unwrap());
+                rest = &rest[4 + n..];
+            }
+            let status = |reply: &mut Vec<u8>, code: u32| {
+                reply.push(101);
+                reply.extend(&packet[1..5]);
+                reply.extend(code.to_be_bytes());
+                string(reply, b"");
+                string(reply, b"");
+            };
+            match (kind, fields[0].as_str()) {
+                (11, "/photos") => {
+                    reply.push(102);
+                    reply.extend(&packet[1..5]);
+                    string(&mut reply, b"h1");
+                }
+                (12, _) if !listed => {
+                    listed = true;
+                    reply.push(104);
+                    reply.extend(&packet[1..5]);
+                    reply.extend(3u32.to_be_bytes());
+                    for (name, size, mode) in [
+                        (".", 0, 0o040755),
+                        ("b.jpg", 5, 0o100644),
+                        ("2023", 0, 0o040755),
+                    ] {
+                        string(&mut reply, name.as_bytes());
+                        string(&mut reply, b"-rw-r--r-- ...");
+                        attrs(&mut reply, size, mode, 1_700_000_000);
+                    }
+                }
+                (12, _) => status(&mut reply, 1),
+                (4, _) => status(&mut reply, 0),
+                (7, "/photos/b.jpg") => {
+                    reply.push(105);
+                    reply.extend(&packet[1..5]);
+                    attrs(&mut reply, 5, 0o100644, 1_700_000_000);
+                }
+                (7, _) => status(&mut reply, 2),
+                (18, _) => {
+                    renames
+                        .send((fields[0].clone(), fields[1].clone()))
+                        .unwrap();
+                    status(&mut reply, 0);
+                }
+                _ => status(&mut reply, 8),
+            }
+        }
+        stream
+            .write_all(&(reply.len() as u32).to_be_bytes())
+            .unwrap();
+        stream.write_all(&reply).unwrap();
+    }
+}
+
+#[test]
+fn sftp_sessions_list_look_up_and_rename_files() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, renames) = mpsc::channel();
+    let server = thread::spawn(move || serve(listener.accept().unwrap().0, sender));
+    let stream = TcpStream::connect(address).unwrap();
+    let mut sftp = Sftp::over(stream.try_clone().unwrap(), stream);
+
+    let mut entries = sftp.read_dir(Path::new("/photos")).unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, "2023");
+    assert!(entries[0].is_dir);
+    assert_eq!(entries[1].name, "b.jpg");
+    assert!(!entries[1].is_dir && !entries[1].is_symlink);
+    assert_eq!(entries[1].size, 5);
+    assert_eq!(
+        entries[1].modified.map(|m| m.timestamp()),
+        Some(1_700_000_000)
+    );
+
+    let found = sftp.lstat(Path::new("/photos/b.jpg")).unwrap().unwrap();
+    assert_eq!(found.name, "b.jpg");
+    assert_eq!(sftp.lstat(Path::new("/photos/c.jpg")).unwrap(), None);
+    let err = sftp.read_dir(Path::new("/elsewhere")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+
+    sftp.rename(Path::new("/photos/b.jpg"), Path::new("/photos/c.jpg"))
+        .unwrap();
+    assert_eq!(
+        renames.recv().unwrap(),
+        ("/photos/b.jpg".to_string(), "/photos/c.jpg".to_string())
+    );
+    drop(sftp);
+    server.join().unwrap();
+}
+
+/// One HTTP request: the method, the path, the headers, lowercased, and
+/// the body.
+fn read_request(stream: &mut TcpStream) -> (String, String, Vec<(String, String)>, String) {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    let mut lines = head.lines();
+    let mut first = lines.next().unwrap().split(' ');
+    let (method, path) = (first.next().unwrap(), first.next().unwrap());
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|l| l.split_once(": "))
+        .map(|(k, v)| (k.to_lowercase(), v.to_string()))
+        .collect();
+    let length = headers
+        .iter()
+        .find(|(k, _)| k == "content-length")
+        .map_or(0, |(_, v)| v.parse().unwrap());
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).unwrap();
+    let body = String::from_utf8(body).unwrap();
+    (method.to_string(), path.to_string(), headers, body)
+}
+
+fn dav_response(href: &str, collection: bool, extra: &str) -> String {
+    let kind = if collection { "<d:collection/>" } else { "" };
+    format!(
+        "<d:response><d:href>{}</d:href><d:propstat><d:prop><d:resourcetype>{}</d:resourcetype>{}</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        href, kind, extra
+    )
+}
+
+/// Answers as a Nextcloud share would, for the requests the WebDAV test
+/// makes, one connection each.
+fn serve_dav(listener: TcpListener, requests: usize) {
+    for _ in 0..requests {
+        let mut stream = listener.accept().unwrap().0;
+        let (method, path, headers, _) = read_request(&mut stream);
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        let (status, body) = match (method.as_str(), path.as_str()) {
+            ("PROPFIND", "/dav/files/alice") => (207, dav_response("/dav/files/alice/", true, "")),
+            ("PROPFIND", "/dav/files/alice/Photos/") => {
+                assert_eq!(header("depth"), "1");
+                let listing = [
+                    dav_response("/dav/files/alice/Photos/", true, ""),
+                    dav_response(
+                        "/dav/files/alice/Photos/My%20Trip.jpg",
+                        false,
+                        "<d:getcontentlength>10</d:getcontentlength><d:getlastmodified>Tue, 14 Nov 2023 22:13:20 GMT</d:getlastmodified>",
+                    ),
+                    dav_response("/dav/files/alice/Photos/Tom%20&amp;%20Jerry/", true, ""),
+                ];
+                (207, listing.concat())
+            }
+            ("MOVE", "/dav/files/alice/Photos/My%20Trip.jpg") => {
+                assert_eq!(header("overwrite"), "F");
+                let destination = header("destination");
+                if destination.ends_with("/Photos/taken.jpg") {
+                    (412, String::new())
+                } else {
+                    assert!(destination.ends_with("/dav/files/alice/Photos/trip%231.jpg"));
+                    (201, String::new())
+                }
+            }
+            _ => (404, String::new()),
+        };
+        let body = if status == 207 {
+            format!(
+                "<?xml version=\"1.0\"?><d:multistatus xmlns:d=\"DAV:\">{}</d:multistatus>",
+                body
+            )
+        } else {
+            body
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} X\r\nContent-Type: application/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn webdav_shares_list_look_up_and_move_files() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || serve_dav(listener, 5));
+    let target = WebDavTarget {
+        url: format!("http://{}/dav/files/alice/", address),
+        user: Some("alice".into()),
+        password: false,
+    };
+    let mut dav = WebDav::connect(&target).unwrap();
+
+    let mut entries = dav.read_dir(Path::new("/Photos")).unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, "My Trip.jpg");
+    assert_eq!(entries[0].size, 10);
+    assert_eq!(
+        entries[0].modified.map(|m| m.timestamp()),
+        Some(1_700_000_000)
+    );
+    assert_eq!(entries[1].name, "Tom & Jerry");
+    assert!(entries[1].is_dir);
+
+    assert_eq!(dav.lstat(Path::new("/Photos/gone.jpg")).unwrap(), None);
+    dav.rename(
+        Path::new("/Photos/My Trip.jpg"),
+        Path::new("/Photos/trip#1.jpg"),
+    )
+    .unwrap();
+    let err = dav
+        .rename(
+            Path::new("/Photos/My Trip.jpg"),
+            Path::new("/Photos/taken.jpg"),
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    server.join().unwrap();
+}
+
+/// A query string or path, percent-decoded.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            out.push(u8::from_str_radix(&text[i + 1..i + 3], 16).unwrap());
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).unwrap()
+}
+
+/// The objects of a bucket, by key, with their sizes, and the parts of
+/// uploads in progress.
+#[derive(Default)]
+struct Bucket {
+    objects: BTreeMap<String, u64>,
+    parts: Vec<String>,
+    batches: usize,
+}
+
+/// Answers as an S3 store would for the bucket `media`, one connection
+/// per request, until the test ends.
+fn serve_s3(listener: TcpListener, bucket: Arc<Mutex<Bucket>>) {
+    for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let (method, target, headers, body) = read_request(&mut stream);
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+        };
+        let authorization = header("authorization").unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKID/"));
+        assert!(authorization.contains("/eu-west-1/s3/aws4_request"));
+        let signed = authorization.split("SignedHeaders=").nth(1).unwrap();
+        assert!(signed.contains("host;") && signed.contains("x-amz-date"));
+        assert!(header("x-amz-content-sha256").is_some());
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let key = decode(path.strip_prefix("/media").unwrap().trim_start_matches('/'));
+        let query: BTreeMap<String, String> = query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (k, v) = p.split_once('=').unwrap_or((p, ""));
+                (decode(k), decode(v))
+            })
+            .collect();
+        let mut bucket = bucket.lock().unwrap();
+        let (status, length, body) = match method.as_str() {
+            "GET" => {
+                let prefix = &query["prefix"];
+                let mut contents = String::new();
+                let mut folders = Vec::new();
+                for (name, size) in bucket.objects.range(prefix.clone()..) {
+                    let Some(rest) = name.strip_prefix(prefix.as_str()) else {
+                        break;
+                    };
+                    match rest.find('/') {
+                        Some(i) if query.contains_key("delimiter") => {
+                            let folder = format!("{}{}/", prefix, &rest[..i]);
+                            if !folders.contains(&folder) {
+                                folders.push(folder);
+                            }
+                        }
+                        _ => contents.push_str(&format!(
+                            "<Contents><Key>{}</Key><LastModified>2023-11-14T22:13:20.000Z</LastModified><Size>{}</Size></Contents>",
+                            name.replace('&', "&amp;"),
+                            size
+                        )),
+                    }
+                }
+                let folders: String = folders
+                    .iter()
+                    .map(|f| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", f))
+                    .collect();
+                let body = format!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?><ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>media</Name><IsTruncated>false</IsTruncated>{}{}</ListBucketResult>",
+                    contents, folders
+                );
+                (200, body.len(), body)
+            }
+            "HEAD" => match bucket.objects.get(&key) {
+                Some(size) => (200, *size as usize, String::new()),
+                None => (404, 0, String::new()),
+            },
+            "PUT" => {
+                let source = decode(&header("x-amz-copy-source").unwrap());
+                let source = source.strip_prefix("/media/").unwrap().to_string();
+                let size = bucket.objects[&source];
+                let body = if query.contains_key("partNumber") {
+                    assert_eq!(query["uploadId"], "upload-1");
+                    bucket
+                        .parts
+                        .push(header("x-amz-copy-source-range").unwrap());
+                    format!(
+                        "<CopyPartResult><ETag>\"part{}\"</ETag></CopyPartResult>",
+                        query["partNumber"]
+                    )
+                } else {
+                    assert_eq!(header("x-amz-metadata-directive").unwrap(), "COPY");
+                    bucket.objects.insert(key, size);
+                    "<CopyObjectResult><ETag>\"copy\"</ETag></CopyObjectResult>".to_string()
+                };
+                (200, body.len(), body)
+            }
+            "POST" if query.contains_key("uploads") => {
+                let body = "<InitiateMultipartUploadResult><UploadId>upload-1</UploadId></InitiateMultipartUploadResult>".to_string();
+                (200, body.len(), body)
+            }
+            "POST" if query.contains_key("uploadId") => {
+                assert!(body.contains("<PartNumber>3</PartNumber><ETag>&quot;part3&quot;</ETag>"));
+                bucket.objects.insert(key, 25);
+                let body = "<CompleteMultipartUploadResult><ETag>\"done\"</ETag></CompleteMultipartUploadResult>".to_string();
+                (200, body.len(), body)
+            }
+            "POST" if query.contains_key("delete") => {
+                assert!(header("content-md5").is_some());
+                assert!(body.contains("<Quiet>true</Quiet>"));
+                bucket.batches += 1;
+                for key in body.split("<Key>").skip(1) {
+                    let key = key.split("</Key>").next().unwrap();
+                    bucket.objects.remove(&key.replace("&amp;", "&"));
+                }
+                let body = "<DeleteResult></DeleteResult>".to_string();
+                (200, body.len(), body)
+            }
+            "DELETE" => {
+                bucket.objects.remove(&key);
+                (204, 0, String::new())
+            }
+            _ => (400, 0, String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} X\r\nContent-Type: application/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, length, body
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn s3_buckets_list_prefixes_and_rename_by_copying() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let bucket = Arc::new(Mutex::new(Bucket::default()));
+    for (key, size) in [
+        ("Photos/", 0),
+        ("Photos/My Trip.jpg", 10),
+        ("Photos/big.mov", 25),
+        ("Photos/Trip/a.jpg", 3),
+        ("Photos/Trip/b.jpg", 4),
+    ] {
+        bucket.lock().unwrap().objects.insert(key.into(), size);
+    }
+    let served = Arc::clone(&bucket);
+    thread::spawn(move || serve_s3(listener, served));
+    let target = S3Target {
+        endpoint: format!("http://{}", address),
+        region: "eu-west-1".into(),
+        bucket: "media".into(),
+        access_key_id: "AKID".into(),
+        path_style: true,
+    };
+    let mut s3 = S3::login(&target, "secret".into())
+        .unwrap()
+        .with_part_size(10);
+
+    let mut entries = s3.read_dir(Path::new("/Photos")).unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let names: Vec<(&str, bool, u64)> = entries
+        .iter()
+        .map(|e| (e.name.as_str(), e.is_dir, e.size))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("My Trip.jpg", false, 10),
+            ("Trip", true, 0),
+            ("big.mov", false, 25)
+        ]
+    );
+    assert_eq!(
+        entries[0].modified.map(|m| m.timestamp()),
+        Some(1_700_000_000)
+    );
+    assert!(s3.lstat(Path::new("/Photos/Trip")).unwrap().unwrap().is_dir);
+    assert_eq!(s3.lstat(Path::new("/Photos/gone.jpg")).unwrap(), None);
+
+    s3.rename(
+        Path::new("/Photos/My Trip.jpg"),
+        Path::new("/Photos/trip & 1.jpg"),
+    )
+    .unwrap();
+
+    // Larger than a part, so copied in three.
+    let progress = Mutex::new(Vec::new());
+    s3.rename_with_progress(
+        Path::new("/Photos/big.mov"),
+        Path::new("/Photos/clip.mov"),
+        &|done, total| progress.lock().unwrap().push((done, total)),
+    )
+    .unwrap();
+    assert_eq!(*progress.lock().unwrap(), [(10, 25), (20, 25), (25, 25)]);
+
+    s3.rename(Path::new("/Photos/Trip"), Path::new("/Photos/Trip 2023"))
+        .unwrap();
+    let err = s3
+        .rename(
+            Path::new("/Photos/clip.mov"),
+            Path::new("/Photos/trip & 1.jpg"),
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+    let bucket = bucket.lock().unwrap();
+    assert_eq!(
+        bucket.objects.keys().collect::<Vec<_>>(),
+        [
+            "Photos/",
+            "Photos/Trip 2023/a.jpg",
+            "Photos/Trip 2023/b.jpg",
+            "Photos/clip.mov",
+            "Photos/trip & 1.jpg",
+        ]
+    );
+    assert_eq!(bucket.parts, ["bytes=0-9", "bytes=10-19", "bytes=20-24"]);
+    assert_eq!(bucket.batches, 1);
+}
+
+#[test]
+fn imports_copy_files_off_the_host_under_their_new_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut host = FakeHost::new(&[
+        "/DCIM/",
+        "/DCIM/IMG_1.jpg",
+        "/DCIM/IMG_2.jpg",
+        "/DCIM/IMG_3.jpg",
+    ]);
+    host.broken = Some(PathBuf::from("/DCIM/IMG_3.jpg"));
+    std::fs::write(dir.path().join("taken.jpg"), "mine").unwrap();
+    let ops = vec![
+        RenameOp::new("/DCIM/IMG_1.jpg", dir.path().join("2024/beach.jpg")),
+        RenameOp::new("/DCIM/IMG_2.jpg", dir.path().join("taken.jpg")),
+        RenameOp::new("/DCIM/IMG_3.jpg", dir.path().join("cake.jpg")),
+        RenameOp::new("/DCIM/IMG_4.jpg", dir.path().join("gone.jpg")),
+    ];
+    let report = remote::import(&mut host, &ops, &|_| {}, &CancelToken::new());
+    let statuses: Vec<OutcomeStatus> = report.outcomes.iter().map(|o| o.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            OutcomeStatus::Renamed,
+            OutcomeStatus::Skipped,
+            OutcomeStatus::Failed,
+            OutcomeStatus::Skipped,
+        ]
+    );
+    assert!(!report.committed);
+    assert!(report.outcomes[0].verified);
+    assert_eq!(
+        report.outcomes[2].error.as_deref(),
+        Some("device disconnected")
+    );
+    assert_eq!(
+        std::fs::metadata(dir.path().join("2024/beach.jpg"))
+            .unwrap()
+            .len(),
+        16
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("taken.jpg")).unwrap(),
+        "mine"
+    );
+    let mut left: Vec<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    left.sort();
+    assert_eq!(left, ["2024", "taken.jpg"]);
+    // The device is left as it was.
+    assert_eq!(host.paths().len(), 5);
+}
+
+/// Devices are reached through `gio` on Linux, which opens any GIO
+/// location, so a local folder stands in for a phone.
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn mtp_devices_list_rename_and_import_through_gio() {
+    if std::process::Command::new("gio")
+        .arg("version")
+        .output()
+        .is_err()
+    {
+        return;
+    }
+    let phone = tempfile::tempdir().unwrap();
+    std::fs::create_dir(phone.path().join("DCIM")).unwrap();
+    std::fs::write(phone.path().join("DCIM/IMG 1#.jpg"), "photo").unwrap();
+    std::fs::write(phone.path().join("DCIM/taken.jpg"), "other").unwrap();
+    let target = MtpTarget {
+        device: format!("file://{}", phone.path().display()),
+    };
+    let mut mtp = Mtp::connect(&target).unwrap();
+
+    let mut entries = mtp.read_dir(Path::new("/DCIM")).unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let names: Vec<(&str, u64)> = entries.iter().map(|e| (e.name.as_str(), e.size)).collect();
+    assert_eq!(names, [("IMG 1#.jpg", 5), ("taken.jpg", 5)]);
+    assert!(entries[0].modified.is_some());
+    assert!(mtp.lstat(Path::new("/DCIM")).unwrap().unwrap().is_dir);
+    assert_eq!(mtp.lstat(Path::new("/DCIM/gone.jpg")).unwrap(), None);
+
+    let err = mtp
+        .rename(Path::new("/DCIM/IMG 1#.jpg"), Path::new("/DCIM/taken.jpg"))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    mtp.rename(Path::new("/DCIM/IMG 1#.jpg"), Path::new("/DCIM/beach.jpg"))
+        .unwrap();
+    assert!(phone.path().join("DCIM/beach.jpg").exists());
+
+    let local = tempfile::tempdir().unwrap();
+    let ops = vec![RenameOp::new(
+        "/DCIM/beach.jpg",
+        local.path().join("2024-beach.jpg"),
+    )];
+    let report = remote::import(&mut mtp, &ops, &|_| {}, &CancelToken::new());
+    assert!(report.committed);
+    assert!(report.outcomes[0].verified);
+    assert_eq!(
+        std::fs::read_to_string(local.path().join("2024-beach.jpg")).unwrap(),
+        "photo"
+    );
+    assert!(phone.path().join("DCIM/beach.jpg").exists());
+}
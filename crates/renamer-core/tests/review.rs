@@ -0,0 +1,101 @@
+use std::fs;
+
+use renamer_core::review;
+use renamer_core::{plan, review_id, RenameOp, RenamePlan, ReviewProblemKind, ReviewStatus};
+
+fn planned(dir: &std::path::Path) -> RenamePlan {
+    let ops = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| {
+            let source = dir.join(name);
+            fs::write(&source, name).unwrap();
+            RenameOp::new(&source, dir.join(format!("new-{}", name)))
+        })
+        .collect();
+    plan(ops, &Default::default())
+}
+
+#[test]
+fn an_exported_plan_reads_back_unchanged() {
+    let dir = tempfile::tempdir().unwrap();
+    let plan = planned(dir.path());
+    let file = dir.path().join("review.csv");
+    review::export(&plan, &file).unwrap();
+
+    let reviewed = review::read(&file, &plan).unwrap();
+    assert!(reviewed.problems.is_empty());
+    assert!(reviewed
+        .changes
+        .iter()
+        .all(|c| c.status == ReviewStatus::Kept));
+    let targets: Vec<_> = reviewed.ops.iter().map(|op| op.target.clone()).collect();
+    let planned: Vec<_> = plan.entries.iter().map(|e| e.target.clone()).collect();
+    assert_eq!(targets, planned);
+}
+
+#[test]
+fn edited_and_deleted_rows_are_told_apart_from_bad_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let plan = planned(dir.path());
+    let [a, b, c] = ["a.txt", "b.txt", "c.txt"].map(|name| dir.path().join(name));
+    // Sorted, columns moved and saved with semicolons, as a spreadsheet
+    // might; `c.txt` is deleted.
+    let text = format!(
+        "new_name;id;source\n\
+         Invoice A.txt;{a_id};{a}\n\
+         new-b.txt;{b_id};{b}\n\
+         again.txt;{a_id};{a}\n\
+         x.txt;0000;{c}\n",
+        a_id = review_id(&a),
+        b_id = review_id(&b),
+        a = a.display(),
+        b = b.display(),
+        c = c.display(),
+    );
+    let reviewed = review::parse(&text, &plan).unwrap();
+
+    let changes: Vec<_> = reviewed
+        .changes
+        .iter()
+        .map(|c| (c.source.clone(), c.status))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (a.clone(), ReviewStatus::Edited),
+            (b.clone(), ReviewStatus::Kept),
+            (c, ReviewStatus::Removed),
+        ]
+    );
+    assert_eq!(
+        reviewed.ops,
+        [
+            RenameOp::new(&a, dir.path().join("Invoice A.txt")),
+            RenameOp::new(&b, dir.path().join("new-b.txt")),
+        ]
+    );
+    let problems: Vec<_> = reviewed.problems.iter().map(|p| (p.line, p.kind)).collect();
+    assert_eq!(
+        problems,
+        [
+            (4, ReviewProblemKind::Duplicate),
+            (5, ReviewProblemKind::UnknownId),
+        ]
+    );
+}
+
+#[test]
+fn a_row_whose_source_was_edited_is_not_taken() {
+    let dir = tempfile::tempdir().unwrap();
+    let plan = planned(dir.path());
+    let a = dir.path().join("a.txt");
+    let text = format!(
+        "id,source,new_name\n{},{},z.txt\n",
+        review_id(&a),
+        dir.path().join("elsewhere.txt").display()
+    );
+    let reviewed = review::parse(&text, &plan).unwrap();
+    assert!(reviewed.ops.is_empty());
+    assert_eq!(reviewed.problems[0].kind, ReviewProblemKind::SourceChanged);
+    assert!(reviewed.changes.iter().all(|c| c.source != a));
+}
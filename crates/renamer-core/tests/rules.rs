@@ -0,0 +1,262 @@
+use std::path::Path;
+
+use renamer_core::{FileContext, Pipeline, RegexRule, Rule};
+
+fn run(pipeline: &Pipeline, name: &str) -> String {
+    pipeline.apply(name, &FileContext::new(Path::new(name), 0))
+}
+
+fn regex(pattern: &str, replacement: &str) -> RegexRule {
+    RegexRule {
+        pattern: pattern.into(),
+        replacement: replacement.into(),
+        case_insensitive: false,
+        replace_all: true,
+    }
+}
+
+#[test]
+fn regex_substitutes_numbered_and_named_groups() {
+    let numbered = Pipeline::new(&[Rule::Regex(regex(r"^(\w+)-(\d+)", "${2}_$1"))]).unwrap();
+    assert_eq!(run(&numbered, "report-2024.pdf"), "2024_report.pdf");
+
+    let named = Pipeline::new(&[Rule::Regex(regex(
+        r"(?P<y>\d{4})(?P<m>\d{2})(?P<d>\d{2})",
+        "${y}-${m}-${d}",
+    ))])
+    .unwrap();
+    assert_eq!(run(&named, "IMG_20240131.jpg"), "IMG_2024-01-31.jpg");
+}
+
+#[test]
+fn regex_honours_case_and_first_only_flags() {
+    let mut rule = regex("a", "_");
+    rule.case_insensitive = true;
+    rule.replace_all = false;
+    let pipeline = Pipeline::new(&[Rule::Regex(rule)]).unwrap();
+    assert_eq!(run(&pipeline, "AbAa.txt"), "_bAa.txt");
+}
+
+#[test]
+fn invalid_patterns_are_reported() {
+    let err = Pipeline::new(&[Rule::Regex(regex("(unclosed", ""))]).unwrap_err();
+    assert!(err.to_string().contains("(unclosed"));
+}
+
+#[test]
+fn rules_deserialize_from_tagged_json() {
+    let rules: Vec<Rule> = serde_json::from_str(
+        r#"[{"type": "regex", "pattern": "\\s+", "replacement": "_"},
+            {"type": "prefix", "text": "x"}]"#,
+    )
+    .unwrap();
+    assert_eq!(run(&Pipeline::new(&rules).unwrap(), "a  b.txt"), "xa_b.txt");
+}
+
+#[test]
+fn case_rule_keeps_extension_by_default() {
+    let rules: Vec<Rule> = serde_json::from_str(r#"[{"type": "case", "mode": "upper"}]"#).unwrap();
+    assert_eq!(
+        run(&Pipeline::new(&rules).unwrap(), "photo.jpg"),
+        "PHOTO.jpg"
+    );
+}
+
+#[test]
+fn set_modified_reads_the_date_from_the_name() {
+    use chrono::{Local, TimeZone};
+
+    let name = "IMG_20230714_101500.jpg";
+    let pipeline = Pipeline::new(&[
+        Rule::SetModified {
+            format: "yyyyMMdd_HHmmss".into(),
+        },
+        Rule::Prefix { text: "x".into() },
+    ])
+    .unwrap();
+    let renamed = pipeline.run(name, &FileContext::new(Path::new(name), 0));
+    assert_eq!(renamed.name, "xIMG_20230714_101500.jpg");
+    assert_eq!(
+        renamed.modified,
+        Some(Local.with_ymd_and_hms(2023, 7, 14, 10, 15, 0).unwrap())
+    );
+
+    // Impossible dates are passed over; no time means midnight.
+    let dates = Pipeline::new(&[Rule::SetModified {
+        format: "yyyy-MM-dd".into(),
+    }])
+    .unwrap();
+    let name = "scan 2021-02-30 2021-03-01.pdf";
+    let renamed = dates.run(name, &FileContext::new(Path::new(name), 0));
+    assert_eq!(
+        renamed.modified,
+        Some(Local.with_ymd_and_hms(2021, 3, 1, 0, 0, 0).unwrap())
+    );
+    assert_eq!(run(&dates, "notes.txt"), "notes.txt");
+
+    assert!(Pipeline::new(&[Rule::SetModified {
+        format: "HH:mm".into()
+    }])
+    .is_err());
+}
+
+#[test]
+fn when_rules_apply_only_to_files_their_conditions_hold_for() {
+    use renamer_core::{ConditionCheck, MetaValue, Metadata};
+
+    let rules: Vec<Rule> = serde_json::from_str(
+        r#"[
+            {"type": "when", "conditions": [
+                {"type": "extension", "extensions": ["JPG", ".jpeg"]},
+                {"type": "larger_than", "bytes": 1000}
+            ], "rules": [
+                {"type": "prefix", "text": "big-"},
+                {"type": "when", "any": true, "conditions": [
+                    {"type": "matches", "pattern": "^big-img", "case_insensitive": true},
+                    {"type": "has_metadata", "key": "exif.camera"}
+                ], "rules": [{"type": "suffix", "text": "-cam"}]}
+            ]}
+        ]"#,
+    )
+    .unwrap();
+    let pipeline = Pipeline::new(&rules).unwrap();
+    assert!(pipeline.needs_metadata());
+    assert!(pipeline.needs_embedded());
+
+    let path = Path::new("IMG_1.jpg");
+    let big = FileContext {
+        size: Some(5000),
+        ..FileContext::new(path, 0)
+    };
+    let renamed = pipeline.run("IMG_1.jpg", &big);
+    assert_eq!(renamed.name, "big-IMG_1-cam.jpg");
+    assert_eq!(
+        renamed.conditions,
+        [
+            ConditionCheck {
+                rule: vec![0],
+                matched: vec![true, true],
+                applied: true,
+            },
+            ConditionCheck {
+                rule: vec![0, 1],
+                matched: vec![true, false],
+                applied: true,
+            },
+        ]
+    );
+
+    // Conditions read the name as the earlier rules left it.
+    let photo = FileContext {
+        size: Some(5000),
+        embedded: Some(Metadata {
+            path: path.into(),
+            values: [("exif.camera".to_string(), MetaValue::Text("X100".into()))].into(),
+        }),
+        ..FileContext::new(path, 0)
+    };
+    assert_eq!(pipeline.apply("photo.JPG", &photo), "big-photo-cam.JPG");
+    assert_eq!(pipeline.apply("photo.jpeg", &big), "big-photo.jpeg");
+
+    // Unknown sizes never match, and nested rules are not checked.
+    let renamed = pipeline.run("IMG_1.jpg", &FileContext::new(path, 0));
+    assert_eq!(renamed.name, "IMG_1.jpg");
+    assert_eq!(renamed.conditions.len(), 1);
+    assert_eq!(renamed.conditions[0].matched, [true, false]);
+    assert!(!renamed.conditions[0].applied);
+
+    let unknown: Vec<Rule> = serde_json::from_str(
+        r#"[{"type": "when", "conditions": [{"type": "has_metadata", "key": "exif.mood"}], "rules": []}]"#,
+    )
+    .unwrap();
+    assert!(Pipeline::new(&unknown).is_err());
+}
+
+#[test]
+fn scripts_rename_from_the_name_and_metadata_within_a_time_limit() {
+    use renamer_core::{MetaValue, Metadata};
+
+    let script = |source: &str| {
+        Pipeline::new(&[Rule::Script {
+            script: source.into(),
+            timeout_ms: 50,
+        }])
+        .unwrap()
+    };
+    let path = Path::new("IMG_1.jpg");
+    let ctx = FileContext {
+        size: Some(2048),
+        embedded: Some(Metadata {
+            path: path.into(),
+            values: [("exif.camera".to_string(), MetaValue::Text("X100".into()))].into(),
+        }),
+        ..FileContext::new(path, 0)
+    };
+
+    let camera = script(
+        r#"
+        let camera = meta["exif.camera"] ?? "unknown";
+        if meta.size > 1024 { `${camera}-${stem.to_lower()}${ext}` }
+        "#,
+    );
+    assert!(camera.needs_embedded());
+    let renamed = camera.run("IMG_1.jpg", &ctx);
+    assert_eq!(renamed.name, "X100-img_1.jpg");
+    assert!(renamed.step_errors.is_empty());
+    // No value: the name stays.
+    assert_eq!(
+        camera.apply("IMG_1.jpg", &FileContext::new(path, 0)),
+        "IMG_1.jpg"
+    );
+
+    let endless = script("loop { }");
+    let renamed = endless.run("IMG_1.jpg", &ctx);
+    assert_eq!(renamed.name, "IMG_1.jpg");
+    assert_eq!(renamed.step_errors, ["the script ran longer than 50 ms"]);
+
+    let number = script("42").run("IMG_1.jpg", &ctx);
+    assert_eq!(number.name, "IMG_1.jpg");
+    assert_eq!(number.step_errors.len(), 1);
+
+    assert!(Pipeline::new(&[Rule::Script {
+        script: "let = ;".into(),
+        timeout_ms: 50,
+    }])
+    .is_err());
+    // Scripts cannot load other files.
+    let import = script(r#"import "secrets" as s; name"#).run("a.txt", &ctx);
+    assert_eq!(import.step_errors.len(), 1);
+}
+
+#[test]
+fn every_step_reports_the_name_it_left() {
+    use renamer_core::StepName;
+
+    let rules: Vec<Rule> = serde_json::from_str(
+        r#"[
+            {"type": "prefix", "text": "a-"},
+            {"type": "when", "conditions": [{"type": "matches", "pattern": "^a-"}], "rules": [
+                {"type": "suffix", "text": "-b"}
+            ]},
+            {"type": "when", "conditions": [{"type": "matches", "pattern": "^z"}], "rules": [
+                {"type": "suffix", "text": "-never"}
+            ]}
+        ]"#,
+    )
+    .unwrap();
+    let pipeline = Pipeline::new(&rules).unwrap();
+    let renamed = pipeline.run("x.txt", &FileContext::new(Path::new("x.txt"), 0));
+    let step = |rule: &[usize], name: &str| StepName {
+        rule: rule.to_vec(),
+        name: name.to_string(),
+    };
+    assert_eq!(
+        renamed.steps,
+        [
+            step(&[0], "a-x.txt"),
+            step(&[1, 0], "a-x-b.txt"),
+            step(&[1], "a-x-b.txt"),
+            step(&[2], "a-x-b.txt"),
+        ]
+    );
+}
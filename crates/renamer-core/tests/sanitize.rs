@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use renamer_core::sanitize::sanitize;
+use renamer_core::{preview, Rule, SanitizeFix, TargetFs};
+
+#[test]
+fn windows_targets_get_the_windows_rules() {
+    let (name, fixes) = sanitize("a<b>:c?.txt. ", TargetFs::Ntfs, "_");
+    assert_eq!(name, "a_b__c_.txt");
+    assert_eq!(
+        fixes,
+        vec![
+            SanitizeFix::Replaced {
+                character: '<',
+                count: 1
+            },
+            SanitizeFix::Replaced {
+                character: '>',
+                count: 1
+            },
+            SanitizeFix::Replaced {
+                character: ':',
+                count: 1
+            },
+            SanitizeFix::Replaced {
+                character: '?',
+                count: 1
+            },
+            SanitizeFix::TrimmedTrailing {
+                removed: ". ".into()
+            },
+        ]
+    );
+
+    assert_eq!(sanitize("con.txt", TargetFs::Exfat, "").0, "con_.txt");
+    assert_eq!(sanitize("COM3", TargetFs::Smb, "-").0, "COM3-");
+    assert_eq!(sanitize("COM10.txt", TargetFs::Ntfs, "_").0, "COM10.txt");
+    assert_eq!(sanitize("tab\there", TargetFs::Ntfs, "").0, "tabhere");
+}
+
+#[test]
+fn linux_targets_only_refuse_slashes_and_nul() {
+    let (name, fixes) = sanitize("a:b?/c\0. ", TargetFs::Ext4, "_");
+    assert_eq!(name, "a:b?_c_. ");
+    assert_eq!(fixes.len(), 2);
+    assert_eq!(sanitize("con.txt", TargetFs::Ext4, "_").0, "con.txt");
+}
+
+#[test]
+fn preview_reports_sanitized_names() {
+    let rows = preview(
+        &[PathBuf::from("/music/AC|DC - Back? .mp3")],
+        &[Rule::Sanitize {
+            filesystem: TargetFs::Ntfs,
+            replacement: String::new(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "ACDC - Back .mp3");
+    assert_eq!(rows[0].sanitized.len(), 2);
+
+    let err = preview(
+        &[PathBuf::from("/a.txt")],
+        &[Rule::Sanitize {
+            filesystem: TargetFs::Ntfs,
+            replacement: ":".into(),
+        }],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("`:`"));
+}
+
+#[test]
+fn long_names_are_cut_between_words_keeping_counters_and_hashes() {
+    use renamer_core::sanitize::shorten;
+
+    let name = "Holiday in the mountains with friends_0042.jpg";
+    assert_eq!(shorten(name, 100), None);
+    assert_eq!(shorten(name, 30).unwrap(), "Holiday in the_0042.jpg");
+    assert_eq!(
+        shorten("report final-3a7bd3e2.pdf", 20).unwrap(),
+        "report-3a7bd3e2.pdf"
+    );
+    assert_eq!(shorten("Party (2).png", 12).unwrap(), "Part (2).png");
+    // One long word is cut inside, and never mid-character.
+    assert_eq!(shorten("Überraschungsparty.txt", 10).unwrap(), "Überr.txt");
+    assert_eq!(shorten("a.verylongextension", 5).unwrap(), "a.ver");
+
+    let dir = PathBuf::from("/photos/2024");
+    let rows = preview(
+        &[dir.join("Sunset over the harbour.jpg")],
+        &[Rule::Truncate {
+            max: dir.as_os_str().len() + 20,
+            path: true,
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "Sunset over the.jpg");
+    assert!(rows[0].shortened);
+    assert!(rows[0].target.as_os_str().len() <= dir.as_os_str().len() + 20);
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::Path;
+
+use renamer_core::{
+    scan, CancelToken, DropScanner, ScanListing, ScanOptions, ScanQuery, ScanSort, Scanner,
+};
+
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for file in [
+        "a.jpg",
+        "b.JPG",
+        "notes.txt",
+        ".hidden.jpg",
+        "trip/c.jpg",
+        "trip/c_thumb.jpg",
+        "trip/deep/d.jpg",
+        ".cache/e.jpg",
+    ] {
+        let path = dir.path().join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x").unwrap();
+    }
+    dir
+}
+
+fn names(root: &Path, options: ScanOptions) -> Vec<String> {
+    scan(root, options)
+        .unwrap()
+        .entries
+        .iter()
+        .map(|e| {
+            e.path
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/")
+        })
+        .collect()
+}
+
+#[test]
+fn hard_links_share_a_file_id() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.jpg"), "a").unwrap();
+    fs::hard_link(dir.path().join("a.jpg"), dir.path().join("b.jpg")).unwrap();
+    fs::write(dir.path().join("c.jpg"), "c").unwrap();
+
+    let entries = scan(dir.path(), ScanOptions::default()).unwrap().entries;
+    let (a, b, c) = (&entries[0], &entries[1], &entries[2]);
+    assert!(a.file_id.is_some());
+    assert_eq!(a.file_id, b.file_id);
+    assert_ne!(a.file_id, c.file_id);
+    assert_eq!((a.links, b.links, c.links), (2, 2, 1));
+}
+
+#[test]
+fn filters_by_glob_depth_and_hidden() {
+    let dir = tree();
+    let root = dir.path();
+
+    let jpgs = ScanOptions {
+        patterns: vec!["*.jpg".into(), "!*thumb*".into()],
+        ..ScanOptions::default()
+    };
+    assert_eq!(
+        names(root, jpgs.clone()),
+        vec!["a.jpg", "b.JPG", "trip/c.jpg", "trip/deep/d.jpg"]
+    );
+
+    let shallow = ScanOptions {
+        max_depth: Some(1),
+        ..jpgs.clone()
+    };
+    assert_eq!(names(root, shallow), vec!["a.jpg", "b.JPG", "trip/c.jpg"]);
+
+    let hidden = ScanOptions {
+        include_hidden: true,
+        max_depth: Some(0),
+        ..jpgs
+    };
+    assert_eq!(names(root, hidden), vec![".hidden.jpg", "a.jpg", "b.JPG"]);
+
+    let pruned = ScanOptions {
+        patterns: vec!["!trip".into()],
+        ..ScanOptions::default()
+    };
+    assert_eq!(names(root, pruned), vec!["a.jpg", "b.JPG", "notes.txt"]);
+}
+
+#[test]
+fn lists_folders_on_request() {
+    let dir = tree();
+    let options = ScanOptions {
+        include_dirs: true,
+        ..ScanOptions::default()
+    };
+    assert_eq!(
+        names(dir.path(), options.clone()),
+        vec![
+            "a.jpg",
+            "b.JPG",
+            "notes.txt",
+            "trip",
+            "trip/c.jpg",
+            "trip/c_thumb.jpg",
+            "trip/deep",
+            "trip/deep/d.jpg"
+        ]
+    );
+    let page = scan(dir.path(), options).unwrap();
+    assert!(page.entries[3].is_dir && !page.entries[4].is_dir);
+
+    let deep_only = ScanOptions {
+        include_dirs: true,
+        patterns: vec!["deep".into()],
+        ..ScanOptions::default()
+    };
+    assert_eq!(names(dir.path(), deep_only), vec!["trip/deep"]);
+}
+
+#[test]
+fn pages_through_large_trees() {
+    let dir = tree();
+    let mut scanner = Scanner::new(dir.path(), ScanOptions::default()).unwrap();
+
+    let first = scanner.next_page(2);
+    assert_eq!(first.entries.len(), 2);
+    assert!(!first.done);
+
+    let mut total = first.entries.len();
+    loop {
+        let page = scanner.next_page(2);
+        total += page.entries.len();
+        if page.done {
+            break;
+        }
+    }
+    // Everything except the two hidden entries.
+    assert_eq!(total, 6);
+    assert!(Scanner::new(
+        dir.path(),
+        ScanOptions {
+            patterns: vec!["[".into()],
+            ..ScanOptions::default()
+        }
+    )
+    .is_err());
+}
+
+#[test]
+fn cancelled_scans_stop_with_what_they_have() {
+    let dir = tree();
+    let cancel = CancelToken::new();
+    let mut scanner = Scanner::new(dir.path(), ScanOptions::default())
+        .unwrap()
+        .with_cancel(cancel.clone());
+
+    assert_eq!(scanner.next_page(2).entries.len(), 2);
+    cancel.cancel();
+    let page = scanner.next_page(2);
+    assert!(page.done && page.cancelled);
+    assert!(page.entries.is_empty());
+}
+
+#[test]
+fn honors_ignore_files_and_ignore_patterns() {
+    let dir = tree();
+    let root = dir.path();
+    for file in [
+        "node_modules/pkg/index.js",
+        "trip/raw/e.cr2",
+        "trip/raw/keep.cr2",
+    ] {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x").unwrap();
+    }
+    fs::write(root.join(".renamerignore"), "node_modules/\n*.cr2\n").unwrap();
+    fs::write(root.join("trip/.renamerignore"), "deep\n!raw/keep.cr2\n").unwrap();
+
+    let options = ScanOptions {
+        ignore: vec!["*_thumb.jpg".into()],
+        ..ScanOptions::default()
+    };
+    assert_eq!(
+        names(root, options.clone()),
+        [
+            "a.jpg",
+            "b.JPG",
+            "notes.txt",
+            "trip/c.jpg",
+            "trip/raw/keep.cr2"
+        ]
+    );
+
+    let everything = names(
+        root,
+        ScanOptions {
+            skip_ignore_files: true,
+            ..options
+        },
+    );
+    assert!(everything.contains(&"node_modules/pkg/index.js".to_string()));
+    assert!(everything.contains(&"trip/deep/d.jpg".to_string()));
+
+    let bad = ScanOptions {
+        ignore: vec!["[z-a]".into()],
+        ..ScanOptions::default()
+    };
+    assert!(scan(root, bad).is_err());
+}
+
+#[test]
+fn dropped_folders_are_expanded_without_listing_a_file_twice() {
+    let dir = tree();
+    let root = dir.path();
+    let options = ScanOptions {
+        patterns: vec!["*.jpg".into(), "!*thumb*".into()],
+        ..ScanOptions::default()
+    };
+    let mut drop = DropScanner::new(
+        vec![
+            root.join("notes.txt"),
+            root.join("trip"),
+            root.join("trip/c.jpg"),
+            root.join("missing.jpg"),
+            root.join("a.jpg"),
+        ],
+        options,
+        [root.join("a.jpg")],
+    )
+    .unwrap();
+
+    let first = drop.next_page(2);
+    assert!(!first.done);
+    let mut entries = first.entries;
+    let rest = drop.next_page(100);
+    assert!(rest.done);
+    entries.extend(rest.entries);
+    let paths: Vec<_> = entries.iter().map(|e| e.path.clone()).collect();
+    // Dropped files skip the patterns; the folder's contents do not.
+    assert_eq!(
+        paths,
+        [
+            root.join("notes.txt"),
+            root.join("trip/c.jpg"),
+            root.join("trip/deep/d.jpg"),
+        ]
+    );
+    assert_eq!(entries[0].size, 1);
+    assert_eq!(rest.errors.len(), 1);
+    assert_eq!(
+        rest.errors[0].path.as_deref(),
+        Some(&*root.join("missing.jpg"))
+    );
+
+    assert!(DropScanner::new(
+        vec![],
+        ScanOptions {
+            patterns: vec!["[".into()],
+            ..ScanOptions::default()
+        },
+        []
+    )
+    .is_err());
+}
+
+#[test]
+fn listings_hand_out_sorted_and_filtered_windows() {
+    let dir = tree();
+    let mut scanner = Scanner::new(dir.path(), ScanOptions::default()).unwrap();
+    let mut listing = ScanListing::new();
+    listing.extend(scanner.next_page(2).entries);
+    assert_eq!(listing.query(&ScanQuery::default()).total, 2);
+    listing.extend(scanner.next_page(100).entries);
+
+    let window = listing.query(&ScanQuery {
+        sort: ScanSort::Name,
+        descending: true,
+        filter: "JPG".into(),
+        offset: 1,
+        limit: Some(2),
+    });
+    assert_eq!(window.total, 6);
+    assert_eq!(window.matching, 5);
+    let names: Vec<_> = window.entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["c_thumb.jpg", "c.jpg"]);
+
+    let everything = listing.query(&ScanQuery {
+        offset: 4,
+        ..ScanQuery::default()
+    });
+    assert_eq!(everything.matching, 6);
+    assert_eq!(everything.entries.len(), 2);
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+
+use renamer_core::{Error, PathScope};
+
+#[test]
+fn allows_only_what_is_under_an_allowed_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let photos = dir.path().join("photos");
+    let other = dir.path().join("other");
+    fs::create_dir_all(photos.join("2024")).unwrap();
+    fs::create_dir(&other).unwrap();
+    fs::write(other.join("secret.txt"), "x").unwrap();
+
+    let mut scope = PathScope::new();
+    scope.allow(&photos);
+    assert!(scope.allows(&photos));
+    assert!(scope.allows(&photos.join("2024").join("a.jpg")));
+    // Targets in folders that do not exist yet are fine, as long as they
+    // do not climb out through them.
+    assert!(scope.allows(&photos.join("new").join("a.jpg")));
+    assert!(!scope.allows(&photos.join("new").join("..").join("..").join("other")));
+    assert!(!scope.allows(&photos.join("..").join("other").join("secret.txt")));
+    assert!(!scope.allows(&other));
+    assert!(!scope.allows(Path::new("photos/a.jpg")));
+
+    let outside = photos.join("..").join("other").join("secret.txt");
+    match scope.check([photos.join("a.jpg").as_path(), outside.as_path()]) {
+        Err(Error::OutOfScope { path }) => assert_eq!(path, outside),
+        other => panic!("expected out of scope, got {:?}", other),
+    }
+
+    // A folder above an allowed one replaces it.
+    scope.allow(dir.path());
+    assert_eq!(scope.roots().len(), 1);
+    assert!(scope.check([outside.as_path()]).is_ok());
+    scope.clear();
+    assert!(!scope.allows(&photos));
+}
+
+#[cfg(unix)]
+#[test]
+fn links_out_of_an_allowed_folder_are_out_of_scope() {
+    let dir = tempfile::tempdir().unwrap();
+    let photos = dir.path().join("photos");
+    let other = dir.path().join("other");
+    fs::create_dir(&photos).unwrap();
+    fs::create_dir(&other).unwrap();
+    std::os::unix::fs::symlink(&other, photos.join("link")).unwrap();
+
+    let mut scope = PathScope::new();
+    scope.allow(&photos);
+    assert!(!scope.allows(&photos.join("link").join("a.txt")));
+    assert!(scope.allows(&photos.join("a.txt")));
+}
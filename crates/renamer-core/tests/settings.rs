@@ -0,0 +1,97 @@
+use std::fs;
+
+use renamer_core::{Error, Settings, SettingsStore};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct App {
+    last_preset: Option<String>,
+    sidebar_open: bool,
+    recent: Vec<String>,
+}
+
+#[test]
+fn missing_settings_are_the_defaults_and_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = SettingsStore::new(dir.path().join("data/settings.json"));
+
+    let settings: Settings<App> = store.load().unwrap();
+    assert_eq!(settings.app, App::default());
+    assert_eq!(settings.apply.lock_retries, 0);
+
+    let written = store
+        .update(|s: &mut Settings<App>| {
+            s.apply.lock_retries = 2;
+            s.app.sidebar_open = true;
+        })
+        .unwrap();
+    assert!(written.app.sidebar_open);
+
+    let json: Value = serde_json::from_slice(&fs::read(store.path()).unwrap()).unwrap();
+    assert_eq!(json["version"], 1);
+    assert_eq!(json["apply"]["lock_retries"], 2);
+    assert_eq!(json["app"]["sidebar_open"], true);
+
+    let settings: Settings<App> = store.load().unwrap();
+    assert_eq!(settings.apply.lock_retries, 2);
+    assert!(settings.app.sidebar_open);
+}
+
+#[test]
+fn unversioned_settings_move_under_app() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.json");
+    fs::write(&path, r#"{"last_preset": "Photos", "recent": []}"#).unwrap();
+    let store = SettingsStore::new(&path);
+
+    let settings: Settings<App> = store.load().unwrap();
+    assert_eq!(settings.app.last_preset.as_deref(), Some("Photos"));
+
+    // A reader that does not know the app's part keeps it as it is.
+    let settings: Settings = store.load().unwrap();
+    store.save(&settings).unwrap();
+    let json: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(json["app"], json!({"last_preset": "Photos", "recent": []}));
+    assert_eq!(json["version"], 1);
+}
+
+#[test]
+fn settings_from_a_newer_version_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.json");
+    fs::write(&path, r#"{"version": 99, "app": {}}"#).unwrap();
+
+    let loaded: Result<Settings, _> = SettingsStore::new(&path).load();
+    assert!(matches!(
+        loaded,
+        Err(Error::UnsupportedVersion { version: 99, .. })
+    ));
+}
+
+#[test]
+fn patches_change_settings_but_not_the_kept_ones() {
+    let mut settings: Settings<App> = Settings::default();
+    settings.app.last_preset = Some("Photos".into());
+    settings.app.recent = vec!["/home/me/photos".into()];
+    let kept = ["/app/recent"];
+
+    let patched = settings
+        .patched(
+            json!({"app": {"sidebar_open": true, "last_preset": null, "recent": ["/"]}}),
+            &kept,
+        )
+        .unwrap();
+    assert!(patched.app.sidebar_open);
+    assert_eq!(patched.app.last_preset, None);
+    assert_eq!(patched.app.recent, ["/home/me/photos"]);
+
+    let cleared = settings
+        .patched(json!({"app": {"recent": null}}), &kept)
+        .unwrap();
+    assert_eq!(cleared.app.recent, ["/home/me/photos"]);
+    assert!(settings
+        .patched(json!({"audit_log": "yes"}), &kept)
+        .is_err());
+}
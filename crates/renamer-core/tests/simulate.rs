@@ -0,0 +1,111 @@
+use std::fs;
+
+use renamer_core::{
+    simulate, ApplyOptions, CollisionStrategy, EntryStatus, OutcomeStatus, Preset, Resolution,
+    Rule, ScanOptions, Snapshot,
+};
+
+#[test]
+fn collisions_are_found_against_files_the_scan_left_out() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["a.txt", "ab.txt", "b.txt", "bb.txt"] {
+        fs::write(dir.path().join(name), name).unwrap();
+    }
+    let snapshot = Snapshot::scan(
+        dir.path(),
+        ScanOptions {
+            patterns: vec!["a*".into()],
+            ..ScanOptions::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(snapshot.entries.len(), 2);
+    let mut preset = Preset::new(
+        "Swap letters",
+        vec![Rule::Replace {
+            find: "a".into(),
+            replace: "b".into(),
+        }],
+    );
+    preset.plan_options.collision = CollisionStrategy::Suffix;
+    let options = ApplyOptions {
+        safe_mode: true,
+        ..ApplyOptions::default()
+    };
+
+    let simulation = simulate(&snapshot, &preset, &options).unwrap().unwrap();
+    let entry = simulation
+        .plan
+        .entries
+        .iter()
+        .find(|e| e.source == dir.path().join("a.txt"))
+        .unwrap();
+    let conflict = entry.conflict.as_ref().unwrap();
+    assert_eq!(conflict.resolution, Resolution::Suffixed);
+    assert_eq!(conflict.requested_target, dir.path().join("b.txt"));
+    assert_eq!(entry.target.parent(), Some(dir.path()));
+    assert!(simulation.report.committed);
+    assert_eq!(simulation.report.renamed, 2);
+    assert!(simulation
+        .report
+        .outcomes
+        .iter()
+        .all(|o| o.target.starts_with(dir.path()) && o.status == OutcomeStatus::Renamed));
+
+    // Nothing real moved.
+    let mut names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a.txt", "ab.txt", "b.txt", "bb.txt"]);
+}
+
+#[test]
+fn a_rename_that_would_fail_shows_the_rollback() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["a.txt", "b.txt"] {
+        fs::write(dir.path().join(name), name).unwrap();
+    }
+    // A file where the rule wants the folder.
+    fs::write(dir.path().join("txt"), "").unwrap();
+    let snapshot = Snapshot::scan(
+        dir.path(),
+        ScanOptions {
+            patterns: vec!["*.txt".into()],
+            ..ScanOptions::default()
+        },
+    )
+    .unwrap();
+    let preset = Preset::new(
+        "By type",
+        vec![Rule::Organize {
+            folder: "{ext}".into(),
+            root: None,
+        }],
+    );
+
+    let simulation = simulate(&snapshot, &preset, &ApplyOptions::default())
+        .unwrap()
+        .unwrap();
+    assert!(simulation
+        .plan
+        .entries
+        .iter()
+        .all(|e| e.status == EntryStatus::Ready));
+    let report = &simulation.report;
+    assert!(!report.committed);
+    assert!(report.failed >= 1);
+    assert_eq!(report.rollback_failed, 0);
+    assert!(report
+        .outcomes
+        .iter()
+        .all(|o| o.source.parent() == Some(dir.path())));
+    assert!(dir.path().join("a.txt").exists());
+    assert!(dir.path().join("txt").is_file());
+
+    let unchanged = Preset::new("Nothing", Vec::new());
+    assert!(simulate(&snapshot, &unchanged, &ApplyOptions::default())
+        .unwrap()
+        .is_none());
+}
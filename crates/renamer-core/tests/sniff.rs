@@ -0,0 +1,53 @@
+use std::fs;
+
+use renamer_core::{preview, ExtensionFix, Rule};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+const JPEG: &[u8] = b"\xff\xd8\xff\xe0\0\x10JFIF\0";
+
+#[test]
+fn fixes_extensions_that_do_not_match_the_contents() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: [(&str, &[u8]); 6] = [
+        ("holiday.jpg", PNG),
+        ("SCAN.JPG", PNG),
+        ("download", JPEG),
+        ("portrait.jpeg", JPEG),
+        ("report.docx", b"PK\x03\x04\x14\0\0\0\0\0"),
+        ("notes.md", b"# just text"),
+    ];
+    let paths: Vec<_> = files
+        .iter()
+        .map(|(name, contents)| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        })
+        .collect();
+
+    let rows = preview(&paths, &[Rule::FixExtension]).unwrap();
+    let names: Vec<_> = rows.iter().map(|r| r.new_name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "holiday.png",
+            "SCAN.PNG",
+            "download.jpg",
+            "portrait.jpeg",
+            "report.docx",
+            "notes.md"
+        ]
+    );
+    assert_eq!(
+        rows[0].extension_fix,
+        Some(ExtensionFix {
+            from: "jpg".into(),
+            to: "png".into(),
+            mime: "image/png".into(),
+        })
+    );
+    assert_eq!(rows[2].extension_fix.as_ref().unwrap().from, "");
+    assert!(rows[3..]
+        .iter()
+        .all(|r| r.extension_fix.is_none() && !r.changed));
+}
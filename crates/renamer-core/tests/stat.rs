@@ -0,0 +1,33 @@
+use std::fs;
+
+use renamer_core::stat_files;
+
+#[test]
+fn stats_come_back_in_order_with_errors_for_missing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    fs::write(&file, "hello").unwrap();
+    let mut permissions = fs::metadata(&file).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&file, permissions).unwrap();
+    let missing = dir.path().join("missing.txt");
+
+    let stats = stat_files(&[file.clone(), dir.path().to_path_buf(), missing.clone()]);
+    assert_eq!(stats[0].path, file);
+    assert_eq!(stats[0].size, 5);
+    assert!(stats[0].readonly);
+    assert!(stats[0].modified.is_some());
+    assert!(stats[0].error.is_none());
+    assert!(stats[1].is_dir);
+    assert_eq!(stats[1].size, 0);
+    assert_eq!(stats[2].path, missing);
+    assert!(stats[2].error.is_some());
+    assert!(stats[2].modified.is_none());
+
+    #[cfg(unix)]
+    {
+        assert_eq!(stats[0].mode.unwrap() & 0o222, 0);
+        assert_eq!(stats[0].owner, stats[1].owner);
+        assert!(stats[0].uid.is_some());
+    }
+}
@@ -0,0 +1,70 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+
+use renamer_core::{
+    apply, plan, preview, ApplyOptions, EntryStatus, HistoryStore, PlanOptions, RenameOp,
+    SymlinkPolicy,
+};
+
+fn options(symlinks: SymlinkPolicy) -> PlanOptions {
+    PlanOptions {
+        symlinks,
+        ..PlanOptions::default()
+    }
+}
+
+#[test]
+fn links_can_be_renamed_followed_or_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let real = dir.path().join("real.txt");
+    let link = dir.path().join("link.txt");
+    fs::write(&real, "r").unwrap();
+    symlink("real.txt", &link).unwrap();
+    let ops = vec![RenameOp::new(&link, dir.path().join("renamed.txt"))];
+
+    let rows = preview(&[link.clone(), real.clone()], &[]).unwrap();
+    assert!(rows[0].is_symlink);
+    assert_eq!(rows[0].link_target, Some(PathBuf::from("real.txt")));
+    assert!(!rows[1].is_symlink);
+
+    let skipped = plan(ops.clone(), &options(SymlinkPolicy::Skip));
+    assert_eq!(skipped.entries[0].status, EntryStatus::Symlink);
+
+    let followed = plan(ops.clone(), &options(SymlinkPolicy::Target));
+    assert_eq!(followed.entries[0].source, real);
+    assert_eq!(followed.entries[0].link.as_ref(), Some(&link));
+
+    let renamed_link = plan(ops, &PlanOptions::default());
+    assert_eq!(renamed_link.entries[0].source, link);
+    assert!(renamed_link.entries[0].link.is_none());
+}
+
+#[test]
+fn following_a_link_repoints_it_and_undo_restores_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let real = dir.path().join("real.txt");
+    let link = dir.path().join("link.txt");
+    let renamed = dir.path().join("renamed.txt");
+    fs::write(&real, "r").unwrap();
+    symlink("real.txt", &link).unwrap();
+
+    let batch = plan(
+        vec![RenameOp::new(&link, &renamed)],
+        &options(SymlinkPolicy::Target),
+    );
+    let report = apply(&batch, &ApplyOptions::default(), &dir.path().join("j")).unwrap();
+
+    assert!(report.committed);
+    assert!(!real.exists());
+    assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("renamed.txt"));
+    assert_eq!(fs::read_to_string(&link).unwrap(), "r");
+
+    let mut store = HistoryStore::open(dir.path().join("history.json")).unwrap();
+    store.record(&report).unwrap();
+    store.undo_last().unwrap().unwrap();
+    assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("real.txt"));
+    assert_eq!(fs::read_to_string(&real).unwrap(), "r");
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+use renamer_core::{assign_groups, batch_contexts, FileContext, MetaValue, Metadata, Template};
+
+fn ctx(path: &Path) -> FileContext<'_> {
+    FileContext {
+        path,
+        index: 4,
+        folder_index: 0,
+        ext_index: 0,
+        size: Some(1536),
+        modified: Some(Local.with_ymd_and_hms(2024, 1, 31, 9, 5, 0).unwrap()),
+        embedded: None,
+        hashes: Vec::new(),
+        content_type: None,
+        groups: Default::default(),
+        burst: None,
+        ocr: None,
+    }
+}
+
+#[test]
+fn renders_basic_tokens() {
+    let path = Path::new("/photos/Iceland/IMG_1234.JPG");
+    let template =
+        Template::parse("{parent}_{date:yyyy-MM-dd}_{counter}_{name}.{ext} ({size:human})")
+            .unwrap();
+
+    assert!(template.needs_metadata());
+    assert_eq!(
+        template.render("IMG_1234.JPG", &ctx(path)),
+        "Iceland_2024-01-31_5_IMG_1234.JPG (1.5 KB)"
+    );
+}
+
+#[test]
+fn date_patterns_and_escaping() {
+    let path = Path::new("/a/b.txt");
+    let template = Template::parse("{{{date:yyyyMMdd HHmm}}} 100%-{size}").unwrap();
+    assert_eq!(
+        template.render("b.txt", &ctx(path)),
+        "{20240131 0905} 100%-1536"
+    );
+}
+
+#[test]
+fn missing_metadata_renders_empty() {
+    let path = Path::new("/a/b.txt");
+    let template = Template::parse("{name}{date}{size}").unwrap();
+    assert_eq!(template.render("b.txt", &FileContext::new(path, 0)), "b");
+}
+
+#[test]
+fn rejects_malformed_templates() {
+    for bad in ["{name", "name}", "{nope}", "{name:x}"] {
+        assert!(Template::parse(bad).is_err(), "{bad} should fail");
+    }
+}
+
+#[test]
+fn counter_options_and_folder_reset() {
+    let paths: Vec<PathBuf> = ["/a/x.jpg", "/a/y.jpg", "/b/z.jpg", "/a/w.png"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    let contexts = batch_contexts(&paths);
+    let render = |template: &str| -> Vec<String> {
+        let template = Template::parse(template).unwrap();
+        contexts
+            .iter()
+            .map(|ctx| template.render("f.jpg", ctx))
+            .collect()
+    };
+
+    assert_eq!(
+        render("IMG_{counter:4}"),
+        ["IMG_0001", "IMG_0002", "IMG_0003", "IMG_0004"]
+    );
+    assert_eq!(render("{counter:reset=folder}"), ["1", "2", "1", "3"]);
+    assert_eq!(
+        render("{counter:reset=ext,pad=2}"),
+        ["01", "02", "03", "01"]
+    );
+    assert_eq!(render("{counter:start=10,step=-5}"), ["10", "5", "0", "-5"]);
+    assert!(Template::parse("{counter:reset=year}").is_err());
+}
+
+#[test]
+fn counters_in_roman_numerals_letters_and_words() {
+    let paths: Vec<PathBuf> = (0..4)
+        .map(|n| PathBuf::from(format!("/a/{}.mp3", n)))
+        .collect();
+    let contexts = batch_contexts(&paths);
+    let render = |template: &str| -> Vec<String> {
+        let template = Template::parse(template).unwrap();
+        contexts
+            .iter()
+            .map(|ctx| template.render("f.mp3", ctx))
+            .collect()
+    };
+
+    assert_eq!(
+        render("Part {counter:style=ROMAN}"),
+        ["Part I", "Part II", "Part III", "Part IV"]
+    );
+    assert_eq!(
+        render("{counter:style=roman,start=1998,step=-1}"),
+        ["mcmxcviii", "mcmxcvii", "mcmxcvi", "mcmxcv"]
+    );
+    assert_eq!(
+        render("{counter:style=alpha,start=25}"),
+        ["y", "z", "aa", "ab"]
+    );
+    assert_eq!(
+        render("{counter:style=ALPHA,start=0,pad=2}"),
+        ["00", "A", "B", "C"]
+    );
+    assert_eq!(
+        render("{counter:words=Intro; Verse;Chorus}"),
+        ["Intro", "Verse", "Chorus", "4"]
+    );
+    for bad in ["{counter:style=greek}", "{counter:words=;}"] {
+        assert!(Template::parse(bad).is_err(), "{bad} should fail");
+    }
+}
+
+#[test]
+fn group_counters_restart_for_each_value_of_their_key() {
+    let taken = [
+        "2024-05-01 09:00",
+        "2024-05-01 18:30",
+        "2024-05-02 07:15",
+        "",
+        "2024-05-01 23:59",
+    ];
+    let paths: Vec<PathBuf> = (1..=5)
+        .map(|n| PathBuf::from(format!("/p/{}.jpg", n)))
+        .collect();
+    let mut contexts = batch_contexts(&paths);
+    for (ctx, taken) in contexts.iter_mut().zip(taken) {
+        let mut values = std::collections::BTreeMap::new();
+        if let Ok(date) = chrono::NaiveDateTime::parse_from_str(taken, "%Y-%m-%d %H:%M") {
+            values.insert("exif.date_taken".to_string(), MetaValue::Date(date));
+        }
+        ctx.embedded = Some(Metadata {
+            path: ctx.path.into(),
+            values,
+        });
+    }
+    let template =
+        Template::parse("{exif.date:yyyyMMdd|undated}_{counter:3@group(exif.date:yyyy-MM-dd)}")
+            .unwrap();
+    assert!(template.needs_embedded());
+    assign_groups(&mut contexts, &[&template]);
+    let names: Vec<String> = contexts
+        .iter()
+        .map(|ctx| template.render("f.jpg", ctx))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "20240501_001",
+            "20240501_002",
+            "20240502_001",
+            "undated_001",
+            "20240501_003"
+        ]
+    );
+
+    let by_folder = Template::parse("{counter@group(parent)}").unwrap();
+    assert!(!by_folder.needs_embedded());
+    for bad in [
+        "{counter@group()}",
+        "{counter@group(parent}",
+        "{counter@group(nope)}",
+        "{counter:reset=ext@group(parent)}",
+    ] {
+        assert!(Template::parse(bad).is_err(), "{bad} should fail");
+    }
+}
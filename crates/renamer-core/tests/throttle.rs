@@ -0,0 +1,43 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use renamer_core::throttle::{self, IoThrottle};
+use renamer_core::{hash_file, HashAlgorithm};
+
+#[test]
+fn background_work_keeps_to_the_rate_and_the_rest_does_not() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<_> = (0..4)
+        .map(|i| {
+            let path = dir.path().join(format!("{}.bin", i));
+            fs::write(&path, vec![i as u8; 1_000_000]).unwrap();
+            path
+        })
+        .collect();
+    let hash_all = || {
+        for path in &paths {
+            hash_file(path, HashAlgorithm::Xxh3).unwrap();
+        }
+    };
+
+    throttle::set(IoThrottle {
+        max_mb_per_sec: Some(10),
+        low_priority: true,
+    });
+    assert_eq!(throttle::get().max_mb_per_sec, Some(10));
+    let started = Instant::now();
+    hash_all();
+    let unthrottled = started.elapsed();
+
+    // 4 MB at 10 MB/s.
+    let started = Instant::now();
+    throttle::background(hash_all);
+    let throttled = started.elapsed();
+    assert!(throttled >= Duration::from_millis(300), "{:?}", throttled);
+    assert!(unthrottled < throttled);
+
+    throttle::set(IoThrottle::default());
+    let started = Instant::now();
+    throttle::background(hash_all);
+    assert!(started.elapsed() < throttled);
+}
@@ -0,0 +1,192 @@
+use std::fs;
+
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use renamer_core::{thumbnail, Error};
+
+#[test]
+fn images_are_scaled_down_and_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let photo = dir.path().join("photo.png");
+    RgbImage::from_pixel(40, 20, Rgb([200, 30, 30]))
+        .save(&photo)
+        .unwrap();
+
+    let thumb = thumbnail(&photo, 10, &cache).unwrap();
+    assert_eq!((thumb.width, thumb.height), (10, 5));
+    assert_eq!(thumb.media_type, "image/jpeg");
+    assert!(thumb.path.starts_with(&cache));
+    assert_eq!(image::image_dimensions(&thumb.path).unwrap(), (10, 5));
+    assert_eq!(thumbnail(&photo, 10, &cache).unwrap(), thumb);
+    assert_ne!(thumbnail(&photo, 8, &cache).unwrap().path, thumb.path);
+
+    // Transparency survives.
+    let logo = dir.path().join("logo.png");
+    RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 0]))
+        .save(&logo)
+        .unwrap();
+    assert_eq!(
+        thumbnail(&logo, 64, &cache).unwrap().media_type,
+        "image/png"
+    );
+
+    let notes = dir.path().join("notes.txt");
+    fs::write(&notes, "not a picture").unwrap();
+    assert!(matches!(
+        thumbnail(&notes, 64, &cache),
+        Err(Error::Thumbnail { .. })
+    ));
+    let raw = dir.path().join("IMG_1.CR2");
+    fs::write(&raw, "not a raw file").unwrap();
+    assert!(matches!(
+        thumbnail(&raw, 64, &cache),
+        Err(Error::Thumbnail { .. })
+    ));
+}
+
+fn jpeg(width: u32, height: u32, color: Rgb<u8>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    RgbImage::from_pixel(width, height, color)
+        .write_to(
+            &mut std::io::Cursor::new(&mut bytes),
+            image::ImageFormat::Jpeg,
+        )
+        .unwrap();
+    bytes
+}
+
+/// A little-endian IFD of LONG (or, for Orientation, SHORT) entries with
+/// no next one.
+fn ifd(entries: &[(u16, u32)]) -> Vec<u8> {
+    let mut out = (entries.len() as u16).to_le_bytes().to_vec();
+    for &(tag, value) in entries {
+        let kind: u16 = if tag == 0x112 { 3 } else { 4 };
+        out.extend(tag.to_le_bytes());
+        out.extend(kind.to_le_bytes());
+        out.extend(1u32.to_le_bytes());
+        out.extend(value.to_le_bytes());
+    }
+    out.extend([0; 4]);
+    out
+}
+
+fn dominant(path: &std::path::Path) -> Rgb<u8> {
+    *image::open(path).unwrap().to_rgb8().get_pixel(0, 0)
+}
+
+#[test]
+fn raws_use_the_embedded_jpeg_closest_to_the_size_asked_for() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    // IFD0 with a small preview, turned a quarter, and a sub-IFD with a
+    // larger one, as NEF and ARW store them.
+    let small = jpeg(16, 8, Rgb([250, 0, 0]));
+    let large = jpeg(64, 32, Rgb([0, 0, 250]));
+    let sub_at = 8 + 2 + 4 * 12 + 4;
+    let data_at = sub_at + 2 + 2 * 12 + 4;
+    let large_at = data_at + small.len() as u32;
+    let file = [
+        b"II*\0".to_vec(),
+        8u32.to_le_bytes().to_vec(),
+        ifd(&[
+            (0x112, 6),
+            (0x14a, sub_at),
+            (0x201, data_at),
+            (0x202, small.len() as u32),
+        ]),
+        ifd(&[(0x201, large_at), (0x202, large.len() as u32)]),
+        small,
+        large,
+    ]
+    .concat();
+    let nef = dir.path().join("DSC_0001.NEF");
+    fs::write(&nef, file).unwrap();
+
+    let thumb = thumbnail(&nef, 32, &cache).unwrap();
+    assert_eq!((thumb.width, thumb.height), (16, 32));
+    assert!(dominant(&thumb.path).0[2] > 200);
+    let thumb = thumbnail(&nef, 8, &cache).unwrap();
+    assert_eq!((thumb.width, thumb.height), (4, 8));
+    assert!(dominant(&thumb.path).0[0] > 200);
+
+    // CR3 keeps its preview in a PRVW box after a header of its own.
+    let atom = |kind: &[u8], body: &[u8]| {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(body);
+        out
+    };
+    let preview_uuid: &[u8] = &[
+        0xea, 0xf4, 0x2b, 0x5e, 0x1c, 0x98, 0x4b, 0x88, 0xb9, 0xfb, 0xb7, 0xdc, 0x40, 0x6e, 0x4d,
+        0x16,
+    ];
+    let prvw = atom(
+        b"PRVW",
+        &[&[0; 16], jpeg(48, 32, Rgb([0, 250, 0])).as_slice()].concat(),
+    );
+    let file = [
+        atom(b"ftyp", b"crx \0\0\0\x01crx isom"),
+        atom(b"uuid", &[preview_uuid, &[0; 8], &prvw].concat()),
+        atom(b"mdat", &[0; 16]),
+    ]
+    .concat();
+    let cr3 = dir.path().join("IMG_0001.CR3");
+    fs::write(&cr3, file).unwrap();
+    let thumb = thumbnail(&cr3, 24, &cache).unwrap();
+    assert_eq!((thumb.width, thumb.height), (24, 16));
+    assert!(dominant(&thumb.path).0[1] > 200);
+}
+
+#[test]
+fn heic_photos_use_a_jpeg_thumbnail_item_turned_by_its_rotation() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let atom = |kind: &[u8], parts: &[&[u8]]| {
+        let body = parts.concat();
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(body);
+        out
+    };
+    let infe = |id: u8, kind: &[u8]| atom(b"infe", &[&[2, 0, 0, 0, 0, id, 0, 0], kind]);
+    let thumb = jpeg(32, 16, Rgb([250, 0, 0]));
+    let ftyp = atom(b"ftyp", &[b"heic", &[0; 4], b"mif1heic"]);
+    // Item 1 is the HEVC image, item 2 its JPEG thumbnail, turned a quarter
+    // anticlockwise by an `irot` property.
+    let meta = |offset: u32| {
+        let location = [
+            [0, 2, 0, 0, 0, 1].as_slice(),
+            &offset.to_be_bytes(),
+            &(thumb.len() as u32).to_be_bytes(),
+        ]
+        .concat();
+        atom(
+            b"meta",
+            &[
+                &[0; 4],
+                &atom(b"pitm", &[&[0; 4], &[0, 1]]),
+                &atom(
+                    b"iinf",
+                    &[&[0; 4], &[0, 2], &infe(1, b"hvc1"), &infe(2, b"jpeg")],
+                ),
+                &atom(b"iref", &[&[0; 4], &atom(b"thmb", &[&[0, 2, 0, 1, 0, 1]])]),
+                &atom(
+                    b"iprp",
+                    &[
+                        &atom(b"ipco", &[&atom(b"irot", &[&[1]])]),
+                        &atom(b"ipma", &[&[0; 4], &[0, 0, 0, 1], &[0, 2, 1, 0x81]]),
+                    ],
+                ),
+                &atom(b"iloc", &[&[0; 4], &[0x44, 0, 0, 1], &location]),
+            ],
+        )
+    };
+    let offset = (ftyp.len() + meta(0).len() + 8) as u32;
+    let file = [ftyp, meta(offset), atom(b"mdat", &[&thumb])].concat();
+    let heic = dir.path().join("IMG_4021.HEIC");
+    fs::write(&heic, file).unwrap();
+
+    let made = thumbnail(&heic, 16, &cache).unwrap();
+    assert_eq!((made.width, made.height), (8, 16));
+    assert!(dominant(&made.path).0[0] > 200);
+}
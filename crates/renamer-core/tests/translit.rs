@@ -0,0 +1,44 @@
+use renamer_core::translit::{transliterate, TranslitLocale};
+use renamer_core::{preview, Rule};
+
+fn default(s: &str) -> String {
+    transliterate(s, TranslitLocale::default(), None)
+}
+
+#[test]
+fn marks_are_dropped_and_other_alphabets_spelled_out() {
+    assert_eq!(default("Łódź_фото.jpg"), "Lodz_foto.jpg");
+    assert_eq!(default("Straße – Ærø"), "Strasse - Aero");
+    assert_eq!(default("Щука ЁЖ"), "Shchuka YoZh");
+    assert_eq!(default("Ακρόπολη"), "Akropoli");
+    // Names stored decomposed, as macOS does.
+    assert_eq!(default("Cafe\u{301} Noe\u{308}l"), "Cafe Noel");
+    assert_eq!(default("東京 ✓.png"), "東京 ✓.png");
+    assert_eq!(
+        transliterate("東京 ✓.png", TranslitLocale::default(), Some("_")),
+        "__ _.png"
+    );
+}
+
+#[test]
+fn languages_spell_some_letters_their_own_way() {
+    let de = TranslitLocale::from_tag(Some("de-AT"));
+    assert_eq!(transliterate("Müller Öl", de, None), "Mueller Oel");
+    assert_eq!(default("Müller"), "Muller");
+    let da = TranslitLocale::from_tag(Some("da"));
+    assert_eq!(transliterate("Århus Ø", da, None), "Aarhus Oe");
+    let uk = TranslitLocale::from_tag(Some("uk"));
+    assert_eq!(transliterate("Київ Гора", uk, None), "Kyyiv Hora");
+    let bg = TranslitLocale::from_tag(Some("bg"));
+    assert_eq!(transliterate("България", bg, None), "Balgariya");
+
+    let rows = preview(
+        &["/photos/Zürich_фото.JPG".into()],
+        &[Rule::Transliterate {
+            locale: Some("de".into()),
+            unknown: None,
+        }],
+    )
+    .unwrap();
+    assert_eq!(rows[0].new_name, "Zuerich_foto.JPG");
+}
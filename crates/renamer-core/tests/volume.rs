@@ -0,0 +1,46 @@
+use std::ffi::OsStr;
+use std::fs;
+
+use renamer_core::volume::{self, Volume};
+use renamer_core::TargetFs;
+
+#[test]
+fn filesystems_set_how_names_are_counted_and_compared() {
+    let ext4 = Volume::of_filesystem("ext4");
+    assert_eq!(ext4.rules, TargetFs::Ext4);
+    assert!(ext4.case_sensitive && ext4.journaled && !ext4.utf16_names);
+    let exfat = Volume::of_filesystem("exFAT");
+    assert_eq!(exfat.filesystem, "exfat");
+    assert_eq!(exfat.rules, TargetFs::Exfat);
+    assert!(!exfat.case_sensitive && !exfat.journaled);
+    assert_eq!(Volume::of_filesystem("cifs").rules, TargetFs::Smb);
+    assert_eq!(Volume::of_filesystem("fuseblk").rules, TargetFs::Ntfs);
+
+    // 200 accented letters are 400 bytes but 200 UTF-16 units.
+    let accented = "é".repeat(200);
+    assert!(exfat.name_fits(OsStr::new(&accented)));
+    assert!(!ext4.name_fits(OsStr::new(&accented)));
+    let encrypted = Volume::of_filesystem("ecryptfs");
+    assert!(!encrypted.name_fits(OsStr::new(&"x".repeat(150))));
+
+    assert_eq!(exfat.refused_char("a?b"), Some('?'));
+    assert_eq!(exfat.refused_char("trailing "), Some(' '));
+    assert_eq!(ext4.refused_char("a?b "), None);
+    assert_eq!(ext4.refused_char("a/b"), Some('/'));
+}
+
+#[test]
+fn probing_finds_whether_the_volume_folds_case() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("probe.txt");
+    fs::write(&file, "x").unwrap();
+    let folds = dir.path().join("PROBE.TXT").exists();
+
+    let volume = volume::probe(&dir.path().join("not yet").join("deeper"));
+    assert_eq!(volume.case_sensitive, !folds);
+    assert!(volume.max_name_len > 0);
+    if cfg!(target_os = "linux") {
+        assert!(dir.path().starts_with(&volume.mount_point));
+        assert!(!volume.filesystem.is_empty());
+    }
+}
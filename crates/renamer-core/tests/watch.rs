@@ -0,0 +1,110 @@
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use renamer_core::{FolderWatcher, Preset, Rule, WatchEvent, WatchOptions};
+
+#[test]
+fn files_dropped_into_a_watched_folder_are_renamed_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let inbox = dir.path().join("inbox");
+    fs::create_dir(&inbox).unwrap();
+    let preset = Preset::new(
+        "Invoice scans",
+        vec![Rule::Prefix {
+            text: "invoice-".into(),
+        }],
+    );
+    let options = WatchOptions {
+        settle: Duration::from_millis(100),
+        ..WatchOptions::new(&inbox, preset, dir.path().join("journal"))
+    };
+    let (tx, rx) = mpsc::channel();
+    let watcher = FolderWatcher::start(options, move |event| {
+        let _ = tx.send(event);
+    })
+    .unwrap();
+
+    fs::write(inbox.join("scan.pdf"), "pdf").unwrap();
+    fs::write(inbox.join("scan.pdf.part"), "pd").unwrap();
+    fs::write(inbox.join(".scan.pdf.tmp"), "pd").unwrap();
+    fs::create_dir(inbox.join("folder")).unwrap();
+
+    match rx.recv_timeout(Duration::from_secs(10)).unwrap() {
+        WatchEvent::Renamed(report) => {
+            assert!(report.committed);
+            assert_eq!(report.renamed, 1);
+            assert_eq!(report.outcomes[0].target, inbox.join("invoice-scan.pdf"));
+        }
+        WatchEvent::Failed(message) => panic!("{}", message),
+    }
+
+    // The new name is not picked up as another arrival.
+    assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+    watcher.stop();
+
+    let mut names: Vec<_> = fs::read_dir(&inbox)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            ".scan.pdf.tmp",
+            "folder",
+            "invoice-scan.pdf",
+            "scan.pdf.part"
+        ]
+    );
+}
+
+#[test]
+fn missing_folders_cannot_be_watched() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = WatchOptions::new(
+        dir.path().join("missing"),
+        Preset::new("Photo import", Vec::new()),
+        dir.path().join("journal"),
+    );
+    assert!(FolderWatcher::start(options, |_| {}).is_err());
+}
+
+#[test]
+fn files_arriving_while_paused_are_left_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let preset = Preset::new(
+        "Invoice scans",
+        vec![Rule::Prefix {
+            text: "invoice-".into(),
+        }],
+    );
+    let options = WatchOptions {
+        settle: Duration::from_millis(100),
+        ..WatchOptions::new(dir.path(), preset, dir.path().join(".journal"))
+    };
+    let (tx, rx) = mpsc::channel();
+    let watcher = FolderWatcher::start(options, move |event| {
+        let _ = tx.send(event);
+    })
+    .unwrap();
+
+    watcher.pause();
+    assert!(watcher.is_paused());
+    fs::write(dir.path().join("early.pdf"), "pdf").unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+    watcher.resume();
+    fs::write(dir.path().join("late.pdf"), "pdf").unwrap();
+    match rx.recv_timeout(Duration::from_secs(10)).unwrap() {
+        WatchEvent::Renamed(report) => {
+            assert_eq!(report.renamed, 1);
+            assert_eq!(
+                report.outcomes[0].target,
+                dir.path().join("invoice-late.pdf")
+            );
+        }
+        WatchEvent::Failed(message) => panic!("{}", message),
+    }
+    assert!(dir.path().join("early.pdf").exists());
+}
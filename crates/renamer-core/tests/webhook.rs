@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+
+use renamer_core::{apply, plan, webhook, ApplyOptions, JobSummary, PlanOptions, RenameOp};
+use renamer_core::{Webhook, WebhookEvent};
+use serde_json::Value;
+
+/// A server answering one request with `status`, handing over its head and
+/// body.
+fn hook(status: &'static str) -> (String, mpsc::Receiver<(String, Value)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/sortify", listener.local_addr().unwrap());
+    let (sent, received) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap().to_lowercase();
+        let length = head
+            .lines()
+            .find_map(|l| l.strip_prefix("content-length: "))
+            .map_or(0, |v| v.parse().unwrap());
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            status
+        )
+        .unwrap();
+        sent.send((head, serde_json::from_slice(&body).unwrap()))
+            .unwrap();
+    });
+    (url, received)
+}
+
+#[test]
+fn finished_batches_are_posted_to_the_hooks_that_want_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, "a").unwrap();
+    let report = apply(
+        &plan(
+            vec![RenameOp::new(&a, dir.path().join("b.txt"))],
+            &PlanOptions::default(),
+        ),
+        &ApplyOptions::default(),
+        &dir.path().join("journal"),
+    )
+    .unwrap();
+
+    let (ok_url, ok) = hook("204 No Content");
+    let (broken_url, broken) = hook("500 Internal Server Error");
+    let hooks = vec![
+        Webhook {
+            url: ok_url,
+            events: vec![WebhookEvent::Batch],
+            headers: BTreeMap::from([("Authorization".into(), "Bearer pipeline".into())]),
+        },
+        Webhook {
+            // Nothing listens here, and it only wants watched folders.
+            url: "http://127.0.0.1:9/never".into(),
+            events: vec![WebhookEvent::Watch],
+            headers: BTreeMap::new(),
+        },
+        Webhook {
+            url: broken_url.clone(),
+            events: Vec::new(),
+            headers: BTreeMap::new(),
+        },
+    ];
+    let summary = JobSummary::new(WebhookEvent::Batch, &report, Some("Photos"));
+    let failures = webhook::send(&hooks, &summary);
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].url, broken_url);
+    assert!(failures[0].message.contains("500"));
+
+    let (head, body) = ok.recv().unwrap();
+    assert!(head.starts_with("post /sortify "));
+    assert!(head.contains("authorization: bearer pipeline"));
+    assert!(head.contains("content-type: application/json"));
+    assert_eq!(body["event"], "batch");
+    assert_eq!(body["batch_id"], report.batch_id.to_string());
+    assert_eq!(body["preset"], "Photos");
+    assert_eq!(body["committed"], true);
+    assert_eq!(body["renamed"], 1);
+    assert_eq!(body["errors"], Value::Array(Vec::new()));
+    assert!(broken.recv().is_ok());
+}
@@ -0,0 +1,241 @@
+package com.renamer.app
+
+import android.app.Activity
+import android.content.Intent
+import android.database.Cursor
+import android.net.Uri
+import android.provider.DocumentsContract
+import android.provider.DocumentsContract.Document
+import androidx.activity.result.ActivityResult
+import app.tauri.annotation.ActivityCallback
+import app.tauri.annotation.Command
+import app.tauri.annotation.InvokeArg
+import app.tauri.annotation.TauriPlugin
+import app.tauri.plugin.Invoke
+import app.tauri.plugin.JSObject
+import app.tauri.plugin.Plugin
+import org.json.JSONArray
+import org.json.JSONObject
+
+// The native side of `storage.rs`: document trees of the Storage Access
+// Framework, picked with the system's folder picker and kept across runs as
+// persisted URI permissions. Paths are `/`-separated names from the top of a
+// tree. Every command resolves with `{ value }`; failures are rejected with
+// the codes `notFound`, `exists` and `denied` where those apply.
+
+@InvokeArg
+class FolderArgs {
+    lateinit var uri: String
+}
+
+@InvokeArg
+class PathArgs {
+    lateinit var uri: String
+    lateinit var path: String
+}
+
+@InvokeArg
+class RenameArgs {
+    lateinit var uri: String
+    lateinit var from: String
+    lateinit var to: String
+}
+
+private class Rejected(message: String, val code: String) : Exception(message)
+
+// A document found in a tree, with the folder it is in.
+private class Found(val id: String, val parentId: String?, val entry: JSObject)
+
+private val COLUMNS = arrayOf(
+    Document.COLUMN_DOCUMENT_ID,
+    Document.COLUMN_DISPLAY_NAME,
+    Document.COLUMN_MIME_TYPE,
+    Document.COLUMN_SIZE,
+    Document.COLUMN_LAST_MODIFIED,
+)
+
+private const val GRANT =
+    Intent.FLAG_GRANT_READ_URI_PERMISSION or Intent.FLAG_GRANT_WRITE_URI_PERMISSION
+
+@TauriPlugin
+class StoragePlugin(private val activity: Activity) : Plugin(activity) {
+    private val resolver get() = activity.contentResolver
+
+    private fun reply(invoke: Invoke, value: Any?) {
+        val result = JSObject()
+        result.put("value", value ?: JSONObject.NULL)
+        invoke.resolve(result)
+    }
+
+    // Runs `body` on a background thread, since providers may be slow or
+    // remote, and answers `invoke` with what it returns.
+    private fun run(invoke: Invoke, body: () -> Any?) {
+        Thread {
+            try {
+                reply(invoke, body())
+            } catch (e: Rejected) {
+                invoke.reject(e.message, e.code)
+            } catch (e: SecurityException) {
+                invoke.reject(e.message, "denied")
+            } catch (e: Exception) {
+                invoke.reject(e.message)
+            }
+        }.start()
+    }
+
+    @Command
+    fun pickFolder(invoke: Invoke) {
+        val intent = Intent(Intent.ACTION_OPEN_DOCUMENT_TREE)
+        intent.addFlags(GRANT or Intent.FLAG_GRANT_PERSISTABLE_URI_PERMISSION)
+        startActivityForResult(invoke, intent, "folderPicked")
+    }
+
+    @ActivityCallback
+    fun folderPicked(invoke: Invoke, result: ActivityResult) {
+        val tree = result.data?.data
+        if (result.resultCode != Activity.RESULT_OK || tree == null) {
+            reply(invoke, null)
+            return
+        }
+        run(invoke) {
+            resolver.takePersistableUriPermission(tree, GRANT)
+            folder(tree)
+        }
+    }
+
+    @Command
+    fun listFolders(invoke: Invoke) {
+        run(invoke) {
+            val folders = JSONArray()
+            resolver.persistedUriPermissions
+                .filter { it.isWritePermission && DocumentsContract.isTreeUri(it.uri) }
+                .forEach { folders.put(folder(it.uri)) }
+            folders
+        }
+    }
+
+    @Command
+    fun releaseFolder(invoke: Invoke) {
+        val args = invoke.parseArgs(FolderArgs::class.java)
+        run(invoke) {
+            resolver.releasePersistableUriPermission(Uri.parse(args.uri), GRANT)
+            null
+        }
+    }
+
+    @Command
+    fun list(invoke: Invoke) {
+        val args = invoke.parseArgs(PathArgs::class.java)
+        run(invoke) {
+            val tree = Uri.parse(args.uri)
+            val dir = find(tree, names(args.path))
+                ?: throw Rejected("${args.path} does not exist", "notFound")
+            val entries = JSONArray()
+            children(tree, dir.id).forEach { entries.put(it.entry) }
+            entries
+        }
+    }
+
+    @Command
+    fun stat(invoke: Invoke) {
+        val args = invoke.parseArgs(PathArgs::class.java)
+        run(invoke) { find(Uri.parse(args.uri), names(args.path))?.entry }
+    }
+
+    // Renames in place where the folder stays the same, keeping the
+    // document; otherwise moves it first. Nothing already at `to` is
+    // replaced, and a provider that picks another name than the one asked
+    // for has the rename undone.
+    @Command
+    fun rename(invoke: Invoke) {
+        val args = invoke.parseArgs(RenameArgs::class.java)
+        run(invoke) {
+            val tree = Uri.parse(args.uri)
+            val from = names(args.from)
+            val to = names(args.to)
+            val source = find(tree, from)
+                ?: throw Rejected("${args.from} does not exist", "notFound")
+            val sourceDir = source.parentId
+                ?: throw Rejected("the top of a folder cannot be renamed", "denied")
+            val targetDir = find(tree, to.dropLast(1))
+                ?: throw Rejected("${args.to} is in a folder that does not exist", "notFound")
+            val name = to.last()
+            val taken = children(tree, targetDir.id)
+                .any { it.entry.getString("name") == name && it.id != source.id }
+            if (taken) {
+                throw Rejected("${args.to} already exists", "exists")
+            }
+            var uri = DocumentsContract.buildDocumentUriUsingTree(tree, source.id)
+            if (sourceDir != targetDir.id) {
+                uri = DocumentsContract.moveDocument(
+                    resolver,
+                    uri,
+                    DocumentsContract.buildDocumentUriUsingTree(tree, sourceDir),
+                    DocumentsContract.buildDocumentUriUsingTree(tree, targetDir.id),
+                ) ?: throw Exception("${args.from} could not be moved")
+            }
+            if (from.last() != name) {
+                val renamed = DocumentsContract.renameDocument(resolver, uri, name)
+                    ?: throw Exception("${args.from} could not be renamed")
+                val given = displayName(renamed)
+                if (given != name) {
+                    DocumentsContract.renameDocument(resolver, renamed, from.last())
+                    throw Rejected("${args.to} already exists", "exists")
+                }
+            }
+            null
+        }
+    }
+
+    private fun names(path: String): List<String> = path.split('/').filter { it.isNotEmpty() }
+
+    private fun folder(tree: Uri): JSObject {
+        val top = DocumentsContract.buildDocumentUriUsingTree(
+            tree,
+            DocumentsContract.getTreeDocumentId(tree),
+        )
+        val folder = JSObject()
+        folder.put("uri", tree.toString())
+        folder.put("name", displayName(top) ?: tree.lastPathSegment ?: "")
+        return folder
+    }
+
+    private fun displayName(document: Uri): String? =
+        resolver.query(document, arrayOf(Document.COLUMN_DISPLAY_NAME), null, null, null)?.use {
+            if (it.moveToFirst()) it.getString(0) else null
+        }
+
+    // Walks down from the top of `tree` by `names`; `null` if one is not
+    // there.
+    private fun find(tree: Uri, names: List<String>): Found? {
+        val topId = DocumentsContract.getTreeDocumentId(tree)
+        val top = JSObject()
+        top.put("name", "")
+        top.put("isDir", true)
+        top.put("size", 0)
+        var found = Found(topId, null, top)
+        for (name in names) {
+            found = children(tree, found.id).firstOrNull { it.entry.getString("name") == name }
+                ?: return null
+        }
+        return found
+    }
+
+    private fun children(tree: Uri, parentId: String): List<Found> {
+        val uri = DocumentsContract.buildChildDocumentsUriUsingTree(tree, parentId)
+        val cursor = resolver.query(uri, COLUMNS, null, null, null)
+            ?: throw Rejected("the folder cannot be read", "denied")
+        return cursor.use {
+            generateSequence { if (it.moveToNext()) entry(it, parentId) else null }.toList()
+        }
+    }
+
+    private fun entry(cursor: Cursor, parentId: String): Found {
+        val entry = JSObject()
+        entry.put("name", cursor.getString(1))
+        entry.put("isDir", cursor.getString(2) == Document.MIME_TYPE_DIR)
+        entry.put("size", if (cursor.isNull(3)) 0 else cursor.getLong(3))
+        entry.put("modified", if (cursor.isNull(4)) JSONObject.NULL else cursor.getLong(4))
+        return Found(cursor.getString(0), parentId, entry)
+    }
+}
@@ -0,0 +1,217 @@
+// The native side of `storage.rs`: folders picked in the Files app, kept
+// across runs as security-scoped bookmarks in the user defaults. Paths are
+// `/`-separated names from the top of a folder. Every command resolves with
+// `{ value }`; failures are rejected with the codes `notFound`, `exists` and
+// `denied` where those apply.
+
+import Foundation
+import SwiftRs
+import Tauri
+import UIKit
+import UniformTypeIdentifiers
+import WebKit
+
+class FolderArgs: Decodable {
+  let uri: String
+}
+
+class PathArgs: Decodable {
+  let uri: String
+  let path: String
+}
+
+class RenameArgs: Decodable {
+  let uri: String
+  let from: String
+  let to: String
+}
+
+struct Folder: Encodable {
+  let uri: String
+  let name: String
+}
+
+struct Entry: Encodable {
+  let name: String
+  let isDir: Bool
+  let size: UInt64
+  // Milliseconds since the epoch.
+  let modified: Int64?
+}
+
+// Encodes a missing value as `null` rather than leaving the key out.
+struct Reply<T: Encodable>: Encodable {
+  let value: T?
+
+  enum CodingKeys: String, CodingKey {
+    case value
+  }
+
+  func encode(to encoder: Encoder) throws {
+    var container = encoder.container(keyedBy: CodingKeys.self)
+    try container.encode(value, forKey: .value)
+  }
+}
+
+struct Rejected: Error {
+  let message: String
+  let code: String
+}
+
+private let foldersKey = "storageFolders"
+private let keys: [URLResourceKey] = [.isDirectoryKey, .fileSizeKey, .contentModificationDateKey]
+
+class StoragePlugin: Plugin, UIDocumentPickerDelegate {
+  private var picking: Invoke?
+
+  private var bookmarks: [String] {
+    get { UserDefaults.standard.stringArray(forKey: foldersKey) ?? [] }
+    set { UserDefaults.standard.set(newValue, forKey: foldersKey) }
+  }
+
+  // Runs `body` off the main thread, since a file provider may be slow or
+  // remote, and answers `invoke` with what it returns.
+  private func run<T: Encodable>(_ invoke: Invoke, _ body: @escaping () throws -> T?) {
+    DispatchQueue.global(qos: .userInitiated).async {
+      do {
+        invoke.resolve(Reply(value: try body()))
+      } catch let e as Rejected {
+        invoke.reject(e.message, code: e.code)
+      } catch let e as CocoaError where e.code == .fileWriteFileExists {
+        invoke.reject(e.localizedDescription, code: "exists")
+      } catch let e as CocoaError where e.code == .fileNoSuchFile || e.code == .fileReadNoSuchFile {
+        invoke.reject(e.localizedDescription, code: "notFound")
+      } catch {
+        invoke.reject(error.localizedDescription)
+      }
+    }
+  }
+
+  // Resolves the bookmark `uri` and runs `body` with access to the folder.
+  private func access<T>(_ uri: String, _ body: (URL) throws -> T) throws -> T {
+    guard let data = Data(base64Encoded: uri) else {
+      throw Rejected(message: "not a storage folder", code: "notFound")
+    }
+    var stale = false
+    let url = try URL(resolvingBookmarkData: data, bookmarkDataIsStale: &stale)
+    guard url.startAccessingSecurityScopedResource() else {
+      throw Rejected(message: "\(url.lastPathComponent) may no longer be used", code: "denied")
+    }
+    defer { url.stopAccessingSecurityScopedResource() }
+    return try body(url)
+  }
+
+  private func url(_ top: URL, _ path: String) -> URL {
+    path.split(separator: "/").reduce(top) { $0.appendingPathComponent(String($1)) }
+  }
+
+  private func entry(_ url: URL) throws -> Entry {
+    let values = try url.resourceValues(forKeys: Set(keys))
+    let isDir = values.isDirectory ?? false
+    return Entry(
+      name: url.lastPathComponent,
+      isDir: isDir,
+      size: isDir ? 0 : UInt64(values.fileSize ?? 0),
+      modified: values.contentModificationDate.map { Int64($0.timeIntervalSince1970 * 1000) }
+    )
+  }
+
+  @objc public func pickFolder(_ invoke: Invoke) {
+    DispatchQueue.main.async {
+      let picker = UIDocumentPickerViewController(forOpeningContentTypes: [.folder])
+      picker.delegate = self
+      self.picking = invoke
+      self.manager.viewController?.present(picker, animated: true)
+    }
+  }
+
+  func documentPicker(_ controller: UIDocumentPickerViewController, didPickDocumentsAt urls: [URL]) {
+    guard let invoke = picking, let url = urls.first else { return }
+    picking = nil
+    run(invoke) { () -> Folder? in
+      guard url.startAccessingSecurityScopedResource() else {
+        throw Rejected(message: "\(url.lastPathComponent) may not be used", code: "denied")
+      }
+      defer { url.stopAccessingSecurityScopedResource() }
+      let uri = try url.bookmarkData().base64EncodedString()
+      self.bookmarks.append(uri)
+      return Folder(uri: uri, name: url.lastPathComponent)
+    }
+  }
+
+  func documentPickerWasCancelled(_ controller: UIDocumentPickerViewController) {
+    picking?.resolve(Reply<Folder>(value: nil))
+    picking = nil
+  }
+
+  @objc public func listFolders(_ invoke: Invoke) {
+    run(invoke) { () -> [Folder]? in
+      self.bookmarks.compactMap { uri in
+        try? self.access(uri) { Folder(uri: uri, name: $0.lastPathComponent) }
+      }
+    }
+  }
+
+  @objc public func releaseFolder(_ invoke: Invoke) throws {
+    let args = try invoke.parseArgs(FolderArgs.self)
+    run(invoke) { () -> Bool? in
+      self.bookmarks.removeAll { $0 == args.uri }
+      return nil
+    }
+  }
+
+  @objc public func list(_ invoke: Invoke) throws {
+    let args = try invoke.parseArgs(PathArgs.self)
+    run(invoke) {
+      try self.access(args.uri) { top in
+        try FileManager.default
+          .contentsOfDirectory(at: self.url(top, args.path), includingPropertiesForKeys: keys)
+          .map(self.entry)
+      }
+    }
+  }
+
+  @objc public func stat(_ invoke: Invoke) throws {
+    let args = try invoke.parseArgs(PathArgs.self)
+    run(invoke) {
+      try self.access(args.uri) { top -> Entry? in
+        let url = self.url(top, args.path)
+        return (try? url.checkResourceIsReachable()) == true ? try self.entry(url) : nil
+      }
+    }
+  }
+
+  // Moves through a file coordinator, so the file provider sees the change;
+  // `FileManager` never replaces what is already at `to`.
+  @objc public func rename(_ invoke: Invoke) throws {
+    let args = try invoke.parseArgs(RenameArgs.self)
+    run(invoke) { () -> Bool? in
+      try self.access(args.uri) { top in
+        let from = self.url(top, args.from)
+        let to = self.url(top, args.to)
+        var coordination: NSError?
+        var moved: Error?
+        NSFileCoordinator().coordinate(
+          writingItemAt: from, options: .forMoving,
+          writingItemAt: to, options: .forReplacing,
+          error: &coordination
+        ) { from, to in
+          do {
+            try FileManager.default.moveItem(at: from, to: to)
+          } catch {
+            moved = error
+          }
+        }
+        if let error = coordination ?? moved {
+          throw error
+        }
+      }
+      return nil
+    }
+  }
+}
+
+@_cdecl("init_plugin_storage")
+func initPlugin() -> Plugin {
+  return StoragePlugin()
+}
@@ -0,0 +1,215 @@
+//! Status announcements for screen readers, so a long batch can be followed
+//! without seeing the progress bar. `job-progress` comes ten times a second,
+//! far too often to read out; `job-announcement` comes when a job starts,
+//! as it passes a quarter, half and three quarters, when it ends and when
+//! it fails, each with a sentence ready for a live region and the fields
+//! it was made from, for a webview that words it itself.
+//!
+//! With `verbose_announcements` in the settings, milestones come every
+//! tenth of the way and name the file being worked on.
+
+use std::path::{Path, PathBuf};
+
+use renamer_core::ApplyReport;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::error::CommandError;
+use crate::jobs::ProgressKind;
+use crate::settings::SettingsState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnouncementKind {
+    Started,
+    Milestone,
+    Finished,
+    Failed,
+}
+
+/// How urgently a screen reader should speak, as `aria-live` has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Politeness {
+    /// After what is being read now.
+    Polite,
+    /// At once, interrupting.
+    Assertive,
+}
+
+/// The payload of `job-announcement`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Announcement {
+    pub job_id: Option<String>,
+    pub job: ProgressKind,
+    pub kind: AnnouncementKind,
+    pub politeness: Politeness,
+    /// What to read out.
+    pub message: String,
+    /// How far the job got, for milestones and the end of jobs with a
+    /// known total.
+    pub percent: Option<u8>,
+    pub done: Option<u64>,
+    pub total: Option<u64>,
+    /// The file being worked on, in verbose milestones.
+    pub current_path: Option<PathBuf>,
+    /// Files that failed, at the end of a batch.
+    pub failed: Option<u64>,
+}
+
+impl Announcement {
+    fn new(job_id: Option<String>, job: ProgressKind, kind: AnnouncementKind) -> Self {
+        Announcement {
+            job_id,
+            job,
+            kind,
+            politeness: Politeness::Polite,
+            message: String::new(),
+            percent: None,
+            done: None,
+            total: None,
+            current_path: None,
+            failed: None,
+        }
+    }
+}
+
+/// What a job is doing, to start a sentence with.
+fn doing(job: ProgressKind) -> &'static str {
+    match job {
+        ProgressKind::Scan => "Scanning",
+        ProgressKind::Hash => "Hashing",
+        ProgressKind::Metadata => "Reading metadata",
+        ProgressKind::Apply => "Renaming",
+        ProgressKind::Dedupe => "Finding duplicates",
+    }
+}
+
+fn files(count: u64) -> String {
+    match count {
+        1 => "1 file".to_string(),
+        n => format!("{} files", n),
+    }
+}
+
+fn emit(app: &AppHandle, announcement: Announcement) {
+    let _ = app.emit("job-announcement", announcement);
+}
+
+pub(crate) fn verbose(app: &AppHandle) -> bool {
+    app.state::<SettingsState>().get().verbose_announcements
+}
+
+/// How far apart milestones are, in percent.
+pub(crate) fn step(verbose: bool) -> u8 {
+    if verbose {
+        10
+    } else {
+        25
+    }
+}
+
+pub(crate) fn started(app: &AppHandle, job_id: Option<String>, job: ProgressKind) {
+    let mut announcement = Announcement::new(job_id, job, AnnouncementKind::Started);
+    announcement.message = format!("{} started", doing(job));
+    emit(app, announcement);
+}
+
+/// `percent` of the job is done, `done` files of `total`.
+pub(crate) fn milestone(
+    app: &AppHandle,
+    job_id: Option<String>,
+    job: ProgressKind,
+    percent: u8,
+    (done, total): (u64, u64),
+    current: Option<&Path>,
+) {
+    let mut announcement = Announcement::new(job_id, job, AnnouncementKind::Milestone);
+    announcement.message = format!(
+        "{} {}% done, {} of {}",
+        doing(job),
+        percent,
+        done,
+        files(total)
+    );
+    if let Some(name) = current.and_then(Path::file_name) {
+        announcement
+            .message
+            .push_str(&format!(", now {}", name.to_string_lossy()));
+    }
+    announcement.percent = Some(percent);
+    announcement.done = Some(done);
+    announcement.total = Some(total);
+    announcement.current_path = current.map(Path::to_path_buf);
+    emit(app, announcement);
+}
+
+/// A job other than a batch ended after `done` files.
+pub(crate) fn finished(app: &AppHandle, job_id: Option<String>, job: ProgressKind, done: u64) {
+    let mut announcement = Announcement::new(job_id, job, AnnouncementKind::Finished);
+    announcement.message = format!("{} finished, {}", doing(job), files(done));
+    announcement.done = Some(done);
+    emit(app, announcement);
+}
+
+/// A batch ended with `report`; it is a failure, spoken at once, if files
+/// failed or the batch was rolled back.
+pub(crate) fn batch_finished(app: &AppHandle, job_id: Option<String>, report: &ApplyReport) {
+    let failed = report.failed + report.rollback_failed;
+    let kind = if failed > 0 || !report.committed {
+        AnnouncementKind::Failed
+    } else {
+        AnnouncementKind::Finished
+    };
+    let mut announcement = Announcement::new(job_id, ProgressKind::Apply, kind);
+    announcement.message = if report.cancelled {
+        format!(
+            "Renaming cancelled, {} renamed",
+            files(report.renamed as u64)
+        )
+    } else if !report.committed {
+        "Renaming failed and was rolled back".to_string()
+    } else {
+        format!(
+            "Renaming finished, {} renamed",
+            files(report.renamed as u64)
+        )
+    };
+    for (count, what) in [
+        (report.failed, "failed"),
+        (report.skipped, "skipped"),
+        (report.rollback_failed, "not moved back"),
+    ] {
+        if count > 0 {
+            announcement
+                .message
+                .push_str(&format!(", {} {}", count, what));
+        }
+    }
+    if kind == AnnouncementKind::Failed {
+        announcement.politeness = Politeness::Assertive;
+    }
+    announcement.done = Some(report.renamed as u64);
+    announcement.failed = Some(failed as u64);
+    emit(app, announcement);
+}
+
+/// Announces how a batch ended, from what its command returns.
+pub(crate) fn applied(
+    app: &AppHandle,
+    job_id: Option<String>,
+    applied: &Result<ApplyReport, CommandError>,
+) {
+    match applied {
+        Ok(report) => batch_finished(app, job_id, report),
+        Err(e) => failed(app, job_id, ProgressKind::Apply, &e.message),
+    }
+}
+
+/// A job stopped with `error` before it could finish.
+pub(crate) fn failed(app: &AppHandle, job_id: Option<String>, job: ProgressKind, error: &str) {
+    let mut announcement = Announcement::new(job_id, job, AnnouncementKind::Failed);
+    announcement.politeness = Politeness::Assertive;
+    announcement.message = format!("{} failed: {}", doing(job), error);
+    emit(app, announcement);
+}
@@ -0,0 +1,182 @@
+//! The app's own log: written by the log plugin to the OS log folder and
+//! kept in memory for the in-app log viewer, in every build.
+//!
+//! The level can be changed while the app runs and is remembered in the
+//! settings, so someone reporting a problem can turn on debug logging,
+//! restart and reproduce it without a special build.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_log::{Target, TargetKind};
+
+use crate::paths;
+use crate::settings::SettingsState;
+
+const RECENT_ENTRIES: usize = 2000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LogLevel::Error,
+            log::Level::Warn => LogLevel::Warn,
+            log::Level::Info => LogLevel::Info,
+            log::Level::Debug => LogLevel::Debug,
+            log::Level::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    pub time: String,
+    pub level: LogLevel,
+    /// The module that logged it.
+    pub target: String,
+    pub message: String,
+}
+
+impl LogEntry {
+    fn line(&self) -> String {
+        format!(
+            "{} {:<5} {}: {}",
+            self.time,
+            log::LevelFilter::from(self.level),
+            self.target,
+            self.message
+        )
+    }
+}
+
+type Entries = Arc<Mutex<VecDeque<LogEntry>>>;
+
+pub struct LogState(Entries);
+
+/// Keeps each record in memory before handing it to the plugin's logger.
+struct RingLogger {
+    inner: Box<dyn log::Log>,
+    entries: Entries,
+}
+
+impl log::Log for RingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let entry = LogEntry {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            level: record.level().into(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() == RECENT_ENTRIES {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the logger at the level in the settings. Everything down to
+/// trace passes the plugin, so the level set here alone decides what is
+/// logged. In portable mode the log file goes next to the app's data.
+pub fn init(app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = tauri_plugin_log::Builder::default().level(log::LevelFilter::Trace);
+    if let Some(path) = paths::portable_log_dir() {
+        builder = builder.clear_targets().targets([
+            Target::new(TargetKind::Stdout),
+            Target::new(TargetKind::Folder {
+                path,
+                file_name: None,
+            }),
+        ]);
+    }
+    let (plugin, _, inner) = builder.split(app)?;
+    app.plugin(plugin)?;
+    let entries = Entries::default();
+    log::set_boxed_logger(Box::new(RingLogger {
+        inner,
+        entries: entries.clone(),
+    }))?;
+    let level = app.state::<SettingsState>().get().log_level;
+    log::set_max_level(level.into());
+    app.manage(LogState(entries));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_log_level(settings: State<SettingsState>) -> LogLevel {
+    settings.get().log_level
+}
+
+/// Takes effect at once and is kept for the next run.
+#[tauri::command]
+pub fn set_log_level(settings: State<SettingsState>, level: LogLevel) {
+    log::set_max_level(level.into());
+    settings.update(|s| s.log_level = level);
+    log::info!("log level set to {:?}", level);
+}
+
+/// Every entry still in memory as a line of text, oldest first.
+pub(crate) fn recent_lines(app: &AppHandle) -> Vec<String> {
+    let state = app.state::<LogState>();
+    let entries = state.0.lock().unwrap();
+    entries.iter().map(LogEntry::line).collect()
+}
+
+/// The last `n` entries at `min_level` or more severe, oldest first.
+#[tauri::command]
+pub fn get_recent_logs(
+    state: State<LogState>,
+    n: usize,
+    min_level: Option<LogLevel>,
+) -> Vec<LogEntry> {
+    let min_level = min_level.unwrap_or(LogLevel::Trace);
+    let entries = state.0.lock().unwrap();
+    let mut recent: Vec<LogEntry> = entries
+        .iter()
+        .rev()
+        .filter(|e| e.level <= min_level)
+        .take(n)
+        .cloned()
+        .collect();
+    recent.reverse();
+    recent
+}
@@ -0,0 +1,44 @@
+//! Renaming the files inside a zip or tar archive without extracting it;
+//! see `renamer_core::archive`. Entries are named with `remote_preview`,
+//! as they come with paths inside the archive.
+
+use std::path::PathBuf;
+
+use renamer_core::{archive, ApplyReport, CancelToken, RenameOp, ScanOptions, ScanPage};
+use tauri::{AppHandle, Manager};
+
+use crate::error::CommandError;
+use crate::scope;
+use crate::settings::SettingsState;
+
+/// Lists the archive at `path`, which must be in the session's scope.
+#[tauri::command]
+pub async fn archive_scan(
+    app: AppHandle,
+    path: PathBuf,
+    options: Option<ScanOptions>,
+) -> Result<ScanPage, CommandError> {
+    scope::check(&app, [&path])?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let options = options.unwrap_or_default();
+        archive::scan(&path, &options, &CancelToken::new())
+    })
+    .await?
+    .map_err(CommandError::from)
+}
+
+/// Renames entries in the archive at `path` and writes it anew. Refused in
+/// safe mode; the batch is not kept in the history, so it cannot be undone
+/// from there.
+#[tauri::command]
+pub async fn archive_apply(
+    app: AppHandle,
+    path: PathBuf,
+    ops: Vec<RenameOp>,
+) -> Result<ApplyReport, CommandError> {
+    app.state::<SettingsState>().check_safe_mode()?;
+    scope::check(&app, [&path])?;
+    tauri::async_runtime::spawn_blocking(move || archive::apply(&path, &ops, &CancelToken::new()))
+        .await?
+        .map_err(CommandError::from)
+}
@@ -0,0 +1,425 @@
+//! Crash reports, when `crash_reports` is on in the settings: kept in
+//! `crashes/` in the app data folder until deleted, and put into the
+//! diagnostic bundle when asked to.
+//!
+//! A panic on any thread is written as a report right away, with its
+//! message, where it happened and a backtrace. A native crash cannot write
+//! much from inside the dying process: on Windows a minidump is written
+//! next to the reports, elsewhere the signal that killed the app is noted in
+//! a file opened for it in advance. Either is turned into a report the next
+//! time the app starts.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cmp::Reverse;
+use std::fs;
+use std::panic::{self, Location};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::error::CommandError;
+use crate::paths;
+
+/// Where a native crash notes the signal that killed the app.
+const PENDING: &str = "native.pending";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+static VERSION: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashKind {
+    Panic,
+    /// Killed by the OS, e.g. for reading memory it does not own.
+    Native,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// The report's file name without `.json`.
+    pub id: String,
+    pub at: DateTime<Utc>,
+    pub kind: CrashKind,
+    pub app_version: String,
+    pub thread: Option<String>,
+    pub message: String,
+    /// The file and line that panicked.
+    pub location: Option<String>,
+    pub backtrace: Option<String>,
+    /// The minidump written with a native crash, in the same folder.
+    pub minidump: Option<String>,
+}
+
+impl CrashReport {
+    fn new(kind: CrashKind, at: DateTime<Utc>, message: String) -> Self {
+        CrashReport {
+            id: format!("{}-{}", kind_name(kind), at.format("%Y%m%dT%H%M%S%.3fZ")),
+            at,
+            kind,
+            app_version: VERSION.get().cloned().unwrap_or_default(),
+            thread: None,
+            message,
+            location: None,
+            backtrace: None,
+            minidump: None,
+        }
+    }
+
+    fn write(&self, dir: &Path) {
+        if fs::create_dir_all(dir).is_err() {
+            return;
+        }
+        if let Ok(json) = serde_json::to_vec_pretty(self) {
+            let _ = fs::write(dir.join(format!("{}.json", self.id)), json);
+        }
+    }
+}
+
+fn kind_name(kind: CrashKind) -> &'static str {
+    match kind {
+        CrashKind::Panic => "panic",
+        CrashKind::Native => "native",
+    }
+}
+
+fn crash_dir(app: &AppHandle) -> tauri::Result<PathBuf> {
+    Ok(paths::data_dir(app)?.join("crashes"))
+}
+
+/// Hooks panics and native crashes, turns what the last run left of a
+/// native crash into a report, and starts reporting if `enabled`. Called
+/// once, as early as the settings are known.
+pub(crate) fn install(app: &AppHandle, enabled: bool) {
+    let Ok(dir) = crash_dir(app) else {
+        return;
+    };
+    let _ = VERSION.set(app.package_info().version.to_string());
+    collect_native(&dir);
+    let _ = DIR.set(dir);
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if ENABLED.load(Ordering::Relaxed) {
+            if let Some(dir) = DIR.get() {
+                panicked(info.payload(), info.location()).write(dir);
+            }
+        }
+        previous(info);
+    }));
+    set_enabled(enabled);
+}
+
+/// Starts or stops writing reports, when the setting changes.
+pub(crate) fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if let Some(dir) = DIR.get() {
+        platform::set_enabled(dir, enabled);
+    }
+}
+
+fn panicked(payload: &(dyn Any + Send), location: Option<&Location>) -> CrashReport {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "a panic without a message".to_string());
+    let mut report = CrashReport::new(CrashKind::Panic, Utc::now(), message);
+    report.thread = std::thread::current().name().map(str::to_string);
+    report.location = location.map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+    report.backtrace = Some(Backtrace::force_capture().to_string());
+    report
+}
+
+/// Writes a report for the signal noted, or each minidump written, when the
+/// app last crashed.
+fn collect_native(dir: &Path) {
+    let pending = dir.join(PENDING);
+    if let Ok(noted) = fs::read_to_string(&pending) {
+        let signal = noted.trim();
+        if !signal.is_empty() {
+            let at = modified(&pending);
+            let report = CrashReport::new(
+                CrashKind::Native,
+                at,
+                format!("the app was killed by {}", signal),
+            );
+            report.write(dir);
+        }
+        let _ = fs::remove_file(&pending);
+    }
+    let Ok(listing) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in listing.flatten().map(|e| e.path()) {
+        if path.extension().is_some_and(|e| e == "dmp") && !path.with_extension("json").exists() {
+            let mut report = CrashReport::new(
+                CrashKind::Native,
+                modified(&path),
+                "the app crashed; see the minidump".to_string(),
+            );
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            report.id = stem.into_owned();
+            report.minidump = path.file_name().map(|n| n.to_string_lossy().into_owned());
+            report.write(dir);
+        }
+    }
+}
+
+fn modified(path: &Path) -> DateTime<Utc> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now())
+}
+
+/// The files of every report, with their minidumps, for the diagnostic
+/// bundle.
+pub(crate) fn files(app: &AppHandle) -> Vec<PathBuf> {
+    let Some(listing) = crash_dir(app).ok().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = listing
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json" || e == "dmp"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// The crash reports kept, newest first, so the app can offer to send
+/// them along with a diagnostic bundle.
+#[tauri::command]
+pub fn list_crash_reports(app: AppHandle) -> Vec<CrashReport> {
+    let mut reports: Vec<CrashReport> = files(&app)
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .filter_map(|p| serde_json::from_slice(&fs::read(p).ok()?).ok())
+        .collect();
+    reports.sort_by_key(|r| Reverse(r.at));
+    reports
+}
+
+/// Deletes every crash report and minidump; returns how many reports there
+/// were.
+#[tauri::command]
+pub fn delete_crash_reports(app: AppHandle) -> Result<usize, CommandError> {
+    let mut deleted = 0;
+    for path in files(&app) {
+        fs::remove_file(&path)?;
+        deleted += usize::from(path.extension().is_some_and(|e| e == "json"));
+    }
+    Ok(deleted)
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs::File;
+    use std::os::unix::io::IntoRawFd;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::OnceLock;
+
+    use super::PENDING;
+
+    const SIGNALS: [(libc::c_int, &[u8]); 5] = [
+        (libc::SIGSEGV, b"SIGSEGV\n"),
+        (libc::SIGBUS, b"SIGBUS\n"),
+        (libc::SIGILL, b"SIGILL\n"),
+        (libc::SIGFPE, b"SIGFPE\n"),
+        (libc::SIGABRT, b"SIGABRT\n"),
+    ];
+
+    /// The pending file, while reports are on.
+    static FD: AtomicI32 = AtomicI32::new(-1);
+    /// What handled each signal before, restored before it is handled
+    /// again.
+    static PREVIOUS: OnceLock<[libc::sigaction; 5]> = OnceLock::new();
+
+    pub fn set_enabled(dir: &Path, enabled: bool) {
+        if enabled && FD.load(Ordering::Relaxed) < 0 {
+            let _ = std::fs::create_dir_all(dir);
+            if let Ok(file) = File::create(dir.join(PENDING)) {
+                FD.store(file.into_raw_fd(), Ordering::Relaxed);
+            }
+            install();
+        } else if !enabled {
+            let fd = FD.swap(-1, Ordering::Relaxed);
+            if fd >= 0 {
+                // SAFETY: the descriptor was ours and nothing uses it now.
+                unsafe { libc::close(fd) };
+                let _ = std::fs::remove_file(dir.join(PENDING));
+            }
+        }
+    }
+
+    fn install() {
+        PREVIOUS.get_or_init(|| {
+            // SAFETY: the handler only makes async-signal-safe calls, and
+            // every sigaction struct is zeroed before it is filled in.
+            unsafe {
+                let mut previous: [libc::sigaction; 5] = std::mem::zeroed();
+                for (i, (signal, _)) in SIGNALS.iter().enumerate() {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = on_signal as *const () as usize;
+                    action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    libc::sigaction(*signal, &action, &mut previous[i]);
+                }
+                previous
+            }
+        });
+    }
+
+    /// Notes the signal, then puts back what handled it before and returns:
+    /// the faulting instruction runs again and that handler, or the OS,
+    /// takes over, as `abort` does for `SIGABRT`.
+    extern "C" fn on_signal(signal: libc::c_int, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
+        let fd = FD.load(Ordering::Relaxed);
+        let Some(i) = SIGNALS.iter().position(|(s, _)| *s == signal) else {
+            return;
+        };
+        // SAFETY: write and sigaction are async-signal-safe; `previous`
+        // was filled in before the handler was installed.
+        unsafe {
+            if fd >= 0 {
+                let name = SIGNALS[i].1;
+                libc::write(fd, name.as_ptr().cast(), name.len());
+            }
+            if let Some(previous) = PREVIOUS.get() {
+                libc::sigaction(signal, &previous[i], std::ptr::null_mut());
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::OnceLock;
+
+    const GENERIC_WRITE: u32 = 0x4000_0000;
+    const CREATE_ALWAYS: u32 = 2;
+    const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+    const INVALID_HANDLE_VALUE: *mut c_void = -1isize as *mut c_void;
+    /// Stacks, threads and the data segments, but not the whole heap.
+    const MINI_DUMP_WITH_DATA_SEGS: u32 = 0x0001;
+    const MINI_DUMP_WITH_THREAD_INFO: u32 = 0x1000;
+    /// Lets Windows Error Reporting and debuggers see the crash too.
+    const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+
+    #[repr(C, packed(4))]
+    struct MinidumpExceptionInformation {
+        thread_id: u32,
+        exception_pointers: *mut c_void,
+        client_pointers: i32,
+    }
+
+    type Filter = unsafe extern "system" fn(*mut c_void) -> i32;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetUnhandledExceptionFilter(filter: Option<Filter>) -> Option<Filter>;
+        fn GetCurrentProcess() -> *mut c_void;
+        fn GetCurrentProcessId() -> u32;
+        fn GetCurrentThreadId() -> u32;
+        fn CreateFileW(
+            name: *const u16,
+            access: u32,
+            share: u32,
+            security: *mut c_void,
+            disposition: u32,
+            flags: u32,
+            template: *mut c_void,
+        ) -> *mut c_void;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    #[link(name = "dbghelp")]
+    extern "system" {
+        fn MiniDumpWriteDump(
+            process: *mut c_void,
+            process_id: u32,
+            file: *mut c_void,
+            dump_type: u32,
+            exception: *const MinidumpExceptionInformation,
+            user_streams: *const c_void,
+            callback: *const c_void,
+        ) -> i32;
+    }
+
+    static ENABLED: AtomicBool = AtomicBool::new(false);
+    /// The dump's path, worked out in advance, NUL-terminated.
+    static DUMP: OnceLock<Vec<u16>> = OnceLock::new();
+    static PREVIOUS: OnceLock<Option<Filter>> = OnceLock::new();
+
+    pub fn set_enabled(dir: &Path, enabled: bool) {
+        ENABLED.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            return;
+        }
+        let _ = std::fs::create_dir_all(dir);
+        DUMP.get_or_init(|| {
+            let name = format!(
+                "native-{}.dmp",
+                chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+            );
+            dir.join(name)
+                .as_os_str()
+                .encode_wide()
+                .chain(Some(0))
+                .collect()
+        });
+        // SAFETY: installs a filter that only runs on a crash.
+        PREVIOUS.get_or_init(|| unsafe { SetUnhandledExceptionFilter(Some(on_exception)) });
+    }
+
+    unsafe extern "system" fn on_exception(pointers: *mut c_void) -> i32 {
+        if let (true, Some(path)) = (ENABLED.load(Ordering::Relaxed), DUMP.get()) {
+            let file = CreateFileW(
+                path.as_ptr(),
+                GENERIC_WRITE,
+                0,
+                std::ptr::null_mut(),
+                CREATE_ALWAYS,
+                FILE_ATTRIBUTE_NORMAL,
+                std::ptr::null_mut(),
+            );
+            if file != INVALID_HANDLE_VALUE {
+                let exception = MinidumpExceptionInformation {
+                    thread_id: GetCurrentThreadId(),
+                    exception_pointers: pointers,
+                    client_pointers: 0,
+                };
+                MiniDumpWriteDump(
+                    GetCurrentProcess(),
+                    GetCurrentProcessId(),
+                    file,
+                    MINI_DUMP_WITH_DATA_SEGS | MINI_DUMP_WITH_THREAD_INFO,
+                    &exception,
+                    std::ptr::null(),
+                    std::ptr::null(),
+                );
+                CloseHandle(file);
+            }
+        }
+        match PREVIOUS.get().copied().flatten() {
+            Some(previous) => previous(pointers),
+            None => EXCEPTION_CONTINUE_SEARCH,
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    /// Only panics are reported here.
+    pub fn set_enabled(_dir: &std::path::Path, _enabled: bool) {}
+}
@@ -0,0 +1,121 @@
+//! Hand-offs to the desktop the app runs on.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::error::CommandError;
+use crate::scope;
+use crate::settings::SettingsState;
+
+/// `path` as a `file://` URI, percent-encoding everything but unreserved
+/// characters and `/`.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn file_uri(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+#[cfg(target_os = "windows")]
+fn reveal(path: &Path) -> std::io::Result<()> {
+    use std::os::windows::process::CommandExt;
+    // Explorer parses its own command line; quoting the path keeps commas
+    // and spaces in it.
+    Command::new("explorer")
+        .raw_arg(format!("/select,\"{}\"", path.display()))
+        .spawn()
+        .map(drop)
+}
+
+#[cfg(target_os = "macos")]
+fn reveal(path: &Path) -> std::io::Result<()> {
+    Command::new("open").arg("-R").arg(path).spawn().map(drop)
+}
+
+/// Asks the file manager to select the file over D-Bus, which Nautilus,
+/// Dolphin, Nemo and others understand, and falls back to opening the
+/// folder it is in.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn reveal(path: &Path) -> std::io::Result<()> {
+    let selected = Command::new("dbus-send")
+        .args([
+            "--session",
+            "--print-reply",
+            "--dest=org.freedesktop.FileManager1",
+            "--type=method_call",
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1.ShowItems",
+        ])
+        .arg(format!("array:string:{}", file_uri(path)))
+        .arg("string:")
+        .output()
+        .is_ok_and(|out| out.status.success());
+    if selected {
+        return Ok(());
+    }
+    let folder = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap_or(path)
+    };
+    Command::new("xdg-open").arg(folder).spawn().map(drop)
+}
+
+/// Shows `path` selected in Explorer, Finder or the desktop's file manager.
+#[tauri::command]
+pub async fn reveal_in_file_manager(app: AppHandle, path: PathBuf) -> Result<(), CommandError> {
+    scope::check(&app, [&path])?;
+    if !path.exists() {
+        return Err(CommandError::from(renamer_core::Error::io(
+            &path,
+            std::io::ErrorKind::NotFound.into(),
+        )));
+    }
+    tauri::async_runtime::spawn_blocking(move || reveal(&path))
+        .await?
+        .map_err(|e| format!("cannot open the file manager: {}", e).into())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Trashed {
+    pub path: PathBuf,
+    /// Why the file is still where it was.
+    pub error: Option<String>,
+}
+
+/// Moves `paths` to the recycle bin or trash, one at a time so one failure
+/// does not keep the rest, and reports how each went, in the order given.
+/// Nothing is ever deleted outright, and nothing is moved in safe mode.
+#[tauri::command]
+pub async fn trash_files(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<Trashed>, CommandError> {
+    scope::check(&app, &paths)?;
+    app.state::<SettingsState>().check_safe_mode()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        paths
+            .into_iter()
+            .map(|path| {
+                let error = if path.symlink_metadata().is_err() {
+                    Some("the file does not exist".to_string())
+                } else {
+                    trash::delete(&path).err().map(|e| e.to_string())
+                };
+                Trashed { path, error }
+            })
+            .collect()
+    })
+    .await
+    .map_err(CommandError::from)
+}
@@ -0,0 +1,296 @@
+//! A zip to attach to a bug report: the recent app log, the sidecar's log
+//! files, versions and OS, the last batch that failed and, if asked for,
+//! the crash reports kept; see `crash`.
+//!
+//! The failed batch is the one thing in it that lists the user's files, so
+//! it can be left out or have its paths redacted: each folder and file name
+//! is replaced by a numbered placeholder, the same one wherever the name
+//! comes up, keeping extensions so the shape of the batch survives.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use renamer_core::{ApplyReport, RenamePlan};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::error::CommandError;
+use crate::settings::SettingsState;
+use crate::{app_log, crash, scope, sidecar, sidecar_log};
+
+/// A batch that failed or was rolled back, as planned and as it ran.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedBatch {
+    pub at: DateTime<Utc>,
+    pub plan: RenamePlan,
+    /// `None` when applying failed before any report was made.
+    pub report: Option<ApplyReport>,
+    pub error: Option<String>,
+}
+
+/// The last failed batch of this run.
+#[derive(Default)]
+pub struct FailedBatchState(Mutex<Option<FailedBatch>>);
+
+/// Remembers a batch that did not go through for the next bundle.
+pub(crate) fn batch_failed(
+    app: &AppHandle,
+    plan: RenamePlan,
+    report: Option<ApplyReport>,
+    error: Option<String>,
+) {
+    *app.state::<FailedBatchState>().0.lock().unwrap() = Some(FailedBatch {
+        at: Utc::now(),
+        plan,
+        report,
+        error,
+    });
+}
+
+/// Whether, and how, the last failed batch goes into the bundle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailedBatchExport {
+    Omit,
+    #[default]
+    Redacted,
+    Full,
+}
+
+fn is_path(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    text.starts_with('/')
+        || text.starts_with("\\\\")
+        || (bytes.len() > 2
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && matches!(bytes[2], b'\\' | b'/'))
+}
+
+/// Replaces names in paths with placeholders, numbering each new name.
+#[derive(Default)]
+struct Redactor {
+    names: HashMap<String, usize>,
+    /// Whole paths seen so far with their redacted form, to catch them in
+    /// error messages too.
+    paths: Vec<(String, String)>,
+}
+
+impl Redactor {
+    fn path(&mut self, text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        // Keep a drive letter as it is.
+        if text.as_bytes().get(1) == Some(&b':') {
+            out.push_str(&text[..2]);
+            rest = &text[2..];
+        }
+        let mut name = String::new();
+        for c in rest.chars() {
+            if c == '/' || c == '\\' {
+                self.name(&name, &mut out);
+                name.clear();
+                out.push(c);
+            } else {
+                name.push(c);
+            }
+        }
+        self.name(&name, &mut out);
+        self.paths.push((text.to_string(), out.clone()));
+        out
+    }
+
+    fn name(&mut self, name: &str, out: &mut String) {
+        if name.is_empty() {
+            return;
+        }
+        let (stem, ext) = match name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() && ext.len() <= 5 => (stem, Some(ext)),
+            _ => (name, None),
+        };
+        let next = self.names.len() + 1;
+        let n = *self.names.entry(stem.to_string()).or_insert(next);
+        out.push_str(&format!("name{}", n));
+        if let Some(ext) = ext {
+            out.push('.');
+            out.push_str(ext);
+        }
+    }
+
+    fn collect(&mut self, value: &Value) {
+        match value {
+            Value::String(s) if is_path(s) => {
+                self.path(s);
+            }
+            Value::Array(items) => items.iter().for_each(|v| self.collect(v)),
+            Value::Object(map) => map.values().for_each(|v| self.collect(v)),
+            _ => {}
+        }
+    }
+
+    fn replace(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some((_, redacted)) = self.paths.iter().find(|(p, _)| p == s) {
+                    *s = redacted.clone();
+                    return;
+                }
+                for (path, redacted) in &self.paths {
+                    if s.contains(path.as_str()) {
+                        *s = s.replace(path.as_str(), redacted);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| self.replace(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.replace(v)),
+            _ => {}
+        }
+    }
+}
+
+/// `value` with every path in it, and in messages quoting one, redacted.
+fn redact(mut value: Value) -> Value {
+    let mut redactor = Redactor::default();
+    redactor.collect(&value);
+    // Longer paths first, so a folder does not redact part of a file in it.
+    redactor
+        .paths
+        .sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.cmp(b)));
+    redactor.paths.dedup();
+    redactor.replace(&mut value);
+    value
+}
+
+fn write_bundle(
+    path: &Path,
+    info: &Value,
+    app_log: &[String],
+    sidecar_logs: &[PathBuf],
+    crashes: &[PathBuf],
+    failed: Option<&Value>,
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, bytes: &[u8]| -> Result<(), String> {
+        zip.start_file(name, options).map_err(|e| e.to_string())?;
+        zip.write_all(bytes).map_err(|e| e.to_string())
+    };
+    add(
+        "info.json",
+        &serde_json::to_vec_pretty(info).map_err(|e| e.to_string())?,
+    )?;
+    add("app.log", app_log.join("\n").as_bytes())?;
+    for log in sidecar_logs {
+        // A file rotated away since it was listed is simply missing.
+        if let (Ok(bytes), Some(name)) = (fs::read(log), log.file_name()) {
+            add(&format!("sidecar/{}", name.to_string_lossy()), &bytes)?;
+        }
+    }
+    for report in crashes {
+        if let (Ok(bytes), Some(name)) = (fs::read(report), report.file_name()) {
+            add(&format!("crashes/{}", name.to_string_lossy()), &bytes)?;
+        }
+    }
+    if let Some(failed) = failed {
+        add(
+            "failed-batch.json",
+            &serde_json::to_vec_pretty(failed).map_err(|e| e.to_string())?,
+        )?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The versions of every part of the app.
+#[derive(Debug, Clone, Serialize)]
+pub struct Versions {
+    pub app: String,
+    pub core: &'static str,
+    pub tauri: &'static str,
+    pub webview: Option<String>,
+    /// `None` until the sidecar is ready.
+    pub sidecar: Option<String>,
+    /// A part reports a version other than the app's, e.g. a sidecar left
+    /// behind by a broken update.
+    pub mismatch: bool,
+}
+
+pub(crate) fn versions(app: &AppHandle) -> Versions {
+    let version = app.package_info().version.to_string();
+    let sidecar = sidecar::sidecar_version(app);
+    Versions {
+        mismatch: renamer_core::VERSION != version
+            || sidecar.as_ref().is_some_and(|s| *s != version),
+        app: version,
+        core: renamer_core::VERSION,
+        tauri: tauri::VERSION,
+        webview: tauri::webview_version().ok(),
+        sidecar,
+    }
+}
+
+/// For the About dialog and bug reports.
+#[tauri::command]
+pub fn get_versions(app: AppHandle) -> Versions {
+    versions(&app)
+}
+
+/// Writes the bundle to `path`, which usually comes from `pick_save_path`.
+/// Crash reports go in only with `crash_reports`.
+#[tauri::command]
+pub async fn create_diagnostic_bundle(
+    app: AppHandle,
+    path: PathBuf,
+    failed_batch: Option<FailedBatchExport>,
+    crash_reports: Option<bool>,
+) -> Result<(), CommandError> {
+    scope::check(&app, [&path])?;
+    let info = json!({
+        "created_at": Utc::now(),
+        "versions": versions(&app),
+        "os": std::env::consts::OS,
+        "os_family": std::env::consts::FAMILY,
+        "arch": std::env::consts::ARCH,
+        "log_level": app.state::<SettingsState>().get().log_level,
+    });
+    let app_log = app_log::recent_lines(&app);
+    let sidecar_logs = sidecar_log::files(&app);
+    let crashes = if crash_reports.unwrap_or(false) {
+        crash::files(&app)
+    } else {
+        Vec::new()
+    };
+    let failed = match failed_batch.unwrap_or_default() {
+        FailedBatchExport::Omit => None,
+        export => {
+            let failed = app.state::<FailedBatchState>().0.lock().unwrap().clone();
+            let failed = failed
+                .map(|f| serde_json::to_value(f).map_err(CommandError::from))
+                .transpose()?;
+            match export {
+                FailedBatchExport::Redacted => failed.map(redact),
+                _ => failed,
+            }
+        }
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        write_bundle(
+            &path,
+            &info,
+            &app_log,
+            &sidecar_logs,
+            &crashes,
+            failed.as_ref(),
+        )
+    })
+    .await?
+    .map_err(CommandError::from)
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::DialogExt;
+
+#[cfg(mobile)]
+use crate::error::AppErrorCode;
+use crate::error::CommandError;
+use crate::scope;
+use crate::settings::SettingsState;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PickFilter {
+    pub name: String,
+    /// Without the dot, e.g. `["jpg", "png"]`.
+    pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PickOptions {
+    /// Pick folders instead of files.
+    pub folders: bool,
+    /// Allow picking one item only.
+    pub single: bool,
+    /// Offered for files; ignored for folders.
+    pub filters: Vec<PickFilter>,
+    pub title: Option<String>,
+}
+
+/// `path` made absolute with links resolved, without the `\\?\` prefix
+/// Windows adds, so it reads the way the user expects in the list.
+fn canonical(path: PathBuf) -> PathBuf {
+    let Ok(resolved) = fs::canonicalize(&path) else {
+        return path;
+    };
+    let text = resolved.to_string_lossy();
+    match text.strip_prefix(r"\\?\") {
+        Some(rest) if !rest.starts_with("UNC") => PathBuf::from(rest),
+        _ => resolved,
+    }
+}
+
+/// Opens the native file or folder picker, starting where the last one was
+/// left, and returns the canonicalized paths chosen, ready for
+/// `scan_directory`; they are added to the session's scope. Nothing chosen
+/// gives an empty list. On Android and iOS, where picked files and folders
+/// have no paths, folders are picked with `pick_storage_folder` instead.
+#[tauri::command]
+pub async fn pick_sources(
+    app: AppHandle,
+    options: Option<PickOptions>,
+) -> Result<Vec<PathBuf>, CommandError> {
+    let options = options.unwrap_or_default();
+    #[cfg(mobile)]
+    if options.folders {
+        return Err(CommandError::new(
+            AppErrorCode::Unsupported,
+            "folders are picked with `pick_storage_folder` on Android and iOS",
+        ));
+    }
+    let mut dialog = app.dialog().file();
+    if let Some(dir) = app.state::<SettingsState>().get().last_dir {
+        if dir.is_dir() {
+            dialog = dialog.set_directory(dir);
+        }
+    }
+    if let Some(title) = &options.title {
+        dialog = dialog.set_title(title);
+    }
+    if !options.folders {
+        for filter in &options.filters {
+            let extensions: Vec<&str> = filter
+                .extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.'))
+                .collect();
+            dialog = dialog.add_filter(&filter.name, &extensions);
+        }
+    }
+    let picked =
+        tauri::async_runtime::spawn_blocking(move || match (options.folders, options.single) {
+            #[cfg(desktop)]
+            (true, true) => dialog.blocking_pick_folder().map(|p| vec![p]),
+            #[cfg(desktop)]
+            (true, false) => dialog.blocking_pick_folders(),
+            (_, true) => dialog.blocking_pick_file().map(|p| vec![p]),
+            (_, false) => dialog.blocking_pick_files(),
+        })
+        .await?
+        .unwrap_or_default();
+    let paths: Vec<PathBuf> = picked
+        .into_iter()
+        .filter_map(|p| p.into_path().ok())
+        .map(canonical)
+        .collect();
+    if let Some(dir) = paths
+        .first()
+        .and_then(|p| p.parent())
+        .map(Path::to_path_buf)
+    {
+        app.state::<SettingsState>()
+            .update(|settings| settings.last_dir = Some(dir));
+    }
+    scope::allow_sources(&app, &paths);
+    Ok(paths)
+}
+
+/// Opens the native save dialog, e.g. for `export_report`, and returns the
+/// file chosen, which is added to the scope on its own. `None` if the user
+/// cancelled.
+#[tauri::command]
+pub async fn pick_save_path(
+    app: AppHandle,
+    default_name: Option<String>,
+    filters: Option<Vec<PickFilter>>,
+    title: Option<String>,
+) -> Result<Option<PathBuf>, CommandError> {
+    let mut dialog = app.dialog().file();
+    if let Some(dir) = app.state::<SettingsState>().get().last_dir {
+        if dir.is_dir() {
+            dialog = dialog.set_directory(dir);
+        }
+    }
+    if let Some(name) = &default_name {
+        dialog = dialog.set_file_name(name);
+    }
+    if let Some(title) = &title {
+        dialog = dialog.set_title(title);
+    }
+    for filter in filters.unwrap_or_default() {
+        let extensions: Vec<&str> = filter
+            .extensions
+            .iter()
+            .map(|e| e.trim_start_matches('.'))
+            .collect();
+        dialog = dialog.add_filter(&filter.name, &extensions);
+    }
+    let picked = tauri::async_runtime::spawn_blocking(move || dialog.blocking_save_file()).await?;
+    let Some(path) = picked.and_then(|p| p.into_path().ok()) else {
+        return Ok(None);
+    };
+    scope::allow(&app, &path);
+    Ok(Some(path))
+}
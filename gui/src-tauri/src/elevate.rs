@@ -0,0 +1,269 @@
+//! Applying the renames pre-flight found need admin rights, e.g. in Program
+//! Files or on a mount owned by root, by starting the app again elevated
+//! (UAC, polkit's `pkexec` or macOS authorization) to apply just those.
+//!
+//! The elevated copy is given a request file with the plan and is told by
+//! `--elevated-apply` to apply it with the engine and write the report next
+//! to the request, then exit, without starting the app. The request sits in
+//! the data folder, which the user's other programs can write to as well,
+//! so the copy is also given the data folder and the number of entries on
+//! its command line, which goes through the prompt, and applies nothing
+//! that does not match them. The batch is
+//! journaled in the app's journal folder like any other, so an interrupted
+//! one can be recovered, and recorded in the history by the app once the
+//! helper is done.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use renamer_core::{ApplyOptions, ApplyReport, PlanOptions, RenameOp, RenamePlan, WebhookEvent};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::engine::{journal_dir, record_batch, run_post_action};
+use crate::error::{AppErrorCode, CommandError};
+use crate::settings::SettingsState;
+use crate::{notify, paths, scope};
+
+const HELPER_ARG: &str = "--elevated-apply";
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    plan: RenamePlan,
+    options: ApplyOptions,
+    journal_dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Report(ApplyReport),
+    Error(String),
+}
+
+fn outcome_path(request: &Path) -> PathBuf {
+    request.with_extension("outcome.json")
+}
+
+/// Reads the request at `request`, refusing one outside `data_dir`, with
+/// its journal folder outside it, with a plan that is blocked or with
+/// another number of entries than `entries`.
+fn read_request(data_dir: &Path, request: &Path, entries: usize) -> Result<Request, String> {
+    let inside = |path: &Path| -> Result<(), String> {
+        let data_dir = data_dir.canonicalize().map_err(|e| e.to_string())?;
+        match path.canonicalize() {
+            Ok(path) if path.starts_with(&data_dir) => Ok(()),
+            Ok(_) => Err(format!("{} is outside the data folder", path.display())),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    };
+    inside(request)?;
+    let bytes = fs::read(request).map_err(|e| e.to_string())?;
+    let req: Request = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    inside(&req.journal_dir)?;
+    if req.plan.blocked {
+        return Err("the plan is blocked".into());
+    }
+    if req.plan.entries.len() != entries {
+        return Err(format!(
+            "the plan has {} entries rather than {}",
+            req.plan.entries.len(),
+            entries
+        ));
+    }
+    Ok(req)
+}
+
+/// Runs the batch in the request file named after `--elevated-apply` and
+/// the data folder, if the app was started with it, and returns the exit
+/// code; `None` for a normal start.
+pub fn helper_main() -> Option<i32> {
+    let mut args = std::env::args_os().skip(1);
+    if args.next()? != HELPER_ARG {
+        return None;
+    }
+    let (Some(data_dir), Some(request), Some(entries)) = (args.next(), args.next(), args.next())
+    else {
+        return Some(2);
+    };
+    let (data_dir, request) = (PathBuf::from(data_dir), PathBuf::from(request));
+    let Some(entries) = entries.to_str().and_then(|n| n.parse().ok()) else {
+        return Some(2);
+    };
+    let outcome = match read_request(&data_dir, &request, entries) {
+        Ok(req) => match renamer_core::apply(&req.plan, &req.options, &req.journal_dir) {
+            Ok(report) => Outcome::Report(report),
+            Err(e) => Outcome::Error(e.to_string()),
+        },
+        Err(e) => Outcome::Error(e),
+    };
+    let failed = matches!(outcome, Outcome::Error(_));
+    let written = serde_json::to_vec(&outcome)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| fs::write(outcome_path(&request), bytes).map_err(|e| e.to_string()));
+    Some(if written.is_err() || failed { 2 } else { 0 })
+}
+
+/// What to start elevated: the AppImage itself rather than the executable
+/// inside its mount, which root cannot see.
+fn executable() -> std::io::Result<PathBuf> {
+    if let Some(appimage) = std::env::var_os("APPIMAGE") {
+        return Ok(PathBuf::from(appimage));
+    }
+    std::env::current_exe()
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::path::Path;
+    use std::process::{Command, ExitStatus};
+
+    /// A PowerShell string literal.
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', "''"))
+    }
+
+    pub fn run_elevated(exe: &Path, args: &[String]) -> std::io::Result<ExitStatus> {
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| quote(&format!("\"{}\"", arg)))
+            .collect();
+        let script = format!(
+            "$p = Start-Process -FilePath {} -ArgumentList {} -Verb RunAs -Wait -PassThru; exit $p.ExitCode",
+            quote(&exe.display().to_string()),
+            args.join(", "),
+        );
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .status()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::path::Path;
+    use std::process::{Command, ExitStatus};
+
+    /// A shell word.
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+
+    pub fn run_elevated(exe: &Path, args: &[String]) -> std::io::Result<ExitStatus> {
+        let command = std::iter::once(exe.display().to_string())
+            .chain(args.iter().cloned())
+            .map(|word| quote(&word))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let command = command.replace('\\', r"\\").replace('"', "\\\"");
+        Command::new("osascript")
+            .arg("-e")
+            .arg(format!(
+                "do shell script \"{}\" with administrator privileges",
+                command
+            ))
+            .status()
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::path::Path;
+    use std::process::{Command, ExitStatus};
+
+    pub fn run_elevated(exe: &Path, args: &[String]) -> std::io::Result<ExitStatus> {
+        Command::new("pkexec").arg(exe).args(args).status()
+    }
+}
+
+/// Applies `ops` with admin rights, asking for them the way the system
+/// does; only for the entries pre-flight lists as `elevatable`, which is run
+/// again first, and fails with `not_elevatable` for any others. Resolves
+/// with the report as `apply_renames` does, and records the batch the same
+/// way. Declining the prompt fails with `elevation_refused`.
+#[tauri::command]
+pub async fn apply_elevated(
+    app: AppHandle,
+    ops: Vec<RenameOp>,
+    plan_options: Option<PlanOptions>,
+    apply_options: Option<ApplyOptions>,
+    preset: Option<String>,
+) -> Result<ApplyReport, CommandError> {
+    scope::check_ops(&app, &ops)?;
+    scope::check_preset(&app, preset.as_deref(), &ops)?;
+    // Refused before the password prompt rather than by the helper.
+    let settings = app.state::<SettingsState>();
+    settings.check_safe_mode()?;
+    let plan_options = plan_options.unwrap_or_default();
+    let plan = tauri::async_runtime::spawn_blocking(move || {
+        let plan = renamer_core::plan(ops, &plan_options);
+        let preflight = renamer_core::preflight(&plan);
+        let refused: Vec<String> = plan
+            .ready()
+            .filter(|e| !preflight.elevatable.contains(&e.id))
+            .map(|e| e.id.to_string())
+            .collect();
+        if plan.blocked || !refused.is_empty() {
+            return Err(CommandError::new(
+                AppErrorCode::NotElevatable,
+                "only renames admin rights get past can be applied with them",
+            )
+            .with("entries", refused.join(",")));
+        }
+        Ok(plan)
+    })
+    .await??;
+    let journal_dir = journal_dir(&app)?;
+    // Made here so it is not left owned by root; the data folder with it.
+    fs::create_dir_all(&journal_dir)?;
+    let entries = plan.entries.len();
+    let request = Request {
+        plan,
+        options: settings.enforce(apply_options.unwrap_or_default()),
+        journal_dir,
+    };
+    // Not in the temp folder, where the sticky bit would keep the outcome
+    // root writes from being removed.
+    let data_dir = paths::data_dir(&app)?;
+    let path = data_dir.join(format!("elevated-{}.json", Uuid::new_v4()));
+    fs::write(&path, serde_json::to_vec(&request)?)?;
+
+    let exe = executable()?;
+    let args = [
+        HELPER_ARG.to_string(),
+        data_dir.display().to_string(),
+        path.display().to_string(),
+        entries.to_string(),
+    ];
+    let status =
+        tauri::async_runtime::spawn_blocking(move || platform::run_elevated(&exe, &args)).await?;
+    let outcome = fs::read(outcome_path(&path))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Outcome>(&bytes).ok());
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(outcome_path(&path));
+
+    let report = match outcome {
+        Some(Outcome::Report(report)) => report,
+        Some(Outcome::Error(message)) => return Err(message.into()),
+        None => {
+            let detail = match status {
+                Ok(status) => status.to_string(),
+                Err(e) => e.to_string(),
+            };
+            return Err(CommandError::new(
+                AppErrorCode::ElevationRefused,
+                format!("admin rights were not given ({})", detail),
+            )
+            .with("detail", detail));
+        }
+    };
+    record_batch(&app, &report, preset.as_deref());
+    notify::batch_finished(&app, &report, None);
+    notify::webhooks(&app, &report, WebhookEvent::Batch, preset.as_deref());
+    if let Some(preset) = &preset {
+        run_post_action(&app, &report, preset);
+    }
+    Ok(report)
+}
@@ -0,0 +1,1309 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use renamer_core::post_action;
+use renamer_core::{
+    ApplyOptions, ApplyReport, ArchivedBatch, AuditLog, AuditVerification, BatchRecord,
+    BurstOptions, CancelToken, DateRange, DedupeOptions, DropScanner, DuplicateGroup, ExportFormat,
+    ExtractOptions, ExtractQueue, Extracted, FileCache, FileChange, FileContext, FileHash,
+    FileStat, HashAlgorithm, HistoryDb, HistoryStats, HistoryStore, Journal, Mapping, Metadata,
+    OutcomeStatus, Pipeline, PlanOptions, PluginError, PluginHost, PluginInfo, PostActionRun,
+    PreflightReport, Preset, PresetComparison, PresetStore, PreviewRow, Recovery, RegexRule,
+    RenameOp, RenamePlan, ReplayReport, ReplayStatus, Review, Rule, ScanListing, ScanOptions,
+    ScanPage, ScanQuery, ScanWindow, Scanner, Simulation, Snapshot, Template, WebhookEvent,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
+
+use crate::error::{AppErrorCode, CommandError};
+use crate::jobs::{self, ProgressKind, ProgressReporter};
+use crate::settings::SettingsState;
+#[cfg(desktop)]
+use crate::tray;
+use crate::{announce, diagnostics, notify, paths, scope};
+
+// Planning stats the filesystem and applying a batch can take a while, so both
+// run on the blocking pool instead of the main thread. Every command taking
+// paths from the webview checks them against the session's scope first.
+
+pub struct HistoryState(pub Mutex<HistoryStore>);
+
+impl HistoryState {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let path = paths::data_dir(app)
+            .map_err(|e| e.to_string())?
+            .join("history.json");
+        let store = HistoryStore::open(path).map_err(|e| e.to_string())?;
+        Ok(HistoryState(Mutex::new(store)))
+    }
+}
+
+/// The permanent, searchable history in `history.db`. Batches already in the
+/// undo history are added when it is first opened.
+pub struct HistoryDbState(pub Mutex<HistoryDb>);
+
+impl HistoryDbState {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let path = paths::data_dir(app)
+            .map_err(|e| e.to_string())?
+            .join("history.db");
+        let mut db = HistoryDb::open(path).map_err(|e| e.to_string())?;
+        // Batches maintenance forgot would otherwise come back from the
+        // undo history.
+        let cutoff = app
+            .state::<SettingsState>()
+            .all()
+            .retention
+            .history_cutoff();
+        let batches: Vec<_> = app
+            .state::<HistoryState>()
+            .0
+            .lock()
+            .unwrap()
+            .batches()
+            .iter()
+            .filter(|b| !cutoff.is_some_and(|cutoff| b.applied_at < cutoff))
+            .cloned()
+            .collect();
+        db.import(&batches).map_err(|e| e.to_string())?;
+        Ok(HistoryDbState(Mutex::new(db)))
+    }
+}
+
+/// The audit log, `audit.jsonl` in the data folder, if it is on.
+fn audit_log(app: &AppHandle) -> Option<AuditLog> {
+    if !app.state::<SettingsState>().audit_log() {
+        return None;
+    }
+    match paths::data_dir(app) {
+        Ok(dir) => Some(AuditLog::new(dir.join("audit.jsonl"))),
+        Err(e) => {
+            log::error!("no folder for the audit log: {}", e);
+            None
+        }
+    }
+}
+
+/// Records a finished batch in the undo history, the history database and,
+/// if it is on, the audit log. Failures are only logged: the files have been
+/// renamed either way.
+pub(crate) fn record_batch(app: &AppHandle, report: &ApplyReport, preset: Option<&str>) {
+    let history = app.state::<HistoryState>();
+    if let Err(e) = history.0.lock().unwrap().record(report) {
+        log::error!("failed to record batch {}: {}", report.batch_id, e);
+    }
+    let archived = app
+        .state::<HistoryDbState>()
+        .0
+        .lock()
+        .unwrap()
+        .record(report, preset);
+    if let Err(e) = archived {
+        log::error!("failed to archive batch {}: {}", report.batch_id, e);
+    }
+    if let Some(Err(e)) = audit_log(app).map(|log| log.record(report)) {
+        log::error!("failed to audit batch {}: {}", report.batch_id, e);
+    }
+    move_cached(
+        app,
+        report
+            .outcomes
+            .iter()
+            .filter(|o| o.status == OutcomeStatus::Renamed)
+            .map(|o| (o.source.as_path(), o.target.as_path())),
+    );
+}
+
+#[derive(Clone, Serialize)]
+struct PostActionFinished {
+    batch_id: Uuid,
+    preset: String,
+    runs: Vec<PostActionRun>,
+}
+
+/// Runs the post action of the preset called `preset`, if it has one, on
+/// what `report` renamed, in the background, then has the history take the
+/// files as the action left them; the runs are emitted as
+/// `post-action-finished`. `cancel_job` with the job id
+/// `post-action-<batch id>` stops it.
+pub(crate) fn run_post_action(app: &AppHandle, report: &ApplyReport, preset: &str) {
+    let action = app
+        .state::<PresetState>()
+        .0
+        .lock()
+        .unwrap()
+        .get(preset)
+        .and_then(|p| p.post_action.clone());
+    let Some(action) = action else {
+        return;
+    };
+    let job = match jobs::register(app, Some(format!("post-action-{}", report.batch_id))) {
+        Ok(job) => job,
+        Err(e) => {
+            log::error!("post action of {} not run: {}", preset, e);
+            return;
+        }
+    };
+    let app = app.clone();
+    let report = report.clone();
+    let preset = preset.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        let runs = post_action::run(&action, &report, &job.token);
+        let history = app.state::<HistoryState>();
+        if let Err(e) = history.0.lock().unwrap().fingerprint_again(report.batch_id) {
+            log::error!("failed to record batch {}: {}", report.batch_id, e);
+        }
+        for run in runs.iter().filter(|r| !r.succeeded()) {
+            log::warn!(
+                "post action of {} failed{}: {}",
+                preset,
+                run.path
+                    .as_ref()
+                    .map(|p| format!(" for {}", p.display()))
+                    .unwrap_or_default(),
+                run.error.as_deref().unwrap_or(run.stderr.trim())
+            );
+        }
+        let _ = app.emit(
+            "post-action-finished",
+            PostActionFinished {
+                batch_id: report.batch_id,
+                preset,
+                runs,
+            },
+        );
+    });
+}
+
+fn archive_replay(app: &AppHandle, report: &ReplayReport, undo: bool) {
+    let archived = app
+        .state::<HistoryDbState>()
+        .0
+        .lock()
+        .unwrap()
+        .record_replay(report, undo);
+    if let Err(e) = archived {
+        log::error!("failed to archive replay of {}: {}", report.batch_id, e);
+    }
+    if let Some(Err(e)) = audit_log(app).map(|log| log.record_replay(report, undo)) {
+        log::error!("failed to audit replay of {}: {}", report.batch_id, e);
+    }
+    // Batches are staged by the engine; moving them back is staged here.
+    if app.state::<SettingsState>().apply_options().git {
+        for repo in renamer_core::git::stage_replay(report, undo) {
+            if let Some(e) = repo.error {
+                log::error!("failed to stage replay in {}: {}", repo.root.display(), e);
+            }
+        }
+    }
+    move_cached(
+        app,
+        report
+            .outcomes
+            .iter()
+            .filter(|o| o.status == ReplayStatus::Done)
+            .map(|o| (o.from.as_path(), o.to.as_path())),
+    );
+}
+
+/// Hashes and metadata already read, in `file-cache.db` in the app's cache
+/// folder, so reopening a folder does not read every file again.
+pub struct FileCacheState(pub Arc<FileCache>);
+
+impl FileCacheState {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let path = paths::cache_dir(app)
+            .map_err(|e| e.to_string())?
+            .join("file-cache.db");
+        let cache = FileCache::open(path).map_err(|e| e.to_string())?;
+        Ok(FileCacheState(Arc::new(cache)))
+    }
+}
+
+pub(crate) fn file_cache(app: &AppHandle) -> Arc<FileCache> {
+    app.state::<FileCacheState>().0.clone()
+}
+
+/// Moves the cache entries of renamed files to their new names. Failures
+/// are only logged: the files are read again next time.
+fn move_cached<'a>(app: &AppHandle, moves: impl IntoIterator<Item = (&'a Path, &'a Path)>) {
+    if let Err(e) = app.state::<FileCacheState>().0.moved(moves) {
+        log::warn!("failed to update the file cache: {}", e);
+    }
+}
+
+/// Drops every cached hash and metadata value.
+#[tauri::command]
+pub fn clear_file_cache(cache: State<FileCacheState>) -> Result<(), CommandError> {
+    cache.0.clear().map_err(CommandError::from)
+}
+
+pub struct PresetState(pub Mutex<PresetStore>);
+
+impl PresetState {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let path = paths::data_dir(app)
+            .map_err(|e| e.to_string())?
+            .join("presets.json");
+        let store = PresetStore::open(path).map_err(|e| e.to_string())?;
+        Ok(PresetState(Mutex::new(store)))
+    }
+}
+
+/// Plugins live in `plugins/` under the app data folder; which are enabled is
+/// kept in `plugins.json` next to it.
+pub struct PluginState(pub Mutex<PluginHost>);
+
+impl PluginState {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let dir = paths::data_dir(app).map_err(|e| e.to_string())?;
+        let host = PluginHost::open(dir.join("plugins"), dir.join("plugins.json"))
+            .map_err(|e| e.to_string())?;
+        Ok(PluginState(Mutex::new(host)))
+    }
+}
+
+pub(crate) fn journal_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    paths::data_dir(app)
+        .map(|dir| dir.join("journal"))
+        .map_err(|e| e.to_string())
+}
+
+/// Computes new names for `paths` without writing anything, so the preview can
+/// never mutate files. Rows and counters follow the natural order of the
+/// paths, unless `keep_order` is set for a list the user ordered by hand.
+/// Names edited by hand, by source path in `overrides`, take the place of
+/// the rules'; the webview keeps them in the session so previewing again
+/// does not lose them. With `bursts`, shots of one moment are numbered
+/// once, and each row tells which burst it is in. `locale`, a preset's own,
+/// takes the place of the settings' for month names and numbers. Background
+/// jobs make way while the preview is computed.
+#[tauri::command]
+pub async fn preview_renames(
+    app: AppHandle,
+    mut paths: Vec<PathBuf>,
+    rules: Vec<Rule>,
+    keep_order: Option<bool>,
+    overrides: Option<HashMap<PathBuf, String>>,
+    bursts: Option<BurstOptions>,
+    locale: Option<String>,
+) -> Result<Vec<PreviewRow>, CommandError> {
+    scope::check(&app, &paths)?;
+    if !keep_order.unwrap_or(false) {
+        renamer_core::sort_natural(&mut paths);
+    }
+    let locale = locale
+        .as_deref()
+        .map(renamer_core::locale::parse)
+        .transpose()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let bursts = bursts.unwrap_or_default();
+        let mut rows = renamer_core::throttle::interactive(|| {
+            renamer_core::locale::scoped(locale, || {
+                renamer_core::preview_bursts(&paths, &rules, &bursts)
+            })
+        })?;
+        renamer_core::apply_overrides(&mut rows, &overrides.unwrap_or_default());
+        Ok::<_, renamer_core::Error>(rows)
+    })
+    .await?
+    .map_err(CommandError::from)
+}
+
+/// Runs a single regex transform over `names`, compiling the pattern once.
+#[tauri::command]
+pub async fn apply_regex_rule(
+    names: Vec<String>,
+    rule: RegexRule,
+) -> Result<Vec<String>, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let pipeline = Pipeline::new(&[Rule::Regex(rule)])?;
+        Ok(names
+            .iter()
+            .enumerate()
+            .map(|(i, n)| pipeline.apply(n, &FileContext::new(Path::new(n), i)))
+            .collect())
+    })
+    .await?
+}
+
+/// Size, times, the read-only flag and, on Unix, permissions and owner of
+/// each of `paths`, in the order given, in one round trip.
+#[tauri::command]
+pub async fn stat_files(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<FileStat>, CommandError> {
+    scope::check(&app, &paths)?;
+    tauri::async_runtime::spawn_blocking(move || renamer_core::stat_files(&paths))
+        .await
+        .map_err(CommandError::from)
+}
+
+/// The top of the git work tree `path` is in, so the UI can offer to
+/// rename through the git index there; `None` outside one or without git.
+#[tauri::command]
+pub async fn git_work_tree(app: AppHandle, path: PathBuf) -> Result<Option<PathBuf>, CommandError> {
+    scope::check(&app, [&path])?;
+    tauri::async_runtime::spawn_blocking(move || renamer_core::git::work_tree(&path))
+        .await
+        .map_err(CommandError::from)
+}
+
+const DEFAULT_THUMBNAIL: u32 = 256;
+
+#[derive(Serialize)]
+pub struct ThumbnailData {
+    /// A `data:` URL the webview can use as an image source.
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A preview of the image at `path` at most `size` pixels (default 256) on
+/// either side. Previews are cached in the app's cache folder; background
+/// jobs make way while one is made.
+#[tauri::command]
+pub async fn get_thumbnail(
+    app: AppHandle,
+    path: PathBuf,
+    size: Option<u32>,
+) -> Result<ThumbnailData, CommandError> {
+    scope::check(&app, [&path])?;
+    let cache = paths::cache_dir(&app)?.join("thumbnails");
+    tauri::async_runtime::spawn_blocking(move || {
+        let size = size.unwrap_or(DEFAULT_THUMBNAIL);
+        let thumb =
+            renamer_core::throttle::interactive(|| renamer_core::thumbnail(&path, size, &cache))?;
+        let bytes = std::fs::read(&thumb.path)?;
+        Ok(ThumbnailData {
+            data_url: format!("data:{};base64,{}", thumb.media_type, BASE64.encode(bytes)),
+            width: thumb.width,
+            height: thumb.height,
+        })
+    })
+    .await?
+}
+
+/// Reads EXIF and similar properties of `paths` in parallel, in the order
+/// given, so the UI can show what `{exif.*}` tokens will produce. Files read
+/// before and unchanged since come from the file cache. Progress is emitted
+/// as `job-progress` events carrying `job_id`.
+#[tauri::command]
+pub async fn extract_metadata(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+    job_id: Option<String>,
+) -> Result<Vec<Metadata>, CommandError> {
+    scope::check(&app, &paths)?;
+    let reporter = ProgressReporter::new(&app, job_id, ProgressKind::Metadata);
+    let cache = file_cache(&app);
+    tauri::async_runtime::spawn_blocking(move || {
+        let total = paths.len() as u64;
+        renamer_core::extract_all_cached(&paths, &cache, &|done, path| {
+            reporter.report(done as u64, Some(total), Some(path), None);
+        })
+    })
+    .await
+    .map_err(CommandError::from)
+}
+
+/// Hashes the contents of `paths` in parallel, in the order given, taking
+/// files unchanged since they were last hashed from the file cache. Progress
+/// is emitted as `job-progress` events; with a `job_id`, `cancel_job` stops
+/// it and only the files hashed so far are returned.
+#[tauri::command]
+pub async fn hash_files(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+    algorithm: HashAlgorithm,
+    job_id: Option<String>,
+) -> Result<Vec<FileHash>, CommandError> {
+    scope::check(&app, &paths)?;
+    let job = jobs::register(&app, job_id)?;
+    let reporter = job.reporter(ProgressKind::Hash);
+    let cache = file_cache(&app);
+    tauri::async_runtime::spawn_blocking(move || {
+        renamer_core::hash_files_cached(
+            &paths,
+            algorithm,
+            &cache,
+            &|p| {
+                reporter.report(
+                    p.completed as u64,
+                    Some(p.total as u64),
+                    Some(&p.current),
+                    Some(p.bytes),
+                );
+            },
+            &job.token,
+        )
+    })
+    .await
+    .map_err(CommandError::from)
+}
+
+/// Extractions started with a job id, so the files scrolled into view can
+/// be moved to the front.
+#[derive(Default)]
+pub struct ExtractState(pub Mutex<HashMap<String, ExtractQueue>>);
+
+/// Minimum time between two `extracted` events of one extraction.
+const EXTRACTED_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Serialize)]
+struct ExtractedBatch {
+    job_id: Option<String>,
+    results: Vec<Extracted>,
+}
+
+/// Reads metadata and hashes of `paths` on `options.threads` threads,
+/// `priority` first, for the preview columns, using the file cache. Results are sent as they come
+/// in as `extracted` events, at most every 100 ms, and the count as
+/// `job-progress`; the command resolves with how many files were read. With
+/// a `job_id`, `prioritize_extraction` moves files to the front while it
+/// runs and `cancel_job` stops it.
+#[tauri::command]
+pub async fn extract_files(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+    options: Option<ExtractOptions>,
+    priority: Option<Vec<PathBuf>>,
+    job_id: Option<String>,
+) -> Result<usize, CommandError> {
+    scope::check(&app, &paths)?;
+    let options = options.unwrap_or_default();
+    let job = jobs::register(&app, job_id.clone())?;
+    let kind = if options.metadata {
+        ProgressKind::Metadata
+    } else {
+        ProgressKind::Hash
+    };
+    let reporter = job.reporter(kind);
+    let total = paths.len() as u64;
+    let queue = ExtractQueue::with_cache(paths, file_cache(&app));
+    queue.prioritize(&priority.unwrap_or_default());
+    if let Some(id) = &job_id {
+        let state = app.state::<ExtractState>();
+        state.0.lock().unwrap().insert(id.clone(), queue.clone());
+    }
+    let read = tauri::async_runtime::spawn_blocking({
+        let app = app.clone();
+        let job_id = job_id.clone();
+        move || {
+            let emit = |results: Vec<Extracted>| {
+                let job_id = job_id.clone();
+                let _ = app.emit("extracted", ExtractedBatch { job_id, results });
+            };
+            let done = AtomicUsize::new(0);
+            let buffer: Mutex<(Instant, Vec<Extracted>)> = Mutex::new((Instant::now(), Vec::new()));
+            let read = queue.run(
+                &options,
+                &|extracted| {
+                    let done = done.fetch_add(1, Ordering::Relaxed) as u64 + 1;
+                    reporter.report(done, Some(total), Some(&extracted.path), None);
+                    let mut buffer = buffer.lock().unwrap();
+                    buffer.1.push(extracted);
+                    if buffer.0.elapsed() >= EXTRACTED_INTERVAL {
+                        buffer.0 = Instant::now();
+                        emit(std::mem::take(&mut buffer.1));
+                    }
+                },
+                &job.token,
+            );
+            let rest = buffer.into_inner().unwrap().1;
+            if !rest.is_empty() {
+                emit(rest);
+            }
+            read
+        }
+    })
+    .await
+    .map_err(CommandError::from);
+    if let Some(id) = &job_id {
+        app.state::<ExtractState>().0.lock().unwrap().remove(id);
+    }
+    read
+}
+
+/// Moves `paths` to the front of a running extraction, for the rows that
+/// just scrolled into view. Returns false if no such extraction is running.
+#[tauri::command]
+pub fn prioritize_extraction(
+    extractions: State<ExtractState>,
+    job_id: String,
+    paths: Vec<PathBuf>,
+) -> bool {
+    match extractions.0.lock().unwrap().get(&job_id) {
+        Some(queue) => {
+            queue.prioritize(&paths);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Groups `paths` whose contents are identical, so the webview can skip the
+/// copies or give them a suffix. `options` set the rounds files are compared
+/// in; by default size, then a quick hash of both ends, then SHA-256. Each
+/// round reports its progress as `job-progress` events of kind `dedupe`
+/// with the round as `stage`. With a `job_id`, `cancel_job` stops the
+/// search and only groups confirmed so far are returned.
+#[tauri::command]
+pub async fn find_duplicates(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+    options: Option<DedupeOptions>,
+    job_id: Option<String>,
+) -> Result<Vec<DuplicateGroup>, CommandError> {
+    scope::check(&app, &paths)?;
+    let job = jobs::register(&app, job_id)?;
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let reporter: Mutex<Option<ProgressReporter>> = Mutex::default();
+        renamer_core::find_duplicates_with_progress(
+            &paths,
+            &options,
+            &|p| {
+                let mut reporter = reporter.lock().unwrap();
+                let reporter = match &mut *reporter {
+                    Some(r) if r.stage() == Some(p.stage) => r,
+                    slot => slot.insert(job.reporter(ProgressKind::Dedupe).in_stage(p.stage)),
+                };
+                reporter.report(
+                    p.completed as u64,
+                    Some(p.total as u64),
+                    Some(&p.current),
+                    Some(p.bytes),
+                );
+            },
+            &job.token,
+        )
+    })
+    .await
+    .map_err(CommandError::from)
+}
+
+/// Renders `template` for each of `paths` with the same engine the preview and
+/// apply pipeline use.
+#[tauri::command]
+pub async fn render_template(
+    app: AppHandle,
+    template: String,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<String>, CommandError> {
+    scope::check(&app, &paths)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let template = Template::parse(&template)?;
+        Ok(renamer_core::batch_contexts(&paths)
+            .into_iter()
+            .map(|mut ctx| {
+                if template.needs_metadata() {
+                    ctx = ctx.with_metadata();
+                }
+                if template.needs_embedded() {
+                    ctx = ctx.with_embedded();
+                }
+                ctx = ctx.with_hashes(&template.hash_algorithms());
+                let name = ctx
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                template.render(&name, &ctx)
+            })
+            .collect())
+    })
+    .await?
+}
+
+/// Reads a CSV or TSV file of `old, new` rows and matches it against `files`,
+/// the scanned files. The ops go to `plan_renames` or `apply_renames`; rows
+/// that match no file, or several, come back as problems.
+#[tauri::command]
+pub async fn import_mapping(
+    app: AppHandle,
+    path: PathBuf,
+    files: Vec<PathBuf>,
+) -> Result<Mapping, CommandError> {
+    scope::check(&app, std::iter::once(&path).chain(&files))?;
+    tauri::async_runtime::spawn_blocking(move || renamer_core::mapping::read(&path, &files))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Checks `ops` against the filesystem and annotates collisions resolved
+/// according to `options` (skip, suffix, overwrite or fail).
+#[tauri::command]
+pub async fn plan_renames(
+    app: AppHandle,
+    ops: Vec<RenameOp>,
+    options: Option<PlanOptions>,
+) -> Result<RenamePlan, CommandError> {
+    scope::check_ops(&app, &ops)?;
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || renamer_core::plan(ops, &options))
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Plans `ops` and checks, without renaming anything, that every folder and
+/// file involved can be changed now. Run before `apply_renames`; a blocked
+/// report lists what would make the batch fail part way.
+#[tauri::command]
+pub async fn preflight_renames(
+    app: AppHandle,
+    ops: Vec<RenameOp>,
+    options: Option<PlanOptions>,
+) -> Result<PreflightReport, CommandError> {
+    scope::check_ops(&app, &ops)?;
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        renamer_core::preflight(&renamer_core::plan(ops, &options))
+    })
+    .await
+    .map_err(CommandError::from)
+}
+
+/// Re-plans `ops` and applies them as one transaction: if any rename fails the
+/// already-renamed files are moved back and the report says which rows
+/// succeeded, failed, or were rolled back. Committed batches go into the undo
+/// history. Plans that replace existing files need
+/// `apply_options.confirm_overwrite`. Progress is emitted as `job-progress`
+/// events while the batch runs; with a `job_id`, `cancel_job` stops it and the
+/// report lists what was renamed up to that point. `preset` names the preset
+/// the names came from, for the history; files outside its allowed scope
+/// fail the whole batch. In safe mode the batch is refused whatever
+/// `apply_options` say.
+#[tauri::command]
+pub async fn apply_renames(
+    app: AppHandle,
+    ops: Vec<RenameOp>,
+    plan_options: Option<PlanOptions>,
+    apply_options: Option<ApplyOptions>,
+    job_id: Option<String>,
+    preset: Option<String>,
+) -> Result<ApplyReport, CommandError> {
+    scope::check_ops(&app, &ops)?;
+    scope::check_preset(&app, preset.as_deref(), &ops)?;
+    let journal_dir = journal_dir(&app)?;
+    let job = jobs::register(&app, job_id.clone())?;
+    let plan_options = plan_options.unwrap_or_default();
+    let apply_options = app
+        .state::<SettingsState>()
+        .enforce(apply_options.unwrap_or_default());
+    let reporter = job.reporter(ProgressKind::Apply);
+    // Bytes copied across volumes by earlier files, and the file being
+    // copied with how far it got, for the throughput.
+    let copied: Mutex<(u64, PathBuf, u64)> = Mutex::default();
+    let (plan, applied) = tauri::async_runtime::spawn_blocking(move || {
+        let plan = renamer_core::plan(ops, &plan_options);
+        let applied = renamer_core::apply_with_progress(
+            &plan,
+            &apply_options,
+            &journal_dir,
+            &|p| {
+                let bytes = {
+                    let mut copied = copied.lock().unwrap();
+                    if copied.1 != p.current {
+                        copied.0 += copied.2;
+                        copied.1 = p.current.clone();
+                        copied.2 = 0;
+                    }
+                    copied.2 = copied.2.max(p.copied_bytes);
+                    copied.0 + copied.2
+                };
+                reporter.report(
+                    p.completed as u64,
+                    Some(p.total as u64),
+                    Some(&p.current),
+                    (bytes > 0).then_some(bytes),
+                );
+            },
+            &job.token,
+        );
+        (plan, applied)
+    })
+    .await?;
+    let report = match applied {
+        Ok(report) => report,
+        Err(e) => {
+            announce::failed(&app, job_id, ProgressKind::Apply, &e.to_string());
+            diagnostics::batch_failed(&app, plan, None, Some(e.to_string()));
+            return Err(e.into());
+        }
+    };
+    announce::batch_finished(&app, job_id, &report);
+    if report.failed > 0 || report.rollback_failed > 0 || !report.committed {
+        diagnostics::batch_failed(&app, plan, Some(report.clone()), None);
+    }
+
+    record_batch(&app, &report, preset.as_deref());
+    notify::batch_finished(&app, &report, None);
+    notify::webhooks(&app, &report, WebhookEvent::Batch, preset.as_deref());
+    if let Some(preset) = &preset {
+        run_post_action(&app, &report, preset);
+    }
+    if let Some(preset) = preset.filter(|_| report.committed) {
+        app.state::<SettingsState>()
+            .update(|settings| settings.last_preset = Some(preset));
+        #[cfg(desktop)]
+        tray::refresh(&app);
+    }
+    Ok(report)
+}
+
+/// Writes `plan`, as it would run, or `report`, the results of a batch, to
+/// `path` as CSV or JSON; `path` usually comes from `pick_save_path`.
+#[tauri::command]
+pub async fn export_report(
+    app: AppHandle,
+    path: PathBuf,
+    format: ExportFormat,
+    plan: Option<RenamePlan>,
+    report: Option<ApplyReport>,
+) -> Result<(), CommandError> {
+    scope::check(&app, [&path])?;
+    tauri::async_runtime::spawn_blocking(move || {
+        match (plan, report) {
+            (Some(plan), None) => renamer_core::export::export_plan(&plan, format, &path),
+            (None, Some(report)) => renamer_core::export::export_batch(&report, format, &path),
+            _ => return Err("pass either a plan or a report".into()),
+        }
+        .map_err(CommandError::from)
+    })
+    .await?
+}
+
+/// Writes `plan` to `path` as a CSV file for someone to check and edit the
+/// new names in; `import_review` reads it back.
+#[tauri::command]
+pub async fn export_review(
+    app: AppHandle,
+    path: PathBuf,
+    plan: RenamePlan,
+) -> Result<(), CommandError> {
+    scope::check(&app, [&path])?;
+    tauri::async_runtime::spawn_blocking(move || renamer_core::review::export(&plan, &path))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Reads a file `export_review` wrote, after someone edited it, and matches
+/// its rows to the files of `plan`, the plan it was written from. The ops go
+/// to `plan_renames` or `apply_renames`; changes say which names were kept,
+/// edited or removed, and rows that match no file come back as problems.
+#[tauri::command]
+pub async fn import_review(
+    app: AppHandle,
+    path: PathBuf,
+    plan: RenamePlan,
+) -> Result<Review, CommandError> {
+    scope::check(&app, [&path])?;
+    scope::check(
+        &app,
+        plan.entries.iter().flat_map(|e| [&e.source, &e.target]),
+    )?;
+    tauri::async_runtime::spawn_blocking(move || renamer_core::review::read(&path, &plan))
+        .await?
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_rename_history(history: State<HistoryState>) -> Vec<BatchRecord> {
+    history.0.lock().unwrap().batches().to_vec()
+}
+
+/// Returns `None` when there is nothing left to undo.
+#[tauri::command]
+pub async fn undo_last_batch(app: AppHandle) -> Result<Option<ReplayReport>, CommandError> {
+    app.state::<SettingsState>().check_safe_mode()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let history = app.state::<HistoryState>();
+        let report = history.0.lock().unwrap().undo_last();
+        let report = report?;
+        if let Some(report) = &report {
+            archive_replay(&app, report, true);
+        }
+        Ok(report)
+    })
+    .await?
+}
+
+/// Moves back only the files `file_ids` of `batch_id`, after checking each
+/// is still as the batch left it. Returns `None` when there is no such
+/// batch in the history.
+#[tauri::command]
+pub async fn undo_files(
+    app: AppHandle,
+    batch_id: Uuid,
+    file_ids: Vec<usize>,
+) -> Result<Option<ReplayReport>, CommandError> {
+    app.state::<SettingsState>().check_safe_mode()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let history = app.state::<HistoryState>();
+        let report = history.0.lock().unwrap().undo_files(batch_id, &file_ids);
+        let report = report?;
+        if let Some(report) = &report {
+            archive_replay(&app, report, true);
+        }
+        Ok(report)
+    })
+    .await?
+}
+
+/// Re-applies `batch_id`, or the most recently undone batch when omitted.
+#[tauri::command]
+pub async fn redo_batch(
+    app: AppHandle,
+    batch_id: Option<Uuid>,
+) -> Result<Option<ReplayReport>, CommandError> {
+    app.state::<SettingsState>().check_safe_mode()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let history = app.state::<HistoryState>();
+        let report = history.0.lock().unwrap().redo(batch_id);
+        let report = report?;
+        if let Some(report) = &report {
+            archive_replay(&app, report, false);
+        }
+        Ok(report)
+    })
+    .await?
+}
+
+/// Batches a crash or power loss cut off, oldest first. The app asks at
+/// startup whether to resume or roll back each of them.
+#[tauri::command]
+pub fn get_incomplete_batches(app: AppHandle) -> Result<Vec<Journal>, CommandError> {
+    Journal::incomplete(&journal_dir(&app)?).map_err(CommandError::from)
+}
+
+/// Makes the moves of an interrupted batch that did not happen yet.
+#[tauri::command]
+pub async fn resume_batch(app: AppHandle, batch_id: Uuid) -> Result<ReplayReport, CommandError> {
+    recover_batch(app, batch_id, Recovery::Resume).await
+}
+
+/// Moves back what an interrupted batch had moved.
+#[tauri::command]
+pub async fn rollback_batch(app: AppHandle, batch_id: Uuid) -> Result<ReplayReport, CommandError> {
+    recover_batch(app, batch_id, Recovery::Rollback).await
+}
+
+async fn recover_batch(
+    app: AppHandle,
+    batch_id: Uuid,
+    how: Recovery,
+) -> Result<ReplayReport, CommandError> {
+    app.state::<SettingsState>().check_safe_mode()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let dir = journal_dir(&app)?;
+        let journals = Journal::incomplete(&dir)?;
+        let journal = journals
+            .iter()
+            .find(|j| j.batch_id == batch_id)
+            .ok_or_else(|| {
+                CommandError::new(
+                    AppErrorCode::NotAvailable,
+                    format!("no interrupted batch {}", batch_id),
+                )
+                .with("batch_id", batch_id)
+            })?;
+        Ok(journal.recover(&dir, how)?)
+    })
+    .await?
+}
+
+/// Changes, newest first, whose old or new path contains `query`: "what did
+/// this file used to be called?".
+#[tauri::command]
+pub fn search_history(
+    db: State<HistoryDbState>,
+    query: String,
+    date_range: Option<DateRange>,
+) -> Result<Vec<FileChange>, CommandError> {
+    db.0.lock()
+        .unwrap()
+        .search(&query, date_range.unwrap_or_default())
+        .map_err(CommandError::from)
+}
+
+/// What the history database adds up to in `date_range`: renames and
+/// failures by day, by preset and by folder, for the statistics page.
+#[tauri::command]
+pub fn history_stats(
+    db: State<HistoryDbState>,
+    date_range: Option<DateRange>,
+) -> Result<HistoryStats, CommandError> {
+    db.0.lock()
+        .unwrap()
+        .stats(date_range.unwrap_or_default())
+        .map_err(CommandError::from)
+}
+
+/// Checks that the audit log has not been edited since it was written; see
+/// `renamer_core::audit`. Works whether or not the log is on now.
+#[tauri::command]
+pub async fn verify_audit_log(app: AppHandle) -> Result<AuditVerification, CommandError> {
+    let log = AuditLog::new(paths::data_dir(&app)?.join("audit.jsonl"));
+    tauri::async_runtime::spawn_blocking(move || log.verify())
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Returns `None` for a batch the history database does not know.
+#[tauri::command]
+pub fn get_batch(
+    db: State<HistoryDbState>,
+    batch_id: Uuid,
+) -> Result<Option<ArchivedBatch>, CommandError> {
+    db.0.lock()
+        .unwrap()
+        .batch(batch_id)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_presets(presets: State<PresetState>) -> Vec<Preset> {
+    presets.0.lock().unwrap().presets().to_vec()
+}
+
+/// Stores `preset`, replacing any preset with the same name.
+#[tauri::command]
+pub fn save_preset(presets: State<PresetState>, preset: Preset) -> Result<(), CommandError> {
+    presets
+        .0
+        .lock()
+        .unwrap()
+        .insert(preset)
+        .map_err(CommandError::from)
+}
+
+/// Returns false when there was no preset called `name`.
+#[tauri::command]
+pub fn delete_preset(presets: State<PresetState>, name: String) -> Result<bool, CommandError> {
+    presets
+        .0
+        .lock()
+        .unwrap()
+        .remove(&name)
+        .map_err(CommandError::from)
+}
+
+#[derive(Serialize)]
+pub struct PluginList {
+    pub plugins: Vec<PluginInfo>,
+    /// Files in the plugin folder that could not be loaded.
+    pub errors: Vec<PluginError>,
+}
+
+/// Loads the plugin folder again and lists what is in it.
+#[tauri::command]
+pub fn list_plugins(plugins: State<PluginState>) -> Result<PluginList, CommandError> {
+    let mut host = plugins.0.lock().unwrap();
+    host.reload()?;
+    Ok(PluginList {
+        plugins: host.plugins(),
+        errors: host.errors().to_vec(),
+    })
+}
+
+/// Returns false when there is no plugin called `name`.
+#[tauri::command]
+pub fn enable_plugin(plugins: State<PluginState>, name: String) -> Result<bool, CommandError> {
+    plugins
+        .0
+        .lock()
+        .unwrap()
+        .set_enabled(&name, true)
+        .map_err(CommandError::from)
+}
+
+/// Returns false when there is no plugin called `name`.
+#[tauri::command]
+pub fn disable_plugin(plugins: State<PluginState>, name: String) -> Result<bool, CommandError> {
+    plugins
+        .0
+        .lock()
+        .unwrap()
+        .set_enabled(&name, false)
+        .map_err(CommandError::from)
+}
+
+/// Previews `paths` with the preset called `name`, as the CLI does: the
+/// preset picks which of them it renames, sends them to its target folder
+/// and fails on any outside its scope, or whose new place is.
+#[tauri::command]
+pub async fn apply_preset(
+    app: AppHandle,
+    name: String,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<PreviewRow>, CommandError> {
+    scope::check(&app, &paths)?;
+    let preset = app
+        .state::<PresetState>()
+        .0
+        .lock()
+        .unwrap()
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| CommandError::unknown_preset(&name))?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let paths = preset.select(&paths)?;
+        let mut rows = preset.localized(|| renamer_core::preview(&paths, &preset.rules))??;
+        rows.iter_mut().for_each(|row| preset.place(row));
+        preset.check_scope(
+            rows.iter()
+                .filter(|row| row.changed)
+                .map(|row| row.target.as_path()),
+        )?;
+        Ok::<_, renamer_core::Error>(rows)
+    })
+    .await?
+    .map_err(CommandError::from)
+}
+
+/// How the preset called `name` would do on what scanning `root` with
+/// `scan` lists, applied with `options` to a stand-in for the folder rather
+/// than the folder; see `renamer_core::simulate`. Nothing is renamed, so it
+/// works in safe mode too.
+#[tauri::command]
+pub async fn simulate_preset(
+    app: AppHandle,
+    name: String,
+    root: PathBuf,
+    scan: Option<ScanOptions>,
+    options: ApplyOptions,
+) -> Result<Option<Simulation>, CommandError> {
+    scope::check(&app, [&root])?;
+    let preset = app
+        .state::<PresetState>()
+        .0
+        .lock()
+        .unwrap()
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| CommandError::unknown_preset(&name))?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = Snapshot::scan(root, scan.unwrap_or_default())?;
+        renamer_core::simulate(&snapshot, &preset, &options)
+    })
+    .await?
+    .map_err(CommandError::from)
+}
+
+/// What the presets called `preset_a` and `preset_b` would each do with
+/// `paths`, file by file, without renaming anything; see
+/// `renamer_core::compare_presets`.
+#[tauri::command]
+pub async fn compare_presets(
+    app: AppHandle,
+    preset_a: String,
+    preset_b: String,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<PresetComparison>, CommandError> {
+    scope::check(&app, &paths)?;
+    let (a, b) = {
+        let presets = app.state::<PresetState>();
+        let presets = presets.0.lock().unwrap();
+        let get = |name: &str| {
+            presets
+                .get(name)
+                .cloned()
+                .ok_or_else(|| CommandError::unknown_preset(name))
+        };
+        (get(&preset_a)?, get(&preset_b)?)
+    };
+    tauri::async_runtime::spawn_blocking(move || renamer_core::compare_presets(&a, &b, &paths))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// A directory walk and the entries it listed so far.
+pub struct OpenScan {
+    pub(crate) listing: Arc<Mutex<ScanListing>>,
+    cancel: CancelToken,
+}
+
+/// Scans whose entries the webview can still query with [`query_scan`].
+#[derive(Default)]
+pub struct ScanState(pub Mutex<HashMap<Uuid, OpenScan>>);
+
+const DEFAULT_SCAN_PAGE: usize = 1000;
+
+#[derive(Clone, Serialize)]
+struct ScanBatch {
+    scan_id: Uuid,
+    /// Entries listed so far, including these.
+    total: usize,
+    #[serde(flatten)]
+    page: ScanPage,
+}
+
+/// Reports `listed` entries after `page`; the total is known once the walk
+/// is done, and then the end is announced.
+fn report_page(reporter: &ProgressReporter, listed: usize, page: &ScanPage) {
+    let current = page.entries.last().map(|e| e.path.as_path());
+    let total = page.done.then_some(listed as u64);
+    reporter.report(listed as u64, total, current, None);
+    if page.done {
+        reporter.finished(listed as u64);
+    }
+}
+
+/// Starts walking `root` in the background and returns its scan id at once,
+/// leaving out what `.renamerignore` files and `options.ignore` exclude.
+/// Entries are streamed as `scan-batch` events of `page_size` entries, the
+/// last one reporting `done`, and kept so `query_scan` can hand out sorted
+/// and filtered windows of them; the count is also reported as
+/// `job-progress`. With a `job_id`, `cancel_job` ends the scan at the next
+/// entry.
+#[tauri::command]
+pub fn scan_directory(
+    app: AppHandle,
+    root: PathBuf,
+    options: Option<ScanOptions>,
+    page_size: Option<usize>,
+    job_id: Option<String>,
+) -> Result<Uuid, CommandError> {
+    scope::check(&app, [&root])?;
+    let job = jobs::register(&app, job_id)?;
+    let mut scanner =
+        Scanner::new(root, options.unwrap_or_default())?.with_cancel(job.token.clone());
+    let scan_id = Uuid::new_v4();
+    let listing = Arc::new(Mutex::new(ScanListing::new()));
+    app.state::<ScanState>().0.lock().unwrap().insert(
+        scan_id,
+        OpenScan {
+            listing: listing.clone(),
+            cancel: job.token.clone(),
+        },
+    );
+    let page_size = page_size.unwrap_or(DEFAULT_SCAN_PAGE);
+    let reporter = job.reporter(ProgressKind::Scan);
+    tauri::async_runtime::spawn_blocking(move || {
+        let _job = job;
+        loop {
+            let page = scanner.next_page(page_size);
+            let total = {
+                let mut listing = listing.lock().unwrap();
+                listing.extend(page.entries.iter().cloned());
+                listing.len()
+            };
+            let done = page.done;
+            report_page(&reporter, total, &page);
+            let _ = app.emit(
+                "scan-batch",
+                ScanBatch {
+                    scan_id,
+                    total,
+                    page,
+                },
+            );
+            if done {
+                break;
+            }
+        }
+    });
+    Ok(scan_id)
+}
+
+/// The entries of a scan, in `query`'s order and filtered by it, from
+/// `query.offset`; works while the scan is still running.
+#[tauri::command]
+pub async fn query_scan(
+    app: AppHandle,
+    scan_id: Uuid,
+    query: Option<ScanQuery>,
+) -> Result<ScanWindow, CommandError> {
+    let listing = app
+        .state::<ScanState>()
+        .0
+        .lock()
+        .unwrap()
+        .get(&scan_id)
+        .map(|scan| scan.listing.clone())
+        .ok_or_else(|| CommandError::unknown_scan(scan_id))?;
+    tauri::async_runtime::spawn_blocking(move || {
+        listing.lock().unwrap().query(&query.unwrap_or_default())
+    })
+    .await
+    .map_err(CommandError::from)
+}
+
+/// Stops a scan if it is still running and drops its entries.
+#[tauri::command]
+pub fn close_scan(scans: State<ScanState>, scan_id: Uuid) {
+    if let Some(scan) = scans.0.lock().unwrap().remove(&scan_id) {
+        scan.cancel.cancel();
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct DroppedPage {
+    job_id: Option<String>,
+    #[serde(flatten)]
+    page: ScanPage,
+}
+
+#[derive(Serialize)]
+pub struct DropResolved {
+    /// Entries sent in `dropped-entries` events.
+    pub added: usize,
+    pub cancelled: bool,
+}
+
+/// Resolves paths dropped onto the window (from Tauri's `tauri://drag-drop`
+/// event): files as they are and folders expanded with `options`, the way
+/// `scan_directory` lists them, leaving out everything in `loaded`. Entries
+/// are streamed a page at a time as `dropped-entries` events carrying
+/// `job_id`, and counted in `job-progress` events; with a `job_id`,
+/// `cancel_job` stops the walk.
+#[tauri::command]
+pub async fn resolve_dropped_paths(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+    options: Option<ScanOptions>,
+    loaded: Option<Vec<PathBuf>>,
+    page_size: Option<usize>,
+    job_id: Option<String>,
+) -> Result<DropResolved, CommandError> {
+    scope::check(&app, &paths)?;
+    let job = jobs::register(&app, job_id.clone())?;
+    let mut scanner = DropScanner::new(
+        paths,
+        options.unwrap_or_default(),
+        loaded.unwrap_or_default(),
+    )?
+    .with_cancel(job.token.clone());
+    let page_size = page_size.unwrap_or(DEFAULT_SCAN_PAGE);
+    let reporter = job.reporter(ProgressKind::Scan);
+    tauri::async_runtime::spawn_blocking(move || {
+        let _job = job;
+        let mut added = 0;
+        loop {
+            let page = scanner.next_page(page_size);
+            added += page.entries.len();
+            report_page(&reporter, added, &page);
+            let (done, cancelled) = (page.done, page.cancelled);
+            let _ = app.emit(
+                "dropped-entries",
+                DroppedPage {
+                    job_id: job_id.clone(),
+                    page,
+                },
+            );
+            if done {
+                return DropResolved { added, cancelled };
+            }
+        }
+    })
+    .await
+    .map_err(CommandError::from)
+}
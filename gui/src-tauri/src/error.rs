@@ -0,0 +1,148 @@
+//! The error commands fail with: a code the frontend can translate and
+//! handle errors by, the parameters a translation needs, and the English
+//! message, for logs and for codes a frontend does not know yet.
+//!
+//! Errors from the rename engine keep the code `renamer_core::Error` gives
+//! them; the shell's own have the codes below, and anything it does not
+//! tell apart yet is `other`.
+
+use std::collections::BTreeMap;
+use std::{fmt, io};
+
+use renamer_core::ErrorCode as CoreCode;
+use serde::Serialize;
+
+/// Codes for errors of the shell's own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppErrorCode {
+    /// No preset has the name in the `name` parameter.
+    UnknownPreset,
+    /// The scan, batch or update asked about is gone or never was.
+    NotAvailable,
+    /// A job with that id, or a watch on that folder, is already running.
+    AlreadyRunning,
+    /// The sidecar is not running or did not answer.
+    SidecarUnavailable,
+    /// The user declined to give admin rights, or they could not be asked
+    /// for.
+    ElevationRefused,
+    /// Renames asked to be applied with admin rights that pre-flight does
+    /// not find need them, or that they would not get past; their ids are
+    /// in the `entries` parameter.
+    NotElevatable,
+    /// Not available on this platform.
+    Unsupported,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum ErrorCode {
+    Core(CoreCode),
+    App(AppErrorCode),
+}
+
+/// Serialized as `{"code", "message", "params"}`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub params: BTreeMap<&'static str, String>,
+}
+
+impl CommandError {
+    pub fn new(code: AppErrorCode, message: impl Into<String>) -> Self {
+        CommandError {
+            code: ErrorCode::App(code),
+            message: message.into(),
+            params: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the parameter `name`.
+    pub fn with(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.params.insert(name, value.to_string());
+        self
+    }
+
+    pub fn unknown_preset(name: &str) -> Self {
+        CommandError::new(
+            AppErrorCode::UnknownPreset,
+            format!("no preset named `{}`", name),
+        )
+        .with("name", name)
+    }
+
+    pub fn unknown_scan(scan_id: impl fmt::Display) -> Self {
+        CommandError::new(
+            AppErrorCode::NotAvailable,
+            format!("no open scan {}", scan_id),
+        )
+        .with("scan_id", scan_id)
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<renamer_core::Error> for CommandError {
+    fn from(error: renamer_core::Error) -> Self {
+        CommandError {
+            code: ErrorCode::Core(error.code()),
+            message: error.to_string(),
+            params: error.params(),
+        }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::new(AppErrorCode::Other, message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        CommandError::new(AppErrorCode::Other, message)
+    }
+}
+
+/// Coded by its cause, like `renamer_core::Error::Io`, but without a path.
+impl From<io::Error> for CommandError {
+    fn from(error: io::Error) -> Self {
+        let code = match error.kind() {
+            io::ErrorKind::NotFound => CoreCode::NotFound,
+            io::ErrorKind::PermissionDenied => CoreCode::PermissionDenied,
+            io::ErrorKind::AlreadyExists => CoreCode::AlreadyExists,
+            _ => CoreCode::Io,
+        };
+        CommandError {
+            code: ErrorCode::Core(code),
+            message: error.to_string(),
+            params: BTreeMap::from([("detail", error.to_string())]),
+        }
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(error: serde_json::Error) -> Self {
+        renamer_core::Error::from(error).into()
+    }
+}
+
+#[cfg(desktop)]
+impl From<tauri_plugin_updater::Error> for CommandError {
+    fn from(error: tauri_plugin_updater::Error) -> Self {
+        CommandError::new(AppErrorCode::Other, error.to_string())
+    }
+}
+
+impl From<tauri::Error> for CommandError {
+    fn from(error: tauri::Error) -> Self {
+        CommandError::new(AppErrorCode::Other, error.to_string())
+    }
+}
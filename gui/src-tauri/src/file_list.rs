@@ -0,0 +1,95 @@
+//! The loaded files, held here rather than in the webview, which only asks
+//! for the rows it shows. Files are referred to by the ids the list gives
+//! them.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use renamer_core::{FileList, FileListQuery, FileListWindow, ScanQuery};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::engine::ScanState;
+use crate::error::CommandError;
+use crate::scope;
+
+#[derive(Default)]
+pub struct FileListState(pub Arc<Mutex<FileList>>);
+
+fn list(app: &AppHandle) -> Arc<Mutex<FileList>> {
+    app.state::<FileListState>().0.clone()
+}
+
+/// Adds `paths`, reading each one's size and modification time, and
+/// returns how many were not listed yet.
+#[tauri::command]
+pub async fn add_to_file_list(app: AppHandle, paths: Vec<PathBuf>) -> Result<usize, CommandError> {
+    scope::check(&app, &paths)?;
+    let list = list(&app);
+    tauri::async_runtime::spawn_blocking(move || list.lock().unwrap().add_paths(paths))
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Adds everything a scan listed so far, without sending it through the
+/// webview, and returns how many were not listed yet.
+#[tauri::command]
+pub async fn add_scan_to_file_list(app: AppHandle, scan_id: Uuid) -> Result<usize, CommandError> {
+    let listing = app
+        .state::<ScanState>()
+        .0
+        .lock()
+        .unwrap()
+        .get(&scan_id)
+        .map(|scan| scan.listing.clone())
+        .ok_or_else(|| CommandError::unknown_scan(scan_id))?;
+    let list = list(&app);
+    tauri::async_runtime::spawn_blocking(move || {
+        let entries = listing.lock().unwrap().query(&ScanQuery::default()).entries;
+        list.lock().unwrap().add_entries(entries)
+    })
+    .await
+    .map_err(CommandError::from)
+}
+
+/// Takes files out of the list and returns how many it held.
+#[tauri::command]
+pub fn remove_from_file_list(app: AppHandle, ids: Vec<u64>) -> usize {
+    list(&app).lock().unwrap().remove(&ids)
+}
+
+#[tauri::command]
+pub fn clear_file_list(app: AppHandle) {
+    list(&app).lock().unwrap().clear();
+}
+
+/// A window of the list in `query`'s order, filtered by it.
+#[tauri::command]
+pub async fn query_file_list(
+    app: AppHandle,
+    query: Option<FileListQuery>,
+) -> Result<FileListWindow, CommandError> {
+    let list = list(&app);
+    tauri::async_runtime::spawn_blocking(move || {
+        list.lock().unwrap().query(&query.unwrap_or_default())
+    })
+    .await
+    .map_err(CommandError::from)
+}
+
+/// Selects or deselects files by id, or with `filter` every file whose name
+/// contains it (all files for an empty one). Returns how many are selected.
+#[tauri::command]
+pub fn select_in_file_list(
+    app: AppHandle,
+    ids: Option<Vec<u64>>,
+    filter: Option<String>,
+    selected: bool,
+) -> usize {
+    let list = list(&app);
+    let mut list = list.lock().unwrap();
+    match (ids, filter) {
+        (Some(ids), _) => list.select(&ids, selected),
+        (None, filter) => list.select_matching(&filter.unwrap_or_default(), selected),
+    }
+}
@@ -0,0 +1,34 @@
+//! Importing from memory cards and other removable media; see
+//! `renamer_core::ingest`. Imports themselves run as `ingest` jobs in the
+//! queue, so they can be cancelled and picked up again.
+
+use std::path::PathBuf;
+
+use renamer_core::{ingest, IngestOptions, PreviewRow};
+use tauri::AppHandle;
+
+use crate::error::CommandError;
+use crate::scope;
+
+/// The files under `source` an import would copy, with their targets in
+/// the destination.
+#[tauri::command]
+pub async fn ingest_preview(
+    app: AppHandle,
+    source: PathBuf,
+    options: IngestOptions,
+) -> Result<Vec<PreviewRow>, CommandError> {
+    scope::check(&app, [&source, &options.destination])?;
+    tauri::async_runtime::spawn_blocking(move || ingest::preview(&source, &options))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Ejects the volume `path` is on.
+#[tauri::command]
+pub async fn eject_volume(app: AppHandle, path: PathBuf) -> Result<(), CommandError> {
+    scope::check(&app, [&path])?;
+    tauri::async_runtime::spawn_blocking(move || ingest::eject(&path))
+        .await?
+        .map_err(CommandError::from)
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use renamer_core::{CancelToken, DedupeStage};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::announce;
+use crate::error::{AppErrorCode, CommandError};
+
+/// Minimum time between two `job-progress` events of one job.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancellation tokens of running operations, keyed by the job id the webview
+/// passed when starting them.
+#[derive(Default)]
+pub struct JobState(Mutex<HashMap<String, CancelToken>>);
+
+/// Keeps a job registered while it runs; dropping it unregisters the job.
+pub struct JobGuard {
+    app: AppHandle,
+    job_id: Option<String>,
+    pub token: CancelToken,
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        if let Some(job_id) = &self.job_id {
+            self.app
+                .state::<JobState>()
+                .0
+                .lock()
+                .unwrap()
+                .remove(job_id);
+        }
+    }
+}
+
+/// Registers `job_id` so `cancel_job` can reach it. Operations started
+/// without an id get a token nobody else holds.
+pub fn register(app: &AppHandle, job_id: Option<String>) -> Result<JobGuard, CommandError> {
+    let token = CancelToken::new();
+    if let Some(id) = &job_id {
+        let state = app.state::<JobState>();
+        let mut jobs = state.0.lock().unwrap();
+        if jobs.contains_key(id) {
+            return Err(CommandError::new(
+                AppErrorCode::AlreadyRunning,
+                format!("job {} is already running", id),
+            )
+            .with("job_id", id));
+        }
+        jobs.insert(id.clone(), token.clone());
+    }
+    Ok(JobGuard {
+        app: app.clone(),
+        job_id,
+        token,
+    })
+}
+
+impl JobGuard {
+    /// Reports this job's progress as `kind`.
+    pub fn reporter(&self, kind: ProgressKind) -> ProgressReporter {
+        ProgressReporter::new(&self.app, self.job_id.clone(), kind)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressKind {
+    Scan,
+    Hash,
+    Metadata,
+    Apply,
+    /// A duplicate search; see `stage`.
+    Dedupe,
+}
+
+/// The payload of `job-progress`, the one event every long-running command
+/// reports through.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobProgress {
+    /// The id the webview started the job with, to tell concurrent jobs
+    /// apart.
+    pub job_id: Option<String>,
+    pub kind: ProgressKind,
+    /// Files done so far.
+    pub done: u64,
+    /// Files in total, when known up front; scans do not know.
+    pub total: Option<u64>,
+    /// The file just finished.
+    pub current_path: Option<PathBuf>,
+    /// Average throughput since the job started, for jobs that read or copy
+    /// file contents.
+    pub bytes_per_sec: Option<f64>,
+    /// The round of a duplicate search. Each round counts its files, and
+    /// its throughput, afresh.
+    pub stage: Option<DedupeStage>,
+}
+
+/// Emits `job-progress` events for one job, at most every 100 ms apart from
+/// the last one, and announces its start and milestones; see `announce`.
+pub struct ProgressReporter {
+    app: AppHandle,
+    job_id: Option<String>,
+    kind: ProgressKind,
+    stage: Option<DedupeStage>,
+    started: Instant,
+    /// When the last event went out, and the count it carried.
+    last: Mutex<(Option<Instant>, u64)>,
+    /// Percent between two milestones, and the last one announced.
+    step: u8,
+    milestone: Mutex<u8>,
+    verbose: bool,
+}
+
+impl ProgressReporter {
+    pub fn new(app: &AppHandle, job_id: Option<String>, kind: ProgressKind) -> Self {
+        announce::started(app, job_id.clone(), kind);
+        let verbose = announce::verbose(app);
+        ProgressReporter {
+            app: app.clone(),
+            job_id,
+            kind,
+            stage: None,
+            started: Instant::now(),
+            last: Mutex::new((None, 0)),
+            step: announce::step(verbose),
+            milestone: Mutex::new(0),
+            verbose,
+        }
+    }
+
+    /// Announces the milestone `done` of `total` passed, if it is one not
+    /// announced yet. The end is not a milestone; the job announces how it
+    /// ended itself.
+    fn announce(&self, done: u64, total: u64, current: Option<&Path>) {
+        let percent = (done * 100 / total) as u8;
+        let reached = percent / self.step * self.step;
+        let mut milestone = self.milestone.lock().unwrap();
+        if reached <= *milestone || reached >= 100 {
+            return;
+        }
+        *milestone = reached;
+        drop(milestone);
+        announce::milestone(
+            &self.app,
+            self.job_id.clone(),
+            self.kind,
+            reached,
+            (done, total),
+            current.filter(|_| self.verbose),
+        );
+    }
+
+    /// Announces that the job ended after `done` files.
+    pub fn finished(&self, done: u64) {
+        announce::finished(&self.app, self.job_id.clone(), self.kind, done);
+    }
+
+    /// Reports the round `stage` of a duplicate search.
+    pub fn in_stage(mut self, stage: DedupeStage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+
+    pub fn stage(&self) -> Option<DedupeStage> {
+        self.stage
+    }
+
+    /// `bytes` is how much file data the job has gone through so far.
+    pub fn report(
+        &self,
+        done: u64,
+        total: Option<u64>,
+        current: Option<&Path>,
+        bytes: Option<u64>,
+    ) {
+        if let Some(total) = total.filter(|&t| t > 0) {
+            self.announce(done.min(total), total, current);
+        }
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap();
+        let due = last
+            .0
+            .map_or(true, |t| now.duration_since(t) >= PROGRESS_INTERVAL);
+        if done < last.1 || !(due || total == Some(done)) {
+            return;
+        }
+        *last = (Some(now), done);
+        let elapsed = now.duration_since(self.started).as_secs_f64();
+        let _ = self.app.emit(
+            "job-progress",
+            JobProgress {
+                job_id: self.job_id.clone(),
+                kind: self.kind,
+                done,
+                total,
+                current_path: current.map(Path::to_path_buf),
+                bytes_per_sec: bytes.filter(|_| elapsed > 0.0).map(|b| b as f64 / elapsed),
+                stage: self.stage,
+            },
+        );
+    }
+}
+
+/// Asks a running job to stop at its next safe point; it then resolves with
+/// its partial results. Returns false if no such job is running.
+#[tauri::command]
+pub fn cancel_job(jobs: State<JobState>, job_id: String) -> bool {
+    match jobs.0.lock().unwrap().get(&job_id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
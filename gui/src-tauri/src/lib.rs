@@ -1,69 +1,341 @@
-use tauri_plugin_shell::ShellExt;
-use tauri_plugin_shell::process::CommandEvent;
-use std::sync::{Arc, Mutex};
-use tauri::Manager;
+mod announce;
+mod app_log;
+mod archive;
+mod crash;
+#[cfg(desktop)]
+mod desktop;
+mod diagnostics;
+mod dialogs;
+mod elevate;
+mod engine;
+mod error;
+mod file_list;
+mod ingest;
+mod jobs;
+mod maintenance;
+mod monitor;
+mod notify;
+mod orphans;
+#[cfg(desktop)]
+mod palette;
+mod paths;
+mod queue;
+mod recent;
+mod remote;
+mod rpc;
+mod scope;
+mod self_test;
+mod session;
+mod settings;
+#[cfg(desktop)]
+mod shell_integration;
+mod sidecar;
+mod sidecar_log;
+mod storage;
+mod suggest;
+#[cfg(desktop)]
+mod tray;
+#[cfg(desktop)]
+mod updates;
+mod user_data;
+mod watch;
+mod workers;
 
-#[derive(Default)]
-struct ApiState {
-    port: Arc<Mutex<u16>>,
+use diagnostics::FailedBatchState;
+use engine::{
+    ExtractState, FileCacheState, HistoryDbState, HistoryState, PluginState, PresetState, ScanState,
+};
+use file_list::FileListState;
+use jobs::JobState;
+use notify::NotifyState;
+#[cfg(desktop)]
+use palette::PaletteState;
+use queue::QueueState;
+use rpc::RpcState;
+use scope::ScopeState;
+use session::SessionState;
+use settings::SettingsState;
+#[cfg(desktop)]
+use shell_integration::LaunchState;
+use sidecar::ApiState;
+use sidecar_log::SidecarLogState;
+use suggest::SuggestState;
+use tauri::{DragDropEvent, Manager, RunEvent, WindowEvent, Wry};
+#[cfg(desktop)]
+use updates::UpdateState;
+use watch::{SourceWatchState, WatchState};
+use workers::WorkerPool;
+
+/// Applies a batch for the app when started by it with admin rights;
+/// returns the exit code then, and `None` for a normal start. See
+/// `elevate`.
+pub fn run_elevated_helper() -> Option<i32> {
+    elevate::helper_main()
 }
 
-#[tauri::command]
-fn get_api_port(state: tauri::State<ApiState>) -> u16 {
-    *state.port.lock().unwrap()
+/// Answers ssh's password prompt when started by it for a remote folder;
+/// returns the exit code then, and `None` for a normal start. See
+/// `renamer_core::remote`.
+pub fn run_askpass() -> Option<i32> {
+    renamer_core::remote::askpass_main()
 }
 
+/// The plugins and state only the desktop has: one instance at a time,
+/// updates and window sizes, the quick rename palette and launching with
+/// files to rename.
+#[cfg(desktop)]
+fn desktop(builder: tauri::Builder<Wry>) -> tauri::Builder<Wry> {
+    let mut window_state = tauri_plugin_window_state::Builder::new();
+    if let Some(dir) = paths::portable_dir() {
+        // An absolute name takes the place of the config folder.
+        let path = dir.join(tauri_plugin_window_state::DEFAULT_FILENAME);
+        window_state = window_state.with_filename(path.to_string_lossy());
+    }
+    builder
+        // Registered first, so a second launch exits before starting its
+        // own sidecar and hands its paths to this one instead.
+        .plugin(tauri_plugin_single_instance::init(
+            shell_integration::second_instance,
+        ))
+        .plugin(tauri_plugin_updater::Builder::new().build())
+        .plugin(window_state.build())
+        .manage(LaunchState::default())
+        .manage(PaletteState::default())
+        .manage(UpdateState::default())
+}
+
+/// On Android and iOS there is no sidecar: the engine's own commands do
+/// all the work, and folders the user picks are reached through `storage`.
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
+    let builder = tauri::Builder::<Wry>::default();
+    #[cfg(desktop)]
+    let builder = desktop(builder);
+    builder
         .plugin(tauri_plugin_shell::init())
-        .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(storage::init())
         .manage(ApiState::default())
-        .invoke_handler(tauri::generate_handler![get_api_port])
-        .setup(|app| {
-            if cfg!(debug_assertions) {
-                app.handle().plugin(
-                    tauri_plugin_log::Builder::default()
-                        .level(log::LevelFilter::Info)
-                        .build(),
-                )?;
+        .manage(ScanState::default())
+        .manage(ExtractState::default())
+        .manage(FileListState::default())
+        .manage(JobState::default())
+        .manage(WatchState::default())
+        .manage(SourceWatchState::default())
+        .manage(NotifyState::default())
+        .manage(FailedBatchState::default())
+        .manage(WorkerPool::default())
+        .manage(SuggestState::default())
+        .on_window_event(|window, event| match event {
+            WindowEvent::Focused(true) => notify::window_focused(window.app_handle()),
+            WindowEvent::Destroyed => session::window_closed(window),
+            // Seen here before the webview gets `tauri://drag-drop`, so the
+            // paths are in scope by the time it asks about them.
+            WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) => {
+                scope::allow_sources(window.app_handle(), paths)
             }
+            _ => {}
+        })
+        .invoke_handler(tauri::generate_handler![
+            app_log::get_log_level,
+            app_log::set_log_level,
+            app_log::get_recent_logs,
+            archive::archive_scan,
+            archive::archive_apply,
+            diagnostics::create_diagnostic_bundle,
+            diagnostics::get_versions,
+            self_test::run_self_test,
+            ingest::ingest_preview,
+            ingest::eject_volume,
+            monitor::get_sidecar_limits,
+            monitor::set_sidecar_limits,
+            settings::get_settings,
+            settings::update_settings,
+            sidecar::get_api_port,
+            sidecar::api_request,
+            sidecar::retry_sidecar,
+            sidecar::get_sidecar_error,
+            sidecar_log::get_recent_sidecar_logs,
+            sidecar::wait_for_api_ready,
+            suggest::suggest_names,
+            storage::pick_storage_folder,
+            storage::list_storage_folders,
+            storage::release_storage_folder,
+            storage::storage_scan,
+            storage::storage_apply,
+            #[cfg(desktop)]
+            updates::check_for_updates,
+            #[cfg(desktop)]
+            updates::defer_update,
+            #[cfg(desktop)]
+            updates::install_update,
+            #[cfg(desktop)]
+            updates::install_update_from_file,
+            user_data::export_user_data,
+            user_data::inspect_user_data,
+            user_data::import_user_data,
+            workers::get_sidecar_workers,
+            workers::set_sidecar_workers,
+            dialogs::pick_sources,
+            dialogs::pick_save_path,
+            recent::get_recent,
+            recent::add_recent,
+            recent::pin_recent,
+            #[cfg(desktop)]
+            desktop::reveal_in_file_manager,
+            #[cfg(desktop)]
+            desktop::trash_files,
+            #[cfg(desktop)]
+            shell_integration::get_launch_paths,
+            #[cfg(desktop)]
+            tray::quick_rename,
+            #[cfg(desktop)]
+            palette::open_quick_rename_palette,
+            #[cfg(desktop)]
+            palette::get_palette_paths,
+            #[cfg(desktop)]
+            palette::set_quick_rename_shortcut,
+            #[cfg(desktop)]
+            shell_integration::install_shell_integration,
+            #[cfg(desktop)]
+            shell_integration::uninstall_shell_integration,
+            #[cfg(desktop)]
+            shell_integration::shell_integration_installed,
+            session::save_session,
+            session::restore_session,
+            session::clear_session,
+            session::get_session,
+            session::list_sessions,
+            #[cfg(desktop)]
+            session::open_window,
+            engine::preview_renames,
+            engine::apply_regex_rule,
+            engine::render_template,
+            engine::extract_metadata,
+            engine::get_thumbnail,
+            engine::stat_files,
+            engine::git_work_tree,
+            engine::hash_files,
+            engine::clear_file_cache,
+            engine::extract_files,
+            engine::prioritize_extraction,
+            engine::find_duplicates,
+            engine::import_mapping,
+            engine::plan_renames,
+            engine::preflight_renames,
+            engine::apply_renames,
+            elevate::apply_elevated,
+            engine::export_report,
+            engine::export_review,
+            engine::import_review,
+            engine::get_rename_history,
+            engine::undo_last_batch,
+            engine::undo_files,
+            engine::redo_batch,
+            engine::get_incomplete_batches,
+            engine::resume_batch,
+            engine::rollback_batch,
+            engine::search_history,
+            engine::history_stats,
+            maintenance::run_maintenance,
+            crash::list_crash_reports,
+            crash::delete_crash_reports,
+            maintenance::get_storage_usage,
+            engine::verify_audit_log,
+            engine::get_batch,
+            engine::list_presets,
+            engine::save_preset,
+            engine::delete_preset,
+            engine::apply_preset,
+            engine::simulate_preset,
+            engine::compare_presets,
+            engine::list_plugins,
+            engine::enable_plugin,
+            engine::disable_plugin,
+            engine::scan_directory,
+            engine::query_scan,
+            engine::close_scan,
+            engine::resolve_dropped_paths,
+            file_list::add_to_file_list,
+            file_list::add_scan_to_file_list,
+            file_list::remove_from_file_list,
+            file_list::clear_file_list,
+            file_list::query_file_list,
+            file_list::select_in_file_list,
+            jobs::cancel_job,
+            queue::enqueue_job,
+            queue::get_queue,
+            queue::pause_queue,
+            queue::resume_queue,
+            queue::hold_job,
+            queue::reorder_job,
+            queue::cancel_queued_job,
+            queue::set_queue_parallelism,
+            queue::clear_finished_jobs,
+            watch::start_watch,
+            watch::stop_watch,
+            watch::list_watches,
+            watch::set_watches_paused,
+            watch::watch_sources,
+            watch::unwatch_sources,
+            watch::stale_preview_rows,
+            remote::remote_scan,
+            remote::remote_preview,
+            remote::remote_apply,
+            remote::remote_devices,
+            remote::remote_import,
+            remote::remote_save_password,
+            rpc::get_rpc_server,
+            rpc::reset_rpc_token
+        ])
+        .setup(|app| {
+            app.manage(SettingsState::load(app.handle())?);
+            app_log::init(app.handle())?;
+            let crash_reports = app.state::<SettingsState>().get().crash_reports;
+            crash::install(app.handle(), crash_reports);
+            app.manage(ScopeState::load(app.handle())?);
+            app.manage(SessionState::load(app.handle())?);
+            app.manage(HistoryState::load(app.handle())?);
+            app.manage(HistoryDbState::load(app.handle())?);
+            app.manage(FileCacheState::load(app.handle())?);
+            app.manage(PresetState::load(app.handle())?);
+            app.manage(PluginState::load(app.handle())?);
+            app.manage(QueueState::load(app.handle())?);
+            app.manage(SidecarLogState::load(app.handle())?);
+            app.manage(RpcState::load(app.handle())?);
+            maintenance::start(app.handle());
+            rpc::apply(app.handle());
+            #[cfg(desktop)]
+            {
+                tray::create(app.handle())?;
+                shell_integration::open_launch_args(app.handle());
 
-            // Find a free port
-            let port = std::net::TcpListener::bind("127.0.0.1:0")
-                .map(|l| l.local_addr().unwrap().port())
-                .expect("failed to find free port");
-            
-            // Store port in state
-            let state = app.state::<ApiState>();
-            *state.port.lock().unwrap() = port;
+                // The token the sidecar will require; the supervisor finds
+                // it a free port.
+                let state = app.state::<ApiState>();
+                *state.token.lock().unwrap() = sidecar::new_token();
 
-            let handle = app.handle().clone();
-            
-            // Spawn sidecar
-            tauri::async_runtime::spawn(async move {
-                let (mut rx, _child) = handle.shell().sidecar("renamer-api")
-                    .expect("failed to create sidecar")
-                    .args(["--port", &port.to_string()])
-                    .spawn()
-                    .expect("Failed to spawn sidecar");
-            
-                while let Some(event) = rx.recv().await {
-                    match event {
-                        CommandEvent::Stdout(line) => {
-                             log::info!("[PY]: {}", String::from_utf8_lossy(&line));
-                        }
-                        CommandEvent::Stderr(line) => {
-                             log::warn!("[PY]: {}", String::from_utf8_lossy(&line));
-                        }
-                        _ => {}
-                    }
-                }
-            });
+                // Spawn sidecar under supervision
+                sidecar::spawn_supervisor(app.handle().clone());
+            }
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| match event {
+            #[cfg(desktop)]
+            RunEvent::ExitRequested { .. } | RunEvent::Exit => {
+                tauri::async_runtime::block_on(sidecar::shutdown(app));
+            }
+            // Finder hands over files opened with the app, including from
+            // the quick action, as events rather than arguments.
+            #[cfg(target_os = "macos")]
+            RunEvent::Opened { urls } => shell_integration::open_paths(
+                app,
+                urls.iter().filter_map(|url| url.to_file_path().ok()),
+            ),
+            _ => {}
+        });
 }
@@ -2,5 +2,13 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // Started elevated by the app to rename in protected folders.
+    if let Some(code) = app_lib::run_elevated_helper() {
+        std::process::exit(code);
+    }
+    // Started by ssh to log in to a remote folder with a saved password.
+    if let Some(code) = app_lib::run_askpass() {
+        std::process::exit(code);
+    }
     app_lib::run();
 }
@@ -0,0 +1,76 @@
+//! Prunes the history database, journals and caches to the retention in the
+//! settings (see `renamer_core::maintenance`), a minute after the app starts
+//! and once a day while it runs, and on demand with [`run_maintenance`].
+//! Each run is logged with what it freed.
+
+use std::time::Duration;
+
+use renamer_core::{maintenance, DataPaths, MaintenanceReport, StorageUsage};
+use tauri::{AppHandle, Manager};
+
+use crate::engine::{journal_dir, FileCacheState, HistoryDbState};
+use crate::error::CommandError;
+use crate::paths;
+use crate::settings::SettingsState;
+
+const FIRST_RUN: Duration = Duration::from_secs(60);
+const INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn data_paths(app: &AppHandle) -> Result<DataPaths, CommandError> {
+    let data = paths::data_dir(app)?;
+    let cache = paths::cache_dir(app)?;
+    Ok(DataPaths {
+        history_db: data.join("history.db"),
+        journal_dir: journal_dir(app)?,
+        file_cache_db: cache.join("file-cache.db"),
+        thumbnail_dir: cache.join("thumbnails"),
+    })
+}
+
+fn run(app: &AppHandle) -> Result<MaintenanceReport, CommandError> {
+    let paths = data_paths(app)?;
+    let retention = app.state::<SettingsState>().all().retention;
+    let cache = app.state::<FileCacheState>().0.clone();
+    let history = app.state::<HistoryDbState>();
+    let mut history = history.0.lock().unwrap();
+    let report = maintenance::run(&retention, &mut history, &cache, &paths)?;
+    log::info!(
+        "maintenance freed {} bytes: {} batches, {} journals, {} cache entries, {} thumbnails",
+        report.freed(),
+        report.batches_forgotten,
+        report.journals_removed,
+        report.cache_entries_dropped,
+        report.thumbnails_removed
+    );
+    Ok(report)
+}
+
+/// Runs maintenance in the background from now on.
+pub(crate) fn start(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(FIRST_RUN).await;
+        loop {
+            let handle = app.clone();
+            match tauri::async_runtime::spawn_blocking(move || run(&handle)).await {
+                Ok(Err(e)) => log::warn!("maintenance failed: {}", e),
+                Err(e) => log::warn!("maintenance failed: {}", e),
+                Ok(Ok(_)) => {}
+            }
+            tokio::time::sleep(INTERVAL).await;
+        }
+    });
+}
+
+/// Prunes the stores now rather than at the next daily run.
+#[tauri::command]
+pub async fn run_maintenance(app: AppHandle) -> Result<MaintenanceReport, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || run(&app)).await?
+}
+
+/// How much the history, journals and caches take up on disk.
+#[tauri::command]
+pub async fn get_storage_usage(app: AppHandle) -> Result<StorageUsage, CommandError> {
+    let paths = data_paths(&app)?;
+    Ok(tauri::async_runtime::spawn_blocking(move || StorageUsage::measure(&paths)).await?)
+}
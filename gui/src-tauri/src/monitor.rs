@@ -0,0 +1,249 @@
+//! Watches the memory and CPU use of a ready sidecar and whether it still
+//! answers, and restarts one that went past its limits or hung, emitting
+//! `sidecar-unhealthy` with what it saw first so the restart of a job it
+//! was running can be explained.
+//!
+//! CPU use is worked out from the CPU time the process used between two
+//! samples, which `ps` and `tasklist` both report.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::settings::SettingsState;
+use crate::sidecar;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the sidecar may use before it is restarted; in the settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SidecarLimits {
+    /// Resident memory in MiB; `None` for no limit.
+    pub max_memory_mb: Option<u64>,
+    /// CPU use in percent of one core that it may not stay above for
+    /// `cpu_window_secs`; `None` for no limit. Scanning a big folder keeps
+    /// a core busy for a while, so this is off by default.
+    pub max_cpu_percent: Option<f64>,
+    pub cpu_window_secs: u64,
+    /// Health pings in a row it may miss before it counts as hung.
+    pub missed_pings: u32,
+}
+
+impl Default for SidecarLimits {
+    fn default() -> Self {
+        SidecarLimits {
+            max_memory_mb: Some(2048),
+            max_cpu_percent: None,
+            cpu_window_secs: 60,
+            missed_pings: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnhealthyReason {
+    Memory,
+    Cpu,
+    Unresponsive,
+}
+
+/// The last sample taken before the sidecar was restarted. A figure the
+/// system would not give is `None`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SidecarStats {
+    pub pid: u32,
+    pub memory_mb: Option<u64>,
+    pub cpu_percent: Option<f64>,
+    pub missed_pings: u32,
+    pub uptime_secs: u64,
+}
+
+/// The payload of `sidecar-unhealthy`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SidecarUnhealthy {
+    pub reason: UnhealthyReason,
+    pub stats: SidecarStats,
+    pub limits: SidecarLimits,
+}
+
+/// Samples the sidecar with `pid` until it is replaced or restarted.
+pub(crate) fn watch(app: AppHandle, pid: u32) {
+    tauri::async_runtime::spawn(async move {
+        let started = Instant::now();
+        let mut last: Option<(Instant, Duration)> = None;
+        let mut busy_since: Option<Instant> = None;
+        let mut missed = 0;
+        loop {
+            tokio::time::sleep(SAMPLE_INTERVAL).await;
+            if sidecar::running_pid(&app) != Some(pid) {
+                break;
+            }
+            let limits = app.state::<SettingsState>().get().sidecar_limits;
+            let sample = tauri::async_runtime::spawn_blocking(move || platform::sample(pid))
+                .await
+                .ok()
+                .flatten();
+            let now = Instant::now();
+            let cpu_percent = match (sample, last) {
+                (Some(sample), Some((at, cpu_time))) => {
+                    let wall = now.duration_since(at).as_secs_f64();
+                    let used = sample.cpu_time.saturating_sub(cpu_time).as_secs_f64();
+                    (wall > 0.0).then(|| used / wall * 100.0)
+                }
+                _ => None,
+            };
+            last = sample.map(|s| (now, s.cpu_time));
+            if sidecar::ping(&app, PING_TIMEOUT).await {
+                missed = 0;
+            } else {
+                missed += 1;
+            }
+            // The sidecar may have gone away during the ping.
+            if sidecar::running_pid(&app) != Some(pid) {
+                break;
+            }
+
+            let memory_mb = sample.map(|s| s.memory_bytes / (1024 * 1024));
+            let over_cpu = matches!(
+                (cpu_percent, limits.max_cpu_percent),
+                (Some(used), Some(max)) if used > max
+            );
+            busy_since = if over_cpu {
+                busy_since.or(Some(now))
+            } else {
+                None
+            };
+            let reason = if matches!(
+                (memory_mb, limits.max_memory_mb),
+                (Some(used), Some(max)) if used > max
+            ) {
+                Some(UnhealthyReason::Memory)
+            } else if busy_since
+                .is_some_and(|since| now - since >= Duration::from_secs(limits.cpu_window_secs))
+            {
+                Some(UnhealthyReason::Cpu)
+            } else if limits.missed_pings > 0 && missed >= limits.missed_pings {
+                Some(UnhealthyReason::Unresponsive)
+            } else {
+                None
+            };
+            let Some(reason) = reason else {
+                continue;
+            };
+            let unhealthy = SidecarUnhealthy {
+                reason,
+                stats: SidecarStats {
+                    pid,
+                    memory_mb,
+                    cpu_percent,
+                    missed_pings: missed,
+                    uptime_secs: started.elapsed().as_secs(),
+                },
+                limits,
+            };
+            sidecar::note(
+                &app,
+                log::Level::Warn,
+                format!("restarting unhealthy sidecar: {:?}", unhealthy),
+            );
+            let _ = app.emit("sidecar-unhealthy", unhealthy);
+            sidecar::restart(&app);
+            break;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_sidecar_limits(settings: tauri::State<SettingsState>) -> SidecarLimits {
+    settings.get().sidecar_limits
+}
+
+/// Takes effect at the next sample and is kept for the next run.
+#[tauri::command]
+pub fn set_sidecar_limits(settings: tauri::State<SettingsState>, limits: SidecarLimits) {
+    settings.update(|s| s.sidecar_limits = limits);
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    memory_bytes: u64,
+    /// CPU time used since the process started.
+    cpu_time: Duration,
+}
+
+/// Parses a CPU time as `ps` and `tasklist` print it: `[[dd-]hh:]mm:ss`,
+/// with the seconds possibly fractional.
+fn parse_cpu_time(text: &str) -> Option<Duration> {
+    let (days, clock) = match text.trim().split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, text.trim()),
+    };
+    let mut fields = clock.rsplit(':');
+    let seconds: f64 = fields.next()?.parse().ok()?;
+    let mut total = days as f64 * 86_400.0 + seconds;
+    for (field, scale) in fields.zip([60.0, 3600.0]) {
+        total += field.parse::<f64>().ok()? * scale;
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::os::windows::process::CommandExt;
+    use std::process::Command;
+
+    use super::{parse_cpu_time, Sample};
+
+    /// Keeps `tasklist` from flashing a console window.
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    pub fn sample(pid: u32) -> Option<Sample> {
+        let out = Command::new("tasklist")
+            .args(["/V", "/FO", "CSV", "/NH", "/FI"])
+            .arg(format!("PID eq {}", pid))
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+            .ok()?;
+        let out = String::from_utf8_lossy(&out.stdout);
+        // "renamer-api.exe","1234","Console","1","45,000 K","Running",
+        // "PC\user","0:00:05","N/A"
+        let fields: Vec<&str> = out.lines().next()?.trim().split("\",\"").collect();
+        let memory_kb: String = fields
+            .get(4)?
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        Some(Sample {
+            memory_bytes: memory_kb.parse::<u64>().ok()? * 1024,
+            cpu_time: parse_cpu_time(fields.get(7)?)?,
+        })
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    use std::process::Command;
+
+    use super::{parse_cpu_time, Sample};
+
+    pub fn sample(pid: u32) -> Option<Sample> {
+        let out = Command::new("ps")
+            .args(["-o", "rss=,time=", "-p", &pid.to_string()])
+            .output()
+            .ok()?;
+        if !out.status.success() {
+            return None;
+        }
+        let out = String::from_utf8_lossy(&out.stdout);
+        let mut fields = out.split_whitespace();
+        let rss_kb: u64 = fields.next()?.parse().ok()?;
+        Some(Sample {
+            memory_bytes: rss_kb * 1024,
+            cpu_time: parse_cpu_time(fields.next()?)?,
+        })
+    }
+}
@@ -0,0 +1,99 @@
+//! System notifications for batches that finish while nobody is looking,
+//! and the webhooks in the settings for every batch that finishes.
+//!
+//! Desktop notifications cannot carry a click handler, but clicking one
+//! brings the app to the front; the first time the window gains focus after
+//! a notification, an `open-report` event with the batch id is emitted so
+//! the webview can show that batch's report.
+
+use std::sync::Mutex;
+
+use renamer_core::{webhook, ApplyReport, JobSummary, WebhookEvent};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+use uuid::Uuid;
+
+use crate::settings::SettingsState;
+
+/// The batch whose notification was shown last, until the window is
+/// focused.
+#[derive(Default)]
+pub struct NotifyState(Mutex<Option<Uuid>>);
+
+fn window_in_view(app: &AppHandle) -> bool {
+    app.get_webview_window("main").is_some_and(|window| {
+        window.is_visible().unwrap_or(false)
+            && !window.is_minimized().unwrap_or(false)
+            && window.is_focused().unwrap_or(false)
+    })
+}
+
+/// Shows a notification with the counts of `report` if the window is
+/// hidden, minimized or in the background. `source` says where the batch
+/// came from, e.g. a watched folder's preset.
+pub(crate) fn batch_finished(app: &AppHandle, report: &ApplyReport, source: Option<&str>) {
+    if window_in_view(app) {
+        return;
+    }
+    let title = if report.cancelled {
+        "Rename cancelled"
+    } else if report.committed {
+        "Rename finished"
+    } else {
+        "Rename failed and was rolled back"
+    };
+    let mut body = format!("{} renamed", report.renamed);
+    for (count, what) in [
+        (report.failed, "failed"),
+        (report.skipped, "skipped"),
+        (report.rolled_back, "rolled back"),
+    ] {
+        if count > 0 {
+            body.push_str(&format!(", {} {}", count, what));
+        }
+    }
+    if let Some(source) = source {
+        body = format!("{}: {}", source, body);
+    }
+    body.push_str(". Click to open the report.");
+
+    let shown = app.notification().builder().title(title).body(body).show();
+    match shown {
+        Ok(()) => *app.state::<NotifyState>().0.lock().unwrap() = Some(report.batch_id),
+        Err(e) => log::error!("failed to notify about {}: {}", report.batch_id, e),
+    }
+}
+
+/// Sends the summary of `report` to the webhooks that want `event`, on a
+/// blocking thread so the batch's caller does not wait for them. Hooks
+/// that fail are only logged.
+pub(crate) fn webhooks(
+    app: &AppHandle,
+    report: &ApplyReport,
+    event: WebhookEvent,
+    preset: Option<&str>,
+) {
+    let hooks = app.state::<SettingsState>().all().webhooks;
+    if hooks.is_empty() {
+        return;
+    }
+    let summary = JobSummary::new(event, report, preset);
+    tauri::async_runtime::spawn_blocking(move || {
+        for failure in webhook::send(&hooks, &summary) {
+            log::warn!(
+                "webhook {} not told about {}: {}",
+                failure.url,
+                summary.batch_id,
+                failure.message
+            );
+        }
+    });
+}
+
+/// Called when the main window gains focus: emits `open-report` for the
+/// batch last notified about, if any.
+pub(crate) fn window_focused(app: &AppHandle) {
+    if let Some(batch_id) = app.state::<NotifyState>().0.lock().unwrap().take() {
+        let _ = app.emit("open-report", batch_id);
+    }
+}
@@ -0,0 +1,173 @@
+//! Sidecars left running by an app that crashed or was killed. They would
+//! hold their port and keep a copy of the API token around, so they are
+//! stopped before a new sidecar is spawned.
+//!
+//! The pid of the running sidecar is kept in `sidecar.pid` in the app data
+//! folder. Since that misses the process a bundled sidecar starts for
+//! itself, processes named like the sidecar are looked for too, but only
+//! among the user's own: another user's sidecar belongs to their app. The
+//! single-instance plugin exits a second launch before it gets here, so no
+//! sidecar of a live app is ever among them.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tauri::AppHandle;
+
+use crate::paths;
+
+const PID_FILE: &str = "sidecar.pid";
+const PROCESS_NAME: &str = "renamer-api";
+
+/// How long an orphan gets to exit when asked before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+fn pid_file(app: &AppHandle) -> Option<PathBuf> {
+    Some(paths::data_dir(app).ok()?.join(PID_FILE))
+}
+
+/// Notes the pid of a sidecar just spawned.
+pub(crate) fn record(app: &AppHandle, pid: u32) {
+    let Some(path) = pid_file(app) else {
+        return;
+    };
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, pid.to_string()));
+    if let Err(e) = written {
+        log::warn!("failed to write {}: {}", path.display(), e);
+    }
+}
+
+/// Forgets the sidecar once it has exited for good.
+pub(crate) fn clear(app: &AppHandle) {
+    if let Some(path) = pid_file(app) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Stops every orphaned sidecar, waiting for each to exit. Blocks.
+pub(crate) fn reap(app: &AppHandle) {
+    let recorded = pid_file(app)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|pid| pid.trim().parse::<u32>().ok());
+    let mut orphans = platform::own_processes(PROCESS_NAME);
+    // A pid reused by something else since is not in the list, so it is
+    // left alone.
+    orphans.sort_by_key(|&pid| Some(pid) != recorded);
+    orphans.retain(|&pid| pid != std::process::id());
+    for pid in orphans {
+        log::warn!("stopping orphaned sidecar (pid {})", pid);
+        platform::stop(pid);
+        let deadline = Instant::now() + STOP_GRACE;
+        while platform::is_running(pid) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        if platform::is_running(pid) {
+            platform::kill(pid);
+        }
+    }
+    clear(app);
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::os::windows::process::CommandExt;
+    use std::process::Command;
+
+    /// Keeps `tasklist` and `taskkill` from flashing a console window.
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    fn run(program: &str, args: &[&str]) -> Option<String> {
+        let out = Command::new(program)
+            .args(args)
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+            .ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    fn tasklist(filters: &[String]) -> Vec<u32> {
+        let mut args = vec!["/FO", "CSV", "/NH"];
+        for filter in filters {
+            args.extend(["/FI", filter.as_str()]);
+        }
+        let Some(out) = run("tasklist", &args) else {
+            return Vec::new();
+        };
+        // "renamer-api.exe","1234","Console","1","45,000 K"
+        out.lines()
+            .filter_map(|line| line.split("\",\"").nth(1)?.parse().ok())
+            .collect()
+    }
+
+    pub fn own_processes(name: &str) -> Vec<u32> {
+        let Ok(user) = std::env::var("USERNAME") else {
+            return Vec::new();
+        };
+        tasklist(&[
+            format!("IMAGENAME eq {}.exe", name),
+            format!("USERNAME eq {}", user),
+        ])
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        !tasklist(&[format!("PID eq {}", pid)]).is_empty()
+    }
+
+    /// Windows has no polite way to ask a console-less process to exit, so
+    /// stopping is killing, with the processes it started.
+    pub fn stop(pid: u32) {
+        kill(pid);
+    }
+
+    pub fn kill(pid: u32) {
+        let _ = run("taskkill", &["/PID", &pid.to_string(), "/T", "/F"]);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    use std::path::Path;
+    use std::process::Command;
+
+    fn run(program: &str, args: &[&str]) -> Option<String> {
+        let out = Command::new(program).args(args).output().ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    /// `ps x` lists the processes of the user running it, on Linux and
+    /// macOS alike.
+    pub fn own_processes(name: &str) -> Vec<u32> {
+        let Some(out) = run("ps", &["x", "-o", "pid=,comm="]) else {
+            return Vec::new();
+        };
+        out.lines()
+            .filter_map(|line| {
+                let (pid, command) = line.trim().split_once(char::is_whitespace)?;
+                // macOS gives the whole path, Linux the name cut to 15 bytes.
+                let file = Path::new(command.trim()).file_name()?.to_str()?;
+                file.starts_with(name).then(|| pid.parse().ok())?
+            })
+            .collect()
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        run("kill", &["-0", &pid.to_string()]).is_some()
+    }
+
+    pub fn stop(pid: u32) {
+        let _ = run("kill", &["-TERM", &pid.to_string()]);
+    }
+
+    pub fn kill(pid: u32) {
+        let _ = run("kill", &["-KILL", &pid.to_string()]);
+    }
+}
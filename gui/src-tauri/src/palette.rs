@@ -0,0 +1,407 @@
+//! The quick rename palette: a small window above all others, holding the
+//! files selected in Explorer or Finder, for renaming a few files without
+//! the full window.
+//!
+//! It opens from the tray, from `open_quick_rename_palette`, and when the
+//! app is launched with [`ARG`], which is what the global shortcut runs. The
+//! shortcut is registered with the desktop rather than held by the app: a
+//! Start menu shortcut with a hotkey on Windows, a custom keybinding on
+//! GNOME. It so works while the app is not running, and a second launch
+//! hands over to the running one like opening files does.
+//!
+//! The files are those selected in the front Finder window or an Explorer
+//! window, or, where that cannot be asked for, those copied to the
+//! clipboard. The palette's webview loads `index.html?palette` and takes
+//! them with `get_palette_paths` on every `palette-opened`.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Emitter, Manager, State, WebviewUrl, WebviewWindowBuilder};
+
+use crate::error::{AppErrorCode, CommandError};
+use crate::scope;
+use crate::settings::SettingsState;
+
+/// The argument that opens the palette instead of the main window.
+pub const ARG: &str = "--quick-rename";
+
+const LABEL: &str = "palette";
+
+/// The files the palette was last opened with, until its webview takes
+/// them.
+#[derive(Default)]
+pub struct PaletteState(Mutex<Vec<PathBuf>>);
+
+/// Opens the palette, or brings it back, with the current selection, off
+/// the main thread since asking the file manager takes a moment.
+pub(crate) fn open(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let paths = selection();
+        scope::allow_sources(&app, &paths);
+        let count = paths.len();
+        *app.state::<PaletteState>().0.lock().unwrap() = paths;
+        if let Err(e) = show(&app) {
+            log::error!("failed to open the quick rename palette: {}", e);
+            return;
+        }
+        let _ = app.emit_to(LABEL, "palette-opened", count);
+    });
+}
+
+/// The selected files that exist, or the copied ones if none are selected.
+fn selection() -> Vec<PathBuf> {
+    let selected: Vec<PathBuf> = platform::selection()
+        .into_iter()
+        .filter(|p| p.exists())
+        .collect();
+    if !selected.is_empty() {
+        return selected;
+    }
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get().file_list())
+        .unwrap_or_default()
+}
+
+fn show(app: &AppHandle) -> tauri::Result<()> {
+    let window = match app.get_webview_window(LABEL) {
+        Some(window) => window,
+        None => WebviewWindowBuilder::new(app, LABEL, WebviewUrl::App("index.html?palette".into()))
+            .title("Quick rename")
+            .inner_size(560.0, 220.0)
+            .resizable(false)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .center()
+            .build()?,
+    };
+    window.show()?;
+    window.set_focus()
+}
+
+#[tauri::command]
+pub fn open_quick_rename_palette(app: AppHandle) {
+    open(&app);
+}
+
+/// The files the palette was opened with since the last call.
+#[tauri::command]
+pub fn get_palette_paths(palette: State<PaletteState>) -> Vec<PathBuf> {
+    std::mem::take(&mut *palette.0.lock().unwrap())
+}
+
+/// A key with modifiers, written like `Ctrl+Alt+R`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+struct Shortcut {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    /// The Windows or Command key.
+    super_key: bool,
+    /// An upper-case letter or digit, or `F1` to `F12`.
+    key: String,
+}
+
+impl Shortcut {
+    fn parse(text: &str) -> Result<Self, CommandError> {
+        let invalid = || {
+            CommandError::new(
+                AppErrorCode::Other,
+                format!(
+                    "`{}` is not a shortcut like Ctrl+Alt+R: modifiers, then one letter, digit or F1 to F12",
+                    text
+                ),
+            )
+        };
+        let mut shortcut = Shortcut {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            super_key: false,
+            key: String::new(),
+        };
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default().to_ascii_uppercase();
+        for part in parts {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut shortcut.ctrl,
+                "alt" | "option" => &mut shortcut.alt,
+                "shift" => &mut shortcut.shift,
+                "super" | "win" | "cmd" | "command" | "meta" => &mut shortcut.super_key,
+                _ => return Err(invalid()),
+            };
+            *modifier = true;
+        }
+        let function_key = key
+            .strip_prefix('F')
+            .and_then(|n| n.parse::<u8>().ok())
+            .is_some_and(|n| (1..=12).contains(&n));
+        let plain_key = key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric());
+        if !(function_key || plain_key) {
+            return Err(invalid());
+        }
+        // A bare letter would take the key away from every other program.
+        if !(function_key || shortcut.ctrl || shortcut.alt || shortcut.super_key) {
+            return Err(invalid());
+        }
+        shortcut.key = key;
+        Ok(shortcut)
+    }
+}
+
+/// Registers `shortcut` with the desktop to open the palette, in place of
+/// the one registered before, or removes it when `None`, and keeps it in
+/// the settings.
+#[tauri::command]
+pub async fn set_quick_rename_shortcut(
+    app: AppHandle,
+    shortcut: Option<String>,
+) -> Result<(), CommandError> {
+    let parsed = shortcut.as_deref().map(Shortcut::parse).transpose()?;
+    let exe = std::env::current_exe()?;
+    tauri::async_runtime::spawn_blocking(move || match &parsed {
+        Some(shortcut) => platform::register(&exe, shortcut),
+        None => platform::unregister(),
+    })
+    .await??;
+    app.state::<SettingsState>()
+        .update(|settings| settings.quick_rename_shortcut = shortcut);
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::os::windows::process::CommandExt;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+
+    use super::{Shortcut, ARG};
+    use crate::error::{AppErrorCode, CommandError};
+
+    /// Keeps PowerShell from flashing a console window.
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    fn powershell(script: &str) -> Result<String, String> {
+        let out = Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", script])
+            .stdin(Stdio::null())
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+            .map_err(|e| e.to_string())?;
+        if out.status.success() {
+            Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+        } else {
+            Err(String::from_utf8_lossy(&out.stderr).trim().to_string())
+        }
+    }
+
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', "''"))
+    }
+
+    /// The items selected in the first Explorer window that has any.
+    pub fn selection() -> Vec<PathBuf> {
+        let script = "foreach ($w in (New-Object -ComObject Shell.Application).Windows()) { \
+                      try { $items = @($w.Document.SelectedItems()) } catch { continue }; \
+                      if ($items.Count) { $items | ForEach-Object { $_.Path }; break } }";
+        powershell(script)
+            .map(|out| out.lines().map(PathBuf::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// A Start menu shortcut: Windows only honours hotkeys on those and on
+    /// desktop ones.
+    fn link() -> Result<PathBuf, String> {
+        let appdata = std::env::var_os("APPDATA").ok_or("APPDATA is not set")?;
+        Ok(PathBuf::from(appdata)
+            .join(r"Microsoft\Windows\Start Menu\Programs\Sortify quick rename.lnk"))
+    }
+
+    pub fn register(exe: &Path, shortcut: &Shortcut) -> Result<(), CommandError> {
+        if shortcut.super_key || !(shortcut.ctrl && shortcut.alt) {
+            return Err(CommandError::new(
+                AppErrorCode::Unsupported,
+                "shortcuts on Windows need Ctrl and Alt and cannot use the Windows key",
+            ));
+        }
+        let mut hotkey = vec!["CTRL", "ALT"];
+        if shortcut.shift {
+            hotkey.push("SHIFT");
+        }
+        hotkey.push(&shortcut.key);
+        let script = format!(
+            "$s = (New-Object -ComObject WScript.Shell).CreateShortcut({}); \
+             $s.TargetPath = {}; $s.Arguments = {}; $s.Hotkey = {}; $s.Save()",
+            quote(&link()?.display().to_string()),
+            quote(&exe.display().to_string()),
+            quote(ARG),
+            quote(&hotkey.join("+")),
+        );
+        powershell(&script)?;
+        Ok(())
+    }
+
+    pub fn unregister() -> Result<(), CommandError> {
+        let link = link()?;
+        if link.exists() {
+            std::fs::remove_file(&link)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use super::Shortcut;
+    use crate::error::{AppErrorCode, CommandError};
+
+    const SCRIPT: &str = r#"tell application "Finder"
+	set out to ""
+	repeat with f in (selection as alias list)
+		set out to out & POSIX path of f & linefeed
+	end repeat
+end tell"#;
+
+    /// The items selected in the front Finder window.
+    pub fn selection() -> Vec<PathBuf> {
+        Command::new("osascript")
+            .args(["-e", SCRIPT])
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| {
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .filter(|l| !l.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn register(_exe: &Path, _shortcut: &Shortcut) -> Result<(), CommandError> {
+        Err(CommandError::new(
+            AppErrorCode::Unsupported,
+            "on macOS, shortcuts for other apps are set in System Settings > Keyboard",
+        ))
+    }
+
+    pub fn unregister() -> Result<(), CommandError> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use super::{Shortcut, ARG};
+    use crate::error::{AppErrorCode, CommandError};
+
+    const SCHEMA: &str = "org.gnome.settings-daemon.plugins.media-keys";
+    const BINDING: &str =
+        "/org/gnome/settings-daemon/plugins/media-keys/custom-keybindings/sortify-quick-rename/";
+
+    /// File managers cannot be asked; the clipboard is used instead.
+    pub fn selection() -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    fn gsettings(args: &[&str]) -> Result<String, CommandError> {
+        let out = Command::new("gsettings").args(args).output().map_err(|e| {
+            CommandError::new(
+                AppErrorCode::Unsupported,
+                format!("global shortcuts need GNOME's gsettings: {}", e),
+            )
+        })?;
+        if out.status.success() {
+            Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+        } else {
+            Err(String::from_utf8_lossy(&out.stderr)
+                .trim()
+                .to_string()
+                .into())
+        }
+    }
+
+    /// A GVariant string.
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+
+    /// The custom keybinding paths, read from GVariant like
+    /// `['/a/', '/b/']` or `@as []`.
+    fn bindings() -> Result<Vec<String>, CommandError> {
+        let list = gsettings(&["get", SCHEMA, "custom-keybindings"])?;
+        Ok(list
+            .split('\'')
+            .skip(1)
+            .step_by(2)
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn set_bindings(paths: &[String]) -> Result<(), CommandError> {
+        let list: Vec<String> = paths.iter().map(|p| quote(p)).collect();
+        gsettings(&[
+            "set",
+            SCHEMA,
+            "custom-keybindings",
+            &format!("[{}]", list.join(", ")),
+        ])?;
+        Ok(())
+    }
+
+    pub fn register(exe: &Path, shortcut: &Shortcut) -> Result<(), CommandError> {
+        let mut binding = String::new();
+        for (on, name) in [
+            (shortcut.ctrl, "<Control>"),
+            (shortcut.alt, "<Alt>"),
+            (shortcut.shift, "<Shift>"),
+            (shortcut.super_key, "<Super>"),
+        ] {
+            if on {
+                binding.push_str(name);
+            }
+        }
+        if shortcut.key.len() == 1 {
+            binding.push_str(&shortcut.key.to_ascii_lowercase());
+        } else {
+            binding.push_str(&shortcut.key);
+        }
+        let schema = format!("{}.custom-keybinding:{}", SCHEMA, BINDING);
+        let command = format!("\"{}\" {}", exe.display(), ARG);
+        for (key, value) in [
+            ("name", "Sortify quick rename"),
+            ("command", command.as_str()),
+            ("binding", binding.as_str()),
+        ] {
+            gsettings(&["set", &schema, key, &quote(value)])?;
+        }
+        let mut paths = bindings()?;
+        if !paths.iter().any(|p| p == BINDING) {
+            paths.push(BINDING.to_string());
+            set_bindings(&paths)?;
+        }
+        Ok(())
+    }
+
+    pub fn unregister() -> Result<(), CommandError> {
+        let Ok(mut paths) = bindings() else {
+            return Ok(());
+        };
+        if paths.iter().any(|p| p == BINDING) {
+            paths.retain(|p| p != BINDING);
+            set_bindings(&paths)?;
+            let schema = format!("{}.custom-keybinding:{}", SCHEMA, BINDING);
+            gsettings(&["reset-recursively", &schema])?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! Where the app keeps its files, and portable mode.
+//!
+//! With a `portable.flag` file next to the executable, or when started with
+//! `--portable`, settings, presets, history, caches and logs all go into a
+//! `data` folder next to the executable instead of the system's app data,
+//! cache and log folders, and the sidecar is told to keep its config and
+//! history there too. That lets the app run from a USB stick on machines
+//! where it cannot be installed and should leave nothing behind; the
+//! webview's own cache is the one thing still kept where the system puts it.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use tauri::{AppHandle, Manager};
+
+const FLAG_FILE: &str = "portable.flag";
+const PORTABLE_ARG: &str = "--portable";
+const PORTABLE_DIR: &str = "data";
+
+fn detect() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    let asked = std::env::args_os().skip(1).any(|arg| arg == PORTABLE_ARG);
+    (asked || dir.join(FLAG_FILE).is_file()).then(|| dir.join(PORTABLE_DIR))
+}
+
+/// The folder holding everything in portable mode; `None` otherwise. Known
+/// before the app is built, so plugins can be pointed at it too.
+pub fn portable_dir() -> Option<&'static Path> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    DIR.get_or_init(detect).as_deref()
+}
+
+pub fn data_dir(app: &AppHandle) -> tauri::Result<PathBuf> {
+    match portable_dir() {
+        Some(dir) => Ok(dir.to_path_buf()),
+        None => app.path().app_data_dir(),
+    }
+}
+
+pub fn cache_dir(app: &AppHandle) -> tauri::Result<PathBuf> {
+    match portable_dir() {
+        Some(dir) => Ok(dir.join("cache")),
+        None => app.path().app_cache_dir(),
+    }
+}
+
+/// Where the log plugin writes in portable mode; the system's log folder
+/// otherwise.
+pub fn portable_log_dir() -> Option<PathBuf> {
+    portable_dir().map(|dir| dir.join("logs"))
+}
@@ -0,0 +1,130 @@
+use renamer_core::{Job, JobOutput, JobQueue, Lane, QueueEvent, QueueSnapshot, WebhookEvent};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
+
+use crate::engine::{journal_dir, record_batch};
+use crate::error::CommandError;
+use crate::settings::SettingsState;
+use crate::{notify, paths, scope};
+
+/// Jobs started at once unless the webview asks for another limit.
+const DEFAULT_PARALLEL: usize = 2;
+
+/// The background job queue. Every change is emitted as a `queue-updated`
+/// event holding the whole queue, and each finished job's results as a
+/// `queue-job-finished` event. Renames run from the queue go into the
+/// history like any other batch.
+pub struct QueueState(JobQueue);
+
+#[derive(Clone, Serialize)]
+struct JobFinished {
+    job_id: Uuid,
+    output: JobOutput,
+}
+
+impl QueueState {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let app = app.clone();
+        let queue = JobQueue::new(
+            journal_dir(&app)?,
+            DEFAULT_PARALLEL,
+            move |event| match event {
+                QueueEvent::Updated(snapshot) => {
+                    let _ = app.emit("queue-updated", snapshot);
+                }
+                QueueEvent::Finished { job_id, output } => {
+                    if let JobOutput::Renamed(report) = &output {
+                        record_batch(&app, report, None);
+                        notify::webhooks(&app, report, WebhookEvent::Batch, None);
+                    }
+                    let _ = app.emit("queue-job-finished", JobFinished { job_id, output });
+                }
+            },
+        );
+        Ok(QueueState(queue))
+    }
+}
+
+/// Adds `job` to the end of `lane`, or of the lane its kind runs in;
+/// `label` is what the queue panel shows. Renames are applied in safe mode
+/// if the settings have it, as `apply_renames` applies them. Thumbnails are
+/// cached where `get_thumbnail` caches them.
+#[tauri::command]
+pub fn enqueue_job(
+    app: AppHandle,
+    queue: State<QueueState>,
+    label: String,
+    mut job: Job,
+    lane: Option<Lane>,
+) -> Result<Uuid, CommandError> {
+    match &job {
+        Job::Rename { ops, .. } => scope::check_ops(&app, ops)?,
+        Job::Scan { root, .. } => scope::check(&app, [root])?,
+        Job::Hash { paths, .. } | Job::Preview { paths, .. } | Job::Thumbnails { paths, .. } => {
+            scope::check(&app, paths)?
+        }
+        Job::Ingest { source, options } => scope::check(&app, [source, &options.destination])?,
+    }
+    match &mut job {
+        Job::Rename { apply_options, .. } => {
+            *apply_options = app
+                .state::<SettingsState>()
+                .enforce(std::mem::take(apply_options));
+        }
+        Job::Thumbnails { cache_dir, .. } => {
+            *cache_dir = paths::cache_dir(&app)?.join("thumbnails");
+        }
+        _ => {}
+    }
+    Ok(match lane {
+        Some(lane) => queue.0.enqueue_in(label, job, lane),
+        None => queue.0.enqueue(label, job),
+    })
+}
+
+#[tauri::command]
+pub fn get_queue(queue: State<QueueState>) -> QueueSnapshot {
+    queue.0.snapshot()
+}
+
+/// Stops starting jobs; running ones finish.
+#[tauri::command]
+pub fn pause_queue(queue: State<QueueState>) {
+    queue.0.pause();
+}
+
+#[tauri::command]
+pub fn resume_queue(queue: State<QueueState>) {
+    queue.0.resume();
+}
+
+/// Keeps a waiting job from starting, or releases it. Returns false if the
+/// job is not waiting.
+#[tauri::command]
+pub fn hold_job(queue: State<QueueState>, job_id: Uuid, held: bool) -> bool {
+    queue.0.hold(job_id, held)
+}
+
+/// Moves a waiting job to `position` among the waiting jobs, 0 starting next.
+#[tauri::command]
+pub fn reorder_job(queue: State<QueueState>, job_id: Uuid, position: usize) -> bool {
+    queue.0.reorder(job_id, position)
+}
+
+/// Cancels a waiting job or stops a running one at its next safe point.
+/// Returns false if the job has already finished.
+#[tauri::command]
+pub fn cancel_queued_job(queue: State<QueueState>, job_id: Uuid) -> bool {
+    queue.0.cancel(job_id)
+}
+
+#[tauri::command]
+pub fn set_queue_parallelism(queue: State<QueueState>, max_parallel: usize) {
+    queue.0.set_max_parallel(max_parallel);
+}
+
+#[tauri::command]
+pub fn clear_finished_jobs(queue: State<QueueState>) {
+    queue.0.clear_finished();
+}
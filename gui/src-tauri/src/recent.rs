@@ -0,0 +1,134 @@
+//! Recently scanned folders and recently used presets for the "recent"
+//! menus, kept with the settings so they survive restarts.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::error::CommandError;
+use crate::scope;
+use crate::settings::SettingsState;
+
+/// Unpinned entries kept of each kind; pinned ones are always kept.
+const MAX_RECENT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecentKind {
+    Folder,
+    Preset,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recent {
+    pub kind: RecentKind,
+    /// The folder path or the preset name.
+    pub value: String,
+    /// Kept at the top and never pushed out by newer entries.
+    pub pinned: bool,
+}
+
+/// Moves `value` to the front of its kind, adding it if new.
+fn add(recent: &mut Vec<Recent>, kind: RecentKind, value: String) {
+    let pinned = match recent
+        .iter()
+        .position(|r| r.kind == kind && r.value == value)
+    {
+        Some(i) => recent.remove(i).pinned,
+        None => false,
+    };
+    recent.insert(
+        0,
+        Recent {
+            kind,
+            value,
+            pinned,
+        },
+    );
+    trim(recent, kind);
+}
+
+/// Drops the oldest unpinned entries of `kind` past the limit.
+fn trim(recent: &mut Vec<Recent>, kind: RecentKind) {
+    let mut unpinned = 0;
+    recent.retain(|r| {
+        if r.kind != kind || r.pinned {
+            return true;
+        }
+        unpinned += 1;
+        unpinned <= MAX_RECENT
+    });
+}
+
+/// The recent entries of `kind`, or of both kinds, pinned first and then
+/// most recent first.
+#[tauri::command]
+pub fn get_recent(settings: State<SettingsState>, kind: Option<RecentKind>) -> Vec<Recent> {
+    let mut recent: Vec<Recent> = settings
+        .get()
+        .recent
+        .into_iter()
+        .filter(|r| kind.map_or(true, |kind| r.kind == kind))
+        .collect();
+    // Stable, so each group stays in most-recent order.
+    recent.sort_by_key(|r| !r.pinned);
+    recent
+}
+
+/// Recent folders are allowed again on the next start, so only folders in
+/// scope now can be added.
+fn check_folder(app: &AppHandle, kind: RecentKind, value: &str) -> Result<(), String> {
+    if kind == RecentKind::Folder && !scope::allows(app, Path::new(value)) {
+        return Err(format!(
+            "{}: outside the folders opened in this session",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Records that a folder was scanned or a preset used.
+#[tauri::command]
+pub fn add_recent(
+    app: AppHandle,
+    settings: State<SettingsState>,
+    kind: RecentKind,
+    value: String,
+) -> Result<(), CommandError> {
+    check_folder(&app, kind, &value)?;
+    settings.update(|settings| add(&mut settings.recent, kind, value));
+    Ok(())
+}
+
+/// Pins or unpins an entry, adding it first if it is not in the list.
+#[tauri::command]
+pub fn pin_recent(
+    app: AppHandle,
+    settings: State<SettingsState>,
+    kind: RecentKind,
+    value: String,
+    pinned: bool,
+) -> Result<(), CommandError> {
+    check_folder(&app, kind, &value)?;
+    settings.update(|settings| {
+        match settings
+            .recent
+            .iter_mut()
+            .find(|r| r.kind == kind && r.value == value)
+        {
+            Some(entry) => entry.pinned = pinned,
+            None => settings.recent.insert(
+                0,
+                Recent {
+                    kind,
+                    value,
+                    pinned,
+                },
+            ),
+        }
+        // Unpinning may leave one unpinned entry too many.
+        trim(&mut settings.recent, kind);
+    });
+    Ok(())
+}
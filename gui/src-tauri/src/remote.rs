@@ -0,0 +1,158 @@
+//! Renaming files on a remote host, over SFTP or WebDAV, keys in an S3
+//! bucket, or files on a phone or camera connected over MTP, which can also
+//! be imported into local folders; see `renamer_core::remote`.
+//! Each command logs in anew, so no connection is held between them, and
+//! remote paths are outside the session's scope, which only covers local
+//! folders.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use renamer_core::{
+    remote, ApplyReport, CancelToken, Error, MtpDevice, PreviewRow, RemoteTarget, RenameOp, Rule,
+    ScanEntry, ScanOptions, ScanPage,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::announce;
+use crate::error::CommandError;
+use crate::jobs::{self, ProgressKind};
+use crate::scope;
+use crate::settings::SettingsState;
+
+/// Lists `root` on the host, `.` being the folder a login starts in;
+/// entries come with absolute paths.
+#[tauri::command]
+pub async fn remote_scan(
+    target: RemoteTarget,
+    root: PathBuf,
+    options: Option<ScanOptions>,
+) -> Result<ScanPage, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut host = target.connect()?;
+        let root = host.absolute(&root).map_err(|e| Error::io(&root, e))?;
+        let options = options.unwrap_or_default();
+        Ok(remote::scan(
+            &mut *host,
+            &root,
+            &options,
+            &CancelToken::new(),
+        )?)
+    })
+    .await?
+}
+
+/// New names for entries `remote_scan` listed. Nothing is read from the
+/// host or from local files.
+#[tauri::command]
+pub async fn remote_preview(
+    entries: Vec<ScanEntry>,
+    rules: Vec<Rule>,
+) -> Result<Vec<PreviewRow>, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || remote::preview(&entries, &rules))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Renames files on the host. Refused in safe mode; the batch is not kept
+/// in the local history, so it cannot be undone from there. Progress is
+/// emitted as `job-progress` events, with the bytes copied for S3, and with
+/// a `job_id` `cancel_job` stops the batch.
+#[tauri::command]
+pub async fn remote_apply(
+    app: AppHandle,
+    target: RemoteTarget,
+    ops: Vec<RenameOp>,
+    job_id: Option<String>,
+) -> Result<ApplyReport, CommandError> {
+    app.state::<SettingsState>().check_safe_mode()?;
+    let job = jobs::register(&app, job_id.clone())?;
+    let reporter = job.reporter(ProgressKind::Apply);
+    // Bytes copied by earlier objects, and the one being copied with how
+    // far it got, for the throughput.
+    let copied: Mutex<(u64, PathBuf, u64)> = Mutex::default();
+    let applied = tauri::async_runtime::spawn_blocking(move || {
+        let mut host = target.connect()?;
+        Ok(remote::apply_with_progress(
+            &mut *host,
+            &ops,
+            &|p| {
+                let bytes = {
+                    let mut copied = copied.lock().unwrap();
+                    if copied.1 != p.current {
+                        copied.0 += copied.2;
+                        copied.1 = p.current.clone();
+                        copied.2 = 0;
+                    }
+                    copied.2 = copied.2.max(p.copied_bytes);
+                    copied.0 + copied.2
+                };
+                reporter.report(
+                    p.completed as u64,
+                    Some(p.total as u64),
+                    Some(&p.current),
+                    (bytes > 0).then_some(bytes),
+                );
+            },
+            &job.token,
+        ))
+    })
+    .await?;
+    announce::applied(&app, job_id, &applied);
+    applied
+}
+
+/// The phones and cameras connected over MTP, to open as
+/// `{ kind: "mtp", device }` targets.
+#[tauri::command]
+pub async fn remote_devices() -> Result<Vec<MtpDevice>, CommandError> {
+    tauri::async_runtime::spawn_blocking(remote::devices)
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Copies files off the host into local folders under new names; each op's
+/// source is a path on the host and its target a local path, which must be
+/// in the session's scope. Taken targets are skipped and nothing on the
+/// host changes. Progress is emitted as `job-progress` events, and with a
+/// `job_id` `cancel_job` stops the import.
+#[tauri::command]
+pub async fn remote_import(
+    app: AppHandle,
+    target: RemoteTarget,
+    ops: Vec<RenameOp>,
+    job_id: Option<String>,
+) -> Result<ApplyReport, CommandError> {
+    scope::check(&app, ops.iter().map(|op| &op.target))?;
+    let job = jobs::register(&app, job_id)?;
+    let reporter = job.reporter(ProgressKind::Apply);
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut host = target.connect()?;
+        Ok(remote::import(
+            &mut *host,
+            &ops,
+            &|p| {
+                reporter.report(
+                    p.completed as u64,
+                    Some(p.total as u64),
+                    Some(&p.current),
+                    None,
+                )
+            },
+            &job.token,
+        ))
+    })
+    .await?
+}
+
+/// Saves the password for `target` in the OS keychain, for logins with
+/// `password` set.
+#[tauri::command]
+pub async fn remote_save_password(
+    target: RemoteTarget,
+    password: String,
+) -> Result<(), CommandError> {
+    tauri::async_runtime::spawn_blocking(move || remote::save_password(&target, &password))
+        .await?
+        .map_err(CommandError::from)
+}
@@ -0,0 +1,498 @@
+//! A JSON-RPC 2.0 server on a loopback port for other programs, such as
+//! asset managers and scripts, to scan, preview, rename, undo and manage
+//! presets through the app. It is off unless `rpc.enabled` is set.
+//!
+//! Requests are single JSON-RPC calls POSTed over HTTP with the token in
+//! an `Authorization: Bearer` header; the token is kept in `rpc-token` in
+//! the app data folder, readable by the user alone, so a script running as
+//! them can pick it up. Calls go through the same commands as the webview
+//! does: the same scope, safe mode and history apply, renames report
+//! `job-progress`, and every call is announced as an `rpc-call` event when
+//! it starts and when it ends, so the window shows what is going on. Only
+//! folders scanned through `scan` or opened in the app can be previewed or
+//! renamed.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use renamer_core::{
+    ApplyOptions, BurstOptions, PlanOptions, Preset, RenameOp, Rule, ScanOptions, Scanner,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+use crate::engine::{self, PresetState};
+use crate::error::CommandError;
+use crate::settings::SettingsState;
+use crate::{paths, scope, sidecar};
+
+/// The port the server listens on unless the settings name another.
+const DEFAULT_PORT: u16 = 47_615;
+
+/// Largest request head and body taken.
+const MAX_HEAD: usize = 64 * 1024;
+const MAX_BODY: usize = 32 * 1024 * 1024;
+
+// JSON-RPC error codes; errors from the commands are `APP_ERROR`, with the
+// command's error as `data`.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const APP_ERROR: i64 = -32000;
+
+/// The server's part of the settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RpcSettings {
+    pub enabled: bool,
+    /// If not the default port.
+    pub port: Option<u16>,
+}
+
+struct Running {
+    port: u16,
+    task: JoinHandle<()>,
+}
+
+pub struct RpcState {
+    token_path: PathBuf,
+    token: Mutex<String>,
+    running: Mutex<Option<Running>>,
+    /// Why the server is not running though enabled.
+    error: Mutex<Option<String>>,
+}
+
+impl RpcState {
+    /// Reads the token, making one on first start.
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let token_path = paths::data_dir(app)
+            .map_err(|e| e.to_string())?
+            .join("rpc-token");
+        let token = match std::fs::read_to_string(&token_path) {
+            Ok(token) if !token.trim().is_empty() => token.trim().to_string(),
+            Ok(_) => write_token(&token_path)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => write_token(&token_path)?,
+            Err(e) => return Err(format!("{}: {}", token_path.display(), e)),
+        };
+        Ok(RpcState {
+            token_path,
+            token: Mutex::new(token),
+            running: Mutex::new(None),
+            error: Mutex::new(None),
+        })
+    }
+}
+
+/// Writes a new token where only the user can read it: to a file created
+/// readable by the user alone, which then replaces the old one, so the token
+/// is never in a file others can read, not even for a moment.
+fn write_token(path: &std::path::Path) -> Result<String, String> {
+    use std::io::Write;
+
+    let token = sidecar::new_token();
+    let failed = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(failed)?;
+    }
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+    let fresh = PathBuf::from(name);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options
+        .open(&fresh)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .and_then(|()| std::fs::rename(&fresh, path));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&fresh);
+        return Err(failed(e));
+    }
+    Ok(token)
+}
+
+/// Starts, stops or moves the server to match the settings.
+pub(crate) fn apply(app: &AppHandle) {
+    let settings = app.state::<SettingsState>().get().rpc;
+    let state = app.state::<RpcState>();
+    let mut running = state.running.lock().unwrap();
+    let port = settings.port.unwrap_or(DEFAULT_PORT);
+    if settings.enabled && running.as_ref().is_some_and(|r| r.port == port) {
+        return;
+    }
+    if let Some(previous) = running.take() {
+        previous.task.abort();
+    }
+    *state.error.lock().unwrap() = None;
+    if !settings.enabled {
+        return;
+    }
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener));
+    match listener {
+        Ok(listener) => {
+            let app = app.clone();
+            let task = tauri::async_runtime::spawn(async move {
+                match TcpListener::from_std(listener) {
+                    Ok(listener) => serve(app, listener).await,
+                    Err(e) => log::error!("automation server failed to start: {}", e),
+                }
+            });
+            *running = Some(Running { port, task });
+            log::info!("automation server listening on port {}", port);
+        }
+        Err(e) => {
+            log::error!("automation server cannot listen on port {}: {}", port, e);
+            *state.error.lock().unwrap() = Some(e.to_string());
+        }
+    }
+}
+
+async fn serve(app: AppHandle, listener: TcpListener) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let _ = connection(app, stream).await;
+        });
+    }
+}
+
+/// Answers one request on `stream`, then closes it.
+async fn connection(app: AppHandle, mut stream: TcpStream) -> std::io::Result<()> {
+    let (status, body) = match read_request(&app, &mut stream).await? {
+        Ok(body) => ("200 OK", handle(&app, &body).await),
+        Err((status, message)) => (status, json!({ "error": message }).to_string()),
+    };
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// The body of an authorized POST, or the HTTP status to refuse it with.
+async fn read_request(
+    app: &AppHandle,
+    stream: &mut TcpStream,
+) -> std::io::Result<Result<Vec<u8>, (&'static str, &'static str)>> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 8192];
+    let end = loop {
+        if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i;
+        }
+        if request.len() > MAX_HEAD {
+            return Ok(Err((
+                "431 Request Header Fields Too Large",
+                "headers too large",
+            )));
+        }
+        match stream.read(&mut buf).await? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    };
+    let head = String::from_utf8_lossy(&request[..end]).into_owned();
+    let mut body = request[end + 4..].to_vec();
+    let mut lines = head.lines();
+    if !lines.next().is_some_and(|line| line.starts_with("POST ")) {
+        return Ok(Err(("405 Method Not Allowed", "send calls with POST")));
+    }
+    let mut length = 0;
+    let mut authorized = false;
+    let token = app.state::<RpcState>().token.lock().unwrap().clone();
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value.parse().unwrap_or(usize::MAX);
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorized = value
+                .strip_prefix("Bearer ")
+                .is_some_and(|given| same(given.trim(), &token));
+        }
+    }
+    if !authorized {
+        return Ok(Err(("401 Unauthorized", "missing or wrong token")));
+    }
+    if length > MAX_BODY {
+        return Ok(Err(("413 Payload Too Large", "request too large")));
+    }
+    while body.len() < length {
+        match stream.read(&mut buf).await? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            n => body.extend_from_slice(&buf[..n]),
+        }
+    }
+    body.truncate(length);
+    Ok(Ok(body))
+}
+
+/// Compares tokens in time that does not depend on where they differ.
+fn same(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<CommandError>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<CommandError> for RpcError {
+    fn from(error: CommandError) -> Self {
+        RpcError {
+            code: APP_ERROR,
+            message: error.message.clone(),
+            data: Some(error),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct RpcCall {
+    method: String,
+    finished: bool,
+    error: Option<String>,
+}
+
+/// Runs the call in `body` and returns the JSON-RPC response.
+async fn handle(app: &AppHandle, body: &[u8]) -> String {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return response(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = match (request.get("jsonrpc"), request.get("method")) {
+        (Some(Value::String(version)), Some(Value::String(method))) if version == "2.0" => {
+            method.clone()
+        }
+        _ => {
+            let error = RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 call");
+            return response(id, Err(error));
+        }
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let announce = |finished, error| {
+        let _ = app.emit(
+            "rpc-call",
+            RpcCall {
+                method: method.clone(),
+                finished,
+                error,
+            },
+        );
+    };
+    announce(false, None);
+    let result = call(app, &method, params).await;
+    announce(true, result.as_ref().err().map(|e| e.message.clone()));
+    response(id, result)
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> String {
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message, "data": error.data },
+        }),
+    };
+    response.to_string()
+}
+
+/// Named parameters as `T`; none at all are an empty object.
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = match params {
+        Value::Null => json!({}),
+        params => params,
+    };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn result(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(APP_ERROR, e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct ScanParams {
+    root: PathBuf,
+    #[serde(default)]
+    options: ScanOptions,
+}
+
+#[derive(Deserialize)]
+struct PreviewParams {
+    paths: Vec<PathBuf>,
+    rules: Vec<Rule>,
+    keep_order: Option<bool>,
+    #[serde(default)]
+    overrides: Option<HashMap<PathBuf, String>>,
+    #[serde(default)]
+    bursts: Option<BurstOptions>,
+    #[serde(default)]
+    locale: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApplyParams {
+    ops: Vec<RenameOp>,
+    plan_options: Option<PlanOptions>,
+    apply_options: Option<ApplyOptions>,
+    job_id: Option<String>,
+    preset: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UndoFilesParams {
+    batch_id: Uuid,
+    file_ids: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct SavePresetParams {
+    preset: Preset,
+}
+
+#[derive(Deserialize)]
+struct PresetNameParams {
+    name: String,
+}
+
+async fn call(app: &AppHandle, method: &str, p: Value) -> Result<Value, RpcError> {
+    let app = app.clone();
+    match method {
+        // Scanning a folder brings it into scope, as picking it would.
+        "scan" => {
+            let ScanParams { root, options } = params(p)?;
+            let page = tauri::async_runtime::spawn_blocking(move || {
+                let page = Scanner::new(&root, options)?.next_page(usize::MAX);
+                scope::allow(&app, &root);
+                Ok::<_, CommandError>(page)
+            })
+            .await
+            .map_err(CommandError::from)??;
+            result(page)
+        }
+        "preview" => {
+            let PreviewParams {
+                paths,
+                rules,
+                keep_order,
+                overrides,
+                bursts,
+                locale,
+            } = params(p)?;
+            let preview =
+                engine::preview_renames(app, paths, rules, keep_order, overrides, bursts, locale);
+            result(preview.await?)
+        }
+        "apply" => {
+            let ApplyParams {
+                ops,
+                plan_options,
+                apply_options,
+                job_id,
+                preset,
+            } = params(p)?;
+            result(
+                engine::apply_renames(app, ops, plan_options, apply_options, job_id, preset)
+                    .await?,
+            )
+        }
+        "undo" => result(engine::undo_last_batch(app).await?),
+        "undo.files" => {
+            let UndoFilesParams { batch_id, file_ids } = params(p)?;
+            result(engine::undo_files(app, batch_id, file_ids).await?)
+        }
+        "presets.list" => result(engine::list_presets(app.state::<PresetState>())),
+        "presets.save" => {
+            let SavePresetParams { preset } = params(p)?;
+            result(engine::save_preset(app.state::<PresetState>(), preset)?)
+        }
+        "presets.delete" => {
+            let PresetNameParams { name } = params(p)?;
+            result(engine::delete_preset(app.state::<PresetState>(), name)?)
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("no method `{}`", method),
+        )),
+    }
+}
+
+#[derive(Serialize)]
+pub struct RpcInfo {
+    pub running: bool,
+    pub port: u16,
+    pub token: String,
+    pub error: Option<String>,
+}
+
+/// Whether the server runs and how to reach it, for the settings page to
+/// show to whoever sets up a script.
+#[tauri::command]
+pub fn get_rpc_server(app: AppHandle) -> RpcInfo {
+    let state = app.state::<RpcState>();
+    let running = state.running.lock().unwrap();
+    let port = match running.as_ref() {
+        Some(running) => running.port,
+        None => app
+            .state::<SettingsState>()
+            .get()
+            .rpc
+            .port
+            .unwrap_or(DEFAULT_PORT),
+    };
+    let token = state.token.lock().unwrap().clone();
+    let error = state.error.lock().unwrap().clone();
+    RpcInfo {
+        running: running.is_some(),
+        port,
+        token,
+        error,
+    }
+}
+
+/// Replaces the token, locking out every program given the old one.
+#[tauri::command]
+pub fn reset_rpc_token(app: AppHandle) -> Result<String, CommandError> {
+    let state = app.state::<RpcState>();
+    let token = write_token(&state.token_path)?;
+    *state.token.lock().unwrap() = token.clone();
+    Ok(token)
+}
@@ -0,0 +1,90 @@
+//! Which files the commands may touch: only those under what the user
+//! picked, dropped onto the window or opened the app with. The webview
+//! passes paths around freely, but cannot widen the scope itself, so a
+//! compromised page or a bad sidecar reply cannot rename, trash or read
+//! files elsewhere.
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use renamer_core::{PathScope, RenameOp};
+use tauri::{AppHandle, Manager};
+
+use crate::engine::PresetState;
+use crate::error::CommandError;
+use crate::recent::RecentKind;
+use crate::settings::SettingsState;
+
+pub struct ScopeState(RwLock<PathScope>);
+
+impl ScopeState {
+    /// Starts with the recent folders, which only ever held folders that
+    /// were in scope when they were added.
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let mut scope = PathScope::new();
+        let recent = app.state::<SettingsState>().get().recent;
+        for entry in recent.iter().filter(|r| r.kind == RecentKind::Folder) {
+            scope.allow(Path::new(&entry.value));
+        }
+        Ok(ScopeState(RwLock::new(scope)))
+    }
+}
+
+/// Allows sources the user chose. A file allows its folder, since renaming
+/// it writes next to it.
+pub(crate) fn allow_sources<'a>(app: &AppHandle, paths: impl IntoIterator<Item = &'a PathBuf>) {
+    let state = app.state::<ScopeState>();
+    let mut scope = state.0.write().unwrap();
+    for path in paths {
+        match path.parent() {
+            Some(parent) if !path.is_dir() => scope.allow(parent),
+            _ => scope.allow(path),
+        }
+    }
+}
+
+/// Allows exactly `path`, e.g. a file chosen to save a report to.
+pub(crate) fn allow(app: &AppHandle, path: &Path) {
+    app.state::<ScopeState>().0.write().unwrap().allow(path);
+}
+
+/// Fails on the first of `paths` outside the scope.
+pub(crate) fn check<'a>(
+    app: &AppHandle,
+    paths: impl IntoIterator<Item = &'a PathBuf>,
+) -> Result<(), CommandError> {
+    let state = app.state::<ScopeState>();
+    let scope = state.0.read().unwrap();
+    Ok(scope.check(paths.into_iter().map(PathBuf::as_path))?)
+}
+
+pub(crate) fn allows(app: &AppHandle, path: &Path) -> bool {
+    app.state::<ScopeState>().0.read().unwrap().allows(path)
+}
+
+/// [`check`] for the sources and targets of `ops`.
+pub(crate) fn check_ops(app: &AppHandle, ops: &[RenameOp]) -> Result<(), CommandError> {
+    check(app, ops.iter().flat_map(|op| [&op.source, &op.target]))
+}
+
+/// Fails if `ops` reach outside the folders the preset called `preset` may
+/// rename in; a preset that is not saved restricts nothing.
+pub(crate) fn check_preset(
+    app: &AppHandle,
+    preset: Option<&str>,
+    ops: &[RenameOp],
+) -> Result<(), CommandError> {
+    let Some(name) = preset else {
+        return Ok(());
+    };
+    let store = app.state::<PresetState>();
+    let store = store.0.lock().unwrap();
+    let Some(preset) = store.get(name) else {
+        return Ok(());
+    };
+    preset.check_scope(
+        ops.iter()
+            .flat_map(|op| [op.source.as_path(), op.target.as_path()]),
+    )?;
+    Ok(())
+}
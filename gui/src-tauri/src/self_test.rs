@@ -0,0 +1,283 @@
+//! A check of what the app needs and what it can do on this machine, for
+//! the first run and for when something misbehaves: whether the sidecar
+//! starts, whether the app data and cache folders can be written, how much
+//! room the caches have, and which optional parts work (HEIC previews,
+//! OCR, long paths).
+//!
+//! Missing optional parts are warnings; the app works without them. A
+//! sidecar that does not start or a data folder that cannot be written is a
+//! failure.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use renamer_core::environment::{self, Feature};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_shell::process::CommandEvent;
+
+use crate::diagnostics::{self, Versions};
+use crate::error::CommandError;
+use crate::settings::SettingsState;
+use crate::{paths, sidecar};
+
+/// How long a sidecar started for the test gets to print its usage.
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Below this much free space next to the caches, writing them fails soon.
+const MIN_FREE_MB: u64 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    /// Works, but not fully: an optional part is missing, or space is short.
+    Warning,
+    /// The app cannot work like this.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    /// Stable name of the check: `sidecar`, `data_dir`, `cache_dir`,
+    /// `cache_space`, `heif_previews`, `ocr` or `long_paths`.
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    /// An optional part: a warning when it is missing.
+    fn feature(name: &'static str, feature: Feature) -> Self {
+        let status = if feature.available {
+            CheckStatus::Passed
+        } else {
+            CheckStatus::Warning
+        };
+        Check::new(name, status, feature.detail)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub ran_at: DateTime<Utc>,
+    pub versions: Versions,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub checks: Vec<Check>,
+    /// The worst status of the checks.
+    pub status: CheckStatus,
+}
+
+/// Whether the sidecar runs, or else can be started: a sidecar that is up
+/// has to answer `/health`, one that is not is started to print its usage.
+async fn check_sidecar(app: &AppHandle) -> Check {
+    if sidecar::ping(app, Duration::from_secs(2)).await {
+        let version = sidecar::sidecar_version(app).unwrap_or_else(|| "unknown".into());
+        return Check::new(
+            "sidecar",
+            CheckStatus::Passed,
+            format!("running, version {}", version),
+        );
+    }
+    let failed = |detail: String| Check::new("sidecar", CheckStatus::Failed, detail);
+    let (mut events, child) = match sidecar::spawn_sidecar(app, &["--worker", "--help"]) {
+        Ok(spawned) => spawned,
+        Err(e) => return failed(format!("could not be started: {}", e)),
+    };
+    let exited = tokio::time::timeout(SPAWN_TIMEOUT, async {
+        while let Some(event) = events.recv().await {
+            if let CommandEvent::Terminated(payload) = event {
+                return payload.code;
+            }
+        }
+        None
+    })
+    .await;
+    match exited {
+        Ok(Some(0)) => Check::new(
+            "sidecar",
+            CheckStatus::Passed,
+            "starts, but is not running now",
+        ),
+        Ok(code) => failed(match code {
+            Some(code) => format!("exited with {} right after starting", code),
+            None => "stopped right after starting".into(),
+        }),
+        Err(_) => {
+            let _ = child.kill();
+            failed(format!(
+                "did not answer within {}s of starting",
+                SPAWN_TIMEOUT.as_secs()
+            ))
+        }
+    }
+}
+
+/// Writes, reads back and removes a file in `dir`, creating it first.
+fn check_writable(name: &'static str, dir: tauri::Result<PathBuf>) -> Check {
+    let dir = match dir {
+        Ok(dir) => dir,
+        Err(e) => return Check::new(name, CheckStatus::Failed, e.to_string()),
+    };
+    let probe = dir.join(format!(".self-test-{}", uuid::Uuid::new_v4().simple()));
+    let written = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&probe, b"ok"))
+        .and_then(|_| fs::read(&probe));
+    let _ = fs::remove_file(&probe);
+    match written {
+        Ok(read) if read == b"ok" => Check::new(
+            name,
+            CheckStatus::Passed,
+            format!("{} can be written", dir.display()),
+        ),
+        Ok(_) => Check::new(
+            name,
+            CheckStatus::Failed,
+            format!(
+                "{}: a file written there read back different",
+                dir.display()
+            ),
+        ),
+        Err(e) => Check::new(
+            name,
+            CheckStatus::Failed,
+            format!("{}: {}", dir.display(), e),
+        ),
+    }
+}
+
+/// Whether the disk of the caches has room for the thumbnail cache to grow
+/// to the size the settings allow.
+fn check_space(app: &AppHandle, dir: &Path) -> Check {
+    let Some(free) = dir.ancestors().find(|d| d.exists()).and_then(free_bytes) else {
+        return Check::new(
+            "cache_space",
+            CheckStatus::Warning,
+            format!("could not tell how much room is left at {}", dir.display()),
+        );
+    };
+    let free_mb = free / (1024 * 1024);
+    let cap = app
+        .state::<SettingsState>()
+        .all()
+        .retention
+        .thumbnail_cache_mb;
+    let status = if free_mb < MIN_FREE_MB {
+        CheckStatus::Failed
+    } else if cap.is_some_and(|cap| free_mb < cap) {
+        CheckStatus::Warning
+    } else {
+        CheckStatus::Passed
+    };
+    let detail = match cap {
+        Some(cap) => format!("{} MB free, thumbnails may take {} MB", free_mb, cap),
+        None => format!("{} MB free", free_mb),
+    };
+    Check::new("cache_space", status, detail)
+}
+
+/// The bytes left to the user on the volume of `path`.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn free_bytes(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `path` is a valid C string and `stat` is written by the call.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn free_bytes(path: &Path) -> Option<u64> {
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    /// Keeps PowerShell from flashing a console window.
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let script = format!(
+        "([System.IO.DriveInfo]::new('{}')).AvailableFreeSpace",
+        path.display().to_string().replace('\'', "''")
+    );
+    let out = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .stdin(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&out.stdout).trim().parse().ok()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn free_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Runs every check. Nothing is changed: the files written to try the
+/// folders are removed again, and a sidecar started for the test exits by
+/// itself.
+#[tauri::command]
+pub async fn run_self_test(app: AppHandle) -> Result<SelfTestReport, CommandError> {
+    let sidecar = check_sidecar(&app).await;
+    let ocr = app.state::<SettingsState>().all().ocr;
+    let handle = app.clone();
+    let mut checks = tauri::async_runtime::spawn_blocking(move || {
+        let cache = paths::cache_dir(&handle);
+        let mut checks = vec![
+            check_writable("data_dir", paths::data_dir(&handle)),
+            check_writable("cache_dir", paths::cache_dir(&handle)),
+        ];
+        if let Ok(cache) = &cache {
+            checks.push(check_space(&handle, cache));
+        }
+        checks.push(Check::feature(
+            "heif_previews",
+            environment::heif_previews(),
+        ));
+        checks.push(Check::feature("ocr", environment::ocr(&ocr)));
+        let scratch = cache.unwrap_or_else(|_| std::env::temp_dir());
+        checks.push(Check::feature(
+            "long_paths",
+            environment::long_paths(&scratch),
+        ));
+        checks
+    })
+    .await?;
+    checks.insert(0, sidecar);
+    for check in checks.iter().filter(|c| c.status != CheckStatus::Passed) {
+        log::warn!(
+            "self-test: {} {:?}: {}",
+            check.name,
+            check.status,
+            check.detail
+        );
+    }
+    Ok(SelfTestReport {
+        ran_at: Utc::now(),
+        versions: diagnostics::versions(&app),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        status: checks
+            .iter()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(CheckStatus::Passed),
+        checks,
+    })
+}
@@ -0,0 +1,263 @@
+//! The work in progress — loaded files, the chosen preset and the preview
+//! being reviewed — kept in the app data folder, so closing the window by
+//! accident or a crash does not lose a long review.
+//!
+//! Each window has a session of its own: the main window's is
+//! `session.json`, those opened with `open_window` are in `sessions/`, by
+//! window label. Two session windows can so hold the same folder with two
+//! rule chains, side by side; a preview window shows another window's session,
+//! reading it with `get_session` on every `session-saved` for it.
+//!
+//! The webview saves its session whenever it changes and asks for it back
+//! on start. Closing a window other than the main one discards its session;
+//! those left behind by a crash are listed by `list_sessions`, for a new
+//! window to take over. Window size and position are kept by the
+//! window-state plugin.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(desktop)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use renamer_core::PreviewRow;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State, Window};
+#[cfg(desktop)]
+use tauri::{WebviewUrl, WebviewWindowBuilder};
+
+use crate::error::CommandError;
+use crate::{paths, scope};
+
+/// The window opened at start, whose session is `session.json`.
+const MAIN: &str = "main";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// The file list, in the order shown.
+    pub paths: Vec<PathBuf>,
+    /// Name of the preset the rules came from.
+    pub preset: Option<String>,
+    /// The preview not yet applied, if one was shown.
+    pub preview: Option<Vec<PreviewRow>>,
+    /// Names edited by hand, by source path, to preview again with.
+    pub overrides: HashMap<PathBuf, String>,
+}
+
+pub struct SessionState {
+    dir: PathBuf,
+    /// Numbers the windows `open_window` opens.
+    #[cfg(desktop)]
+    next: AtomicUsize,
+}
+
+impl SessionState {
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let dir = paths::data_dir(app).map_err(|e| e.to_string())?;
+        Ok(SessionState {
+            dir,
+            #[cfg(desktop)]
+            next: AtomicUsize::new(1),
+        })
+    }
+
+    /// Where the session of the window labelled `label` is kept.
+    fn path(&self, label: &str) -> PathBuf {
+        if label == MAIN {
+            return self.dir.join("session.json");
+        }
+        let name: String = label
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        self.dir.join("sessions").join(format!("{}.json", name))
+    }
+}
+
+fn read(path: &Path) -> Option<Session> {
+    let json = fs::read(path).ok()?;
+    match serde_json::from_slice(&json) {
+        Ok(session) => Some(session),
+        Err(e) => {
+            log::error!("ignoring unreadable {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn remove(path: &Path) -> Result<(), CommandError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces the calling window's saved session and emits `session-saved`
+/// with its label. It is written next to the old one and moved over it, so
+/// a crash while writing leaves the previous session intact. Paths outside
+/// the scope are left out, since restoring allows them again.
+#[tauri::command]
+pub async fn save_session(
+    app: AppHandle,
+    window: Window,
+    session: State<'_, SessionState>,
+    mut current: Session,
+) -> Result<(), CommandError> {
+    current.paths.retain(|p| scope::allows(&app, p));
+    current.overrides.retain(|p, _| scope::allows(&app, p));
+    let path = session.path(window.label());
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("json.partial");
+        let json = serde_json::to_vec(&current)?;
+        fs::write(&partial, json)?;
+        fs::rename(&partial, &path).map_err(CommandError::from)
+    })
+    .await??;
+    let _ = app.emit("session-saved", window.label());
+    Ok(())
+}
+
+/// The session the calling window saved in the last run, or `None` if there
+/// is none or it cannot be read. Its files are allowed again. With `from`,
+/// the session left by that window is taken over instead, and is this
+/// window's from then on.
+#[tauri::command]
+pub async fn restore_session(
+    app: AppHandle,
+    window: Window,
+    session: State<'_, SessionState>,
+    from: Option<String>,
+) -> Result<Option<Session>, CommandError> {
+    let path = session.path(window.label());
+    let from = from.map(|label| session.path(&label));
+    let restored = tauri::async_runtime::spawn_blocking(move || {
+        let Some(from) = from.filter(|from| *from != path) else {
+            return Ok::<_, CommandError>(read(&path));
+        };
+        let restored = read(&from);
+        if restored.is_some() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::rename(&from, &path)?;
+        }
+        Ok(restored)
+    })
+    .await??;
+    if let Some(session) = &restored {
+        scope::allow_sources(&app, &session.paths);
+    }
+    Ok(restored)
+}
+
+/// The session the window labelled `label` last saved, for a preview
+/// window to show; it stays that window's.
+#[tauri::command]
+pub async fn get_session(
+    session: State<'_, SessionState>,
+    label: String,
+) -> Result<Option<Session>, CommandError> {
+    let path = session.path(&label);
+    Ok(tauri::async_runtime::spawn_blocking(move || read(&path)).await?)
+}
+
+/// Forgets the calling window's saved session, once its renames are
+/// applied or discarded.
+#[tauri::command]
+pub fn clear_session(window: Window, session: State<SessionState>) -> Result<(), CommandError> {
+    remove(&session.path(window.label()))
+}
+
+/// Labels of the sessions saved by windows no longer open, as a crash
+/// leaves them.
+#[tauri::command]
+pub fn list_sessions(app: AppHandle, session: State<SessionState>) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(session.dir.join("sessions")) else {
+        return Vec::new();
+    };
+    let open = app.webview_windows();
+    let mut labels: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_suffix(".json").map(str::to_string)
+        })
+        .filter(|label| !open.contains_key(label))
+        .collect();
+    labels.sort();
+    labels
+}
+
+#[cfg(desktop)]
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowKind {
+    /// A session of its own, with its own files and rules.
+    Session,
+    /// Another window's preview, full-screen.
+    Preview,
+}
+
+/// Opens another window and returns its label. It loads
+/// `index.html?window=session` or, for a preview of the window labelled
+/// `of`, `index.html?window=preview&of=<label>`. Android and iOS have the
+/// one window only.
+#[cfg(desktop)]
+#[tauri::command]
+pub async fn open_window(
+    app: AppHandle,
+    session: State<'_, SessionState>,
+    kind: WindowKind,
+    of: Option<String>,
+) -> Result<String, CommandError> {
+    let (prefix, url, title) = match kind {
+        WindowKind::Session => (
+            "session",
+            "index.html?window=session".to_string(),
+            "Sortify",
+        ),
+        WindowKind::Preview => {
+            let of = of.ok_or("a preview window needs the window it previews")?;
+            if app.get_webview_window(&of).is_none() {
+                return Err(format!("no window labelled `{}`", of).into());
+            }
+            let url = format!("index.html?window=preview&of={}", of);
+            ("preview", url, "Sortify preview")
+        }
+    };
+    // Skips the labels of sessions a crash left, so they can be taken over.
+    let label = loop {
+        let n = session.next.fetch_add(1, Ordering::Relaxed);
+        let label = format!("{}-{}", prefix, n);
+        if app.get_webview_window(&label).is_none() && !session.path(&label).exists() {
+            break label;
+        }
+    };
+    let mut builder = WebviewWindowBuilder::new(&app, &label, WebviewUrl::App(url.into()))
+        .title(title)
+        .inner_size(1200.0, 800.0)
+        .min_inner_size(1024.0, 768.0);
+    if let WindowKind::Preview = kind {
+        builder = builder.maximized(true);
+    }
+    builder.build()?;
+    Ok(label)
+}
+
+/// Discards the session of a window other than the main one as it closes.
+pub(crate) fn window_closed(window: &Window) {
+    if window.label() == MAIN {
+        return;
+    }
+    let session = window.state::<SessionState>();
+    if let Err(e) = remove(&session.path(window.label())) {
+        log::error!("failed to discard the session of {}: {}", window.label(), e);
+    }
+}
@@ -0,0 +1,260 @@
+//! The app's settings, kept with those it shares with the CLI and folder
+//! watchers in `settings.json` in the app data folder; see
+//! `renamer_core::settings` for the file and its versions.
+//!
+//! Every change is read, changed and written back in one go, so one made by
+//! the CLI meanwhile is kept, and is announced with `settings-changed`
+//! carrying the new settings. A change the CLI makes while the app runs is
+//! seen by the app at its next change or start.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use renamer_core::{ApplyOptions, Error, SettingsStore};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::app_log::LogLevel;
+use crate::error::CommandError;
+use crate::monitor::SidecarLimits;
+use crate::recent::Recent;
+use crate::rpc::RpcSettings;
+#[cfg(desktop)]
+use crate::updates::{Deferral, UpdateChannel};
+use crate::watch::WatchState;
+use crate::{crash, paths, rpc};
+#[cfg(desktop)]
+use crate::{tray, workers};
+
+/// What the app remembers between runs that is not a preset or history.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Where the last file picker was left, to open the next one there.
+    pub last_dir: Option<PathBuf>,
+    /// The preset the last batch renamed from the app used, which the tray
+    /// runs on copied files.
+    pub last_preset: Option<String>,
+    /// The preset quick rename applies to copied files; the last preset
+    /// when not set.
+    pub quick_rename_preset: Option<String>,
+    /// The global shortcut that opens the quick rename palette; see
+    /// `palette`.
+    pub quick_rename_shortcut: Option<String>,
+    /// Recent folders and presets, most recent first.
+    pub recent: Vec<Recent>,
+    /// How much the app logs; see `app_log`.
+    pub log_level: LogLevel,
+    /// How long the sidecar gets to answer after starting, if not the
+    /// default 30 seconds.
+    pub sidecar_timeout_secs: Option<u64>,
+    /// How many worker sidecars take the heavy requests; none by default.
+    /// See `workers`.
+    pub sidecar_workers: usize,
+    /// When the sidecar is restarted as runaway or hung; see `monitor`.
+    pub sidecar_limits: SidecarLimits,
+    /// The sidebar was narrowed to its icons.
+    pub sidebar_collapsed: bool,
+    /// Which releases to update to; see `updates`. The stores update the
+    /// app on Android and iOS.
+    #[cfg(desktop)]
+    pub update_channel: UpdateChannel,
+    /// An update the user asked to be reminded about later.
+    #[cfg(desktop)]
+    pub update_deferred: Option<Deferral>,
+    /// The automation server; see `rpc`.
+    pub rpc: RpcSettings,
+    /// Writes a report when the app crashes; see `crash`. Off unless the
+    /// user turns it on.
+    pub crash_reports: bool,
+    /// Announces progress to screen readers every tenth of a job rather
+    /// than every quarter, naming the file; see `announce`.
+    pub verbose_announcements: bool,
+}
+
+pub type Settings = renamer_core::Settings<AppSettings>;
+
+pub struct SettingsState {
+    app: AppHandle,
+    store: SettingsStore,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsState {
+    /// A missing or unreadable settings file gives the defaults.
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let path = paths::data_dir(app)
+            .map_err(|e| e.to_string())?
+            .join("settings.json");
+        let store = SettingsStore::new(path);
+        let settings = store.load().unwrap_or_else(|e| {
+            log::error!("failed to read settings: {}", e);
+            Settings::default()
+        });
+        use_ocr(&settings);
+        use_locale(&settings);
+        use_policy(&settings);
+        renamer_core::throttle::set(settings.io_throttle);
+        Ok(SettingsState {
+            app: app.clone(),
+            store,
+            settings: Mutex::new(settings),
+        })
+    }
+
+    /// The app's own settings.
+    pub fn get(&self) -> AppSettings {
+        self.settings.lock().unwrap().app.clone()
+    }
+
+    /// Everything in the settings file.
+    pub fn all(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// How batches the user was not asked about are applied.
+    pub fn apply_options(&self) -> ApplyOptions {
+        self.settings.lock().unwrap().apply.clone()
+    }
+
+    /// `options` as sent by the frontend, with safe mode on if the settings
+    /// have it, so the webview cannot turn it off for one batch.
+    pub fn enforce(&self, mut options: ApplyOptions) -> ApplyOptions {
+        options.safe_mode |= self.settings.lock().unwrap().apply.safe_mode;
+        options
+    }
+
+    /// Fails with `safe_mode` while safe mode is on; for commands that move
+    /// or trash files without `renamer_core::apply`, which refuses by itself.
+    pub fn check_safe_mode(&self) -> Result<(), CommandError> {
+        if self.settings.lock().unwrap().apply.safe_mode {
+            return Err(Error::SafeMode.into());
+        }
+        Ok(())
+    }
+
+    /// Whether changes are written to the audit log.
+    pub fn audit_log(&self) -> bool {
+        self.settings.lock().unwrap().audit_log
+    }
+
+    /// Changes the app's settings and writes them out; failing to write is
+    /// only logged.
+    pub fn update(&self, change: impl FnOnce(&mut AppSettings)) {
+        let changed = self.change(|settings| {
+            change(&mut settings.app);
+            Ok(())
+        });
+        if let Err(e) = changed {
+            log::error!("failed to save settings: {}", e);
+        }
+    }
+
+    /// Changes the settings as they are on disk and writes them out. A file
+    /// too broken to read is replaced; one from a newer version is not.
+    pub fn change(
+        &self,
+        change: impl FnOnce(&mut Settings) -> Result<(), String>,
+    ) -> Result<Settings, String> {
+        let mut current = self.settings.lock().unwrap();
+        let mut settings = match self.store.load() {
+            Ok(settings) => settings,
+            Err(e @ Error::UnsupportedVersion { .. }) => return Err(e.to_string()),
+            Err(e) => {
+                log::warn!("replacing unreadable settings: {}", e);
+                current.clone()
+            }
+        };
+        change(&mut settings)?;
+        self.store.save(&settings).map_err(|e| e.to_string())?;
+        *current = settings.clone();
+        drop(current);
+        let _ = self.app.emit("settings-changed", &settings);
+        Ok(settings)
+    }
+}
+
+/// Settings `update_settings` leaves alone. Only the commands of `recent`
+/// and `dialogs` put folders in them, each checked against the scope, since
+/// the scope starts with the recent folders at the next start.
+const CHANGED_BY_COMMANDS: &[&str] = &["/app/recent", "/app/last_dir"];
+
+#[tauri::command]
+pub fn get_settings(settings: State<SettingsState>) -> Settings {
+    settings.all()
+}
+
+/// Changes the settings by a JSON merge patch, e.g.
+/// `{"app": {"sidebar_collapsed": true}}`; a removed key goes back to its
+/// default, and the recent files and folders and the last folder are left
+/// as they are. Returns the settings as saved. Changes to the log level, the
+/// number of workers and the automation server take effect at once.
+#[tauri::command]
+pub fn update_settings(
+    app: AppHandle,
+    settings: State<SettingsState>,
+    patch: Value,
+) -> Result<Settings, CommandError> {
+    let saved = settings.change(|settings| {
+        *settings = settings
+            .patched(patch, CHANGED_BY_COMMANDS)
+            .map_err(|e| e.to_string())?;
+        if let Some(tag) = &settings.locale {
+            renamer_core::locale::parse(tag).map_err(|e| e.to_string())?;
+        }
+        if let Some(path) = &settings.naming_policy {
+            renamer_core::policy::load(path).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    })?;
+    applied(&app, &saved);
+    Ok(saved)
+}
+
+/// Has `{ocr.*}` tokens read files with the program the settings name.
+fn use_ocr(settings: &Settings) {
+    renamer_core::ocr::set_engine(Some(Arc::new(settings.ocr.clone())));
+}
+
+/// Has template tokens write names and numbers in the locale the settings
+/// name, or the system's.
+fn use_locale(settings: &Settings) {
+    let locale = settings.locale.as_deref().map(renamer_core::locale::parse);
+    renamer_core::locale::set_default(locale.and_then(Result::ok));
+}
+
+/// Has every plan checked against the naming policy the settings name. A
+/// policy file that cannot be read is logged, and blocks every plan until
+/// it can be, as the CLI refuses to rename without it.
+fn use_policy(settings: &Settings) {
+    match &settings.naming_policy {
+        Some(path) => {
+            if let Err(e) = renamer_core::policy::enforce_file(path) {
+                log::error!("failed to read the naming policy: {}", e);
+            }
+        }
+        None => renamer_core::policy::set_enforced(None),
+    }
+}
+
+/// Puts into effect settings just saved that take effect at once. Turning
+/// safe mode on pauses the folder watches, which were started with the
+/// options of before; they stay paused until resumed.
+pub(crate) fn applied(app: &AppHandle, settings: &Settings) {
+    log::set_max_level(settings.app.log_level.into());
+    crash::set_enabled(settings.app.crash_reports);
+    use_ocr(settings);
+    use_locale(settings);
+    use_policy(settings);
+    renamer_core::throttle::set(settings.io_throttle);
+    #[cfg(desktop)]
+    workers::resize(app);
+    rpc::apply(app);
+    let watches = app.state::<WatchState>();
+    if settings.apply.safe_mode && !watches.is_paused() {
+        watches.set_paused(true);
+        #[cfg(desktop)]
+        tray::refresh(app);
+    }
+}
@@ -0,0 +1,311 @@
+//! "Rename with Sortify" in Explorer's context menu and as a Finder quick
+//! action, and the paths the app was opened with.
+//!
+//! On Windows the entry is a verb under `HKEY_CURRENT_USER\Software\Classes`
+//! for files and folders, so no administrator rights are needed. On macOS it
+//! is an Automator service in `~/Library/Services` that hands the selection
+//! to the app the way double-clicking a document does.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::CommandError;
+use crate::{palette, scope, tray};
+
+/// Paths the app was started or asked to open with, until the webview
+/// takes them.
+#[derive(Default)]
+pub struct LaunchState(Mutex<Vec<PathBuf>>);
+
+/// Queues the paths among the command-line arguments, made absolute, so
+/// launching the app on a folder starts with it loaded, and opens the quick
+/// rename palette if asked to. Other flags and anything else are left for
+/// whoever else reads them.
+pub(crate) fn open_launch_args(app: &AppHandle) {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    if std::env::args_os().any(|arg| arg == palette::ARG) {
+        palette::open(app);
+    }
+    open_paths(app, std::env::args_os().skip(1).map(|arg| cwd.join(arg)));
+}
+
+fn existing(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    paths.into_iter().filter(|p| p.exists()).collect()
+}
+
+/// Queues `paths` for the file list and emits `paths-opened`, on which the
+/// webview calls `get_launch_paths`. Paths that do not exist are dropped.
+pub(crate) fn open_paths(app: &AppHandle, paths: impl IntoIterator<Item = PathBuf>) {
+    let paths = existing(paths);
+    if paths.is_empty() {
+        return;
+    }
+    let count = paths.len();
+    scope::allow_sources(app, &paths);
+    app.state::<LaunchState>().0.lock().unwrap().extend(paths);
+    let _ = app.emit("paths-opened", count);
+}
+
+/// Handles a second launch of the app: its path arguments, relative to the
+/// folder it was started in, go to this instance's file list, and the
+/// window comes to the front, or the quick rename palette if that was asked
+/// for.
+pub(crate) fn second_instance(app: &AppHandle, args: Vec<String>, cwd: String) {
+    if args.iter().any(|arg| arg == palette::ARG) {
+        palette::open(app);
+        return;
+    }
+    let cwd = PathBuf::from(cwd);
+    open_paths(app, args.into_iter().skip(1).map(|arg| cwd.join(arg)));
+    tray::show_window(app);
+}
+
+/// The paths the app was opened with since the last call, to preload into
+/// the file list. The webview calls it once on start for the launch
+/// arguments and again on every `paths-opened`.
+#[tauri::command]
+pub fn get_launch_paths(launch: State<LaunchState>) -> Vec<PathBuf> {
+    std::mem::take(&mut *launch.0.lock().unwrap())
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+const MENU_TEXT: &str = "Rename with Sortify";
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::path::Path;
+    use std::process::Command;
+
+    use super::MENU_TEXT;
+    use crate::error::CommandError;
+
+    /// Files, and folders both when selected and when right-clicking one.
+    const KEYS: &[&str] = &[
+        r"HKCU\Software\Classes\*\shell\Sortify",
+        r"HKCU\Software\Classes\Directory\shell\Sortify",
+    ];
+
+    fn reg(args: &[&str]) -> Result<(), String> {
+        let out = Command::new("reg")
+            .args(args)
+            .output()
+            .map_err(|e| e.to_string())?;
+        if out.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&out.stderr).trim().to_string())
+        }
+    }
+
+    pub fn install(exe: &Path, _identifier: &str) -> Result<(), CommandError> {
+        let exe = exe.display().to_string();
+        let command = format!("\"{}\" \"%1\"", exe);
+        for key in KEYS {
+            reg(&["add", key, "/ve", "/d", MENU_TEXT, "/f"])?;
+            reg(&["add", key, "/v", "Icon", "/d", &exe, "/f"])?;
+            let command_key = format!(r"{}\command", key);
+            reg(&["add", &command_key, "/ve", "/d", &command, "/f"])?;
+        }
+        Ok(())
+    }
+
+    pub fn uninstall() -> Result<(), CommandError> {
+        for key in KEYS {
+            if installed_key(key) {
+                reg(&["delete", key, "/f"])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn installed_key(key: &str) -> bool {
+        reg(&["query", key]).is_ok()
+    }
+
+    pub fn is_installed() -> bool {
+        KEYS.iter().all(|key| installed_key(key))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::MENU_TEXT;
+    use crate::error::CommandError;
+
+    fn workflow() -> Result<PathBuf, String> {
+        let home = std::env::var_os("HOME").ok_or("HOME is not set")?;
+        Ok(PathBuf::from(home)
+            .join("Library/Services")
+            .join(format!("{}.workflow", MENU_TEXT)))
+    }
+
+    const INFO_PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>NSServices</key>
+	<array>
+		<dict>
+			<key>NSMenuItem</key>
+			<dict>
+				<key>default</key>
+				<string>MENU_TEXT</string>
+			</dict>
+			<key>NSMessage</key>
+			<string>runWorkflowAsService</string>
+			<key>NSRequiredContext</key>
+			<dict>
+				<key>NSApplicationIdentifier</key>
+				<string>com.apple.finder</string>
+			</dict>
+			<key>NSSendFileTypes</key>
+			<array>
+				<string>public.item</string>
+			</array>
+		</dict>
+	</array>
+</dict>
+</plist>
+"#;
+
+    /// A one-action workflow: pass the selected files to the app.
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>AMApplicationBuild</key>
+	<string>523</string>
+	<key>AMApplicationVersion</key>
+	<string>2.10</string>
+	<key>AMDocumentVersion</key>
+	<string>2</string>
+	<key>actions</key>
+	<array>
+		<dict>
+			<key>action</key>
+			<dict>
+				<key>AMAccepts</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Optional</key>
+					<true/>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.path</string>
+					</array>
+				</dict>
+				<key>AMActionVersion</key>
+				<string>2.0.3</string>
+				<key>ActionBundlePath</key>
+				<string>/System/Library/Automator/Run Shell Script.action</string>
+				<key>ActionName</key>
+				<string>Run Shell Script</string>
+				<key>ActionParameters</key>
+				<dict>
+					<key>COMMAND_STRING</key>
+					<string>open -b IDENTIFIER "$@"</string>
+					<key>CheckedForUserDefaultShell</key>
+					<true/>
+					<key>inputMethod</key>
+					<integer>1</integer>
+					<key>shell</key>
+					<string>/bin/sh</string>
+					<key>source</key>
+					<string></string>
+				</dict>
+				<key>BundleIdentifier</key>
+				<string>com.apple.RunShellScript</string>
+				<key>CFBundleVersion</key>
+				<string>2.0.3</string>
+				<key>Class Name</key>
+				<string>RunShellScriptAction</string>
+			</dict>
+		</dict>
+	</array>
+	<key>connectors</key>
+	<dict/>
+	<key>workflowMetaData</key>
+	<dict>
+		<key>serviceInputTypeIdentifier</key>
+		<string>com.apple.Automator.fileSystemObject</string>
+		<key>serviceOutputTypeIdentifier</key>
+		<string>com.apple.Automator.nothing</string>
+		<key>workflowTypeIdentifier</key>
+		<string>com.apple.Automator.servicesMenu</string>
+	</dict>
+</dict>
+</plist>
+"#;
+
+    pub fn install(_exe: &Path, identifier: &str) -> Result<(), CommandError> {
+        let contents = workflow()?.join("Contents");
+        fs::create_dir_all(&contents)?;
+        fs::write(
+            contents.join("Info.plist"),
+            INFO_PLIST.replace("MENU_TEXT", MENU_TEXT),
+        )?;
+        fs::write(
+            contents.join("document.wflow"),
+            DOCUMENT.replace("IDENTIFIER", identifier),
+        )
+        .map_err(CommandError::from)
+    }
+
+    pub fn uninstall() -> Result<(), CommandError> {
+        let workflow = workflow()?;
+        if workflow.exists() {
+            fs::remove_dir_all(&workflow)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_installed() -> bool {
+        workflow().is_ok_and(|w| w.join("Contents/document.wflow").exists())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::path::Path;
+
+    use crate::error::{AppErrorCode, CommandError};
+
+    pub fn install(_exe: &Path, _identifier: &str) -> Result<(), CommandError> {
+        Err(CommandError::new(
+            AppErrorCode::Unsupported,
+            "shell integration is only available on Windows and macOS",
+        ))
+    }
+
+    pub fn uninstall() -> Result<(), CommandError> {
+        Ok(())
+    }
+
+    pub fn is_installed() -> bool {
+        false
+    }
+}
+
+/// Adds "Rename with Sortify" to Explorer's context menu or Finder's quick
+/// actions, opening the app with the selected files in the list.
+#[tauri::command]
+pub fn install_shell_integration(app: AppHandle) -> Result<(), CommandError> {
+    let exe = std::env::current_exe()?;
+    platform::install(&exe, &app.config().identifier)
+}
+
+#[tauri::command]
+pub fn uninstall_shell_integration() -> Result<(), CommandError> {
+    platform::uninstall()
+}
+
+#[tauri::command]
+pub fn shell_integration_installed() -> bool {
+    platform::is_installed()
+}
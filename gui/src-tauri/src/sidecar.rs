@@ -0,0 +1,1020 @@
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::async_runtime::Receiver;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent, TerminatedPayload};
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::oneshot;
+
+use crate::error::{AppErrorCode, CommandError};
+use crate::settings::SettingsState;
+use crate::sidecar_log::{self, Source};
+use crate::{monitor, orphans, paths, scope, suggest, workers};
+
+const SIDECAR_NAME: &str = "renamer-api";
+
+/// Environment variable the sidecar reads its API token from. An argument
+/// would show up in every other process's view of the command line.
+const TOKEN_ENV: &str = "SORTIFY_API_TOKEN";
+
+// Backoff between restarts doubles up to MAX_BACKOFF and resets once the
+// sidecar has stayed up for STABLE_UPTIME.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+// Readiness polling of `/health` after each (re)start. The timeout can be
+// raised in the settings for machines where the sidecar starts slowly.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How long the sidecar gets to exit after `/shutdown` before it is killed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+
+// Requests proxied for the frontend give up after REQUEST_TIMEOUT unless it
+// asks for longer. One that never reached the sidecar, e.g. because it was
+// restarting, is sent again up to REQUEST_RETRIES times.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+const REQUEST_RETRIES: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Starts stdout lines that answer a request sent over stdin; every other
+/// line is the sidecar's own output and is logged.
+const FRAME_PREFIX: &[u8] = b"@sortify-rpc ";
+
+/// Where a portable app's sidecar keeps its config, history and logs,
+/// instead of the user's home folder.
+const DATA_DIR_ENV: &str = "SORTIFY_DATA_DIR";
+
+/// Set to `http` to keep the sidecar on its port, e.g. to try the API with
+/// curl.
+const TRANSPORT_ENV: &str = "SORTIFY_SIDECAR_TRANSPORT";
+
+/// How the app talks to the sidecar. Requests go over its stdin and stdout
+/// when it answers there, which needs no port and so no firewall prompt;
+/// builds that only listen on the port are reached over HTTP instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    #[default]
+    Http,
+    Stdio,
+}
+
+/// A request sent to the sidecar over stdin, one JSON line each.
+#[derive(Serialize)]
+struct RequestFrame<'a> {
+    id: u64,
+    method: &'a str,
+    path: &'a str,
+    body: Option<&'a Value>,
+}
+
+/// The sidecar's answer to the request with the same `id`.
+#[derive(Deserialize)]
+struct ResponseFrame {
+    id: u64,
+    status: u16,
+    #[serde(default)]
+    body: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiResponse {
+    pub status: u16,
+    /// The JSON body, or the text of one that is not JSON.
+    pub body: Value,
+}
+
+type Pending = HashMap<u64, oneshot::Sender<ApiResponse>>;
+
+/// A running sidecar reached over its stdin and stdout, with the requests
+/// sent to it that have not been answered yet.
+#[derive(Default)]
+pub(crate) struct Link {
+    child: Mutex<Option<CommandChild>>,
+    pending: Mutex<Pending>,
+    next_request: AtomicU64,
+}
+
+impl Link {
+    pub(crate) fn attach(&self, child: CommandChild) {
+        self.pending.lock().unwrap().clear();
+        *self.child.lock().unwrap() = Some(child);
+    }
+
+    /// Lets go of the process, e.g. once it exited. Dropping the senders
+    /// fails the requests still waiting.
+    pub(crate) fn detach(&self) -> Option<CommandChild> {
+        let child = self.child.lock().unwrap().take();
+        self.pending.lock().unwrap().clear();
+        child
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.child.lock().unwrap().is_some()
+    }
+
+    pub(crate) fn pid(&self) -> Option<u32> {
+        self.child.lock().unwrap().as_ref().map(CommandChild::pid)
+    }
+
+    /// Writes the request to the process's stdin and waits for the stdout
+    /// line answering it. Fails if the process exits first.
+    pub(crate) async fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<ApiResponse, Failure> {
+        let id = self.next_request.fetch_add(1, Ordering::Relaxed);
+        let mut line = serde_json::to_vec(&RequestFrame {
+            id,
+            method,
+            path,
+            body,
+        })
+        .map_err(|e| Failure::Unsent(e.to_string()))?;
+        line.push(b'\n');
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        let written = match self.child.lock().unwrap().as_mut() {
+            Some(child) => child
+                .write(&line)
+                .map_err(|e| Failure::Unsent(e.to_string())),
+            None => Err(Failure::Unsent("renamer-api is not running".to_string())),
+        };
+        if let Err(e) = written {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+        rx.await
+            .map_err(|_| Failure::Lost("renamer-api exited before answering".to_string()))
+    }
+
+    /// Hands a `FRAME_PREFIX` line to the request waiting for it. Returns
+    /// false for any other line.
+    pub(crate) fn answer(&self, line: &[u8]) -> bool {
+        let end = line
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        let Some(frame) = line[..end].strip_prefix(FRAME_PREFIX) else {
+            return false;
+        };
+        match serde_json::from_slice::<ResponseFrame>(frame) {
+            Ok(frame) => {
+                let waiting = self.pending.lock().unwrap().remove(&frame.id);
+                // Nobody waits for answers that came after their probe gave up.
+                if let Some(tx) = waiting {
+                    let _ = tx.send(ApiResponse {
+                        status: frame.status,
+                        body: frame.body,
+                    });
+                }
+            }
+            Err(e) => log::warn!("[PY]: unreadable response frame: {}", e),
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SidecarErrorKind {
+    /// The binary is not where the app expects it.
+    Missing,
+    /// The system or security software refused to run it.
+    Blocked,
+    /// No local port could be opened for it.
+    NoPort,
+    /// Spawning it failed for another reason.
+    SpawnFailed,
+    /// It started but did not answer in time.
+    Timeout,
+    /// It exited before it became ready.
+    Crashed,
+}
+
+/// Why the sidecar is not running, with what the user can do about it;
+/// the payload of `sidecar-error`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SidecarError {
+    pub kind: SidecarErrorKind,
+    pub message: String,
+    pub hints: Vec<String>,
+}
+
+impl SidecarError {
+    fn new(kind: SidecarErrorKind, message: String) -> Self {
+        let hints: &[&str] = match kind {
+            SidecarErrorKind::Missing => &[
+                "Reinstall Sortify: the renamer-api program that comes with it is missing.",
+                "Security software may have quarantined renamer-api; restore it and allow it to run.",
+            ],
+            SidecarErrorKind::Blocked => &[
+                "Security software may be blocking renamer-api; allow it to run and retry.",
+                "On macOS and Linux, check that renamer-api next to the app is executable.",
+            ],
+            SidecarErrorKind::NoPort => &[
+                "Check that firewall or security software lets Sortify listen on 127.0.0.1.",
+            ],
+            SidecarErrorKind::SpawnFailed => &[
+                "Restart Sortify; if it keeps failing, create a diagnostic bundle and report it.",
+            ],
+            SidecarErrorKind::Timeout => &[
+                "On a slow or busy machine, retry with a longer startup timeout.",
+                "Security software scanning renamer-api when it starts can slow it down.",
+            ],
+            SidecarErrorKind::Crashed => &[
+                "The sidecar log in the diagnostic bundle shows what it exited with.",
+            ],
+        };
+        SidecarError {
+            kind,
+            message,
+            hints: hints.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+
+    fn spawn(error: &tauri_plugin_shell::Error) -> Self {
+        use std::io::ErrorKind;
+        use tauri_plugin_shell::Error;
+
+        // Windows refuses files flagged by antivirus with these codes.
+        const ERROR_VIRUS_INFECTED: i32 = 225;
+        const ERROR_VIRUS_DELETED: i32 = 226;
+        let kind = match error {
+            Error::Io(e) if e.kind() == ErrorKind::NotFound => SidecarErrorKind::Missing,
+            Error::Io(e) if e.kind() == ErrorKind::PermissionDenied => SidecarErrorKind::Blocked,
+            Error::Io(e)
+                if matches!(
+                    e.raw_os_error(),
+                    Some(ERROR_VIRUS_INFECTED | ERROR_VIRUS_DELETED)
+                ) =>
+            {
+                SidecarErrorKind::Blocked
+            }
+            Error::CurrentExeHasNoParent => SidecarErrorKind::Missing,
+            _ => SidecarErrorKind::SpawnFailed,
+        };
+        SidecarError::new(kind, format!("failed to spawn sidecar: {}", error))
+    }
+}
+
+/// Why a request got no answer.
+pub(crate) enum Failure {
+    /// It never reached the sidecar, so sending it again is safe.
+    Unsent(String),
+    /// It may have reached the sidecar.
+    Lost(String),
+}
+
+impl Failure {
+    /// Whether `method` may be sent again after this. Requests that may
+    /// have arrived are only repeated if reading them twice is harmless.
+    pub(crate) fn retryable(&self, method: &str) -> bool {
+        match self {
+            Failure::Unsent(_) => true,
+            Failure::Lost(_) => method.eq_ignore_ascii_case("GET"),
+        }
+    }
+
+    pub(crate) fn message(self) -> String {
+        match self {
+            Failure::Unsent(e) | Failure::Lost(e) => e,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ApiState {
+    pub port: Arc<Mutex<u16>>,
+    /// Sent as a bearer token on every request; the sidecar rejects the
+    /// rest. Made in `setup()` and kept across restarts, and never handed
+    /// to the webview.
+    pub token: Arc<Mutex<String>>,
+    client: reqwest::Client,
+    /// Found while waiting for the sidecar to become ready.
+    transport: Arc<Mutex<Transport>>,
+    link: Link,
+    shutting_down: Arc<AtomicBool>,
+    ready: Arc<AtomicBool>,
+    /// Why it last failed to start, until it starts.
+    error: Arc<Mutex<Option<SidecarError>>>,
+    /// What the running sidecar said its version is when it became ready.
+    version: Arc<Mutex<Option<String>>>,
+    /// Set by `retry_sidecar` for the supervisor.
+    retrying: Arc<AtomicBool>,
+    supervising: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Serialize)]
+struct SidecarRestarted {
+    port: u16,
+    restarts: u32,
+}
+
+#[derive(Clone, Serialize)]
+struct ApiReady {
+    port: u16,
+}
+
+#[tauri::command]
+pub fn get_api_port(state: tauri::State<ApiState>) -> u16 {
+    *state.port.lock().unwrap()
+}
+
+/// `path` as the sidecar's router sees it, split into the route and the
+/// query (with its `?`): doubled slashes are one and a trailing one is
+/// dropped, so `//execute/?x=1` is `/execute` and `?x=1`. A route with
+/// percent escapes or backslashes is refused, since the sidecar could read
+/// it as another one than the checks below do.
+fn route(path: &str) -> Result<(String, &str), CommandError> {
+    let (route, query) = match path.find(['?', '#']) {
+        Some(at) => path.split_at(at),
+        None => (path, ""),
+    };
+    if route.contains(['%', '\\']) {
+        return Err(format!("`{}` is not a sidecar route", path).into());
+    }
+    let parts: Vec<&str> = route.split('/').filter(|p| !p.is_empty()).collect();
+    let query = query.split('#').next().unwrap_or_default();
+    Ok((format!("/{}", parts.join("/")), query))
+}
+
+/// Fails if a request to `route` would have the sidecar touch files outside
+/// the scope: those a scan reads, those `/execute` moves and those `/undo`
+/// moves back, which are the last batch of the sidecar's history. A scan
+/// that names no folder reads the one set in the sidecar's config.
+async fn check_scope(
+    app: &AppHandle,
+    route: &str,
+    body: Option<&Value>,
+) -> Result<(), CommandError> {
+    let strings = |value: Option<&Value>| -> Vec<PathBuf> {
+        let values = match value {
+            Some(Value::Array(values)) => values.iter().collect(),
+            Some(value) => vec![value],
+            None => Vec::new(),
+        };
+        values
+            .into_iter()
+            .filter_map(Value::as_str)
+            .map(PathBuf::from)
+            .collect()
+    };
+    let paths = match route {
+        "/scan" => {
+            let body = body.unwrap_or(&Value::Null);
+            let mut paths = strings(body.get("paths"));
+            paths.extend(strings(body.get("path")));
+            paths
+        }
+        "/execute" => body
+            .and_then(|b| b.get("files"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .flat_map(|file| strings(file.get("original_path")))
+            .collect(),
+        "/undo" => {
+            let history = request(app, "GET", "/history", None)
+                .await
+                .map_err(unavailable)?;
+            history
+                .body
+                .get(0)
+                .and_then(|batch| batch.get("operations"))
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .flat_map(|op| [strings(op.get("src")), strings(op.get("dest"))])
+                .flatten()
+                .collect()
+        }
+        _ => return Ok(()),
+    };
+    scope::check(app, &paths)
+}
+
+/// Sends one request to the sidecar for the frontend, which never talks to
+/// it directly: it waits for the sidecar to be ready, attaches the token,
+/// sends the request again if a restart kept it from arriving and gives up
+/// after `timeout_ms` (two minutes by default). Heavy requests go to a
+/// worker if there is one; see `workers`. Error statuses are answers and
+/// come back as they are; only getting none is an error, coded
+/// `sidecar_unavailable`, except that requests that move files fail with
+/// `safe_mode` while safe mode is on and ones that would touch files outside
+/// the scope with `out_of_scope`.
+#[tauri::command]
+pub async fn api_request(
+    app: AppHandle,
+    method: String,
+    path: String,
+    body: Option<Value>,
+    timeout_ms: Option<u64>,
+) -> Result<ApiResponse, CommandError> {
+    let (route, query) = route(&path)?;
+    // The sidecar moves files for organizing and undoing it.
+    if method.eq_ignore_ascii_case("POST") && matches!(route.as_str(), "/execute" | "/undo") {
+        app.state::<SettingsState>().check_safe_mode()?;
+    }
+    if method.eq_ignore_ascii_case("POST") {
+        check_scope(&app, &route, body.as_ref()).await?;
+    }
+    // What is checked is what is sent.
+    let path = format!("{}{}", route, query);
+    let timeout = timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(REQUEST_TIMEOUT);
+    let attempts = async {
+        let mut retries = 0;
+        loop {
+            wait_until_healthy(&app, READY_TIMEOUT)
+                .await
+                .map_err(unavailable)?;
+            if let Some(response) = workers::send(&app, &method, &path, body.as_ref()).await {
+                return Ok(response);
+            }
+            match send(&app, &method, &path, body.as_ref()).await {
+                Ok(response) => {
+                    if method.eq_ignore_ascii_case("POST")
+                        && route == "/config"
+                        && response.status < 300
+                    {
+                        workers::recycle(&app);
+                        suggest::forget(&app);
+                    }
+                    return Ok(response);
+                }
+                Err(e) if retries < REQUEST_RETRIES && e.retryable(&method) => {
+                    retries += 1;
+                    tokio::time::sleep(RETRY_BACKOFF * retries).await;
+                }
+                Err(e) => return Err(unavailable(format!("{} {}: {}", method, path, e.message()))),
+            }
+        }
+    };
+    match tokio::time::timeout(timeout, attempts).await {
+        Ok(result) => result,
+        Err(_) => Err(unavailable(format!(
+            "{} {}: no answer within {}s",
+            method,
+            path,
+            timeout.as_secs()
+        ))),
+    }
+}
+
+async fn request(
+    app: &AppHandle,
+    method: &str,
+    path: &str,
+    body: Option<&Value>,
+) -> Result<ApiResponse, String> {
+    send(app, method, path, body)
+        .await
+        .map_err(Failure::message)
+}
+
+async fn send(
+    app: &AppHandle,
+    method: &str,
+    path: &str,
+    body: Option<&Value>,
+) -> Result<ApiResponse, Failure> {
+    let transport = *app.state::<ApiState>().transport.lock().unwrap();
+    match transport {
+        Transport::Http => http_request(app, method, path, body).await,
+        Transport::Stdio => stdio_request(app, method, path, body).await,
+    }
+}
+
+async fn http_request(
+    app: &AppHandle,
+    method: &str,
+    path: &str,
+    body: Option<&Value>,
+) -> Result<ApiResponse, Failure> {
+    let state = app.state::<ApiState>();
+    let port = *state.port.lock().unwrap();
+    let token = state.token.lock().unwrap().clone();
+    let method = reqwest::Method::from_bytes(method.as_bytes())
+        .map_err(|e| Failure::Unsent(e.to_string()))?;
+    let mut request = state
+        .client
+        .request(method, api_url(port, path))
+        .bearer_auth(token);
+    if let Some(body) = body {
+        request = request.json(body);
+    }
+    let response = request.send().await.map_err(|e| {
+        if e.is_connect() {
+            Failure::Unsent(e.to_string())
+        } else {
+            Failure::Lost(e.to_string())
+        }
+    })?;
+    let status = response.status().as_u16();
+    let text = response
+        .text()
+        .await
+        .map_err(|e| Failure::Lost(e.to_string()))?;
+    let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+    Ok(ApiResponse { status, body })
+}
+
+async fn stdio_request(
+    app: &AppHandle,
+    method: &str,
+    path: &str,
+    body: Option<&Value>,
+) -> Result<ApiResponse, Failure> {
+    app.state::<ApiState>().link.send(method, path, body).await
+}
+
+/// The pid of the sidecar process, while there is one.
+pub(crate) fn running_pid(app: &AppHandle) -> Option<u32> {
+    app.state::<ApiState>().link.pid()
+}
+
+/// Whether the running sidecar answers `/health` within `timeout`.
+pub(crate) async fn ping(app: &AppHandle, timeout: Duration) -> bool {
+    let health = request(app, "GET", "/health", None);
+    matches!(
+        tokio::time::timeout(timeout, health).await,
+        Ok(Ok(res)) if res.status == 200
+    )
+}
+
+/// Whether the sidecar is ready and answering over stdio, which workers
+/// need.
+pub(crate) fn over_stdio(app: &AppHandle) -> bool {
+    let state = app.state::<ApiState>();
+    state.ready.load(Ordering::SeqCst) && *state.transport.lock().unwrap() == Transport::Stdio
+}
+
+/// The version the sidecar reported when it became ready; `None` before
+/// then or if it did not say.
+pub(crate) fn sidecar_version(app: &AppHandle) -> Option<String> {
+    app.state::<ApiState>().version.lock().unwrap().clone()
+}
+
+/// Asks the sidecar for its version, giving up after a couple of seconds.
+async fn query_version(app: &AppHandle) -> Option<String> {
+    let health = request(app, "GET", "/health", None);
+    match tokio::time::timeout(Duration::from_secs(2), health).await {
+        Ok(Ok(res)) => res.body.get("version")?.as_str().map(str::to_string),
+        _ => None,
+    }
+}
+
+/// A fresh random token, for `setup()`.
+pub fn new_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Resolves with the API port once the sidecar answers `/health`, so the
+/// frontend never fires requests at a server that isn't listening yet.
+#[tauri::command]
+pub async fn wait_for_api_ready(
+    app: AppHandle,
+    timeout_ms: Option<u64>,
+) -> Result<u16, CommandError> {
+    let timeout = timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or_else(|| startup_timeout(&app));
+    wait_until_healthy(&app, timeout).await.map_err(unavailable)
+}
+
+fn unavailable(message: String) -> CommandError {
+    CommandError::new(AppErrorCode::SidecarUnavailable, message)
+}
+
+fn startup_timeout(app: &AppHandle) -> Duration {
+    app.state::<SettingsState>()
+        .get()
+        .sidecar_timeout_secs
+        .map_or(READY_TIMEOUT, Duration::from_secs)
+}
+
+async fn probe_health(client: &reqwest::Client, port: u16, token: &str) -> bool {
+    match client
+        .get(api_url(port, "/health"))
+        .bearer_auth(token)
+        .timeout(Duration::from_secs(1))
+        .send()
+        .await
+    {
+        Ok(res) => res.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+async fn probe_stdio(app: &AppHandle) -> bool {
+    let request = stdio_request(app, "GET", "/health", None);
+    matches!(
+        tokio::time::timeout(Duration::from_secs(1), request).await,
+        Ok(Ok(res)) if res.status == 200
+    )
+}
+
+/// Waits for `/health` to answer over stdio or, failing that, HTTP, and
+/// settles the transport on the one that did.
+async fn wait_until_healthy(app: &AppHandle, timeout: Duration) -> Result<u16, String> {
+    let state = app.state::<ApiState>();
+    let client = state.client.clone();
+    let deadline = Instant::now() + timeout;
+    let token = state.token.lock().unwrap().clone();
+
+    loop {
+        // The port can change under us if the sidecar restarts while we wait.
+        let port = *state.port.lock().unwrap();
+        if state.ready.load(Ordering::SeqCst) {
+            return Ok(port);
+        }
+        let found = if probe_stdio(app).await {
+            Some(Transport::Stdio)
+        } else if probe_health(&client, port, &token).await {
+            Some(Transport::Http)
+        } else {
+            None
+        };
+        if let Some(transport) = found {
+            *state.transport.lock().unwrap() = transport;
+            return Ok(port);
+        }
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "renamer-api did not become ready within {}s",
+                timeout.as_secs()
+            ));
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
+/// Logs what the supervisor did and keeps it in the sidecar log, next to the
+/// output it explains.
+pub(crate) fn note(app: &AppHandle, level: log::Level, message: String) {
+    log::log!(level, "[PY]: {}", message);
+    sidecar_log::append(app, Source::Supervisor, &message);
+}
+
+/// Polls the freshly spawned sidecar and emits `api-ready` once it responds.
+fn watch_readiness(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        match wait_until_healthy(&app, startup_timeout(&app)).await {
+            Ok(port) => {
+                app.state::<ApiState>().ready.store(true, Ordering::SeqCst);
+                app.state::<ApiState>().error.lock().unwrap().take();
+                let version = query_version(&app).await;
+                if version.as_deref() != Some(app.package_info().version.to_string().as_str()) {
+                    note(
+                        &app,
+                        log::Level::Warn,
+                        format!("sidecar reports version {:?}", version),
+                    );
+                }
+                *app.state::<ApiState>().version.lock().unwrap() = version;
+                let transport = *app.state::<ApiState>().transport.lock().unwrap();
+                let message = match transport {
+                    Transport::Http => format!("API ready on port {}", port),
+                    Transport::Stdio => "API ready over stdio".to_string(),
+                };
+                note(&app, log::Level::Info, message);
+                workers::resize(&app);
+                if let Some(pid) = running_pid(&app) {
+                    monitor::watch(app.clone(), pid);
+                }
+                let _ = app.emit("api-ready", ApiReady { port });
+            }
+            // A sidecar that exited meanwhile is reported by the supervisor.
+            Err(e) if app.state::<ApiState>().link.is_running() => {
+                fail(&app, SidecarError::new(SidecarErrorKind::Timeout, e))
+            }
+            Err(e) => note(&app, log::Level::Error, e),
+        }
+    });
+}
+
+pub fn api_url(port: u16, path: &str) -> String {
+    format!("http://127.0.0.1:{}{}", port, path)
+}
+
+fn free_port() -> std::io::Result<u16> {
+    TcpListener::bind("127.0.0.1:0")?
+        .local_addr()
+        .map(|addr| addr.port())
+}
+
+fn port_available(port: u16) -> bool {
+    port != 0 && TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// Keeps the previous port when it can be bound again so the frontend's cached
+/// base URL stays valid, otherwise picks a fresh one.
+fn ensure_port(app: &AppHandle) -> std::io::Result<u16> {
+    let state = app.state::<ApiState>();
+    let mut port = state.port.lock().unwrap();
+    if !port_available(*port) {
+        *port = free_port()?;
+    }
+    Ok(*port)
+}
+
+/// Spawns the sidecar binary with `args`, handing it the API token and, in
+/// portable mode, the folder to keep its files in.
+pub(crate) fn spawn_sidecar(
+    app: &AppHandle,
+    args: &[&str],
+) -> Result<(Receiver<CommandEvent>, CommandChild), tauri_plugin_shell::Error> {
+    let token = app.state::<ApiState>().token.lock().unwrap().clone();
+    let mut cmd = app
+        .shell()
+        .sidecar(SIDECAR_NAME)?
+        .args(args)
+        .env(TOKEN_ENV, token);
+    if let Some(dir) = paths::portable_dir() {
+        cmd = cmd.env(DATA_DIR_ENV, dir.join("sidecar"));
+    }
+    cmd.spawn()
+}
+
+/// A clean exit (code 0) is intentional: `/shutdown` before an update, or the
+/// heartbeat monitor giving up. Anything else is treated as a crash.
+fn is_crash(payload: &Option<TerminatedPayload>) -> bool {
+    match payload {
+        Some(p) => p.code != Some(0),
+        None => true,
+    }
+}
+
+/// Reports why the sidecar is not running: emits `sidecar-error` and keeps
+/// it for `get_sidecar_error`.
+fn fail(app: &AppHandle, error: SidecarError) {
+    note(app, log::Level::Error, error.message.clone());
+    *app.state::<ApiState>().error.lock().unwrap() = Some(error.clone());
+    let _ = app.emit("sidecar-error", error);
+}
+
+/// Waits until `retry_sidecar` is called or, given a limit, that long.
+/// Returns false if the app is shutting down instead.
+async fn wait_for_retry(app: &AppHandle, limit: Option<Duration>) -> bool {
+    let state = app.state::<ApiState>();
+    let deadline = limit.map(|limit| Instant::now() + limit);
+    loop {
+        if state.shutting_down.load(Ordering::SeqCst) {
+            return false;
+        }
+        if state.retrying.swap(false, Ordering::SeqCst) {
+            return true;
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return true;
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
+/// Stops sidecars orphaned by an earlier run, then spawns the sidecar and
+/// restarts it with exponential backoff whenever it crashes, emitting
+/// `sidecar-restarted` so the frontend can reconnect. A sidecar that cannot
+/// be spawned at all is not tried again until `retry_sidecar`.
+pub fn spawn_supervisor(app: AppHandle) {
+    if app
+        .state::<ApiState>()
+        .supervising
+        .swap(true, Ordering::SeqCst)
+    {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut restarts: u32 = 0;
+
+        let reaping = app.clone();
+        let _ = tauri::async_runtime::spawn_blocking(move || orphans::reap(&reaping)).await;
+
+        loop {
+            app.state::<ApiState>().ready.store(false, Ordering::SeqCst);
+            app.state::<ApiState>()
+                .retrying
+                .store(false, Ordering::SeqCst);
+            app.state::<ApiState>().version.lock().unwrap().take();
+            let port = match ensure_port(&app) {
+                Ok(port) => port,
+                Err(e) => {
+                    fail(
+                        &app,
+                        SidecarError::new(SidecarErrorKind::NoPort, e.to_string()),
+                    );
+                    if !wait_for_retry(&app, None).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let started = Instant::now();
+            // Builds without stdio support ignore `--stdio` and listen on
+            // the port, where the readiness probe finds them.
+            let stdio = std::env::var(TRANSPORT_ENV).map_or(true, |t| t != "http");
+            let port_arg = port.to_string();
+            let mut args = vec!["--port", port_arg.as_str()];
+            if stdio {
+                args.push("--stdio");
+            }
+            let spawned = spawn_sidecar(&app, &args);
+
+            let (mut rx, child) = match spawned {
+                Ok(spawned) => spawned,
+                Err(e) => {
+                    // Trying again will not bring back a missing or blocked
+                    // binary; the user has to fix it first.
+                    fail(&app, SidecarError::spawn(&e));
+                    if !wait_for_retry(&app, None).await {
+                        break;
+                    }
+                    backoff = INITIAL_BACKOFF;
+                    continue;
+                }
+            };
+            note(
+                &app,
+                log::Level::Info,
+                format!("sidecar started on port {} (pid {})", port, child.pid()),
+            );
+            orphans::record(&app, child.pid());
+            app.state::<ApiState>().link.attach(child);
+            watch_readiness(app.clone());
+
+            if restarts > 0 {
+                let _ = app.emit("sidecar-restarted", SidecarRestarted { port, restarts });
+            }
+
+            let mut terminated = None;
+            while let Some(event) = rx.recv().await {
+                match event {
+                    CommandEvent::Stdout(line) if !app.state::<ApiState>().link.answer(&line) => {
+                        let text = String::from_utf8_lossy(&line);
+                        log::info!("[PY]: {}", text);
+                        sidecar_log::append(&app, Source::Stdout, &text);
+                    }
+                    CommandEvent::Stderr(line) => {
+                        let text = String::from_utf8_lossy(&line);
+                        log::warn!("[PY]: {}", text);
+                        sidecar_log::append(&app, Source::Stderr, &text);
+                    }
+                    CommandEvent::Terminated(payload) => {
+                        terminated = Some(payload);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            app.state::<ApiState>().link.detach();
+            orphans::clear(&app);
+            let was_ready = app.state::<ApiState>().ready.load(Ordering::SeqCst);
+
+            if app.state::<ApiState>().shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+            // `retry_sidecar` stopped it to start over.
+            if app
+                .state::<ApiState>()
+                .retrying
+                .swap(false, Ordering::SeqCst)
+            {
+                backoff = INITIAL_BACKOFF;
+                restarts += 1;
+                continue;
+            }
+            if !is_crash(&terminated) {
+                note(
+                    &app,
+                    log::Level::Info,
+                    "sidecar exited cleanly, not restarting".to_string(),
+                );
+                break;
+            }
+            let status = match &terminated {
+                Some(p) => format!(
+                    "sidecar terminated (code {:?}, signal {:?})",
+                    p.code, p.signal
+                ),
+                None => "sidecar output closed without an exit status".to_string(),
+            };
+            if was_ready {
+                note(&app, log::Level::Warn, status);
+            } else {
+                fail(&app, SidecarError::new(SidecarErrorKind::Crashed, status));
+            }
+
+            if started.elapsed() >= STABLE_UPTIME {
+                backoff = INITIAL_BACKOFF;
+            }
+            note(
+                &app,
+                log::Level::Info,
+                format!("restarting sidecar in {:?}", backoff),
+            );
+            if !wait_for_retry(&app, Some(backoff)).await {
+                break;
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            restarts += 1;
+        }
+        app.state::<ApiState>()
+            .supervising
+            .store(false, Ordering::SeqCst);
+    });
+}
+
+/// Starts the sidecar over after a `sidecar-error`, remembering
+/// `timeout_secs` as the startup timeout if given. One that is still
+/// starting is stopped first; a healthy one is left alone.
+#[tauri::command]
+pub fn retry_sidecar(app: AppHandle, timeout_secs: Option<u64>) {
+    if let Some(secs) = timeout_secs {
+        app.state::<SettingsState>()
+            .update(|s| s.sidecar_timeout_secs = Some(secs.max(1)));
+    }
+    let state = app.state::<ApiState>();
+    if state.ready.load(Ordering::SeqCst) {
+        return;
+    }
+    state.error.lock().unwrap().take();
+    state.retrying.store(true, Ordering::SeqCst);
+    if let Some(child) = state.link.detach() {
+        let _ = child.kill();
+    }
+    spawn_supervisor(app.clone());
+}
+
+/// Kills the sidecar for the supervisor to start it again at once, e.g.
+/// when it stopped answering.
+pub(crate) fn restart(app: &AppHandle) {
+    let state = app.state::<ApiState>();
+    state.retrying.store(true, Ordering::SeqCst);
+    if let Some(child) = state.link.detach() {
+        let _ = child.kill();
+    }
+}
+
+/// The error the sidecar last failed with, if it has not started since; for
+/// a frontend that loaded after `sidecar-error` was emitted.
+#[tauri::command]
+pub fn get_sidecar_error(state: tauri::State<ApiState>) -> Option<SidecarError> {
+    state.error.lock().unwrap().clone()
+}
+
+/// Asks the sidecar to exit via `/shutdown` and waits briefly for it to go away,
+/// killing the child if it is still around after the grace period.
+pub async fn shutdown(app: &AppHandle) {
+    let state = app.state::<ApiState>();
+    if state.shutting_down.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    workers::stop(app);
+    if !state.link.is_running() {
+        return;
+    }
+
+    let shutdown = request(app, "POST", "/shutdown", None);
+    match tokio::time::timeout(Duration::from_secs(1), shutdown).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => log::warn!("[PY]: shutdown request failed: {}", e),
+        Err(_) => log::warn!("[PY]: shutdown request timed out"),
+    }
+
+    let deadline = Instant::now() + SHUTDOWN_GRACE;
+    while Instant::now() < deadline {
+        if !state.link.is_running() {
+            log::info!("[PY]: sidecar stopped");
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let child = state.link.detach();
+    if let Some(child) = child {
+        log::warn!(
+            "[PY]: sidecar did not exit in time, killing pid {}",
+            child.pid()
+        );
+        if let Err(e) = child.kill() {
+            log::error!("[PY]: failed to kill sidecar: {}", e);
+        }
+    }
+}
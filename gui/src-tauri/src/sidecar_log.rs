@@ -0,0 +1,153 @@
+//! What the sidecar prints, kept in `logs/sidecar.log` in the app data
+//! folder so a failure can still be looked into after the app is closed.
+//!
+//! Each line is stamped with the time it was read and where it came from.
+//! Once the file passes a few megabytes it is rotated to `sidecar.1.log`,
+//! pushing older files up, and the oldest is dropped. The last lines are
+//! also kept in memory for the in-app log viewer.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::Local;
+use tauri::{AppHandle, Manager, State};
+
+use crate::paths;
+
+const FILE_NAME: &str = "sidecar";
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+/// The current file and the rotated ones before it.
+const KEPT_FILES: usize = 5;
+const RECENT_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    Stdout,
+    Stderr,
+    /// The supervisor's own notes: starts, exits, restarts.
+    Supervisor,
+}
+
+impl Source {
+    fn tag(self) -> &'static str {
+        match self {
+            Source::Stdout => "out",
+            Source::Stderr => "err",
+            Source::Supervisor => "app",
+        }
+    }
+}
+
+struct SidecarLog {
+    dir: PathBuf,
+    /// `None` if the file could not be opened; lines are then only kept in
+    /// memory.
+    file: Option<File>,
+    size: u64,
+    recent: VecDeque<String>,
+}
+
+fn file_path(dir: &Path, index: usize) -> PathBuf {
+    match index {
+        0 => dir.join(format!("{}.log", FILE_NAME)),
+        n => dir.join(format!("{}.{}.log", FILE_NAME, n)),
+    }
+}
+
+fn open(path: &Path) -> Option<File> {
+    OpenOptions::new().create(true).append(true).open(path).ok()
+}
+
+impl SidecarLog {
+    fn write(&mut self, line: String) {
+        if self.size + line.len() as u64 + 1 > MAX_FILE_BYTES {
+            self.rotate();
+        }
+        if let Some(file) = &mut self.file {
+            if writeln!(file, "{}", line).is_ok() {
+                self.size += line.len() as u64 + 1;
+            }
+        }
+        if self.recent.len() == RECENT_LINES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(line);
+    }
+
+    fn rotate(&mut self) {
+        self.file = None;
+        let _ = fs::remove_file(file_path(&self.dir, KEPT_FILES - 1));
+        for index in (0..KEPT_FILES - 1).rev() {
+            let _ = fs::rename(file_path(&self.dir, index), file_path(&self.dir, index + 1));
+        }
+        self.file = open(&file_path(&self.dir, 0));
+        self.size = 0;
+    }
+}
+
+pub struct SidecarLogState(Mutex<SidecarLog>);
+
+impl SidecarLogState {
+    /// Opens the current log file, reading its last lines back so the viewer
+    /// shows what the previous run printed too.
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let dir = paths::data_dir(app)
+            .map_err(|e| e.to_string())?
+            .join("logs");
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = file_path(&dir, 0);
+        let previous = fs::read(&path).unwrap_or_default();
+        let previous = String::from_utf8_lossy(&previous);
+        let mut recent: VecDeque<String> = previous
+            .lines()
+            .rev()
+            .take(RECENT_LINES)
+            .map(str::to_string)
+            .collect();
+        recent.make_contiguous().reverse();
+        let file = open(&path);
+        let size = file
+            .as_ref()
+            .and_then(|f| f.metadata().ok())
+            .map_or(0, |m| m.len());
+        Ok(SidecarLogState(Mutex::new(SidecarLog {
+            dir,
+            file,
+            size,
+            recent,
+        })))
+    }
+}
+
+/// Stamps and keeps one line of sidecar output.
+pub(crate) fn append(app: &AppHandle, source: Source, text: &str) {
+    let line = format!(
+        "{} {} {}",
+        Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+        source.tag(),
+        text.trim_end()
+    );
+    app.state::<SidecarLogState>().0.lock().unwrap().write(line);
+}
+
+/// The log files that exist, oldest first.
+pub(crate) fn files(app: &AppHandle) -> Vec<PathBuf> {
+    let state = app.state::<SidecarLogState>();
+    let log = state.0.lock().unwrap();
+    (0..KEPT_FILES)
+        .rev()
+        .map(|index| file_path(&log.dir, index))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// The last `n` lines logged, oldest first.
+#[tauri::command]
+pub fn get_recent_sidecar_logs(state: State<SidecarLogState>, n: usize) -> Vec<String> {
+    let log = state.0.lock().unwrap();
+    let skip = log.recent.len().saturating_sub(n);
+    log.recent.iter().skip(skip).cloned().collect()
+}
@@ -0,0 +1,278 @@
+//! Folders on Android and iOS, where the app only gets at what the user
+//! picks with the system's folder picker: a document tree of the Storage
+//! Access Framework on Android, a security-scoped folder from the Files app
+//! on iOS. No path to them opens with `std::fs`, so the native side of the
+//! `storage` plugin lists and renames their documents, and batches in them
+//! are scanned, named and applied through `renamer_core::remote` the way
+//! batches on a remote host are, by the engine itself; there is no sidecar
+//! on mobile.
+//!
+//! The system keeps a grant after the app exits, so a folder picked once is
+//! listed by [`list_storage_folders`] until it is released. Paths within a
+//! folder are `/`-separated from its top, `/`. On the desktop, where
+//! folders are opened by path, the commands fail with `unsupported`.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::DateTime;
+use renamer_core::{
+    remote, ApplyReport, CancelToken, Error, RemoteEntry, RemoteFs, RenameOp, ScanOptions, ScanPage,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{AppHandle, Manager, Wry};
+
+use crate::announce;
+use crate::error::{AppErrorCode, CommandError};
+use crate::jobs::{self, ProgressKind};
+use crate::settings::SettingsState;
+
+/// A folder the user granted the app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageFolder {
+    /// The tree URI on Android, the bookmark of the folder on iOS.
+    pub uri: String,
+    pub name: String,
+}
+
+/// An entry as the native side lists it.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    /// Milliseconds since the epoch.
+    modified: Option<i64>,
+}
+
+impl From<Document> for RemoteEntry {
+    fn from(document: Document) -> Self {
+        RemoteEntry {
+            name: document.name,
+            is_dir: document.is_dir,
+            is_symlink: false,
+            size: document.size,
+            modified: document.modified.and_then(DateTime::from_timestamp_millis),
+            link_target: None,
+        }
+    }
+}
+
+/// The native side of the plugin, where there is one.
+pub struct StorageState(Option<platform::Native>);
+
+pub fn init() -> TauriPlugin<Wry> {
+    Builder::new("storage")
+        .setup(|app, api| {
+            app.manage(StorageState(platform::Native::register(api)?));
+            Ok(())
+        })
+        .build()
+}
+
+fn native(app: &AppHandle) -> Result<platform::Native, CommandError> {
+    app.state::<StorageState>().0.clone().ok_or_else(|| {
+        CommandError::new(
+            AppErrorCode::Unsupported,
+            "storage folders are only picked on Android and iOS; open folders by path here",
+        )
+    })
+}
+
+/// A granted folder, as what remote renaming works on.
+struct Folder {
+    native: platform::Native,
+    uri: String,
+}
+
+impl RemoteFs for Folder {
+    fn read_dir(&mut self, dir: &Path) -> io::Result<Vec<RemoteEntry>> {
+        let documents: Vec<Document> = self
+            .native
+            .call("list", json!({ "uri": self.uri, "path": dir }))?;
+        Ok(documents.into_iter().map(RemoteEntry::from).collect())
+    }
+
+    fn lstat(&mut self, path: &Path) -> io::Result<Option<RemoteEntry>> {
+        let document: Option<Document> = self
+            .native
+            .call("stat", json!({ "uri": self.uri, "path": path }))?;
+        Ok(document.map(RemoteEntry::from))
+    }
+
+    /// A rename within a folder stays a rename, keeping the document's
+    /// identity; one into another folder is a move and then a rename.
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.native
+            .call("rename", json!({ "uri": self.uri, "from": from, "to": to }))
+    }
+}
+
+/// Opens the system's folder picker and keeps the grant for the folder
+/// chosen; `None` if the user backed out.
+#[tauri::command]
+pub async fn pick_storage_folder(app: AppHandle) -> Result<Option<StorageFolder>, CommandError> {
+    let native = native(&app)?;
+    tauri::async_runtime::spawn_blocking(move || Ok(native.call("pickFolder", json!({}))?)).await?
+}
+
+/// The folders granted so far.
+#[tauri::command]
+pub async fn list_storage_folders(app: AppHandle) -> Result<Vec<StorageFolder>, CommandError> {
+    let native = native(&app)?;
+    tauri::async_runtime::spawn_blocking(move || Ok(native.call("listFolders", json!({}))?)).await?
+}
+
+/// Gives up the grant for the folder `uri`.
+#[tauri::command]
+pub async fn release_storage_folder(app: AppHandle, uri: String) -> Result<(), CommandError> {
+    let native = native(&app)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        Ok(native.call("releaseFolder", json!({ "uri": uri }))?)
+    })
+    .await?
+}
+
+/// Lists `root` in the granted folder `uri`, its top when not given;
+/// entries come with paths from the top. New names for them come from
+/// `remote_preview`.
+#[tauri::command]
+pub async fn storage_scan(
+    app: AppHandle,
+    uri: String,
+    root: Option<PathBuf>,
+    options: Option<ScanOptions>,
+) -> Result<ScanPage, CommandError> {
+    let native = native(&app)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut folder = Folder { native, uri };
+        let root = folder
+            .absolute(&root.unwrap_or_default())
+            .map_err(|e| Error::io(Path::new(&folder.uri), e))?;
+        let options = options.unwrap_or_default();
+        Ok(remote::scan(
+            &mut folder,
+            &root,
+            &options,
+            &CancelToken::new(),
+        )?)
+    })
+    .await?
+}
+
+/// Renames files in the granted folder `uri`, as `remote_apply` does on a
+/// host: refused in safe mode, and kept out of the history. Progress is
+/// emitted as `job-progress` events, and with a `job_id` `cancel_job` stops
+/// the batch.
+#[tauri::command]
+pub async fn storage_apply(
+    app: AppHandle,
+    uri: String,
+    ops: Vec<RenameOp>,
+    job_id: Option<String>,
+) -> Result<ApplyReport, CommandError> {
+    app.state::<SettingsState>().check_safe_mode()?;
+    let native = native(&app)?;
+    let job = jobs::register(&app, job_id.clone())?;
+    let reporter = job.reporter(ProgressKind::Apply);
+    let applied = tauri::async_runtime::spawn_blocking(move || {
+        let mut folder = Folder { native, uri };
+        Ok(remote::apply_with_progress(
+            &mut folder,
+            &ops,
+            &|p| {
+                reporter.report(
+                    p.completed as u64,
+                    Some(p.total as u64),
+                    Some(&p.current),
+                    None,
+                )
+            },
+            &job.token,
+        ))
+    })
+    .await?;
+    announce::applied(&app, job_id, &applied);
+    applied
+}
+
+#[cfg(mobile)]
+mod platform {
+    use std::io;
+
+    use serde::de::DeserializeOwned;
+    use serde::Deserialize;
+    use serde_json::Value;
+    use tauri::plugin::mobile::PluginInvokeError;
+    use tauri::plugin::{PluginApi, PluginHandle};
+    use tauri::Wry;
+
+    #[cfg(target_os = "ios")]
+    tauri::ios_plugin_binding!(init_plugin_storage);
+
+    /// `StoragePlugin.kt` on Android, `StoragePlugin.swift` on iOS.
+    #[derive(Clone)]
+    pub struct Native(PluginHandle<Wry>);
+
+    /// What every command resolves with, so that nothing and nothing found
+    /// come back as `null`.
+    #[derive(Deserialize)]
+    struct Reply<T> {
+        value: T,
+    }
+
+    impl Native {
+        pub fn register(api: PluginApi<Wry, ()>) -> Result<Option<Self>, PluginInvokeError> {
+            #[cfg(target_os = "android")]
+            let handle = api.register_android_plugin("com.renamer.app", "StoragePlugin")?;
+            #[cfg(target_os = "ios")]
+            let handle = api.register_ios_plugin(init_plugin_storage)?;
+            Ok(Some(Native(handle)))
+        }
+
+        /// Runs `command` on the native side, which rejects with `notFound`,
+        /// `exists` or `denied` where those apply.
+        pub fn call<T: DeserializeOwned>(&self, command: &str, payload: Value) -> io::Result<T> {
+            let reply = self.0.run_mobile_plugin::<Reply<T>>(command, payload);
+            reply.map(|r| r.value).map_err(|e| {
+                let kind = match &e {
+                    PluginInvokeError::InvokeRejected(e) => match e.code.as_deref() {
+                        Some("notFound") => io::ErrorKind::NotFound,
+                        Some("exists") => io::ErrorKind::AlreadyExists,
+                        Some("denied") => io::ErrorKind::PermissionDenied,
+                        _ => io::ErrorKind::Other,
+                    },
+                    _ => io::ErrorKind::Other,
+                };
+                io::Error::new(kind, e.to_string())
+            })
+        }
+    }
+}
+
+#[cfg(desktop)]
+mod platform {
+    use std::io;
+
+    use serde::de::DeserializeOwned;
+    use serde_json::Value;
+    use tauri::plugin::PluginApi;
+    use tauri::Wry;
+
+    /// There is no native side on the desktop.
+    #[derive(Clone)]
+    pub enum Native {}
+
+    impl Native {
+        pub fn register(_api: PluginApi<Wry, ()>) -> Result<Option<Self>, io::Error> {
+            Ok(None)
+        }
+
+        pub fn call<T: DeserializeOwned>(&self, _command: &str, _payload: Value) -> io::Result<T> {
+            match *self {}
+        }
+    }
+}
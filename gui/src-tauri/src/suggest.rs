@@ -0,0 +1,251 @@
+//! Name suggestions for single files: the catalogue matches the sidecar's
+//! `/scan` finds for them, asked for through one broker so the frontend
+//! never has to pace or repeat its requests.
+//!
+//! Answers are kept in the file cache by each file's content hash and name,
+//! which is what the sidecar matches by, and dropped whenever its settings
+//! change. Files are asked about in batches, one batch at a time and no more
+//! than one a second, so a large selection neither floods the sidecar nor
+//! the catalogues behind it. When the sidecar does not answer, the files
+//! come back with an error instead of failing the whole call, and it is not
+//! asked again for a while.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use renamer_core::{FileCache, HashAlgorithm};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::error::CommandError;
+use crate::{engine, scope, sidecar};
+
+/// The kind of the cache's lookups that suggestions are kept as.
+const KIND: &str = "suggestion";
+/// How many files are sent in one request.
+const BATCH: usize = 50;
+/// How long after one request the next may be sent.
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a sidecar that did not answer is left alone.
+const DOWN_FOR: Duration = Duration::from_secs(30);
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the sidecar suggested for a file, as kept in the cache.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Answer {
+    /// The sidecar's `FileCandidate`s, best first; empty if it found none.
+    pub candidates: Vec<Value>,
+    pub selected_index: usize,
+    /// Where organizing would move the file with the selected candidate.
+    pub proposed_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub answer: Answer,
+    /// Answered from the cache, without asking the sidecar.
+    pub cached: bool,
+    /// Why there is no answer: the file could not be read, or the sidecar
+    /// failed or was down.
+    pub error: Option<String>,
+}
+
+impl Suggestion {
+    fn answered(path: &Path, answer: Answer, cached: bool) -> Self {
+        Suggestion {
+            path: path.to_path_buf(),
+            answer,
+            cached,
+            error: None,
+        }
+    }
+
+    fn failed(path: &Path, error: impl Into<String>) -> Self {
+        Suggestion {
+            path: path.to_path_buf(),
+            answer: Answer::default(),
+            cached: false,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SuggestState {
+    /// Held while a batch is out, with when the last one was sent.
+    gate: tokio::sync::Mutex<Option<Instant>>,
+    /// Until when the sidecar is taken to be down.
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl SuggestState {
+    fn down(&self) -> bool {
+        self.down_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn mark_down(&self) {
+        *self.down_until.lock().unwrap() = Some(Instant::now() + DOWN_FOR);
+    }
+}
+
+/// Where a file's suggestion is kept: its contents and its name, since the
+/// sidecar matches by name.
+fn key(cache: &FileCache, path: &Path) -> Result<String, String> {
+    let hash = cache
+        .hash(path, HashAlgorithm::Xxh3)
+        .map_err(|e| e.to_string())?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    Ok(format!("{}:{}", hash, name))
+}
+
+fn cached(cache: &FileCache, key: &str) -> Option<Answer> {
+    serde_json::from_str(&cache.lookup(KIND, key)?).ok()
+}
+
+/// Suggests names for `paths`, in their order: from the cache where it
+/// can, asking the sidecar about the rest. Only paths outside the scope
+/// fail the call; a file without a suggestion has its `error` set.
+#[tauri::command]
+pub async fn suggest_names(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<Suggestion>, CommandError> {
+    scope::check(&app, &paths)?;
+    let cache = engine::file_cache(&app);
+    let keys = {
+        let (cache, paths) = (cache.clone(), paths.clone());
+        tauri::async_runtime::spawn_blocking(move || {
+            paths
+                .iter()
+                .map(|path| key(&cache, path))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    };
+
+    let mut found: Vec<Option<Suggestion>> = vec![None; paths.len()];
+    let mut missing = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        match key {
+            Err(e) => found[i] = Some(Suggestion::failed(&paths[i], e.as_str())),
+            Ok(key) => match cached(&cache, key) {
+                Some(answer) => found[i] = Some(Suggestion::answered(&paths[i], answer, true)),
+                None => missing.push(i),
+            },
+        }
+    }
+
+    let state = app.state::<SuggestState>();
+    for batch in missing.chunks(BATCH) {
+        let mut last_sent = state.gate.lock().await;
+        // Another call may have asked about the same files meanwhile.
+        let batch: Vec<usize> = batch
+            .iter()
+            .copied()
+            .filter(|&i| {
+                let key = keys[i].as_deref().unwrap_or_default();
+                match cached(&cache, key) {
+                    Some(answer) => {
+                        found[i] = Some(Suggestion::answered(&paths[i], answer, true));
+                        false
+                    }
+                    None => true,
+                }
+            })
+            .collect();
+        if batch.is_empty() {
+            continue;
+        }
+        if state.down() || !sidecar::ping(&app, PING_TIMEOUT).await {
+            state.mark_down();
+            for &i in &batch {
+                found[i] = Some(Suggestion::failed(&paths[i], "the sidecar is not running"));
+            }
+            continue;
+        }
+        if let Some(at) = *last_sent {
+            tokio::time::sleep(MIN_INTERVAL.saturating_sub(at.elapsed())).await;
+        }
+        *last_sent = Some(Instant::now());
+
+        let batch_paths: Vec<&Path> = batch.iter().map(|&i| paths[i].as_path()).collect();
+        match scan(&app, &batch_paths).await {
+            Ok(mut answers) => {
+                for &i in &batch {
+                    let answer = answers.remove(paths[i].as_path()).unwrap_or_default();
+                    if let (Ok(key), Ok(json)) = (&keys[i], serde_json::to_string(&answer)) {
+                        if let Err(e) = cache.store_lookup(KIND, key, &json) {
+                            log::warn!("failed to keep a name suggestion: {}", e);
+                        }
+                    }
+                    found[i] = Some(Suggestion::answered(&paths[i], answer, false));
+                }
+            }
+            Err(Failure::Down(e)) => {
+                state.mark_down();
+                for &i in &batch {
+                    found[i] = Some(Suggestion::failed(&paths[i], e.as_str()));
+                }
+            }
+            Err(Failure::Refused(e)) => {
+                for &i in &batch {
+                    found[i] = Some(Suggestion::failed(&paths[i], e.as_str()));
+                }
+            }
+        }
+    }
+    Ok(found.into_iter().flatten().collect())
+}
+
+enum Failure {
+    /// No answer came.
+    Down(String),
+    /// The sidecar answered with an error.
+    Refused(String),
+}
+
+/// Asks the sidecar about `paths`; files it has no answer for are left out.
+async fn scan(app: &AppHandle, paths: &[&Path]) -> Result<HashMap<PathBuf, Answer>, Failure> {
+    let body = json!({ "paths": paths, "min_size_mb": 0 });
+    let response =
+        sidecar::api_request(app.clone(), "POST".into(), "/scan".into(), Some(body), None)
+            .await
+            .map_err(|e| Failure::Down(e.message))?;
+    if response.status >= 300 {
+        let detail = response.body.get("detail").unwrap_or(&response.body);
+        return Err(Failure::Refused(format!(
+            "the sidecar answered {}: {}",
+            response.status, detail
+        )));
+    }
+    #[derive(Deserialize)]
+    struct Scanned {
+        original_path: PathBuf,
+        #[serde(flatten)]
+        answer: Answer,
+    }
+    let files = response.body.get("files").cloned().unwrap_or_default();
+    let files: Vec<Scanned> = serde_json::from_value(files)
+        .map_err(|e| Failure::Refused(format!("unexpected answer from the sidecar: {}", e)))?;
+    Ok(files
+        .into_iter()
+        .map(|file| (file.original_path, file.answer))
+        .collect())
+}
+
+/// Drops every suggestion kept, once the sidecar's settings changed.
+pub(crate) fn forget(app: &AppHandle) {
+    if let Err(e) = engine::file_cache(app).clear_lookups(KIND) {
+        log::warn!("failed to drop the kept name suggestions: {}", e);
+    }
+}
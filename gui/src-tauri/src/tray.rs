@@ -0,0 +1,275 @@
+//! The tray icon: watch status, pausing watches, and renaming copied files
+//! with the last preset without opening the window. Also quick rename,
+//! which takes the files copied in Explorer or Finder straight into the file
+//! list, for the webview to bind to a shortcut.
+
+use std::path::{Path, PathBuf};
+
+use renamer_core::{ApplyReport, OutcomeStatus, Preset};
+use serde::Serialize;
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::engine::{journal_dir, record_batch, PresetState};
+use crate::error::CommandError;
+use crate::settings::SettingsState;
+use crate::watch::WatchState;
+use crate::{palette, shell_integration};
+
+const TRAY_ID: &str = "main";
+
+#[derive(Clone, Serialize)]
+struct TrayRenamed {
+    preset: String,
+    report: ApplyReport,
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
+    let watch_state = app.state::<WatchState>();
+    let watches = watch_state.list();
+    let paused = watch_state.is_paused();
+    let menu = Menu::new(app)?;
+
+    let status = match (watches.len(), paused) {
+        (0, _) => "No folders watched".to_string(),
+        (1, false) => "Watching 1 folder".to_string(),
+        (n, false) => format!("Watching {} folders", n),
+        (n, true) => format!("{} watched, paused", n),
+    };
+    menu.append(&MenuItem::with_id(
+        app,
+        "status",
+        status,
+        false,
+        None::<&str>,
+    )?)?;
+    for watch in &watches {
+        let text = format!("  {} ({})", folder_name(&watch.folder), watch.preset);
+        menu.append(&MenuItem::new(app, text, false, None::<&str>)?)?;
+    }
+    let toggle = if paused {
+        "Resume watching"
+    } else {
+        "Pause watching"
+    };
+    menu.append(&MenuItem::with_id(
+        app,
+        "toggle-watches",
+        toggle,
+        !watches.is_empty() || paused,
+        None::<&str>,
+    )?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+
+    let last_preset = app.state::<SettingsState>().get().last_preset;
+    let run = match &last_preset {
+        Some(preset) => format!("Rename copied files with \u{201c}{}\u{201d}", preset),
+        None => "Rename copied files (no preset used yet)".to_string(),
+    };
+    menu.append(&MenuItem::with_id(
+        app,
+        "rename-copied",
+        run,
+        last_preset.is_some(),
+        None::<&str>,
+    )?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        "quick-rename",
+        "Quick rename selected files\u{2026}",
+        true,
+        None::<&str>,
+    )?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        "open",
+        "Open Sortify",
+        true,
+        None::<&str>,
+    )?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?)?;
+    Ok(menu)
+}
+
+/// Adds the tray icon. Called once from setup.
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("Sortify")
+        .menu(&menu(app)?)
+        .on_menu_event(|app, event| match event.id().as_ref() {
+            "toggle-watches" => {
+                let watches = app.state::<WatchState>();
+                watches.set_paused(!watches.is_paused());
+                refresh(app);
+            }
+            "rename-copied" => rename_copied(app),
+            "quick-rename" => palette::open(app),
+            "open" => show_window(app),
+            "quit" => app.exit(0),
+            _ => {}
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+/// Rebuilds the tray menu after watches, their state or the last preset
+/// changed.
+pub fn refresh(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    match menu(app) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        }
+        Err(e) => log::error!("failed to update the tray menu: {}", e),
+    }
+}
+
+pub(crate) fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Renames the files copied to the clipboard with the last preset, off the
+/// main thread. The batch goes into the history like any other and is
+/// emitted as `tray-renamed`; problems are emitted as `tray-error`.
+fn rename_copied(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || match run_last_preset(&app) {
+        Ok(Some(renamed)) => {
+            let _ = app.emit("tray-renamed", renamed);
+        }
+        Ok(None) => {}
+        Err(message) => {
+            log::error!("renaming copied files failed: {}", message);
+            let _ = app.emit("tray-error", message);
+        }
+    });
+}
+
+fn run_last_preset(app: &AppHandle) -> Result<Option<TrayRenamed>, String> {
+    let name = app
+        .state::<SettingsState>()
+        .get()
+        .last_preset
+        .ok_or("no preset has been used yet")?;
+    let preset = preset(app, &name)?;
+    let paths = copied_paths()?;
+    Ok(run_preset(app, &preset, &paths)?.map(|report| TrayRenamed {
+        preset: preset.name,
+        report,
+    }))
+}
+
+fn preset(app: &AppHandle, name: &str) -> Result<Preset, String> {
+    app.state::<PresetState>()
+        .0
+        .lock()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("no preset named `{}`", name))
+}
+
+/// The files copied in Explorer or Finder.
+fn copied_paths() -> Result<Vec<PathBuf>, String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get().file_list())
+        .map_err(|e| format!("no copied files: {}", e))
+}
+
+/// Renames `paths` with `preset` as a batch the user was not asked about,
+/// and records it in the history.
+fn run_preset(
+    app: &AppHandle,
+    preset: &Preset,
+    paths: &[PathBuf],
+) -> Result<Option<ApplyReport>, String> {
+    let report = preset
+        .run(
+            paths,
+            &app.state::<SettingsState>().apply_options(),
+            &journal_dir(app)?,
+        )
+        .map_err(|e| e.to_string())?;
+    if let Some(report) = &report {
+        record_batch(app, report, Some(&preset.name));
+    }
+    Ok(report)
+}
+
+#[derive(Serialize)]
+pub struct QuickRename {
+    /// The files loaded into the file list, under their new names if they
+    /// were renamed.
+    pub paths: Vec<PathBuf>,
+    /// The preset applied, if `apply` was asked for.
+    pub preset: Option<String>,
+    /// The batch, if the preset changed anything.
+    pub report: Option<ApplyReport>,
+}
+
+/// Loads the files copied to the clipboard into the file list, the way
+/// opening them with the app does, and brings the window to the front.
+/// With `apply` they are first renamed with the quick rename preset, or the
+/// last preset used if none is set, so a shortcut bound to this renames a
+/// selection without going through the picker.
+#[tauri::command]
+pub async fn quick_rename(app: AppHandle, apply: bool) -> Result<QuickRename, CommandError> {
+    let preset = if apply {
+        let settings = app.state::<SettingsState>().get();
+        let name = settings
+            .quick_rename_preset
+            .or(settings.last_preset)
+            .ok_or("no quick rename preset is set and no preset has been used yet")?;
+        Some(preset(&app, &name)?)
+    } else {
+        None
+    };
+    let name = preset.as_ref().map(|p| p.name.clone());
+    let handle = app.clone();
+    let (paths, report) = tauri::async_runtime::spawn_blocking(move || {
+        let paths = copied_paths()?;
+        let Some(preset) = &preset else {
+            return Ok::<_, String>((paths, None));
+        };
+        let report = run_preset(&handle, preset, &paths)?;
+        let paths = match &report {
+            Some(report) => paths
+                .into_iter()
+                .map(|path| {
+                    report
+                        .outcomes
+                        .iter()
+                        .find(|o| o.source == path && o.status == OutcomeStatus::Renamed)
+                        .map_or(path, |o| o.target.clone())
+                })
+                .collect(),
+            None => paths,
+        };
+        Ok((paths, report))
+    })
+    .await??;
+    shell_integration::open_paths(&app, paths.clone());
+    show_window(&app);
+    Ok(QuickRename {
+        paths,
+        preset: name,
+        report,
+    })
+}
@@ -0,0 +1,336 @@
+//! Checking for, downloading and installing updates from the frontend.
+//!
+//! Releases come in two channels: stable, GitHub's latest release, and
+//! beta, the manifest kept under the rolling `beta` release. The channel,
+//! and a version the user asked to be reminded about later, are in the
+//! settings. Downloads report their progress as `download-progress` events
+//! so the frontend can show it, then the sidecar is shut down, the update
+//! installed and the app restarted.
+//!
+//! Where the update server cannot be reached, a bundle downloaded by hand
+//! can be installed with `install_update_from_file`. The updater only takes
+//! updates from an endpoint, so the bundle is served to it on a loopback
+//! port over plain HTTP, which is why the config allows insecure transport;
+//! the endpoints used online are all HTTPS, and every bundle, either way,
+//! is only installed if it is signed with the app's key.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State, Url};
+use tauri_plugin_updater::{Update, UpdaterExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::error::{AppErrorCode, CommandError};
+use crate::settings::SettingsState;
+use crate::{scope, sidecar};
+
+const STABLE_ENDPOINT: &str =
+    "https://github.com/kbruxvoort/file_renamer/releases/latest/download/latest.json";
+const BETA_ENDPOINT: &str =
+    "https://github.com/kbruxvoort/file_renamer/releases/download/beta/latest.json";
+
+/// How long "remind me later" puts off a version, if not said otherwise.
+const DEFAULT_DEFER_DAYS: i64 = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    /// Pre-releases as well as releases.
+    Beta,
+}
+
+impl UpdateChannel {
+    fn endpoint(self) -> Url {
+        let url = match self {
+            UpdateChannel::Stable => STABLE_ENDPOINT,
+            UpdateChannel::Beta => BETA_ENDPOINT,
+        };
+        Url::parse(url).expect("valid update endpoint")
+    }
+}
+
+/// A version the user asked to be reminded about later; in the settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deferral {
+    pub version: String,
+    pub until: DateTime<Utc>,
+}
+
+/// An update found by `check_for_updates`.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateInfo {
+    pub version: String,
+    pub current_version: String,
+    pub channel: UpdateChannel,
+    pub notes: Option<String>,
+    /// When it was published, as RFC 3339.
+    pub date: Option<String>,
+}
+
+/// The payload of `download-progress`. `total` is `None` when the server
+/// did not say.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    pub finished: bool,
+}
+
+/// The update last found, which `install_update` installs.
+#[derive(Default)]
+pub struct UpdateState(Mutex<Option<Update>>);
+
+fn deferred(settings: &SettingsState, version: &str) -> bool {
+    settings
+        .get()
+        .update_deferred
+        .is_some_and(|d| d.version == version && d.until > Utc::now())
+}
+
+/// Looks for an update on `channel`, or the one in the settings; a given
+/// channel is saved as the one to use from now on. Unless `manual`, a
+/// version put off with `defer_update` is not reported until its time is up.
+#[tauri::command]
+pub async fn check_for_updates(
+    app: AppHandle,
+    channel: Option<UpdateChannel>,
+    manual: Option<bool>,
+) -> Result<Option<UpdateInfo>, CommandError> {
+    let settings = app.state::<SettingsState>();
+    let channel = match channel {
+        Some(channel) => {
+            settings.update(|s| s.update_channel = channel);
+            channel
+        }
+        None => settings.get().update_channel,
+    };
+    let update = app
+        .updater_builder()
+        .endpoints(vec![channel.endpoint()])
+        .and_then(|builder| builder.build())?
+        .check()
+        .await?;
+
+    let state = app.state::<UpdateState>();
+    let update = update.filter(|u| manual.unwrap_or(false) || !deferred(&settings, &u.version));
+    let info = update.as_ref().map(|u| UpdateInfo {
+        version: u.version.clone(),
+        current_version: u.current_version.clone(),
+        channel,
+        notes: u.body.clone(),
+        date: u.date.map(|d| d.to_string()),
+    });
+    *state.0.lock().unwrap() = update;
+    Ok(info)
+}
+
+/// Puts off the update last found for `days`, three if not given; checks
+/// that are not manual skip it until then. A newer version is still reported.
+#[tauri::command]
+pub fn defer_update(
+    state: State<UpdateState>,
+    settings: State<SettingsState>,
+    days: Option<u32>,
+) -> Result<Deferral, CommandError> {
+    let version = match &*state.0.lock().unwrap() {
+        Some(update) => update.version.clone(),
+        None => return Err(no_update("no update to put off")),
+    };
+    let days = days.map_or(DEFAULT_DEFER_DAYS, i64::from);
+    let deferral = Deferral {
+        version,
+        until: Utc::now() + Duration::days(days),
+    };
+    let saved = deferral.clone();
+    settings.update(|s| s.update_deferred = Some(saved));
+    Ok(deferral)
+}
+
+/// Installs the update last found; see `download_and_install`.
+#[tauri::command]
+pub async fn install_update(app: AppHandle) -> Result<(), CommandError> {
+    let update = app
+        .state::<UpdateState>()
+        .0
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| no_update("no update to install"))?;
+    download_and_install(&app, &update).await
+}
+
+/// Installs the bundle at `path`, e.g. `Sortify_1.4.0_x64-setup.exe` from
+/// the releases page, for machines that cannot reach the update server. Its
+/// signature is read from `signature`, or the `.sig` file next to it, and
+/// checked against the app's key before anything is installed. A bundle of
+/// the same or an older version than the running app, or whose name has no
+/// version, is refused unless `allow_downgrade` is set.
+#[tauri::command]
+pub async fn install_update_from_file(
+    app: AppHandle,
+    path: PathBuf,
+    signature: Option<PathBuf>,
+    allow_downgrade: Option<bool>,
+) -> Result<(), CommandError> {
+    scope::check(&app, [&path])?;
+    let signature = signature.unwrap_or_else(|| signature_path(&path));
+    let read = |path: &Path| std::fs::read(path).map_err(|e| renamer_core::Error::io(path, e));
+    let bundle = read(&path)?;
+    let signature = String::from_utf8(read(&signature)?)
+        .map_err(|e| format!("{}: {}", signature.display(), e))?;
+    let version = bundle_version(&path).unwrap_or_else(|| app.package_info().version.to_string());
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    let manifest = serde_json::json!({
+        "version": version,
+        "url": format!("http://{}/bundle", addr),
+        "signature": signature.trim(),
+    });
+    let server = tauri::async_runtime::spawn(serve(listener, manifest.to_string(), bundle));
+
+    let installed = match check_local(&app, addr, allow_downgrade.unwrap_or(false)).await {
+        Ok(update) => download_and_install(&app, &update).await,
+        Err(e) => Err(e),
+    };
+    server.abort();
+    installed
+}
+
+/// `Sortify_1.4.0_x64-setup.exe.sig` for `Sortify_1.4.0_x64-setup.exe`.
+fn signature_path(bundle: &Path) -> PathBuf {
+    let mut name = bundle.as_os_str().to_os_string();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// The version in a bundle name like `Sortify_1.4.0_x64-setup.exe`.
+fn bundle_version(bundle: &Path) -> Option<String> {
+    let name = bundle.file_name()?.to_str()?;
+    name.split('_')
+        .find(|part| {
+            let mut numbers = part.split('.');
+            part.contains('.')
+                && numbers
+                    .next()
+                    .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .map(str::to_string)
+}
+
+fn no_update(message: &str) -> CommandError {
+    CommandError::new(AppErrorCode::NotAvailable, message)
+}
+
+/// The update served at `addr`: only a newer version than the running app,
+/// as from the update server, unless `allow_downgrade`.
+async fn check_local(
+    app: &AppHandle,
+    addr: SocketAddr,
+    allow_downgrade: bool,
+) -> Result<Update, CommandError> {
+    let endpoint =
+        Url::parse(&format!("http://{}/latest.json", addr)).map_err(|e| e.to_string())?;
+    app.updater_builder()
+        .endpoints(vec![endpoint])
+        .map(|builder| {
+            if allow_downgrade {
+                builder.version_comparator(|_, _| true)
+            } else {
+                builder
+            }
+        })
+        .and_then(|builder| builder.build())?
+        .check()
+        .await?
+        .ok_or_else(|| {
+            no_update(&format!(
+                "the bundle is not newer than version {}, which is installed",
+                app.package_info().version
+            ))
+        })
+}
+
+/// Answers every request on `listener` with the manifest at `/latest.json`
+/// and the bundle anywhere else, until aborted.
+async fn serve(listener: TcpListener, manifest: String, bundle: Vec<u8>) {
+    let manifest = Arc::new(manifest);
+    let bundle = Arc::new(bundle);
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let manifest = manifest.clone();
+        let bundle = bundle.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let (kind, body) = if request.starts_with(b"GET /latest.json") {
+                ("application/json", manifest.as_bytes())
+            } else {
+                ("application/octet-stream", bundle.as_slice())
+            };
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                kind,
+                body.len()
+            );
+            if stream.write_all(head.as_bytes()).await.is_ok() {
+                let _ = stream.write_all(body).await;
+            }
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+/// Downloads `update`, emitting `download-progress` as it goes, then shuts
+/// the sidecar down, installs it and restarts the app.
+async fn download_and_install(app: &AppHandle, update: &Update) -> Result<(), CommandError> {
+    let downloaded = AtomicU64::new(0);
+    let bytes = update
+        .download(
+            |chunk, total| {
+                let downloaded =
+                    downloaded.fetch_add(chunk as u64, Ordering::Relaxed) + chunk as u64;
+                let _ = app.emit(
+                    "download-progress",
+                    DownloadProgress {
+                        downloaded,
+                        total,
+                        finished: false,
+                    },
+                );
+            },
+            || {
+                let downloaded = downloaded.load(Ordering::Relaxed);
+                let _ = app.emit(
+                    "download-progress",
+                    DownloadProgress {
+                        downloaded,
+                        total: Some(downloaded),
+                        finished: true,
+                    },
+                );
+            },
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // The installer cannot replace a sidecar that is still running.
+    sidecar::shutdown(app).await;
+    app.state::<SettingsState>()
+        .update(|s| s.update_deferred = None);
+    update.install(bytes)?;
+    app.restart();
+}
@@ -0,0 +1,300 @@
+//! Moving a setup between machines: presets, settings and, if asked, the
+//! rename history in one zip.
+//!
+//! Settings that only make sense on the machine they were made on, the last
+//! folder picked and the recent list, are left where they are on import.
+//! History goes into the searchable archive only, never the undo history:
+//! the files it moved are on the other machine.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use renamer_core::{BatchRecord, Preset};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::engine::{HistoryDbState, HistoryState, PresetState};
+use crate::error::CommandError;
+use crate::scope;
+use crate::settings::{self, Settings, SettingsState};
+
+/// Format of the archive this version reads and writes.
+const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const PRESETS: &str = "presets.json";
+const SETTINGS: &str = "settings.json";
+const HISTORY: &str = "history.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub app_version: String,
+    pub exported_at: DateTime<Utc>,
+    pub presets: usize,
+    /// Batches in the archive; `None` when history was left out.
+    pub batches: Option<usize>,
+}
+
+/// What to do with an imported preset named like one already here.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresetConflict {
+    /// Keep the one here.
+    #[default]
+    Skip,
+    Replace,
+    /// Import it under a new name, e.g. `Photos (2)`.
+    KeepBoth,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    pub settings: bool,
+    pub history: bool,
+    pub conflict: PresetConflict,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            settings: true,
+            history: true,
+            conflict: PresetConflict::default(),
+        }
+    }
+}
+
+/// An archive as it would be imported: its manifest and the presets in it
+/// named like ones already here.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveInfo {
+    pub manifest: Manifest,
+    pub conflicts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub presets_added: Vec<String>,
+    pub presets_replaced: Vec<String>,
+    /// Imported under a new name, as `(name in the archive, name here)`.
+    pub presets_renamed: Vec<(String, String)>,
+    pub presets_skipped: Vec<String>,
+    pub settings_imported: bool,
+    /// Batches added to the history; ones it already had are not counted.
+    pub batches_imported: usize,
+}
+
+fn write_archive(
+    path: &Path,
+    manifest: &Manifest,
+    presets: &[Preset],
+    settings: &Settings,
+    history: Option<&[BatchRecord]>,
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, bytes: &[u8]| -> Result<(), String> {
+        zip.start_file(name, options).map_err(|e| e.to_string())?;
+        zip.write_all(bytes).map_err(|e| e.to_string())
+    };
+    add(MANIFEST, &json(manifest)?)?;
+    add(PRESETS, &json(presets)?)?;
+    add(SETTINGS, &json(settings)?)?;
+    if let Some(history) = history {
+        add(HISTORY, &json(history)?)?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn json<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(value).map_err(|e| e.to_string())
+}
+
+struct Archive(ZipArchive<File>);
+
+impl Archive {
+    fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let zip = ZipArchive::new(file).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut archive = Archive(zip);
+        let manifest: Manifest = archive
+            .read(MANIFEST)?
+            .ok_or_else(|| format!("{}: not a Sortify export", path.display()))?;
+        if manifest.version > ARCHIVE_VERSION {
+            return Err(format!(
+                "{}: written by a newer version (format {})",
+                path.display(),
+                manifest.version
+            ));
+        }
+        Ok(archive)
+    }
+
+    /// The JSON file `name` in the archive; `None` if it has none.
+    fn read<T: DeserializeOwned>(&mut self, name: &str) -> Result<Option<T>, String> {
+        let mut file = match self.0.by_name(name) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", name, e))
+    }
+
+    fn manifest(&mut self) -> Result<Manifest, String> {
+        self.read(MANIFEST)?
+            .ok_or_else(|| "the export has no manifest".to_string())
+    }
+}
+
+/// `name` with the lowest ` (n)` suffix no preset here has.
+fn free_name(app: &AppHandle, name: &str) -> String {
+    let presets = app.state::<PresetState>();
+    let presets = presets.0.lock().unwrap();
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| presets.get(candidate).is_none())
+        .unwrap_or_else(|| name.to_string())
+}
+
+fn conflicts(app: &AppHandle, presets: &[Preset]) -> Vec<String> {
+    let store = app.state::<PresetState>();
+    let store = store.0.lock().unwrap();
+    presets
+        .iter()
+        .filter(|p| store.get(&p.name).is_some())
+        .map(|p| p.name.clone())
+        .collect()
+}
+
+/// Writes presets, settings and, with `include_history`, the undo history
+/// to `path`, usually from `pick_save_path`.
+#[tauri::command]
+pub async fn export_user_data(
+    app: AppHandle,
+    path: PathBuf,
+    include_history: Option<bool>,
+) -> Result<Manifest, CommandError> {
+    scope::check(&app, [&path])?;
+    let presets = app
+        .state::<PresetState>()
+        .0
+        .lock()
+        .unwrap()
+        .presets()
+        .to_vec();
+    let mut settings = app.state::<SettingsState>().all();
+    settings.app.last_dir = None;
+    settings.app.recent.clear();
+    let history = include_history.unwrap_or(false).then(|| {
+        app.state::<HistoryState>()
+            .0
+            .lock()
+            .unwrap()
+            .batches()
+            .to_vec()
+    });
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        app_version: app.package_info().version.to_string(),
+        exported_at: Utc::now(),
+        presets: presets.len(),
+        batches: history.as_ref().map(Vec::len),
+    };
+    let written = manifest.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        write_archive(&path, &manifest, &presets, &settings, history.as_deref())
+    })
+    .await??;
+    Ok(written)
+}
+
+/// What importing `path` would bring in, so the frontend can ask about
+/// presets named like ones already here before `import_user_data`.
+#[tauri::command]
+pub fn inspect_user_data(app: AppHandle, path: PathBuf) -> Result<ArchiveInfo, CommandError> {
+    scope::check(&app, [&path])?;
+    let mut archive = Archive::open(&path)?;
+    let presets: Vec<Preset> = archive.read(PRESETS)?.unwrap_or_default();
+    Ok(ArchiveInfo {
+        manifest: archive.manifest()?,
+        conflicts: conflicts(&app, &presets),
+    })
+}
+
+/// Merges an archive from `export_user_data` into this machine's data.
+/// Presets named like ones here are handled as `options.conflict` says.
+#[tauri::command]
+pub fn import_user_data(
+    app: AppHandle,
+    path: PathBuf,
+    options: Option<ImportOptions>,
+) -> Result<ImportReport, CommandError> {
+    scope::check(&app, [&path])?;
+    let options = options.unwrap_or_default();
+    let mut archive = Archive::open(&path)?;
+    let presets: Vec<Preset> = archive.read(PRESETS)?.unwrap_or_default();
+    let imported_settings: Option<Settings> = archive.read(SETTINGS)?;
+    let history: Option<Vec<BatchRecord>> = archive.read(HISTORY)?;
+    let mut report = ImportReport::default();
+
+    let taken = conflicts(&app, &presets);
+    for mut preset in presets {
+        let name = preset.name.clone();
+        if taken.contains(&name) {
+            match options.conflict {
+                PresetConflict::Skip => {
+                    report.presets_skipped.push(name);
+                    continue;
+                }
+                PresetConflict::Replace => report.presets_replaced.push(name),
+                PresetConflict::KeepBoth => {
+                    preset.name = free_name(&app, &name);
+                    report.presets_renamed.push((name, preset.name.clone()));
+                }
+            }
+        } else {
+            report.presets_added.push(name);
+        }
+        app.state::<PresetState>()
+            .0
+            .lock()
+            .unwrap()
+            .insert(preset)?;
+    }
+
+    if let (true, Some(imported)) = (options.settings, imported_settings) {
+        let saved = app.state::<SettingsState>().change(|settings| {
+            let last_dir = settings.app.last_dir.take();
+            let recent = std::mem::take(&mut settings.app.recent);
+            *settings = imported;
+            settings.app.last_dir = last_dir;
+            settings.app.recent = recent;
+            Ok(())
+        })?;
+        settings::applied(&app, &saved);
+        report.settings_imported = true;
+    }
+
+    if let (true, Some(batches)) = (options.history, history) {
+        report.batches_imported = app
+            .state::<HistoryDbState>()
+            .0
+            .lock()
+            .unwrap()
+            .import(&batches)?;
+    }
+    Ok(report)
+}
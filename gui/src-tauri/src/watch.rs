@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use renamer_core::{
+    ApplyOptions, ApplyReport, FolderWatcher, PreviewRow, SourceChanges, SourceWatcher, WatchEvent,
+    WatchOptions, WebhookEvent,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
+
+use crate::engine::{journal_dir, record_batch, run_post_action, PresetState};
+use crate::error::{AppErrorCode, CommandError};
+use crate::settings::SettingsState;
+#[cfg(desktop)]
+use crate::tray;
+use crate::{notify, scope};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchInfo {
+    pub watch_id: Uuid,
+    pub folder: PathBuf,
+    pub preset: String,
+    pub paused: bool,
+}
+
+struct RunningWatch {
+    info: WatchInfo,
+    watcher: FolderWatcher,
+}
+
+/// Folders being watched, keyed by the id `start_watch` returned, and
+/// whether watching is paused; watches started while paused start paused.
+#[derive(Default)]
+pub struct WatchState {
+    watches: Mutex<HashMap<Uuid, RunningWatch>>,
+    paused: AtomicBool,
+}
+
+impl WatchState {
+    pub fn list(&self) -> Vec<WatchInfo> {
+        self.watches
+            .lock()
+            .unwrap()
+            .values()
+            .map(|w| WatchInfo {
+                paused: w.watcher.is_paused(),
+                ..w.info.clone()
+            })
+            .collect()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+        for watch in self.watches.lock().unwrap().values() {
+            if paused {
+                watch.watcher.pause();
+            } else {
+                watch.watcher.resume();
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct WatchRenamed {
+    watch_id: Uuid,
+    report: ApplyReport,
+}
+
+#[derive(Clone, Serialize)]
+struct WatchFailed {
+    watch_id: Uuid,
+    message: String,
+}
+
+/// Starts renaming files that arrive in `folder` with the rules of the preset
+/// called `preset`, as it is now. Each batch is recorded in the history, so it
+/// can be undone, and in the history database under the preset's name, and emitted as a `watch-renamed` event; problems are emitted
+/// as `watch-error` events. Batches are applied with `apply_options`, or
+/// the ones in the settings if not given.
+#[tauri::command]
+pub fn start_watch(
+    app: AppHandle,
+    watches: State<WatchState>,
+    folder: PathBuf,
+    preset: String,
+    apply_options: Option<ApplyOptions>,
+) -> Result<WatchInfo, CommandError> {
+    let found = app
+        .state::<PresetState>()
+        .0
+        .lock()
+        .unwrap()
+        .get(&preset)
+        .cloned()
+        .ok_or_else(|| CommandError::unknown_preset(&preset))?;
+    scope::check(&app, [&folder])?;
+    let mut running = watches.watches.lock().unwrap();
+    if running.values().any(|w| w.info.folder == folder) {
+        return Err(CommandError::new(
+            AppErrorCode::AlreadyRunning,
+            format!("{} is already being watched", folder.display()),
+        )
+        .with("path", folder.display()));
+    }
+    let info = WatchInfo {
+        watch_id: Uuid::new_v4(),
+        folder: folder.clone(),
+        preset: found.name.clone(),
+        paused: watches.is_paused(),
+    };
+    let options = WatchOptions {
+        apply_options: match apply_options {
+            Some(options) => app.state::<SettingsState>().enforce(options),
+            None => app.state::<SettingsState>().apply_options(),
+        },
+        ..WatchOptions::new(folder, found, journal_dir(&app)?)
+    };
+    let watch_id = info.watch_id;
+    let preset = info.preset.clone();
+    let emitter = app.clone();
+    let watcher = FolderWatcher::start(options, move |event| match event {
+        WatchEvent::Renamed(report) => {
+            record_batch(&emitter, &report, Some(&preset));
+            notify::batch_finished(&emitter, &report, Some(&preset));
+            notify::webhooks(&emitter, &report, WebhookEvent::Watch, Some(&preset));
+            run_post_action(&emitter, &report, &preset);
+            let _ = emitter.emit("watch-renamed", WatchRenamed { watch_id, report });
+        }
+        WatchEvent::Failed(message) => {
+            let _ = emitter.emit("watch-error", WatchFailed { watch_id, message });
+        }
+    })?;
+    if info.paused {
+        watcher.pause();
+    }
+    running.insert(
+        watch_id,
+        RunningWatch {
+            info: info.clone(),
+            watcher,
+        },
+    );
+    drop(running);
+    #[cfg(desktop)]
+    tray::refresh(&app);
+    Ok(info)
+}
+
+/// Stops a watch, letting a batch in progress finish first. Returns false if
+/// no such watch is running.
+#[tauri::command]
+pub async fn stop_watch(app: AppHandle, watch_id: Uuid) -> Result<bool, CommandError> {
+    let removed = app
+        .state::<WatchState>()
+        .watches
+        .lock()
+        .unwrap()
+        .remove(&watch_id);
+    let Some(watch) = removed else {
+        return Ok(false);
+    };
+    #[cfg(desktop)]
+    tray::refresh(&app);
+    tauri::async_runtime::spawn_blocking(move || watch.watcher.stop())
+        .await
+        .map(|_| true)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_watches(watches: State<WatchState>) -> Vec<WatchInfo> {
+    watches.list()
+}
+
+/// Pauses or resumes every watch, as the tray menu does. Files that arrive
+/// while paused are left as they are.
+#[tauri::command]
+pub fn set_watches_paused(app: AppHandle, paused: bool) {
+    app.state::<WatchState>().set_paused(paused);
+    #[cfg(desktop)]
+    tray::refresh(&app);
+}
+
+/// The watcher of the loaded file list's folders, if one is running.
+#[derive(Default)]
+pub struct SourceWatchState(Mutex<Option<SourceWatcher>>);
+
+/// Watches the folders of `paths`, the loaded file list, replacing any
+/// earlier list. Files added, removed or renamed there by other programs
+/// are emitted as `source-changed` events, which `stale_preview_rows` turns
+/// into the rows to preview again.
+#[tauri::command]
+pub fn watch_sources(
+    app: AppHandle,
+    sources: State<SourceWatchState>,
+    paths: Vec<PathBuf>,
+) -> Result<(), CommandError> {
+    scope::check(&app, &paths)?;
+    let mut current = sources.0.lock().unwrap();
+    // The old watcher goes first, so its folders are not watched twice.
+    current.take();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let watcher = SourceWatcher::start(&paths, move |changes| {
+        let _ = app.emit("source-changed", changes);
+    })?;
+    *current = Some(watcher);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unwatch_sources(sources: State<SourceWatchState>) {
+    sources.0.lock().unwrap().take();
+}
+
+/// Ids of the preview rows `changes` make stale.
+#[tauri::command]
+pub fn stale_preview_rows(rows: Vec<PreviewRow>, changes: SourceChanges) -> Vec<usize> {
+    changes.stale_rows(&rows)
+}
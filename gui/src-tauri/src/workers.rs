@@ -0,0 +1,319 @@
+//! Extra sidecar processes that take the heavy requests (scanning, searching
+//! and previews) off the main one, so several can run at once.
+//!
+//! Workers are the same binary started with `--worker`: they answer over
+//! stdio only, keep no heartbeat and exit once their stdin closes. They are
+//! started when the main sidecar is ready over stdio, so a build that only
+//! listens on a port gets none. Each worker is taken into rotation once it
+//! answers `/health` and out of it when a request to it fails, until it
+//! answers again; one that exits is started again with backoff. A request
+//! goes to the healthy worker with the fewest in flight, or to the main
+//! sidecar when there is none.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_shell::process::CommandEvent;
+
+use crate::settings::SettingsState;
+use crate::sidecar::{self, ApiResponse, Link};
+use crate::sidecar_log::{self, Source};
+
+/// The requests workers take. None of them change anything, so one a
+/// worker failed is safely sent again to the main sidecar.
+const HEAVY_PATHS: &[&str] = &["/scan", "/search", "/preview_rename"];
+
+pub const MAX_WORKERS: usize = 8;
+
+// Backoff between restarts of a worker, as for the main sidecar.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+// Probing `/health` before a worker is taken into rotation.
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Worker {
+    id: usize,
+    link: Link,
+    healthy: AtomicBool,
+    probing: AtomicBool,
+    in_flight: AtomicUsize,
+    served: AtomicU64,
+    failures: AtomicU32,
+    /// Set when the pool shrinks, so its supervisor stops.
+    retired: AtomicBool,
+    /// Set when it is stopped to pick up a new config, so it is started
+    /// again at once.
+    recycling: AtomicBool,
+}
+
+impl Worker {
+    fn new(id: usize) -> Self {
+        Worker {
+            id,
+            link: Link::default(),
+            healthy: AtomicBool::new(false),
+            probing: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            served: AtomicU64::new(0),
+            failures: AtomicU32::new(0),
+            retired: AtomicBool::new(false),
+            recycling: AtomicBool::new(false),
+        }
+    }
+
+    fn stop(&self) {
+        self.healthy.store(false, Ordering::SeqCst);
+        if let Some(child) = self.link.detach() {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Counts a request as in flight on a worker until it is dropped, also when
+/// the caller gives up on it.
+struct InFlight<'a>(&'a Worker);
+
+impl<'a> InFlight<'a> {
+    fn new(worker: &'a Worker) -> Self {
+        worker.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(worker)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Default)]
+pub struct WorkerPool {
+    workers: Mutex<Vec<Arc<Worker>>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerStatus {
+    pub id: usize,
+    /// `None` while it is being restarted.
+    pub pid: Option<u32>,
+    pub healthy: bool,
+    pub in_flight: usize,
+    pub served: u64,
+    /// Failed requests and exits since the app started.
+    pub failures: u32,
+}
+
+/// Takes the worker into rotation once it answers `/health`. Only one probe
+/// runs per worker; it gives up when the worker exits or never answers.
+fn probe(app: &AppHandle, worker: Arc<Worker>) {
+    if worker.probing.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let deadline = Instant::now() + PROBE_TIMEOUT;
+        while worker.link.is_running() && Instant::now() < deadline {
+            let health = worker.link.send("GET", "/health", None);
+            if matches!(
+                tokio::time::timeout(Duration::from_secs(1), health).await,
+                Ok(Ok(res)) if res.status == 200
+            ) {
+                worker.healthy.store(true, Ordering::SeqCst);
+                sidecar::note(
+                    &app,
+                    log::Level::Info,
+                    format!("worker {} ready", worker.id),
+                );
+                break;
+            }
+            tokio::time::sleep(PROBE_INTERVAL).await;
+        }
+        worker.probing.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Runs the worker and starts it again whenever it exits, until it retires.
+fn supervise(app: AppHandle, worker: Arc<Worker>) {
+    tauri::async_runtime::spawn(async move {
+        let mut backoff = INITIAL_BACKOFF;
+        while !worker.retired.load(Ordering::SeqCst) {
+            let started = Instant::now();
+            match sidecar::spawn_sidecar(&app, &["--stdio", "--worker"]) {
+                Ok((mut rx, child)) => {
+                    sidecar::note(
+                        &app,
+                        log::Level::Info,
+                        format!("worker {} started (pid {})", worker.id, child.pid()),
+                    );
+                    worker.link.attach(child);
+                    probe(&app, worker.clone());
+                    while let Some(event) = rx.recv().await {
+                        match event {
+                            CommandEvent::Stdout(line) if !worker.link.answer(&line) => {
+                                let text = String::from_utf8_lossy(&line);
+                                log::info!("[PY {}]: {}", worker.id, text);
+                                let text = format!("[worker {}] {}", worker.id, text);
+                                sidecar_log::append(&app, Source::Stdout, &text);
+                            }
+                            CommandEvent::Stderr(line) => {
+                                let text = String::from_utf8_lossy(&line);
+                                log::warn!("[PY {}]: {}", worker.id, text);
+                                let text = format!("[worker {}] {}", worker.id, text);
+                                sidecar_log::append(&app, Source::Stderr, &text);
+                            }
+                            CommandEvent::Terminated(_) => break,
+                            _ => {}
+                        }
+                    }
+                    worker.stop();
+                }
+                Err(e) => sidecar::note(
+                    &app,
+                    log::Level::Warn,
+                    format!("failed to spawn worker {}: {}", worker.id, e),
+                ),
+            }
+            if worker.retired.load(Ordering::SeqCst) {
+                break;
+            }
+            if worker.recycling.swap(false, Ordering::SeqCst) {
+                backoff = INITIAL_BACKOFF;
+                continue;
+            }
+            worker.failures.fetch_add(1, Ordering::SeqCst);
+            if started.elapsed() >= STABLE_UPTIME {
+                backoff = INITIAL_BACKOFF;
+            }
+            sidecar::note(
+                &app,
+                log::Level::Warn,
+                format!("worker {} exited, restarting in {:?}", worker.id, backoff),
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+}
+
+/// Starts or stops workers to match the settings.
+pub(crate) fn resize(app: &AppHandle) {
+    let count = if sidecar::over_stdio(app) {
+        app.state::<SettingsState>()
+            .get()
+            .sidecar_workers
+            .min(MAX_WORKERS)
+    } else {
+        0
+    };
+    let pool = app.state::<WorkerPool>();
+    let mut workers = pool.workers.lock().unwrap();
+    while workers.len() > count {
+        if let Some(worker) = workers.pop() {
+            worker.retired.store(true, Ordering::SeqCst);
+            worker.stop();
+        }
+    }
+    while workers.len() < count {
+        let worker = Arc::new(Worker::new(workers.len() + 1));
+        supervise(app.clone(), worker.clone());
+        workers.push(worker);
+    }
+}
+
+/// Stops every worker, for the app exiting.
+pub(crate) fn stop(app: &AppHandle) {
+    let pool = app.state::<WorkerPool>();
+    for worker in pool.workers.lock().unwrap().drain(..) {
+        worker.retired.store(true, Ordering::SeqCst);
+        worker.stop();
+    }
+}
+
+/// Restarts every worker, which read the config only when they start.
+pub(crate) fn recycle(app: &AppHandle) {
+    let pool = app.state::<WorkerPool>();
+    for worker in pool.workers.lock().unwrap().iter() {
+        worker.recycling.store(true, Ordering::SeqCst);
+        worker.stop();
+    }
+}
+
+/// Sends a heavy request to the least busy healthy worker. `None` for any
+/// other request, when no worker is healthy or when the one picked failed,
+/// for the main sidecar to answer instead.
+pub(crate) async fn send(
+    app: &AppHandle,
+    method: &str,
+    path: &str,
+    body: Option<&Value>,
+) -> Option<ApiResponse> {
+    let route = path.split('?').next().unwrap_or(path);
+    if !HEAVY_PATHS.contains(&route) {
+        return None;
+    }
+    let worker = {
+        let pool = app.state::<WorkerPool>();
+        let workers = pool.workers.lock().unwrap();
+        workers
+            .iter()
+            .filter(|w| w.healthy.load(Ordering::SeqCst))
+            .min_by_key(|w| w.in_flight.load(Ordering::SeqCst))
+            .cloned()?
+    };
+    let result = {
+        let _in_flight = InFlight::new(&worker);
+        worker.link.send(method, path, body).await
+    };
+    match result {
+        Ok(response) => {
+            worker.served.fetch_add(1, Ordering::SeqCst);
+            Some(response)
+        }
+        Err(e) => {
+            worker.healthy.store(false, Ordering::SeqCst);
+            worker.failures.fetch_add(1, Ordering::SeqCst);
+            sidecar::note(
+                app,
+                log::Level::Warn,
+                format!("worker {} failed {}: {}", worker.id, path, e.message()),
+            );
+            probe(app, worker);
+            None
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_sidecar_workers(pool: tauri::State<WorkerPool>) -> Vec<WorkerStatus> {
+    pool.workers
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|w| WorkerStatus {
+            id: w.id,
+            pid: w.link.pid(),
+            healthy: w.healthy.load(Ordering::SeqCst),
+            in_flight: w.in_flight.load(Ordering::SeqCst),
+            served: w.served.load(Ordering::SeqCst),
+            failures: w.failures.load(Ordering::SeqCst),
+        })
+        .collect()
+}
+
+/// Sets how many workers to run, up to `MAX_WORKERS`, and returns the
+/// number kept. Takes effect at once and is kept for the next run.
+#[tauri::command]
+pub fn set_sidecar_workers(app: AppHandle, count: usize) -> usize {
+    let count = count.min(MAX_WORKERS);
+    app.state::<SettingsState>()
+        .update(|s| s.sidecar_workers = count);
+    resize(&app);
+    count
+}
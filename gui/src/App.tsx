@@ -12,20 +12,27 @@ import { Loader2, Settings as SettingsIcon, Home, RefreshCw, FolderOpen, Play, R
 import { getCurrentWindow } from '@tauri-apps/api/window';
 import { getVersion } from '@tauri-apps/api/app';
 import { useUpdater } from './hooks/useUpdater';
+import { useSidecarError } from './hooks/useSidecarError';
+import { useSettings } from './hooks/useSettings';
+import { SidecarErrorModal } from './components/SidecarErrorModal';
 
 function App() {
   // Navigation
   const [view, setView] = useState<'scanner' | 'settings'>('scanner');
 
   // UI State
-  const [isSidebarOpen, setIsSidebarOpen] = useState(() => {
-    const saved = localStorage.getItem('sidebarOpen');
-    return saved !== null ? JSON.parse(saved) : true;
-  });
+  const { settings, updateSettings } = useSettings();
+  const isSidebarOpen = !(settings?.app.sidebar_collapsed ?? false);
+  const setIsSidebarOpen = (open: boolean) => updateSettings({ app: { sidebar_collapsed: !open } });
 
+  // Earlier versions kept the sidebar state in localStorage.
   useEffect(() => {
-    localStorage.setItem('sidebarOpen', JSON.stringify(isSidebarOpen));
-  }, [isSidebarOpen]);
+    const saved = localStorage.getItem('sidebarOpen');
+    if (saved !== null) {
+      localStorage.removeItem('sidebarOpen');
+      updateSettings({ app: { sidebar_collapsed: !JSON.parse(saved) } });
+    }
+  }, [updateSettings]);
 
   const [sourcePath, setSourcePath] = useState<string | null>(null); // Display Label
   const [selectedPaths, setSelectedPaths] = useState<string[]>([]); // Actual paths
@@ -54,12 +61,15 @@ function App() {
     downloadProgress,
     checkUpdate,
     installUpdate,
+    remindLater,
     mockUpdate,
     error: updateError
   } = useUpdater();
 
   const [isUpdateModalOpen, setIsUpdateModalOpen] = useState(false);
 
+  const sidecar = useSidecarError();
+
   // Open modal when update becomes available (optional auto-open)
   useEffect(() => {
     if (updateStatus === 'available') {
@@ -878,6 +888,10 @@ function App() {
               status={updateStatus}
               progress={downloadProgress}
               error={updateError}
+              onRemindLater={() => {
+                remindLater();
+                setIsUpdateModalOpen(false);
+              }}
               onConfirm={() => {
                 installUpdate();
                 // do not close modal here, it will show progress
@@ -895,6 +909,13 @@ function App() {
           </div>
         )}
       </main>
+
+      <SidecarErrorModal
+        error={sidecar.error}
+        retrying={sidecar.retrying}
+        onRetry={sidecar.retry}
+        onClose={sidecar.dismiss}
+      />
     </div>
   );
 }
@@ -22,8 +22,7 @@ export interface ScannedFile {
 
 export async function sendHeartbeat(): Promise<void> {
     try {
-        const baseUrl = await getApiBase();
-        await fetch(`${baseUrl}/heartbeat`, { method: 'POST' });
+        await apiFetch(`/heartbeat`, { method: 'POST' });
     } catch (e) {
         console.error("Heartbeat failed:", e);
     }
@@ -31,8 +30,7 @@ export async function sendHeartbeat(): Promise<void> {
 
 export async function shutdownBackend(): Promise<void> {
     try {
-        const baseUrl = await getApiBase();
-        await fetch(`${baseUrl}/shutdown`, { method: 'POST' });
+        await apiFetch(`/shutdown`, { method: 'POST' });
         console.log("Backend shutdown requested");
     } catch (e) {
         // Expected - server shuts down before responding
@@ -48,26 +46,20 @@ export interface ScanResponse {
 
 import { invoke } from "@tauri-apps/api/core";
 
-let apiBaseUrl: string | null = null;
-
-async function getApiBase(): Promise<string> {
-    if (apiBaseUrl) return apiBaseUrl;
-
-    // In development, prefer local python server if available
-    // if (import.meta.env.DEV) {
-    //    console.log("Dev mode: Using local API at 8742");
-    //    apiBaseUrl = "http://127.0.0.1:8742";
-    //    return apiBaseUrl;
-    // }
-
-    try {
-        const port = await invoke<number>("get_api_port");
-        apiBaseUrl = `http://127.0.0.1:${port}`;
-    } catch (e) {
-        console.warn("Failed to get dynamic port, falling back to default:", e);
-        apiBaseUrl = "http://127.0.0.1:8742";
-    }
-    return apiBaseUrl;
+// Every request goes through the shell's `api_request` command, which
+// knows how to reach the sidecar, attaches its token and retries requests
+// lost to a restart. Replies come back as a `Response` so callers read them
+// the way they would a `fetch`.
+async function apiFetch(path: string, init: RequestInit = {}): Promise<Response> {
+    const res = await invoke<{ status: number; body: any }>("api_request", {
+        method: init.method ?? 'GET',
+        path,
+        body: typeof init.body === 'string' ? JSON.parse(init.body) : null,
+    });
+    return new Response(JSON.stringify(res.body), {
+        status: res.status,
+        headers: { 'Content-Type': 'application/json' },
+    });
 }
 
 export async function scanDirectory(paths: string | string[] | null): Promise<ScanResponse> {
@@ -79,8 +71,7 @@ export async function scanDirectory(paths: string | string[] | null): Promise<Sc
         payload.path = paths; // or payload.paths = [paths]
     }
 
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/scan`, {
+    const res = await apiFetch(`/scan`, {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify(payload)
@@ -95,8 +86,7 @@ export async function scanDirectory(paths: string | string[] | null): Promise<Sc
 }
 
 export async function manualSearch(query: string, type: string): Promise<FileCandidate[]> {
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/search`, {
+    const res = await apiFetch(`/search`, {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify({ query, type })
@@ -118,8 +108,7 @@ export interface ExecuteResult {
 }
 
 export async function executeMoves(payload: { files: any[] }): Promise<ExecuteResult> {
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/execute`, {
+    const res = await apiFetch(`/execute`, {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify(payload)
@@ -134,15 +123,13 @@ export async function executeMoves(payload: { files: any[] }): Promise<ExecuteRe
 }
 
 export async function getConfig(reveal_keys: boolean = false): Promise<any> {
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/config?reveal_keys=${reveal_keys}`);
+    const res = await apiFetch(`/config?reveal_keys=${reveal_keys}`);
     if (!res.ok) throw new Error("Failed to load config");
     return res.json();
 }
 
 export async function updateConfig(key: string, value: string): Promise<void> {
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/config`, {
+    const res = await apiFetch(`/config`, {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify({ key, value })
@@ -154,8 +141,7 @@ export async function updateConfig(key: string, value: string): Promise<void> {
 }
 
 export async function previewRename(original_path: string, selected_candidate: FileCandidate): Promise<string> {
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/preview_rename`, {
+    const res = await apiFetch(`/preview_rename`, {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify({ original_path, selected_candidate })
@@ -167,8 +153,7 @@ export async function previewRename(original_path: string, selected_candidate: F
 }
 
 export async function undoLastOperation(): Promise<{ success: boolean; message?: string; restored_count?: number }> {
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/undo`, {
+    const res = await apiFetch(`/undo`, {
         method: 'POST'
     });
 
@@ -176,8 +161,7 @@ export async function undoLastOperation(): Promise<{ success: boolean; message?:
 }
 
 export async function getHistory(): Promise<any[]> {
-    const baseUrl = await getApiBase();
-    const res = await fetch(`${baseUrl}/history`);
+    const res = await apiFetch(`/history`);
     if (!res.ok) return [];
     return res.json();
 }
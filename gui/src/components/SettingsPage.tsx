@@ -3,6 +3,7 @@ import { Save, FolderOpen, Eye, EyeOff } from 'lucide-react';
 import { useState, useEffect } from 'react';
 import { open } from '@tauri-apps/plugin-dialog';
 import { getConfig, updateConfig } from '../api';
+import { errorMessage } from '../errors';
 
 interface Settings {
     TMDB_API_KEY: string | null;
@@ -53,7 +54,7 @@ export function SettingsPage() {
             setSettings(data);
         } catch (err) {
             console.error(err);
-            setMessage({ type: 'error', text: `Failed to load settings: ${err}` });
+            setMessage({ type: 'error', text: `Failed to load settings: ${errorMessage(err)}` });
         } finally {
             setLoading(false);
         }
@@ -74,7 +75,7 @@ export function SettingsPage() {
             setTimeout(() => setMessage(null), 3000);
         } catch (err) {
             console.error(err);
-            setMessage({ type: 'error', text: `Failed to save ${key}: ${err}` });
+            setMessage({ type: 'error', text: `Failed to save ${key}: ${errorMessage(err)}` });
         }
     }
 
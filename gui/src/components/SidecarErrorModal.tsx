@@ -0,0 +1,95 @@
+import { useState } from 'react';
+import { X, AlertTriangle, RefreshCw, Loader2 } from 'lucide-react';
+import type { SidecarError } from '../hooks/useSidecarError';
+
+interface SidecarErrorModalProps {
+    error: SidecarError | null;
+    retrying: boolean;
+    onRetry: (timeoutSecs?: number) => void;
+    onClose: () => void;
+}
+
+const TITLES: Record<SidecarError['kind'], string> = {
+    missing: "Background service is missing",
+    blocked: "Background service was blocked",
+    no_port: "Background service could not open a port",
+    spawn_failed: "Background service failed to start",
+    timeout: "Background service is not responding",
+    crashed: "Background service stopped while starting",
+};
+
+export function SidecarErrorModal({ error, retrying, onRetry, onClose }: SidecarErrorModalProps) {
+    const [timeoutSecs, setTimeoutSecs] = useState(60);
+    if (!error) return null;
+
+    return (
+        <div className="fixed inset-0 z-[70] flex items-center justify-center p-4">
+            {/* Backdrop */}
+            <div className="absolute inset-0 bg-black/80 backdrop-blur-sm" onClick={onClose} />
+
+            {/* Modal */}
+            <div className="relative bg-gray-900 rounded-2xl border border-gray-700 shadow-2xl max-w-lg w-full overflow-hidden flex flex-col animate-fade-in">
+
+                {/* Header */}
+                <div className="px-6 py-4 border-b border-gray-800 flex items-center justify-between">
+                    <div className="flex items-center gap-3">
+                        <div className="bg-red-600/20 p-2 rounded-lg text-red-400">
+                            <AlertTriangle size={24} />
+                        </div>
+                        <h2 className="text-lg font-bold text-white">{TITLES[error.kind]}</h2>
+                    </div>
+                    <button
+                        onClick={onClose}
+                        className="p-2 rounded-lg hover:bg-gray-800 transition-colors text-gray-400 hover:text-white"
+                    >
+                        <X size={20} />
+                    </button>
+                </div>
+
+                {/* Content */}
+                <div className="p-6 space-y-4">
+                    <p className="text-sm text-gray-300">
+                        Scanning and matching need Sortify's background service, which did not start.
+                    </p>
+                    <ul className="list-disc pl-5 space-y-1 text-sm text-gray-300">
+                        {error.hints.map(hint => <li key={hint}>{hint}</li>)}
+                    </ul>
+                    <pre className="whitespace-pre-wrap text-xs text-gray-500 bg-gray-950/50 p-3 rounded-lg border border-gray-800 font-mono">
+                        {error.message}
+                    </pre>
+                    {error.kind === 'timeout' && (
+                        <label className="flex items-center gap-2 text-sm text-gray-300">
+                            Wait up to
+                            <input
+                                type="number"
+                                min={5}
+                                value={timeoutSecs}
+                                onChange={e => setTimeoutSecs(Number(e.target.value))}
+                                className="w-20 px-2 py-1 rounded bg-gray-800 border border-gray-700 text-white"
+                            />
+                            seconds
+                        </label>
+                    )}
+                </div>
+
+                {/* Footer */}
+                <div className="px-6 py-4 border-t border-gray-800 flex justify-end gap-3 bg-gray-900/50">
+                    <button
+                        onClick={onClose}
+                        className="px-4 py-2 rounded-lg bg-gray-800 hover:bg-gray-700 text-gray-300 text-sm font-medium transition-colors"
+                    >
+                        Close
+                    </button>
+                    <button
+                        onClick={() => onRetry(error.kind === 'timeout' ? timeoutSecs : undefined)}
+                        disabled={retrying}
+                        className="flex items-center gap-2 px-6 py-2 rounded-lg bg-blue-600 hover:bg-blue-500 disabled:opacity-50 text-white text-sm font-bold transition-colors shadow-lg shadow-blue-900/20"
+                    >
+                        {retrying ? <Loader2 size={18} className="animate-spin" /> : <RefreshCw size={18} />}
+                        Retry
+                    </button>
+                </div>
+            </div>
+        </div>
+    );
+}
@@ -1,23 +1,24 @@
 import { X, Download, AlertTriangle, CheckCircle, Package } from 'lucide-react';
-import type { Update } from '@tauri-apps/plugin-updater';
+import type { UpdateInfo } from '../hooks/useUpdater';
 
 interface UpdateModalProps {
     isOpen: boolean;
     onClose: () => void;
-    update: Update | null;
+    update: UpdateInfo | null;
     status: 'idle' | 'checking' | 'available' | 'downloading' | 'installing' | 'up-to-date' | 'error';
     onConfirm: () => void;
+    onRemindLater: () => void;
     progress: number;
     error: string | null;
 }
 
-export function UpdateModal({ isOpen, onClose, update, status, onConfirm, progress, error }: UpdateModalProps) {
+export function UpdateModal({ isOpen, onClose, update, status, onConfirm, onRemindLater, progress, error }: UpdateModalProps) {
     if (!isOpen || !update) return null;
 
     const isDownloading = status === 'downloading' || status === 'installing';
 
     // Parse body if it exists, simple formatting
-    const releaseNotes = update.body || "No release notes provided.";
+    const releaseNotes = update.notes || "No release notes provided.";
 
     return (
         <div className="fixed inset-0 z-[60] flex items-center justify-center p-4">
@@ -38,7 +39,9 @@ export function UpdateModal({ isOpen, onClose, update, status, onConfirm, progre
                         </div>
                         <div>
                             <h2 className="text-lg font-bold text-white">Update Available</h2>
-                            <p className="text-xs text-gray-400">Version {update.version}</p>
+                            <p className="text-xs text-gray-400">
+                                Version {update.version}{update.channel === 'beta' && ' (beta)'}
+                            </p>
                         </div>
                     </div>
                     {!isDownloading && (
@@ -104,7 +107,7 @@ export function UpdateModal({ isOpen, onClose, update, status, onConfirm, progre
                 {!isDownloading && (
                     <div className="px-6 py-4 border-t border-gray-800 flex justify-end gap-3 bg-gray-900/50">
                         <button
-                            onClick={onClose}
+                            onClick={onRemindLater}
                             className="px-4 py-2 rounded-lg bg-gray-800 hover:bg-gray-700 text-gray-300 text-sm font-medium transition-colors"
                         >
                             Remind Me Later
@@ -0,0 +1,44 @@
+// Commands reject with a `CommandError`: a code that stays the same across
+// versions, the parameters a message needs and the shell's English message,
+// used for codes not listed below.
+export interface CommandError {
+    code: string;
+    message: string;
+    params: Record<string, string>;
+}
+
+export function isCommandError(e: unknown): e is CommandError {
+    return typeof e === 'object' && e !== null && 'code' in e && 'message' in e;
+}
+
+// Messages by code, with `{name}` standing for a parameter; the place to
+// translate them.
+const MESSAGES: Record<string, string> = {
+    not_found: "{path} does not exist.",
+    permission_denied: "Sortify is not allowed to change {path}.",
+    file_locked: "{path} is open in another program.",
+    invalid_pattern: "The pattern {pattern} is not a valid regular expression.",
+    invalid_template: "The template {template} is not valid: {detail}",
+    unknown_preset: "There is no preset named {name}.",
+    out_of_scope: "{path} is outside the folders opened in this session.",
+    safe_mode: "Safe mode is on, so files cannot be renamed, moved or trashed. Turn it off in the settings first.",
+    sidecar_unavailable: "The background service is not responding. Try again in a moment.",
+};
+
+// A message for `e` to show the user, whatever it is.
+export function errorMessage(e: unknown): string {
+    if (isCommandError(e)) {
+        const template = MESSAGES[e.code];
+        if (!template) return e.message;
+        // Parameters a message names but the error lacks fall back to the
+        // shell's message
+        let missing = false;
+        const text = template.replace(/\{(\w+)\}/g, (_, name) => {
+            if (!(name in e.params)) missing = true;
+            return e.params[name] ?? '';
+        });
+        return missing ? e.message : text;
+    }
+    if (e instanceof Error) return e.message;
+    return String(e);
+}
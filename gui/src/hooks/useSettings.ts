@@ -0,0 +1,55 @@
+import { useState, useEffect, useCallback } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+
+// The parts of `settings.json` the frontend reads; the rest is passed
+// through untouched.
+export interface AppSettings {
+    sidebar_collapsed: boolean;
+    [key: string]: unknown;
+}
+
+export interface Settings {
+    apply: Record<string, unknown>;
+    // Whether changes are written to the tamper-evident audit log.
+    audit_log: boolean;
+    app: AppSettings;
+}
+
+// A JSON merge patch: given keys replace, `null` resets one to its default.
+export type SettingsPatch = {
+    apply?: Record<string, unknown>;
+    audit_log?: boolean | null;
+    app?: Partial<Record<keyof AppSettings, unknown>>;
+};
+
+interface UseSettingsReturn {
+    settings: Settings | null;
+    updateSettings: (patch: SettingsPatch) => Promise<void>;
+}
+
+// The settings the shell keeps for the app, the CLI and the folder
+// watchers, kept current through `settings-changed`.
+export function useSettings(): UseSettingsReturn {
+    const [settings, setSettings] = useState<Settings | null>(null);
+
+    useEffect(() => {
+        invoke<Settings>('get_settings').then(setSettings).catch(() => {});
+        const unlisten = listen<Settings>('settings-changed', (event) => {
+            setSettings(event.payload);
+        });
+        return () => {
+            unlisten.then(unlisten => unlisten());
+        };
+    }, []);
+
+    const updateSettings = useCallback(async (patch: SettingsPatch) => {
+        try {
+            setSettings(await invoke<Settings>('update_settings', { patch }));
+        } catch (e) {
+            console.error("Failed to save settings:", e);
+        }
+    }, []);
+
+    return { settings, updateSettings };
+}
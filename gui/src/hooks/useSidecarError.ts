@@ -0,0 +1,53 @@
+import { useState, useEffect, useCallback } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+
+export interface SidecarError {
+    kind: 'missing' | 'blocked' | 'no_port' | 'spawn_failed' | 'timeout' | 'crashed';
+    message: string;
+    hints: string[];
+}
+
+interface UseSidecarErrorReturn {
+    error: SidecarError | null;
+    retrying: boolean;
+    retry: (timeoutSecs?: number) => Promise<void>;
+    dismiss: () => void;
+}
+
+// The shell reports a sidecar that will not start with `sidecar-error`;
+// one reported before we started listening is fetched once on mount.
+export function useSidecarError(): UseSidecarErrorReturn {
+    const [error, setError] = useState<SidecarError | null>(null);
+    const [retrying, setRetrying] = useState(false);
+
+    useEffect(() => {
+        invoke<SidecarError | null>('get_sidecar_error').then(setError).catch(() => {});
+        const unlistenError = listen<SidecarError>('sidecar-error', (event) => {
+            setRetrying(false);
+            setError(event.payload);
+        });
+        const unlistenReady = listen('api-ready', () => {
+            setRetrying(false);
+            setError(null);
+        });
+        return () => {
+            unlistenError.then(unlisten => unlisten());
+            unlistenReady.then(unlisten => unlisten());
+        };
+    }, []);
+
+    const retry = useCallback(async (timeoutSecs?: number) => {
+        setRetrying(true);
+        try {
+            await invoke('retry_sidecar', { timeoutSecs: timeoutSecs ?? null });
+        } catch (e) {
+            console.error("Sidecar retry failed:", e);
+            setRetrying(false);
+        }
+    }, []);
+
+    const dismiss = useCallback(() => setError(null), []);
+
+    return { error, retrying, retry, dismiss };
+}
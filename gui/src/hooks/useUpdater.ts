@@ -1,43 +1,88 @@
-import { useState, useEffect, useCallback } from 'react';
-import { check, Update } from '@tauri-apps/plugin-updater';
-import { relaunch } from '@tauri-apps/plugin-process';
+import { useState, useEffect, useCallback, useRef } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { errorMessage } from '../errors';
 
 export type UpdateStatus = 'idle' | 'checking' | 'available' | 'downloading' | 'installing' | 'up-to-date' | 'error';
 
+export type UpdateChannel = 'stable' | 'beta';
+
+// An update found by `check_for_updates`.
+export interface UpdateInfo {
+    version: string;
+    current_version: string;
+    channel: UpdateChannel;
+    notes: string | null;
+    date: string | null;
+}
+
+interface DownloadProgress {
+    downloaded: number;
+    total: number | null;
+    finished: boolean;
+}
+
 interface UseUpdaterReturn {
     status: UpdateStatus;
-    updateAvailable: Update | null;
+    updateAvailable: UpdateInfo | null;
     downloadProgress: number; // 0-100
     downloadedBytes: number;
     totalBytes: number;
     error: string | null;
-    checkUpdate: (silent?: boolean) => Promise<void>;
+    // Without a channel, the one in the settings
+    checkUpdate: (silent?: boolean, channel?: UpdateChannel) => Promise<void>;
     installUpdate: () => Promise<void>;
+    remindLater: (days?: number) => Promise<void>;
+    // For machines that cannot reach the update server
+    installFromFile: () => Promise<void>;
     mockUpdate: () => void; // Trigger for testing
 }
 
 export function useUpdater(): UseUpdaterReturn {
     const [status, setStatus] = useState<UpdateStatus>('idle');
-    const [updateAvailable, setUpdateAvailable] = useState<Update | null>(null);
+    const [updateAvailable, setUpdateAvailable] = useState<UpdateInfo | null>(null);
     const [downloadProgress, setDownloadProgress] = useState(0);
     const [downloadedBytes, setDownloadedBytes] = useState(0);
     const [totalBytes, setTotalBytes] = useState(0);
     const [error, setError] = useState<string | null>(null);
 
-    // Use ref to track total bytes in callback without closure staleness
-    const totalBytesRef = useState<{ value: number }>({ value: 0 })[0];
-
-    // Track if we are mocking to prevent real relaunch
-    const [isMocking, setIsMocking] = useState(false);
+    // Track if we are mocking to prevent a real install
+    const isMocking = useRef(false);
+
+    const onProgress = useCallback((progress: DownloadProgress) => {
+        setDownloadedBytes(progress.downloaded);
+        setTotalBytes(progress.total || 0);
+        if (progress.finished) {
+            setStatus('installing');
+            setDownloadProgress(100);
+        } else if (progress.total) {
+            setDownloadProgress((progress.downloaded / progress.total) * 100);
+        }
+    }, []);
 
-    const checkUpdate = useCallback(async (silent = false) => {
+    // The shell reports download progress as `download-progress`
+    useEffect(() => {
+        const unlisten = listen<DownloadProgress>('download-progress', (event) => {
+            onProgress(event.payload);
+        });
+        return () => {
+            unlisten.then(unlisten => unlisten());
+        };
+    }, [onProgress]);
+
+    const checkUpdate = useCallback(async (silent = false, channel?: UpdateChannel) => {
         if (!silent) setStatus('checking');
         setError(null);
-        setIsMocking(false);
+        isMocking.current = false;
 
         try {
-            const update = await check();
-            if (update && update.available) {
+            // A version put off with "remind me later" only shows up when
+            // asked for
+            const update = await invoke<UpdateInfo | null>('check_for_updates', {
+                channel,
+                manual: !silent,
+            });
+            if (update) {
                 setUpdateAvailable(update);
                 setStatus('available');
             } else {
@@ -49,7 +94,7 @@ export function useUpdater(): UseUpdaterReturn {
         } catch (e: any) {
             console.error("Update check failed:", e);
             if (!silent) {
-                setError(e.message || "Failed to check for updates");
+                setError(errorMessage(e) || "Failed to check for updates");
                 setStatus('error');
             }
         }
@@ -60,89 +105,85 @@ export function useUpdater(): UseUpdaterReturn {
 
         setStatus('downloading');
         setDownloadProgress(0);
+        setDownloadedBytes(0);
         setError(null);
-        totalBytesRef.value = 0; // Reset ref
 
-        try {
-            // Shutdown the backend first to avoid file lock issues during update
-            const { shutdownBackend } = await import('../api');
-            await shutdownBackend();
-            // Give it a moment to fully terminate
-            await new Promise(resolve => setTimeout(resolve, 1000));
-
-            await updateAvailable.downloadAndInstall((event) => {
-                if (event.event === 'Started') {
-                    const total = event.data.contentLength || 0;
-                    setTotalBytes(total);
-                    totalBytesRef.value = total;
-                    setDownloadedBytes(0);
-                } else if (event.event === 'Progress') {
-                    setDownloadedBytes(prev => {
-                        const newBytes = prev + event.data.chunkLength;
-                        if (totalBytesRef.value > 0) {
-                            setDownloadProgress((newBytes / totalBytesRef.value) * 100);
-                        }
-                        return newBytes;
-                    });
-                } else if (event.event === 'Finished') {
-                    setStatus('installing');
-                    setDownloadProgress(100);
-                }
+        if (isMocking.current) {
+            // Pseudo-download
+            const total = 100 * 1024 * 1024; // 100MB
+            const chunk = 2 * 1024 * 1024; // 2MB chunks (slower for visibility)
+            let current = 0;
+            await new Promise<void>((resolve) => {
+                const interval = setInterval(() => {
+                    current += chunk;
+                    if (current <= total) {
+                        onProgress({ downloaded: current, total, finished: false });
+                    } else {
+                        clearInterval(interval);
+                        onProgress({ downloaded: total, total, finished: true });
+                        setTimeout(resolve, 1000); // Wait a bit then resolve
+                    }
+                }, 100);
             });
+            alert("Update Complete! App would restart now.");
+            setStatus('idle');
+            setUpdateAvailable(null);
+            isMocking.current = false;
+            return;
+        }
 
-            // Relaunch only if real update (or if we want to test relaunch failure in dev)
-            // But for mock, we should just alert.
-            if (!isMocking) {
-                await relaunch();
-            } else {
-                // Mock cleanup
-                alert("Update Complete! App would restart now.");
-                setStatus('idle');
-                setUpdateAvailable(null);
-                setIsMocking(false);
+        try {
+            // Shuts the backend down, installs and restarts the app
+            await invoke('install_update');
+        } catch (e: any) {
+            console.error("Update installation failed:", e);
+            setError(errorMessage(e) || "Failed to install update");
+            setStatus('error');
+        }
+    }, [updateAvailable, onProgress]);
+
+    const remindLater = useCallback(async (days?: number) => {
+        if (!isMocking.current) {
+            try {
+                await invoke('defer_update', { days });
+            } catch (e) {
+                console.error("Failed to put off the update:", e);
             }
+        }
+        setUpdateAvailable(null);
+        setStatus('idle');
+    }, []);
+
+    const installFromFile = useCallback(async () => {
+        const [path] = await invoke<string[]>('pick_sources', {
+            options: { single: true, title: 'Choose a downloaded update' },
+        });
+        if (!path) return;
 
+        setStatus('downloading');
+        setDownloadProgress(0);
+        setDownloadedBytes(0);
+        setError(null);
+        try {
+            // Checks the signature in the `.sig` file next to it first
+            await invoke('install_update_from_file', { path });
         } catch (e: any) {
             console.error("Update installation failed:", e);
-            setError(e.message || "Failed to install update");
+            setError(errorMessage(e) || "Failed to install update");
             setStatus('error');
         }
-    }, [updateAvailable, isMocking]);
+    }, []);
 
     // Mock function for testing
     const mockUpdate = useCallback(() => {
-        setIsMocking(true);
-        // Pseudo-Update Object
-        const mockObj = {
-            available: true,
+        isMocking.current = true;
+        setUpdateAvailable({
             version: "0.2.0-beta",
-            currentVersion: "0.1.6",
+            current_version: "0.1.6",
+            channel: 'beta',
             date: new Date().toISOString(),
-            body: "## Amazing New Features\n- Better UI\n- Faster Scanning\n- Bug Fixes\n\nThis is a mock update for testing.",
-            downloadAndInstall: (cb: any) => {
-                // Return a Promise that resolves when "download" finishes
-                return new Promise<void>((resolve) => {
-                    const total = 100 * 1024 * 1024; // 100MB
-                    cb({ event: 'Started', data: { contentLength: total } });
-
-                    let current = 0;
-                    const chunk = 2 * 1024 * 1024; // 2MB chunks (slower for visibility)
-
-                    const interval = setInterval(() => {
-                        current += chunk;
-                        if (current <= total) {
-                            cb({ event: 'Progress', data: { chunkLength: chunk } });
-                        } else {
-                            clearInterval(interval);
-                            cb({ event: 'Finished' });
-                            setTimeout(resolve, 1000); // Wait a bit then resolve
-                        }
-                    }, 100);
-                });
-            }
-        } as unknown as Update;
-
-        setUpdateAvailable(mockObj);
+            notes: "## Amazing New Features\n- Better UI\n- Faster Scanning\n- Bug Fixes\n\nThis is a mock update for testing.",
+        });
         setStatus('available');
     }, []);
 
@@ -160,6 +201,8 @@ export function useUpdater(): UseUpdaterReturn {
         error,
         checkUpdate,
         installUpdate,
+        remindLater,
+        installFromFile,
         mockUpdate
     };
 }
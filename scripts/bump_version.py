@@ -22,10 +22,21 @@ def bump_version(new_version: str):
             "type": "toml",
             "pattern": r'^version = ".*"$'
         },
+        {
+            "path": root / "crates" / "renamer-core" / "Cargo.toml",
+            "type": "toml",
+            "pattern": r'^version = ".*"$'
+        },
         {
             "path": root / "pyproject.toml",
             "type": "toml",
             "pattern": r'^version = ".*"$'
+        },
+        {
+            # One `version = "..."` line, which the toml handling covers
+            "path": root / "src" / "version.py",
+            "type": "toml",
+            "pattern": r'^version = ".*"$'
         }
     ]
     
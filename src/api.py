@@ -2,17 +2,23 @@
 FastAPI backend for Sortify GUI.
 Exposes REST endpoints for the Tauri frontend.
 """
-from fastapi import FastAPI, HTTPException
+from fastapi import FastAPI, HTTPException, Request
 from fastapi.middleware.cors import CORSMiddleware
+from fastapi.responses import JSONResponse
 from pydantic import BaseModel
 from pathlib import Path
 from typing import Optional, List, Dict, Any
 import asyncio
+import hmac
 import logging
+import os
+import secrets
+import sys
 
 # Initialize Logging EARLY to capture import errors
 from src.logger import setup_logging
-setup_logging()
+from src.version import version as __version__
+setup_logging(to_file="--worker" not in sys.argv)
 logger = logging.getLogger(__name__)
 
 try:
@@ -25,7 +31,7 @@ except Exception as e:
     logger.critical(f"Startup Failure: {e}", exc_info=True)
     raise e
 
-app = FastAPI(title="Sortify API", version="1.0.0")
+app = FastAPI(title="Sortify API", version=__version__)
 
 # Allow CORS for local Tauri app
 app.add_middleware(
@@ -36,6 +42,28 @@ app.add_middleware(
     allow_headers=["*"],
 )
 
+# The Tauri shell passes a fresh token in the environment at every launch.
+# Started by hand there is none, so a random one is made up: the API is
+# never open to whoever can reach the port.
+API_TOKEN = os.environ.get("SORTIFY_API_TOKEN") or secrets.token_urlsafe(32)
+LOOPBACK_HOSTS = {"127.0.0.1", "localhost"}
+
+@app.middleware("http")
+async def require_token(request: Request, call_next):
+    """Reject requests without the shared token, or addressed to a host name
+    other than the loopback one, so neither other local programs nor web pages
+    rebinding a domain to 127.0.0.1 can use the API."""
+    # CORS preflights never carry our header; CORSMiddleware answers them.
+    if request.method == "OPTIONS":
+        return await call_next(request)
+    if request.url.hostname not in LOOPBACK_HOSTS:
+        return JSONResponse(status_code=403, content={"detail": "Forbidden host"})
+    supplied = request.headers.get("authorization", "")
+    expected = f"Bearer {API_TOKEN}"
+    if not hmac.compare_digest(supplied.encode(), expected.encode()):
+        return JSONResponse(status_code=401, content={"detail": "Missing or invalid API token"})
+    return await call_next(request)
+
 # ============== Models ==============
 
 class ScanRequest(BaseModel):
@@ -648,6 +676,11 @@ async def update_config(update: ConfigUpdate):
 last_heartbeat = 0
 HEARTBEAT_TIMEOUT = 30 # Seconds
 
+@app.get("/health")
+async def health():
+    """Readiness probe polled by the Tauri shell before the UI talks to us."""
+    return {"status": "ok", "version": app.version}
+
 @app.post("/heartbeat")
 async def heartbeat():
     global last_heartbeat
@@ -655,6 +688,110 @@ async def heartbeat():
     last_heartbeat = time.time()
     return {"status": "ok"}
 
+# Starts every stdout line that answers a request read from stdin, so the
+# shell can tell answers from anything else printed.
+RPC_PREFIX = "@sortify-rpc "
+
+async def call_app(frame):
+    """Run one request framed as `{id, method, path, body}` through the app,
+    as if it had come over HTTP, and return its status and JSON body."""
+    import json
+    path, _, query = frame.get("path", "/").partition("?")
+    body = b"" if frame.get("body") is None else json.dumps(frame["body"]).encode()
+    # The pipe is private to the shell that started us, so the request is
+    # authorised by having arrived on it.
+    headers = [
+        (b"host", b"127.0.0.1"),
+        (b"authorization", f"Bearer {API_TOKEN}".encode()),
+        (b"content-type", b"application/json"),
+        (b"content-length", str(len(body)).encode()),
+    ]
+    scope = {
+        "type": "http",
+        "asgi": {"version": "3.0"},
+        "http_version": "1.1",
+        "method": frame.get("method", "GET").upper(),
+        "scheme": "http",
+        "path": path,
+        "raw_path": path.encode(),
+        "query_string": query.encode(),
+        "root_path": "",
+        "headers": headers,
+        "client": ("127.0.0.1", 0),
+        "server": ("127.0.0.1", 0),
+    }
+    sent = False
+    async def receive():
+        nonlocal sent
+        if not sent:
+            sent = True
+            return {"type": "http.request", "body": body, "more_body": False}
+        # Nothing more will come; wait until the response is done.
+        await asyncio.Event().wait()
+    status = 500
+    chunks = []
+    async def send(message):
+        nonlocal status
+        if message["type"] == "http.response.start":
+            status = message["status"]
+        elif message["type"] == "http.response.body":
+            chunks.append(message.get("body", b""))
+    await app(scope, receive, send)
+    text = b"".join(chunks).decode("utf-8", errors="replace")
+    try:
+        return status, json.loads(text) if text else None
+    except ValueError:
+        return status, text
+
+def serve_stdio():
+    """Answer requests read from stdin, one JSON line each, on stdout instead
+    of listening on a port, so locked-down machines see no firewall prompt
+    and no port can be taken. Ends when the shell closes stdin."""
+    import json
+    import sys
+    import threading
+
+    # Whatever else is printed or logged goes to stderr from now on, so it
+    # cannot land in the middle of an answer.
+    out = sys.stdout
+    sys.stdout = sys.stderr
+    for handler in logging.getLogger().handlers:
+        if isinstance(handler, logging.StreamHandler) and handler.stream is out:
+            handler.setStream(sys.stderr)
+    write_lock = threading.Lock()
+    loop = asyncio.new_event_loop()
+
+    def reply(frame):
+        line = RPC_PREFIX + json.dumps(frame) + "\n"
+        with write_lock:
+            out.write(line)
+            out.flush()
+
+    async def handle(frame):
+        try:
+            status, body = await call_app(frame)
+        except Exception as e:
+            logger.error(f"Request {frame.get('path')} failed: {e}", exc_info=True)
+            status, body = 500, {"detail": str(e)}
+        reply({"id": frame.get("id"), "status": status, "body": body})
+
+    def read_requests():
+        for line in sys.stdin:
+            if not line.strip():
+                continue
+            try:
+                frame = json.loads(line)
+            except ValueError:
+                logger.warning(f"Ignoring unreadable request: {line[:200]!r}")
+                continue
+            asyncio.run_coroutine_threadsafe(handle(frame), loop)
+        logger.info("stdin closed, shutting down")
+        loop.call_soon_threadsafe(loop.stop)
+
+    threading.Thread(target=read_requests, daemon=True).start()
+    logger.info("Serving the API over stdio")
+    loop.run_forever()
+
 def start_server():
     """Start the API server (called by Tauri)"""
     import uvicorn
@@ -668,11 +805,19 @@ def start_server():
 
     parser = argparse.ArgumentParser(description="Sortify API Server")
     parser.add_argument("--port", type=int, default=8742, help="Port to bind to")
+    parser.add_argument("--stdio", action="store_true", help="Serve over stdin/stdout instead of a port")
+    parser.add_argument("--worker", action="store_true", help="Run as an extra worker: stdio only, no heartbeat")
     
     # Check if we are being called correctly
     args, unknown = parser.parse_known_args()
     port = args.port
-    
+
+    # Workers end when the app closes their stdin, so need no heartbeat.
+    if args.worker:
+        logger.info("Starting API worker")
+        serve_stdio()
+        return
+
     logger.info(f"Starting API server on port {port}")
 
     # Process Management: Monitor Heartbeat
@@ -688,7 +833,10 @@ def start_server():
     # Start monitor in background
     threading.Thread(target=monitor_heartbeat, daemon=True).start()
 
-    uvicorn.run(app, host="127.0.0.1", port=port, log_level="warning")
+    if args.stdio:
+        serve_stdio()
+    else:
+        uvicorn.run(app, host="127.0.0.1", port=port, log_level="warning")
 
 if __name__ == "__main__":
     start_server()
@@ -2,11 +2,12 @@ import os
 import json
 from pathlib import Path
 from dotenv import load_dotenv
+from src.paths import data_path
 
 # Load .env first (Project specific overrides)
 load_dotenv()
 
-CONFIG_PATH = Path.home() / ".renamer_config.json"
+CONFIG_PATH = data_path(".renamer_config.json")
 
 class Config:
     def __init__(self):
@@ -1,12 +1,16 @@
 import logging
 import sys
-from pathlib import Path
 from logging.handlers import RotatingFileHandler
 
-LOG_DIR = Path.home() / ".renamer"
+from src.paths import data_path
+
+LOG_DIR = data_path(".renamer")
 LOG_FILE = LOG_DIR / "renamer.log"
 
-def setup_logging():
+def setup_logging(to_file=True):
+    """Log to stdout and, unless to_file is false, to LOG_FILE. Sidecar
+    workers leave the file to the main sidecar, since several processes
+    rotating one file trip over each other."""
     LOG_DIR.mkdir(exist_ok=True)
 
     # Root logger
@@ -20,6 +24,9 @@ def setup_logging():
     console_handler.setFormatter(console_formatter)
     logger.addHandler(console_handler)
 
+    if not to_file:
+        return
+
     # File Handler
     file_handler = RotatingFileHandler(
         LOG_FILE, maxBytes=10*1024*1024, backupCount=5, encoding='utf-8'
@@ -0,0 +1,14 @@
+import os
+from pathlib import Path
+
+# Set by a portable app to keep the sidecar's files next to it instead of
+# in the user's home folder.
+DATA_DIR_ENV = "SORTIFY_DATA_DIR"
+
+def data_path(name):
+    """Where the file or folder called name is kept."""
+    base = os.environ.get(DATA_DIR_ENV)
+    if base:
+        Path(base).mkdir(parents=True, exist_ok=True)
+        return Path(base) / name
+    return Path.home() / name
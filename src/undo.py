@@ -4,10 +4,11 @@ import uuid
 from pathlib import Path
 from typing import List, Dict, Any
 from src import filesystem
+from src.paths import data_path
 
 from datetime import datetime
 
-HISTORY_FILE = Path.home() / ".renamer_history.json"
+HISTORY_FILE = data_path(".renamer_history.json")
 
 class UndoManager:
     def __init__(self):
@@ -0,0 +1,3 @@
+"""The version the sidecar reports on /health. Kept in step with the app by
+scripts/bump_version.py, so the shell can tell a stale sidecar apart."""
+version = "0.1.14"
@@ -1,10 +1,14 @@
 import pytest
 from fastapi.testclient import TestClient
 from pathlib import Path
-from src.api import app
+from src.api import app, API_TOKEN
 from src.config import config
 
-client = TestClient(app)
+client = TestClient(
+    app,
+    base_url="http://127.0.0.1",
+    headers={"Authorization": f"Bearer {API_TOKEN}"},
+)
 
 @pytest.fixture
 def temp_env(tmp_path):
@@ -139,3 +143,23 @@ def test_execute_directory_cleanup(temp_env):
     
     # Source root should still exist
     assert source_dir.exists()
+
+def test_requests_need_the_token_and_a_loopback_host():
+    anonymous = TestClient(app, base_url="http://127.0.0.1")
+    assert anonymous.get("/health").status_code == 401
+    wrong = TestClient(app, base_url="http://127.0.0.1", headers={"Authorization": "Bearer nope"})
+    assert wrong.get("/history").status_code == 401
+    rebound = TestClient(
+        app,
+        base_url="http://evil.example",
+        headers={"Authorization": f"Bearer {API_TOKEN}"},
+    )
+    assert rebound.get("/health").status_code == 403
+    assert client.get("/health").status_code == 200
+
+def test_stdio_frames_run_through_the_app():
+    import asyncio
+    from src.api import call_app
+    status, body = asyncio.run(call_app({"id": 1, "method": "GET", "path": "/health"}))
+    assert status == 200
+    assert body["status"] == "ok"
@@ -2,10 +2,14 @@ import pytest
 import time
 from pathlib import Path
 from src.undo import UndoManager
-from src.api import app, undo_manager
+from src.api import app, undo_manager, API_TOKEN
 from fastapi.testclient import TestClient
 
-client = TestClient(app)
+client = TestClient(
+    app,
+    base_url="http://127.0.0.1",
+    headers={"Authorization": f"Bearer {API_TOKEN}"},
+)
 
 @pytest.fixture
 def clean_undo_manager(tmp_path):